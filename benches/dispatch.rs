@@ -0,0 +1,153 @@
+//! Compares the instruction dispatch strategies on a few representative workloads.
+//!
+//! Run with `cargo bench --bench dispatch`. Each workload is run with every strategy, and the
+//! fastest of several runs is reported, along with how it compares to `Dispatch::Match`.
+
+extern crate warthog;
+
+use std::time::{Duration, Instant};
+
+use warthog::{
+    builder::{FuncBuilder, ModuleBuilder},
+    hosting::Host,
+    interp::{Dispatch, Thread},
+    module::MemoryType,
+    Instruction::{self, *},
+    ValType, Value,
+};
+
+const RUNS: usize = 5;
+
+/// A workload: a module exporting `run`, which takes an `i32` and returns an `i32`.
+struct Workload {
+    name: &'static str,
+    arg: u32,
+    body: Vec<Instruction>,
+}
+
+fn workloads() -> Vec<Workload> {
+    vec![
+        // Sums the integers below the argument, with locals, arithmetic and branches
+        Workload {
+            name: "arithmetic loop",
+            arg: 1_000_000,
+            body: vec![
+                Block(ValType::Nil),
+                Loop(ValType::Nil),
+                LocalGet(0),
+                I32Eqz,
+                BrIf(1),
+                LocalGet(1),
+                LocalGet(0),
+                I32Add,
+                LocalSet(1),
+                LocalGet(0),
+                I32Const(Value::I32(1)),
+                I32Sub,
+                LocalSet(0),
+                Br(0),
+                End,
+                End,
+                LocalGet(1),
+            ],
+        },
+        // Increments a counter in memory, with loads and stores
+        Workload {
+            name: "memory loop",
+            arg: 500_000,
+            body: vec![
+                Block(ValType::Nil),
+                Loop(ValType::Nil),
+                LocalGet(0),
+                I32Eqz,
+                BrIf(1),
+                I32Const(Value::I32(0)),
+                I32Const(Value::I32(0)),
+                I32Load(2, 0),
+                I32Const(Value::I32(1)),
+                I32Add,
+                I32Store(2, 0),
+                LocalGet(0),
+                I32Const(Value::I32(1)),
+                I32Sub,
+                LocalSet(0),
+                Br(0),
+                End,
+                End,
+                I32Const(Value::I32(0)),
+                I32Load(2, 0),
+            ],
+        },
+        // Computes fibonacci numbers recursively, which is dominated by calls
+        Workload {
+            name: "recursive calls",
+            arg: 22,
+            body: vec![
+                LocalGet(0),
+                I32Const(Value::I32(2)),
+                I32LtU,
+                If(ValType::Nil),
+                LocalGet(0),
+                Return,
+                End,
+                LocalGet(0),
+                I32Const(Value::I32(1)),
+                I32Sub,
+                Call(0),
+                LocalGet(0),
+                I32Const(Value::I32(2)),
+                I32Sub,
+                Call(0),
+                I32Add,
+            ],
+        },
+    ]
+}
+
+/// Runs `workload` with `dispatch`, producing the fastest time.
+fn measure(workload: &Workload, dispatch: Dispatch) -> Duration {
+    let module = ModuleBuilder::new()
+        .mem("memory", MemoryType::new(1, None))
+        .func(
+            FuncBuilder::new()
+                .export_as("run")
+                .param(ValType::I32)
+                .result(ValType::I32)
+                .locals(vec![ValType::I32])
+                .body(workload.body.clone()),
+        );
+    let mut host = Host::new();
+    let addr = host.instantiate("bench", module.build()).unwrap();
+    let func = host.get_module(addr).unwrap().export_func("run").unwrap();
+
+    let mut thread = Thread::new();
+    thread.set_dispatch(dispatch);
+    (0..RUNS)
+        .map(|_| {
+            let start = Instant::now();
+            thread
+                .call(&mut host, addr, func, &[Value::I32(workload.arg)])
+                .unwrap();
+            start.elapsed()
+        })
+        .min()
+        .unwrap()
+}
+
+fn main() {
+    println!(
+        "{:<20} {:>12} {:>12} {:>8}",
+        "workload", "match", "table", "table %"
+    );
+    for workload in workloads() {
+        let matched = measure(&workload, Dispatch::Match);
+        let table = measure(&workload, Dispatch::Table);
+        println!(
+            "{:<20} {:>10.2}ms {:>10.2}ms {:>7.1}%",
+            workload.name,
+            matched.as_secs_f64() * 1000.0,
+            table.as_secs_f64() * 1000.0,
+            table.as_secs_f64() / matched.as_secs_f64() * 100.0
+        );
+    }
+}
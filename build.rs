@@ -20,10 +20,14 @@ pub enum InstructionType {
     BranchTable,
     TableIndex,
     MemArg,
+    Shuffle,
+    Lane,
+    HeapType,
 }
 
 pub struct InstructionRecord {
-    pub opcode: usize,
+    pub prefix: Option<u8>,
+    pub opcode: u32,
     pub old_name: String,
     pub new_name: String,
     pub enum_name: String,
@@ -37,8 +41,8 @@ pub struct IndentingWriter<W: Write> {
     start_of_line: bool,
 }
 
-static INDENT: &'static [u8] = b"    ";
-static NEWLINE: &'static [u8] = b"\n";
+static INDENT: &[u8] = b"    ";
+static NEWLINE: &[u8] = b"\n";
 
 impl<W: Write> IndentingWriter<W> {
     pub fn new(writer: W) -> IndentingWriter<W> {
@@ -104,7 +108,7 @@ impl<W: Write> Write for IndentingWriter<W> {
 
         let lines: Vec<_> = buf.split(|x| *x == b'\n').collect();
         for (i, line) in lines.iter().enumerate() {
-            if line.len() > 0 {
+            if !line.is_empty() {
                 // Don't add indent data to the count
                 self.write_indent()?;
                 self.writer.write_all(line)?;
@@ -132,28 +136,39 @@ impl<W: Write> Write for IndentingWriter<W> {
 fn main() {
     let instructions: Vec<_> = {
         let manifest_dir = env::var("CARGO_MANIFEST_DIR").unwrap();
-        let input_path = Path::new(&manifest_dir).join("instructions.csv");;
+        let input_path = Path::new(&manifest_dir).join("instructions.csv");
         let input_file = File::open(&input_path).unwrap();
-        let mut reader = csv::Reader::from_reader(input_file);
+        // Rows for instructions that are part of an opt-in proposal name the Cargo feature
+        // that enables them in an optional fifth column.
+        let mut reader = csv::ReaderBuilder::new().flexible(true).from_reader(input_file);
 
         reader
             .records()
+            .map(|record| record.unwrap())
+            .filter(|record| match record.get(4) {
+                None | Some("") => true,
+                Some(feature) => is_feature_enabled(feature),
+            })
             .map(|record| {
-                let record = record.unwrap();
                 let name = record.get(2).unwrap().to_owned();
                 let typ = match record.get(3) {
                     None | Some("") => InstructionType::Empty,
                     Some("block") => InstructionType::Block,
                     Some("index") => InstructionType::Index,
                     Some("branch-table") => InstructionType::BranchTable,
-                    Some("table-index") => InstructionType::TableIndex,
+                    Some("table-index") | Some("index-pair") => InstructionType::TableIndex,
                     Some("memarg") => InstructionType::MemArg,
                     Some("const") => InstructionType::Const,
+                    Some("shuffle") => InstructionType::Shuffle,
+                    Some("lane") => InstructionType::Lane,
+                    Some("heap-type") => InstructionType::HeapType,
                     Some(x) => panic!("Unknown instruction type: {}", x),
                 };
                 let enum_name = create_enum_name(name.clone());
+                let (prefix, opcode) = parse_opcode(record.get(0).unwrap());
                 InstructionRecord {
-                    opcode: parse_usize(record.get(0).unwrap()),
+                    prefix,
+                    opcode,
                     old_name: record.get(1).unwrap().to_owned(),
                     new_name: name,
                     enum_name: enum_name.clone(),
@@ -167,9 +182,25 @@ fn main() {
     generate_instruction_type(&instructions).unwrap();
 }
 
-fn parse_usize(inp: &str) -> usize {
+fn is_feature_enabled(feature: &str) -> bool {
+    let var = format!("CARGO_FEATURE_{}", feature.to_uppercase().replace('-', "_"));
+    env::var_os(var).is_some()
+}
+
+fn parse_hex(inp: &str) -> u32 {
     let s: String = inp.chars().skip(2).collect();
-    usize::from_str_radix(&s, 16).unwrap()
+    u32::from_str_radix(&s, 16).unwrap()
+}
+
+/// Parses an opcode in the form `0xNN` or, for prefixed opcodes, `0xPP 0xNN` where
+/// `0xPP` is the prefix byte and `0xNN` is the (LEB128-encoded in the binary) sub-opcode.
+fn parse_opcode(inp: &str) -> (Option<u8>, u32) {
+    let parts: Vec<_> = inp.split_whitespace().collect();
+    match parts.as_slice() {
+        [opcode] => (None, parse_hex(opcode)),
+        [prefix, opcode] => (Some(parse_hex(prefix) as u8), parse_hex(opcode)),
+        _ => panic!("Invalid opcode: {}", inp),
+    }
 }
 
 fn create_enum_name(name: String) -> String {
@@ -186,7 +217,15 @@ fn create_enum_name(name: String) -> String {
     new_str
 }
 
-fn generate_instruction_type(instructions: &Vec<InstructionRecord>) -> io::Result<()> {
+/// Gets the distinct opcode prefixes used by the instruction set, in order.
+fn get_prefixes(instructions: &[InstructionRecord]) -> Vec<u8> {
+    let mut prefixes: Vec<u8> = instructions.iter().filter_map(|i| i.prefix).collect();
+    prefixes.sort();
+    prefixes.dedup();
+    prefixes
+}
+
+fn generate_instruction_type(instructions: &[InstructionRecord]) -> io::Result<()> {
     let out_dir = env::var("OUT_DIR").unwrap();
     let dest_path = Path::new(&out_dir).join("instructions.g.rs");
     let f = File::create(&dest_path).unwrap();
@@ -196,17 +235,22 @@ fn generate_instruction_type(instructions: &Vec<InstructionRecord>) -> io::Resul
     w.writeln("// Included via 'include!' in src/instruction.rs")?;
 
     w.writeln("#[derive(PartialEq, Clone, Debug)]")?;
-    w.block("pub enum Instruction {", |w| generate_enum_members(w, &instructions))?;
+    w.block("pub enum Instruction {", |w| generate_enum_members(w, instructions))?;
     w.writeln("")?;
-    w.block("impl Instruction {", |w| generate_instruction_methods(w, &instructions))?;
+    w.block("impl Instruction {", |w| generate_instruction_methods(w, instructions))?;
 
     w.writeln("")?;
-    generate_fmt(&mut w, &instructions)?;
+    generate_fmt(&mut w, instructions)?;
+
+    w.writeln("")?;
+    generate_decoder_tables(&mut w, instructions)?;
+    w.writeln("")?;
+    generate_decoders(&mut w, instructions)?;
 
     Ok(())
 }
 
-fn generate_enum_members<W: io::Write>(w: &mut IndentingWriter<W>, instructions: &Vec<InstructionRecord>) -> io::Result<()> {
+fn generate_enum_members<W: io::Write>(w: &mut IndentingWriter<W>, instructions: &[InstructionRecord]) -> io::Result<()> {
     for record in instructions {
         match record.typ {
             Empty => writeln!(w, "{},", record.enum_name)?,
@@ -215,7 +259,10 @@ fn generate_enum_members<W: io::Write>(w: &mut IndentingWriter<W>, instructions:
             Index => writeln!(w, "{}(u32),", record.enum_name)?,
             BranchTable => writeln!(w, "{}(crate::instruction::BranchTable),", record.enum_name)?,
             TableIndex => writeln!(w, "{}(u32, u32),", record.enum_name)?,
-            MemArg => writeln!(w, "{}(u32, u32),", record.enum_name)?,
+            MemArg => writeln!(w, "{}(u32, u64),", record.enum_name)?,
+            Shuffle => writeln!(w, "{}(crate::instruction::ShuffleLanes),", record.enum_name)?,
+            Lane => writeln!(w, "{}(u8),", record.enum_name)?,
+            HeapType => writeln!(w, "{}(crate::value::HeapType),", record.enum_name)?,
         }
     }
 
@@ -228,18 +275,19 @@ fn get_value_type(name: &str) -> &'static str {
         "i64.const" => "i64",
         "f32.const" => "f32",
         "f64.const" => "f64",
+        "v128.const" => "v128",
         x => panic!("Unknown constant instruction: '{}'", x),
     }
 }
 
-fn generate_fmt<W: io::Write>(w: &mut IndentingWriter<W>, instructions: &Vec<InstructionRecord>) -> io::Result<()> {
+fn generate_fmt<W: io::Write>(w: &mut IndentingWriter<W>, instructions: &[InstructionRecord]) -> io::Result<()> {
     w.block("impl std::fmt::Display for Instruction {", |w| {
         w.block("fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {", |w| {
             w.block("match self {", |w| {
                 for record in instructions {
                     match record.typ {
                         Empty => writeln!(w, "{} => write!(f, \"{}\"),", record.enum_ref, record.new_name)?,
-                        Const | Block | Index | BranchTable => writeln!(w, "{}(x) => write!(f, \"{} {{}}\", x),", record.enum_ref, record.new_name)?,
+                        Const | Block | Index | BranchTable | Shuffle | Lane | HeapType => writeln!(w, "{}(x) => write!(f, \"{} {{}}\", x),", record.enum_ref, record.new_name)?,
                         TableIndex | MemArg => writeln!(w, "{}(x, y) => write!(f, \"{} {{}} {{}}\", x, y),", record.enum_ref, record.new_name)?,
                     }
                 }
@@ -249,36 +297,129 @@ fn generate_fmt<W: io::Write>(w: &mut IndentingWriter<W>, instructions: &Vec<Ins
     })
 }
 
-fn generate_instruction_methods<W: io::Write>(w: &mut IndentingWriter<W>, instructions: &Vec<InstructionRecord>) -> io::Result<()> {
-    w.block("pub fn read<R: std::io::Read>(reader: &mut R) -> Result<Instruction, crate::Error> {", |w| {
-        w.writeln("let opcode = byteorder::ReadBytesExt::read_u8(reader)?;")?;
-        w.block("match opcode {", |w| {
-            for record in instructions {
-                let opcode = format!("0x{:02X}", record.opcode);
+/// Gets the expression that decodes the immediates of `record` from `reader` and produces the instruction.
+fn get_read_expr(record: &InstructionRecord) -> String {
+    match record.typ {
+        Empty => record.enum_ref.clone(),
+        Const => format!("{}(read_{}(reader)?)", record.enum_ref, get_value_type(&record.new_name)),
+        Block => format!("{}(crate::ValType::read(reader)?)", record.enum_ref),
+        Index => format!("{}(read_idx(reader)?)", record.enum_ref),
+        BranchTable => format!("{}(BranchTable::read(reader)?)", record.enum_ref),
+        TableIndex => format!("{}(read_idx(reader)?, read_idx(reader)?)", record.enum_ref),
+        MemArg => format!("{}(read_idx(reader)?, read_offset(reader)?)", record.enum_ref),
+        Shuffle => format!("{}(ShuffleLanes::read(reader)?)", record.enum_ref),
+        Lane => format!("{}(byteorder::ReadBytesExt::read_u8(reader)?)", record.enum_ref),
+        HeapType => format!("{}(crate::value::HeapType::read(reader)?)", record.enum_ref),
+    }
+}
+
+fn get_decoder_name(record: &InstructionRecord) -> String {
+    match record.prefix {
+        Some(prefix) => format!("decode_{:02x}_{:02x}", prefix, record.opcode),
+        None => format!("decode_{:02x}", record.opcode),
+    }
+}
+
+fn generate_decoders<W: io::Write>(w: &mut IndentingWriter<W>, instructions: &[InstructionRecord]) -> io::Result<()> {
+    for record in instructions {
+        let name = get_decoder_name(record);
+        if record.typ == Empty {
+            writeln!(w, "fn {}(_: &mut dyn std::io::Read) -> Result<Instruction, crate::Error> {{", name)?;
+        } else {
+            writeln!(w, "fn {}(mut reader: &mut dyn std::io::Read) -> Result<Instruction, crate::Error> {{", name)?;
+            w.indent();
+            w.writeln("let reader = &mut reader;")?;
+            w.dedent();
+        }
+        w.indent();
+        writeln!(w, "Ok({})", get_read_expr(record))?;
+        w.end_block("}")?;
+    }
+    Ok(())
+}
+
+/// Writes a decoder table for the opcodes with the specified prefix, indexed by (sub-)opcode.
+fn generate_decoder_table<W: io::Write>(w: &mut IndentingWriter<W>, instructions: &[InstructionRecord], prefix: Option<u8>) -> io::Result<()> {
+    let records: Vec<_> = instructions.iter().filter(|i| i.prefix == prefix).collect();
+    let len = match prefix {
+        Some(_) => records.iter().map(|r| r.opcode as usize + 1).max().unwrap_or(0),
+        None => 256,
+    };
+    let name = match prefix {
+        Some(prefix) => format!("DECODERS_{:02X}", prefix),
+        None => "DECODERS".to_owned(),
+    };
+
+    let start = format!("static {}: [Option<Decoder>; {}] = [", name, len);
+    w.start_block(&start)?;
+    for opcode in 0..len {
+        match records.iter().find(|r| r.opcode as usize == opcode) {
+            Some(record) => writeln!(w, "Some({} as Decoder),", get_decoder_name(record))?,
+            None => w.writeln("None,").map(|_| ())?,
+        }
+    }
+    w.end_block("];")?;
+    Ok(())
+}
+
+fn generate_decoder_tables<W: io::Write>(w: &mut IndentingWriter<W>, instructions: &[InstructionRecord]) -> io::Result<()> {
+    let prefixes = get_prefixes(instructions);
+
+    w.writeln("type Decoder = fn(&mut dyn std::io::Read) -> Result<Instruction, crate::Error>;")?;
+    w.writeln("")?;
+    generate_decoder_table(w, instructions, None)?;
+    for prefix in prefixes.iter() {
+        w.writeln("")?;
+        generate_decoder_table(w, instructions, Some(*prefix))?;
+    }
+    w.writeln("")?;
+
+    let start = format!("static NAMESPACES: [(u8, &[Option<Decoder>]); {}] = [", prefixes.len());
+    w.start_block(&start)?;
+    for prefix in prefixes.iter() {
+        writeln!(w, "(0x{:02X}, &DECODERS_{:02X}),", prefix, prefix)?;
+    }
+    w.end_block("];")?;
+    Ok(())
+}
+
+fn generate_instruction_methods<W: io::Write>(w: &mut IndentingWriter<W>, instructions: &[InstructionRecord]) -> io::Result<()> {
+    let prefixes = get_prefixes(instructions);
+
+    w.writeln("/// The mnemonic, prefix and opcode of every instruction, in the order they're declared.")?;
+    w.start_block("pub const OPCODES: &[(&str, Option<u8>, u32)] = &[")?;
+    for record in instructions {
+        writeln!(w, "(\"{}\", {:?}, 0x{:02X}),", record.new_name, record.prefix, record.opcode)?;
+    }
+    w.end_block("];")?;
+    w.writeln("")?;
+
+    w.block("pub fn prefix(&self) -> Option<u8> {", |w| {
+        if prefixes.is_empty() {
+            return w.writeln("None").map(|_| ());
+        }
+        w.block("match self {", |w| {
+            for record in instructions.iter().filter(|i| i.prefix.is_some()) {
+                let prefix = format!("Some(0x{:02X})", record.prefix.unwrap());
                 match record.typ {
-                    Empty => writeln!(w, "{} => Ok({}),", opcode, record.enum_ref)?,
-                    Const => writeln!(w, "{} => Ok({}(read_{}(reader)?)),", opcode, record.enum_ref, get_value_type(&record.new_name))?,
-                    Block => writeln!(w, "{} => Ok({}(crate::ValType::read(reader)?)),", opcode, record.enum_ref)?,
-                    Index => writeln!(w, "{} => Ok({}(read_idx(reader)?)),", opcode, record.enum_ref)?,
-                    BranchTable => writeln!(w, "{} => Ok({}(BranchTable::read(reader)?)),", opcode, record.enum_ref)?,
-                    TableIndex => writeln!(w, "{} => Ok({}(read_idx(reader)?, read_idx(reader)?)),", opcode, record.enum_ref)?,
-                    MemArg => writeln!(w, "{} => Ok({}(read_idx(reader)?, read_idx(reader)?)),", opcode, record.enum_ref)?,
+                    Empty => writeln!(w, "{} => {},", record.enum_ref, prefix)?,
+                    Const | Block | Index | BranchTable | Shuffle | Lane | HeapType => writeln!(w, "{}(_) => {},", record.enum_ref, prefix)?,
+                    TableIndex | MemArg => writeln!(w, "{}(_, _) => {},", record.enum_ref, prefix)?,
                 }
             }
-            writeln!(w, "x => Err(Error::UnknownOpcode(x)),")?;
+            writeln!(w, "_ => None,")?;
             Ok(())
-        })?;
-        Ok(())
+        })
     })?;
     w.writeln("")?;
 
-    w.block("pub fn opcode(&self) -> u8 {", |w| {
+    w.block("pub fn opcode(&self) -> u32 {", |w| {
         w.block("match self {", |w| {
             for record in instructions {
                 let opcode = format!("0x{:02X}", record.opcode);
                 match record.typ {
                     Empty => writeln!(w, "{} => {},", record.enum_ref, opcode)?,
-                    Const | Block | Index | BranchTable => writeln!(w, "{}(_) => {},", record.enum_ref, opcode)?,
+                    Const | Block | Index | BranchTable | Shuffle | Lane | HeapType => writeln!(w, "{}(_) => {},", record.enum_ref, opcode)?,
                     TableIndex | MemArg => writeln!(w, "{}(_, _) => {},", record.enum_ref, opcode)?,
                 }
             }
@@ -287,15 +428,73 @@ fn generate_instruction_methods<W: io::Write>(w: &mut IndentingWriter<W>, instru
     })?;
     w.writeln("")?;
 
+    w.writeln("/// Gets the mnemonic of the instruction in the text format, such as `i32.add`.")?;
+    w.block("pub fn name(&self) -> &'static str {", |w| {
+        w.block("match self {", |w| {
+            for record in instructions {
+                match record.typ {
+                    Empty => writeln!(w, "{} => \"{}\",", record.enum_ref, record.new_name)?,
+                    Const | Block | Index | BranchTable | Shuffle | Lane | HeapType => writeln!(w, "{}(_) => \"{}\",", record.enum_ref, record.new_name)?,
+                    TableIndex | MemArg => writeln!(w, "{}(_, _) => \"{}\",", record.enum_ref, record.new_name)?,
+                }
+            }
+            Ok(())
+        })
+    })?;
+    w.writeln("")?;
+
+    w.block("pub fn memarg(&self) -> Option<(u32, u64)> {", |w| {
+        w.block("match self {", |w| {
+            for record in instructions.iter().filter(|i| i.typ == MemArg) {
+                writeln!(w, "{}(align, offset) => Some((*align, *offset)),", record.enum_ref)?;
+            }
+            writeln!(w, "_ => None,")?;
+            Ok(())
+        })
+    })?;
+    w.writeln("")?;
+
     w.block("pub fn is_block(&self) -> bool {", |w| {
+        let blocks: Vec<_> = instructions
+            .iter()
+            .filter(|i| i.typ == InstructionType::Block)
+            .map(|i| format!("{}(_)", i.enum_ref))
+            .collect();
+        writeln!(w, "matches!(self, {})", blocks.join(" | "))?;
+        Ok(())
+    })?;
+    w.writeln("")?;
+
+    w.writeln("/// Writes the instruction in the binary format that [`Instruction::read`] reads.")?;
+    w.block("pub fn write<W: io::Write>(&self, writer: &mut W) -> io::Result<()> {", |w| {
         w.block("match self {", |w| {
-            for record in instructions.iter().filter(|i| i.typ == InstructionType::Block) {
-                writeln!(w, "{}(_) => true,", record.enum_ref)?;
+            for record in instructions {
+                let opcode = match record.prefix {
+                    Some(prefix) => format!("write_opcode(writer, Some(0x{:02X}), 0x{:02X})?;", prefix, record.opcode),
+                    None => format!("write_opcode(writer, None, 0x{:02X})?;", record.opcode),
+                };
+                match record.typ {
+                    Empty => writeln!(w, "{} => {{ {} Ok(()) }}", record.enum_ref, opcode)?,
+                    TableIndex | MemArg => writeln!(w, "{}(x, y) => {{ {} {} }}", record.enum_ref, opcode, get_write_expr(record))?,
+                    _ => writeln!(w, "{}(x) => {{ {} {} }}", record.enum_ref, opcode, get_write_expr(record))?,
+                }
             }
-            writeln!(w, "_ => false,")?;
             Ok(())
         })
     })?;
 
     Ok(())
-}
\ No newline at end of file
+}
+
+/// Gets the expression that encodes the immediates `x` (and `y`) of `record` to `writer`.
+fn get_write_expr(record: &InstructionRecord) -> String {
+    match record.typ {
+        Empty => "Ok(())".to_owned(),
+        Const => "write_const(writer, x)".to_owned(),
+        Block | BranchTable | Shuffle | HeapType => "x.write(writer)".to_owned(),
+        Index => "write_idx(writer, *x)".to_owned(),
+        TableIndex => "write_idx(writer, *x)?; write_idx(writer, *y)".to_owned(),
+        MemArg => "write_idx(writer, *x)?; write_offset(writer, *y)".to_owned(),
+        Lane => "writer.write_all(&[*x])".to_owned(),
+    }
+}
@@ -73,8 +73,10 @@ fn write_command<W: Write>(target: &mut W, command: &Map<String, Value>) {
     writeln!(target, "    {{").unwrap();
     match typ {
         "module" => write_module(target, command),
+        "register" => write_register(target, command),
         "assert_return" => write_assert_return(target, command),
-        "assert_trap" => write_assert_trap(target, command),
+        // Exhaustion is reported as a trap, with the expected message in the same place
+        "assert_trap" | "assert_exhaustion" => write_assert_trap(target, command),
         "assert_return_canonical_nan" | "assert_return_arithmetic_nan" => {
             write_assert_nan(target, command)
         }
@@ -96,10 +98,12 @@ fn write_source_location<W: Write>(target: &mut W, command: &Map<String, Value>)
 fn write_module<W: Write>(target: &mut W, command: &Map<String, Value>) {
     write_source_location(target, command);
 
+    // Named modules are referred to by name in later commands
     let module_file = command.get("filename").and_then(|x| x.as_str()).unwrap();
-    let module_name = Path::new(module_file)
-        .file_stem()
-        .and_then(|x| x.to_str())
+    let module_name = command
+        .get("name")
+        .and_then(|x| x.as_str())
+        .or_else(|| Path::new(module_file).file_stem().and_then(|x| x.to_str()))
         .unwrap();
     writeln!(
         target,
@@ -110,6 +114,19 @@ fn write_module<W: Write>(target: &mut W, command: &Map<String, Value>) {
     .unwrap();
 }
 
+fn write_register<W: Write>(target: &mut W, command: &Map<String, Value>) {
+    write_source_location(target, command);
+
+    let as_name = command.get("as").and_then(|x| x.as_str()).unwrap();
+    writeln!(
+        target,
+        "        c.register({}, {});",
+        to_literal(as_name),
+        to_option_literal(command.get("name").and_then(|x| x.as_str()))
+    )
+    .unwrap();
+}
+
 fn write_assert_nan<W: Write>(target: &mut W, command: &Map<String, Value>) {
     write_source_location(target, command);
 
@@ -162,9 +179,11 @@ fn write_action<W: Write>(target: &mut W, command: &Map<String, Value>) {
 fn write_invoke<W: Write>(target: &mut W, command: &Map<String, Value>) {
     let field = command.get("field").and_then(|x| x.as_str()).unwrap();
     let args = command.get("args").and_then(|x| x.as_array()).unwrap();
+    let module = command.get("module").and_then(|x| x.as_str());
     write!(
         target,
-        "        let actual = c.invoke(None, {}, vals!(",
+        "        let actual = c.invoke({}, {}, vals!(",
+        to_option_literal(module),
         to_literal(field)
     )
     .unwrap();
@@ -212,6 +231,13 @@ fn to_literal<S: AsRef<str>>(val: S) -> String {
     s
 }
 
+fn to_option_literal(val: Option<&str>) -> String {
+    match val {
+        Some(val) => format!("Some({})", to_literal(val)),
+        None => "None".to_owned(),
+    }
+}
+
 // Borrowed from https://github.com/pepyakin/wabt-rs/blob/16603d07aedbf071659b0f2d60bc4bd9e9066aed/src/script/mod.rs#L255
 // Under Apache 2 license: https://github.com/pepyakin/wabt-rs/blob/16603d07aedbf071659b0f2d60bc4bd9e9066aed/LICENSE
 
@@ -2,33 +2,501 @@
 
 extern crate warthog;
 
-use std::{borrow::Cow, env, fs, path::Path, process};
+use std::{
+    borrow::Cow,
+    env, fs,
+    io::{self, Cursor, Write},
+    path::Path,
+    process,
+};
 
 use warthog::{
-    hosting::{ExternVal, Host},
-    interp::Thread,
-    module::Module,
-    reader::Reader,
-    runtime,
+    hosting::{Host, JsonEventWriter},
+    interp::{first_divergence, PrecompiledModule, Thread, TraceWriter},
+    module::{ExportDesc, MemberDesc, Module},
+    reader::{CustomSection, Reader, SectionId, TargetFeaturesSection},
+    runtime, Trap, ValType,
 };
 
+/// The number of operands, counted from the top of the stack, included in a post-mortem.
+const POST_MORTEM_OPERANDS: usize = 16;
+
+/// The number of bytes of memory around a faulting address included in a post-mortem.
+const POST_MORTEM_WINDOW: usize = 256;
+
 fn main() {
     // Arg 0 is the executable name
-    let arg0 = env::args().nth(0).unwrap();
-    let args: Vec<_> = env::args().skip(1).collect();
+    let arg0 = env::args().next().unwrap();
+    let mut args = env::args().skip(1).peekable();
+
+    if args.peek().map(String::as_str) == Some("compile") {
+        args.next();
+        let mut input = None;
+        let mut output = None;
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "-o" => output = args.next(),
+                _ => input = Some(arg),
+            }
+        }
+        match (input, output) {
+            (Some(input), Some(output)) => compile(Path::new(&input), Path::new(&output)),
+            _ => {
+                eprintln!("Usage: {} compile <wasm file> -o <output file>", arg0);
+                process::exit(1);
+            }
+        }
+        return;
+    }
+
+    if args.peek().map(String::as_str) == Some("trace-diff") {
+        args.next();
+        match (args.next(), args.next()) {
+            (Some(left), Some(right)) => trace_diff(Path::new(&left), Path::new(&right)),
+            _ => {
+                eprintln!("Usage: {} trace-diff <trace file> <trace file>", arg0);
+                process::exit(1);
+            }
+        }
+        return;
+    }
+
+    if args.peek().map(String::as_str) == Some("meta") {
+        args.next();
+        let mut json = false;
+        let mut file = None;
+        for arg in args {
+            match arg.as_str() {
+                "--json" => json = true,
+                _ => file = Some(arg),
+            }
+        }
+        match file {
+            Some(file) => meta(Path::new(&file), json),
+            None => {
+                eprintln!("Usage: {} meta [--json] <wasm file>", arg0);
+                process::exit(1);
+            }
+        }
+        return;
+    }
+
+    // 'run' is the default command
+    if args.peek().map(String::as_str) == Some("run") {
+        args.next();
+    }
+
+    let mut post_mortem = None;
+    let mut trace = None;
+    let mut history = 0;
+    let mut events = None;
+    let mut explain_linking = false;
+    let mut file = None;
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--post-mortem" => post_mortem = args.next(),
+            "--trace" => trace = args.next(),
+            "--events" => events = args.next(),
+            "--explain-linking" => explain_linking = true,
+            "--history" => history = args.next().and_then(|n| n.parse().ok()).unwrap_or(0),
+            _ => file = Some(arg),
+        }
+    }
+
+    match file {
+        Some(file) => run(
+            Path::new(&file),
+            post_mortem.as_ref().map(Path::new),
+            trace.as_ref().map(Path::new),
+            history,
+            events.as_ref().map(Path::new),
+            explain_linking,
+        ),
+        None => {
+            eprintln!(
+                "Usage: {} [run] [--post-mortem <dump file>] [--trace <trace file>] [--history <count>] [--events <jsonl file>] [--explain-linking] <wasm or precompiled file>",
+                arg0
+            );
+            eprintln!("       {} compile <wasm file> -o <output file>", arg0);
+            eprintln!("       {} trace-diff <trace file> <trace file>", arg0);
+            eprintln!("       {} meta [--json] <wasm file>", arg0);
+            process::exit(1);
+        }
+    }
+}
+
+/// Loads and lowers a module ahead of time, so runs of the output file can skip both.
+pub fn compile(input: &Path, output: &Path) {
+    let wasm = fs::read(input).unwrap();
+    let module = PrecompiledModule::compile(&wasm).unwrap();
+    let mut file = io::BufWriter::new(fs::File::create(output).unwrap());
+    module.write(&mut file).unwrap();
+    file.flush().unwrap();
+}
+
+/// Reports the first point at which two traces written by `run --trace` differ.
+pub fn trace_diff(left: &Path, right: &Path) {
+    let open = |path| io::BufReader::new(fs::File::open(path).unwrap());
+    match first_divergence(open(left), open(right)).unwrap() {
+        None => println!("traces are identical"),
+        Some(divergence) => {
+            println!("traces diverge at line {}:", divergence.line);
+            let describe = |line: Option<String>| line.unwrap_or_else(|| "<end of trace>".into());
+            println!("  {}: {}", left.display(), describe(divergence.left));
+            println!("  {}: {}", right.display(), describe(divergence.right));
+            process::exit(1);
+        }
+    }
+}
+
+/// What a module says about where it came from and what it needs, for reviewing third-party
+/// modules before running them.
+struct Metadata {
+    size: usize,
+    sha256: String,
+    version: u32,
+    /// The `producers` section, as (field, [(name, version)]).
+    producers: Vec<(String, Vec<(String, String)>)>,
+    /// Custom sections that look like they hold a version, license or build id, as text.
+    stamps: Vec<(String, String)>,
+    /// Every custom section, with its size.
+    custom_sections: Vec<(String, usize)>,
+    /// Imports and exports, counted as [functions, tables, memories, globals].
+    imports: [usize; 4],
+    exports: [usize; 4],
+    features: Vec<String>,
+}
+
+/// Reports the provenance of a module, its digest, its imports and exports, and the features
+/// it needs, as text or as JSON.
+pub fn meta(file: &Path, json: bool) {
+    let wasm = fs::read(file).unwrap();
+    let metadata = read_metadata(&wasm);
+    let stdout = io::stdout();
+    let mut out = stdout.lock();
+    if json {
+        write_metadata_json(&mut out, &metadata).unwrap();
+    } else {
+        write_metadata(&mut out, &metadata).unwrap();
+    }
+}
+
+fn read_metadata(wasm: &[u8]) -> Metadata {
+    let mut stamps = Vec::new();
+    let mut custom_sections = Vec::new();
+    let mut features = Vec::new();
+
+    let mut reader = Reader::new(Cursor::new(wasm));
+    let version = reader.read_module_header().unwrap().version;
+    while let Some(header) = reader.read_section_header().unwrap() {
+        if header.id != SectionId::Custom {
+            reader.skip(header.size as usize).unwrap();
+            continue;
+        }
+
+        let section: CustomSection = reader.read_section(header).unwrap();
+        custom_sections.push((section.name.clone(), section.content.len()));
+        let name = section.name.to_lowercase();
+        if section.name == "target_features" {
+            let section: TargetFeaturesSection = section.read_content().unwrap();
+            // Features prefixed with '-' are ones the module doesn't use
+            features.extend(
+                section
+                    .features
+                    .into_iter()
+                    .filter(|f| f.prefix != b'-')
+                    .map(|f| f.name),
+            );
+        } else if ["version", "license", "build_id", "build-id"]
+            .iter()
+            .any(|stamp| name.contains(stamp))
+        {
+            let text = String::from_utf8_lossy(&section.content);
+            stamps.push((section.name, text.trim().to_owned()));
+        }
+    }
+
+    let module = Module::load(Reader::new(Cursor::new(wasm))).unwrap();
+    let producers = match module.producers().unwrap() {
+        Some(section) => section
+            .fields
+            .into_iter()
+            .map(|field| {
+                let values = field.values.into_iter().map(|v| (v.name, v.version));
+                (field.name, values.collect())
+            })
+            .collect(),
+        None => Vec::new(),
+    };
+    let mut imports = [0; 4];
+    let mut mems = module.mems().clone();
+    for import in module.imports() {
+        match import.description() {
+            MemberDesc::Function(_) => imports[0] += 1,
+            MemberDesc::Table(_) => imports[1] += 1,
+            MemberDesc::Memory(mem) => {
+                imports[2] += 1;
+                mems.push(mem.clone());
+            }
+            MemberDesc::Global(_) => imports[3] += 1,
+        }
+    }
+    let mut exports = [0; 4];
+    for export in module.exports() {
+        match export.description() {
+            ExportDesc::Function(_) => exports[0] += 1,
+            ExportDesc::Table(_) => exports[1] += 1,
+            ExportDesc::Memory(_) => exports[2] += 1,
+            ExportDesc::Global(_) => exports[3] += 1,
+        }
+    }
+
+    // The toolchain's record can be incomplete, so add what the module's structure requires
+    let types = module.types().iter().filter_map(|t| t.func());
+    let mut required = Vec::new();
+    if mems.len() > 1 {
+        required.push("multi-memory");
+    }
+    if mems.iter().any(|m| m.shared()) {
+        required.push("atomics");
+    }
+    if mems.iter().any(|m| m.is_64()) {
+        required.push("memory64");
+    }
+    if types.clone().any(|t| t.results().len() > 1) {
+        required.push("multivalue");
+    }
+    if types
+        .flat_map(|t| t.params().iter().chain(t.results()).cloned())
+        .chain(module.globals().iter().map(|g| g.typ().typ()))
+        .any(|t| t == ValType::V128)
+    {
+        required.push("simd128");
+    }
+    features.extend(required.into_iter().map(String::from));
+    features.sort();
+    features.dedup();
+
+    Metadata {
+        size: wasm.len(),
+        sha256: sha256(wasm).iter().map(|b| format!("{:02x}", b)).collect(),
+        version,
+        producers,
+        stamps,
+        custom_sections,
+        imports,
+        exports,
+        features,
+    }
+}
 
-    if args.len() > 0 {
-        let file = &args[0];
-        run(Path::new(file));
+const KINDS: [&str; 4] = ["functions", "tables", "memories", "globals"];
+
+fn write_metadata<W: Write>(out: &mut W, metadata: &Metadata) -> io::Result<()> {
+    writeln!(out, "size: {} bytes", metadata.size)?;
+    writeln!(out, "sha256: {}", metadata.sha256)?;
+    writeln!(out, "version: {}", metadata.version)?;
+    writeln!(out, "producers:")?;
+    if metadata.producers.is_empty() {
+        writeln!(out, "  (none recorded)")?;
+    }
+    for (field, values) in &metadata.producers {
+        for (name, version) in values {
+            writeln!(out, "  {}: {} {}", field, name, version)?;
+        }
+    }
+    for (name, text) in &metadata.stamps {
+        writeln!(out, "{}: {}", name, text)?;
+    }
+    let counts = |counts: &[usize; 4]| {
+        let counts: Vec<_> = KINDS
+            .iter()
+            .zip(counts)
+            .map(|(kind, count)| format!("{} {}", count, kind))
+            .collect();
+        counts.join(", ")
+    };
+    writeln!(out, "imports: {}", counts(&metadata.imports))?;
+    writeln!(out, "exports: {}", counts(&metadata.exports))?;
+    if metadata.features.is_empty() {
+        writeln!(out, "features: (none beyond the core specification)")?;
     } else {
-        eprintln!("Usage: {} <wasm file>", arg0);
-        process::exit(1);
+        writeln!(out, "features: {}", metadata.features.join(", "))?;
+    }
+    writeln!(out, "custom sections:")?;
+    for (name, size) in &metadata.custom_sections {
+        writeln!(out, "  {} ({} bytes)", name, size)?;
+    }
+    Ok(())
+}
+
+fn write_metadata_json<W: Write>(out: &mut W, metadata: &Metadata) -> io::Result<()> {
+    let strings = |values: &mut dyn Iterator<Item = String>| {
+        format!("[{}]", values.collect::<Vec<_>>().join(","))
+    };
+    let counts = |counts: &[usize; 4]| {
+        let counts: Vec<_> = KINDS
+            .iter()
+            .zip(counts)
+            .map(|(kind, count)| format!("\"{}\":{}", kind, count))
+            .collect();
+        format!("{{{}}}", counts.join(","))
+    };
+
+    write!(out, "{{\"size\":{}", metadata.size)?;
+    write!(out, ",\"sha256\":\"{}\"", metadata.sha256)?;
+    write!(out, ",\"version\":{}", metadata.version)?;
+    write!(
+        out,
+        ",\"producers\":{}",
+        strings(&mut metadata.producers.iter().map(|(field, values)| {
+            format!(
+                "{{\"field\":{},\"values\":{}}}",
+                json_string(field),
+                strings(&mut values.iter().map(|(name, version)| format!(
+                    "{{\"name\":{},\"version\":{}}}",
+                    json_string(name),
+                    json_string(version)
+                )))
+            )
+        }))
+    )?;
+    write!(
+        out,
+        ",\"stamps\":{}",
+        strings(&mut metadata.stamps.iter().map(|(name, text)| format!(
+            "{{\"section\":{},\"text\":{}}}",
+            json_string(name),
+            json_string(text)
+        )))
+    )?;
+    write!(out, ",\"imports\":{}", counts(&metadata.imports))?;
+    write!(out, ",\"exports\":{}", counts(&metadata.exports))?;
+    write!(
+        out,
+        ",\"features\":{}",
+        strings(&mut metadata.features.iter().map(|f| json_string(f)))
+    )?;
+    write!(
+        out,
+        ",\"custom_sections\":{}",
+        strings(
+            &mut metadata.custom_sections.iter().map(|(name, size)| format!(
+                "{{\"name\":{},\"size\":{}}}",
+                json_string(name),
+                size
+            ))
+        )
+    )?;
+    writeln!(out, "}}")
+}
+
+fn json_string(s: &str) -> String {
+    let mut json = String::with_capacity(s.len() + 2);
+    json.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => json.push_str("\\\""),
+            '\\' => json.push_str("\\\\"),
+            '\n' => json.push_str("\\n"),
+            '\r' => json.push_str("\\r"),
+            '\t' => json.push_str("\\t"),
+            c if (c as u32) < 0x20 => json.push_str(&format!("\\u{:04x}", c as u32)),
+            c => json.push(c),
+        }
+    }
+    json.push('"');
+    json
+}
+
+/// The SHA-256 digest of `bytes`, as specified by FIPS 180-4.
+fn sha256(bytes: &[u8]) -> [u8; 32] {
+    const K: [u32; 64] = [
+        0x428a2f98, 0x71374491, 0xb5c0fbcf, 0xe9b5dba5, 0x3956c25b, 0x59f111f1, 0x923f82a4,
+        0xab1c5ed5, 0xd807aa98, 0x12835b01, 0x243185be, 0x550c7dc3, 0x72be5d74, 0x80deb1fe,
+        0x9bdc06a7, 0xc19bf174, 0xe49b69c1, 0xefbe4786, 0x0fc19dc6, 0x240ca1cc, 0x2de92c6f,
+        0x4a7484aa, 0x5cb0a9dc, 0x76f988da, 0x983e5152, 0xa831c66d, 0xb00327c8, 0xbf597fc7,
+        0xc6e00bf3, 0xd5a79147, 0x06ca6351, 0x14292967, 0x27b70a85, 0x2e1b2138, 0x4d2c6dfc,
+        0x53380d13, 0x650a7354, 0x766a0abb, 0x81c2c92e, 0x92722c85, 0xa2bfe8a1, 0xa81a664b,
+        0xc24b8b70, 0xc76c51a3, 0xd192e819, 0xd6990624, 0xf40e3585, 0x106aa070, 0x19a4c116,
+        0x1e376c08, 0x2748774c, 0x34b0bcb5, 0x391c0cb3, 0x4ed8aa4a, 0x5b9cca4f, 0x682e6ff3,
+        0x748f82ee, 0x78a5636f, 0x84c87814, 0x8cc70208, 0x90befffa, 0xa4506ceb, 0xbef9a3f7,
+        0xc67178f2,
+    ];
+    let mut state: [u32; 8] = [
+        0x6a09e667, 0xbb67ae85, 0x3c6ef372, 0xa54ff53a, 0x510e527f, 0x9b05688c, 0x1f83d9ab,
+        0x5be0cd19,
+    ];
+
+    // Pad to a whole number of blocks: a 1 bit, zeros, then the length in bits
+    let mut message = bytes.to_vec();
+    message.push(0x80);
+    while message.len() % 64 != 56 {
+        message.push(0);
+    }
+    message.extend_from_slice(&((bytes.len() as u64) * 8).to_be_bytes());
+
+    for block in message.chunks(64) {
+        let mut w = [0u32; 64];
+        for (i, word) in block.chunks(4).enumerate() {
+            w[i] = u32::from_be_bytes([word[0], word[1], word[2], word[3]]);
+        }
+        for i in 16..64 {
+            let s0 = w[i - 15].rotate_right(7) ^ w[i - 15].rotate_right(18) ^ (w[i - 15] >> 3);
+            let s1 = w[i - 2].rotate_right(17) ^ w[i - 2].rotate_right(19) ^ (w[i - 2] >> 10);
+            w[i] = w[i - 16]
+                .wrapping_add(s0)
+                .wrapping_add(w[i - 7])
+                .wrapping_add(s1);
+        }
+
+        let [mut a, mut b, mut c, mut d, mut e, mut f, mut g, mut h] = state;
+        for i in 0..64 {
+            let s1 = e.rotate_right(6) ^ e.rotate_right(11) ^ e.rotate_right(25);
+            let ch = (e & f) ^ (!e & g);
+            let t1 = h
+                .wrapping_add(s1)
+                .wrapping_add(ch)
+                .wrapping_add(K[i])
+                .wrapping_add(w[i]);
+            let s0 = a.rotate_right(2) ^ a.rotate_right(13) ^ a.rotate_right(22);
+            let maj = (a & b) ^ (a & c) ^ (b & c);
+            let t2 = s0.wrapping_add(maj);
+            h = g;
+            g = f;
+            f = e;
+            e = d.wrapping_add(t1);
+            d = c;
+            c = b;
+            b = a;
+            a = t1.wrapping_add(t2);
+        }
+        for (s, v) in state.iter_mut().zip(&[a, b, c, d, e, f, g, h]) {
+            *s = s.wrapping_add(*v);
+        }
+    }
+
+    let mut digest = [0; 32];
+    for (chunk, s) in digest.chunks_mut(4).zip(&state) {
+        chunk.copy_from_slice(&s.to_be_bytes());
     }
+    digest
 }
 
-pub fn run(file: &Path) {
-    // Create a host
+pub fn run(
+    file: &Path,
+    post_mortem: Option<&Path>,
+    trace: Option<&Path>,
+    history: usize,
+    events: Option<&Path>,
+    explain_linking: bool,
+) {
+    // Create a host, which reports what happens in it as JSON if asked to
     let mut host = Host::new();
+    if let Some(path) = events {
+        let out = io::BufWriter::new(fs::File::create(path).unwrap());
+        host.set_event_sink(JsonEventWriter::new(out));
+    }
 
     // Determine the module name
     let name = match file.file_stem() {
@@ -36,40 +504,164 @@ pub fn run(file: &Path) {
         None => Cow::from("unnamed"),
     };
 
-    // Load the module
-    let module = {
-        // Close the file once we're done loading
-        let file = fs::File::open(file).unwrap();
-        let reader = Reader::new(file);
-        Module::load(reader).unwrap()
-    };
-
-    // Synthesize the 'env' module
+    // Synthesize the 'env' module, and WASI with the module's name as the program's
     host.external(runtime::Env::new()).unwrap();
+    host.external(runtime::Wasi::new().arg(name.to_string()))
+        .unwrap();
 
-    // Instantiate the module
-    let entry_point = host.instantiate(name, module).unwrap();
-
-    // Look for the main entry point
-    let main_func = match host.resolve_import(entry_point, "_main").unwrap().value() {
-        ExternVal::Func(f) => *f,
-        _ => panic!("'_main' is not a function!"),
+    // Load and instantiate the module, which may have been precompiled
+    let bytes = fs::read(file).unwrap();
+    let entry_point = if PrecompiledModule::is_precompiled(&bytes) {
+        let module = PrecompiledModule::read(&mut Cursor::new(bytes)).unwrap();
+        if explain_linking {
+            write_linking(&mut io::stderr(), &host, module.module()).unwrap();
+        }
+        host.instantiate_precompiled(name, module).unwrap()
+    } else {
+        let module = Module::load(Reader::new(Cursor::new(bytes))).unwrap();
+        if explain_linking {
+            write_linking(&mut io::stderr(), &host, &module).unwrap();
+        }
+        host.instantiate(name, module).unwrap()
     };
 
+    // Look for the main entry point, or the one WASI programs export
+    let module = host.get_module(entry_point).unwrap();
+    let main_func = module
+        .export_func("_main")
+        .or_else(|_| module.export_func("_start"))
+        .unwrap();
+
     // Create a thread
     let mut thread = Thread::new();
+    if let Some(path) = trace {
+        let out = io::BufWriter::new(fs::File::create(path).unwrap());
+        thread.set_trace_sink(TraceWriter::new(out));
+    }
+    thread.set_history_len(history);
 
     // Invoke the entry point
-    if let Err(trap) = thread.invoke(&mut host, main_func) {
-        eprintln!("trap! {}", trap.cause());
-        if let Some(trace) = trap.trace() {
-            for frame in trace.frames() {
-                if let Some(loc) = frame.func().and_then(|f| host.get_location(f, 0)) {
-                    eprintln!(" at {}", loc);
-                } else {
-                    eprintln!(" at {}", frame);
-                }
+    let res = thread.invoke(&mut host, main_func);
+    host.report_metrics();
+    if let Err(trap) = res {
+        // A guest that exits ends the process with its status code
+        if let Some(code) = trap.exit_code() {
+            process::exit(code as i32);
+        }
+
+        eprintln!("trap! {}", trap.message());
+        write_trace(&mut io::stderr(), &host, &trap).unwrap();
+
+        if let Some(history) = thread.history() {
+            eprintln!("last {} instructions:", history.len());
+            for entry in history.entries() {
+                eprintln!("  {}", entry);
+            }
+        }
+
+        if let Some(path) = post_mortem {
+            match fs::File::create(path).and_then(|mut f| write_post_mortem(&mut f, &host, &trap)) {
+                Ok(()) => eprintln!("post-mortem written to {}", path.display()),
+                Err(e) => eprintln!("failed to write post-mortem: {}", e),
+            }
+        }
+    }
+}
+
+/// Writes how each import of `module` is resolved, including the ones that fail to resolve.
+fn write_linking<W: Write>(out: &mut W, host: &Host, module: &Module) -> io::Result<()> {
+    writeln!(out, "imports:")?;
+    for resolution in host.explain_imports(module) {
+        match resolution.outcome() {
+            Ok(item) => writeln!(
+                out,
+                "  {} ({})",
+                resolution,
+                host.item_name(item).as_deref().unwrap_or("unnamed")
+            )?,
+            Err(_) => writeln!(out, "  {}", resolution)?,
+        }
+    }
+    Ok(())
+}
+
+fn write_trace<W: Write>(out: &mut W, host: &Host, trap: &Trap) -> io::Result<()> {
+    if let Some(trace) = trap.trace() {
+        for frame in trace.frames() {
+            if let Some(loc) = frame
+                .func()
+                .and_then(|f| host.get_location(f, frame.offset()))
+            {
+                writeln!(out, " at {}", loc)?;
+            } else {
+                writeln!(out, " at {}", frame)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Writes everything known about the state of the program when `trap` occurred, for
+/// debugging offline.
+fn write_post_mortem<W: Write>(out: &mut W, host: &Host, trap: &Trap) -> io::Result<()> {
+    writeln!(out, "trap: {}", trap.message())?;
+    writeln!(out)?;
+    writeln!(out, "stack trace:")?;
+    write_trace(out, host, trap)?;
+
+    if let Some(state) = trap.frame_state() {
+        let operands = state.operands();
+        writeln!(out)?;
+        writeln!(
+            out,
+            "operands (top {} of {}, top first):",
+            operands.len().min(POST_MORTEM_OPERANDS),
+            operands.len()
+        )?;
+        for (depth, value) in operands.iter().rev().take(POST_MORTEM_OPERANDS).enumerate() {
+            writeln!(out, "  [{}] {} {}", depth, value.typ(), value)?;
+        }
+
+        writeln!(out)?;
+        writeln!(out, "locals:")?;
+        for (idx, value) in state.locals().iter().enumerate() {
+            writeln!(out, "  ${} {} {}", idx, value.typ(), value)?;
+        }
+    }
+
+    // Show the memory of the faulting frame's module around the address it tried to access
+    let mem = trap
+        .trace()
+        .and_then(|t| t.frames().first())
+        .and_then(|frame| host.get_module(frame.module()).ok())
+        .and_then(|module| module.mems().first().cloned())
+        .and_then(|addr| host.get_mem(addr).ok());
+    if let (Some(address), Some(mem)) = (trap.address(), mem) {
+        let len = mem.memory().len();
+        let address = address.min(usize::MAX as u64) as usize;
+        let start = address
+            .saturating_sub(POST_MORTEM_WINDOW / 2)
+            .min(len.saturating_sub(POST_MORTEM_WINDOW))
+            & !0xF;
+        let end = (start + POST_MORTEM_WINDOW).min(len);
+        let mut window = vec![0; end - start];
+        if mem.read(start, &mut window).is_err() {
+            return Ok(());
+        }
+
+        writeln!(out)?;
+        writeln!(
+            out,
+            "memory around 0x{:08X} ({} bytes in memory):",
+            address, len
+        )?;
+        for (row, bytes) in window.chunks(16).enumerate() {
+            write!(out, "  {:08X} ", start + row * 16)?;
+            for byte in bytes {
+                write!(out, " {:02X}", byte)?;
             }
+            writeln!(out)?;
         }
     }
+    Ok(())
 }
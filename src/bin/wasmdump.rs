@@ -5,16 +5,17 @@ extern crate warthog;
 use std::{env, fs, io, process};
 
 use warthog::reader::{
-    CodeSection, CustomSection, DataSection, ExportSection, FunctionSection, ImportSection,
-    NameSection, Reader, SectionHeader, SectionId, TypeSection,
+    CodeSection, CustomSection, DataSection, ElementSection, ExportSection, FunctionSection,
+    GlobalSection, ImportSection, MemorySection, NameSection, Reader, SectionHeader, SectionId,
+    TableSection, TypeSection,
 };
 
 fn main() {
     // Arg 0 is the executable name
-    let arg0 = env::args().nth(0).unwrap();
+    let arg0 = env::args().next().unwrap();
     let args: Vec<_> = env::args().skip(1).collect();
 
-    if args.len() > 0 {
+    if !args.is_empty() {
         let file = &args[0];
         run(file);
     } else {
@@ -36,7 +37,11 @@ pub fn run(file: &str) {
             SectionId::Type => dump_type_section(&mut r, header),
             SectionId::Import => dump_import_section(&mut r, header),
             SectionId::Function => dump_function_section(&mut r, header),
+            SectionId::Table => dump_table_section(&mut r, header),
+            SectionId::Memory => dump_memory_section(&mut r, header),
+            SectionId::Global => dump_global_section(&mut r, header),
             SectionId::Export => dump_export_section(&mut r, header),
+            SectionId::Element => dump_element_section(&mut r, header),
             SectionId::Data => dump_data_section(&mut r, header),
             SectionId::Code => dump_code_section(&mut r, header),
             SectionId::Custom => dump_custom_section(&mut r, header),
@@ -48,9 +53,8 @@ pub fn run(file: &str) {
 fn dump_custom_section<R: io::Read>(r: &mut Reader<R>, header: SectionHeader) {
     let section: CustomSection = r.read_section(header).unwrap();
     println!("  Custom Section Name: {}", section.name);
-    match section.name.as_str() {
-        "name" => dump_name_section(section),
-        _ => {}
+    if section.name == "name" {
+        dump_name_section(section);
     }
 }
 
@@ -70,6 +74,30 @@ fn dump_name_section(custom_section: CustomSection) {
             println!("    * {:04} {}", local.index(), local.name());
         }
     }
+    if !section.label_names.is_empty() {
+        println!("  Labels:");
+        for func in section.label_names {
+            println!("  * Function {:04}", func.index());
+            for label in func.names() {
+                println!("    * {:04} {}", label.index(), label.name());
+            }
+        }
+    }
+
+    let maps = [
+        ("Types", section.type_names),
+        ("Tables", section.table_names),
+        ("Memories", section.memory_names),
+        ("Globals", section.global_names),
+        ("Element Segments", section.elem_names),
+        ("Data Segments", section.data_names),
+    ];
+    for (kind, names) in maps.iter().filter(|(_, names)| !names.is_empty()) {
+        println!("  {}:", kind);
+        for name in names {
+            println!("  * {:04} {}", name.index(), name.name());
+        }
+    }
 }
 
 fn dump_type_section<R: io::Read>(r: &mut Reader<R>, header: SectionHeader) {
@@ -93,6 +121,27 @@ fn dump_function_section<R: io::Read>(r: &mut Reader<R>, header: SectionHeader)
     }
 }
 
+fn dump_table_section<R: io::Read>(r: &mut Reader<R>, header: SectionHeader) {
+    let section: TableSection = r.read_section(header).unwrap();
+    for (i, table) in section.tables.iter().enumerate() {
+        println!("* {:04} {}", i, table);
+    }
+}
+
+fn dump_memory_section<R: io::Read>(r: &mut Reader<R>, header: SectionHeader) {
+    let section: MemorySection = r.read_section(header).unwrap();
+    for (i, mem) in section.mems.iter().enumerate() {
+        println!("* {:04} {}", i, mem);
+    }
+}
+
+fn dump_global_section<R: io::Read>(r: &mut Reader<R>, header: SectionHeader) {
+    let section: GlobalSection = r.read_section(header).unwrap();
+    for (i, global) in section.globals.iter().enumerate() {
+        println!("* {:04} {}", i, global);
+    }
+}
+
 fn dump_export_section<R: io::Read>(r: &mut Reader<R>, header: SectionHeader) {
     let section: ExportSection = r.read_section(header).unwrap();
     for (i, export) in section.exports.iter().enumerate() {
@@ -100,6 +149,13 @@ fn dump_export_section<R: io::Read>(r: &mut Reader<R>, header: SectionHeader) {
     }
 }
 
+fn dump_element_section<R: io::Read>(r: &mut Reader<R>, header: SectionHeader) {
+    let section: ElementSection = r.read_section(header).unwrap();
+    for (i, item) in section.elems.iter().enumerate() {
+        println!("* {:04} {}", i, item);
+    }
+}
+
 fn dump_data_section<R: io::Read>(r: &mut Reader<R>, header: SectionHeader) {
     let section: DataSection = r.read_section(header).unwrap();
     for (i, item) in section.data.iter().enumerate() {
@@ -8,15 +8,15 @@ use warthog::{
     hosting::{FuncImpl, Host, MemInst, ModuleAddr, ModuleInst},
     module::{Module, ModuleNames},
     reader::Reader,
-    runtime,
+    runtime, Error,
 };
 
 fn main() {
     // Arg 0 is the executable name
-    let arg0 = env::args().nth(0).unwrap();
+    let arg0 = env::args().next().unwrap();
     let args: Vec<_> = env::args().skip(1).collect();
 
-    if args.len() > 0 {
+    if !args.is_empty() {
         let file = &args[0];
         run(Path::new(file));
     } else {
@@ -46,8 +46,14 @@ pub fn run(file: &Path) {
     // Synthesize the 'env' module
     host.external(runtime::Env::new()).unwrap();
 
-    // Instantiate the module
-    let entry_point = host.instantiate(name, module).unwrap();
+    // Instantiate the module. A start function that exits ends the process with its status code
+    let entry_point = match host.instantiate(name, module) {
+        Ok(addr) => addr,
+        Err(Error::Trap(trap)) if trap.exit_code().is_some() => {
+            process::exit(trap.exit_code().unwrap() as i32)
+        }
+        Err(e) => panic!("failed to instantiate the module: {:?}", e),
+    };
 
     // Dump the host
     println!("Host information:");
@@ -93,26 +99,28 @@ fn dump_mems(host: &Host) {
 }
 
 fn dump_initialized_ranges(mem: &MemInst) {
+    let mut data = vec![0; mem.memory().len()];
+    if mem.read(0, &mut data).is_err() {
+        return;
+    }
+
     let mut range_start = None;
-    let mem = mem.memory();
-    unsafe {
-        for (i, v) in mem.data().iter().enumerate() {
-            match (v, range_start) {
-                (0, Some(start)) => {
-                    // End of a range
-                    let end = i - 1;
-                    println!(
-                        "    * 0x{:08x} - 0x{:08x} (size: {})",
-                        start,
-                        end,
-                        end - start
-                    );
-                    range_start = None;
-                }
-                (0, None) => { /* no-op */ }
-                (_, None) => range_start = Some(i),
-                _ => { /* no-op */ }
+    for (i, v) in data.iter().enumerate() {
+        match (v, range_start) {
+            (0, Some(start)) => {
+                // End of a range
+                let end = i - 1;
+                println!(
+                    "    * 0x{:08x} - 0x{:08x} (size: {})",
+                    start,
+                    end,
+                    end - start
+                );
+                range_start = None;
             }
+            (0, None) => { /* no-op */ }
+            (_, None) => range_start = Some(i),
+            _ => { /* no-op */ }
         }
     }
 }
@@ -151,12 +159,30 @@ fn dump_instance_names(names: &ModuleNames) {
             for (idx, local_name) in func_names.locals().iter() {
                 println!("      * {:04} {}", idx, local_name);
             }
+            for (idx, label_name) in func_names.labels().iter() {
+                println!("      * label {:04} {}", idx, label_name);
+            }
+        }
+    }
+
+    let maps = [
+        ("Types", names.types()),
+        ("Tables", names.tables()),
+        ("Memories", names.mems()),
+        ("Globals", names.globals()),
+        ("Element Segments", names.elems()),
+        ("Data Segments", names.data()),
+    ];
+    for (kind, names) in maps.iter().filter(|(_, names)| names.len() > 0) {
+        println!("    {}:", kind);
+        for (idx, name) in names.iter() {
+            println!("    * {:04} {}", idx, name);
         }
     }
 }
 
 fn dump_instance_funcs(module_inst: &ModuleInst) {
-    if module_inst.funcs().len() > 0 {
+    if !module_inst.funcs().is_empty() {
         println!("  Functions:");
         for (i, func_addr) in module_inst.funcs().iter().enumerate() {
             println!("  * {:04} {}", i, func_addr);
@@ -165,7 +191,7 @@ fn dump_instance_funcs(module_inst: &ModuleInst) {
 }
 
 fn dump_instance_mems(module_inst: &ModuleInst) {
-    if module_inst.mems().len() > 0 {
+    if !module_inst.mems().is_empty() {
         println!("  Memories:");
         for (i, mem_addr) in module_inst.mems().iter().enumerate() {
             println!("  * {:04} {}", i, mem_addr);
@@ -174,7 +200,7 @@ fn dump_instance_mems(module_inst: &ModuleInst) {
 }
 
 fn dump_instance_exports(module_inst: &ModuleInst) {
-    if module_inst.exports().len() > 0 {
+    if !module_inst.exports().is_empty() {
         println!("  Exports:");
         for (i, export_inst) in module_inst.exports().iter().enumerate() {
             println!(
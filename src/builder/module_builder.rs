@@ -1,16 +1,25 @@
 use crate::{
     builder::{FuncBuilder, TypeUse},
-    module::{DataItem, Export, FuncBody, FuncType, Import, MemberDesc, Module, ModuleNames},
+    module::{
+        DataItem, ElemItem, Export, FuncBody, FuncType, Global, Import, MemberDesc, MemoryType,
+        Module, ModuleNames, TableType, TypeDef,
+    },
+    reader::CustomSection,
 };
 
 pub struct ModuleBuilder {
-    pub types: Vec<FuncType>,
+    pub types: Vec<TypeDef>,
     pub imports: Vec<Import>,
     pub funcs: Vec<usize>,
+    pub tables: Vec<TableType>,
+    pub mems: Vec<MemoryType>,
+    pub globals: Vec<Global>,
     pub exports: Vec<Export>,
+    pub elems: Vec<ElemItem>,
     pub code: Vec<FuncBody>,
     pub data: Vec<DataItem>,
     pub names: Option<ModuleNames>,
+    pub custom_sections: Vec<CustomSection>,
 }
 
 impl ModuleBuilder {
@@ -19,10 +28,15 @@ impl ModuleBuilder {
             types: Vec::new(),
             imports: Vec::new(),
             funcs: Vec::new(),
+            tables: Vec::new(),
+            mems: Vec::new(),
+            globals: Vec::new(),
             exports: Vec::new(),
+            elems: Vec::new(),
             code: Vec::new(),
             data: Vec::new(),
             names: None,
+            custom_sections: Vec::new(),
         }
     }
 
@@ -35,12 +49,12 @@ impl ModuleBuilder {
                 let typ = FuncType::new(type_use.params, type_use.results);
 
                 // Check if a matching one already exists
-                match self.types.iter().position(|f| f == &typ) {
+                match self.types.iter().position(|t| t.func() == Some(&typ)) {
                     Some(id) => id,
                     None => {
                         // If not, add one
                         let id = self.types.len();
-                        self.types.push(typ);
+                        self.types.push(TypeDef::Func(typ));
                         id
                     }
                 }
@@ -48,6 +62,12 @@ impl ModuleBuilder {
         }
     }
 
+    /// Adds a type definition to the builder without checking for an existing match
+    pub fn add_type_def(&mut self, typ: TypeDef) -> usize {
+        self.types.push(typ);
+        self.types.len() - 1
+    }
+
     pub fn add_func(&mut self, func: FuncBuilder) -> usize {
         let type_id = self.add_type(func.type_use);
 
@@ -58,16 +78,17 @@ impl ModuleBuilder {
                 self.funcs.len(),
                 "Cannot add imports after local functions are defined!"
             );
-            let func_id = self.imports.len();
+            let func_id = self.count_imports(|d| matches!(d, MemberDesc::Function(_)));
             self.imports
                 .push(Import::new(module, name, MemberDesc::Function(type_id)));
             func_id
         } else {
-            let func_id = self.imports.len() + self.funcs.len();
+            let imported = self.count_imports(|d| matches!(d, MemberDesc::Function(_)));
+            let func_id = imported + self.funcs.len();
             self.funcs.push(type_id);
 
             // Add the body
-            debug_assert_eq!(func_id - self.imports.len(), self.code.len());
+            debug_assert_eq!(func_id - imported, self.code.len());
             let body = FuncBody::new(func.locals, func.body);
             self.code.push(body);
 
@@ -80,12 +101,106 @@ impl ModuleBuilder {
         }
     }
 
+    /// Adds a table to the builder, exporting it under `export` if provided
+    pub fn add_table(&mut self, typ: TableType, export: Option<String>) -> usize {
+        let table_id =
+            self.count_imports(|d| matches!(d, MemberDesc::Table(_))) + self.tables.len();
+        self.tables.push(typ);
+        if let Some(export) = export {
+            self.exports.push(Export::table(export, table_id));
+        }
+        table_id
+    }
+
+    /// Adds a memory to the builder, exporting it under `export` if provided
+    pub fn add_mem(&mut self, typ: MemoryType, export: Option<String>) -> usize {
+        let mem_id = self.count_imports(|d| matches!(d, MemberDesc::Memory(_))) + self.mems.len();
+        self.mems.push(typ);
+        if let Some(export) = export {
+            self.exports.push(Export::mem(export, mem_id));
+        }
+        mem_id
+    }
+
+    /// Adds a memory imported as `name` from `module` to the builder. Like imported
+    /// functions, imported memories come before the ones the module defines.
+    pub fn add_mem_import<S: Into<String>, T: Into<String>>(
+        &mut self,
+        module: S,
+        name: T,
+        typ: MemoryType,
+    ) -> usize {
+        assert_eq!(
+            0,
+            self.mems.len(),
+            "Cannot add imports after local memories are defined!"
+        );
+        let mem_id = self.count_imports(|d| matches!(d, MemberDesc::Memory(_)));
+        self.imports
+            .push(Import::new(module, name, MemberDesc::Memory(typ)));
+        mem_id
+    }
+
+    /// Adds a global to the builder, exporting it under `export` if provided
+    pub fn add_global(&mut self, global: Global, export: Option<String>) -> usize {
+        let global_id =
+            self.count_imports(|d| matches!(d, MemberDesc::Global(_))) + self.globals.len();
+        self.globals.push(global);
+        if let Some(export) = export {
+            self.exports.push(Export::global(export, global_id));
+        }
+        global_id
+    }
+
+    /// Adds a type definition to the builder (chaining variant)
+    pub fn type_def(mut self, typ: TypeDef) -> Self {
+        self.add_type_def(typ);
+        self
+    }
+
     /// Adds a function to the builder (chaining variant)
     pub fn func(mut self, func: FuncBuilder) -> Self {
         self.add_func(func);
         self
     }
 
+    /// Adds an exported table to the builder (chaining variant)
+    pub fn table<S: Into<String>>(mut self, name: S, typ: TableType) -> Self {
+        self.add_table(typ, Some(name.into()));
+        self
+    }
+
+    /// Adds an exported memory to the builder (chaining variant)
+    pub fn mem<S: Into<String>>(mut self, name: S, typ: MemoryType) -> Self {
+        self.add_mem(typ, Some(name.into()));
+        self
+    }
+
+    /// Adds an imported memory to the builder (chaining variant)
+    pub fn import_mem<S: Into<String>, T: Into<String>>(
+        mut self,
+        module: S,
+        name: T,
+        typ: MemoryType,
+    ) -> Self {
+        self.add_mem_import(module, name, typ);
+        self
+    }
+
+    /// Adds an exported global to the builder (chaining variant)
+    pub fn global<S: Into<String>>(mut self, name: S, global: Global) -> Self {
+        self.add_global(global, Some(name.into()));
+        self
+    }
+
+    /// Counts the imports matching `pred`, which come first in their index space
+    fn count_imports<F: Fn(&MemberDesc) -> bool>(&self, pred: F) -> usize {
+        self.imports
+            .iter()
+            .filter(|i| pred(i.description()))
+            .count()
+    }
+
     pub fn build(self) -> Module {
         Module::from_builder(self)
     }
@@ -1,26 +1,355 @@
-use crate::Trap;
+use std::fmt;
+
+use crate::{module::FuncType, reader::SectionId, Trap, ValType};
 
 #[derive(Debug)]
 pub enum Error {
-    InvalidModule,
-    ModuleNotFound { module: String },
-    ExportNotFound { module: String, name: String },
-    ExportTypeMismatch { module: String, name: String },
-    UnsupportedVersion { version: u32 },
+    /// The binary doesn't start with the `\0asm` magic number.
+    InvalidMagic,
+    UnknownSection {
+        id: u8,
+        at: SectionOffset,
+    },
+    /// A section other than a custom section appears more than once.
+    DuplicateSection {
+        at: SectionOffset,
+    },
+    /// A section other than a custom section appears after `after`, which must come later.
+    SectionOutOfOrder {
+        after: SectionId,
+        at: SectionOffset,
+    },
+    /// A section's contents aren't the size its header says. `consumed` is how many bytes
+    /// reading the section used, or `None` if it needed more than `size`.
+    SectionSizeMismatch {
+        size: u32,
+        consumed: Option<u64>,
+        at: SectionOffset,
+    },
+    /// An integer's LEB128 encoding has bits set beyond the width of its type, so its value
+    /// doesn't fit.
+    MalformedInteger {
+        at: SectionOffset,
+    },
+    /// An integer's LEB128 encoding uses more bytes than its type needs.
+    IntegerTooLong {
+        at: SectionOffset,
+    },
+    /// A vector declares more items than there are bytes left for, given the fewest bytes
+    /// each item can take.
+    CountTooLarge {
+        count: u32,
+        remaining: usize,
+        at: SectionOffset,
+    },
+    /// A function declares more locals than the reader allows, which is [`MAX_LOCALS`].
+    ///
+    /// [`MAX_LOCALS`]: crate::module::MAX_LOCALS
+    TooManyLocals {
+        count: u64,
+        at: SectionOffset,
+    },
+    /// A name isn't valid UTF-8.
+    InvalidUtf8 {
+        at: SectionOffset,
+    },
+    InvalidValType {
+        byte: u8,
+        at: SectionOffset,
+    },
+    InvalidHeapType {
+        index: i64,
+        at: SectionOffset,
+    },
+    InvalidTypeForm {
+        form: u8,
+        at: SectionOffset,
+    },
+    InvalidMutability {
+        byte: u8,
+        at: SectionOffset,
+    },
+    InvalidElemType {
+        byte: u8,
+        at: SectionOffset,
+    },
+    InvalidExternKind {
+        kind: u8,
+        at: SectionOffset,
+    },
+    InvalidLimits {
+        flags: u8,
+        at: SectionOffset,
+    },
+    InvalidPageSize {
+        at: SectionOffset,
+    },
+    SharedMemoryWithoutMaximum {
+        at: SectionOffset,
+    },
+    UnsupportedElemSegment {
+        flags: u32,
+        at: SectionOffset,
+    },
+    /// The function and code sections declare different numbers of functions.
+    FunctionCodeMismatch {
+        funcs: usize,
+        bodies: usize,
+        at: SectionOffset,
+    },
+    UnknownTypeIndex {
+        index: usize,
+        at: SectionOffset,
+    },
+    UnknownFunctionIndex {
+        index: usize,
+        at: SectionOffset,
+    },
+    UnknownTableIndex {
+        index: usize,
+        at: SectionOffset,
+    },
+    UnknownMemoryIndex {
+        index: usize,
+        at: SectionOffset,
+    },
+    UnknownGlobalIndex {
+        index: usize,
+        at: SectionOffset,
+    },
+    DuplicateExportName {
+        name: String,
+        at: SectionOffset,
+    },
+    MultipleMemories {
+        at: SectionOffset,
+    },
+    /// The limits of a table or memory are out of order, or larger than the index type allows.
+    LimitsOutOfRange {
+        min: u64,
+        max: Option<u64>,
+        at: SectionOffset,
+    },
+    /// An initializer isn't a constant expression, or produces a value of the wrong type.
+    InvalidConstExpr {
+        at: SectionOffset,
+    },
+    /// An element or data segment doesn't fit in its table or memory.
+    SegmentOutOfBounds {
+        at: SectionOffset,
+    },
+    ModuleNotFound {
+        module: String,
+    },
+    ExportNotFound {
+        module: String,
+        name: String,
+    },
+    ExportTypeMismatch {
+        module: String,
+        name: String,
+    },
+    ImportTypeMismatch {
+        module: String,
+        name: String,
+        expected: Box<FuncType>,
+        actual: Box<FuncType>,
+    },
+    /// The arguments passed to an exported function don't match its parameters.
+    ArgumentTypeMismatch {
+        module: String,
+        name: String,
+        expected: Vec<ValType>,
+        actual: Vec<ValType>,
+    },
+    /// An address refers to an item that has been dropped from its host.
+    StaleAddress {
+        addr: String,
+    },
+    /// The instance can't be dropped because another instance still uses its functions.
+    InstanceInUse {
+        module: String,
+    },
+    /// The module has no `dylink.0` section, so it can't be linked in as a side module. See
+    /// [`Host::instantiate_side_module`](crate::hosting::Host::instantiate_side_module).
+    NotSideModule {
+        module: String,
+    },
+    /// The instance wasn't created from a [`Module`](crate::module::Module), such as an
+    /// external module, so there's nothing to instantiate again.
+    NotInstantiable {
+        module: String,
+    },
+    /// The host's [`ResourceLimiter`](crate::hosting::ResourceLimiter) refused to let the
+    /// instance `module` allocate a `resource`, either "memory" or "table", or the memory is
+    /// larger than the host's [`MemoryConfig`](crate::MemoryConfig) allows.
+    ResourceLimitExceeded {
+        module: String,
+        resource: &'static str,
+    },
+    /// A [`MemoryBackend`](crate::MemoryBackend) couldn't allocate `size` bytes for a memory.
+    OutOfMemory {
+        size: usize,
+    },
+    /// Saved host state, or a memory's persisted contents, is corrupt or doesn't fit what it's
+    /// being loaded into. `module` names the instance the problem was found in, if it's
+    /// specific to one.
+    InvalidState {
+        module: Option<String>,
+        reason: &'static str,
+    },
+    /// A function body failed validation. `func` and `instruction` locate the failure within
+    /// the body.
+    ValidationFailed {
+        func: usize,
+        instruction: Option<usize>,
+        message: String,
+    },
+    UnsupportedVersion {
+        version: u32,
+    },
     LayoutError,
     Utf8Error(std::string::FromUtf8Error),
     IoError(String),
-    UnknownOpcode(u8),
+    /// The WebAssembly text couldn't be parsed.
+    InvalidText(String),
+    /// A fuel cost table couldn't be loaded, see [`FuelCosts::load`](crate::interp::FuelCosts::load).
+    InvalidFuelCosts(String),
+    UnknownOpcode {
+        prefix: Option<u8>,
+        opcode: u32,
+        at: SectionOffset,
+    },
+    /// An `i8x16.shuffle` selects a lane outside the 32 lanes of its two operands.
+    InvalidLaneIndex {
+        lane: u8,
+        at: SectionOffset,
+    },
     Trap(Trap),
 }
 
+/// Matches every variant that carries a [`SectionOffset`], binding it to `$at`.
+macro_rules! located {
+    ($at: pat) => {
+        Error::UnknownSection { at: $at, .. }
+            | Error::DuplicateSection { at: $at }
+            | Error::SectionOutOfOrder { at: $at, .. }
+            | Error::SectionSizeMismatch { at: $at, .. }
+            | Error::MalformedInteger { at: $at }
+            | Error::IntegerTooLong { at: $at }
+            | Error::CountTooLarge { at: $at, .. }
+            | Error::TooManyLocals { at: $at, .. }
+            | Error::InvalidUtf8 { at: $at }
+            | Error::InvalidValType { at: $at, .. }
+            | Error::InvalidHeapType { at: $at, .. }
+            | Error::InvalidTypeForm { at: $at, .. }
+            | Error::InvalidMutability { at: $at, .. }
+            | Error::InvalidElemType { at: $at, .. }
+            | Error::InvalidExternKind { at: $at, .. }
+            | Error::InvalidLimits { at: $at, .. }
+            | Error::InvalidPageSize { at: $at }
+            | Error::SharedMemoryWithoutMaximum { at: $at }
+            | Error::UnsupportedElemSegment { at: $at, .. }
+            | Error::FunctionCodeMismatch { at: $at, .. }
+            | Error::UnknownTypeIndex { at: $at, .. }
+            | Error::UnknownFunctionIndex { at: $at, .. }
+            | Error::UnknownTableIndex { at: $at, .. }
+            | Error::UnknownMemoryIndex { at: $at, .. }
+            | Error::UnknownGlobalIndex { at: $at, .. }
+            | Error::DuplicateExportName { at: $at, .. }
+            | Error::MultipleMemories { at: $at }
+            | Error::LimitsOutOfRange { at: $at, .. }
+            | Error::InvalidConstExpr { at: $at }
+            | Error::SegmentOutOfBounds { at: $at }
+            | Error::UnknownOpcode { at: $at, .. }
+            | Error::InvalidLaneIndex { at: $at, .. }
+    };
+}
+
+impl Error {
+    /// Gets where in the module the problem was found, for errors that describe a malformed or
+    /// invalid module.
+    pub fn section_offset(&self) -> Option<SectionOffset> {
+        match self {
+            located!(at) => Some(*at),
+            _ => None,
+        }
+    }
+
+    /// Records the section an error was found in, and the byte offset it was found at, unless a
+    /// more specific location is already known.
+    pub(crate) fn in_section(mut self, section: SectionId, offset: Option<u64>) -> Error {
+        if let located!(at) = &mut self {
+            if at.section.is_none() {
+                *at = SectionOffset {
+                    section: Some(section),
+                    offset,
+                };
+            }
+        }
+        self
+    }
+}
+
+/// Locates a problem within a module: the section it was found in and, for modules read from a
+/// binary, the byte offset of the problem. That's the byte that couldn't be decoded for decoding
+/// errors, or the start of the section's contents for problems with a whole section.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct SectionOffset {
+    section: Option<SectionId>,
+    offset: Option<u64>,
+}
+
+impl SectionOffset {
+    /// A location that isn't known yet, for errors raised while decoding an individual item.
+    /// The reader fills in the section it was reading.
+    pub fn unknown() -> SectionOffset {
+        SectionOffset::default()
+    }
+
+    /// A location within `section` of a module that may not have been read from a binary.
+    pub fn in_section(section: SectionId) -> SectionOffset {
+        SectionOffset {
+            section: Some(section),
+            offset: None,
+        }
+    }
+
+    /// A location at a byte offset within the module's binary, outside of any section.
+    pub fn at(offset: u64) -> SectionOffset {
+        SectionOffset {
+            section: None,
+            offset: Some(offset),
+        }
+    }
+
+    pub fn section(&self) -> Option<SectionId> {
+        self.section
+    }
+
+    pub fn offset(&self) -> Option<u64> {
+        self.offset
+    }
+}
+
+impl fmt::Display for SectionOffset {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match (self.section, self.offset) {
+            (Some(section), Some(offset)) => write!(f, "{} section at 0x{:X}", section, offset),
+            (Some(section), None) => write!(f, "{} section", section),
+            (None, Some(offset)) => write!(f, "offset 0x{:X}", offset),
+            (None, None) => write!(f, "unknown location"),
+        }
+    }
+}
+
 impl From<std::io::Error> for Error {
     fn from(e: std::io::Error) -> Error {
         Error::IoError(format!("{}", e))
     }
 }
 
-impl<'a> From<&'a std::io::Error> for Error {
+impl From<&std::io::Error> for Error {
     fn from(e: &std::io::Error) -> Error {
         Error::IoError(format!("{}", e))
     }
@@ -32,14 +361,8 @@ impl From<std::string::FromUtf8Error> for Error {
     }
 }
 
-impl From<leb128::read::Error> for Error {
-    fn from(_: leb128::read::Error) -> Error {
-        Error::InvalidModule
-    }
-}
-
-impl From<std::alloc::LayoutErr> for Error {
-    fn from(_: std::alloc::LayoutErr) -> Error {
+impl From<std::alloc::LayoutError> for Error {
+    fn from(_: std::alloc::LayoutError) -> Error {
         Error::LayoutError
     }
 }
@@ -0,0 +1,138 @@
+use std::sync::Arc;
+
+use crate::{
+    hosting::{ExternVal, Host, MemInst, ModuleAddr},
+    interp::Thread,
+};
+
+/// The context a host function is called in: the host, the thread it runs on, and the module
+/// instance whose code called it.
+pub struct Caller<'a> {
+    host: &'a mut Host,
+    thread: &'a mut Thread,
+    module: ModuleAddr,
+}
+
+impl<'a> Caller<'a> {
+    /// Creates the context for a call made by the function running on `thread`.
+    pub fn new(host: &'a mut Host, thread: &'a mut Thread) -> Caller<'a> {
+        let module = thread.stack().current().frame().module();
+        Caller {
+            host,
+            thread,
+            module,
+        }
+    }
+
+    /// Gets the address of the calling module instance.
+    pub fn module(&self) -> ModuleAddr {
+        self.module
+    }
+
+    pub fn host(&self) -> &Host {
+        self.host
+    }
+
+    pub fn host_mut(&mut self) -> &mut Host {
+        self.host
+    }
+
+    pub fn thread(&self) -> &Thread {
+        self.thread
+    }
+
+    pub fn thread_mut(&mut self) -> &mut Thread {
+        self.thread
+    }
+
+    /// Gets memory `idx` of the calling module, if it has one.
+    pub fn memory(&self, idx: usize) -> Option<Arc<MemInst>> {
+        self.host
+            .resolve_mem(self.module, idx)
+            .map(|addr| self.host.mem(addr))
+    }
+
+    /// Gets the item the calling module exports as `name`, if any.
+    pub fn get_export(&self, name: &str) -> Option<ExternVal> {
+        self.host
+            .module(self.module)
+            .find_export(name)
+            .map(|e| *e.value())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use crate::{
+        builder::{FuncBuilder, ModuleBuilder},
+        hosting::{Caller, ExternVal, ExternalFunc, ExternalMemory, ExternalModule, Host},
+        module::{FuncType, MemoryType},
+        Trap, ValType, Value,
+    };
+
+    struct Peek(Vec<Arc<ExternalFunc>>);
+
+    impl ExternalModule for Peek {
+        fn name(&self) -> &str {
+            "peek"
+        }
+
+        fn funcs(&self) -> &[Arc<ExternalFunc>] {
+            &self.0
+        }
+
+        fn mems(&self) -> &[ExternalMemory] {
+            &[]
+        }
+    }
+
+    /// Produces the first byte of the caller's memory, or -1 if it doesn't export `main`.
+    fn peek(caller: &mut Caller, _values: &[Value]) -> Result<Vec<Value>, Trap> {
+        match caller.get_export("main") {
+            Some(ExternVal::Func(_)) => {
+                let mut byte = [0];
+                caller.memory(0).unwrap().read(0, &mut byte)?;
+                Ok(vec![Value::I32(u32::from(byte[0]))])
+            }
+            _ => Ok(vec![Value::I32(-1i32 as u32)]),
+        }
+    }
+
+    #[test]
+    pub fn host_functions_see_their_caller() {
+        use crate::Instruction::*;
+
+        let mut host = Host::new();
+        host.external(Peek(vec![Arc::new(ExternalFunc::new(
+            "peek",
+            FuncType::new(vec![], vec![ValType::I32]),
+            peek,
+        ))]))
+        .unwrap();
+        let module = ModuleBuilder::new()
+            .mem("memory", MemoryType::new(1, None))
+            .func(
+                FuncBuilder::new()
+                    .import_from("peek", "peek")
+                    .result(ValType::I32),
+            )
+            .func(
+                FuncBuilder::new()
+                    .export_as("main")
+                    .result(ValType::I32)
+                    .body(vec![
+                        I32Const(Value::I32(0)),
+                        I32Const(Value::I32(42)),
+                        I32Store8(0, 0),
+                        Call(0),
+                    ]),
+            );
+        let addr = host.instantiate("test", module.build()).unwrap();
+        assert_eq!(
+            vec![Value::I32(42)],
+            host.invoke(addr, "main", &[]).unwrap()
+        );
+    }
+}
@@ -0,0 +1,131 @@
+use crate::{
+    hosting::{GlobalAddr, Host},
+    module::Expr,
+    Error, FromValue, SectionOffset, Value,
+};
+
+/// Evaluates the constant expressions that initialize globals and locate element and data
+/// segments.
+///
+/// In addition to the `*.const` instructions, constant expressions may read immutable globals
+/// with `global.get` and combine integers with the extended-const `add`, `sub` and `mul`.
+pub struct ConstExpr<'a> {
+    host: &'a Host,
+    globals: &'a [GlobalAddr],
+}
+
+impl<'a> ConstExpr<'a> {
+    /// Creates an evaluator that resolves `global.get` indices against `globals`.
+    pub fn new(host: &'a Host, globals: &'a [GlobalAddr]) -> ConstExpr<'a> {
+        ConstExpr { host, globals }
+    }
+
+    pub fn eval(&self, expr: &Expr) -> Result<Value, Error> {
+        use crate::Instruction::*;
+
+        let mut stack = Vec::new();
+        for inst in expr.iter() {
+            let value = match inst {
+                I32Const(v) | I64Const(v) | F32Const(v) | F64Const(v) | V128Const(v) => *v,
+                GlobalGet(idx) => {
+                    let addr = self.globals.get(*idx as usize).ok_or_else(|| {
+                        Error::UnknownGlobalIndex {
+                            index: *idx as usize,
+                            at: SectionOffset::unknown(),
+                        }
+                    })?;
+                    let global = self.host.global(*addr);
+                    if global.typ().mutable() {
+                        return Err(invalid());
+                    }
+                    global.get()
+                }
+                I32Add => binop::<u32, _>(&mut stack, u32::wrapping_add)?,
+                I32Sub => binop::<u32, _>(&mut stack, u32::wrapping_sub)?,
+                I32Mul => binop::<u32, _>(&mut stack, u32::wrapping_mul)?,
+                I64Add => binop::<u64, _>(&mut stack, u64::wrapping_add)?,
+                I64Sub => binop::<u64, _>(&mut stack, u64::wrapping_sub)?,
+                I64Mul => binop::<u64, _>(&mut stack, u64::wrapping_mul)?,
+                _ => return Err(invalid()),
+            };
+            stack.push(value);
+        }
+
+        // A constant expression produces exactly one value
+        match stack.as_slice() {
+            [value] => Ok(*value),
+            _ => Err(invalid()),
+        }
+    }
+}
+
+fn binop<T: FromValue, F: Fn(T, T) -> T>(stack: &mut Vec<Value>, f: F) -> Result<Value, Error>
+where
+    Value: From<T>,
+{
+    let mut pop = || {
+        let value = stack.pop().ok_or_else(invalid)?;
+        T::from_value(value).map_err(|_| invalid())
+    };
+    let right = pop()?;
+    let left = pop()?;
+    Ok(f(left, right).into())
+}
+
+fn invalid() -> Error {
+    Error::InvalidConstExpr {
+        at: SectionOffset::unknown(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        builder::ModuleBuilder,
+        module::{Global, GlobalType},
+        Instruction::{self, *},
+        ValType,
+    };
+
+    fn global(typ: ValType, mutable: bool, init: Instruction) -> Global {
+        Global::new(GlobalType::new(typ, mutable), Expr::new(vec![init]))
+    }
+
+    #[test]
+    pub fn extended_const_reads_immutable_globals() {
+        let mut host = Host::new();
+        let base = ModuleBuilder::new()
+            .global(
+                "base",
+                global(ValType::I32, false, I32Const(Value::I32(40))),
+            )
+            .global(
+                "counter",
+                global(ValType::I32, true, I32Const(Value::I32(0))),
+            )
+            .build();
+        let base = host.instantiate("base", base).unwrap();
+        let globals = host.get_module(base).unwrap().globals().to_vec();
+        let eval = ConstExpr::new(&host, &globals);
+
+        let expr = Expr::new(vec![
+            GlobalGet(0),
+            I32Const(Value::I32(4)),
+            I32Const(Value::I32(2)),
+            I32Sub,
+            I32Add,
+        ]);
+        assert_eq!(Value::I32(42), eval.eval(&expr).unwrap());
+
+        // Mutable globals, unknown globals and ill-typed operands are rejected
+        for expr in [
+            vec![GlobalGet(1)],
+            vec![GlobalGet(2)],
+            vec![I32Const(Value::I32(1)), I64Const(Value::I64(1)), I64Add],
+            vec![I32Const(Value::I32(1)), I32Const(Value::I32(1))],
+        ] {
+            assert!(eval.eval(&Expr::new(expr)).is_err());
+        }
+    }
+}
@@ -0,0 +1,243 @@
+use std::{
+    fmt::Write as _,
+    io::{self, Write},
+    sync::Mutex,
+    time::Duration,
+};
+
+use crate::{
+    hosting::{FuncAddr, MemAddr, ModuleAddr},
+    Trap,
+};
+
+/// Something that happened in a [`Host`](crate::hosting::Host), or on a thread running code
+/// in it, reported to its [`EventSink`].
+#[derive(Clone, Copy, Debug)]
+pub enum Event<'a> {
+    /// A module was instantiated, or an external module was added.
+    Instantiated { module: ModuleAddr, name: &'a str },
+    /// WebAssembly code called an external function, which returned or trapped after
+    /// `duration`.
+    HostCall {
+        caller: ModuleAddr,
+        func: FuncAddr,
+        name: &'a str,
+        duration: Duration,
+        trapped: bool,
+    },
+    /// A call made by the embedder trapped.
+    Trapped { trap: &'a Trap },
+    /// A `memory.grow` instruction tried to grow a memory by `delta` pages. `pages` is the
+    /// previous size of the memory, or `None` if it couldn't grow.
+    MemoryGrow {
+        mem: MemAddr,
+        delta: u64,
+        pages: Option<u64>,
+    },
+    /// The size of the host. See
+    /// [`Host::report_metrics`](crate::hosting::Host::report_metrics).
+    Metrics {
+        modules: usize,
+        funcs: usize,
+        mems: usize,
+        memory_bytes: usize,
+    },
+}
+
+/// Receives the [`Event`]s of a host. See
+/// [`Host::set_event_sink`](crate::hosting::Host::set_event_sink).
+pub trait EventSink: Send + Sync {
+    fn event(&self, event: &Event);
+}
+
+/// An [`EventSink`] that writes each event as a line of JSON, for log pipelines and
+/// dashboards to consume.
+///
+/// Each line is an object with an `event` field naming the kind of event, and a field for
+/// each of its values. Addresses are written as numbers, and durations in microseconds.
+pub struct JsonEventWriter<W: Write> {
+    out: Mutex<W>,
+}
+
+impl<W: Write> JsonEventWriter<W> {
+    pub fn new(out: W) -> JsonEventWriter<W> {
+        JsonEventWriter {
+            out: Mutex::new(out),
+        }
+    }
+
+    pub fn into_inner(self) -> W {
+        self.out.into_inner().unwrap()
+    }
+}
+
+impl<W: Write + Send> EventSink for JsonEventWriter<W> {
+    fn event(&self, event: &Event) {
+        let line = to_json(event);
+        let mut out = self.out.lock().unwrap();
+        // Diagnostics are best-effort, so a failed write shouldn't stop the program
+        let _: io::Result<()> = writeln!(out, "{}", line).and_then(|_| out.flush());
+    }
+}
+
+/// Formats an event as a single-line JSON object.
+pub fn to_json(event: &Event) -> String {
+    let mut json = String::new();
+    match *event {
+        Event::Instantiated { module, name } => {
+            let _ = write!(
+                json,
+                r#"{{"event":"instantiated","module":{},"name":{}}}"#,
+                module.val(),
+                string(name)
+            );
+        }
+        Event::HostCall {
+            caller,
+            func,
+            name,
+            duration,
+            trapped,
+        } => {
+            let _ = write!(
+                json,
+                r#"{{"event":"host_call","caller":{},"func":{},"name":{},"micros":{},"trapped":{}}}"#,
+                caller.val(),
+                func.val(),
+                string(name),
+                duration.as_micros(),
+                trapped
+            );
+        }
+        Event::Trapped { trap } => {
+            let frames: Vec<_> = trap
+                .trace()
+                .map(|t| t.frames())
+                .unwrap_or(&[])
+                .iter()
+                .filter(|f| f.func().is_some())
+                .map(|f| string(&format!("{}+{}", f, f.offset())))
+                .collect();
+            let _ = write!(
+                json,
+                r#"{{"event":"trapped","cause":{},"message":{},"frames":[{}]}}"#,
+                string(&trap.cause().message()),
+                string(&trap.message()),
+                frames.join(",")
+            );
+        }
+        Event::MemoryGrow { mem, delta, pages } => {
+            let pages = match pages {
+                Some(pages) => pages.to_string(),
+                None => "null".to_owned(),
+            };
+            let _ = write!(
+                json,
+                r#"{{"event":"memory_grow","mem":{},"delta":{},"pages":{}}}"#,
+                mem.val(),
+                delta,
+                pages
+            );
+        }
+        Event::Metrics {
+            modules,
+            funcs,
+            mems,
+            memory_bytes,
+        } => {
+            let _ = write!(
+                json,
+                r#"{{"event":"metrics","modules":{},"funcs":{},"mems":{},"memory_bytes":{}}}"#,
+                modules, funcs, mems, memory_bytes
+            );
+        }
+    }
+    json
+}
+
+/// Quotes and escapes a JSON string.
+fn string(s: &str) -> String {
+    let mut quoted = String::with_capacity(s.len() + 2);
+    quoted.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\r' => quoted.push_str("\\r"),
+            '\t' => quoted.push_str("\\t"),
+            c if (c as u32) < 0x20 => {
+                let _ = write!(quoted, "\\u{:04x}", c as u32);
+            }
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io::{self, Write},
+        sync::{Arc, Mutex},
+    };
+
+    use super::JsonEventWriter;
+    use crate::{
+        builder::{FuncBuilder, ModuleBuilder},
+        hosting::Host,
+        interp::Thread,
+        module::MemoryType,
+        Value,
+    };
+
+    #[derive(Clone, Default)]
+    struct SharedBuf(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for SharedBuf {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    pub fn events_are_written_as_json_lines() {
+        use crate::Instruction::*;
+
+        let buf = SharedBuf::default();
+        let mut host = Host::new();
+        host.set_event_sink(JsonEventWriter::new(buf.clone()));
+        let module = ModuleBuilder::new()
+            .mem("memory", MemoryType::new(1, None))
+            .func(FuncBuilder::new().export_as("main").body(vec![
+                I32Const(Value::I32(0)),
+                MemoryGrow(0),
+                Drop,
+                Unreachable,
+            ]));
+        let addr = host.instantiate("a \"b\"", module.build()).unwrap();
+        let main = host.get_module(addr).unwrap().export_func("main").unwrap();
+        Thread::new().invoke(&mut host, main).unwrap_err();
+        host.report_metrics();
+
+        let out = String::from_utf8(buf.0.lock().unwrap().clone()).unwrap();
+        let lines: Vec<_> = out.lines().collect();
+        assert_eq!(
+            vec![
+                r#"{"event":"instantiated","module":0,"name":"a \"b\""}"#,
+                r#"{"event":"memory_grow","mem":0,"delta":0,"pages":1}"#,
+                concat!(
+                    r#"{"event":"trapped","cause":"unreachable","message":"unreachable","#,
+                    r#""frames":["a \"b\"!<function 0>+3"]}"#
+                ),
+                r#"{"event":"metrics","modules":1,"funcs":1,"mems":1,"memory_bytes":65536}"#,
+            ],
+            lines
+        );
+    }
+}
@@ -1,7 +1,8 @@
 use std::fmt;
 
-use crate::hosting::{FuncAddr, MemAddr};
+use crate::hosting::{FuncAddr, GlobalAddr, MemAddr, TableAddr};
 
+#[derive(Clone)]
 pub struct ExportInst {
     name: String,
     value: ExternVal,
@@ -15,6 +16,13 @@ impl ExportInst {
         }
     }
 
+    pub fn table<S: Into<String>>(name: S, addr: TableAddr) -> ExportInst {
+        ExportInst {
+            name: name.into(),
+            value: ExternVal::Table(addr),
+        }
+    }
+
     pub fn mem<S: Into<String>>(name: S, addr: MemAddr) -> ExportInst {
         ExportInst {
             name: name.into(),
@@ -22,6 +30,13 @@ impl ExportInst {
         }
     }
 
+    pub fn global<S: Into<String>>(name: S, addr: GlobalAddr) -> ExportInst {
+        ExportInst {
+            name: name.into(),
+            value: ExternVal::Global(addr),
+        }
+    }
+
     pub fn name(&self) -> &str {
         &self.name
     }
@@ -31,16 +46,41 @@ impl ExportInst {
     }
 }
 
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
 pub enum ExternVal {
     Func(FuncAddr),
+    Table(TableAddr),
     Mem(MemAddr),
+    Global(GlobalAddr),
+}
+
+impl ExternVal {
+    pub fn kind(&self) -> ExternKind {
+        match self {
+            ExternVal::Func(_) => ExternKind::Func,
+            ExternVal::Table(_) => ExternKind::Table,
+            ExternVal::Mem(_) => ExternKind::Mem,
+            ExternVal::Global(_) => ExternKind::Global,
+        }
+    }
 }
 
 impl fmt::Debug for ExternVal {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
             ExternVal::Func(a) => write!(f, "{}", a),
+            ExternVal::Table(a) => write!(f, "{}", a),
             ExternVal::Mem(a) => write!(f, "{}", a),
+            ExternVal::Global(a) => write!(f, "{}", a),
         }
     }
 }
+
+/// Identifies the kind of item an [`ExternVal`] refers to.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ExternKind {
+    Func,
+    Table,
+    Mem,
+    Global,
+}
@@ -1,31 +1,87 @@
-use std::sync::Arc;
+use std::{
+    future,
+    sync::Arc,
+    task::{Context, Poll, Wake, Waker},
+    thread,
+};
 
 use crate::{
-    hosting::{Host, HostFunc},
+    hosting::{AsyncHostFunc, Caller, DynHostFunc, Host, HostFuture, IntoHostFunc},
     interp::Thread,
-    module::{FuncType, MemoryType},
-    Trap, Value,
+    module::{FuncType, GlobalType, MemoryType, TableType},
+    LinearMemory, Trap, TrapCause, Value, PAGE_SIZE,
 };
 
+/// A module implemented by the host, which guests import from like any other. See
+/// [`Host::external`](crate::hosting::Host::external).
+///
+/// Each of its items is allocated in the host when the module is added, and exported by name.
+/// The instances that import a table, memory or mutable global share it, so an external module
+/// can provide what dynamically linked modules need in common, such as the function table
+/// side modules place their functions in and the globals that say where.
 pub trait ExternalModule {
     fn name(&self) -> &str;
     fn funcs(&self) -> &[Arc<ExternalFunc>];
     fn mems(&self) -> &[ExternalMemory];
+
+    /// Gets the tables the module exports. By default it has none.
+    fn tables(&self) -> &[ExternalTable] {
+        &[]
+    }
+
+    /// Gets the globals the module exports. By default it has none.
+    fn globals(&self) -> &[ExternalGlobal] {
+        &[]
+    }
 }
 
 #[derive(Clone)]
 pub struct ExternalFunc {
     name: String,
     typ: FuncType,
-    imp: Arc<HostFunc>,
+    imp: HostImpl,
+}
+
+#[derive(Clone)]
+enum HostImpl {
+    Sync(Arc<DynHostFunc>),
+    Async(Arc<AsyncHostFunc>),
 }
 
 impl ExternalFunc {
-    pub fn new<S: Into<String>>(name: S, typ: FuncType, imp: HostFunc) -> ExternalFunc {
+    pub fn new<S, F>(name: S, typ: FuncType, imp: F) -> ExternalFunc
+    where
+        S: Into<String>,
+        F: Fn(&mut Caller, &[Value]) -> Result<Vec<Value>, Trap> + Send + Sync + 'static,
+    {
         ExternalFunc {
             name: name.into(),
             typ,
-            imp: Arc::new(imp),
+            imp: HostImpl::Sync(Arc::new(imp)),
+        }
+    }
+
+    /// Creates an external function that produces a future of its results. See
+    /// [`AsyncHostFunc`].
+    pub fn new_async<S, F>(name: S, typ: FuncType, imp: F) -> ExternalFunc
+    where
+        S: Into<String>,
+        F: Fn(&mut Caller, &[Value]) -> HostFuture + Send + Sync + 'static,
+    {
+        ExternalFunc {
+            name: name.into(),
+            typ,
+            imp: HostImpl::Async(Arc::new(imp)),
+        }
+    }
+
+    /// Creates an external function from a closure, taking its type from the closure's
+    /// signature. See [`IntoHostFunc`].
+    pub fn wrap<S: Into<String>, P, R, F: IntoHostFunc<P, R>>(name: S, f: F) -> ExternalFunc {
+        ExternalFunc {
+            name: name.into(),
+            typ: F::func_type(),
+            imp: HostImpl::Sync(f.into_host_func()),
         }
     }
 
@@ -37,32 +93,89 @@ impl ExternalFunc {
         &self.typ
     }
 
+    /// Gets a boolean indicating if the function produces a future of its results.
+    pub fn is_async(&self) -> bool {
+        match self.imp {
+            HostImpl::Sync(_) => false,
+            HostImpl::Async(_) => true,
+        }
+    }
+
+    /// Calls the function with its arguments from the top of `thread`'s stack. An
+    /// asynchronous function blocks the OS thread until its future completes.
     pub fn invoke(&self, host: &mut Host, thread: &mut Thread) -> Result<Vec<Value>, Trap> {
+        let values = self.pop_params(thread)?;
+        match &self.imp {
+            HostImpl::Sync(imp) => imp(&mut Caller::new(host, thread), &values),
+            HostImpl::Async(imp) => block_on(imp(&mut Caller::new(host, thread), &values)),
+        }
+    }
+
+    /// Calls the function with its arguments from the top of `thread`'s stack, producing a
+    /// future of its results instead of waiting for them. The future of a function that
+    /// isn't asynchronous is ready straight away.
+    pub fn start(&self, host: &mut Host, thread: &mut Thread) -> HostFuture {
+        let values = match self.pop_params(thread) {
+            Ok(values) => values,
+            Err(trap) => return Box::pin(future::ready(Err(trap))),
+        };
+        match &self.imp {
+            HostImpl::Sync(imp) => {
+                Box::pin(future::ready(imp(&mut Caller::new(host, thread), &values)))
+            }
+            HostImpl::Async(imp) => imp(&mut Caller::new(host, thread), &values),
+        }
+    }
+
+    fn pop_params(&self, thread: &mut Thread) -> Result<Vec<Value>, Trap> {
         // Pop values off the stack
         let values = {
             let mut vals = Vec::new();
-            for param in self.typ.params().iter() {
+            for param in self.typ.params().iter().rev() {
                 match thread.stack_mut().pop()? {
-                    v if v.typ() != *param => {
-                        return Err(format!(
-                            "Type mismatch. Function expects '{}' but '{}' is on top of the stack.",
-                            param,
-                            v.typ()
-                        ).into())
+                    v if !param.accepts(v.typ()) => {
+                        return Err(TrapCause::TypeMismatch {
+                            expected: *param,
+                            actual: v.typ(),
+                        }
+                        .into())
                     }
                     v => vals.push(v),
                 }
             }
+            vals.reverse();
             vals
         };
+        Ok(values)
+    }
+}
+
+/// Wakes an OS thread that's waiting for a future, see [`block_on`].
+struct Unparker(thread::Thread);
 
-        (self.imp)(host, thread, &values)
+impl Wake for Unparker {
+    fn wake(self: Arc<Self>) {
+        self.0.unpark();
+    }
+}
+
+/// Runs `future` to completion on the current OS thread, parking it while the future is
+/// pending.
+fn block_on(mut future: HostFuture) -> Result<Vec<Value>, Trap> {
+    let waker = Waker::from(Arc::new(Unparker(thread::current())));
+    let mut cx = Context::from_waker(&waker);
+    loop {
+        match future.as_mut().poll(&mut cx) {
+            Poll::Ready(results) => return results,
+            Poll::Pending => thread::park(),
+        }
     }
 }
 
 pub struct ExternalMemory {
     name: String,
     typ: MemoryType,
+    storage: Option<Arc<dyn LinearMemory>>,
 }
 
 impl ExternalMemory {
@@ -74,6 +187,22 @@ impl ExternalMemory {
         ExternalMemory {
             name: name.into(),
             typ: MemoryType::new(min_size, max_size),
+            storage: None,
+        }
+    }
+
+    /// Creates a memory kept in `storage` rather than allocated by the host, so the embedder
+    /// can share a buffer it already has with guests, such as a [`BufferMemory`](crate::BufferMemory).
+    /// The memory starts out as many whole pages as the storage holds.
+    pub fn with_storage<S: Into<String>>(
+        name: S,
+        storage: Arc<dyn LinearMemory>,
+        max_pages: Option<usize>,
+    ) -> ExternalMemory {
+        ExternalMemory {
+            name: name.into(),
+            typ: MemoryType::new(storage.len() / PAGE_SIZE, max_pages),
+            storage: Some(storage),
         }
     }
 
@@ -84,4 +213,331 @@ impl ExternalMemory {
     pub fn typ(&self) -> &MemoryType {
         &self.typ
     }
+
+    /// Gets the storage the embedder gave for the memory, if any.
+    pub fn storage(&self) -> Option<&Arc<dyn LinearMemory>> {
+        self.storage.as_ref()
+    }
+}
+
+/// A table of functions an external module exports, which starts out empty.
+pub struct ExternalTable {
+    name: String,
+    typ: TableType,
+}
+
+impl ExternalTable {
+    pub fn new<S: Into<String>>(name: S, min: usize, max: Option<usize>) -> ExternalTable {
+        ExternalTable {
+            name: name.into(),
+            typ: TableType::new(min, max),
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn typ(&self) -> &TableType {
+        &self.typ
+    }
+}
+
+/// A global an external module exports, holding `value` when the module is added to a host.
+pub struct ExternalGlobal {
+    name: String,
+    value: Value,
+    mutable: bool,
+}
+
+impl ExternalGlobal {
+    pub fn new<S: Into<String>>(name: S, value: Value, mutable: bool) -> ExternalGlobal {
+        ExternalGlobal {
+            name: name.into(),
+            value,
+            mutable,
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn value(&self) -> Value {
+        self.value
+    }
+
+    pub fn typ(&self) -> GlobalType {
+        GlobalType::new(self.value.typ(), self.mutable)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        future::Future,
+        pin::Pin,
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+        },
+        task::{Context, Poll, Wake, Waker},
+    };
+
+    use crate::{
+        builder::{FuncBuilder, ModuleBuilder},
+        hosting::{
+            ExternalFunc, ExternalGlobal, ExternalMemory, ExternalModule, ExternalTable, Host,
+        },
+        interp::Thread,
+        module::{ElemItem, Expr, FuncType, GlobalType, Import, MemberDesc, MemoryType, TableType},
+        BufferMemory,
+        Instruction::*,
+        LinearMemory, Trap, ValType, Value, PAGE_SIZE,
+    };
+
+    /// Completes on the second poll, like an I/O operation that's still in flight at first.
+    struct InFlight(Option<u32>, bool);
+
+    impl Future for InFlight {
+        type Output = Result<Vec<Value>, Trap>;
+
+        fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+            if !self.1 {
+                self.1 = true;
+                cx.waker().wake_by_ref();
+                return Poll::Pending;
+            }
+            Poll::Ready(Ok(vec![Value::I32(self.0.take().unwrap() * 2)]))
+        }
+    }
+
+    struct Wakes(AtomicUsize);
+
+    impl Wake for Wakes {
+        fn wake(self: Arc<Self>) {
+            self.0.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    #[test]
+    pub fn async_host_functions_suspend_the_interpreter() {
+        use crate::Instruction::*;
+
+        let mut host = Host::new();
+        let typ = FuncType::new(vec![ValType::I32], vec![ValType::I32]);
+        host.define_async("env", "fetch", typ, |_, values| {
+            let n = match values[0] {
+                Value::I32(n) => n,
+                _ => unreachable!("the parameter is checked before the call"),
+            };
+            Box::pin(InFlight(Some(n), false))
+        })
+        .unwrap();
+        let module = ModuleBuilder::new()
+            .func(
+                FuncBuilder::new()
+                    .import_from("env", "fetch")
+                    .param(ValType::I32)
+                    .result(ValType::I32),
+            )
+            .func(
+                FuncBuilder::new()
+                    .export_as("main")
+                    .result(ValType::I32)
+                    .body(vec![
+                        I32Const(Value::I32(20)),
+                        Call(0),
+                        I32Const(Value::I32(1)),
+                        I32Add,
+                    ]),
+            );
+        let addr = host.instantiate("test", module.build()).unwrap();
+        let main = host.get_module(addr).unwrap().export_func("main").unwrap();
+
+        let wakes = Arc::new(Wakes(AtomicUsize::new(0)));
+        let waker = Waker::from(wakes.clone());
+        let mut cx = Context::from_waker(&waker);
+        let mut thread = Thread::new();
+        {
+            let mut call = thread.invoke_async(&mut host, main);
+            assert!(Pin::new(&mut call).poll(&mut cx).is_pending());
+            assert_eq!(1, wakes.0.load(Ordering::SeqCst));
+            match Pin::new(&mut call).poll(&mut cx) {
+                Poll::Ready(results) => assert_eq!(vec![Value::I32(41)], results.unwrap()),
+                Poll::Pending => panic!("the host function should have completed"),
+            }
+        }
+        assert_eq!(0, thread.stack().depth());
+
+        // Called synchronously, the thread waits for the future instead
+        assert_eq!(
+            vec![Value::I32(41)],
+            host.invoke(addr, "main", &[]).unwrap()
+        );
+    }
+
+    #[test]
+    pub fn external_tables_and_globals_are_shared() {
+        use crate::Instruction::*;
+
+        struct Linker {
+            tables: [ExternalTable; 1],
+            globals: [ExternalGlobal; 2],
+        }
+
+        impl ExternalModule for Linker {
+            fn name(&self) -> &str {
+                "env"
+            }
+
+            fn funcs(&self) -> &[Arc<ExternalFunc>] {
+                &[]
+            }
+
+            fn mems(&self) -> &[ExternalMemory] {
+                &[]
+            }
+
+            fn tables(&self) -> &[ExternalTable] {
+                &self.tables
+            }
+
+            fn globals(&self) -> &[ExternalGlobal] {
+                &self.globals
+            }
+        }
+
+        let mut host = Host::new();
+        host.external(Linker {
+            tables: [ExternalTable::new("table", 4, None)],
+            globals: [
+                ExternalGlobal::new("__table_base", Value::I32(2), false),
+                ExternalGlobal::new("calls", Value::I32(0), true),
+            ],
+        })
+        .unwrap();
+        let imports = |module: &mut ModuleBuilder| {
+            let imports = [
+                ("table", MemberDesc::Table(TableType::new(4, None))),
+                (
+                    "__table_base",
+                    MemberDesc::Global(GlobalType::new(ValType::I32, false)),
+                ),
+                (
+                    "calls",
+                    MemberDesc::Global(GlobalType::new(ValType::I32, true)),
+                ),
+            ];
+            for (name, desc) in imports {
+                module.imports.push(Import::new("env", name, desc));
+            }
+        };
+
+        // The side module places its function in the table where the linker says, and counts
+        // its calls in the shared global
+        let mut side = ModuleBuilder::new();
+        imports(&mut side);
+        let mut side = side.func(FuncBuilder::new().result(ValType::I32).body(vec![
+            GlobalGet(1),
+            I32Const(Value::I32(1)),
+            I32Add,
+            GlobalSet(1),
+            I32Const(Value::I32(42)),
+        ]));
+        side.elems
+            .push(ElemItem::new(0, Expr::new(vec![GlobalGet(0)]), vec![0]));
+        host.instantiate("side", side.build()).unwrap();
+
+        let mut main = ModuleBuilder::new();
+        imports(&mut main);
+        let main = main.func(
+            FuncBuilder::new()
+                .export_as("main")
+                .result(ValType::I32)
+                .body(vec![GlobalGet(0), CallIndirect(0, 0), GlobalGet(1), I32Add]),
+        );
+        let addr = host.instantiate("main", main.build()).unwrap();
+        assert_eq!(
+            vec![Value::I32(43)],
+            host.invoke(addr, "main", &[]).unwrap()
+        );
+        assert_eq!(
+            vec![Value::I32(44)],
+            host.invoke(addr, "main", &[]).unwrap()
+        );
+    }
+
+    #[test]
+    pub fn external_memories_share_the_embedders_buffer() {
+        struct Shared([ExternalMemory; 1]);
+
+        impl ExternalModule for Shared {
+            fn name(&self) -> &str {
+                "env"
+            }
+
+            fn funcs(&self) -> &[Arc<ExternalFunc>] {
+                &[]
+            }
+
+            fn mems(&self) -> &[ExternalMemory] {
+                &self.0
+            }
+        }
+
+        let mut buf = vec![0; PAGE_SIZE];
+        buf[4] = 7;
+        buf.reserve_exact(2 * PAGE_SIZE);
+        let grown = Arc::new(AtomicUsize::new(0));
+        let storage = {
+            let grown = grown.clone();
+            Arc::new(BufferMemory::new(buf).on_grow(move |len| {
+                grown.store(len, Ordering::SeqCst);
+                len <= 2 * PAGE_SIZE
+            }))
+        };
+        let mut host = Host::new();
+        let mem = ExternalMemory::with_storage("memory", storage.clone(), None);
+        host.external(Shared([mem])).unwrap();
+
+        let module = ModuleBuilder::new()
+            .import_mem("env", "memory", MemoryType::new(1, None))
+            .func(
+                FuncBuilder::new()
+                    .export_as("store")
+                    .result(ValType::I32)
+                    .body(vec![
+                        I32Const(Value::I32(8)),
+                        I32Const(Value::I32(42)),
+                        I32Store(2, 0),
+                        I32Const(Value::I32(4)),
+                        I32Load(2, 0),
+                    ]),
+            )
+            .func(
+                FuncBuilder::new()
+                    .export_as("grow")
+                    .result(ValType::I32)
+                    .body(vec![I32Const(Value::I32(1)), MemoryGrow(0)]),
+            );
+        let addr = host.instantiate("app", module.build()).unwrap();
+
+        // The guest sees what the embedder put in the buffer, and the embedder what it stores
+        assert_eq!(
+            vec![Value::I32(7)],
+            host.invoke(addr, "store", &[]).unwrap()
+        );
+        assert_eq!(42, unsafe { *storage.ptr().add(8) });
+
+        // Growth is up to the embedder
+        assert_eq!(vec![Value::I32(1)], host.invoke(addr, "grow", &[]).unwrap());
+        assert_eq!(2 * PAGE_SIZE, storage.len());
+        assert_eq!(
+            vec![Value::I32(u32::MAX)],
+            host.invoke(addr, "grow", &[]).unwrap()
+        );
+        assert_eq!(3 * PAGE_SIZE, grown.load(Ordering::SeqCst));
+        assert_eq!(2 * PAGE_SIZE, storage.len());
+    }
 }
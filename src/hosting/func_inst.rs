@@ -2,7 +2,8 @@ use std::sync::Arc;
 
 use crate::{
     hosting::{ExternalFunc, ModuleAddr},
-    module::{FuncBody, FuncType},
+    interp::Code,
+    module::FuncType,
 };
 
 addr_type!(FuncAddr);
@@ -11,14 +12,17 @@ pub struct FuncInst {
     typ: FuncType,
     module: ModuleAddr,
     imp: FuncImpl,
+    name: Option<Arc<str>>,
 }
 
 impl FuncInst {
-    pub fn local(typ: FuncType, module: ModuleAddr, func_id: usize, code: FuncBody) -> FuncInst {
+    /// Creates a local function from its lowered body.
+    pub fn local(typ: FuncType, module: ModuleAddr, func_id: usize, code: Arc<Code>) -> FuncInst {
         FuncInst {
             typ,
             module,
             imp: FuncImpl::Local(code, func_id),
+            name: None,
         }
     }
 
@@ -27,9 +31,26 @@ impl FuncInst {
             typ,
             module,
             imp: FuncImpl::External(func),
+            name: None,
         }
     }
 
+    /// Creates a copy of this function for `module`, sharing its code.
+    pub fn fork(&self, module: ModuleAddr) -> FuncInst {
+        FuncInst {
+            typ: self.typ.clone(),
+            module,
+            imp: self.imp.clone(),
+            name: self.name.clone(),
+        }
+    }
+
+    /// Sets the name shown for this function in stack traces, such as `module!function`.
+    pub fn with_name<S: Into<Arc<str>>>(mut self, name: S) -> FuncInst {
+        self.name = Some(name.into());
+        self
+    }
+
     pub fn typ(&self) -> &FuncType {
         &self.typ
     }
@@ -38,12 +59,17 @@ impl FuncInst {
         self.module
     }
 
+    pub fn name(&self) -> Option<&Arc<str>> {
+        self.name.as_ref()
+    }
+
     pub fn imp(&self) -> &FuncImpl {
         &self.imp
     }
 }
 
+#[derive(Clone)]
 pub enum FuncImpl {
-    Local(FuncBody, usize),
+    Local(Arc<Code>, usize),
     External(Arc<ExternalFunc>),
 }
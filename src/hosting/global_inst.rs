@@ -0,0 +1,49 @@
+use std::sync::RwLock;
+
+use crate::{hosting::ModuleAddr, module::GlobalType, Trap, TrapCause, Value};
+
+addr_type!(GlobalAddr);
+
+pub struct GlobalInst {
+    module: ModuleAddr,
+    typ: GlobalType,
+    value: RwLock<Value>,
+}
+
+impl GlobalInst {
+    pub fn new(module: ModuleAddr, typ: GlobalType, value: Value) -> GlobalInst {
+        GlobalInst {
+            module,
+            typ,
+            value: RwLock::new(value),
+        }
+    }
+
+    /// Gets the address of the module that allocated this global.
+    pub fn module(&self) -> ModuleAddr {
+        self.module
+    }
+
+    pub fn typ(&self) -> &GlobalType {
+        &self.typ
+    }
+
+    pub fn get(&self) -> Value {
+        *self.value.read().unwrap()
+    }
+
+    pub fn set(&self, value: Value) -> Result<(), Trap> {
+        if !self.typ.mutable() {
+            Err(TrapCause::ImmutableGlobal.into())
+        } else if !self.typ.typ().accepts(value.typ()) {
+            Err(TrapCause::TypeMismatch {
+                expected: self.typ.typ(),
+                actual: value.typ(),
+            }
+            .into())
+        } else {
+            *self.value.write().unwrap() = value;
+            Ok(())
+        }
+    }
+}
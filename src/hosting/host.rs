@@ -1,74 +1,306 @@
-use std::sync::Arc;
+use std::{
+    any::Any,
+    collections::{HashMap, HashSet},
+    fs::File,
+    io::{self, Read, Write},
+    iter,
+    path::Path,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
 
+#[cfg(feature = "gc")]
+use crate::hosting::{ObjectAddr, ObjectInst};
 use crate::{
+    builder::ModuleBuilder,
     hosting::{
-        ExportInst, ExternVal, ExternalModule, FuncAddr, FuncImpl, FuncInst, MemAddr, MemInst,
-        ModuleAddr, ModuleInst,
+        host_data::HostData, saved_state, write_to, Caller, ConstExpr, Event, EventSink,
+        ExportInst, ExternVal, ExternalFunc, ExternalModule, FuncAddr, FuncImpl, FuncInst,
+        GlobalAddr, GlobalInst, HostFuture, HostSnapshot, ImportResolution, Imports, IntoHostFunc,
+        ItemFilter, LatencyHistogram, MemAddr, MemInst, MemRegion, ModuleAddr, ModuleInst,
+        OutputSink, ResourceLimiter, SlotAddr, Slots, Stub, TableAddr, TableInst, UnresolvedImport,
     },
-    module::{Export, Expr, MemberDesc, Module},
-    Error, Instruction, Location, Value,
+    interp::{Code, PrecompiledModule, Thread},
+    module::{Export, ExportDesc, FuncType, GlobalType, Import, MemberDesc, MemoryType, Module},
+    reader::SectionId,
+    Error, HeapBackend, Location, MemoryBackend, MemoryConfig, SectionOffset, Trap, Value,
 };
 
+/// A callback invoked with the old and new values of a watched global when `global.set` sets
+/// it.
+type GlobalWatcher = Arc<dyn Fn(Value, Value) + Send + Sync>;
+
+/// The result of one of the calls [`Host::invoke_matching`] makes.
+type Invocation = Result<Vec<Value>, Trap>;
+
+/// Holds the modules, functions, tables, memories and globals that have been instantiated,
+/// which are referred to by their addresses.
+///
+/// Everything a host registers belongs to it alone: warthog keeps no registries or caches in
+/// process-global state, so any number of hosts can be used side by side, on one OS thread or
+/// several, with the same module names and conflicting host functions. Addresses are only
+/// meaningful to the host that produced them.
+///
+/// # Thread safety
+///
+/// A host is `Send` and `Sync`, so it can be moved to or shared with other OS threads, but it
+/// isn't a concurrent store: one host runs one call at a time. Locking works at two levels:
+///
+/// * The host's registry of instances and items has no locks of its own. It's only changed
+///   through `&mut Host`, which includes instantiating and calling into guests, so Rust's
+///   borrow rules are the lock. Sharing a host in an `Arc<Mutex<Host>>` lets several OS
+///   threads call guests, but only one after another; an `RwLock` at least lets them inspect
+///   the host while a call runs.
+/// * Items are kept in `Arc`s, and each synchronizes its own mutable state: tables and
+///   globals with a `RwLock`, GC objects with a `Mutex`. Linear memories aren't locked, as
+///   WebAssembly's memory model makes the guest responsible for ordering its accesses; use an
+///   [`ObserverHandle`](crate::interp::ObserverHandle) to read one safely while it runs.
+///
+/// To run guests in parallel, such as a server handling each request on its own OS thread,
+/// give each thread a clone of the host instead. Cloning copies the registry, which refers to
+/// items by `Arc`, so clones share every item that exists when they're made, while instances
+/// created later only exist in the clone that created them. Each clone is then a host of its
+/// own, so [`Thread`]s on several OS threads can run different instances at once; forking an
+/// instance per request with [`Host::fork_instance`] before cloning is the usual way to get
+/// them. A thread claims the memories of the modules it runs for as long as its outermost call
+/// lasts, and calling a function whose memory another thread has claimed traps with
+/// [`TrapCause::MemoryInUse`](crate::TrapCause::MemoryInUse), rather than letting two guests
+/// race on memory that wasn't declared `shared`. Shared memories are never claimed.
+///
+/// Claims only cover guest code. The embedder's own accesses to a memory it gets from
+/// [`Host::get_mem`], such as [`MemInst::read`] and [`MemInst::write`], don't check them, so
+/// reading a memory from outside while another thread runs code over it races with the guest
+/// like a shared memory would. Host functions are called on the thread holding the claim, so
+/// they can use their caller's memory freely.
+///
+/// Host functions, memory backends, event sinks, output sinks, global watchers and host data
+/// are all required to be `Send + Sync` to keep it this way.
 #[derive(Clone)]
 pub struct Host {
-    modules: Vec<Arc<ModuleInst>>,
-    funcs: Vec<Arc<FuncInst>>,
-    mems: Vec<Arc<MemInst>>,
+    modules: Slots<ModuleAddr, Arc<ModuleInst>>,
+    funcs: Slots<FuncAddr, Arc<FuncInst>>,
+    tables: Slots<TableAddr, Arc<TableInst>>,
+    mems: Slots<MemAddr, Arc<MemInst>>,
+    globals: Slots<GlobalAddr, Arc<GlobalInst>>,
+    #[cfg(feature = "gc")]
+    objects: Slots<ObjectAddr, Arc<ObjectInst>>,
+    /// The exports that may be called by the embedder, for modules that have been restricted.
+    invokable: Vec<(ModuleAddr, Vec<String>)>,
+    /// Latencies of calls to external functions, by the calling module. `None` unless enabled.
+    import_latencies: Option<HashMap<(ModuleAddr, FuncAddr), LatencyHistogram>>,
+    memory_backend: Arc<dyn MemoryBackend>,
+    memory_config: MemoryConfig,
+    stubs: HashMap<FuncAddr, Stub>,
+    watchers: HashMap<GlobalAddr, GlobalWatcher>,
+    event_sink: Option<Arc<dyn EventSink>>,
+    limiter: Option<Arc<dyn ResourceLimiter>>,
+    /// Where guests' standard output and error go, if not to the process's own.
+    stdout: Option<OutputSink>,
+    stderr: Option<OutputSink>,
+    data: Option<Box<dyn HostData>>,
+    /// The modules instances were created from, with their lowered code, so they can be
+    /// instantiated again.
+    sources: HashMap<ModuleAddr, (Arc<Module>, Vec<Arc<Code>>)>,
 }
 
-// TODO: Consider if this type needs to be thread-safe
 impl Host {
     pub fn new() -> Host {
         Host {
-            modules: Vec::new(),
-            funcs: Vec::new(),
-            mems: Vec::new(),
+            modules: Slots::new(),
+            funcs: Slots::new(),
+            tables: Slots::new(),
+            mems: Slots::new(),
+            globals: Slots::new(),
+            #[cfg(feature = "gc")]
+            objects: Slots::new(),
+            invokable: Vec::new(),
+            import_latencies: None,
+            memory_backend: Arc::new(HeapBackend),
+            memory_config: MemoryConfig::default(),
+            stubs: HashMap::new(),
+            watchers: HashMap::new(),
+            event_sink: None,
+            limiter: None,
+            stdout: None,
+            stderr: None,
+            data: None,
+            sources: HashMap::new(),
         }
     }
 
-    pub fn get_module(&self, addr: ModuleAddr) -> Arc<ModuleInst> {
-        self.modules[addr.val()].clone()
+    /// Sets the backend that allocates the memories of modules instantiated from now on.
+    /// Memories that have already been allocated are unaffected.
+    pub fn set_memory_backend<B: MemoryBackend + 'static>(&mut self, backend: B) {
+        self.memory_backend = Arc::new(backend);
+    }
+
+    pub fn memory_backend(&self) -> &dyn MemoryBackend {
+        self.memory_backend.as_ref()
+    }
+
+    /// Allocates memories as `config` says from now on, such as to reserve room for them to
+    /// grow or to cap their size. Memories that already exist keep the storage they have.
+    pub fn set_memory_config(&mut self, config: MemoryConfig) {
+        self.memory_config = config;
+    }
+
+    pub fn memory_config(&self) -> &MemoryConfig {
+        &self.memory_config
+    }
+
+    /// Stores embedder state in the host, replacing any that was stored before. Host functions
+    /// are given the host they're called from, so they can use [`Host::data_mut`] to update
+    /// this instead of a global. The data is cloned along with the host.
+    pub fn set_data<T: Any + Clone + Send + Sync>(&mut self, data: T) {
+        self.data = Some(Box::new(data));
+    }
+
+    /// Gets the embedder state stored with [`Host::set_data`], if there is some of type `T`.
+    pub fn data<T: Any>(&self) -> Option<&T> {
+        self.data
+            .as_ref()
+            .and_then(|d| (**d).as_any().downcast_ref())
+    }
+
+    /// Gets the embedder state stored with [`Host::set_data`] mutably, if there is some of
+    /// type `T`.
+    pub fn data_mut<T: Any>(&mut self) -> Option<&mut T> {
+        self.data
+            .as_mut()
+            .and_then(|d| (**d).as_any_mut().downcast_mut())
+    }
+
+    /// Removes the embedder state from the host, returning it if it's of type `T`. Data of
+    /// another type is left in place.
+    pub fn take_data<T: Any>(&mut self) -> Option<T> {
+        self.data_mut::<T>()?;
+        let data = self.data.take()?.into_any();
+        data.downcast().ok().map(|d| *d)
+    }
+
+    /// Gets the instance at `addr`. Fails with [`Error::StaleAddress`] if it has been dropped,
+    /// like the other getters below.
+    pub fn get_module(&self, addr: ModuleAddr) -> Result<Arc<ModuleInst>, Error> {
+        self.modules.checked(addr).cloned()
+    }
+
+    pub fn get_func(&self, addr: FuncAddr) -> Result<Arc<FuncInst>, Error> {
+        self.funcs.checked(addr).cloned()
+    }
+
+    pub fn get_table(&self, addr: TableAddr) -> Result<Arc<TableInst>, Error> {
+        self.tables.checked(addr).cloned()
+    }
+
+    pub fn get_mem(&self, addr: MemAddr) -> Result<Arc<MemInst>, Error> {
+        self.mems.checked(addr).cloned()
+    }
+
+    pub fn get_global(&self, addr: GlobalAddr) -> Result<Arc<GlobalInst>, Error> {
+        self.globals.checked(addr).cloned()
     }
 
-    pub fn get_func(&self, addr: FuncAddr) -> Arc<FuncInst> {
-        self.funcs[addr.val()].clone()
+    #[cfg(feature = "gc")]
+    pub fn get_object(&self, addr: ObjectAddr) -> Result<Arc<ObjectInst>, Error> {
+        self.objects.checked(addr).cloned()
     }
 
-    pub fn get_mem(&self, addr: MemAddr) -> Arc<MemInst> {
-        self.mems[addr.val()].clone()
+    // The interpreter reaches items through addresses the host handed out to the instances
+    // that are still alive, so it uses these, which panic on a stale address.
+
+    pub(crate) fn module(&self, addr: ModuleAddr) -> Arc<ModuleInst> {
+        self.modules[addr].clone()
+    }
+
+    pub(crate) fn func(&self, addr: FuncAddr) -> Arc<FuncInst> {
+        self.funcs[addr].clone()
+    }
+
+    pub(crate) fn table(&self, addr: TableAddr) -> Arc<TableInst> {
+        self.tables[addr].clone()
+    }
+
+    pub(crate) fn mem(&self, addr: MemAddr) -> Arc<MemInst> {
+        self.mems[addr].clone()
+    }
+
+    pub(crate) fn global(&self, addr: GlobalAddr) -> Arc<GlobalInst> {
+        self.globals[addr].clone()
+    }
+
+    #[cfg(feature = "gc")]
+    pub(crate) fn object(&self, addr: ObjectAddr) -> Arc<ObjectInst> {
+        self.objects[addr].clone()
+    }
+
+    /// Allocates a struct or array on the heap.
+    #[cfg(feature = "gc")]
+    pub fn alloc_object(&mut self, object: ObjectInst) -> ObjectAddr {
+        let addr = self.objects.next_addr();
+        self.objects.push(Arc::new(object));
+        addr
     }
 
     pub fn modules<'a>(&'a self) -> impl 'a + Iterator<Item = Arc<ModuleInst>> {
         self.modules.iter().cloned()
     }
 
+    /// Enumerates the addresses of the modules in the host, in the order of their slots.
+    pub fn module_addrs<'a>(&'a self) -> impl 'a + Iterator<Item = ModuleAddr> {
+        self.modules.entries().map(|(addr, _)| addr)
+    }
+
+    /// Gets the address of the instance in slot `index`, if there is one.
+    pub(crate) fn module_at(&self, index: usize) -> Option<ModuleAddr> {
+        self.modules.addr(index)
+    }
+
+    /// Gets the address of the function in slot `index`, if there is one.
+    pub(crate) fn func_at(&self, index: usize) -> Option<FuncAddr> {
+        self.funcs.addr(index)
+    }
+
     pub fn funcs<'a>(&'a self) -> impl 'a + Iterator<Item = Arc<FuncInst>> {
         self.funcs.iter().cloned()
     }
 
+    pub fn tables<'a>(&'a self) -> impl 'a + Iterator<Item = Arc<TableInst>> {
+        self.tables.iter().cloned()
+    }
+
     pub fn mems<'a>(&'a self) -> impl 'a + Iterator<Item = Arc<MemInst>> {
         self.mems.iter().cloned()
     }
 
+    pub fn globals<'a>(&'a self) -> impl 'a + Iterator<Item = Arc<GlobalInst>> {
+        self.globals.iter().cloned()
+    }
+
     pub fn find_module(&self, name: &str) -> Option<ModuleAddr> {
         self.modules
-            .iter()
-            .position(|m| m.name() == name)
-            .map(|a| ModuleAddr::new(a + 1).expect("Searched module address should be non-zero!"))
+            .entries()
+            .find(|(_, m)| m.name() == name)
+            .map(|(a, _)| a)
+    }
+
+    pub fn resolve_mem(&self, module: ModuleAddr, mem_idx: usize) -> Option<MemAddr> {
+        self.modules.get(module)?.get_mem(mem_idx)
+    }
+
+    pub fn resolve_func(&self, module: ModuleAddr, func_idx: usize) -> Option<FuncAddr> {
+        self.modules.get(module)?.get_func(func_idx)
     }
 
-    pub fn resolve_mem(&self, module: ModuleAddr, mem_idx: usize) -> MemAddr {
-        let module_inst = &self.modules[module.val()];
-        module_inst.get_mem(mem_idx)
+    pub fn resolve_table(&self, module: ModuleAddr, table_idx: usize) -> Option<TableAddr> {
+        self.modules.get(module)?.get_table(table_idx)
     }
 
-    pub fn resolve_func(&self, module: ModuleAddr, func_idx: usize) -> FuncAddr {
-        let module_inst = &self.modules[module.val()];
-        module_inst.get_func(func_idx)
+    pub fn resolve_global(&self, module: ModuleAddr, global_idx: usize) -> Option<GlobalAddr> {
+        self.modules.get(module)?.get_global(global_idx)
     }
 
     pub fn resolve_import(&self, module: ModuleAddr, name: &str) -> Result<&ExportInst, Error> {
-        let module_inst = &self.modules[module.val()];
+        let module_inst = self.modules.checked(module)?;
         if let Some(export) = module_inst.find_export(name) {
             Ok(export)
         } else {
@@ -79,210 +311,1701 @@ impl Host {
         }
     }
 
-    /// Evaluates an expression at the module scope.
-    pub fn eval_expr(&mut self, expr: &Expr) -> Result<Value, Error> {
-        // Offset must be a constant expression
-        match expr.instructions() {
-            [Instruction::I32Const(v)] => Ok(*v),
-            _ => panic!("expr not implemented!"),
+    /// Gets the memory region named `name` that `module` declares, see [`MemRegion`].
+    pub fn region(&self, module: ModuleAddr, name: &str) -> Result<MemRegion, Error> {
+        let start = self.region_global(module, &format!("__region_{}", name))?;
+        let len = self.region_global(module, &format!("__region_{}_size", name))?;
+        match self.modules[module].get_mem(0) {
+            Some(mem) => Ok(MemRegion::new(mem, start, len)),
+            None => Err(Error::UnknownMemoryIndex {
+                index: 0,
+                at: SectionOffset::in_section(SectionId::Memory),
+            }),
+        }
+    }
+
+    /// Gets the value of an integer global that `module` exports as `name`.
+    fn region_global(&self, module: ModuleAddr, name: &str) -> Result<usize, Error> {
+        if let ExternVal::Global(addr) = *self.resolve_import(module, name)?.value() {
+            match self.globals[addr].get() {
+                Value::I32(x) => return Ok(x as usize),
+                Value::I64(x) => return Ok(x as usize),
+                _ => {}
+            }
+        }
+        Err(Error::ExportTypeMismatch {
+            module: self.modules[module].name().to_owned(),
+            name: name.to_owned(),
+        })
+    }
+
+    /// Restricts the functions of `module` that [`Thread::call`](crate::interp::Thread::call)
+    /// may invoke to the exports named in `names`, replacing any previous restriction. Calls
+    /// made from WebAssembly code, including calls to imported functions, are unaffected.
+    pub fn set_invokable<I, S>(&mut self, module: ModuleAddr, names: I) -> Result<(), Error>
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        let module_inst = self.modules.checked(module)?;
+        let mut allowed = Vec::new();
+        for name in names {
+            let name = name.into();
+            module_inst.export_func(&name)?;
+            allowed.push(name);
+        }
+
+        self.invokable.retain(|(m, _)| *m != module);
+        self.invokable.push((module, allowed));
+        Ok(())
+    }
+
+    /// Gets a boolean indicating if `func` may be called by the embedder. Functions are
+    /// invokable unless [`Host::set_invokable`] has restricted the module that defines them.
+    pub fn is_invokable(&self, func: FuncAddr) -> Result<bool, Error> {
+        let module = self.funcs.checked(func)?.module();
+        Ok(match self.invokable.iter().find(|(m, _)| *m == module) {
+            Some((_, allowed)) => self.modules.checked(module)?.exports().iter().any(|e| {
+                *e.value() == ExternVal::Func(func) && allowed.iter().any(|n| n == e.name())
+            }),
+            None => true,
+        })
+    }
+
+    /// Calls the function `module` exports as `name` with `args` on a new thread, and returns
+    /// its results. This is all that embedders who just want to run an export need; use a
+    /// [`Thread`] directly to configure fuel, limits or debugging.
+    ///
+    /// The arguments are checked against the function's parameters before it's called, and
+    /// a trap is returned as [`Error::Trap`].
+    pub fn invoke(
+        &mut self,
+        module: ModuleAddr,
+        name: &str,
+        args: &[Value],
+    ) -> Result<Vec<Value>, Error> {
+        let module_inst = self.modules.checked(module)?;
+        let func = module_inst.export_func(name)?;
+        let params = self.funcs.checked(func)?.typ().params();
+        if params.len() != args.len() || params.iter().zip(args).any(|(p, a)| !p.accepts(a.typ())) {
+            return Err(Error::ArgumentTypeMismatch {
+                module: module_inst.name().to_owned(),
+                name: name.to_owned(),
+                expected: params.to_vec(),
+                actual: args.iter().map(|a| a.typ()).collect(),
+            });
+        }
+
+        Ok(Thread::new().call(self, module, func, args)?)
+    }
+
+    /// Calls each function `module` exports under a name matching `pattern`, one after another
+    /// on `thread`, and collects the results or trap of each call by export name, in the order
+    /// the module exports them. This is the core of a test runner for tests written in the
+    /// module itself.
+    ///
+    /// A `*` in `pattern` matches any sequence of characters, so `test_*` matches `test_add`.
+    /// A pattern without a `*` matches names starting with it. Only functions without
+    /// parameters are called, and a trap in one function doesn't stop the rest being called.
+    pub fn invoke_matching(
+        &mut self,
+        thread: &mut Thread,
+        module: ModuleAddr,
+        pattern: &str,
+    ) -> Result<Vec<(String, Invocation)>, Error> {
+        let funcs: Vec<_> = self
+            .modules
+            .checked(module)?
+            .exports()
+            .iter()
+            .filter(|e| matches_pattern(pattern, e.name()))
+            .filter_map(|e| match *e.value() {
+                ExternVal::Func(func)
+                    if self
+                        .funcs
+                        .get(func)
+                        .is_some_and(|f| f.typ().params().is_empty()) =>
+                {
+                    Some((e.name().to_owned(), func))
+                }
+                _ => None,
+            })
+            .collect();
+        Ok(funcs
+            .into_iter()
+            .map(|(name, func)| (name, thread.call(self, module, func, &[])))
+            .collect())
+    }
+
+    /// Makes calls to `func` behave as described by `stub` instead of running the function,
+    /// whether they come from WebAssembly code or from the embedder. To stub a function by its
+    /// index in a module, get its address with [`Host::resolve_func`].
+    pub fn stub_func(&mut self, func: FuncAddr, stub: Stub) {
+        self.stubs.insert(func, stub);
+    }
+
+    /// Stubs the function of `module` that has the debug name, or failing that the export name,
+    /// `name`. See [`Host::stub_func`].
+    pub fn stub_func_named(
+        &mut self,
+        module: ModuleAddr,
+        name: &str,
+        stub: Stub,
+    ) -> Result<FuncAddr, Error> {
+        let module_inst = self.modules.checked(module)?.clone();
+        let func = module_inst
+            .funcs()
+            .iter()
+            .find(|f| {
+                let func = self.funcs.get(**f);
+                func.and_then(|func| self.func_name(func)).as_deref() == Some(name)
+            })
+            .cloned();
+        let func = match func {
+            Some(func) => func,
+            None => match self.resolve_import(module, name)?.value() {
+                ExternVal::Func(func) => *func,
+                _ => {
+                    return Err(Error::ExportTypeMismatch {
+                        module: module_inst.name().to_owned(),
+                        name: name.to_owned(),
+                    })
+                }
+            },
+        };
+        self.stub_func(func, stub);
+        Ok(func)
+    }
+
+    /// Runs `func` normally again.
+    pub fn unstub_func(&mut self, func: FuncAddr) {
+        self.stubs.remove(&func);
+    }
+
+    /// Gets how calls to `func` are stubbed, if they are.
+    pub fn stub(&self, func: FuncAddr) -> Option<Stub> {
+        if self.stubs.is_empty() {
+            return None;
+        }
+        self.stubs.get(&func).cloned()
+    }
+
+    /// Calls `watcher` with the old and new value of the mutable global `global` each time a
+    /// `global.set` instruction sets it, replacing any previous watcher. This lets the embedder
+    /// observe flags that the module raises, without polling the global after every call.
+    pub fn watch_global<F: Fn(Value, Value) + Send + Sync + 'static>(
+        &mut self,
+        global: GlobalAddr,
+        watcher: F,
+    ) {
+        self.watchers.insert(global, Arc::new(watcher));
+    }
+
+    /// Watches the global `module` exports as `name`. See [`Host::watch_global`].
+    pub fn watch_global_named<F: Fn(Value, Value) + Send + Sync + 'static>(
+        &mut self,
+        module: ModuleAddr,
+        name: &str,
+        watcher: F,
+    ) -> Result<GlobalAddr, Error> {
+        let global = self.modules.checked(module)?.export_global(name)?;
+        self.watch_global(global, watcher);
+        Ok(global)
+    }
+
+    pub fn unwatch_global(&mut self, global: GlobalAddr) {
+        self.watchers.remove(&global);
+    }
+
+    /// Sets a global on behalf of a `global.set` instruction, and notifies its watcher.
+    pub(crate) fn set_global(&self, global: GlobalAddr, value: Value) -> Result<(), Trap> {
+        let inst = &self.globals[global];
+        match self.watchers.get(&global) {
+            None => inst.set(value),
+            Some(watcher) => {
+                let old = inst.get();
+                inst.set(value)?;
+                watcher(old, value);
+                Ok(())
+            }
+        }
+    }
+
+    /// Reports instantiations, calls to external functions, traps and memory growth to `sink`
+    /// from now on, replacing any previous sink.
+    pub fn set_event_sink<S: EventSink + 'static>(&mut self, sink: S) {
+        self.event_sink = Some(Arc::new(sink));
+    }
+
+    /// Removes the event sink, if any.
+    pub fn take_event_sink(&mut self) -> Option<Arc<dyn EventSink>> {
+        self.event_sink.take()
+    }
+
+    pub fn has_event_sink(&self) -> bool {
+        self.event_sink.is_some()
+    }
+
+    /// Consults `limiter` before memories, tables and arrays are allocated or grown from now on,
+    /// replacing any previous limiter. Memories and tables that already exist are not checked
+    /// again.
+    pub fn set_limiter<L: ResourceLimiter + 'static>(&mut self, limiter: L) {
+        self.limiter = Some(Arc::new(limiter));
+    }
+
+    /// Removes the resource limiter, if any.
+    pub fn take_limiter(&mut self) -> Option<Arc<dyn ResourceLimiter>> {
+        self.limiter.take()
+    }
+
+    /// Sends what guests write to their standard output through host functions, such as
+    /// `spectest.print_i32` or WASI's `fd_write`, to `writer` instead of the process's standard
+    /// output. Use an [`OutputBuffer`](crate::hosting::OutputBuffer) to capture it.
+    pub fn set_stdout<W: Write + Send + 'static>(&mut self, writer: W) {
+        self.stdout = Some(Arc::new(Mutex::new(writer)));
+    }
+
+    /// Sends what guests write to their standard error to `writer`. See [`Host::set_stdout`].
+    pub fn set_stderr<W: Write + Send + 'static>(&mut self, writer: W) {
+        self.stderr = Some(Arc::new(Mutex::new(writer)));
+    }
+
+    /// Removes the standard output sink, if any, so guests write to the process's again.
+    pub fn take_stdout(&mut self) -> Option<Arc<Mutex<dyn Write + Send>>> {
+        self.stdout.take()
+    }
+
+    /// Removes the standard error sink, if any.
+    pub fn take_stderr(&mut self) -> Option<Arc<Mutex<dyn Write + Send>>> {
+        self.stderr.take()
+    }
+
+    /// Writes `bytes` to the guests' standard output, for host functions to print with.
+    pub fn write_stdout(&self, bytes: &[u8]) -> io::Result<()> {
+        write_to(self.stdout.as_ref(), io::stdout(), bytes)
+    }
+
+    /// Writes `bytes` to the guests' standard error.
+    pub fn write_stderr(&self, bytes: &[u8]) -> io::Result<()> {
+        write_to(self.stderr.as_ref(), io::stderr(), bytes)
+    }
+
+    /// Asks the resource limiter, if any, whether `mem` may grow to `desired` bytes.
+    pub(crate) fn allows_memory_growth(&self, mem: &MemInst, desired: usize) -> bool {
+        self.limiter.as_ref().is_none_or(|limiter| {
+            let current = mem.memory().len();
+            limiter.memory_growing(mem.module(), current, desired, mem.memory().max_size())
+        })
+    }
+
+    /// Asks the resource limiter, if any, whether the instance at `module` may allocate an
+    /// array of `len` elements.
+    #[cfg(feature = "gc")]
+    pub(crate) fn allows_array(&self, module: ModuleAddr, len: usize) -> bool {
+        self.limiter
+            .as_ref()
+            .is_none_or(|limiter| limiter.array_allocating(module, len))
+    }
+
+    /// Gets the size and maximum size, in bytes, of a memory of type `typ`, taking the
+    /// memory configuration's limit into account.
+    fn mem_limits(&self, typ: &MemoryType) -> (usize, Option<usize>) {
+        let bytes = |pages: usize| pages.saturating_mul(typ.page_size());
+        let max = match (typ.max(), self.memory_config.max_pages) {
+            (Some(max), Some(cap)) => Some(max.min(cap)),
+            (max, cap) => max.or(cap),
+        };
+        (bytes(typ.min()), max.map(bytes))
+    }
+
+    /// Asks the resource limiter, if any, whether the instance `name` at `addr` may allocate
+    /// tables and memories of these sizes and maximums, in elements and bytes respectively.
+    fn limit_allocations<T, M>(
+        &self,
+        addr: ModuleAddr,
+        name: &str,
+        tables: T,
+        mems: M,
+    ) -> Result<(), Error>
+    where
+        T: IntoIterator<Item = (usize, Option<usize>)>,
+        M: IntoIterator<Item = (usize, Option<usize>)>,
+    {
+        let exceeded = |resource| Error::ResourceLimitExceeded {
+            module: name.to_owned(),
+            resource,
+        };
+        let limiter = self.limiter.as_ref();
+        for (size, max) in tables {
+            if limiter.is_some_and(|limiter| !limiter.table_growing(addr, 0, size, max)) {
+                return Err(exceeded("table"));
+            }
+        }
+        for (size, max) in mems {
+            // Memories that start larger than the memory configuration allows are refused too
+            if max.is_some_and(|max| size > max)
+                || limiter.is_some_and(|limiter| !limiter.memory_growing(addr, 0, size, max))
+            {
+                return Err(exceeded("memory"));
+            }
+        }
+        Ok(())
+    }
+
+    /// Reports `event` to the event sink, if there is one.
+    pub fn report(&self, event: &Event) {
+        if let Some(sink) = &self.event_sink {
+            sink.event(event);
+        }
+    }
+
+    /// Reports an [`Event::Metrics`] describing the current size of the host.
+    pub fn report_metrics(&self) {
+        if self.event_sink.is_some() {
+            self.report(&Event::Metrics {
+                modules: self.modules.iter().count(),
+                funcs: self.funcs.iter().count(),
+                mems: self.mems.iter().count(),
+                memory_bytes: self.mems.iter().map(|m| m.memory().len()).sum(),
+            });
+        }
+    }
+
+    fn report_instantiated(&self, module: ModuleAddr) {
+        if self.event_sink.is_some() {
+            self.report(&Event::Instantiated {
+                module,
+                name: self.modules[module].name(),
+            });
+        }
+    }
+
+    /// Enables or disables recording how long each call to an external (host) function takes.
+    /// Disabling discards any latencies recorded so far.
+    pub fn track_import_latency(&mut self, enabled: bool) {
+        match (enabled, &self.import_latencies) {
+            (true, None) => self.import_latencies = Some(HashMap::new()),
+            (false, _) => self.import_latencies = None,
+            _ => {}
+        }
+    }
+
+    pub fn is_tracking_import_latency(&self) -> bool {
+        self.import_latencies.is_some()
+    }
+
+    /// Gets the latencies of calls `module` made to external functions, ordered by function.
+    pub fn import_latencies(&self, module: ModuleAddr) -> Vec<(FuncAddr, &LatencyHistogram)> {
+        let mut latencies: Vec<_> = self
+            .import_latencies
+            .iter()
+            .flat_map(|l| l.iter())
+            .filter(|((caller, _), _)| *caller == module)
+            .map(|((_, func), histogram)| (*func, histogram))
+            .collect();
+        latencies.sort_by_key(|(func, _)| func.val());
+        latencies
+    }
+
+    pub(crate) fn record_import_latency(
+        &mut self,
+        caller: ModuleAddr,
+        func: FuncAddr,
+        duration: Duration,
+    ) {
+        if let Some(latencies) = &mut self.import_latencies {
+            latencies
+                .entry((caller, func))
+                .or_default()
+                .record(duration);
         }
     }
 
     /// Resolves a [`Location`] based on a provided [`FuncAddr`] and offset
     pub fn get_location(&self, addr: FuncAddr, offset: usize) -> Option<Location> {
-        if addr.val() < self.funcs.len() {
-            let func = &self.funcs[addr.val()];
-            let module = &self.modules[func.module().val()];
-
-            let func_name = match func.imp() {
-                FuncImpl::External(f) => Some(f.name().to_owned()),
-                FuncImpl::Local(_, id) => module
-                    .names()
-                    .and_then(|n| n.funcs().get(*id))
-                    .and_then(|n| n.func_name())
-                    .map(|x| x.to_owned()),
-            };
+        let func = self.funcs.get(addr)?;
+        let module = &self.modules[func.module()];
+        Some(Location::new(
+            func.module(),
+            addr,
+            Some(module.name().to_owned()),
+            self.func_name(func),
+            offset,
+        ))
+    }
 
-            Some(Location::new(
-                func.module(),
-                addr,
-                Some(module.name().to_owned()),
-                func_name,
-                offset,
-            ))
-        } else {
-            None
+    /// Takes a [`HostSnapshot`] of the items currently in the host.
+    pub fn snapshot(&self) -> HostSnapshot {
+        HostSnapshot {
+            modules: self.modules.len(),
+            funcs: self.funcs.len(),
+            tables: self.tables.len(),
+            mems: self.mems.len(),
+            globals: self.globals.len(),
+        }
+    }
+
+    /// Saves the contents of the memories, globals and tables of every instance to a file at
+    /// `path`, so the host can be checkpointed and [loaded](Host::load) again after a restart.
+    /// The file starts with a magic number and a version, followed by a chunk for each
+    /// instance that is checksummed to catch corruption.
+    ///
+    /// Code isn't saved, and neither are GC objects: globals holding references can't be
+    /// saved.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), Error> {
+        let mut file = io::BufWriter::new(File::create(path)?);
+        self.save_to(&mut file)?;
+        file.flush()?;
+        Ok(())
+    }
+
+    /// Saves the state [`Host::save`] does to `writer`, such as a `Vec<u8>` to checkpoint a
+    /// long-running guest in memory. A paused [`Thread`](crate::interp::Thread) can be saved
+    /// alongside it with [`Thread::save_to`](crate::interp::Thread::save_to).
+    pub fn save_to<W: Write>(&self, writer: &mut W) -> Result<(), Error> {
+        saved_state::write(self, writer)
+    }
+
+    /// Loads the state saved by [`Host::save`] from the file at `path`, replacing the contents
+    /// of the memories, globals and tables of every instance.
+    ///
+    /// The host must have instantiated the same modules, in the same order, as the host that
+    /// was saved. The whole file is checked before anything is changed, so if it's corrupt or
+    /// doesn't fit, [`Error::InvalidState`] is returned and the host is left as it was.
+    pub fn load<P: AsRef<Path>>(&self, path: P) -> Result<(), Error> {
+        let mut file = io::BufReader::new(File::open(path)?);
+        self.load_from(&mut file)
+    }
+
+    /// Loads the state [`Host::save_to`] saved from `reader`, like [`Host::load`] does.
+    pub fn load_from<R: Read>(&self, reader: &mut R) -> Result<(), Error> {
+        saved_state::read(self, reader)
+    }
+
+    /// Enumerates the modules in the snapshot whose name starts with `prefix`.
+    pub fn find_modules<'a>(
+        &'a self,
+        snapshot: HostSnapshot,
+        prefix: &'a str,
+    ) -> impl 'a + Iterator<Item = ModuleAddr> {
+        self.modules
+            .entries()
+            .filter(move |(a, m)| a.val() < snapshot.modules && m.name().starts_with(prefix))
+            .map(|(a, _)| a)
+    }
+
+    /// Enumerates the items in the snapshot that match `filter`.
+    ///
+    /// Items are produced grouped by kind (functions, tables, memories, then globals) and
+    /// ordered by address within each kind, so the results for a given snapshot and filter are
+    /// stable and can be paged through with `skip` and `take`.
+    pub fn items<'a>(
+        &'a self,
+        snapshot: HostSnapshot,
+        filter: &'a ItemFilter,
+    ) -> impl 'a + Iterator<Item = ExternVal> {
+        // When filtering by module, only that module's members need to be considered.
+        let candidates: Box<dyn 'a + Iterator<Item = ExternVal>> = match filter.module {
+            // A module that has been dropped has no members left
+            Some(module) => match self.modules.get(module) {
+                Some(inst) => Box::new(
+                    inst.funcs()
+                        .iter()
+                        .map(|a| ExternVal::Func(*a))
+                        .chain(inst.tables().iter().map(|a| ExternVal::Table(*a)))
+                        .chain(inst.mems().iter().map(|a| ExternVal::Mem(*a)))
+                        .chain(inst.globals().iter().map(|a| ExternVal::Global(*a)))
+                        .filter(move |item| self.owner(*item).ok() == Some(module)),
+                ),
+                None => Box::new(iter::empty()),
+            },
+            None => Box::new(
+                addrs(&self.funcs, snapshot.funcs, ExternVal::Func)
+                    .chain(addrs(&self.tables, snapshot.tables, ExternVal::Table))
+                    .chain(addrs(&self.mems, snapshot.mems, ExternVal::Mem))
+                    .chain(addrs(&self.globals, snapshot.globals, ExternVal::Global)),
+            ),
+        };
+
+        candidates.filter(move |item| {
+            filter.allows_kind(item.kind())
+                && in_snapshot(&snapshot, *item)
+                && (filter.name_prefix.is_none()
+                    || filter.allows_name(self.item_name(*item).as_deref()))
+        })
+    }
+
+    /// Gets the address of the module that allocated an item.
+    pub fn owner(&self, item: ExternVal) -> Result<ModuleAddr, Error> {
+        Ok(match item {
+            ExternVal::Func(a) => self.funcs.checked(a)?.module(),
+            ExternVal::Table(a) => self.tables.checked(a)?.module(),
+            ExternVal::Mem(a) => self.mems.checked(a)?.module(),
+            ExternVal::Global(a) => self.globals.checked(a)?.module(),
+        })
+    }
+
+    /// Gets the name of an item.
+    ///
+    /// Functions are named by the debug names of their module (or the name given by the external
+    /// module that defined them). Other items, and functions without a debug name, are named by
+    /// the export their owning module provides them under, if any. Items that have been
+    /// dropped have no name.
+    pub fn item_name(&self, item: ExternVal) -> Option<String> {
+        let debug_name = match item {
+            ExternVal::Func(a) => self.func_name(self.funcs.get(a)?),
+            _ => None,
+        };
+        debug_name.or_else(|| {
+            // Items outlive their owner if another instance still uses them
+            self.modules
+                .get(self.owner(item).ok()?)?
+                .exports()
+                .iter()
+                .find(|e| *e.value() == item)
+                .map(|e| e.name().to_owned())
+        })
+    }
+
+    fn func_name(&self, func: &FuncInst) -> Option<String> {
+        match func.imp() {
+            FuncImpl::External(f) => Some(f.name().to_owned()),
+            FuncImpl::Local(_, id) => self
+                .modules
+                .get(func.module())?
+                .names()
+                .and_then(|n| n.funcs().get(*id))
+                .and_then(|n| n.func_name())
+                .map(|x| x.to_owned()),
         }
     }
 
     /// Instantiates an external module.
     pub fn external<M: ExternalModule>(&mut self, module: M) -> Result<ModuleAddr, Error> {
-        let module_addr = ModuleAddr::new(self.modules.len() + 1)
-            .expect("New module address should be non-zero!");
+        let module_addr = self.modules.next_addr();
+        let table_limits = module
+            .tables()
+            .iter()
+            .map(|table| (table.typ().min(), table.typ().max()));
+        let mem_limits = module.mems().iter().map(|mem| self.mem_limits(mem.typ()));
+        self.limit_allocations(module_addr, module.name(), table_limits, mem_limits)?;
 
         let mut funcs = Vec::new();
+        let mut tables = Vec::new();
+        let mut mems = Vec::new();
+        let mut globals = Vec::new();
         let mut exports = Vec::new();
         for (idx, func) in module.funcs().iter().enumerate() {
             // Allocate a func in the host
-            let func_addr = FuncAddr::new(self.funcs.len() + 1)
-                .expect("New function address should be non-zero!");
-            let func_inst = FuncInst::external(func.typ().clone(), module_addr, func.clone());
+            let func_addr = self.funcs.next_addr();
+            let func_inst = FuncInst::external(func.typ().clone(), module_addr, func.clone())
+                .with_name(format!("{}!{}", module.name(), func.name()));
             self.funcs.push(Arc::new(func_inst));
             funcs.push(func_addr);
-            exports.push(Export::new(
-                func.name().to_owned(),
-                MemberDesc::Function(idx),
-            ))
+            exports.push(Export::func(func.name(), idx))
         }
 
-        // Export memories
-        for mem in module.mems() {
-            exports.push(Export::new(
-                mem.name().to_owned(),
-                MemberDesc::Memory(mem.typ().clone()),
-            ));
+        // Allocate and export memories
+        for (idx, mem) in module.mems().iter().enumerate() {
+            let mem_inst = match mem.storage() {
+                Some(storage) => MemInst::with_storage(
+                    module_addr,
+                    Box::new(storage.clone()),
+                    mem.typ().min(),
+                    mem.typ().max(),
+                ),
+                None => self.alloc_mem_inst(module_addr, module.name(), mem.typ())?,
+            };
+            mems.push(self.alloc_mem(mem_inst));
+            exports.push(Export::mem(mem.name(), idx));
+        }
+
+        // Allocate and export tables and globals
+        for (idx, table) in module.tables().iter().enumerate() {
+            tables.push(self.tables.next_addr());
+            self.tables
+                .push(Arc::new(TableInst::from_type(module_addr, table.typ())));
+            exports.push(Export::table(table.name(), idx));
+        }
+        for (idx, global) in module.globals().iter().enumerate() {
+            globals.push(self.globals.next_addr());
+            self.globals.push(Arc::new(GlobalInst::new(
+                module_addr,
+                global.typ(),
+                global.value(),
+            )));
+            exports.push(Export::global(global.name(), idx));
         }
 
         // Export the synthetic module
-        let exports = self.export_module(&funcs, &exports)?;
+        let exports = export_module(&funcs, &tables, &mems, &globals, &exports)?;
 
         // Register the module and return
         self.modules.push(Arc::new(ModuleInst::new(
             module.name().to_owned(),
-            funcs,
             Vec::new(),
+            funcs,
+            tables,
+            mems,
+            globals,
             exports,
             None,
         )));
+        self.report_instantiated(module_addr);
         Ok(module_addr)
     }
 
+    /// Defines a host function from a closure, exporting it from the module named `module` so
+    /// later instances can import it. The function's type is taken from the closure's
+    /// signature, and its arguments and results are converted to and from [`Value`]s:
+    ///
+    /// ```
+    /// # use warthog::hosting::Host;
+    /// let mut host = Host::new();
+    /// host.define("env", "add", |a: i32, b: i32| -> i32 { a + b }).unwrap();
+    /// ```
+    ///
+    /// If there is no module named `module`, an empty one is registered first. Fails with
+    /// [`Error::DuplicateExportName`] if the module already exports `name`.
+    pub fn define<P, R, F: IntoHostFunc<P, R>>(
+        &mut self,
+        module: &str,
+        name: &str,
+        f: F,
+    ) -> Result<FuncAddr, Error> {
+        let func = ExternalFunc::wrap(name, f);
+        self.define_external(module, func)
+    }
+
+    /// Defines a host function of type `typ`, exporting it from the module named `module` so
+    /// later instances can import it. Unlike [`Host::define`], `f` works with [`Value`]s
+    /// directly, so it can be given any type.
+    ///
+    /// If there is no module named `module`, an empty one is registered first. Fails with
+    /// [`Error::DuplicateExportName`] if the module already exports `name`.
+    pub fn define_func<F>(
+        &mut self,
+        module: &str,
+        name: &str,
+        typ: FuncType,
+        f: F,
+    ) -> Result<FuncAddr, Error>
+    where
+        F: Fn(&mut Caller, &[Value]) -> Result<Vec<Value>, Trap> + Send + Sync + 'static,
+    {
+        self.define_external(module, ExternalFunc::new(name, typ, f))
+    }
+
+    /// Defines an asynchronous host function of type `typ`, exporting it from the module named
+    /// `module`, see [`AsyncHostFunc`](crate::hosting::AsyncHostFunc). Calls made through
+    /// [`Thread::invoke_async`] suspend while its future is pending; other calls block until it
+    /// completes.
+    ///
+    /// If there is no module named `module`, an empty one is registered first. Fails with
+    /// [`Error::DuplicateExportName`] if the module already exports `name`.
+    pub fn define_async<F>(
+        &mut self,
+        module: &str,
+        name: &str,
+        typ: FuncType,
+        f: F,
+    ) -> Result<FuncAddr, Error>
+    where
+        F: Fn(&mut Caller, &[Value]) -> HostFuture + Send + Sync + 'static,
+    {
+        self.define_external(module, ExternalFunc::new_async(name, typ, f))
+    }
+
+    /// Defines a memory of type `typ`, exporting it from the module named `module`. See
+    /// [`Host::define_func`].
+    pub fn define_memory(
+        &mut self,
+        module: &str,
+        name: &str,
+        typ: MemoryType,
+    ) -> Result<MemAddr, Error> {
+        let module_addr = self.define_module(module, name)?;
+        self.limit_allocations(module_addr, module, None, Some(self.mem_limits(&typ)))?;
+        let mem_inst = self.alloc_mem_inst(module_addr, module, &typ)?;
+        let mem_addr = self.alloc_mem(mem_inst);
+        Arc::make_mut(&mut self.modules[module_addr]).add_export(name, ExternVal::Mem(mem_addr))?;
+        Ok(mem_addr)
+    }
+
+    /// Defines a global holding `value`, exporting it from the module named `module`. See
+    /// [`Host::define_func`].
+    pub fn define_global(
+        &mut self,
+        module: &str,
+        name: &str,
+        value: Value,
+        mutable: bool,
+    ) -> Result<GlobalAddr, Error> {
+        let module_addr = self.define_module(module, name)?;
+        let global_addr = self.globals.next_addr();
+        let typ = GlobalType::new(value.typ(), mutable);
+        self.globals
+            .push(Arc::new(GlobalInst::new(module_addr, typ, value)));
+        Arc::make_mut(&mut self.modules[module_addr])
+            .add_export(name, ExternVal::Global(global_addr))?;
+        Ok(global_addr)
+    }
+
+    fn define_external(&mut self, module: &str, func: ExternalFunc) -> Result<FuncAddr, Error> {
+        let module_addr = self.define_module(module, func.name())?;
+        let func_addr = self.funcs.next_addr();
+        Arc::make_mut(&mut self.modules[module_addr])
+            .add_export(func.name(), ExternVal::Func(func_addr))?;
+        let func_name = format!("{}!{}", module, func.name());
+        let func_inst = FuncInst::external(func.typ().clone(), module_addr, Arc::new(func))
+            .with_name(func_name);
+        self.funcs.push(Arc::new(func_inst));
+        Ok(func_addr)
+    }
+
+    /// Finds the module named `module` for an item to be defined in, registering an empty one
+    /// if there isn't one. Fails if `name` is already exported, before anything is allocated.
+    fn define_module(&mut self, module: &str, name: &str) -> Result<ModuleAddr, Error> {
+        if let Some(addr) = self.find_module(module) {
+            if self.modules[addr].find_export(name).is_some() {
+                return Err(Error::DuplicateExportName {
+                    name: name.to_owned(),
+                    at: SectionOffset::in_section(SectionId::Export),
+                });
+            }
+            return Ok(addr);
+        }
+
+        let addr = self.modules.next_addr();
+        self.modules.push(Arc::new(ModuleInst::new(
+            module,
+            Vec::new(),
+            Vec::new(),
+            Vec::new(),
+            Vec::new(),
+            Vec::new(),
+            Vec::new(),
+            None,
+        )));
+        self.report_instantiated(addr);
+        Ok(addr)
+    }
+
+    /// Instantiates a synthetic module described by a [`ModuleBuilder`].
+    ///
+    /// The functions, tables, memories and globals declared by the builder are all allocated in
+    /// the host and exported, so a synthetic module can provide imports like `env.memory`.
+    pub fn synthesize<S: Into<String>>(
+        &mut self,
+        name: S,
+        builder: ModuleBuilder,
+    ) -> Result<ModuleAddr, Error> {
+        self.instantiate(name, builder.build())
+    }
+
     /// Instantiates the provided [`Module`], consuming it in the process.
     pub fn instantiate<S: Into<String>>(
         &mut self,
         name: S,
         module: Module,
     ) -> Result<ModuleAddr, Error> {
-        let module_addr = ModuleAddr::new(self.modules.len() + 1)
-            .expect("New module address should be non-zero!");
+        self.instantiate_arc(name, Arc::new(module))
+    }
+
+    /// Instantiates a [`Module`] that may be shared, so one parsed module can be instantiated
+    /// any number of times without reading it again.
+    pub fn instantiate_arc<S: Into<String>>(
+        &mut self,
+        name: S,
+        module: Arc<Module>,
+    ) -> Result<ModuleAddr, Error> {
+        let code = lower(&module);
+        self.instantiate_lowered(name.into(), module, code, None)
+    }
+
+    /// Creates another instance of the module the instance at `addr` was created from, with
+    /// the same name. Unlike [`Host::fork_instance`], the new instance starts afresh, and its
+    /// imports are resolved again. The module's code was lowered for the first instance, so
+    /// that's reused.
+    ///
+    /// Fails with [`Error::NotInstantiable`] if the instance wasn't created from a module.
+    pub fn instantiate_again(&mut self, addr: ModuleAddr) -> Result<ModuleAddr, Error> {
+        let (module, code) = match self.sources.get(&addr) {
+            Some((module, code)) => (module.clone(), code.clone()),
+            None => {
+                return Err(Error::NotInstantiable {
+                    module: self.modules.checked(addr)?.name().to_owned(),
+                })
+            }
+        };
+        let name = self.modules.checked(addr)?.name().to_owned();
+        self.instantiate_lowered(name, module, code, None)
+    }
+
+    /// Instantiates the provided [`Module`] like [`Host::instantiate`], but satisfies its
+    /// imports with the items in `imports` instead of looking them up by module name. Fails
+    /// with [`Error::ExportNotFound`] if `imports` doesn't provide an import.
+    pub fn instantiate_with<S: Into<String>>(
+        &mut self,
+        name: S,
+        module: Module,
+        imports: &Imports,
+    ) -> Result<ModuleAddr, Error> {
+        let code = lower(&module);
+        self.instantiate_lowered(name.into(), Arc::new(module), code, Some(imports))
+    }
+
+    /// Instantiates a side module, one with a `dylink.0` section, against the `memory` and
+    /// `table` of the module it's being linked into.
+    ///
+    /// The memory and table grow to make room for the side module's data and functions, and
+    /// its `env.__memory_base` and `env.__table_base` imports say where that room starts. Its
+    /// `env.memory` and `env.__indirect_function_table` imports are `memory` and `table`, and
+    /// its other imports are looked up as [`Host::instantiate`] does. The libraries it needs
+    /// must have been instantiated first.
+    pub fn instantiate_side_module<S: Into<String>>(
+        &mut self,
+        name: S,
+        module: Module,
+        memory: MemAddr,
+        table: TableAddr,
+    ) -> Result<ModuleAddr, Error> {
+        let name = name.into();
+        let dylink = module.dylink()?.ok_or_else(|| Error::NotSideModule {
+            module: name.clone(),
+        })?;
+        if let Some(needed) = dylink.needed.iter().find(|n| self.find_module(n).is_none()) {
+            return Err(Error::ModuleNotFound {
+                module: needed.clone(),
+            });
+        }
+        let info = dylink.mem_info;
+        let exceeded = |resource| Error::ResourceLimitExceeded {
+            module: name.clone(),
+            resource,
+        };
+        let reserve = |len: usize, align: u32, size: u32| {
+            1usize
+                .checked_shl(align)
+                .and_then(|align| len.checked_next_multiple_of(align))
+                .and_then(|base| Some((base, base.checked_add(size as usize)?)))
+        };
+
+        let mem = self.mems.checked(memory)?.clone();
+        let table_inst = self.tables.checked(table)?.clone();
+
+        // The side module's data goes past the end of the memory, in pages of its own
+        let len = mem.memory().len();
+        let (memory_base, end) =
+            reserve(len, info.memory_align, info.memory_size).ok_or_else(|| exceeded("memory"))?;
+        if end > len {
+            let pages = (end - len).div_ceil(mem.page_size());
+            let desired = len + pages * mem.page_size();
+            if !self.allows_memory_growth(&mem, desired) || mem.grow(pages as u64).is_none() {
+                return Err(exceeded("memory"));
+            }
+        }
+
+        // And its functions past the end of the table
+        let len = table_inst.len();
+        let (table_base, end) =
+            reserve(len, info.table_align, info.table_size).ok_or_else(|| exceeded("table"))?;
+        if end > len {
+            let max = table_inst.typ().max();
+            let allowed = self
+                .limiter
+                .as_ref()
+                .is_none_or(|limiter| limiter.table_growing(table_inst.module(), len, end, max));
+            if !allowed || table_inst.grow(end - len).is_none() {
+                return Err(exceeded("table"));
+            }
+        }
+
+        let mut imports = Imports::new();
+        for import in module.imports() {
+            let item = match (import.module(), import.name()) {
+                ("env", "memory") => ExternVal::Mem(memory),
+                ("env", "__indirect_function_table") => ExternVal::Table(table),
+                ("env", "__memory_base") => ExternVal::Global(self.alloc_base(&mem, memory_base)),
+                ("env", "__table_base") => ExternVal::Global(self.alloc_base(&mem, table_base)),
+                (module, name) => {
+                    let addr = self
+                        .find_module(module)
+                        .ok_or_else(|| Error::ModuleNotFound {
+                            module: module.to_owned(),
+                        })?;
+                    *self.resolve_import(addr, name)?.value()
+                }
+            };
+            imports.define(import.module(), import.name(), item);
+        }
+        self.instantiate_with(name, module, &imports)
+    }
+
+    /// Allocates an immutable global holding a side module's base offset, owned by whatever
+    /// owns the memory it's linked against.
+    fn alloc_base(&mut self, mem: &MemInst, base: usize) -> GlobalAddr {
+        let addr = self.globals.next_addr();
+        let value = Value::I32(base as u32);
+        let typ = GlobalType::new(value.typ(), false);
+        self.globals
+            .push(Arc::new(GlobalInst::new(mem.module(), typ, value)));
+        addr
+    }
+
+    /// Instantiates a module whose function bodies were lowered ahead of time, skipping the
+    /// lowering [`Host::instantiate`] does.
+    pub fn instantiate_precompiled<S: Into<String>>(
+        &mut self,
+        name: S,
+        module: PrecompiledModule,
+    ) -> Result<ModuleAddr, Error> {
+        let (module, code) = module.into_parts();
+        self.instantiate_lowered(name.into(), Arc::new(module), code, None)
+    }
+
+    fn instantiate_lowered(
+        &mut self,
+        name: String,
+        module: Arc<Module>,
+        code: Vec<Arc<Code>>,
+        imports: Option<&Imports>,
+    ) -> Result<ModuleAddr, Error> {
+        let module_addr = self.modules.next_addr();
+        self.limit_allocations(
+            module_addr,
+            &name,
+            module
+                .tables()
+                .iter()
+                .map(|table| (table.min(), table.max())),
+            module.mems().iter().map(|mem| self.mem_limits(mem)),
+        )?;
 
         let mut funcs = Vec::new();
+        let mut tables = Vec::new();
         let mut mems = Vec::new();
+        let mut globals = Vec::new();
+
+        self.resolve_imports(
+            &module,
+            imports,
+            &mut funcs,
+            &mut tables,
+            &mut mems,
+            &mut globals,
+        )?;
+
+        // Constant expressions may only refer to imported globals
+        let imported_globals = globals.clone();
 
-        self.resolve_imports(&module, &mut funcs, &mut mems)?;
-        self.instantiate_funcs(module_addr, &module, &mut funcs);
-        self.instantiate_data(&module, &mems)?;
+        self.instantiate_funcs(module_addr, &name, &module, &code, &mut funcs)?;
+        self.instantiate_tables(module_addr, &module, &mut tables);
+        self.instantiate_mems(module_addr, &name, &module, &mut mems)?;
+        self.instantiate_globals(module_addr, &module, &imported_globals, &mut globals)?;
+        self.instantiate_elems(&module, &imported_globals, &funcs, &tables)?;
+        self.instantiate_data(&module, &imported_globals, &mems)?;
 
-        let exports = self.export_module(&funcs, module.exports())?;
+        let exports = export_module(&funcs, &tables, &mems, &globals, module.exports())?;
 
         self.modules.push(Arc::new(ModuleInst::new(
-            name.into(),
+            name,
+            module.types().clone(),
             funcs,
+            tables,
             mems,
+            globals,
             exports,
             module.names().cloned(),
         )));
+        self.sources.insert(module_addr, (module, code));
+        self.report_instantiated(module_addr);
         Ok(module_addr)
     }
 
-    fn export_module(
+    /// Parses, validates and instantiates a module written in the WebAssembly text format,
+    /// which is handy for tests and examples.
+    #[cfg(feature = "wat")]
+    pub fn instantiate_wat<S: Into<String>>(
         &mut self,
-        funcs: &[FuncAddr],
-        module_exports: &Vec<Export>,
-    ) -> Result<Vec<ExportInst>, Error> {
-        let mut exports = Vec::new();
-        for export in module_exports {
-            match export.description() {
-                MemberDesc::Function(func_idx) => {
-                    let func_addr = funcs[*func_idx as usize];
-                    let inst = ExportInst::func(export.name(), func_addr);
-                    exports.push(inst);
+        name: S,
+        text: &str,
+    ) -> Result<ModuleAddr, Error> {
+        let module = Module::from_wat(text)?;
+        module.validate()?;
+        self.instantiate(name, module)
+    }
+
+    /// Creates a new instance of the module at `addr`, starting from the parent's current state.
+    ///
+    /// The fork shares the parent's code and imports, but has its own copies of the functions,
+    /// tables, memories and globals the parent defines, so changes made by one aren't seen by the
+    /// other. Memories are copied by the host's [`MemoryBackend`]: the default backend copies
+    /// them eagerly, while the `MmapBackend` shares their pages
+    /// copy-on-write where it can. This lets an instance that has been warmed up once serve as
+    /// a template that is forked for each request.
+    ///
+    /// The fork has the same name as its parent, and the same invokable exports.
+    pub fn fork_instance(&mut self, addr: ModuleAddr) -> Result<ModuleAddr, Error> {
+        let parent = self.modules.checked(addr)?.clone();
+        let fork_addr = self.modules.next_addr();
+
+        // Copying the parent's tables and memories allocates them afresh
+        let owned_tables = parent.tables().iter().map(|table| &self.tables[*table]);
+        let owned_mems = parent.mems().iter().map(|mem| &self.mems[*mem]);
+        self.limit_allocations(
+            fork_addr,
+            parent.name(),
+            owned_tables
+                .filter(|table| table.module() == addr)
+                .map(|table| (table.len(), table.typ().max())),
+            owned_mems
+                .filter(|mem| mem.module() == addr)
+                .map(|mem| (mem.memory().len(), mem.memory().max_size())),
+        )?;
+
+        let mut funcs = Vec::with_capacity(parent.funcs().len());
+        for func in parent.funcs() {
+            let func_inst = self.funcs[*func].clone();
+            if func_inst.module() != addr {
+                funcs.push(*func);
+                continue;
+            }
+            funcs.push(self.funcs.next_addr());
+            self.funcs.push(Arc::new(func_inst.fork(fork_addr)));
+        }
+
+        // Tables that belong to the fork refer to its copies of the parent's functions
+        let remap = |func| forked(parent.funcs(), &funcs, func);
+        let mut tables = Vec::with_capacity(parent.tables().len());
+        for table in parent.tables() {
+            let table_inst = self.tables[*table].clone();
+            if table_inst.module() != addr {
+                tables.push(*table);
+                continue;
+            }
+            tables.push(self.tables.next_addr());
+            self.tables
+                .push(Arc::new(table_inst.fork(fork_addr, remap)));
+        }
+
+        let mut mems = Vec::with_capacity(parent.mems().len());
+        for mem in parent.mems() {
+            let mem_inst = self.mems[*mem].clone();
+            if mem_inst.module() != addr {
+                mems.push(*mem);
+                continue;
+            }
+            let mem_inst = mem_inst.fork(fork_addr, &*self.memory_backend)?;
+            mems.push(self.alloc_mem(mem_inst));
+        }
+
+        let mut globals = Vec::with_capacity(parent.globals().len());
+        for global in parent.globals() {
+            let global_inst = self.globals[*global].clone();
+            if global_inst.module() != addr {
+                globals.push(*global);
+                continue;
+            }
+            globals.push(self.globals.next_addr());
+            self.globals.push(Arc::new(GlobalInst::new(
+                fork_addr,
+                global_inst.typ().clone(),
+                global_inst.get(),
+            )));
+        }
+
+        // Exports refer to the same indices in the fork's index spaces
+        let exports = parent
+            .exports()
+            .iter()
+            .map(|e| match *e.value() {
+                ExternVal::Func(a) => ExportInst::func(e.name(), forked(parent.funcs(), &funcs, a)),
+                ExternVal::Table(a) => {
+                    ExportInst::table(e.name(), forked(parent.tables(), &tables, a))
+                }
+                ExternVal::Mem(a) => ExportInst::mem(e.name(), forked(parent.mems(), &mems, a)),
+                ExternVal::Global(a) => {
+                    ExportInst::global(e.name(), forked(parent.globals(), &globals, a))
+                }
+            })
+            .collect();
+
+        if let Some((_, allowed)) = self.invokable.iter().find(|(m, _)| *m == addr) {
+            let allowed = allowed.clone();
+            self.invokable.push((fork_addr, allowed));
+        }
+
+        self.modules.push(Arc::new(ModuleInst::new(
+            parent.name(),
+            parent.types().to_vec(),
+            funcs,
+            tables,
+            mems,
+            globals,
+            exports,
+            parent.names().cloned(),
+        )));
+        if let Some(source) = self.sources.get(&addr).cloned() {
+            self.sources.insert(fork_addr, source);
+        }
+        self.report_instantiated(fork_addr);
+        Ok(fork_addr)
+    }
+
+    /// Drops the instance at `addr`, releasing the functions, tables, memories and globals it
+    /// allocated unless another instance still uses them. The host's slots for the released
+    /// items may be reused, but their old addresses are stale: the host reports
+    /// [`Error::StaleAddress`] for one rather than referring to whatever replaced it.
+    ///
+    /// Functions need their instance to run, so this fails with [`Error::InstanceInUse`] if
+    /// another instance imports one of them or refers to one from a table. It fails with
+    /// [`Error::StaleAddress`] if the instance has already been dropped.
+    pub fn drop_instance(&mut self, addr: ModuleAddr) -> Result<(), Error> {
+        let inst = self.modules.checked(addr)?.clone();
+
+        // Items in the index spaces of other instances, and functions in the tables that
+        // survive, are still in use
+        let mut used = HashSet::new();
+        for (_, other) in self.modules.entries().filter(|(a, _)| *a != addr) {
+            used.extend(other.funcs().iter().map(|a| ExternVal::Func(*a)));
+            used.extend(other.tables().iter().map(|a| ExternVal::Table(*a)));
+            used.extend(other.mems().iter().map(|a| ExternVal::Mem(*a)));
+            used.extend(other.globals().iter().map(|a| ExternVal::Global(*a)));
+        }
+        let surviving_tables: Vec<_> = self
+            .tables
+            .entries()
+            .filter(|(a, t)| t.module() != addr || used.contains(&ExternVal::Table(*a)))
+            .map(|(_, t)| t.clone())
+            .collect();
+        for table in surviving_tables {
+            used.extend((0..table.len()).filter_map(|i| table.get(i).map(ExternVal::Func)));
+        }
+
+        let items: Vec<_> = inst
+            .funcs()
+            .iter()
+            .map(|a| ExternVal::Func(*a))
+            .chain(inst.tables().iter().map(|a| ExternVal::Table(*a)))
+            .chain(inst.mems().iter().map(|a| ExternVal::Mem(*a)))
+            .chain(inst.globals().iter().map(|a| ExternVal::Global(*a)))
+            .collect();
+        let owned: Vec<_> = items
+            .into_iter()
+            .filter(|item| self.owner(*item).ok() == Some(addr))
+            .collect();
+        if owned
+            .iter()
+            .any(|item| matches!(item, ExternVal::Func(_)) && used.contains(item))
+        {
+            return Err(Error::InstanceInUse {
+                module: inst.name().to_owned(),
+            });
+        }
+        for item in owned.into_iter().filter(|item| !used.contains(item)) {
+            match item {
+                ExternVal::Func(a) => {
+                    self.funcs.remove(a);
+                    self.stubs.remove(&a);
                 }
-                MemberDesc::Memory(ref mem_type) => {
-                    let mem_addr = MemAddr::new(self.mems.len() + 1)
-                        .expect("New memory address should be non-zero!");
-                    self.mems.push(Arc::new(MemInst::from_type(mem_type)?));
-                    let inst = ExportInst::mem(export.name(), mem_addr);
-                    exports.push(inst);
+                ExternVal::Table(a) => {
+                    self.tables.remove(a);
+                }
+                ExternVal::Mem(a) => {
+                    self.mems.remove(a);
+                }
+                ExternVal::Global(a) => {
+                    self.globals.remove(a);
+                    self.watchers.remove(&a);
                 }
-                _ => { /* skip */ }
             }
         }
-        Ok(exports)
+        self.modules.remove(addr);
+        self.sources.remove(&addr);
+        self.invokable.retain(|(a, _)| *a != addr);
+        let funcs = &self.funcs;
+        if let Some(latencies) = &mut self.import_latencies {
+            latencies.retain(|(caller, func), _| *caller != addr && funcs.get(*func).is_some());
+        }
+        Ok(())
+    }
+
+    /// Allocates a memory of type `mem_type` for the instance at `module`, named `name`, as the
+    /// host's memory backend and configuration say.
+    fn alloc_mem_inst(
+        &self,
+        module: ModuleAddr,
+        name: &str,
+        mem_type: &MemoryType,
+    ) -> Result<MemInst, Error> {
+        let backend = &*self.memory_backend;
+        MemInst::with_config(module, mem_type, backend, &self.memory_config).map_err(|e| match e {
+            Error::OutOfMemory { .. } => Error::ResourceLimitExceeded {
+                module: name.to_owned(),
+                resource: "memory",
+            },
+            e => e,
+        })
+    }
+
+    fn alloc_mem(&mut self, mem_inst: MemInst) -> MemAddr {
+        let mem_addr = self.mems.next_addr();
+        self.mems.push(Arc::new(mem_inst));
+        mem_addr
     }
 
     fn instantiate_funcs(
         &mut self,
         instance_addr: ModuleAddr,
+        instance_name: &str,
         module: &Module,
+        code: &[Arc<Code>],
         funcs: &mut Vec<FuncAddr>,
-    ) {
+    ) -> Result<(), Error> {
         // Instantiate functions
         for (code_idx, type_id) in module.funcs().iter().enumerate() {
-            // Assign an address
-            let func_addr = FuncAddr::new(self.funcs.len() + 1)
-                .expect("New function address should be non-zero!");
+            // Assign an address. Imports come first in the function index space, so the
+            // function's index is the number of functions before it.
+            let func_addr = self.funcs.next_addr();
+            let func_idx = funcs.len();
             funcs.push(func_addr);
 
             // Get the function body and type
-            let typ = module.types()[*type_id as usize].clone();
-            let body = module.code()[code_idx].clone();
+            let typ = module
+                .func_type(*type_id)
+                .ok_or(Error::UnknownTypeIndex {
+                    index: *type_id,
+                    at: SectionOffset::in_section(SectionId::Function),
+                })?
+                .clone();
+            let body = code.get(code_idx).ok_or(Error::FunctionCodeMismatch {
+                funcs: module.funcs().len(),
+                bodies: code.len(),
+                at: SectionOffset::in_section(SectionId::Code),
+            })?;
+
+            // Name it for stack traces, by its debug name if it has one
+            let func_name = module
+                .names()
+                .and_then(|n| n.funcs().get(func_idx))
+                .and_then(|n| n.func_name());
+            let name = match func_name {
+                Some(func_name) => format!("{}!{}", instance_name, func_name),
+                None => format!("{}!<function {}>", instance_name, func_idx),
+            };
 
             // Create the instance and register it in the host
-            self.funcs.push(Arc::new(FuncInst::local(
-                typ,
+            self.funcs.push(Arc::new(
+                FuncInst::local(typ, instance_addr, code_idx, body.clone()).with_name(name),
+            ));
+        }
+        Ok(())
+    }
+
+    fn instantiate_tables(
+        &mut self,
+        instance_addr: ModuleAddr,
+        module: &Module,
+        tables: &mut Vec<TableAddr>,
+    ) {
+        for table_type in module.tables() {
+            let table_addr = self.tables.next_addr();
+            self.tables
+                .push(Arc::new(TableInst::from_type(instance_addr, table_type)));
+            tables.push(table_addr);
+        }
+    }
+
+    fn instantiate_mems(
+        &mut self,
+        instance_addr: ModuleAddr,
+        name: &str,
+        module: &Module,
+        mems: &mut Vec<MemAddr>,
+    ) -> Result<(), Error> {
+        for mem_type in module.mems() {
+            let mem_inst = self.alloc_mem_inst(instance_addr, name, mem_type)?;
+            mems.push(self.alloc_mem(mem_inst));
+        }
+        Ok(())
+    }
+
+    fn instantiate_globals(
+        &mut self,
+        instance_addr: ModuleAddr,
+        module: &Module,
+        imported_globals: &[GlobalAddr],
+        globals: &mut Vec<GlobalAddr>,
+    ) -> Result<(), Error> {
+        for global in module.globals() {
+            let at = SectionOffset::in_section(SectionId::Global);
+            let value = ConstExpr::new(self, imported_globals)
+                .eval(global.init())
+                .map_err(|e| e.in_section(SectionId::Global, None))?;
+            if !global.typ().typ().accepts(value.typ()) {
+                return Err(Error::InvalidConstExpr { at });
+            }
+
+            let global_addr = self.globals.next_addr();
+            self.globals.push(Arc::new(GlobalInst::new(
                 instance_addr,
-                code_idx,
-                body,
+                global.typ().clone(),
+                value,
             )));
+            globals.push(global_addr);
         }
+        Ok(())
     }
 
     fn resolve_imports(
         &mut self,
         module: &Module,
+        imports: Option<&Imports>,
         funcs: &mut Vec<FuncAddr>,
+        tables: &mut Vec<TableAddr>,
         mems: &mut Vec<MemAddr>,
+        globals: &mut Vec<GlobalAddr>,
     ) -> Result<(), Error> {
         for import in module.imports() {
-            if let Some(module_addr) = self.find_module(&import.module()) {
-                let export = self.resolve_import(module_addr, &import.name())?;
-                match export.value() {
-                    ExternVal::Func(func_addr) => funcs.push(func_addr.clone()),
-                    ExternVal::Mem(mem_addr) => mems.push(mem_addr.clone()),
-                }
-            } else {
+            let value = match imports {
+                Some(imports) => imports
+                    .get(import.module(), import.name())
+                    .ok_or_else(|| Error::ExportNotFound {
+                        module: import.module().to_owned(),
+                        name: import.name().to_owned(),
+                    })
+                    .and_then(|value| self.check_import(module, import, value))?,
+                None => self.resolve_module_import(module, import)?,
+            };
+            match value {
+                ExternVal::Func(func_addr) => funcs.push(func_addr),
+                ExternVal::Table(table_addr) => tables.push(table_addr),
+                ExternVal::Mem(mem_addr) => mems.push(mem_addr),
+                ExternVal::Global(global_addr) => globals.push(global_addr),
+            }
+        }
+        Ok(())
+    }
+
+    /// Finds the item that satisfies `import`, which `module` declares.
+    fn resolve_module_import(&self, module: &Module, import: &Import) -> Result<ExternVal, Error> {
+        let module_addr = match self.find_module(import.module()) {
+            Some(module_addr) => module_addr,
+            None => {
                 return Err(Error::ModuleNotFound {
                     module: import.module().to_owned(),
+                })
+            }
+        };
+        let export = self.resolve_import(module_addr, import.name())?;
+        self.check_import(module, import, *export.value())
+    }
+
+    /// Checks that `value` can satisfy `import`, which `module` declares.
+    fn check_import(
+        &self,
+        module: &Module,
+        import: &Import,
+        value: ExternVal,
+    ) -> Result<ExternVal, Error> {
+        // Catch signature mismatches now rather than when the function is called
+        if let (MemberDesc::Function(type_id), ExternVal::Func(func_addr)) =
+            (import.description(), value)
+        {
+            let expected = module.func_type(*type_id).ok_or(Error::UnknownTypeIndex {
+                index: *type_id,
+                at: SectionOffset::in_section(SectionId::Import),
+            })?;
+            let actual = self.funcs[func_addr].typ();
+            if expected != actual {
+                return Err(Error::ImportTypeMismatch {
+                    module: import.module().to_owned(),
+                    name: import.name().to_owned(),
+                    expected: Box::new(expected.clone()),
+                    actual: Box::new(actual.clone()),
                 });
             }
         }
-        Ok(())
+        if !self.is_compatible(import.description(), &value) {
+            return Err(Error::ExportTypeMismatch {
+                module: import.module().to_owned(),
+                name: import.name().to_owned(),
+            });
+        }
+        Ok(value)
     }
 
-    fn instantiate_data(&mut self, module: &Module, mems: &Vec<MemAddr>) -> Result<(), Error> {
-        for data in module.data() {
-            let offset = match self.eval_expr(data.expr())? {
+    /// Works out how each import of `module` would be satisfied if it were instantiated now,
+    /// without instantiating it. Unlike instantiating, every import is explained rather than
+    /// stopping at the first that fails, which helps track down a module linked against the
+    /// wrong function.
+    pub fn explain_imports(&self, module: &Module) -> Vec<ImportResolution> {
+        module
+            .imports()
+            .iter()
+            .map(|import| {
+                let provider = self.find_module(import.module());
+                let ignored = self
+                    .modules
+                    .iter()
+                    .filter(|m| m.name() == import.module())
+                    .count()
+                    .saturating_sub(1);
+                ImportResolution::new(
+                    import.module(),
+                    import.name(),
+                    import.description(),
+                    provider,
+                    ignored,
+                    self.resolve_module_import(module, import),
+                )
+            })
+            .collect()
+    }
+
+    /// Finds every import of `module` that couldn't be satisfied if it were instantiated now,
+    /// without instantiating it, so all the problems can be reported at once rather than
+    /// just the first. An empty list means every import resolves.
+    pub fn check_imports(&self, module: &Module) -> Vec<UnresolvedImport> {
+        module
+            .imports()
+            .iter()
+            .filter_map(|import| {
+                let error = self.resolve_module_import(module, import).err()?;
+                let func_type = match *import.description() {
+                    MemberDesc::Function(type_id) => module.func_type(type_id),
+                    _ => None,
+                };
+                Some(UnresolvedImport::new(
+                    import.module(),
+                    import.name(),
+                    import.description(),
+                    func_type,
+                    error,
+                ))
+            })
+            .collect()
+    }
+
+    /// Checks that `value` can be used to satisfy an import declared as `desc`.
+    fn is_compatible(&self, desc: &MemberDesc, value: &ExternVal) -> bool {
+        match (desc, value) {
+            (MemberDesc::Function(_), ExternVal::Func(_)) => true,
+            (MemberDesc::Table(typ), ExternVal::Table(addr)) => {
+                let table = &self.tables[*addr];
+                table.typ().elem_type() == typ.elem_type()
+                    && limits_match(table.len(), table.typ().max(), typ.min(), typ.max())
+            }
+            (MemberDesc::Memory(typ), ExternVal::Mem(addr)) => {
+                let mem = &self.mems[*addr];
+                let page_size = mem.page_size();
+                mem.shared() == typ.shared()
+                    && mem.is_64() == typ.is_64()
+                    && page_size == typ.page_size()
+                    && limits_match(
+                        mem.memory().len() / page_size,
+                        mem.memory().max_size().map(|m| m / page_size),
+                        typ.min(),
+                        typ.max(),
+                    )
+            }
+            (MemberDesc::Global(typ), ExternVal::Global(addr)) => {
+                // Mutable globals are written through the import, so their types must match
+                // exactly rather than by subtyping.
+                let actual = self.globals[*addr].typ();
+                actual.mutable() == typ.mutable()
+                    && if typ.mutable() {
+                        actual.typ() == typ.typ()
+                    } else {
+                        typ.typ().accepts(actual.typ())
+                    }
+            }
+            _ => false,
+        }
+    }
+
+    fn instantiate_elems(
+        &mut self,
+        module: &Module,
+        imported_globals: &[GlobalAddr],
+        funcs: &[FuncAddr],
+        tables: &[TableAddr],
+    ) -> Result<(), Error> {
+        for elem in module.elems() {
+            let expr = match elem.expr() {
+                Some(expr) => expr,
+                None => continue,
+            };
+            let at = SectionOffset::in_section(SectionId::Element);
+            let offset = match ConstExpr::new(self, imported_globals)
+                .eval(expr)
+                .map_err(|e| e.in_section(SectionId::Element, None))?
+            {
                 Value::I32(i) => i as usize,
-                _ => return Err(Error::InvalidModule),
+                _ => return Err(Error::InvalidConstExpr { at }),
             };
 
-            // Find an initialize the memory
-            let mem_addr = mems[data.index() as usize];
-            let mem_inst = &mut self.mems[mem_addr.val()];
-            let mut mem = mem_inst.memory();
-
-            // Bounds check
-            let end = offset + data.init().len();
-            if (offset + end) > mem.len() {
-                return Err(Error::InvalidModule);
+            let table_addr = *tables.get(elem.index()).ok_or(Error::UnknownTableIndex {
+                index: elem.index(),
+                at,
+            })?;
+            let table = &self.tables[table_addr];
+            for (i, func_idx) in elem.init().iter().enumerate() {
+                let func_addr = *funcs.get(*func_idx).ok_or(Error::UnknownFunctionIndex {
+                    index: *func_idx,
+                    at,
+                })?;
+                if !table.set(offset + i, Some(func_addr)) {
+                    return Err(Error::SegmentOutOfBounds { at });
+                }
             }
+        }
+        Ok(())
+    }
 
-            // Safe because instatiation is single-threaded.
-            unsafe {
-                mem.data()[offset..end].copy_from_slice(data.init());
+    fn instantiate_data(
+        &mut self,
+        module: &Module,
+        imported_globals: &[GlobalAddr],
+        mems: &[MemAddr],
+    ) -> Result<(), Error> {
+        for data in module.data() {
+            // Find an initialize the memory
+            let at = SectionOffset::in_section(SectionId::Data);
+            let mem_addr = *mems.get(data.index()).ok_or(Error::UnknownMemoryIndex {
+                index: data.index(),
+                at,
+            })?;
+            let mem_inst = &self.mems[mem_addr];
+
+            // 64-bit memories are indexed by an i64 offset
+            let offset = match ConstExpr::new(self, imported_globals)
+                .eval(data.expr())
+                .map_err(|e| e.in_section(SectionId::Data, None))?
+            {
+                Value::I32(i) if !mem_inst.is_64() => i as usize,
+                Value::I64(i) if mem_inst.is_64() => i as usize,
+                _ => return Err(Error::InvalidConstExpr { at }),
+            };
+            if !mem_inst.memory().write(offset, data.init()) {
+                return Err(Error::SegmentOutOfBounds { at });
             }
         }
         Ok(())
     }
 }
+
+/// Lowers the function bodies of `module` for the interpreter.
+fn lower(module: &Module) -> Vec<Arc<Code>> {
+    module
+        .code()
+        .iter()
+        .map(|body| Arc::new(Code::new(body)))
+        .collect()
+}
+
+/// Finds the address in a forked instance's index space of the item at `addr` in its parent's.
+fn forked<A: Copy + PartialEq>(parent: &[A], fork: &[A], addr: A) -> A {
+    match parent.iter().position(|a| *a == addr) {
+        Some(idx) => fork[idx],
+        None => addr,
+    }
+}
+
+/// Enumerates the addresses of the items in the first `count` slots of `slots` that are in use.
+fn addrs<'a, A, T, W>(
+    slots: &'a Slots<A, T>,
+    count: usize,
+    wrap: W,
+) -> impl 'a + Iterator<Item = ExternVal>
+where
+    A: SlotAddr,
+    W: 'a + Fn(A) -> ExternVal,
+{
+    (0..count).filter_map(move |i| slots.addr(i).map(&wrap))
+}
+
+/// Checks if `name` matches a pattern for [`Host::invoke_matching`].
+fn matches_pattern(pattern: &str, name: &str) -> bool {
+    let mut parts = pattern.split('*');
+    let first = parts.next().unwrap_or("");
+    let mut rest = match name.strip_prefix(first) {
+        Some(rest) => rest,
+        None => return false,
+    };
+    // Without a '*', the pattern is a prefix
+    let parts: Vec<_> = parts.collect();
+    let last = match parts.split_last() {
+        Some((last, middle)) => {
+            for part in middle {
+                match rest.find(part) {
+                    Some(idx) => rest = &rest[idx + part.len()..],
+                    None => return false,
+                }
+            }
+            last
+        }
+        None => return true,
+    };
+    rest.ends_with(last)
+}
+
+fn in_snapshot(snapshot: &HostSnapshot, item: ExternVal) -> bool {
+    match item {
+        ExternVal::Func(a) => a.val() < snapshot.funcs,
+        ExternVal::Table(a) => a.val() < snapshot.tables,
+        ExternVal::Mem(a) => a.val() < snapshot.mems,
+        ExternVal::Global(a) => a.val() < snapshot.globals,
+    }
+}
+
+/// Resolves the exports of a module against the addresses allocated for its members.
+/// Checks that an item whose size is `min` and which may grow to `max` satisfies the limits
+/// an import declares.
+fn limits_match(
+    min: usize,
+    max: Option<usize>,
+    expected_min: usize,
+    expected_max: Option<usize>,
+) -> bool {
+    min >= expected_min
+        && match (max, expected_max) {
+            (_, None) => true,
+            (Some(max), Some(expected_max)) => max <= expected_max,
+            (None, Some(_)) => false,
+        }
+}
+
+fn export_module(
+    funcs: &[FuncAddr],
+    tables: &[TableAddr],
+    mems: &[MemAddr],
+    globals: &[GlobalAddr],
+    module_exports: &[Export],
+) -> Result<Vec<ExportInst>, Error> {
+    let at = SectionOffset::in_section(SectionId::Export);
+    let mut exports = Vec::new();
+    for export in module_exports {
+        let inst = match *export.description() {
+            ExportDesc::Function(index) => funcs
+                .get(index)
+                .map(|addr| ExportInst::func(export.name(), *addr))
+                .ok_or(Error::UnknownFunctionIndex { index, at }),
+            ExportDesc::Table(index) => tables
+                .get(index)
+                .map(|addr| ExportInst::table(export.name(), *addr))
+                .ok_or(Error::UnknownTableIndex { index, at }),
+            ExportDesc::Memory(index) => mems
+                .get(index)
+                .map(|addr| ExportInst::mem(export.name(), *addr))
+                .ok_or(Error::UnknownMemoryIndex { index, at }),
+            ExportDesc::Global(index) => globals
+                .get(index)
+                .map(|addr| ExportInst::global(export.name(), *addr))
+                .ok_or(Error::UnknownGlobalIndex { index, at }),
+        };
+        exports.push(inst?);
+    }
+    Ok(exports)
+}
@@ -0,0 +1,104 @@
+use std::any::Any;
+
+/// Embedder state stored in a [`Host`](crate::hosting::Host). It's cloned along with the
+/// host, so it has to be `Clone`, and shared with it across threads, but is otherwise any
+/// `'static` type.
+pub(crate) trait HostData: Any + Send + Sync {
+    fn clone_box(&self) -> Box<dyn HostData>;
+    fn as_any(&self) -> &dyn Any;
+    fn as_any_mut(&mut self) -> &mut dyn Any;
+    fn into_any(self: Box<Self>) -> Box<dyn Any>;
+}
+
+impl<T: Any + Clone + Send + Sync> HostData for T {
+    fn clone_box(&self) -> Box<dyn HostData> {
+        Box::new(self.clone())
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+
+    fn into_any(self: Box<Self>) -> Box<dyn Any> {
+        self
+    }
+}
+
+impl Clone for Box<dyn HostData> {
+    fn clone(&self) -> Self {
+        (**self).clone_box()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use crate::{
+        builder::{FuncBuilder, ModuleBuilder},
+        hosting::{Caller, ExternalFunc, ExternalMemory, ExternalModule, Host},
+        module::FuncType,
+        Trap, Value,
+    };
+
+    struct Counter(Vec<Arc<ExternalFunc>>);
+
+    impl ExternalModule for Counter {
+        fn name(&self) -> &str {
+            "counter"
+        }
+
+        fn funcs(&self) -> &[Arc<ExternalFunc>] {
+            &self.0
+        }
+
+        fn mems(&self) -> &[ExternalMemory] {
+            &[]
+        }
+    }
+
+    #[derive(Clone)]
+    struct Calls(Vec<String>);
+
+    fn count(caller: &mut Caller, _values: &[Value]) -> Result<Vec<Value>, Trap> {
+        caller
+            .host_mut()
+            .data_mut::<Calls>()
+            .unwrap()
+            .0
+            .push("count".to_owned());
+        Ok(Vec::new())
+    }
+
+    #[test]
+    pub fn host_functions_can_update_host_data() {
+        use crate::Instruction::*;
+
+        let mut host = Host::new();
+        host.set_data(Calls(Vec::new()));
+        host.external(Counter(vec![Arc::new(ExternalFunc::new(
+            "count",
+            FuncType::empty(),
+            count,
+        ))]))
+        .unwrap();
+        let module = ModuleBuilder::new()
+            .func(FuncBuilder::new().import_from("counter", "count"))
+            .func(
+                FuncBuilder::new()
+                    .export_as("main")
+                    .body(vec![Call(0), Call(0)]),
+            );
+        let addr = host.instantiate("test", module.build()).unwrap();
+        host.invoke(addr, "main", &[]).unwrap();
+
+        assert_eq!(2, host.data::<Calls>().unwrap().0.len());
+        assert!(host.data::<String>().is_none());
+        assert_eq!(2, host.clone().take_data::<Calls>().unwrap().0.len());
+        assert!(host.data::<Calls>().is_some());
+    }
+}
@@ -1,3 +1,18 @@
-use crate::{hosting::Host, interp::Thread, Trap, Value};
+use std::{future::Future, pin::Pin};
 
-pub type HostFunc = fn(&mut Host, &mut Thread, &[Value]) -> Result<Vec<Value>, Trap>;
+use crate::{hosting::Caller, Trap, Value};
+
+pub type HostFunc = fn(&mut Caller, &[Value]) -> Result<Vec<Value>, Trap>;
+
+/// A host function that may capture state, such as one built from a closure by
+/// [`Host::define`](crate::hosting::Host::define).
+pub type DynHostFunc = dyn Fn(&mut Caller, &[Value]) -> Result<Vec<Value>, Trap> + Send + Sync;
+
+/// The results of an asynchronous host function, once they're ready.
+pub type HostFuture = Pin<Box<dyn Future<Output = Result<Vec<Value>, Trap>> + Send>>;
+
+/// A host function that starts an operation, such as I/O, and produces a future of its
+/// results instead of waiting for it. The future can't borrow the [`Caller`], so anything it
+/// needs must be copied or moved into it. See
+/// [`Host::define_async`](crate::hosting::Host::define_async).
+pub type AsyncHostFunc = dyn Fn(&mut Caller, &[Value]) -> HostFuture + Send + Sync;
@@ -0,0 +1,38 @@
+/// Records how many items of each kind a [`Host`](crate::hosting::Host) contained at a point
+/// in time.
+///
+/// New items are appended to a host, so enumerating through a snapshot yields the same items in
+/// the same order even if more modules are instantiated in the meantime. This makes it possible
+/// to page through a large host with `skip` and `take` across multiple requests. Dropping an
+/// instance frees slots that later items can reuse, though, so the results only stay the same
+/// while no instances are dropped.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct HostSnapshot {
+    pub(crate) modules: usize,
+    pub(crate) funcs: usize,
+    pub(crate) tables: usize,
+    pub(crate) mems: usize,
+    pub(crate) globals: usize,
+}
+
+impl HostSnapshot {
+    pub fn modules(&self) -> usize {
+        self.modules
+    }
+
+    pub fn funcs(&self) -> usize {
+        self.funcs
+    }
+
+    pub fn tables(&self) -> usize {
+        self.tables
+    }
+
+    pub fn mems(&self) -> usize {
+        self.mems
+    }
+
+    pub fn globals(&self) -> usize {
+        self.globals
+    }
+}
@@ -0,0 +1,112 @@
+use std::collections::HashMap;
+
+use crate::hosting::{ExternVal, ModuleInst};
+
+/// The items to satisfy a module's imports with, by the module and item names the imports
+/// ask for. See [`Host::instantiate_with`](crate::hosting::Host::instantiate_with).
+///
+/// Unlike [`Host::instantiate`](crate::hosting::Host::instantiate), which looks imports up in
+/// the first instance registered under the requested module name, this lets an embedder pick
+/// the exact instance, or item, each import is linked to.
+#[derive(Clone, Debug, Default)]
+pub struct Imports {
+    items: HashMap<(String, String), ExternVal>,
+}
+
+impl Imports {
+    pub fn new() -> Imports {
+        Imports::default()
+    }
+
+    /// Provides `value` for imports of `name` from `module`, replacing anything provided for
+    /// them before.
+    pub fn define<S: Into<String>, T: Into<String>>(
+        &mut self,
+        module: S,
+        name: T,
+        value: ExternVal,
+    ) -> &mut Imports {
+        self.items.insert((module.into(), name.into()), value);
+        self
+    }
+
+    /// Provides every export of `instance` for imports from `module`, whatever the instance
+    /// is called in the host.
+    pub fn define_instance<S: Into<String>>(
+        &mut self,
+        module: S,
+        instance: &ModuleInst,
+    ) -> &mut Imports {
+        let module = module.into();
+        for export in instance.exports() {
+            self.define(module.clone(), export.name(), *export.value());
+        }
+        self
+    }
+
+    /// Gets the item provided for imports of `name` from `module`, if any.
+    pub fn get(&self, module: &str, name: &str) -> Option<ExternVal> {
+        self.items
+            .get(&(module.to_owned(), name.to_owned()))
+            .copied()
+    }
+
+    pub fn len(&self) -> usize {
+        self.items.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        builder::{FuncBuilder, ModuleBuilder},
+        hosting::{Host, Imports},
+        Error, Instruction, ValType, Value,
+    };
+
+    fn constant(value: u32) -> ModuleBuilder {
+        ModuleBuilder::new().func(
+            FuncBuilder::new()
+                .export_as("f")
+                .result(ValType::I32)
+                .body(vec![Instruction::I32Const(Value::I32(value))]),
+        )
+    }
+
+    #[test]
+    pub fn imports_are_satisfied_by_the_chosen_instance() {
+        let mut host = Host::new();
+        host.instantiate("lib", constant(1).build()).unwrap();
+        let second = host.instantiate("lib", constant(2).build()).unwrap();
+
+        let module = || {
+            ModuleBuilder::new()
+                .func(
+                    FuncBuilder::new()
+                        .import_from("lib", "f")
+                        .result(ValType::I32),
+                )
+                .func(
+                    FuncBuilder::new()
+                        .export_as("main")
+                        .result(ValType::I32)
+                        .body(vec![Instruction::Call(0)]),
+                )
+                .build()
+        };
+        let mut imports = Imports::new();
+        imports.define_instance("lib", &host.get_module(second).unwrap());
+        let addr = host.instantiate_with("test", module(), &imports).unwrap();
+        assert_eq!(vec![Value::I32(2)], host.invoke(addr, "main", &[]).unwrap());
+
+        // Name lookup would find the first instance, but only the imports given are used
+        assert!(matches!(
+            host.instantiate_with("test", module(), &Imports::new()),
+            Err(Error::ExportNotFound { .. })
+        ));
+    }
+}
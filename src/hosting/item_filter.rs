@@ -0,0 +1,49 @@
+use crate::hosting::{ExternKind, ModuleAddr};
+
+/// Describes which items to enumerate when querying a [`Host`](crate::hosting::Host).
+///
+/// An empty filter matches every item. Each criteria that is set narrows the results further.
+#[derive(Clone, Default)]
+pub struct ItemFilter {
+    pub module: Option<ModuleAddr>,
+    pub kind: Option<ExternKind>,
+    pub name_prefix: Option<String>,
+}
+
+impl ItemFilter {
+    pub fn new() -> ItemFilter {
+        ItemFilter::default()
+    }
+
+    /// Only match items allocated by the specified module (imports are not included).
+    pub fn module(mut self, module: ModuleAddr) -> Self {
+        self.module = Some(module);
+        self
+    }
+
+    /// Only match items of the specified kind.
+    pub fn kind(mut self, kind: ExternKind) -> Self {
+        self.kind = Some(kind);
+        self
+    }
+
+    /// Only match items with a name starting with `prefix`.
+    pub fn name_prefix<S: Into<String>>(mut self, prefix: S) -> Self {
+        self.name_prefix = Some(prefix.into());
+        self
+    }
+
+    /// Checks if the filter allows items of the specified kind.
+    pub fn allows_kind(&self, kind: ExternKind) -> bool {
+        self.kind.is_none_or(|k| k == kind)
+    }
+
+    /// Checks if the filter allows an item with the specified name.
+    pub fn allows_name(&self, name: Option<&str>) -> bool {
+        match (&self.name_prefix, name) {
+            (None, _) => true,
+            (Some(prefix), Some(name)) => name.starts_with(prefix.as_str()),
+            (Some(_), None) => false,
+        }
+    }
+}
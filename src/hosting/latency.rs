@@ -0,0 +1,188 @@
+use std::time::Duration;
+
+/// The number of buckets each power of two is split into. Bucket bounds are within 1/8th
+/// (12.5%) of any value recorded in them.
+const SUB_BUCKETS: usize = 8;
+const SUB_BUCKET_BITS: u32 = 3;
+
+/// Enough buckets to cover every `u64` count of nanoseconds.
+const BUCKETS: usize = (64 - SUB_BUCKET_BITS as usize + 1) * SUB_BUCKETS;
+
+/// A histogram of call durations with a fixed number of logarithmic buckets.
+///
+/// Like an HDR histogram, each power of two is divided into equally sized sub-buckets, so the
+/// histogram takes the same amount of memory no matter how many durations are recorded, and
+/// the relative error is the same for short and long calls.
+#[derive(Clone)]
+pub struct LatencyHistogram {
+    counts: Box<[u64]>,
+    count: u64,
+    total: Duration,
+    min: Duration,
+    max: Duration,
+}
+
+impl LatencyHistogram {
+    pub fn new() -> LatencyHistogram {
+        LatencyHistogram {
+            counts: vec![0; BUCKETS].into_boxed_slice(),
+            count: 0,
+            total: Duration::ZERO,
+            min: Duration::MAX,
+            max: Duration::ZERO,
+        }
+    }
+
+    pub fn record(&mut self, duration: Duration) {
+        let nanos = duration.as_nanos().min(u64::MAX as u128) as u64;
+        self.counts[bucket_index(nanos)] += 1;
+        self.count += 1;
+        self.total = self.total.saturating_add(duration);
+        self.min = self.min.min(duration);
+        self.max = self.max.max(duration);
+    }
+
+    /// Gets the number of durations that have been recorded.
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+
+    /// Gets the sum of every recorded duration.
+    pub fn total(&self) -> Duration {
+        self.total
+    }
+
+    pub fn min(&self) -> Option<Duration> {
+        if self.count == 0 {
+            None
+        } else {
+            Some(self.min)
+        }
+    }
+
+    pub fn max(&self) -> Option<Duration> {
+        if self.count == 0 {
+            None
+        } else {
+            Some(self.max)
+        }
+    }
+
+    pub fn mean(&self) -> Option<Duration> {
+        if self.count == 0 {
+            None
+        } else {
+            Some(Duration::from_nanos(
+                (self.total.as_nanos() / self.count as u128) as u64,
+            ))
+        }
+    }
+
+    /// Gets an upper bound on the duration that `percentile` percent of calls completed within,
+    /// accurate to the width of the bucket it falls in.
+    pub fn percentile(&self, percentile: f64) -> Option<Duration> {
+        if self.count == 0 {
+            return None;
+        }
+
+        let rank = ((percentile.clamp(0.0, 100.0) / 100.0) * self.count as f64).ceil() as u64;
+        let mut seen = 0;
+        for (index, count) in self.counts.iter().enumerate() {
+            seen += count;
+            if seen >= rank.max(1) {
+                // The bucket bound may be looser than the largest value actually recorded
+                let (_, high) = bucket_range(index);
+                return Some(Duration::from_nanos(high).min(self.max));
+            }
+        }
+        Some(self.max)
+    }
+
+    /// Enumerates the non-empty buckets as the range of durations they cover, from the lowest
+    /// (inclusive) to the highest (inclusive), and the number of durations in that range.
+    pub fn buckets<'a>(&'a self) -> impl 'a + Iterator<Item = (Duration, Duration, u64)> {
+        self.counts
+            .iter()
+            .enumerate()
+            .filter(|(_, count)| **count > 0)
+            .map(|(index, count)| {
+                let (low, high) = bucket_range(index);
+                (
+                    Duration::from_nanos(low),
+                    Duration::from_nanos(high),
+                    *count,
+                )
+            })
+    }
+}
+
+impl Default for LatencyHistogram {
+    fn default() -> LatencyHistogram {
+        LatencyHistogram::new()
+    }
+}
+
+fn bucket_index(nanos: u64) -> usize {
+    if nanos < SUB_BUCKETS as u64 {
+        return nanos as usize;
+    }
+
+    // The highest set bit picks the power of two, the bits below it pick the sub-bucket
+    let exponent = 63 - nanos.leading_zeros();
+    let sub_bucket = (nanos >> (exponent - SUB_BUCKET_BITS)) as usize & (SUB_BUCKETS - 1);
+    (exponent - SUB_BUCKET_BITS + 1) as usize * SUB_BUCKETS + sub_bucket
+}
+
+fn bucket_range(index: usize) -> (u64, u64) {
+    if index < SUB_BUCKETS {
+        return (index as u64, index as u64);
+    }
+
+    let shift = (index / SUB_BUCKETS - 1) as u32;
+    let low = ((SUB_BUCKETS + index % SUB_BUCKETS) as u64) << shift;
+    (low, low + ((1u64 << shift) - 1))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::{bucket_index, bucket_range, LatencyHistogram, BUCKETS};
+
+    #[test]
+    pub fn buckets_cover_every_duration() {
+        for nanos in (0..10_000).chain([u64::MAX / 2, u64::MAX - 1, u64::MAX]) {
+            let index = bucket_index(nanos);
+            let (low, high) = bucket_range(index);
+            assert!(index < BUCKETS);
+            assert!(
+                low <= nanos && nanos <= high,
+                "{} in {}..={}",
+                nanos,
+                low,
+                high
+            );
+        }
+    }
+
+    #[test]
+    pub fn percentiles_are_bounded_by_their_bucket() {
+        let mut histogram = LatencyHistogram::new();
+        assert_eq!(None, histogram.percentile(50.0));
+
+        for micros in 1..=100 {
+            histogram.record(Duration::from_micros(micros));
+        }
+        assert_eq!(100, histogram.count());
+        assert_eq!(Some(Duration::from_micros(1)), histogram.min());
+        assert_eq!(Some(Duration::from_nanos(50_500)), histogram.mean());
+
+        let median = histogram.percentile(50.0).unwrap();
+        assert!(median >= Duration::from_micros(50));
+        assert!(median <= Duration::from_micros(50) * 9 / 8);
+        assert_eq!(
+            Some(Duration::from_micros(100)),
+            histogram.percentile(100.0)
+        );
+    }
+}
@@ -0,0 +1,173 @@
+use crate::hosting::ModuleAddr;
+
+/// Decides whether the memories and tables of a host may grow, and how large its arrays may be,
+/// so embedders can cap what each instance uses. See
+/// [`Host::set_limiter`](crate::hosting::Host::set_limiter).
+///
+/// Each growth method is told the instance that owns the memory or table, its current size, the size
+/// it's about to grow to, and the maximum its type allows. Allocating a memory or table when an
+/// instance is created counts as growing it from nothing. The methods are called before
+/// anything is allocated, so they also serve to observe growth as it happens, but growing may
+/// still fail for other reasons after they allow it.
+///
+/// By default, everything is allowed.
+pub trait ResourceLimiter: Send + Sync {
+    /// Decides whether a memory may grow from `current` to `desired` bytes. Refusing makes
+    /// `memory.grow` produce -1, and instantiation fail with
+    /// [`Error::ResourceLimitExceeded`](crate::Error::ResourceLimitExceeded).
+    fn memory_growing(
+        &self,
+        module: ModuleAddr,
+        current: usize,
+        desired: usize,
+        maximum: Option<usize>,
+    ) -> bool {
+        let _ = (module, current, desired, maximum);
+        true
+    }
+
+    /// Decides whether a table may grow from `current` to `desired` elements. Refusing makes
+    /// instantiation fail with [`Error::ResourceLimitExceeded`](crate::Error::ResourceLimitExceeded).
+    /// The interpreter doesn't implement `table.grow` yet, so tables are only checked when
+    /// they're allocated.
+    fn table_growing(
+        &self,
+        module: ModuleAddr,
+        current: usize,
+        desired: usize,
+        maximum: Option<usize>,
+    ) -> bool {
+        let _ = (module, current, desired, maximum);
+        true
+    }
+
+    /// Decides whether the instance `module` may allocate an array of `len` elements. Refusing
+    /// makes the instruction allocating it trap with
+    /// [`TrapCause::AllocationFailed`](crate::TrapCause::AllocationFailed).
+    fn array_allocating(&self, module: ModuleAddr, len: usize) -> bool {
+        let _ = (module, len);
+        true
+    }
+}
+
+/// A [`ResourceLimiter`] that caps the size of every memory, table and array, whichever
+/// instance owns it.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Limits {
+    memory_size: Option<usize>,
+    table_elements: Option<usize>,
+    array_elements: Option<usize>,
+}
+
+impl Limits {
+    pub fn new() -> Limits {
+        Limits::default()
+    }
+
+    /// Limits each memory to `bytes` bytes.
+    pub fn memory_size(mut self, bytes: usize) -> Limits {
+        self.memory_size = Some(bytes);
+        self
+    }
+
+    /// Limits each table to `elements` elements.
+    pub fn table_elements(mut self, elements: usize) -> Limits {
+        self.table_elements = Some(elements);
+        self
+    }
+
+    /// Limits each array to `elements` elements.
+    pub fn array_elements(mut self, elements: usize) -> Limits {
+        self.array_elements = Some(elements);
+        self
+    }
+}
+
+impl ResourceLimiter for Limits {
+    fn memory_growing(&self, _: ModuleAddr, _: usize, desired: usize, _: Option<usize>) -> bool {
+        self.memory_size.is_none_or(|limit| desired <= limit)
+    }
+
+    fn table_growing(&self, _: ModuleAddr, _: usize, desired: usize, _: Option<usize>) -> bool {
+        self.table_elements.is_none_or(|limit| desired <= limit)
+    }
+
+    fn array_allocating(&self, _: ModuleAddr, len: usize) -> bool {
+        self.array_elements.is_none_or(|limit| len <= limit)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use super::{Limits, ResourceLimiter};
+    use crate::{
+        builder::{FuncBuilder, ModuleBuilder},
+        hosting::{Host, ModuleAddr},
+        module::{MemoryType, Module, TableType},
+        Error, Instruction, ValType, Value,
+    };
+
+    /// Records the memory growth it's asked about, and refuses all of it.
+    struct Recorder(Arc<Mutex<Vec<(usize, usize)>>>);
+
+    impl ResourceLimiter for Recorder {
+        fn memory_growing(
+            &self,
+            _: ModuleAddr,
+            current: usize,
+            desired: usize,
+            _: Option<usize>,
+        ) -> bool {
+            self.0.lock().unwrap().push((current, desired));
+            false
+        }
+    }
+
+    fn with_mem(pages: usize) -> Module {
+        ModuleBuilder::new()
+            .mem("memory", MemoryType::new(pages, None))
+            .build()
+    }
+
+    #[test]
+    pub fn limiters_cap_memories_and_tables() {
+        let mut host = Host::new();
+        host.set_limiter(Limits::new().memory_size(0x10000).table_elements(10));
+        host.instantiate("small", with_mem(1)).unwrap();
+        assert!(matches!(
+            host.instantiate("large", with_mem(2)),
+            Err(Error::ResourceLimitExceeded {
+                resource: "memory",
+                ..
+            })
+        ));
+        let table = ModuleBuilder::new().table("table", TableType::new(20, None));
+        assert!(matches!(
+            host.instantiate("table", table.build()),
+            Err(Error::ResourceLimitExceeded {
+                resource: "table",
+                ..
+            })
+        ));
+
+        let module = ModuleBuilder::new()
+            .mem("memory", MemoryType::new(1, None))
+            .func(
+                FuncBuilder::new()
+                    .export_as("grow")
+                    .result(ValType::I32)
+                    .body(vec![
+                        Instruction::I32Const(Value::I32(1)),
+                        Instruction::MemoryGrow(0),
+                    ]),
+            );
+        let addr = host.instantiate("grow", module.build()).unwrap();
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        host.set_limiter(Recorder(requests.clone()));
+        let res = host.invoke(addr, "grow", &[]).unwrap();
+        assert_eq!(vec![Value::I32(u32::MAX)], res);
+        assert_eq!(vec![(0x10000, 0x20000)], *requests.lock().unwrap());
+    }
+}
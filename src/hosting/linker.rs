@@ -0,0 +1,140 @@
+use std::collections::HashMap;
+
+use crate::{
+    hosting::{Host, Imports, ModuleAddr},
+    module::Module,
+    Error,
+};
+
+/// Links modules against instances registered under names of the embedder's choosing.
+///
+/// [`Host::instantiate`] resolves imports against the first instance with the requested
+/// module name. A linker instead keeps its own names, so an instance or external module can
+/// be registered under several of them with [`Linker::alias`], and registering a name again
+/// replaces what it referred to. This is what the spec tests' `register` command expects.
+#[derive(Clone, Debug, Default)]
+pub struct Linker {
+    names: HashMap<String, ModuleAddr>,
+}
+
+impl Linker {
+    pub fn new() -> Linker {
+        Linker::default()
+    }
+
+    /// Makes the instance at `addr` satisfy imports from `name`, replacing whatever was
+    /// registered under `name` before.
+    pub fn register<S: Into<String>>(&mut self, name: S, addr: ModuleAddr) -> &mut Linker {
+        self.names.insert(name.into(), addr);
+        self
+    }
+
+    /// Registers the instance registered as `name` under `as_name` too. Fails with
+    /// [`Error::ModuleNotFound`] if nothing is registered as `name`.
+    pub fn alias<S: Into<String>>(&mut self, name: &str, as_name: S) -> Result<&mut Linker, Error> {
+        let addr = self.get(name).ok_or_else(|| Error::ModuleNotFound {
+            module: name.to_owned(),
+        })?;
+        Ok(self.register(as_name, addr))
+    }
+
+    /// Gets the instance registered as `name`, if any.
+    pub fn get(&self, name: &str) -> Option<ModuleAddr> {
+        self.names.get(name).copied()
+    }
+
+    /// Looks up the items that satisfy the imports of `module`, by the module name each import
+    /// asks for and then the export name. Types are checked when the module is instantiated.
+    pub fn imports(&self, host: &Host, module: &Module) -> Result<Imports, Error> {
+        let mut imports = Imports::new();
+        for import in module.imports() {
+            let addr = self
+                .get(import.module())
+                .ok_or_else(|| Error::ModuleNotFound {
+                    module: import.module().to_owned(),
+                })?;
+            let export = host.resolve_import(addr, import.name())?;
+            imports.define(import.module(), import.name(), *export.value());
+        }
+        Ok(imports)
+    }
+
+    /// Instantiates `module` in `host` with its imports resolved by this linker, then
+    /// registers the new instance as `name`.
+    pub fn instantiate<S: Into<String>>(
+        &mut self,
+        host: &mut Host,
+        name: S,
+        module: Module,
+    ) -> Result<ModuleAddr, Error> {
+        let name = name.into();
+        let imports = self.imports(host, &module)?;
+        let addr = host.instantiate_with(name.clone(), module, &imports)?;
+        self.register(name, addr);
+        Ok(addr)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        builder::{FuncBuilder, ModuleBuilder},
+        hosting::{Host, Linker},
+        module::Module,
+        Error, Instruction, ValType, Value,
+    };
+
+    fn constant(value: u32) -> Module {
+        ModuleBuilder::new()
+            .func(
+                FuncBuilder::new()
+                    .export_as("f")
+                    .result(ValType::I32)
+                    .body(vec![Instruction::I32Const(Value::I32(value))]),
+            )
+            .build()
+    }
+
+    fn importer(module: &str) -> Module {
+        ModuleBuilder::new()
+            .func(
+                FuncBuilder::new()
+                    .import_from(module, "f")
+                    .result(ValType::I32),
+            )
+            .func(
+                FuncBuilder::new()
+                    .export_as("main")
+                    .result(ValType::I32)
+                    .body(vec![Instruction::Call(0)]),
+            )
+            .build()
+    }
+
+    #[test]
+    pub fn imports_are_resolved_by_registered_name() {
+        let mut host = Host::new();
+        let mut linker = Linker::new();
+        linker.instantiate(&mut host, "one", constant(1)).unwrap();
+        linker.alias("one", "lib").unwrap();
+        assert!(matches!(
+            linker.alias("missing", "lib"),
+            Err(Error::ModuleNotFound { .. })
+        ));
+
+        let addr = linker.instantiate(&mut host, "a", importer("lib")).unwrap();
+        assert_eq!(vec![Value::I32(1)], host.invoke(addr, "main", &[]).unwrap());
+
+        // Registering a name again replaces it, even though the host finds the first instance
+        let two = host.instantiate("lib", constant(2)).unwrap();
+        linker.register("lib", two);
+        let addr = linker.instantiate(&mut host, "b", importer("lib")).unwrap();
+        assert_eq!(vec![Value::I32(2)], host.invoke(addr, "main", &[]).unwrap());
+        assert_eq!(Some(addr), linker.get("b"));
+
+        assert!(matches!(
+            linker.instantiate(&mut host, "c", importer("other")),
+            Err(Error::ModuleNotFound { .. })
+        ));
+    }
+}
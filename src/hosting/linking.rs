@@ -0,0 +1,277 @@
+use std::fmt;
+
+use crate::{
+    hosting::{ExternVal, ModuleAddr},
+    module::{FuncType, MemberDesc},
+    Error,
+};
+
+/// How one import of a module is satisfied by the modules registered with a host, or why it
+/// can't be. See [`Host::explain_imports`](crate::hosting::Host::explain_imports).
+#[derive(Debug)]
+pub struct ImportResolution {
+    module: String,
+    name: String,
+    description: MemberDesc,
+    outcome: Result<ExternVal, Error>,
+    provider: Option<ModuleAddr>,
+    ignored: usize,
+}
+
+impl ImportResolution {
+    pub(crate) fn new(
+        module: &str,
+        name: &str,
+        description: &MemberDesc,
+        provider: Option<ModuleAddr>,
+        ignored: usize,
+        outcome: Result<ExternVal, Error>,
+    ) -> ImportResolution {
+        ImportResolution {
+            module: module.to_owned(),
+            name: name.to_owned(),
+            description: description.clone(),
+            outcome,
+            provider,
+            ignored,
+        }
+    }
+
+    /// Gets the module name the import asks for.
+    pub fn module(&self) -> &str {
+        &self.module
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn description(&self) -> &MemberDesc {
+        &self.description
+    }
+
+    /// Gets the registered module that was searched for the item, if there is one with the
+    /// requested name.
+    pub fn provider(&self) -> Option<ModuleAddr> {
+        self.provider
+    }
+
+    /// Gets the number of other registered modules with the requested name. Only the first
+    /// module registered under a name is searched, so these never satisfy the import.
+    pub fn ignored(&self) -> usize {
+        self.ignored
+    }
+
+    /// Gets the item that satisfies the import, or the error instantiating would fail with.
+    pub fn outcome(&self) -> Result<ExternVal, &Error> {
+        self.outcome.as_ref().map(|v| *v)
+    }
+}
+
+impl fmt::Display for ImportResolution {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}.{} {} ", self.module, self.name, self.description)?;
+        match (&self.outcome, self.provider) {
+            (Ok(value), Some(provider)) => write!(f, "=> {} {:?}", provider, value)?,
+            (Ok(value), None) => write!(f, "=> {:?}", value)?,
+            (Err(Error::ModuleNotFound { .. }), _) => write!(f, "unresolved: no such module")?,
+            (Err(Error::ExportNotFound { .. }), Some(provider)) => {
+                write!(f, "unresolved: {} has no such export", provider)?
+            }
+            (
+                Err(Error::ImportTypeMismatch {
+                    expected, actual, ..
+                }),
+                Some(provider),
+            ) => write!(
+                f,
+                "unresolved: {} exports {}, expected {}",
+                provider, actual, expected
+            )?,
+            (Err(Error::ExportTypeMismatch { .. }), Some(provider)) => {
+                write!(f, "unresolved: {} exports an incompatible item", provider)?
+            }
+            (Err(e), _) => write!(f, "unresolved: {:?}", e)?,
+        }
+        if self.ignored > 0 {
+            write!(
+                f,
+                " (ignoring {} later module(s) named {})",
+                self.ignored, self.module
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// An import of a module that can't be satisfied by the modules registered with a host. See
+/// [`Host::check_imports`](crate::hosting::Host::check_imports).
+#[derive(Debug)]
+pub struct UnresolvedImport {
+    module: String,
+    name: String,
+    expected: MemberDesc,
+    func_type: Option<FuncType>,
+    error: Error,
+}
+
+impl UnresolvedImport {
+    pub(crate) fn new(
+        module: &str,
+        name: &str,
+        expected: &MemberDesc,
+        func_type: Option<&FuncType>,
+        error: Error,
+    ) -> UnresolvedImport {
+        UnresolvedImport {
+            module: module.to_owned(),
+            name: name.to_owned(),
+            expected: expected.clone(),
+            func_type: func_type.cloned(),
+            error,
+        }
+    }
+
+    /// Gets the module name the import asks for.
+    pub fn module(&self) -> &str {
+        &self.module
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Gets the kind of item the import asks for.
+    pub fn expected(&self) -> &MemberDesc {
+        &self.expected
+    }
+
+    /// Gets the signature a function import asks for.
+    pub fn func_type(&self) -> Option<&FuncType> {
+        self.func_type.as_ref()
+    }
+
+    /// Gets the error instantiating would fail with because of this import.
+    pub fn error(&self) -> &Error {
+        &self.error
+    }
+}
+
+impl fmt::Display for UnresolvedImport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}.{}: expected ", self.module, self.name)?;
+        match self.func_type {
+            Some(ref typ) => write_func_type(f, typ)?,
+            None => write!(f, "{}", self.expected)?,
+        }
+        match self.error {
+            Error::ModuleNotFound { .. } => write!(f, ", but there's no such module"),
+            Error::ExportNotFound { .. } => write!(f, ", but there's no such export"),
+            Error::ImportTypeMismatch { ref actual, .. } => {
+                write!(f, ", but found ")?;
+                write_func_type(f, actual)
+            }
+            Error::ExportTypeMismatch { .. } => write!(f, ", but found an incompatible item"),
+            ref e => write!(f, ", but {:?}", e),
+        }
+    }
+}
+
+/// Writes a function type the way the text format declares it.
+fn write_func_type(f: &mut fmt::Formatter, typ: &FuncType) -> fmt::Result {
+    if typ.params().is_empty() && typ.results().is_empty() {
+        write!(f, "(func)")
+    } else {
+        write!(f, "(func {})", typ)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        builder::{FuncBuilder, ModuleBuilder},
+        hosting::{ExternVal, Host},
+        Error, ValType,
+    };
+
+    #[test]
+    pub fn imports_are_explained() {
+        let mut host = Host::new();
+        let first = ModuleBuilder::new().func(FuncBuilder::new().export_as("f").body(vec![]));
+        let first = host.instantiate("lib", first.build()).unwrap();
+        let second = ModuleBuilder::new().func(
+            FuncBuilder::new()
+                .export_as("g")
+                .param(ValType::I32)
+                .body(vec![]),
+        );
+        host.instantiate("lib", second.build()).unwrap();
+
+        let module = ModuleBuilder::new()
+            .func(FuncBuilder::new().import_from("lib", "f"))
+            .func(FuncBuilder::new().import_from("lib", "g"))
+            .func(FuncBuilder::new().import_from("other", "h"))
+            .build();
+        let resolutions = host.explain_imports(&module);
+        assert_eq!(3, resolutions.len());
+
+        let f = host.get_module(first).unwrap().funcs()[0];
+        assert_eq!(Some(ExternVal::Func(f)), resolutions[0].outcome().ok());
+        assert_eq!(Some(first), resolutions[0].provider());
+        assert_eq!(1, resolutions[0].ignored());
+
+        // The second module exports g, but isn't searched
+        assert!(matches!(
+            resolutions[1].outcome(),
+            Err(Error::ExportNotFound { .. })
+        ));
+        assert_eq!(
+            "lib.g (func 0) unresolved: [ModuleAddr]0x0001 has no such export \
+             (ignoring 1 later module(s) named lib)",
+            resolutions[1].to_string()
+        );
+        assert!(matches!(
+            resolutions[2].outcome(),
+            Err(Error::ModuleNotFound { .. })
+        ));
+        assert_eq!(None, resolutions[2].provider());
+    }
+
+    #[test]
+    pub fn unresolved_imports_are_all_reported() {
+        let mut host = Host::new();
+        let lib = ModuleBuilder::new()
+            .func(FuncBuilder::new().export_as("f").body(vec![]))
+            .func(FuncBuilder::new().export_as("g").body(vec![]));
+        host.instantiate("lib", lib.build()).unwrap();
+
+        let module = ModuleBuilder::new()
+            .func(FuncBuilder::new().import_from("lib", "f"))
+            .func(
+                FuncBuilder::new()
+                    .import_from("lib", "g")
+                    .param(ValType::I32)
+                    .result(ValType::I64),
+            )
+            .func(FuncBuilder::new().import_from("lib", "h"))
+            .func(FuncBuilder::new().import_from("other", "i"))
+            .build();
+        let unresolved = host.check_imports(&module);
+        let messages: Vec<_> = unresolved.iter().map(|u| u.to_string()).collect();
+        assert_eq!(
+            vec![
+                "lib.g: expected (func (param i32) (result i64)), but found (func)",
+                "lib.h: expected (func), but there's no such export",
+                "other.i: expected (func), but there's no such module",
+            ],
+            messages
+        );
+        assert!(matches!(
+            unresolved[0].error(),
+            Error::ImportTypeMismatch { .. }
+        ));
+
+        // Checking doesn't instantiate anything
+        assert!(host.find_module("other").is_none());
+    }
+}
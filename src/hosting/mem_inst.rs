@@ -1,29 +1,454 @@
-use crate::{module::MemoryType, Error, Memory, PAGE_SIZE};
+use std::{
+    convert::TryFrom,
+    ffi::CString,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Condvar, Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+use crate::{
+    hosting::ModuleAddr, module::MemoryType, reader::SectionId, Error, LinearMemory, Memory,
+    MemoryBackend, MemoryConfig, SectionOffset, Trap, TrapCause, PAGE_SIZE,
+};
 
 addr_type!(MemAddr);
 
 pub struct MemInst {
+    module: ModuleAddr,
     mem: Memory,
+    shared: bool,
+    memory64: bool,
+    page_size: usize,
+    // Serializes atomic accesses so read-modify-write sequences are indivisible.
+    atomic_lock: Mutex<()>,
+    waiters: Mutex<Vec<Arc<Waiter>>>,
+    /// The thread running code that uses this memory, or 0. See [`MemInst::claim`].
+    owner: AtomicUsize,
+}
+
+/// The result of a `memory.atomic.wait` operation, as reported to WebAssembly code.
+#[repr(u32)]
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum WaitResult {
+    Woken = 0,
+    NotEqual = 1,
+    TimedOut = 2,
 }
 
 impl MemInst {
-    pub fn from_type(mem_type: &MemoryType) -> Result<MemInst, Error> {
-        match mem_type.max() {
-            Some(max) => MemInst::new(
-                mem_type.min() as usize * PAGE_SIZE,
-                Some(max as usize * PAGE_SIZE),
-            ),
-            None => MemInst::new(mem_type.min() as usize * PAGE_SIZE, None),
+    /// Allocates a memory of type `mem_type` from `backend`.
+    pub fn from_type(
+        module: ModuleAddr,
+        mem_type: &MemoryType,
+        backend: &dyn MemoryBackend,
+    ) -> Result<MemInst, Error> {
+        MemInst::with_config(module, mem_type, backend, &MemoryConfig::default())
+    }
+
+    /// Allocates a memory of type `mem_type` from `backend`, as `config` says. The memory's
+    /// maximum size is the smaller of what its type and `config.max_pages` allow.
+    pub fn with_config(
+        module: ModuleAddr,
+        mem_type: &MemoryType,
+        backend: &dyn MemoryBackend,
+        config: &MemoryConfig,
+    ) -> Result<MemInst, Error> {
+        let pages_to_bytes = |pages: usize| {
+            pages
+                .checked_mul(mem_type.page_size())
+                .ok_or(Error::LimitsOutOfRange {
+                    min: mem_type.min() as u64,
+                    max: mem_type.max().map(|m| m as u64),
+                    at: SectionOffset::in_section(SectionId::Memory),
+                })
+        };
+        let max_pages = match (mem_type.max(), config.max_pages) {
+            (Some(max), Some(cap)) => Some(max.min(cap)),
+            (max, cap) => max.or(cap),
+        };
+        let max_size = match max_pages {
+            Some(max) => Some(pages_to_bytes(max)?),
+            None => None,
+        };
+        let min_size = pages_to_bytes(mem_type.min())?;
+        if max_size.is_some_and(|max_size| min_size > max_size) {
+            return Err(Error::LimitsOutOfRange {
+                min: mem_type.min() as u64,
+                max: max_pages.map(|m| m as u64),
+                at: SectionOffset::in_section(SectionId::Memory),
+            });
+        }
+        // Shared memories are used by several threads at once, so they mustn't move to grow
+        let mut config = *config;
+        if mem_type.shared() {
+            config.reserve = max_size.unwrap_or(min_size);
+        }
+        let mem = Memory::with_config(backend, min_size, max_size, &config)?;
+        if mem.len() % mem_type.page_size() != 0 {
+            return Err(Error::InvalidState {
+                module: None,
+                reason: "memory was loaded with contents that aren't a whole number of pages",
+            });
+        }
+        let mut inst = MemInst::with_memory(module, mem);
+        inst.shared = mem_type.shared();
+        inst.memory64 = mem_type.is_64();
+        inst.page_size = mem_type.page_size();
+        Ok(inst)
+    }
+
+    pub fn new(
+        module: ModuleAddr,
+        min_size: usize,
+        max_size: Option<usize>,
+        backend: &dyn MemoryBackend,
+    ) -> Result<MemInst, Error> {
+        let mem = Memory::with_backend(backend, min_size, max_size)?;
+        Ok(MemInst::with_memory(module, mem))
+    }
+
+    /// Creates a memory of `min_pages` pages kept in `storage`, which the caller has already
+    /// allocated. See [`Memory::with_storage`].
+    pub fn with_storage(
+        module: ModuleAddr,
+        storage: Box<dyn LinearMemory>,
+        min_pages: usize,
+        max_pages: Option<usize>,
+    ) -> MemInst {
+        let max_size = max_pages.and_then(|max| max.checked_mul(PAGE_SIZE));
+        let mem = Memory::with_storage(storage, min_pages.saturating_mul(PAGE_SIZE), max_size);
+        MemInst::with_memory(module, mem)
+    }
+
+    fn with_memory(module: ModuleAddr, mem: Memory) -> MemInst {
+        MemInst {
+            module,
+            mem,
+            shared: false,
+            memory64: false,
+            page_size: PAGE_SIZE,
+            atomic_lock: Mutex::new(()),
+            waiters: Mutex::new(Vec::new()),
+            owner: AtomicUsize::new(0),
         }
     }
 
-    pub fn new(min_size: usize, max_size: Option<usize>) -> Result<MemInst, Error> {
+    /// Creates a copy of this memory for `module` with storage from `backend`, see
+    /// [`Host::fork_instance`](crate::hosting::Host::fork_instance).
+    pub fn fork(&self, module: ModuleAddr, backend: &dyn MemoryBackend) -> Result<MemInst, Error> {
+        // Hold the atomic lock so atomic read-modify-writes aren't copied half-done
+        let mem = {
+            let _guard = self.atomic_lock.lock().unwrap();
+            self.mem.fork(backend)?
+        };
         Ok(MemInst {
-            mem: Memory::new(min_size, max_size)?,
+            module,
+            mem,
+            shared: self.shared,
+            memory64: self.memory64,
+            page_size: self.page_size,
+            atomic_lock: Mutex::new(()),
+            waiters: Mutex::new(Vec::new()),
+            owner: AtomicUsize::new(0),
         })
     }
 
+    /// Gets the address of the module that allocated this memory.
+    pub fn module(&self) -> ModuleAddr {
+        self.module
+    }
+
     pub fn memory(&self) -> &Memory {
         &self.mem
     }
+
+    /// Gets a boolean indicating if this memory was declared `shared`.
+    pub fn shared(&self) -> bool {
+        self.shared
+    }
+
+    /// Claims this memory for the thread identified by `thread`, which must not be 0, so no
+    /// other thread runs code that uses it at the same time. Produces `false` if another thread
+    /// holds the claim. Shared memories are meant to be used by several threads, so claiming
+    /// one always succeeds and has no effect.
+    pub(crate) fn claim(&self, thread: usize) -> bool {
+        self.shared
+            || match self
+                .owner
+                .compare_exchange(0, thread, Ordering::Acquire, Ordering::Relaxed)
+            {
+                Ok(_) => true,
+                Err(owner) => owner == thread,
+            }
+    }
+
+    /// Releases the claim `thread` holds on this memory, if any.
+    pub(crate) fn release(&self, thread: usize) {
+        let _ = self
+            .owner
+            .compare_exchange(thread, 0, Ordering::Release, Ordering::Relaxed);
+    }
+
+    /// Gets a boolean indicating if this memory is indexed by 64-bit addresses.
+    pub fn is_64(&self) -> bool {
+        self.memory64
+    }
+
+    /// Grows the memory by `pages` pages, producing its previous size in pages, or `None` if it
+    /// can't grow that far. See [`Memory::grow`].
+    pub fn grow(&self, pages: u64) -> Option<u64> {
+        let additional = usize::try_from(pages).ok()?.checked_mul(self.page_size)?;
+        // Hold the atomic lock so the accessors above never see the storage move
+        let len = {
+            let _guard = self.atomic_lock.lock().unwrap();
+            self.mem.grow(additional)?
+        };
+        Some((len / self.page_size) as u64)
+    }
+
+    /// Gets the size, in bytes, of the pages `memory.size` and `memory.grow` count in.
+    pub fn page_size(&self) -> usize {
+        self.page_size
+    }
+
+    /// Writes the contents of the memory to the storage backing it, such as the file of a
+    /// [`FileBackend`](crate::FileBackend). Does nothing for memories allocated on the heap.
+    pub fn persist(&self) -> Result<(), Error> {
+        // Hold the atomic lock so atomic read-modify-writes aren't persisted half-done
+        let _guard = self.atomic_lock.lock().unwrap();
+        self.mem.persist()
+    }
+
+    /// Runs `f` over the `len` bytes at address `offset` with exclusive access with respect to
+    /// all other atomic operations on this memory, trapping if they aren't all in the memory.
+    ///
+    /// `f` is given a copy of the bytes, which is written back if `f` changes it. Other threads
+    /// may make non-atomic accesses to a shared memory meanwhile, which WebAssembly permits, so
+    /// the contents are never borrowed.
+    pub fn atomically<T, F: FnOnce(&mut [u8]) -> T>(
+        &self,
+        offset: usize,
+        len: usize,
+        f: F,
+    ) -> Result<T, Trap> {
+        let _guard = self.atomic_lock.lock().unwrap();
+        let mut bytes = vec![0; len];
+        if !self.mem.read(offset, &mut bytes) {
+            return Err(TrapCause::OutOfBoundsMemoryAccess.into());
+        }
+        let copy = bytes.clone();
+        let res = f(&mut bytes);
+        if bytes != copy {
+            self.mem.write(offset, &bytes);
+        }
+        Ok(res)
+    }
+
+    /// Copies `buf.len()` bytes, starting at address `offset`, into `buf`.
+    ///
+    /// Like all of the accessors below, this traps with
+    /// [`TrapCause::OutOfBoundsMemoryAccess`] if the bytes aren't all in the memory, so host
+    /// functions can pass the trap on with `?`. Accesses are performed with exclusive access
+    /// with respect to atomic operations on the memory. They don't claim the memory, so they
+    /// race with another thread running code over it, see [`Host`](crate::hosting::Host).
+    pub fn read(&self, offset: usize, buf: &mut [u8]) -> Result<(), Trap> {
+        let _guard = self.atomic_lock.lock().unwrap();
+        if self.mem.read(offset, buf) {
+            Ok(())
+        } else {
+            Err(TrapCause::OutOfBoundsMemoryAccess.into())
+        }
+    }
+
+    /// Copies `bytes` into the memory, starting at address `offset`.
+    pub fn write(&self, offset: usize, bytes: &[u8]) -> Result<(), Trap> {
+        let _guard = self.atomic_lock.lock().unwrap();
+        if self.mem.write(offset, bytes) {
+            Ok(())
+        } else {
+            Err(TrapCause::OutOfBoundsMemoryAccess.into())
+        }
+    }
+
+    /// Reads a little-endian `u32` at address `offset`.
+    pub fn read_u32(&self, offset: usize) -> Result<u32, Trap> {
+        let mut bytes = [0; 4];
+        self.read(offset, &mut bytes)?;
+        Ok(u32::from_le_bytes(bytes))
+    }
+
+    /// Reads a little-endian `u64` at address `offset`.
+    pub fn read_u64(&self, offset: usize) -> Result<u64, Trap> {
+        let mut bytes = [0; 8];
+        self.read(offset, &mut bytes)?;
+        Ok(u64::from_le_bytes(bytes))
+    }
+
+    /// Reads the NUL-terminated string at address `offset`. Traps if the memory ends before
+    /// the terminator.
+    pub fn read_cstr(&self, offset: usize) -> Result<CString, Trap> {
+        let _guard = self.atomic_lock.lock().unwrap();
+        let mut bytes = Vec::new();
+        let mut byte = [0];
+        loop {
+            if !self.mem.read(offset + bytes.len(), &mut byte) {
+                return Err(TrapCause::OutOfBoundsMemoryAccess.into());
+            }
+            match byte[0] {
+                0 => return Ok(CString::new(bytes).expect("the string ends at its NUL")),
+                b => bytes.push(b),
+            }
+        }
+    }
+
+    /// Reads the `len`-byte UTF-8 string at address `offset`. Traps if it isn't valid UTF-8.
+    pub fn read_utf8(&self, offset: usize, len: usize) -> Result<String, Trap> {
+        let mut bytes = vec![0; len];
+        self.read(offset, &mut bytes)?;
+        String::from_utf8(bytes).map_err(|e| format!("Invalid UTF-8 string: {}", e).into())
+    }
+
+    /// Suspends the calling thread until another thread calls [`MemInst::notify`] for `addr`,
+    /// or `timeout` expires.
+    ///
+    /// `expected` is evaluated atomically against the `len` bytes at `addr` before suspending,
+    /// and the wait is abandoned with [`WaitResult::NotEqual`] if it returns `false`. Traps if
+    /// the bytes aren't all in the memory.
+    pub fn wait<F: FnOnce(&[u8]) -> bool>(
+        &self,
+        addr: usize,
+        len: usize,
+        expected: F,
+        timeout: Option<Duration>,
+    ) -> Result<WaitResult, Trap> {
+        let waiter = {
+            let _guard = self.atomic_lock.lock().unwrap();
+            let mut bytes = vec![0; len];
+            if !self.mem.read(addr, &mut bytes) {
+                return Err(TrapCause::OutOfBoundsMemoryAccess.into());
+            }
+            if !expected(&bytes) {
+                return Ok(WaitResult::NotEqual);
+            }
+
+            // Register while still holding the lock so a notify can't slip in between the
+            // comparison and the registration.
+            let waiter = Arc::new(Waiter::new(addr));
+            self.waiters.lock().unwrap().push(waiter.clone());
+            waiter
+        };
+
+        if waiter.wait(timeout) {
+            return Ok(WaitResult::Woken);
+        }
+
+        // If we're still registered, nobody woke us. Otherwise a notify raced the timeout.
+        let mut waiters = self.waiters.lock().unwrap();
+        match waiters.iter().position(|w| Arc::ptr_eq(w, &waiter)) {
+            Some(idx) => {
+                waiters.remove(idx);
+                Ok(WaitResult::TimedOut)
+            }
+            None => Ok(WaitResult::Woken),
+        }
+    }
+
+    /// Wakes up to `count` threads waiting on `addr`, in the order they started waiting.
+    ///
+    /// Returns the number of threads that were woken.
+    pub fn notify(&self, addr: usize, count: u32) -> u32 {
+        let mut waiters = self.waiters.lock().unwrap();
+        let mut woken = 0;
+        let mut idx = 0;
+        while idx < waiters.len() && woken < count {
+            if waiters[idx].addr == addr {
+                waiters.remove(idx).wake();
+                woken += 1;
+            } else {
+                idx += 1;
+            }
+        }
+        woken
+    }
+}
+
+struct Waiter {
+    addr: usize,
+    woken: Mutex<bool>,
+    cond: Condvar,
+}
+
+impl Waiter {
+    fn new(addr: usize) -> Waiter {
+        Waiter {
+            addr,
+            woken: Mutex::new(false),
+            cond: Condvar::new(),
+        }
+    }
+
+    fn wake(&self) {
+        *self.woken.lock().unwrap() = true;
+        self.cond.notify_one();
+    }
+
+    /// Blocks until woken or until `timeout` expires, returning `true` if woken.
+    fn wait(&self, timeout: Option<Duration>) -> bool {
+        let deadline = timeout.map(|t| Instant::now() + t);
+        let mut woken = self.woken.lock().unwrap();
+        while !*woken {
+            woken = match deadline {
+                None => self.cond.wait(woken).unwrap(),
+                Some(deadline) => {
+                    let now = Instant::now();
+                    if now >= deadline {
+                        break;
+                    }
+                    self.cond.wait_timeout(woken, deadline - now).unwrap().0
+                }
+            };
+        }
+        *woken
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        hosting::{MemInst, ModuleAddr},
+        HeapBackend, TrapCause,
+    };
+
+    #[test]
+    pub fn memory_is_accessed_safely() {
+        let mem = MemInst::new(ModuleAddr::new(1).unwrap(), 32, None, &HeapBackend).unwrap();
+        mem.write(0, b"wasm\0").unwrap();
+        mem.write(8, &0x0102_0304u32.to_le_bytes()).unwrap();
+        mem.write(24, &u64::MAX.to_le_bytes()).unwrap();
+
+        assert_eq!(0x0102_0304, mem.read_u32(8).unwrap());
+        assert_eq!(u64::MAX, mem.read_u64(24).unwrap());
+        assert_eq!("wasm", mem.read_cstr(0).unwrap().to_str().unwrap());
+        assert_eq!("as", mem.read_utf8(1, 2).unwrap());
+        assert!(mem.read_utf8(24, 2).is_err());
+
+        let out_of_bounds =
+            |trap: crate::Trap| matches!(trap.cause(), TrapCause::OutOfBoundsMemoryAccess);
+        assert!(out_of_bounds(mem.read_u64(25).unwrap_err()));
+        assert!(out_of_bounds(mem.write(usize::MAX, b"x").unwrap_err()));
+        // The string at 24 runs into the end of the memory without a terminator
+        assert!(out_of_bounds(mem.read_cstr(24).unwrap_err()));
+
+        // Atomic accesses see the bytes they ask for, and only write back what they change
+        let old = mem.atomically(8, 4, |bytes| {
+            let old = u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+            bytes.copy_from_slice(&(old + 1).to_le_bytes());
+            old
+        });
+        assert_eq!(0x0102_0304, old.unwrap());
+        assert_eq!(0x0102_0305, mem.read_u32(8).unwrap());
+        assert!(out_of_bounds(mem.atomically(30, 4, |_| ()).unwrap_err()));
+    }
 }
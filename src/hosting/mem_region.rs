@@ -0,0 +1,169 @@
+use crate::{
+    hosting::{Host, MemAddr},
+    Trap, TrapCause,
+};
+
+/// A named range of bytes in a module's memory, such as a buffer used to pass messages between
+/// the embedder and the module.
+///
+/// A module declares a region named `inbox` by exporting two integer globals:
+/// `__region_inbox`, holding the address the region starts at, and `__region_inbox_size`,
+/// holding its length in bytes. The region is in the module's first memory. See
+/// [`Host::region`](crate::hosting::Host::region).
+///
+/// Accesses are checked against the bounds of the region, and are performed with exclusive
+/// access with respect to atomic operations on the memory.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct MemRegion {
+    mem: MemAddr,
+    start: usize,
+    len: usize,
+}
+
+impl MemRegion {
+    pub fn new(mem: MemAddr, start: usize, len: usize) -> MemRegion {
+        MemRegion { mem, start, len }
+    }
+
+    pub fn mem(&self) -> MemAddr {
+        self.mem
+    }
+
+    /// Gets the address of the first byte of the region.
+    pub fn start(&self) -> usize {
+        self.start
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Copies `buf.len()` bytes, starting `offset` bytes into the region, into `buf`.
+    pub fn read(&self, host: &Host, offset: usize, buf: &mut [u8]) -> Result<(), Trap> {
+        let (start, _) = self.range(offset, buf.len())?;
+        host.mem(self.mem).read(start, buf)
+    }
+
+    /// Copies `bytes` into the region, starting `offset` bytes into it.
+    pub fn write(&self, host: &Host, offset: usize, bytes: &[u8]) -> Result<(), Trap> {
+        let (start, _) = self.range(offset, bytes.len())?;
+        host.mem(self.mem).write(start, bytes)
+    }
+
+    /// Reads a little-endian value `offset` bytes into the region.
+    pub fn get<T: RegionValue>(&self, host: &Host, offset: usize) -> Result<T, Trap> {
+        let mut bytes = [0; 8];
+        self.read(host, offset, &mut bytes[..T::SIZE])?;
+        Ok(T::from_le(&bytes[..T::SIZE]))
+    }
+
+    /// Writes a little-endian value `offset` bytes into the region.
+    pub fn set<T: RegionValue>(&self, host: &Host, offset: usize, value: T) -> Result<(), Trap> {
+        let mut bytes = [0; 8];
+        value.to_le(&mut bytes[..T::SIZE]);
+        self.write(host, offset, &bytes[..T::SIZE])
+    }
+
+    /// Gets the addresses of `len` bytes starting `offset` bytes into the region, if they're
+    /// within it.
+    fn range(&self, offset: usize, len: usize) -> Result<(usize, usize), Trap> {
+        match offset.checked_add(len) {
+            Some(end) if end <= self.len => match self.start.checked_add(end) {
+                Some(last) => Ok((self.start + offset, last)),
+                None => Err(TrapCause::OutOfBoundsMemoryAccess.into()),
+            },
+            _ => Err(TrapCause::OutOfBoundsMemoryAccess.into()),
+        }
+    }
+}
+
+/// A value that can be read from and written to a [`MemRegion`], in little-endian byte order
+/// like WebAssembly loads and stores.
+pub trait RegionValue: Copy {
+    const SIZE: usize;
+
+    fn from_le(bytes: &[u8]) -> Self;
+    fn to_le(self, bytes: &mut [u8]);
+}
+
+macro_rules! region_value {
+    ($($t: ty),*) => {
+        $(
+            impl RegionValue for $t {
+                const SIZE: usize = ::std::mem::size_of::<$t>();
+
+                fn from_le(bytes: &[u8]) -> $t {
+                    let mut buf = [0; ::std::mem::size_of::<$t>()];
+                    buf.copy_from_slice(bytes);
+                    <$t>::from_le_bytes(buf)
+                }
+
+                fn to_le(self, bytes: &mut [u8]) {
+                    bytes.copy_from_slice(&self.to_le_bytes());
+                }
+            }
+        )*
+    };
+}
+
+region_value!(u8, i8, u16, i16, u32, i32, u64, i64, f32, f64);
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        builder::ModuleBuilder,
+        hosting::Host,
+        module::{Expr, Global, GlobalType, MemoryType},
+        Error, Instruction, TrapCause, ValType, Value,
+    };
+
+    fn constant(value: u32) -> Global {
+        Global::new(
+            GlobalType::new(ValType::I32, false),
+            Expr::new(vec![Instruction::I32Const(Value::I32(value))]),
+        )
+    }
+
+    #[test]
+    pub fn regions_are_declared_by_exported_globals() {
+        let module = ModuleBuilder::new()
+            .mem("memory", MemoryType::new(1, None))
+            .global("__region_inbox", constant(0x100))
+            .global("__region_inbox_size", constant(16))
+            .global("__region_bad_size", constant(0));
+        let mut host = Host::new();
+        let addr = host.synthesize("test", module).unwrap();
+
+        let inbox = host.region(addr, "inbox").unwrap();
+        assert_eq!((0x100, 16), (inbox.start(), inbox.len()));
+        inbox.set(&host, 4, 0xCAFE_F00Du32).unwrap();
+        inbox.set(&host, 8, -1.5f64).unwrap();
+        assert_eq!(0xCAFE_F00Du32, inbox.get::<u32>(&host, 4).unwrap());
+        assert_eq!(0xF00Du16, inbox.get::<u16>(&host, 4).unwrap());
+        assert_eq!(-1.5f64, inbox.get::<f64>(&host, 8).unwrap());
+
+        // The bytes land at the region's address in the module's memory
+        let mem = host.get_mem(inbox.mem()).unwrap();
+        let mut stored = [0; 4];
+        mem.read(0x104, &mut stored).unwrap();
+        assert_eq!([0x0D, 0xF0, 0xFE, 0xCA], stored);
+
+        // Accesses can't stray outside of the region
+        let trap = inbox.get::<u64>(&host, 12).unwrap_err();
+        assert!(matches!(trap.cause(), TrapCause::OutOfBoundsMemoryAccess));
+
+        // Both globals are required
+        assert!(matches!(
+            host.region(addr, "outbox"),
+            Err(Error::ExportNotFound { .. })
+        ));
+        assert!(matches!(
+            host.region(addr, "bad"),
+            Err(Error::ExportNotFound { .. })
+        ));
+    }
+}
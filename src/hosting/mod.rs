@@ -1,24 +1,55 @@
 macro_rules! addr_type {
     ($name: ident) => {
-        #[derive(Clone, Copy, PartialEq)]
-        pub struct $name(::std::num::NonZeroUsize);
-        
+        /// The address of an item in a [`Host`](crate::hosting::Host). Along with the slot
+        /// the item is stored in, it records the generation of the slot, which changes when
+        /// the item is dropped, so a stale address can't be mistaken for whatever reuses the
+        /// slot.
+        #[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+        pub struct $name(::std::num::NonZeroUsize, u32);
+
         impl $name {
+            /// Creates the address of slot `id - 1`, in its first generation.
             pub fn new(id: usize) -> Option<$name> {
                 match ::std::num::NonZeroUsize::new(id) {
-                    Some(id) => Some($name(id)),
+                    Some(id) => Some($name(id, 0)),
                     None => None,
                 }
             }
-        
+
             pub fn val(&self) -> usize {
                 self.0.get() - 1
             }
+
+            /// Gets the number of times the slot had been freed when the address was created.
+            pub fn generation(&self) -> u32 {
+                self.1
+            }
+        }
+
+        impl $crate::hosting::SlotAddr for $name {
+            fn at(index: usize, generation: u32) -> $name {
+                $name(
+                    ::std::num::NonZeroUsize::new(index + 1).expect("slot index overflowed"),
+                    generation,
+                )
+            }
+
+            fn val(&self) -> usize {
+                self.0.get() - 1
+            }
+
+            fn generation(&self) -> u32 {
+                self.1
+            }
         }
-        
+
         impl ::std::fmt::Display for $name {
             fn fmt(&self, f: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
-                write!(f, concat!("[", stringify!($name), "]0x{:04X}"), self.0)
+                write!(f, concat!("[", stringify!($name), "]0x{:04X}"), self.0)?;
+                if self.1 > 0 {
+                    write!(f, "/{}", self.1)?;
+                }
+                Ok(())
             }
         }
 
@@ -36,18 +67,66 @@ macro_rules! addr_type {
     };
 }
 
+mod caller;
+mod const_expr;
+mod events;
 mod export_inst;
+mod external;
 mod func_inst;
+mod global_inst;
 mod host;
+mod host_data;
+mod host_func;
+mod host_snapshot;
+mod imports;
+mod item_filter;
+mod latency;
+mod limiter;
+mod linker;
+mod linking;
 mod mem_inst;
+mod mem_region;
 mod module_inst;
-mod external;
-mod host_func;
+#[cfg(feature = "gc")]
+mod object_inst;
+mod output;
+mod saved_state;
+mod slots;
+mod stub;
+mod table_inst;
+#[cfg(test)]
+mod tests;
+mod typed_func;
+mod wasm_ptr;
 
-pub use self::export_inst::{ExportInst, ExternVal};
+pub use self::caller::Caller;
+pub use self::const_expr::ConstExpr;
+pub use self::events::{to_json, Event, EventSink, JsonEventWriter};
+pub use self::export_inst::{ExportInst, ExternKind, ExternVal};
+pub use self::external::{
+    ExternalFunc, ExternalGlobal, ExternalMemory, ExternalModule, ExternalTable,
+};
 pub use self::func_inst::{FuncAddr, FuncImpl, FuncInst};
+pub use self::global_inst::{GlobalAddr, GlobalInst};
 pub use self::host::Host;
-pub use self::mem_inst::{MemAddr, MemInst};
+pub use self::host_func::{AsyncHostFunc, DynHostFunc, HostFunc, HostFuture};
+pub use self::host_snapshot::HostSnapshot;
+pub use self::imports::Imports;
+pub use self::item_filter::ItemFilter;
+pub use self::latency::LatencyHistogram;
+pub use self::limiter::{Limits, ResourceLimiter};
+pub use self::linker::Linker;
+pub use self::linking::{ImportResolution, UnresolvedImport};
+pub use self::mem_inst::{MemAddr, MemInst, WaitResult};
+pub use self::mem_region::{MemRegion, RegionValue};
 pub use self::module_inst::{ModuleAddr, ModuleInst};
-pub use self::external::{ExternalModule, ExternalFunc, ExternalMemory};
-pub use self::host_func::HostFunc;
+#[cfg(feature = "gc")]
+pub use self::object_inst::{ObjectAddr, ObjectInst};
+pub use self::output::OutputBuffer;
+pub(crate) use self::output::{write_to, OutputSink};
+pub(crate) use self::saved_state::{read_value, write_value};
+pub(crate) use self::slots::{SlotAddr, Slots};
+pub use self::stub::Stub;
+pub use self::table_inst::{TableAddr, TableInst};
+pub use self::typed_func::{IntoHostFunc, WasmResults, WasmType};
+pub use self::wasm_ptr::WasmPtr;
@@ -1,31 +1,45 @@
 use crate::{
-    hosting::{ExportInst, FuncAddr, MemAddr},
-    module::ModuleNames,
+    hosting::{ExportInst, ExternVal, FuncAddr, GlobalAddr, MemAddr, TableAddr},
+    module::{ModuleNames, TypeDef},
+    reader::SectionId,
+    Error, SectionOffset,
 };
 
 addr_type!(ModuleAddr);
 
+#[derive(Clone)]
 pub struct ModuleInst {
     // TODO: Consider making names Cow<'static, str>
     name: String,
+    types: Vec<TypeDef>,
     funcs: Vec<FuncAddr>,
+    tables: Vec<TableAddr>,
     mems: Vec<MemAddr>,
+    globals: Vec<GlobalAddr>,
     exports: Vec<ExportInst>,
     names: Option<ModuleNames>,
 }
 
 impl ModuleInst {
+    // Each index space of the instance is provided separately.
+    #[allow(clippy::too_many_arguments)]
     pub fn new<S: Into<String>>(
         name: S,
+        types: Vec<TypeDef>,
         funcs: Vec<FuncAddr>,
+        tables: Vec<TableAddr>,
         mems: Vec<MemAddr>,
+        globals: Vec<GlobalAddr>,
         exports: Vec<ExportInst>,
         names: Option<ModuleNames>,
     ) -> ModuleInst {
         ModuleInst {
             name: name.into(),
+            types,
             funcs,
+            tables,
             mems,
+            globals,
             exports,
             names,
         }
@@ -35,14 +49,26 @@ impl ModuleInst {
         &self.name
     }
 
+    pub fn types(&self) -> &[TypeDef] {
+        &self.types
+    }
+
     pub fn funcs(&self) -> &[FuncAddr] {
         &self.funcs
     }
 
+    pub fn tables(&self) -> &[TableAddr] {
+        &self.tables
+    }
+
     pub fn mems(&self) -> &[MemAddr] {
         &self.mems
     }
 
+    pub fn globals(&self) -> &[GlobalAddr] {
+        &self.globals
+    }
+
     pub fn exports(&self) -> &[ExportInst] {
         &self.exports
     }
@@ -51,15 +77,134 @@ impl ModuleInst {
         self.names.as_ref()
     }
 
-    pub fn get_mem(&self, mem_idx: usize) -> MemAddr {
-        self.mems[mem_idx]
+    pub fn get_mem(&self, mem_idx: usize) -> Option<MemAddr> {
+        self.mems.get(mem_idx).cloned()
+    }
+
+    pub fn get_func(&self, func_idx: usize) -> Option<FuncAddr> {
+        self.funcs.get(func_idx).cloned()
+    }
+
+    pub fn get_table(&self, table_idx: usize) -> Option<TableAddr> {
+        self.tables.get(table_idx).cloned()
     }
 
-    pub fn get_func(&self, func_idx: usize) -> FuncAddr {
-        self.funcs[func_idx]
+    pub fn get_global(&self, global_idx: usize) -> Option<GlobalAddr> {
+        self.globals.get(global_idx).cloned()
+    }
+
+    /// Adds `value` to the end of its index space and exports it as `name`.
+    pub(crate) fn add_export(&mut self, name: &str, value: ExternVal) -> Result<(), Error> {
+        if self.find_export(name).is_some() {
+            return Err(Error::DuplicateExportName {
+                name: name.to_owned(),
+                at: SectionOffset::in_section(SectionId::Export),
+            });
+        }
+        let export = match value {
+            ExternVal::Func(addr) => {
+                self.funcs.push(addr);
+                ExportInst::func(name, addr)
+            }
+            ExternVal::Table(addr) => {
+                self.tables.push(addr);
+                ExportInst::table(name, addr)
+            }
+            ExternVal::Mem(addr) => {
+                self.mems.push(addr);
+                ExportInst::mem(name, addr)
+            }
+            ExternVal::Global(addr) => {
+                self.globals.push(addr);
+                ExportInst::global(name, addr)
+            }
+        };
+        self.exports.push(export);
+        Ok(())
     }
 
     pub fn find_export(&self, name: &str) -> Option<&ExportInst> {
         self.exports.iter().find(|e| e.name() == name)
     }
+
+    /// Gets the function exported as `name`. Fails with [`Error::ExportNotFound`] if nothing
+    /// is exported as `name`, or [`Error::ExportTypeMismatch`] if it isn't a function.
+    pub fn export_func(&self, name: &str) -> Result<FuncAddr, Error> {
+        match self.export_value(name)? {
+            ExternVal::Func(addr) => Ok(addr),
+            _ => Err(self.export_mismatch(name)),
+        }
+    }
+
+    /// Gets the table exported as `name`. See [`ModuleInst::export_func`].
+    pub fn export_table(&self, name: &str) -> Result<TableAddr, Error> {
+        match self.export_value(name)? {
+            ExternVal::Table(addr) => Ok(addr),
+            _ => Err(self.export_mismatch(name)),
+        }
+    }
+
+    /// Gets the memory exported as `name`. See [`ModuleInst::export_func`].
+    pub fn export_mem(&self, name: &str) -> Result<MemAddr, Error> {
+        match self.export_value(name)? {
+            ExternVal::Mem(addr) => Ok(addr),
+            _ => Err(self.export_mismatch(name)),
+        }
+    }
+
+    /// Gets the global exported as `name`. See [`ModuleInst::export_func`].
+    pub fn export_global(&self, name: &str) -> Result<GlobalAddr, Error> {
+        match self.export_value(name)? {
+            ExternVal::Global(addr) => Ok(addr),
+            _ => Err(self.export_mismatch(name)),
+        }
+    }
+
+    fn export_value(&self, name: &str) -> Result<ExternVal, Error> {
+        match self.find_export(name) {
+            Some(export) => Ok(*export.value()),
+            None => Err(Error::ExportNotFound {
+                module: self.name.clone(),
+                name: name.to_owned(),
+            }),
+        }
+    }
+
+    fn export_mismatch(&self, name: &str) -> Error {
+        Error::ExportTypeMismatch {
+            module: self.name.clone(),
+            name: name.to_owned(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        builder::{FuncBuilder, ModuleBuilder},
+        hosting::Host,
+        module::MemoryType,
+        Error,
+    };
+
+    #[test]
+    pub fn exports_are_looked_up_by_kind() {
+        let module = ModuleBuilder::new()
+            .mem("memory", MemoryType::new(1, None))
+            .func(FuncBuilder::new().export_as("main").body(vec![]));
+        let mut host = Host::new();
+        let addr = host.instantiate("test", module.build()).unwrap();
+        let inst = host.get_module(addr).unwrap();
+
+        assert_eq!(inst.funcs()[0], inst.export_func("main").unwrap());
+        assert_eq!(inst.mems()[0], inst.export_mem("memory").unwrap());
+        assert!(matches!(
+            inst.export_global("main"),
+            Err(Error::ExportTypeMismatch { .. })
+        ));
+        assert!(matches!(
+            inst.export_table("table"),
+            Err(Error::ExportNotFound { .. })
+        ));
+    }
 }
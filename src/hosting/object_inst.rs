@@ -0,0 +1,58 @@
+use std::sync::Mutex;
+
+use crate::{hosting::ModuleAddr, Value};
+
+addr_type!(ObjectAddr);
+
+/// A struct or array allocated on the host's heap.
+///
+/// Objects are never collected; they live as long as the host that allocated them.
+pub struct ObjectInst {
+    module: ModuleAddr,
+    type_idx: u32,
+    fields: Mutex<Vec<Value>>,
+}
+
+impl ObjectInst {
+    pub fn new(module: ModuleAddr, type_idx: u32, fields: Vec<Value>) -> ObjectInst {
+        ObjectInst {
+            module,
+            type_idx,
+            fields: Mutex::new(fields),
+        }
+    }
+
+    /// Gets the address of the module whose type section defines this object's type.
+    pub fn module(&self) -> ModuleAddr {
+        self.module
+    }
+
+    /// Gets the index of this object's type in the type section of its module.
+    pub fn type_idx(&self) -> u32 {
+        self.type_idx
+    }
+
+    /// Gets the number of fields (or elements, for arrays) in this object.
+    pub fn len(&self) -> usize {
+        self.fields.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn get(&self, idx: usize) -> Option<Value> {
+        self.fields.lock().unwrap().get(idx).cloned()
+    }
+
+    /// Sets the field with index `idx`, returning `false` if there is no such field.
+    pub fn set(&self, idx: usize, value: Value) -> bool {
+        match self.fields.lock().unwrap().get_mut(idx) {
+            Some(field) => {
+                *field = value;
+                true
+            }
+            None => false,
+        }
+    }
+}
@@ -0,0 +1,114 @@
+use std::{
+    io::{self, Write},
+    sync::{Arc, Mutex},
+};
+
+/// Where a host sends what guests write to one of the standard streams. See
+/// [`Host::set_stdout`](crate::hosting::Host::set_stdout).
+pub(crate) type OutputSink = Arc<Mutex<dyn Write + Send>>;
+
+/// Writes `bytes` to `sink`, or to `default` if the host has no sink for the stream.
+pub(crate) fn write_to<W: Write>(
+    sink: Option<&OutputSink>,
+    mut default: W,
+    bytes: &[u8],
+) -> io::Result<()> {
+    match sink {
+        Some(sink) => sink.lock().unwrap().write_all(bytes),
+        None => default.write_all(bytes),
+    }
+}
+
+/// A buffer that captures what guests write, for an embedder or test to look at afterwards.
+/// Clones share the buffer, so one can be given to
+/// [`Host::set_stdout`](crate::hosting::Host::set_stdout) and another kept to read from.
+#[derive(Clone, Default)]
+pub struct OutputBuffer {
+    bytes: Arc<Mutex<Vec<u8>>>,
+}
+
+impl OutputBuffer {
+    pub fn new() -> OutputBuffer {
+        OutputBuffer::default()
+    }
+
+    /// Gets a copy of everything written so far.
+    pub fn contents(&self) -> Vec<u8> {
+        self.bytes.lock().unwrap().clone()
+    }
+
+    /// Takes everything written so far, leaving the buffer empty.
+    pub fn take(&self) -> Vec<u8> {
+        std::mem::take(&mut *self.bytes.lock().unwrap())
+    }
+
+    /// Gets everything written so far as text, replacing anything that isn't UTF-8.
+    pub fn to_string_lossy(&self) -> String {
+        String::from_utf8_lossy(&self.bytes.lock().unwrap()).into_owned()
+    }
+}
+
+impl Write for OutputBuffer {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.bytes.lock().unwrap().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        builder::{FuncBuilder, ModuleBuilder},
+        hosting::{Host, OutputBuffer},
+        module::MemoryType,
+        runtime::{Env, SpecTest},
+        Instruction::*,
+        ValType, Value,
+    };
+
+    #[test]
+    pub fn guest_output_is_captured() {
+        let mut host = Host::new();
+        let (stdout, stderr) = (OutputBuffer::new(), OutputBuffer::new());
+        host.set_stdout(stdout.clone());
+        host.set_stderr(stderr.clone());
+        host.external(Env::new()).unwrap();
+        host.external(SpecTest::new()).unwrap();
+        let module = ModuleBuilder::new()
+            .import_mem("env", "memory", MemoryType::new(256, Some(256)))
+            .func(
+                FuncBuilder::new()
+                    .import_from("env", "print")
+                    .param(ValType::I32)
+                    .param(ValType::I32),
+            )
+            .func(
+                FuncBuilder::new()
+                    .import_from("spectest", "print_i32")
+                    .param(ValType::I32),
+            )
+            .func(FuncBuilder::new().export_as("main").body(vec![
+                I32Const(Value::I32(0)),
+                I32Const(Value::I32(0x6968)),
+                I32Store16(1, 0),
+                I32Const(Value::I32(0)),
+                I32Const(Value::I32(2)),
+                Call(0),
+                I32Const(Value::I32(42)),
+                Call(1),
+            ]));
+        let addr = host.instantiate("app", module.build()).unwrap();
+        host.invoke(addr, "main", &[]).unwrap();
+        assert_eq!("hi\n42 : i32\n", stdout.to_string_lossy());
+        assert!(stderr.contents().is_empty());
+
+        // Without a sink, output goes to the process's own streams again
+        host.take_stdout();
+        assert_eq!(b"hi\n42 : i32\n".to_vec(), stdout.take());
+        assert!(stdout.contents().is_empty());
+    }
+}
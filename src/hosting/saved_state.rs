@@ -0,0 +1,538 @@
+//! The file format of [`Host::save`] and [`Host::load`].
+//!
+//! A saved state holds the contents of the memories, globals and tables of every instance in
+//! a host, but not their code, so it can only be loaded into a host that instantiated the same
+//! modules in the same order. All integers are little-endian.
+//!
+//! ```text
+//! magic      "\0wst"
+//! version    u32, currently 2
+//! count      u32, the number of instances
+//! instances  one chunk per instance, in the order they were instantiated:
+//!   name      u32 length, then UTF-8
+//!   payload   the items the instance allocated, not the ones it imported:
+//!     memories  u32 count, then for each a u64 length and the contents
+//!     globals   u32 count, then for each a value: a u8 tag (0 for an immutable global,
+//!               which isn't saved; 1 i32, 2 i64, 3 f32, 4 f64 or 5 v128) and its
+//!               bits, with the low half of a v128 first
+//!     tables    u32 count, then for each a u32 length and a u32 per element, which is 0
+//!               for a null element or the function's address plus one
+//!   checksum  u64, the 64-bit FNV-1a hash of the payload
+//! ```
+//!
+//! Memories are written as they're read, rather than buffered, so the payload isn't prefixed
+//! with its length. Counts and lengths that don't fit in their field fail the save.
+//!
+//! [`Host::save`]: crate::hosting::Host::save
+//! [`Host::load`]: crate::hosting::Host::load
+
+use std::{
+    convert::TryFrom,
+    io::{self, Read, Write},
+};
+
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+
+use crate::{
+    hosting::{FuncAddr, Host, ModuleAddr},
+    Error, Value,
+};
+
+const MAGIC: &[u8; 4] = b"\0wst";
+const VERSION: u32 = 2;
+
+/// The number of bytes of a memory copied to the writer at once.
+const CHUNK_SIZE: usize = 0x1_0000;
+
+/// The state of one instance, read from a file but not yet applied.
+struct InstanceState {
+    mems: Vec<Vec<u8>>,
+    globals: Vec<Option<Value>>,
+    tables: Vec<Vec<Option<FuncAddr>>>,
+}
+
+pub(crate) fn write<W: Write>(host: &Host, writer: &mut W) -> Result<(), Error> {
+    writer.write_all(MAGIC)?;
+    writer.write_u32::<LittleEndian>(VERSION)?;
+    let modules: Vec<_> = host.module_addrs().collect();
+    write_len(writer, modules.len())?;
+    for addr in modules {
+        write_name(writer, host.module(addr).name())?;
+        let mut payload = Checksummed::new(&mut *writer);
+        write_instance(host, addr, &mut payload)?;
+        let checksum = payload.checksum;
+        writer.write_u64::<LittleEndian>(checksum)?;
+    }
+    Ok(())
+}
+
+fn write_instance<W: Write>(host: &Host, module: ModuleAddr, out: &mut W) -> Result<(), Error> {
+    let inst = host.module(module);
+    let name = || inst.name().to_owned();
+
+    let mems: Vec<_> = inst
+        .mems()
+        .iter()
+        .map(|a| host.mem(*a))
+        .filter(|m| m.module() == module)
+        .collect();
+    write_len(out, mems.len())?;
+    let mut chunk = vec![0; CHUNK_SIZE];
+    for mem in mems {
+        let len = mem.memory().len();
+        out.write_u64::<LittleEndian>(len as u64)?;
+        for offset in (0..len).step_by(CHUNK_SIZE) {
+            let chunk = &mut chunk[..CHUNK_SIZE.min(len - offset)];
+            mem.read(offset, chunk).expect("memories don't shrink");
+            out.write_all(chunk)?;
+        }
+    }
+
+    let globals: Vec<_> = inst
+        .globals()
+        .iter()
+        .map(|a| host.global(*a))
+        .filter(|g| g.module() == module)
+        .collect();
+    write_len(out, globals.len())?;
+    for global in globals {
+        if !global.typ().mutable() {
+            out.write_u8(0)?;
+        } else if !write_value(out, global.get())? {
+            return Err(Error::InvalidState {
+                module: Some(name()),
+                reason: "only numeric and vector globals can be saved",
+            });
+        }
+    }
+
+    let tables: Vec<_> = inst
+        .tables()
+        .iter()
+        .map(|a| host.table(*a))
+        .filter(|t| t.module() == module)
+        .collect();
+    write_len(out, tables.len())?;
+    for table in tables {
+        write_len(out, table.len())?;
+        for idx in 0..table.len() {
+            write_len(out, table.get(idx).map_or(0, |f| f.val() + 1))?;
+        }
+    }
+    Ok(())
+}
+
+pub(crate) fn read<R: Read>(host: &Host, reader: &mut R) -> Result<(), Error> {
+    let mut magic = [0; 4];
+    reader.read_exact(&mut magic)?;
+    if magic != *MAGIC {
+        return Err(Error::InvalidMagic);
+    }
+    let version = reader.read_u32::<LittleEndian>()?;
+    if version != VERSION {
+        return Err(Error::UnsupportedVersion { version });
+    }
+
+    let modules: Vec<_> = host.modules().collect();
+    if reader.read_u32::<LittleEndian>()? as usize != modules.len() {
+        return Err(Error::InvalidState {
+            module: None,
+            reason: "the host has a different number of instances",
+        });
+    }
+
+    // Read and check everything before changing anything, so a bad file leaves the host as it was
+    let mut states = Vec::with_capacity(modules.len());
+    for module in modules.iter() {
+        let mismatch = |reason| Error::InvalidState {
+            module: Some(module.name().to_owned()),
+            reason,
+        };
+        if read_name(reader)? != module.name() {
+            return Err(mismatch("the instance has a different name"));
+        }
+        let mut payload = Checksummed::new(&mut *reader);
+        let state = read_instance(&mut payload).map_err(|e| match e {
+            Error::IoError(_) => mismatch("the instance is truncated"),
+            e => e,
+        })?;
+        let checksum = payload.checksum;
+        if reader.read_u64::<LittleEndian>()? != checksum {
+            return Err(mismatch("the checksum doesn't match"));
+        }
+        states.push(state);
+    }
+
+    for (addr, state) in host.module_addrs().zip(&states) {
+        check_instance(host, addr, state)?;
+    }
+    for (addr, state) in host.module_addrs().zip(states) {
+        apply_instance(host, addr, state);
+    }
+    Ok(())
+}
+
+fn read_instance<R: Read>(reader: &mut R) -> Result<InstanceState, Error> {
+    let mut mems = Vec::new();
+    for _ in 0..reader.read_u32::<LittleEndian>()? {
+        let len = reader.read_u64::<LittleEndian>()?;
+        let mut data = Vec::new();
+        reader.take(len).read_to_end(&mut data)?;
+        if data.len() as u64 != len {
+            return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into());
+        }
+        mems.push(data);
+    }
+
+    let mut globals = Vec::new();
+    for _ in 0..reader.read_u32::<LittleEndian>()? {
+        globals.push(read_value(reader)?);
+    }
+
+    let mut tables = Vec::new();
+    for _ in 0..reader.read_u32::<LittleEndian>()? {
+        let len = reader.read_u32::<LittleEndian>()?;
+        let mut elems = Vec::new();
+        for _ in 0..len {
+            elems.push(FuncAddr::new(reader.read_u32::<LittleEndian>()? as usize));
+        }
+        tables.push(elems);
+    }
+
+    Ok(InstanceState {
+        mems,
+        globals,
+        tables,
+    })
+}
+
+/// Checks that `state` fits the items `module` allocated.
+fn check_instance(host: &Host, module: ModuleAddr, state: &InstanceState) -> Result<(), Error> {
+    let inst = host.module(module);
+    let mismatch = |reason| Error::InvalidState {
+        module: Some(inst.name().to_owned()),
+        reason,
+    };
+
+    let mems: Vec<_> = inst
+        .mems()
+        .iter()
+        .map(|a| host.mem(*a))
+        .filter(|m| m.module() == module)
+        .collect();
+    if mems.len() != state.mems.len()
+        || mems
+            .iter()
+            .zip(&state.mems)
+            .any(|(m, data)| m.memory().len() != data.len())
+    {
+        return Err(mismatch("the instance's memories are a different size"));
+    }
+
+    let globals: Vec<_> = inst
+        .globals()
+        .iter()
+        .map(|a| host.global(*a))
+        .filter(|g| g.module() == module)
+        .collect();
+    if globals.len() != state.globals.len()
+        || globals
+            .iter()
+            .zip(&state.globals)
+            .any(|(g, value)| match value {
+                Some(value) => !g.typ().mutable() || !g.typ().typ().accepts(value.typ()),
+                None => g.typ().mutable(),
+            })
+    {
+        return Err(mismatch("the instance's globals have different types"));
+    }
+
+    let tables: Vec<_> = inst
+        .tables()
+        .iter()
+        .map(|a| host.table(*a))
+        .filter(|t| t.module() == module)
+        .collect();
+    if tables.len() != state.tables.len()
+        || tables
+            .iter()
+            .zip(&state.tables)
+            .any(|(t, elems)| t.len() != elems.len())
+    {
+        return Err(mismatch("the instance's tables are a different size"));
+    }
+    if state
+        .tables
+        .iter()
+        .flatten()
+        .flatten()
+        .any(|f| host.func_at(f.val()).is_none())
+    {
+        return Err(mismatch(
+            "a table refers to a function the host doesn't have",
+        ));
+    }
+    Ok(())
+}
+
+fn apply_instance(host: &Host, module: ModuleAddr, state: InstanceState) {
+    let inst = host.module(module);
+    let mems = inst
+        .mems()
+        .iter()
+        .map(|a| host.mem(*a))
+        .filter(|m| m.module() == module);
+    for (mem, data) in mems.zip(state.mems) {
+        mem.write(0, &data)
+            .expect("the memory's size was checked before loading");
+    }
+
+    let globals = inst
+        .globals()
+        .iter()
+        .map(|a| host.global(*a))
+        .filter(|g| g.module() == module);
+    for (global, value) in globals.zip(state.globals) {
+        if let Some(value) = value {
+            global
+                .set(value)
+                .expect("the global's type was checked before loading");
+        }
+    }
+
+    let tables = inst
+        .tables()
+        .iter()
+        .map(|a| host.table(*a))
+        .filter(|t| t.module() == module);
+    for (table, elems) in tables.zip(state.tables) {
+        for (idx, elem) in elems.into_iter().enumerate() {
+            // Functions are saved by slot, which may be in a later generation now
+            table.set(idx, elem.and_then(|f| host.func_at(f.val())));
+        }
+    }
+}
+
+/// Writes `value` as a tag and its bits, as globals are saved. Produces `false`, having
+/// written nothing, if the value isn't a number or a vector.
+pub(crate) fn write_value<W: Write>(out: &mut W, value: Value) -> io::Result<bool> {
+    match value {
+        Value::I32(x) => {
+            out.write_u8(1)?;
+            out.write_u32::<LittleEndian>(x)?;
+        }
+        Value::I64(x) => {
+            out.write_u8(2)?;
+            out.write_u64::<LittleEndian>(x)?;
+        }
+        Value::F32(x) => {
+            out.write_u8(3)?;
+            out.write_u32::<LittleEndian>(x.to_bits())?;
+        }
+        Value::F64(x) => {
+            out.write_u8(4)?;
+            out.write_u64::<LittleEndian>(x.to_bits())?;
+        }
+        Value::V128(x) => {
+            out.write_u8(5)?;
+            out.write_u64::<LittleEndian>(x as u64)?;
+            out.write_u64::<LittleEndian>((x >> 64) as u64)?;
+        }
+        _ => return Ok(false),
+    }
+    Ok(true)
+}
+
+/// Reads a value written by [`write_value`], or `None` for the tag of an immutable global.
+pub(crate) fn read_value<R: Read>(reader: &mut R) -> Result<Option<Value>, Error> {
+    let value = match reader.read_u8()? {
+        0 => return Ok(None),
+        1 => Value::I32(reader.read_u32::<LittleEndian>()?),
+        2 => Value::I64(reader.read_u64::<LittleEndian>()?),
+        3 => Value::F32(f32::from_bits(reader.read_u32::<LittleEndian>()?)),
+        4 => Value::F64(f64::from_bits(reader.read_u64::<LittleEndian>()?)),
+        5 => {
+            let low = reader.read_u64::<LittleEndian>()?;
+            let high = reader.read_u64::<LittleEndian>()?;
+            Value::V128(u128::from(high) << 64 | u128::from(low))
+        }
+        _ => {
+            return Err(Error::InvalidState {
+                module: None,
+                reason: "a value has an unknown type",
+            })
+        }
+    };
+    Ok(Some(value))
+}
+
+/// Writes a count or length, failing if it doesn't fit in the `u32` the format has for it.
+fn write_len<W: Write>(writer: &mut W, len: usize) -> Result<(), Error> {
+    let len = u32::try_from(len).map_err(|_| Error::InvalidState {
+        module: None,
+        reason: "a count or length is too large to save",
+    })?;
+    Ok(writer.write_u32::<LittleEndian>(len)?)
+}
+
+fn write_name<W: Write>(writer: &mut W, name: &str) -> Result<(), Error> {
+    write_len(writer, name.len())?;
+    Ok(writer.write_all(name.as_bytes())?)
+}
+
+fn read_name<R: Read>(reader: &mut R) -> Result<String, Error> {
+    let len = reader.read_u32::<LittleEndian>()?;
+    let mut name = Vec::new();
+    reader.take(u64::from(len)).read_to_end(&mut name)?;
+    Ok(String::from_utf8(name)?)
+}
+
+/// Passes the bytes of a payload through to or from `inner`, keeping the 64-bit FNV-1a hash of
+/// them as its checksum.
+struct Checksummed<T> {
+    inner: T,
+    checksum: u64,
+}
+
+impl<T> Checksummed<T> {
+    fn new(inner: T) -> Checksummed<T> {
+        Checksummed {
+            inner,
+            checksum: 0xcbf2_9ce4_8422_2325,
+        }
+    }
+
+    fn update(&mut self, bytes: &[u8]) {
+        self.checksum = bytes.iter().fold(self.checksum, |hash, b| {
+            (hash ^ u64::from(*b)).wrapping_mul(0x0100_0000_01b3)
+        });
+    }
+}
+
+impl<W: Write> Write for Checksummed<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        self.update(&buf[..written]);
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+impl<R: Read> Read for Checksummed<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.inner.read(buf)?;
+        self.update(&buf[..read]);
+        Ok(read)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs, process};
+
+    use crate::{
+        builder::{FuncBuilder, ModuleBuilder},
+        hosting::Host,
+        module::{Expr, Global, GlobalType, MemoryType},
+        Error, Instruction, ValType, Value, PAGE_SIZE,
+    };
+
+    fn world() -> (Host, crate::hosting::ModuleAddr) {
+        use crate::Instruction::*;
+
+        let module = ModuleBuilder::new()
+            .mem("memory", MemoryType::new(1, None))
+            .global(
+                "counter",
+                Global::new(
+                    GlobalType::new(ValType::I32, true),
+                    Expr::new(vec![Instruction::I32Const(Value::I32(0))]),
+                ),
+            )
+            .func(FuncBuilder::new().export_as("tick").body(vec![
+                I32Const(Value::I32(8)),
+                I32Const(Value::I32(0xAB)),
+                I32Store8(0, 0),
+                GlobalGet(0),
+                I32Const(Value::I32(1)),
+                I32Add,
+                GlobalSet(0),
+            ]));
+        let mut host = Host::new();
+        let addr = host.instantiate("world", module.build()).unwrap();
+        (host, addr)
+    }
+
+    #[test]
+    pub fn saved_state_is_loaded_into_a_fresh_host() {
+        let path = env::temp_dir().join(format!("warthog-state-{}.bin", process::id()));
+
+        let (mut host, addr) = world();
+        host.invoke(addr, "tick", &[]).unwrap();
+        host.invoke(addr, "tick", &[]).unwrap();
+        host.save(&path).unwrap();
+
+        let (mut restored, addr) = world();
+        restored.load(&path).unwrap();
+        let inst = restored.get_module(addr).unwrap();
+        let counter = restored
+            .get_global(inst.export_global("counter").unwrap())
+            .unwrap();
+        assert_eq!(Value::I32(2), counter.get());
+        let mem = restored
+            .get_mem(inst.export_mem("memory").unwrap())
+            .unwrap();
+        assert_eq!(0xAB, mem.atomically(8, 1, |data| data[0]).unwrap());
+
+        // A single flipped bit is caught by the checksum
+        let mut bytes = fs::read(&path).unwrap();
+        let last = bytes.len() - 9;
+        bytes[last] ^= 1;
+        fs::write(&path, bytes).unwrap();
+        assert!(matches!(
+            restored.load(&path),
+            Err(Error::InvalidState { .. })
+        ));
+
+        // The state only fits a host with the same instances
+        restored
+            .instantiate("other", ModuleBuilder::new().build())
+            .unwrap();
+        host.save(&path).unwrap();
+        assert!(matches!(
+            restored.load(&path),
+            Err(Error::InvalidState { module: None, .. })
+        ));
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    pub fn memories_are_saved_a_chunk_at_a_time() {
+        let module = || {
+            ModuleBuilder::new()
+                .mem("memory", MemoryType::new(3, None))
+                .build()
+        };
+        let mem = |host: &Host| {
+            let addr = host.find_module("big").unwrap();
+            let inst = host.get_module(addr).unwrap();
+            host.get_mem(inst.export_mem("memory").unwrap()).unwrap()
+        };
+        let mut host = Host::new();
+        host.instantiate("big", module()).unwrap();
+        let last = 3 * PAGE_SIZE - 4;
+        mem(&host).write(last, &[1, 2, 3, 4]).unwrap();
+        let mut saved = Vec::new();
+        host.save_to(&mut saved).unwrap();
+
+        let mut restored = Host::new();
+        restored.instantiate("big", module()).unwrap();
+        restored.load_from(&mut &saved[..]).unwrap();
+        let mut bytes = [0; 4];
+        mem(&restored).read(last, &mut bytes).unwrap();
+        assert_eq!([1, 2, 3, 4], bytes);
+    }
+}
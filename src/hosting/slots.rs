@@ -0,0 +1,141 @@
+use std::{
+    marker::PhantomData,
+    ops::{Index, IndexMut},
+};
+
+use crate::Error;
+
+/// An address type that identifies an item in [`Slots`].
+pub(crate) trait SlotAddr: Copy + std::fmt::Display {
+    fn at(index: usize, generation: u32) -> Self;
+    fn val(&self) -> usize;
+    fn generation(&self) -> u32;
+}
+
+/// Stores the items of one kind in a host, such as its functions, by address.
+///
+/// When an item is removed its slot is freed for a later item to reuse, and the slot's
+/// generation is advanced. Addresses carry the generation they were created in, so a stale
+/// address is detected rather than silently referring to the new item. Indexing with a stale
+/// address panics, like indexing out of bounds.
+#[derive(Clone)]
+pub(crate) struct Slots<A, T> {
+    slots: Vec<Slot<T>>,
+    free: Vec<usize>,
+    addr: PhantomData<A>,
+}
+
+#[derive(Clone)]
+struct Slot<T> {
+    generation: u32,
+    item: Option<T>,
+}
+
+impl<A: SlotAddr, T> Slots<A, T> {
+    pub fn new() -> Slots<A, T> {
+        Slots {
+            slots: Vec::new(),
+            free: Vec::new(),
+            addr: PhantomData,
+        }
+    }
+
+    /// Gets the number of slots, including free ones. Slots below this are never removed, so
+    /// it bounds the addresses in use at any point in time.
+    pub fn len(&self) -> usize {
+        self.slots.len()
+    }
+
+    /// Gets the address the next item pushed will have.
+    pub fn next_addr(&self) -> A {
+        match self.free.last() {
+            Some(&index) => A::at(index, self.slots[index].generation),
+            None => A::at(self.slots.len(), 0),
+        }
+    }
+
+    pub fn push(&mut self, item: T) -> A {
+        let addr = self.next_addr();
+        match self.free.pop() {
+            Some(index) => self.slots[index].item = Some(item),
+            None => self.slots.push(Slot {
+                generation: 0,
+                item: Some(item),
+            }),
+        }
+        addr
+    }
+
+    /// Gets the item at `addr`, unless it has been removed.
+    pub fn get(&self, addr: A) -> Option<&T> {
+        self.slots
+            .get(addr.val())
+            .filter(|slot| slot.generation == addr.generation())
+            .and_then(|slot| slot.item.as_ref())
+    }
+
+    /// Gets the item at `addr`, or fails with [`Error::StaleAddress`] if it has been removed.
+    pub fn checked(&self, addr: A) -> Result<&T, Error> {
+        self.get(addr).ok_or_else(|| Error::StaleAddress {
+            addr: addr.to_string(),
+        })
+    }
+
+    pub fn get_mut(&mut self, addr: A) -> Option<&mut T> {
+        self.slots
+            .get_mut(addr.val())
+            .filter(|slot| slot.generation == addr.generation())
+            .and_then(|slot| slot.item.as_mut())
+    }
+
+    /// Removes the item at `addr`, freeing its slot for reuse.
+    pub fn remove(&mut self, addr: A) -> Option<T> {
+        let slot = self
+            .slots
+            .get_mut(addr.val())
+            .filter(|slot| slot.generation == addr.generation())?;
+        let item = slot.item.take()?;
+        slot.generation = slot.generation.wrapping_add(1);
+        self.free.push(addr.val());
+        Some(item)
+    }
+
+    /// Gets the address of the item in slot `index`, if the slot is in use.
+    pub fn addr(&self, index: usize) -> Option<A> {
+        let slot = self.slots.get(index)?;
+        slot.item.as_ref().map(|_| A::at(index, slot.generation))
+    }
+
+    /// Enumerates the items in use, with their addresses, in slot order.
+    pub fn entries(&self) -> impl Iterator<Item = (A, &T)> {
+        self.slots.iter().enumerate().filter_map(|(index, slot)| {
+            slot.item
+                .as_ref()
+                .map(|item| (A::at(index, slot.generation), item))
+        })
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &T> {
+        self.slots.iter().filter_map(|slot| slot.item.as_ref())
+    }
+}
+
+impl<A: SlotAddr, T> Index<A> for Slots<A, T> {
+    type Output = T;
+
+    fn index(&self, addr: A) -> &T {
+        match self.get(addr) {
+            Some(item) => item,
+            None => panic!("{} refers to an item that has been dropped", addr),
+        }
+    }
+}
+
+impl<A: SlotAddr, T> IndexMut<A> for Slots<A, T> {
+    fn index_mut(&mut self, addr: A) -> &mut T {
+        match self.get_mut(addr) {
+            Some(item) => item,
+            None => panic!("{} refers to an item that has been dropped", addr),
+        }
+    }
+}
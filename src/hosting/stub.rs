@@ -0,0 +1,12 @@
+/// What happens when a stubbed function is called, instead of running its body.
+///
+/// Stubbing lets a large module be brought up piece by piece: functions that rely on features
+/// the interpreter doesn't support yet can be stubbed out so the rest of the module runs.
+/// See [`Host::stub_func`](crate::hosting::Host::stub_func).
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Stub {
+    /// Discard the arguments and return the zero value of each result type.
+    Zeroed,
+    /// Trap with a message naming the function.
+    Trap,
+}
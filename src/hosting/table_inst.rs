@@ -0,0 +1,80 @@
+use std::sync::RwLock;
+
+use crate::{
+    hosting::{FuncAddr, ModuleAddr},
+    module::TableType,
+};
+
+addr_type!(TableAddr);
+
+pub struct TableInst {
+    module: ModuleAddr,
+    typ: TableType,
+    elems: RwLock<Vec<Option<FuncAddr>>>,
+}
+
+impl TableInst {
+    pub fn from_type(module: ModuleAddr, typ: &TableType) -> TableInst {
+        TableInst {
+            module,
+            typ: typ.clone(),
+            elems: RwLock::new(vec![None; typ.min()]),
+        }
+    }
+
+    /// Creates a copy of this table for `module`, with each element replaced by `remap`.
+    pub fn fork<F: Fn(FuncAddr) -> FuncAddr>(&self, module: ModuleAddr, remap: F) -> TableInst {
+        let elems = self.elems.read().unwrap();
+        TableInst {
+            module,
+            typ: self.typ.clone(),
+            elems: RwLock::new(elems.iter().map(|e| e.map(&remap)).collect()),
+        }
+    }
+
+    /// Gets the address of the module that allocated this table.
+    pub fn module(&self) -> ModuleAddr {
+        self.module
+    }
+
+    pub fn typ(&self) -> &TableType {
+        &self.typ
+    }
+
+    pub fn len(&self) -> usize {
+        self.elems.read().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Gets the function stored at `idx`, if the slot exists and has been initialized.
+    pub fn get(&self, idx: usize) -> Option<FuncAddr> {
+        self.elems.read().unwrap().get(idx).cloned().unwrap_or(None)
+    }
+
+    /// Grows the table by `additional` empty slots, producing its previous length, or `None` if
+    /// that would take it past its maximum.
+    pub fn grow(&self, additional: usize) -> Option<usize> {
+        let mut elems = self.elems.write().unwrap();
+        let len = elems.len();
+        let new_len = len.checked_add(additional)?;
+        if self.typ.max().is_some_and(|max| new_len > max) {
+            return None;
+        }
+        elems.resize(new_len, None);
+        Some(len)
+    }
+
+    /// Stores `func` at `idx`, returning `false` if `idx` is out of bounds.
+    pub fn set(&self, idx: usize, func: Option<FuncAddr>) -> bool {
+        match self.elems.write().unwrap().get_mut(idx) {
+            Some(slot) => {
+                *slot = func;
+                true
+            }
+            None => false,
+        }
+    }
+}
@@ -0,0 +1,180 @@
+use super::synthesize_env;
+use crate::{
+    builder::{FuncBuilder, ModuleBuilder},
+    hosting::{ExternVal, Host},
+    interp::Thread,
+    module::{FuncType, GlobalType, Import, MemberDesc, MemoryType, TableType},
+    Error, FromValue, Instruction, ValType, Value,
+};
+
+#[test]
+pub fn synthesize_allocates_and_exports_mems_and_globals() {
+    let mut host = Host::new();
+    synthesize_env(&mut host);
+    let env = host.find_module("env").unwrap();
+
+    let module = host.get_module(env).unwrap();
+    let mem = host.get_mem(module.export_mem("memory").unwrap()).unwrap();
+    assert_eq!(crate::PAGE_SIZE, mem.memory().len());
+    let global = host
+        .get_global(module.export_global("counter").unwrap())
+        .unwrap();
+    assert_eq!(Value::I32(42), global.get());
+    assert_eq!(1, host.get_module(env).unwrap().mems().len());
+    assert_eq!(1, host.get_module(env).unwrap().globals().len());
+}
+
+#[test]
+pub fn imported_global_is_shared_with_synthetic_module() {
+    let mut host = Host::new();
+    synthesize_env(&mut host);
+
+    let mut guest = ModuleBuilder::new();
+    guest.imports.push(Import::new(
+        "env",
+        "counter",
+        MemberDesc::Global(GlobalType::new(ValType::I32, true)),
+    ));
+    guest.add_func(
+        FuncBuilder::new()
+            .export_as("bump")
+            .result(ValType::I32)
+            .body(vec![
+                Instruction::GlobalGet(0),
+                Instruction::I32Const(Value::I32(1)),
+                Instruction::I32Add,
+                Instruction::GlobalSet(0),
+                Instruction::GlobalGet(0),
+            ]),
+    );
+    let guest = host.instantiate("guest", guest.build()).unwrap();
+    let func = host.get_module(guest).unwrap().export_func("bump").unwrap();
+
+    let res = Thread::new().call(&mut host, guest, func, &[]).unwrap();
+    assert_eq!(vec![Value::I32(43)], res);
+
+    let env = host.find_module("env").unwrap();
+    let counter = host.get_module(env).unwrap().globals()[0];
+    assert_eq!(Value::I32(43), host.get_global(counter).unwrap().get());
+}
+
+#[test]
+pub fn imports_are_checked_against_their_declarations() {
+    let mut host = Host::new();
+    synthesize_env(&mut host);
+
+    let mut instantiate = |name: &str, desc: MemberDesc| {
+        let mut guest = ModuleBuilder::new();
+        guest.imports.push(Import::new("env", name, desc));
+        host.instantiate("guest", guest.build())
+    };
+
+    // The memory has 1 page and may grow to 2
+    assert!(instantiate("memory", MemberDesc::Memory(MemoryType::new(1, Some(4)))).is_ok());
+    for typ in [
+        MemoryType::new(2, None),
+        MemoryType::new(1, Some(1)),
+        MemoryType::new_64(1, None),
+        MemoryType::new_shared(1, 2),
+    ] {
+        match instantiate("memory", MemberDesc::Memory(typ)) {
+            Err(Error::ExportTypeMismatch { module, name }) => {
+                assert_eq!(("env", "memory"), (module.as_str(), name.as_str()))
+            }
+            r => panic!("expected a type mismatch, got {:?}", r.map(|_| ())),
+        }
+    }
+
+    // The counter is a mutable i32
+    for typ in [
+        GlobalType::new(ValType::I32, false),
+        GlobalType::new(ValType::I64, true),
+    ] {
+        assert!(matches!(
+            instantiate("counter", MemberDesc::Global(typ)),
+            Err(Error::ExportTypeMismatch { .. })
+        ));
+    }
+    assert!(matches!(
+        instantiate("counter", MemberDesc::Table(TableType::new(0, None))),
+        Err(Error::ExportTypeMismatch { .. })
+    ));
+}
+
+#[test]
+pub fn imported_function_signatures_must_match() {
+    let mut host = Host::new();
+    let env = ModuleBuilder::new().func(
+        FuncBuilder::new()
+            .export_as("square")
+            .param(ValType::I32)
+            .result(ValType::I32)
+            .body(vec![
+                Instruction::LocalGet(0),
+                Instruction::LocalGet(0),
+                Instruction::I32Mul,
+            ]),
+    );
+    host.synthesize("env", env).unwrap();
+
+    let guest = ModuleBuilder::new().func(
+        FuncBuilder::new()
+            .import_from("env", "square")
+            .param(ValType::I64)
+            .result(ValType::I64),
+    );
+    match host.instantiate("guest", guest.build()) {
+        Err(Error::ImportTypeMismatch {
+            module,
+            name,
+            expected,
+            actual,
+        }) => {
+            assert_eq!(("env", "square"), (module.as_str(), name.as_str()));
+            assert_eq!(&[ValType::I64], expected.params());
+            assert_eq!(&[ValType::I32], actual.params());
+        }
+        r => panic!("expected a signature mismatch, got {:?}", r.map(|_| ())),
+    }
+}
+
+#[test]
+pub fn items_are_defined_one_at_a_time() {
+    let mut host = Host::new();
+    let mem = host
+        .define_memory("env", "memory", MemoryType::new(1, Some(1)))
+        .unwrap();
+    host.define_global("env", "base", Value::I32(10), false)
+        .unwrap();
+    host.define_func(
+        "env",
+        "offset",
+        FuncType::new(vec![ValType::I32], vec![ValType::I32]),
+        |caller, values| {
+            let base = match caller.get_export("base") {
+                Some(ExternVal::Global(addr)) => caller.host().get_global(addr).unwrap().get(),
+                _ => unreachable!(),
+            };
+            Ok(vec![Value::I32(
+                u32::from_value(base)? + u32::from_value(values[0])?,
+            )])
+        },
+    )
+    .unwrap();
+
+    let env = host.find_module("env").unwrap();
+    assert_eq!(
+        Some(mem),
+        host.get_module(env).unwrap().export_mem("memory").ok()
+    );
+    let res = host.invoke(env, "offset", &[Value::I32(5)]);
+    assert_eq!(vec![Value::I32(15)], res.unwrap());
+
+    // Nothing is allocated for a duplicate
+    let globals = host.snapshot().globals();
+    assert!(matches!(
+        host.define_global("env", "offset", Value::I32(0), true),
+        Err(Error::DuplicateExportName { .. })
+    ));
+    assert_eq!(globals, host.snapshot().globals());
+}
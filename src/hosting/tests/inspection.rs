@@ -0,0 +1,157 @@
+use std::sync::{Arc, Mutex};
+
+use super::synthesize_env;
+use crate::{
+    builder::{FuncBuilder, ModuleBuilder},
+    hosting::{ExternKind, ExternVal, Host, ItemFilter},
+    interp::Thread,
+    module::{Expr, Global, GlobalType, Import, MemberDesc},
+    runtime::SpecTest,
+    Error, Instruction, ValType, Value,
+};
+
+#[test]
+pub fn import_latency_is_recorded_per_instance() {
+    let mut host = Host::new();
+    let spectest = host.external(SpecTest::new()).unwrap();
+    let print = host.get_module(spectest).unwrap().funcs()[0];
+    let module = || {
+        ModuleBuilder::new()
+            .func(
+                FuncBuilder::new()
+                    .import_from("spectest", "print_i32")
+                    .param(ValType::I32),
+            )
+            .func(FuncBuilder::new().export_as("run").body(vec![
+                Instruction::I32Const(Value::I32(1)),
+                Instruction::Call(0),
+                Instruction::I32Const(Value::I32(2)),
+                Instruction::Call(0),
+            ]))
+            .build()
+    };
+    let first = host.instantiate("first", module()).unwrap();
+    let second = host.instantiate("second", module()).unwrap();
+    let run = |host: &mut Host, addr| {
+        let func = host.get_module(addr).unwrap().export_func("run").unwrap();
+        Thread::new().call(host, addr, func, &[]).unwrap();
+    };
+
+    // Nothing is recorded until tracking is enabled
+    run(&mut host, first);
+    assert!(host.import_latencies(first).is_empty());
+
+    host.track_import_latency(true);
+    run(&mut host, first);
+    run(&mut host, first);
+    let latencies = host.import_latencies(first);
+    assert_eq!(1, latencies.len());
+    assert_eq!(print, latencies[0].0);
+    assert_eq!(4, latencies[0].1.count());
+    assert!(host.import_latencies(second).is_empty());
+}
+
+#[test]
+pub fn items_are_filtered_and_stable_across_snapshots() {
+    let mut host = Host::new();
+    synthesize_env(&mut host);
+    let env = host.find_module("env").unwrap();
+    let snapshot = host.snapshot();
+
+    let mut guest = ModuleBuilder::new();
+    guest.imports.push(Import::new(
+        "env",
+        "counter",
+        MemberDesc::Global(GlobalType::new(ValType::I32, true)),
+    ));
+    guest.add_func(FuncBuilder::new().export_as("counter_get"));
+    let guest = host.instantiate("guest", guest.build()).unwrap();
+
+    // The guest's function didn't exist when the snapshot was taken
+    let all: Vec<_> = host.items(snapshot, &ItemFilter::new()).collect();
+    assert_eq!(2, all.len());
+    let all: Vec<_> = host.items(host.snapshot(), &ItemFilter::new()).collect();
+    assert_eq!(3, all.len());
+
+    // Imports aren't owned by the importing module
+    let filter = ItemFilter::new().module(guest);
+    let owned: Vec<_> = host.items(host.snapshot(), &filter).collect();
+    assert_eq!(1, owned.len());
+    assert_eq!(ExternKind::Func, owned[0].kind());
+
+    let filter = ItemFilter::new().kind(ExternKind::Global);
+    let globals: Vec<_> = host.items(host.snapshot(), &filter).collect();
+    assert_eq!(
+        vec![ExternVal::Global(
+            host.get_module(env).unwrap().globals()[0]
+        )],
+        globals
+    );
+
+    let filter = ItemFilter::new().name_prefix("counter");
+    let names: Vec<_> = host
+        .items(host.snapshot(), &filter)
+        .map(|i| host.item_name(i).unwrap())
+        .collect();
+    assert_eq!(vec!["counter_get", "counter"], names);
+
+    let modules: Vec<_> = host.find_modules(host.snapshot(), "gu").collect();
+    assert_eq!(1, modules.len());
+    assert_eq!(guest.val(), modules[0].val());
+}
+
+#[test]
+pub fn watched_globals_report_changes() {
+    let mut host = Host::new();
+    let module = ModuleBuilder::new()
+        .global(
+            "ready",
+            Global::new(
+                GlobalType::new(ValType::I32, true),
+                Expr::new(vec![Instruction::I32Const(Value::I32(0))]),
+            ),
+        )
+        .func(
+            FuncBuilder::new()
+                .export_as("set_ready")
+                .param(ValType::I32)
+                .body(vec![Instruction::LocalGet(0), Instruction::GlobalSet(0)]),
+        );
+    let addr = host.instantiate("test", module.build()).unwrap();
+    let set_ready = host
+        .get_module(addr)
+        .unwrap()
+        .export_func("set_ready")
+        .unwrap();
+
+    let changes = Arc::new(Mutex::new(Vec::new()));
+    let recorded = changes.clone();
+    let ready = host
+        .watch_global_named(addr, "ready", move |old, new| {
+            recorded.lock().unwrap().push((old, new))
+        })
+        .unwrap();
+    for value in [1, 1, 0].iter() {
+        Thread::new()
+            .call(&mut host, addr, set_ready, &[Value::I32(*value)])
+            .unwrap();
+    }
+    assert_eq!(
+        vec![
+            (Value::I32(0), Value::I32(1)),
+            (Value::I32(1), Value::I32(1)),
+            (Value::I32(1), Value::I32(0)),
+        ],
+        *changes.lock().unwrap()
+    );
+
+    host.unwatch_global(ready);
+    Thread::new()
+        .call(&mut host, addr, set_ready, &[Value::I32(1)])
+        .unwrap();
+    assert_eq!(3, changes.lock().unwrap().len());
+    assert!(matches!(
+        host.watch_global_named(addr, "set_ready", |_, _| {}),
+        Err(Error::ExportTypeMismatch { .. })
+    ));
+}
@@ -0,0 +1,372 @@
+use std::{io::Cursor, sync::Arc};
+
+use super::synthesize_env;
+use crate::{
+    builder::{FuncBuilder, ModuleBuilder},
+    hosting::{ExternVal, Host, ModuleAddr, ModuleInst, Stub},
+    interp::Thread,
+    module::{
+        DataItem, Expr, Global, GlobalType, Import, MemberDesc, MemoryType, Module, TableType,
+    },
+    reader::{Reader, SectionId},
+    Error, Instruction, ValType, Value, PAGE_SIZE,
+};
+
+#[test]
+pub fn malformed_modules_are_errors_rather_than_panics() {
+    let mut host = Host::new();
+
+    // A data segment for a memory the module doesn't have
+    let mut module = ModuleBuilder::new();
+    let bytes = [0x00, 0x41, 0x00, 0x0B, 0x01, 0xAA];
+    module
+        .data
+        .push(DataItem::read(&mut Cursor::new(&bytes[..])).unwrap());
+    match host.instantiate("data", module.build()) {
+        Err(Error::UnknownMemoryIndex { index: 0, at }) => {
+            assert_eq!(Some(SectionId::Data), at.section())
+        }
+        r => panic!("expected an unknown memory, got {:?}", r.map(|_| ())),
+    }
+
+    // A section with an unknown ID
+    let mut reader = Reader::new(Cursor::new(&[0x7F, 0x00][..]));
+    match reader.read_section_header() {
+        Err(Error::UnknownSection { id: 0x7F, at }) => assert_eq!(Some(0), at.offset()),
+        r => panic!("expected an unknown section, got {:?}", r.map(|_| ())),
+    }
+
+    // Decoding errors are located by section and the offset of the byte that couldn't be
+    // decoded, while errors about a whole section are located at the section's contents
+    let load = |sections: &[u8]| {
+        let mut bytes = b"\0asm\x01\0\0\0".to_vec();
+        bytes.extend_from_slice(sections);
+        Module::load(Reader::new(Cursor::new(bytes)))
+    };
+    match load(&[0x05, 0x03, 0x01, 0x00, 0x01, 0x05, 0x03, 0x01, 0x00, 0x01]) {
+        Err(Error::DuplicateSection { at }) => {
+            assert_eq!(
+                (Some(SectionId::Memory), Some(15)),
+                (at.section(), at.offset())
+            )
+        }
+        r => panic!("expected a duplicate section, got {:?}", r.map(|_| ())),
+    }
+    match load(&[0x01, 0x03, 0x01, 0x00, 0x00]) {
+        Err(Error::InvalidTypeForm { form: 0, at }) => {
+            assert_eq!(
+                (Some(SectionId::Type), Some(11)),
+                (at.section(), at.offset())
+            )
+        }
+        r => panic!("expected an invalid type form, got {:?}", r.map(|_| ())),
+    }
+
+    // A call to a function that doesn't exist
+    let module = ModuleBuilder::new().func(
+        FuncBuilder::new()
+            .export_as("test")
+            .body(vec![Instruction::Call(7)]),
+    );
+    let addr = host.instantiate("call", module.build()).unwrap();
+    let func = host.get_module(addr).unwrap().export_func("test").unwrap();
+    let trap = Thread::new().call(&mut host, addr, func, &[]).unwrap_err();
+    assert_eq!("No such function: 7", trap.cause().message());
+}
+
+#[test]
+pub fn forked_instances_start_from_the_parent_state() {
+    let mut host = Host::new();
+    synthesize_env(&mut host);
+
+    // (func $bump (result i32)) increments both its own counter in memory and the shared
+    // counter in 'env', returning the new value of its own counter
+    let mut module = ModuleBuilder::new()
+        .mem("memory", MemoryType::new(1, None))
+        .func(
+            FuncBuilder::new()
+                .export_as("bump")
+                .result(ValType::I32)
+                .body(vec![
+                    Instruction::GlobalGet(0),
+                    Instruction::I32Const(Value::I32(1)),
+                    Instruction::I32Add,
+                    Instruction::GlobalSet(0),
+                    Instruction::I32Const(Value::I32(0)),
+                    Instruction::I32Const(Value::I32(0)),
+                    Instruction::I32Load(2, 0),
+                    Instruction::I32Const(Value::I32(1)),
+                    Instruction::I32Add,
+                    Instruction::I32Store(2, 0),
+                    Instruction::I32Const(Value::I32(0)),
+                    Instruction::I32Load(2, 0),
+                ]),
+        );
+    module.imports.push(Import::new(
+        "env",
+        "counter",
+        MemberDesc::Global(GlobalType::new(ValType::I32, true)),
+    ));
+    let parent = host.instantiate("counter", module.build()).unwrap();
+    let bump = |host: &mut Host, addr| {
+        let func = host.get_module(addr).unwrap().export_func("bump").unwrap();
+        match Thread::new().call(host, addr, func, &[]).unwrap()[0] {
+            Value::I32(v) => v,
+            v => panic!("expected an i32, got {:?}", v),
+        }
+    };
+
+    assert_eq!(1, bump(&mut host, parent));
+    assert_eq!(2, bump(&mut host, parent));
+
+    // The fork starts with a copy of the parent's memory, then the two diverge
+    let fork = host.fork_instance(parent).unwrap();
+    assert_eq!(3, bump(&mut host, fork));
+    assert_eq!(4, bump(&mut host, fork));
+    assert_eq!(3, bump(&mut host, parent));
+    assert_ne!(
+        host.get_module(parent).unwrap().mems()[0],
+        host.get_module(fork).unwrap().mems()[0]
+    );
+
+    // Imports are still shared
+    let env = host.find_module("env").unwrap();
+    let counter = host.get_module(env).unwrap().globals()[0];
+    assert_eq!(Value::I32(47), host.get_global(counter).unwrap().get());
+    assert_eq!(
+        host.get_module(parent).unwrap().globals(),
+        host.get_module(fork).unwrap().globals()
+    );
+}
+
+#[test]
+#[cfg(feature = "wat")]
+pub fn modules_are_instantiated_from_text() {
+    let mut host = Host::new();
+    let addr = host
+        .instantiate_wat(
+            "test",
+            r#"(module
+                (func (export "add") (param i32 i32) (result i32)
+                    local.get 0
+                    local.get 1
+                    i32.add))"#,
+        )
+        .unwrap();
+    let add = host.get_module(addr).unwrap().export_func("add").unwrap();
+    let res = Thread::new().call(&mut host, addr, add, &[Value::I32(2), Value::I32(3)]);
+    assert_eq!(Ok(vec![Value::I32(5)]), res);
+
+    // Text that doesn't parse, and modules that don't validate, are both errors
+    assert!(matches!(
+        host.instantiate_wat("bad", "(module (func"),
+        Err(Error::InvalidText(_))
+    ));
+    assert!(matches!(
+        host.instantiate_wat("bad", "(module (func (result i32)))"),
+        Err(Error::ValidationFailed { .. })
+    ));
+}
+
+#[test]
+pub fn modules_are_instantiated_again() {
+    use crate::Instruction::*;
+
+    // (func $bump (result i32)) increments a global and returns it
+    let module = ModuleBuilder::new()
+        .global(
+            "count",
+            Global::new(
+                GlobalType::new(ValType::I32, true),
+                Expr::new(vec![I32Const(Value::I32(0))]),
+            ),
+        )
+        .func(
+            FuncBuilder::new()
+                .export_as("bump")
+                .result(ValType::I32)
+                .body(vec![
+                    GlobalGet(0),
+                    I32Const(Value::I32(1)),
+                    I32Add,
+                    GlobalSet(0),
+                    GlobalGet(0),
+                ]),
+        )
+        .build();
+    let module = Arc::new(module);
+
+    let mut host = Host::new();
+    let first = host.instantiate_arc("counter", module.clone()).unwrap();
+    let second = host.instantiate_arc("counter", module).unwrap();
+    host.invoke(first, "bump", &[]).unwrap();
+    let again = host.instantiate_again(first).unwrap();
+
+    // Each instance starts afresh, with its own state
+    for addr in [first, second, again] {
+        let count = host.invoke(addr, "bump", &[]).unwrap();
+        assert_eq!(vec![Value::I32(if addr == first { 2 } else { 1 })], count);
+    }
+    assert_eq!("counter", host.get_module(again).unwrap().name());
+
+    // Modules of host functions weren't created from a module
+    host.define("env", "f", || {}).unwrap();
+    let env = host.find_module("env").unwrap();
+    assert!(matches!(
+        host.instantiate_again(env),
+        Err(Error::NotInstantiable { .. })
+    ));
+}
+
+#[test]
+pub fn instances_are_dropped() {
+    let lib = || {
+        ModuleBuilder::new()
+            .mem("memory", MemoryType::new(1, None))
+            .func(FuncBuilder::new().export_as("f").body(vec![]))
+    };
+    let mut host = Host::new();
+    let first = host.instantiate("lib", lib().build()).unwrap();
+    let user = host
+        .instantiate(
+            "user",
+            ModuleBuilder::new()
+                .func(FuncBuilder::new().import_from("lib", "f"))
+                .build(),
+        )
+        .unwrap();
+
+    // Functions can't outlive their instance
+    assert!(matches!(
+        host.drop_instance(first),
+        Err(Error::InstanceInUse { .. })
+    ));
+    host.drop_instance(user).unwrap();
+    let mem = host
+        .get_module(first)
+        .unwrap()
+        .export_mem("memory")
+        .unwrap();
+    host.drop_instance(first).unwrap();
+    assert!(matches!(
+        host.drop_instance(first),
+        Err(Error::StaleAddress { .. })
+    ));
+    assert_eq!(None, host.find_module("lib"));
+    assert_eq!(0, host.modules().count());
+    assert_eq!(0, host.mems().count());
+
+    // The slots are reused, but the old addresses don't refer to the new items
+    let second = host.instantiate("lib", lib().build()).unwrap();
+    assert_eq!(first.val(), second.val());
+    assert_ne!(first, second);
+    assert_eq!(1, second.generation());
+    let new_mem = host
+        .get_module(second)
+        .unwrap()
+        .export_mem("memory")
+        .unwrap();
+    assert_ne!(mem, new_mem);
+    assert!(matches!(host.get_mem(mem), Err(Error::StaleAddress { .. })));
+
+    // Memories outlive their instance while another instance imports them
+    let mut user = ModuleBuilder::new();
+    user.imports.push(Import::new(
+        "lib",
+        "memory",
+        MemberDesc::Memory(MemoryType::new(1, None)),
+    ));
+    let user = host.instantiate("user", user.build()).unwrap();
+    host.drop_instance(second).unwrap();
+    let imported = host.get_module(user).unwrap().mems()[0];
+    assert_eq!(new_mem, imported);
+    assert_eq!(PAGE_SIZE, host.get_mem(imported).unwrap().memory().len());
+}
+
+/// Instantiates a module with one of each kind of item, and drops it again, for its stale
+/// addresses.
+fn dropped_lib() -> (Host, ModuleAddr, Arc<ModuleInst>) {
+    let lib = ModuleBuilder::new()
+        .mem("memory", MemoryType::new(1, None))
+        .global(
+            "counter",
+            Global::new(
+                GlobalType::new(ValType::I32, true),
+                Expr::new(vec![Instruction::I32Const(Value::I32(0))]),
+            ),
+        )
+        .table("table", TableType::new(1, None))
+        .func(FuncBuilder::new().export_as("f").body(vec![]));
+    let mut host = Host::new();
+    let addr = host.instantiate("lib", lib.build()).unwrap();
+    let inst = host.get_module(addr).unwrap();
+    host.drop_instance(addr).unwrap();
+    (host, addr, inst)
+}
+
+fn stale<T>(res: Result<T, Error>) -> bool {
+    matches!(res, Err(Error::StaleAddress { .. }))
+}
+
+#[test]
+pub fn stale_addresses_are_errors() {
+    let (mut host, addr, inst) = dropped_lib();
+    let func = inst.export_func("f").unwrap();
+
+    assert!(stale(host.get_module(addr)));
+    assert!(stale(host.get_func(func)));
+    assert!(stale(host.get_table(inst.tables()[0])));
+    assert!(stale(host.get_mem(inst.mems()[0])));
+    assert!(stale(host.get_global(inst.globals()[0])));
+    assert!(stale(host.resolve_import(addr, "f")));
+    assert!(stale(host.invoke(addr, "f", &[])));
+    assert!(stale(host.set_invokable(addr, vec!["f"])));
+    assert!(stale(host.is_invokable(func)));
+    assert_eq!(None, host.resolve_func(addr, 0));
+}
+
+#[test]
+pub fn stale_instances_cant_be_forked() {
+    let (mut host, addr, _) = dropped_lib();
+    assert!(stale(host.fork_instance(addr)));
+}
+
+#[test]
+pub fn stale_instances_cant_be_instantiated_again() {
+    let (mut host, addr, _) = dropped_lib();
+    assert!(stale(host.instantiate_again(addr)));
+}
+
+#[test]
+pub fn stale_instances_have_nothing_to_invoke() {
+    let (mut host, addr, _) = dropped_lib();
+    assert!(stale(host.invoke_matching(&mut Thread::new(), addr, "*")));
+}
+
+#[test]
+pub fn stale_instances_have_nothing_to_stub() {
+    let (mut host, addr, _) = dropped_lib();
+    assert!(stale(host.stub_func_named(addr, "f", Stub::Trap)));
+}
+
+#[test]
+pub fn stale_instances_have_no_globals_to_watch() {
+    let (mut host, addr, _) = dropped_lib();
+    assert!(stale(host.watch_global_named(addr, "counter", |_, _| ())));
+}
+
+#[test]
+pub fn stale_items_have_no_owner() {
+    let (host, _, inst) = dropped_lib();
+    assert!(stale(host.owner(ExternVal::Func(inst.funcs()[0]))));
+    assert!(stale(host.owner(ExternVal::Table(inst.tables()[0]))));
+    assert!(stale(host.owner(ExternVal::Mem(inst.mems()[0]))));
+    assert!(stale(host.owner(ExternVal::Global(inst.globals()[0]))));
+}
+
+#[test]
+pub fn stale_items_have_no_name() {
+    let (host, _, inst) = dropped_lib();
+    assert_eq!(None, host.item_name(ExternVal::Func(inst.funcs()[0])));
+    assert_eq!(None, host.item_name(ExternVal::Mem(inst.mems()[0])));
+}
@@ -0,0 +1,190 @@
+use crate::{
+    builder::{FuncBuilder, ModuleBuilder},
+    hosting::{Host, Stub},
+    interp::Thread,
+    module::MemoryType,
+    Error, Instruction, ValType, Value,
+};
+
+#[test]
+pub fn only_invokable_exports_may_be_called() {
+    let mut host = Host::new();
+    let module = ModuleBuilder::new()
+        .func(
+            FuncBuilder::new()
+                .export_as("handle_request")
+                .result(ValType::I32)
+                .body(vec![Instruction::Call(1)]),
+        )
+        .func(
+            FuncBuilder::new()
+                .export_as("internal")
+                .result(ValType::I32)
+                .body(vec![Instruction::I32Const(Value::I32(42))]),
+        )
+        .mem("memory", MemoryType::new(1, None));
+    let addr = host.instantiate("guest", module.build()).unwrap();
+    let func = |host: &Host, name| host.get_module(addr).unwrap().export_func(name).unwrap();
+
+    // Names must refer to exported functions
+    assert!(matches!(
+        host.set_invokable(addr, ["missing"]),
+        Err(Error::ExportNotFound { .. })
+    ));
+    assert!(matches!(
+        host.set_invokable(addr, ["memory"]),
+        Err(Error::ExportTypeMismatch { .. })
+    ));
+
+    host.set_invokable(addr, ["handle_request"]).unwrap();
+    let internal = func(&host, "internal");
+    let trap = Thread::new()
+        .call(&mut host, addr, internal, &[])
+        .unwrap_err();
+    assert_eq!("function is not invokable", trap.cause().message());
+
+    // The allowed export can still call other functions in its module
+    let handler = func(&host, "handle_request");
+    let res = Thread::new().call(&mut host, addr, handler, &[]);
+    assert_eq!(Ok(vec![Value::I32(42)]), res);
+}
+
+#[test]
+pub fn stubbed_functions_are_not_run() {
+    let mut host = Host::new();
+    let module = ModuleBuilder::new()
+        .func(
+            FuncBuilder::new()
+                .export_as("main")
+                .result(ValType::I32)
+                .body(vec![
+                    Instruction::I32Const(Value::I32(1)),
+                    Instruction::I32Const(Value::I32(2)),
+                    Instruction::Call(1),
+                    Instruction::I32Add,
+                ]),
+        )
+        .func(
+            FuncBuilder::new()
+                .export_as("unsupported")
+                .param(ValType::I32)
+                .result(ValType::I32)
+                .body(vec![Instruction::Unreachable]),
+        );
+    let addr = host.instantiate("test", module.build()).unwrap();
+    let main = host.get_module(addr).unwrap().export_func("main").unwrap();
+
+    let unsupported = host
+        .stub_func_named(addr, "unsupported", Stub::Zeroed)
+        .unwrap();
+    assert_eq!(Some(Stub::Zeroed), host.stub(unsupported));
+    let res = Thread::new().call(&mut host, addr, main, &[]);
+    assert_eq!(Ok(vec![Value::I32(1)]), res);
+
+    host.stub_func(unsupported, Stub::Trap);
+    let trap = Thread::new().call(&mut host, addr, main, &[]).unwrap_err();
+    assert_eq!("Function 'unsupported' is stubbed.", trap.cause().message());
+
+    host.unstub_func(unsupported);
+    let trap = Thread::new().call(&mut host, addr, main, &[]).unwrap_err();
+    assert_eq!("unreachable", trap.cause().message());
+}
+
+#[test]
+pub fn exports_matching_a_pattern_are_invoked() {
+    let mut host = Host::new();
+    let module = ModuleBuilder::new()
+        .func(
+            FuncBuilder::new()
+                .export_as("test_passes")
+                .result(ValType::I32)
+                .body(vec![Instruction::I32Const(Value::I32(1))]),
+        )
+        .func(
+            FuncBuilder::new()
+                .export_as("helper")
+                .body(vec![Instruction::Unreachable]),
+        )
+        .func(
+            FuncBuilder::new()
+                .export_as("test_fails")
+                .body(vec![Instruction::Unreachable]),
+        )
+        .func(
+            FuncBuilder::new()
+                .export_as("test_needs_args")
+                .param(ValType::I32)
+                .body(vec![]),
+        );
+    let addr = host.instantiate("test", module.build()).unwrap();
+
+    let mut thread = Thread::new();
+    let results = host.invoke_matching(&mut thread, addr, "test_*").unwrap();
+    let names: Vec<_> = results.iter().map(|(n, _)| n.as_str()).collect();
+    assert_eq!(vec!["test_passes", "test_fails"], names);
+    assert_eq!(Ok(vec![Value::I32(1)]), results[0].1);
+    assert_eq!("unreachable", results[1].1.as_ref().unwrap_err().message());
+
+    let mut names = |pattern| -> Vec<String> {
+        host.invoke_matching(&mut Thread::new(), addr, pattern)
+            .unwrap()
+            .into_iter()
+            .map(|(n, _)| n)
+            .collect()
+    };
+    assert_eq!(vec!["helper"], names("help"));
+    assert_eq!(vec!["test_fails"], names("*_f*s"));
+    assert!(names("*passes_").is_empty());
+}
+
+#[test]
+pub fn exports_can_be_invoked_by_name() {
+    let mut host = Host::new();
+    let module = ModuleBuilder::new().func(
+        FuncBuilder::new()
+            .export_as("add")
+            .param(ValType::I32)
+            .param(ValType::I32)
+            .result(ValType::I32)
+            .body(vec![
+                Instruction::LocalGet(0),
+                Instruction::LocalGet(1),
+                Instruction::I32Add,
+            ]),
+    );
+    let addr = host.instantiate("test", module.build()).unwrap();
+
+    let res = host.invoke(addr, "add", &[Value::I32(2), Value::I32(3)]);
+    assert_eq!(vec![Value::I32(5)], res.unwrap());
+    assert!(matches!(
+        host.invoke(addr, "add", &[Value::I32(2), Value::I64(3)]),
+        Err(Error::ArgumentTypeMismatch { ref actual, .. })
+            if actual == &[ValType::I32, ValType::I64]
+    ));
+    assert!(matches!(
+        host.invoke(addr, "add", &[Value::I32(2)]),
+        Err(Error::ArgumentTypeMismatch { .. })
+    ));
+    assert!(matches!(
+        host.invoke(addr, "sub", &[]),
+        Err(Error::ExportNotFound { .. })
+    ));
+}
+
+#[test]
+pub fn multiple_results_are_returned_in_order() {
+    let mut host = Host::new();
+    let module = ModuleBuilder::new().func(
+        FuncBuilder::new()
+            .export_as("swap")
+            .param(ValType::I32)
+            .param(ValType::I64)
+            .result(ValType::I64)
+            .result(ValType::I32)
+            .body(vec![Instruction::LocalGet(1), Instruction::LocalGet(0)]),
+    );
+    let addr = host.instantiate("test", module.build()).unwrap();
+
+    let res = host.invoke(addr, "swap", &[Value::I32(1), Value::I64(2)]);
+    assert_eq!(vec![Value::I64(2), Value::I32(1)], res.unwrap());
+}
@@ -0,0 +1,124 @@
+use std::sync::{Arc, Mutex};
+
+use super::synthesize_env;
+use crate::{
+    builder::{FuncBuilder, ModuleBuilder},
+    hosting::{Host, ModuleAddr},
+    module::{Expr, Global, GlobalType},
+    Instruction, ValType, Value,
+};
+
+#[test]
+pub fn hosts_are_isolated() {
+    use crate::Instruction::*;
+
+    // Builds a host whose env.value returns `n`, and an app module counting its calls
+    fn host_returning(n: u32) -> (Host, ModuleAddr) {
+        let mut host = Host::new();
+        host.define("env", "value", move || -> u32 { n }).unwrap();
+        let app = ModuleBuilder::new()
+            .func(
+                FuncBuilder::new()
+                    .import_from("env", "value")
+                    .result(ValType::I32),
+            )
+            .global(
+                "calls",
+                Global::new(
+                    GlobalType::new(ValType::I32, true),
+                    Expr::new(vec![I32Const(Value::I32(0))]),
+                ),
+            )
+            .func(
+                FuncBuilder::new()
+                    .export_as("main")
+                    .result(ValType::I32)
+                    .body(vec![
+                        GlobalGet(0),
+                        I32Const(Value::I32(1)),
+                        I32Add,
+                        GlobalSet(0),
+                        Call(0),
+                    ]),
+            );
+        let addr = host.instantiate("app", app.build()).unwrap();
+        (host, addr)
+    }
+
+    let (mut first, first_app) = host_returning(1);
+    let (mut second, second_app) = host_returning(2);
+    for _ in 0..3 {
+        assert_eq!(
+            vec![Value::I32(1)],
+            first.invoke(first_app, "main", &[]).unwrap()
+        );
+        assert_eq!(
+            vec![Value::I32(2)],
+            second.invoke(second_app, "main", &[]).unwrap()
+        );
+    }
+    second.invoke(second_app, "main", &[]).unwrap();
+    let calls = |host: &Host, app| {
+        let addr = host
+            .get_module(app)
+            .unwrap()
+            .export_global("calls")
+            .unwrap();
+        host.get_global(addr).unwrap().get()
+    };
+    assert_eq!(Value::I32(3), calls(&first, first_app));
+    assert_eq!(Value::I32(4), calls(&second, second_app));
+
+    // Hosts on different OS threads don't interfere either
+    let workers: Vec<_> = (10..14)
+        .map(|n| {
+            std::thread::spawn(move || {
+                let (mut host, app) = host_returning(n);
+                (0..1000)
+                    .map(|_| host.invoke(app, "main", &[]).unwrap())
+                    .all(|res| res == vec![Value::I32(n)])
+            })
+        })
+        .collect();
+    assert!(workers.into_iter().all(|w| w.join().unwrap()));
+}
+
+#[test]
+pub fn hosts_are_shared_between_threads() {
+    fn assert_send_sync<T: Send + Sync>() {}
+    assert_send_sync::<Host>();
+
+    let mut host = Host::new();
+    synthesize_env(&mut host);
+    host.define("env", "double", |x: u32| -> u32 { x * 2 })
+        .unwrap();
+    let module = ModuleBuilder::new()
+        .func(
+            FuncBuilder::new()
+                .import_from("env", "double")
+                .param(ValType::I32)
+                .result(ValType::I32),
+        )
+        .func(
+            FuncBuilder::new()
+                .export_as("main")
+                .param(ValType::I32)
+                .result(ValType::I32)
+                .body(vec![Instruction::LocalGet(0), Instruction::Call(0)]),
+        );
+    let addr = host.instantiate("test", module.build()).unwrap();
+
+    let host = Arc::new(Mutex::new(host));
+    let workers: Vec<_> = (0..4)
+        .map(|n| {
+            let host = host.clone();
+            std::thread::spawn(move || {
+                let mut host = host.lock().unwrap();
+                host.invoke(addr, "main", &[Value::I32(n)]).unwrap()
+            })
+        })
+        .collect();
+    for (n, worker) in workers.into_iter().enumerate() {
+        assert_eq!(vec![Value::I32(n as u32 * 2)], worker.join().unwrap());
+    }
+}
@@ -0,0 +1,139 @@
+use std::sync::{Arc, Mutex};
+
+use crate::{
+    builder::{FuncBuilder, ModuleBuilder},
+    hosting::{ExternalFunc, ExternalMemory, ExternalModule, Host},
+    interp::Thread,
+    module::MemoryType,
+    Error, Instruction, LinearMemory, MemoryBackend, ValType, Value, PAGE_SIZE,
+};
+
+#[test]
+pub fn memories_are_shared_between_instances() {
+    use crate::Instruction::*;
+
+    struct Heap([ExternalMemory; 1]);
+
+    impl ExternalModule for Heap {
+        fn name(&self) -> &str {
+            "heap"
+        }
+
+        fn funcs(&self) -> &[Arc<ExternalFunc>] {
+            &[]
+        }
+
+        fn mems(&self) -> &[ExternalMemory] {
+            &self.0
+        }
+    }
+
+    // Stores its argument at address 8 of the memory it imports from `module`
+    let store = |module: &str| {
+        ModuleBuilder::new()
+            .import_mem(module, "memory", MemoryType::new(1, None))
+            .func(
+                FuncBuilder::new()
+                    .export_as("store")
+                    .param(ValType::I32)
+                    .body(vec![I32Const(Value::I32(8)), LocalGet(0), I32Store(2, 0)]),
+            )
+            .build()
+    };
+
+    let mut host = Host::new();
+    let lib = ModuleBuilder::new()
+        .mem("memory", MemoryType::new(1, None))
+        .func(
+            FuncBuilder::new()
+                .export_as("load")
+                .result(ValType::I32)
+                .body(vec![I32Const(Value::I32(8)), I32Load(2, 0)]),
+        );
+    let lib = host.instantiate("lib", lib.build()).unwrap();
+    let app = host.instantiate("app", store("lib")).unwrap();
+    host.invoke(app, "store", &[Value::I32(7)]).unwrap();
+    assert_eq!(vec![Value::I32(7)], host.invoke(lib, "load", &[]).unwrap());
+    let mem = host.get_module(lib).unwrap().mems()[0];
+    assert_eq!(&[mem], host.get_module(app).unwrap().mems());
+
+    // The memory outlives the instance that exported it while it's still imported
+    host.drop_instance(lib).unwrap();
+    host.invoke(app, "store", &[Value::I32(9)]).unwrap();
+    assert_eq!(9, host.get_mem(mem).unwrap().read_u32(8).unwrap());
+
+    let heap = host
+        .external(Heap([ExternalMemory::new("memory", 1, None)]))
+        .unwrap();
+    let app = host.instantiate("app", store("heap")).unwrap();
+    host.invoke(app, "store", &[Value::I32(11)]).unwrap();
+    let mem = host.get_module(heap).unwrap().mems()[0];
+    assert_eq!(11, host.get_mem(mem).unwrap().read_u32(8).unwrap());
+}
+
+#[test]
+pub fn memories_are_allocated_from_the_host_backend() {
+    struct VecMemory(Vec<u8>);
+
+    unsafe impl LinearMemory for VecMemory {
+        fn ptr(&self) -> *mut u8 {
+            self.0.as_ptr() as *mut u8
+        }
+
+        fn len(&self) -> usize {
+            self.0.len()
+        }
+    }
+
+    struct VecBackend(Arc<Mutex<Vec<usize>>>);
+
+    impl MemoryBackend for VecBackend {
+        fn allocate(
+            &self,
+            min_size: usize,
+            _max_size: Option<usize>,
+        ) -> Result<Box<dyn LinearMemory>, Error> {
+            self.0.lock().unwrap().push(min_size);
+            Ok(Box::new(VecMemory(vec![0; min_size])))
+        }
+    }
+
+    let allocations = Arc::new(Mutex::new(Vec::new()));
+    let mut host = Host::new();
+    host.set_memory_backend(VecBackend(allocations.clone()));
+
+    let module = ModuleBuilder::new()
+        .mem("memory", MemoryType::new(1, None))
+        .func(
+            FuncBuilder::new()
+                .export_as("test")
+                .result(ValType::I32)
+                .body(vec![
+                    Instruction::I32Const(Value::I32(8)),
+                    Instruction::I32Const(Value::I32(42)),
+                    Instruction::I32Store(2, 0),
+                    Instruction::I32Const(Value::I32(8)),
+                    Instruction::I32Load(2, 0),
+                ]),
+        );
+    let addr = host.instantiate("test", module.build()).unwrap();
+    assert_eq!(vec![PAGE_SIZE], *allocations.lock().unwrap());
+
+    let func = host.get_module(addr).unwrap().export_func("test").unwrap();
+    let res = Thread::new().call(&mut host, addr, func, &[]);
+    assert_eq!(Ok(vec![Value::I32(42)]), res);
+}
+
+#[test]
+pub fn memories_that_cant_be_allocated_fail_instantiation() {
+    let module = ModuleBuilder::new().mem("memory", MemoryType::new_64(1 << 32, None));
+    let mut host = Host::new();
+    let res = host.instantiate("test", module.build());
+    match res {
+        Err(Error::ResourceLimitExceeded { module, resource }) => {
+            assert_eq!("test", module);
+            assert_eq!("memory", resource);
+        }
+        res => panic!("expected instantiation to fail, got {:?}", res),
+    }
+}
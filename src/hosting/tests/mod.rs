@@ -0,0 +1,30 @@
+//! Tests of the [`Host`], by feature.
+
+use crate::{
+    builder::ModuleBuilder,
+    hosting::Host,
+    module::{Expr, Global, GlobalType, MemoryType},
+    Instruction, ValType, Value,
+};
+
+mod imports;
+mod inspection;
+mod instances;
+mod invoking;
+mod isolation;
+mod memories;
+
+/// Synthesizes an `env` module exporting a one-page `memory` and a mutable `counter` global
+/// that starts at 42.
+pub fn synthesize_env(host: &mut Host) {
+    let env = ModuleBuilder::new()
+        .mem("memory", MemoryType::new(1, Some(2)))
+        .global(
+            "counter",
+            Global::new(
+                GlobalType::new(ValType::I32, true),
+                Expr::new(vec![Instruction::I32Const(Value::I32(42))]),
+            ),
+        );
+    host.synthesize("env", env).unwrap();
+}
@@ -0,0 +1,189 @@
+use std::sync::Arc;
+
+use crate::{
+    hosting::{Caller, DynHostFunc},
+    module::FuncType,
+    FromValue, Trap, ValType, Value,
+};
+
+/// A Rust type that can be passed to or returned from WebAssembly as a single value.
+pub trait WasmType: FromValue + Into<Value> {
+    fn val_type() -> ValType;
+}
+
+macro_rules! impl_wasm_type {
+    ($t: ty, $v: ident) => {
+        impl WasmType for $t {
+            fn val_type() -> ValType {
+                ValType::$v
+            }
+        }
+    };
+}
+
+impl_wasm_type!(i32, I32);
+impl_wasm_type!(u32, I32);
+impl_wasm_type!(i64, I64);
+impl_wasm_type!(u64, I64);
+impl_wasm_type!(f32, F32);
+impl_wasm_type!(f64, F64);
+impl_wasm_type!(u128, V128);
+
+/// What a typed host function returns: nothing, a [`WasmType`], a tuple of them, or any of
+/// those in a `Result` to trap.
+pub trait WasmResults {
+    fn val_types() -> Vec<ValType>;
+    fn into_values(self) -> Result<Vec<Value>, Trap>;
+}
+
+impl WasmResults for () {
+    fn val_types() -> Vec<ValType> {
+        Vec::new()
+    }
+
+    fn into_values(self) -> Result<Vec<Value>, Trap> {
+        Ok(Vec::new())
+    }
+}
+
+impl<T: WasmType> WasmResults for T {
+    fn val_types() -> Vec<ValType> {
+        vec![T::val_type()]
+    }
+
+    fn into_values(self) -> Result<Vec<Value>, Trap> {
+        Ok(vec![self.into()])
+    }
+}
+
+impl<R: WasmResults> WasmResults for Result<R, Trap> {
+    fn val_types() -> Vec<ValType> {
+        R::val_types()
+    }
+
+    fn into_values(self) -> Result<Vec<Value>, Trap> {
+        self.and_then(R::into_values)
+    }
+}
+
+macro_rules! impl_wasm_results_tuple {
+    ($($r: ident),+) => {
+        impl<$($r: WasmType),+> WasmResults for ($($r,)+) {
+            fn val_types() -> Vec<ValType> {
+                vec![$($r::val_type()),+]
+            }
+
+            #[allow(non_snake_case)]
+            fn into_values(self) -> Result<Vec<Value>, Trap> {
+                let ($($r,)+) = self;
+                Ok(vec![$($r.into()),+])
+            }
+        }
+    };
+}
+
+impl_wasm_results_tuple!(A, B);
+impl_wasm_results_tuple!(A, B, C);
+impl_wasm_results_tuple!(A, B, C, D);
+
+/// A Rust closure that can be called from WebAssembly, with its [`FuncType`] worked out from
+/// its signature. See [`Host::define`](crate::hosting::Host::define).
+///
+/// This is implemented for closures taking up to six [`WasmType`] parameters and returning
+/// [`WasmResults`]. `Params` and `Results` only exist to tell the implementations apart.
+pub trait IntoHostFunc<Params, Results> {
+    fn func_type() -> FuncType;
+    fn into_host_func(self) -> Arc<DynHostFunc>;
+}
+
+macro_rules! impl_into_host_func {
+    ($($p: ident),*) => {
+        impl<F, R, $($p),*> IntoHostFunc<($($p,)*), R> for F
+        where
+            F: Fn($($p),*) -> R + Send + Sync + 'static,
+            R: WasmResults,
+            $($p: WasmType),*
+        {
+            fn func_type() -> FuncType {
+                FuncType::new(vec![$($p::val_type()),*], R::val_types())
+            }
+
+            #[allow(non_snake_case, unused_mut, unused_variables)]
+            fn into_host_func(self) -> Arc<DynHostFunc> {
+                Arc::new(move |_: &mut Caller, values: &[Value]| {
+                    // The parameters are checked against the function's type before it's called
+                    let mut values = values.iter();
+                    $(let $p = $p::from_value(*values.next().unwrap())?;)*
+                    self($($p),*).into_values()
+                })
+            }
+        }
+    };
+}
+
+impl_into_host_func!();
+impl_into_host_func!(A);
+impl_into_host_func!(A, B);
+impl_into_host_func!(A, B, C);
+impl_into_host_func!(A, B, C, D);
+impl_into_host_func!(A, B, C, D, E);
+impl_into_host_func!(A, B, C, D, E, G);
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        builder::{FuncBuilder, ModuleBuilder},
+        hosting::Host,
+        Error, Trap, TrapCause, ValType, Value,
+    };
+
+    #[test]
+    pub fn closures_are_defined_with_their_signature() {
+        use crate::Instruction::*;
+
+        let mut host = Host::new();
+        host.define("env", "add", |a: i32, b: i32| -> i32 { a + b })
+            .unwrap();
+        host.define("env", "halve", |a: f64| -> Result<f64, Trap> {
+            if a.is_nan() {
+                Err(TrapCause::UnexpectedNaN.into())
+            } else {
+                Ok(a / 2.0)
+            }
+        })
+        .unwrap();
+        assert!(matches!(
+            host.define("env", "add", || {}),
+            Err(Error::DuplicateExportName { .. })
+        ));
+
+        let module = ModuleBuilder::new()
+            .func(
+                FuncBuilder::new()
+                    .import_from("env", "add")
+                    .param(ValType::I32)
+                    .param(ValType::I32)
+                    .result(ValType::I32),
+            )
+            .func(
+                FuncBuilder::new()
+                    .export_as("main")
+                    .result(ValType::I32)
+                    .body(vec![
+                        I32Const(Value::I32(2)),
+                        I32Const(Value::I32(3)),
+                        Call(0),
+                    ]),
+            );
+        let addr = host.instantiate("test", module.build()).unwrap();
+        assert_eq!(vec![Value::I32(5)], host.invoke(addr, "main", &[]).unwrap());
+
+        let env = host.find_module("env").unwrap();
+        let res = host.invoke(env, "halve", &[Value::F64(3.0)]);
+        assert_eq!(vec![Value::F64(1.5)], res.unwrap());
+        assert!(matches!(
+            host.invoke(env, "halve", &[Value::F64(f64::NAN)]),
+            Err(Error::Trap(_))
+        ));
+    }
+}
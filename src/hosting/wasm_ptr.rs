@@ -0,0 +1,216 @@
+use std::{ffi::CString, fmt, marker::PhantomData};
+
+use crate::{
+    hosting::{MemInst, RegionValue, WasmType},
+    FromValue, Trap, TrapCause, ValType, Value,
+};
+
+/// A pointer into a 32-bit guest memory, to a value of type `T`.
+///
+/// Host functions receive pointers from the guest as plain `i32`s. Wrapping them in a
+/// `WasmPtr` lets the host read and write through them without working out byte offsets by
+/// hand. Every access is checked: a pointer that isn't aligned to the size of `T` traps, as
+/// does one whose target isn't entirely within the memory. `WasmPtr` is a [`WasmType`], so it
+/// can be taken directly as a parameter of a closure passed to
+/// [`Host::define`](crate::hosting::Host::define).
+pub struct WasmPtr<T> {
+    offset: u32,
+    typ: PhantomData<fn() -> T>,
+}
+
+impl<T: RegionValue> WasmPtr<T> {
+    pub fn new(offset: u32) -> WasmPtr<T> {
+        WasmPtr {
+            offset,
+            typ: PhantomData,
+        }
+    }
+
+    /// Gets the address the pointer refers to.
+    pub fn offset(&self) -> u32 {
+        self.offset
+    }
+
+    pub fn is_null(&self) -> bool {
+        self.offset == 0
+    }
+
+    /// Gets a pointer `count` values of type `T` further along, or `None` if that would
+    /// overflow the address space.
+    pub fn offset_by(self, count: u32) -> Option<WasmPtr<T>> {
+        count
+            .checked_mul(T::SIZE as u32)
+            .and_then(|n| self.offset.checked_add(n))
+            .map(WasmPtr::new)
+    }
+
+    /// Reinterprets the pointer as one to a value of type `U`.
+    pub fn cast<U: RegionValue>(self) -> WasmPtr<U> {
+        WasmPtr::new(self.offset)
+    }
+
+    /// Reads the little-endian value the pointer refers to.
+    pub fn read(&self, mem: &MemInst) -> Result<T, Trap> {
+        let offset = self.aligned()?;
+        let mut bytes = [0; 8];
+        mem.read(offset, &mut bytes[..T::SIZE])?;
+        Ok(T::from_le(&bytes[..T::SIZE]))
+    }
+
+    /// Writes `value`, little-endian, where the pointer refers to.
+    pub fn write(&self, mem: &MemInst, value: T) -> Result<(), Trap> {
+        let offset = self.aligned()?;
+        let mut bytes = [0; 8];
+        value.to_le(&mut bytes[..T::SIZE]);
+        mem.write(offset, &bytes[..T::SIZE])
+    }
+
+    /// Reads the array of `len` values starting where the pointer refers to.
+    pub fn read_array(&self, mem: &MemInst, len: u32) -> Result<Vec<T>, Trap> {
+        let offset = self.aligned()?;
+        let size = (len as usize)
+            .checked_mul(T::SIZE)
+            .ok_or(TrapCause::OutOfBoundsMemoryAccess)?;
+        let mut bytes = vec![0; size];
+        mem.read(offset, &mut bytes)?;
+        Ok(bytes.chunks(T::SIZE).map(T::from_le).collect())
+    }
+
+    /// Writes `values` as an array starting where the pointer refers to. Nothing is written
+    /// if the array doesn't fit in the memory.
+    pub fn write_array(&self, mem: &MemInst, values: &[T]) -> Result<(), Trap> {
+        let offset = self.aligned()?;
+        let mut bytes = vec![0; values.len() * T::SIZE];
+        for (value, chunk) in values.iter().zip(bytes.chunks_mut(T::SIZE)) {
+            value.to_le(chunk);
+        }
+        mem.write(offset, &bytes)
+    }
+
+    /// Gets the pointer's address, if it's aligned for values of type `T`.
+    fn aligned(&self) -> Result<usize, Trap> {
+        if (self.offset as usize).is_multiple_of(T::SIZE) {
+            Ok(self.offset as usize)
+        } else {
+            Err(format!("Unaligned pointer: 0x{:08x}", self.offset).into())
+        }
+    }
+}
+
+impl WasmPtr<u8> {
+    /// Reads the NUL-terminated string the pointer refers to.
+    pub fn read_cstr(&self, mem: &MemInst) -> Result<CString, Trap> {
+        mem.read_cstr(self.offset as usize)
+    }
+
+    /// Reads the `len`-byte UTF-8 string the pointer refers to.
+    pub fn read_utf8(&self, mem: &MemInst, len: u32) -> Result<String, Trap> {
+        mem.read_utf8(self.offset as usize, len as usize)
+    }
+}
+
+// These are implemented by hand, as deriving them would require `T` to implement them too
+impl<T> Clone for WasmPtr<T> {
+    fn clone(&self) -> WasmPtr<T> {
+        *self
+    }
+}
+
+impl<T> Copy for WasmPtr<T> {}
+
+impl<T> PartialEq for WasmPtr<T> {
+    fn eq(&self, other: &WasmPtr<T>) -> bool {
+        self.offset == other.offset
+    }
+}
+
+impl<T> Eq for WasmPtr<T> {}
+
+impl<T> fmt::Debug for WasmPtr<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "WasmPtr(0x{:08x})", self.offset)
+    }
+}
+
+impl<T: RegionValue> FromValue for WasmPtr<T> {
+    fn from_value(v: Value) -> Result<Self, TrapCause> {
+        u32::from_value(v).map(WasmPtr::new)
+    }
+}
+
+impl<T> From<WasmPtr<T>> for Value {
+    fn from(ptr: WasmPtr<T>) -> Value {
+        Value::I32(ptr.offset)
+    }
+}
+
+impl<T: RegionValue> WasmType for WasmPtr<T> {
+    fn val_type() -> ValType {
+        ValType::I32
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        builder::{FuncBuilder, ModuleBuilder},
+        hosting::{Host, MemInst, ModuleAddr, WasmPtr},
+        HeapBackend, Instruction, Trap, TrapCause, ValType, Value,
+    };
+
+    #[test]
+    pub fn guest_pointers_are_checked() {
+        let mem = MemInst::new(ModuleAddr::new(1).unwrap(), 32, None, &HeapBackend).unwrap();
+        let ptr = WasmPtr::<u32>::new(8);
+        ptr.write(&mem, 0x0102_0304).unwrap();
+        assert_eq!(0x0102_0304, ptr.read(&mem).unwrap());
+        assert_eq!(Some(WasmPtr::new(16)), ptr.offset_by(2));
+        assert_eq!(None, ptr.offset_by(u32::MAX));
+
+        ptr.offset_by(1)
+            .unwrap()
+            .write_array(&mem, &[5, 6])
+            .unwrap();
+        assert_eq!(vec![0x0102_0304, 5, 6], ptr.read_array(&mem, 3).unwrap());
+        assert_eq!(4, ptr.cast::<u8>().read(&mem).unwrap());
+
+        let unaligned = |trap: Trap| matches!(trap.cause(), TrapCause::Other(_));
+        let out_of_bounds = |trap: Trap| matches!(trap.cause(), TrapCause::OutOfBoundsMemoryAccess);
+        assert!(unaligned(WasmPtr::<u32>::new(2).read(&mem).unwrap_err()));
+        assert!(out_of_bounds(
+            WasmPtr::<u64>::new(32).read(&mem).unwrap_err()
+        ));
+        assert!(out_of_bounds(ptr.read_array(&mem, 7).unwrap_err()));
+
+        let text = WasmPtr::<u8>::new(24);
+        text.write_array(&mem, b"hi\0").unwrap();
+        assert_eq!("hi", text.read_cstr(&mem).unwrap().to_str().unwrap());
+        assert_eq!("hi", text.read_utf8(&mem, 2).unwrap());
+
+        // Pointers can be taken directly by host functions
+        let mut host = Host::new();
+        host.define("env", "deref", |ptr: WasmPtr<u32>| ptr.offset())
+            .unwrap();
+        let module = ModuleBuilder::new()
+            .func(
+                FuncBuilder::new()
+                    .import_from("env", "deref")
+                    .param(ValType::I32)
+                    .result(ValType::I32),
+            )
+            .func(
+                FuncBuilder::new()
+                    .export_as("main")
+                    .result(ValType::I32)
+                    .body(vec![
+                        Instruction::I32Const(Value::I32(12)),
+                        Instruction::Call(0),
+                    ]),
+            );
+        let addr = host.instantiate("test", module.build()).unwrap();
+        assert_eq!(
+            vec![Value::I32(12)],
+            host.invoke(addr, "main", &[]).unwrap()
+        );
+    }
+}
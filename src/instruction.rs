@@ -1,8 +1,8 @@
 use std::{fmt, io};
 
-use byteorder::{LittleEndian, ReadBytesExt};
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
 
-use crate::{utils, Error, Value};
+use crate::{utils, Error, SectionOffset, Value};
 
 #[derive(PartialEq, Clone)]
 pub struct BranchTable(Vec<u32>, u32);
@@ -13,6 +13,29 @@ impl BranchTable {
         let else_case = utils::read_leb128_u32(reader)?;
         Ok(BranchTable(branches, else_case))
     }
+
+    pub fn write<W: io::Write>(&self, writer: &mut W) -> io::Result<()> {
+        write_idx(writer, self.0.len() as u32)?;
+        for branch in self.0.iter() {
+            write_idx(writer, *branch)?;
+        }
+        write_idx(writer, self.1)
+    }
+
+    /// Gets the label depth to branch to for the specified operand.
+    pub fn target(&self, idx: u32) -> u32 {
+        self.0.get(idx as usize).cloned().unwrap_or(self.1)
+    }
+
+    /// Gets the label depths of the table's entries, excluding the default.
+    pub fn targets(&self) -> &[u32] {
+        &self.0
+    }
+
+    /// Gets the label depth used when the operand is outside the table.
+    pub fn default_target(&self) -> u32 {
+        self.1
+    }
 }
 
 impl fmt::Display for BranchTable {
@@ -30,9 +53,82 @@ impl fmt::Debug for BranchTable {
     }
 }
 
+/// The lane indices immediate of an `i8x16.shuffle` instruction. Each lane selects one of
+/// the 32 bytes of the two operands, which `read` checks.
+#[derive(PartialEq, Clone)]
+pub struct ShuffleLanes([u8; 16]);
+
+impl ShuffleLanes {
+    pub fn read<R: io::Read>(reader: &mut R) -> Result<ShuffleLanes, Error> {
+        let mut lanes = [0u8; 16];
+        reader.read_exact(&mut lanes)?;
+        match lanes.iter().find(|lane| **lane >= 32) {
+            Some(lane) => Err(Error::InvalidLaneIndex {
+                lane: *lane,
+                at: SectionOffset::unknown(),
+            }),
+            None => Ok(ShuffleLanes(lanes)),
+        }
+    }
+
+    pub fn write<W: io::Write>(&self, writer: &mut W) -> io::Result<()> {
+        writer.write_all(&self.0)
+    }
+
+    pub fn lanes(&self) -> &[u8; 16] {
+        &self.0
+    }
+}
+
+impl fmt::Display for ShuffleLanes {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (i, lane) in self.0.iter().enumerate() {
+            if i > 0 {
+                write!(f, " ")?;
+            }
+            write!(f, "{}", lane)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Debug for ShuffleLanes {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(self, f)
+    }
+}
+
 include!(concat!(env!("OUT_DIR"), "/instructions.g.rs"));
 
 impl Instruction {
+    /// Reads a single instruction. Single-byte opcodes are decoded through one table, while a
+    /// prefix byte selects the table of its namespace, indexed by the sub-opcode that follows.
+    pub fn read<R: io::Read>(reader: &mut R) -> Result<Instruction, Error> {
+        let opcode = reader.read_u8()?;
+        if let Some(decode) = DECODERS[opcode as usize] {
+            return decode(reader);
+        }
+
+        match NAMESPACES.iter().find(|(prefix, _)| *prefix == opcode) {
+            Some((prefix, decoders)) => {
+                let opcode = utils::read_leb128_u32(reader)?;
+                match decoders.get(opcode as usize) {
+                    Some(Some(decode)) => decode(reader),
+                    _ => Err(Error::UnknownOpcode {
+                        prefix: Some(*prefix),
+                        opcode,
+                        at: SectionOffset::unknown(),
+                    }),
+                }
+            }
+            None => Err(Error::UnknownOpcode {
+                prefix: None,
+                opcode: opcode as u32,
+                at: SectionOffset::unknown(),
+            }),
+        }
+    }
+
     pub fn read_sequence<R: io::Read>(reader: &mut R) -> Result<Vec<Instruction>, Error> {
         let mut insts = Vec::new();
         let mut blocks = 1;
@@ -53,17 +149,23 @@ impl Instruction {
 
 #[inline]
 fn read_idx<R: io::Read>(reader: &mut R) -> Result<u32, Error> {
-    Ok(utils::read_leb128_u32(reader)?)
+    utils::read_leb128_u32(reader)
+}
+
+/// Reads a memory access offset, which is 64 bits wide to accommodate 64-bit memories.
+#[inline]
+fn read_offset<R: io::Read>(reader: &mut R) -> Result<u64, Error> {
+    utils::read_leb128_u64(reader)
 }
 
 #[inline]
 fn read_i32<R: io::Read>(reader: &mut R) -> Result<Value, Error> {
-    Ok(Value::I32(utils::read_leb128_s(reader)?))
+    Ok(Value::I32(utils::read_leb128_s32(reader)? as u32))
 }
 
 #[inline]
 fn read_i64<R: io::Read>(reader: &mut R) -> Result<Value, Error> {
-    Ok(Value::I64(utils::read_leb128_s(reader)?))
+    Ok(Value::I64(utils::read_leb128_s64(reader)? as u64))
 }
 
 #[inline]
@@ -77,3 +179,104 @@ fn read_f64<R: io::Read>(reader: &mut R) -> Result<Value, Error> {
     let bits = reader.read_u64::<LittleEndian>()?;
     Ok(Value::F64(f64::from_bits(bits)))
 }
+
+#[inline]
+fn read_v128<R: io::Read>(reader: &mut R) -> Result<Value, Error> {
+    let mut bytes = [0u8; 16];
+    reader.read_exact(&mut bytes)?;
+    Ok(Value::V128(u128::from_le_bytes(bytes)))
+}
+
+/// Writes an opcode, which is followed by a LEB128 sub-opcode if it has a prefix.
+#[inline]
+fn write_opcode<W: io::Write>(writer: &mut W, prefix: Option<u8>, opcode: u32) -> io::Result<()> {
+    match prefix {
+        Some(prefix) => {
+            writer.write_u8(prefix)?;
+            write_idx(writer, opcode)
+        }
+        None => writer.write_u8(opcode as u8),
+    }
+}
+
+#[inline]
+fn write_idx<W: io::Write>(writer: &mut W, idx: u32) -> io::Result<()> {
+    leb128::write::unsigned(writer, idx as u64).map(|_| ())
+}
+
+#[inline]
+fn write_offset<W: io::Write>(writer: &mut W, offset: u64) -> io::Result<()> {
+    leb128::write::unsigned(writer, offset).map(|_| ())
+}
+
+/// Writes the immediate of a constant instruction, whose encoding depends on its type.
+fn write_const<W: io::Write>(writer: &mut W, value: &Value) -> io::Result<()> {
+    match *value {
+        Value::I32(v) => leb128::write::signed(writer, v as i32 as i64).map(|_| ()),
+        Value::I64(v) => leb128::write::signed(writer, v as i64).map(|_| ()),
+        Value::F32(v) => writer.write_u32::<LittleEndian>(v.to_bits()),
+        Value::F64(v) => writer.write_u64::<LittleEndian>(v.to_bits()),
+        Value::V128(v) => writer.write_all(&v.to_le_bytes()),
+        ref v => Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("{} is not a constant of a numeric type", v),
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn read(bytes: &[u8]) -> Result<Instruction, Error> {
+        Instruction::read(&mut io::Cursor::new(bytes))
+    }
+
+    #[test]
+    pub fn prefixed_opcodes_are_decoded_by_namespace() {
+        assert_eq!(Instruction::I32Add, read(&[0x6A]).unwrap());
+        assert_eq!(
+            Instruction::AtomicFence(0),
+            read(&[0xFE, 0x03, 0x00]).unwrap()
+        );
+
+        // Sub-opcodes are LEB128-encoded, so 0x8C 0x00 is sub-opcode 0x0C
+        assert_eq!(
+            Instruction::V128Const(Value::V128(0)),
+            read(&[0xFD, 0x8C, 0x00, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]).unwrap()
+        );
+    }
+
+    #[test]
+    pub fn unknown_opcodes_report_prefix_and_sub_opcode() {
+        match read(&[0xFF]) {
+            Err(Error::UnknownOpcode {
+                prefix: None,
+                opcode: 0xFF,
+                ..
+            }) => {}
+            x => panic!("Unexpected result: {:?}", x),
+        }
+        match read(&[0xFE, 0xFF, 0x01]) {
+            Err(Error::UnknownOpcode {
+                prefix: Some(0xFE),
+                opcode: 0xFF,
+                ..
+            }) => {}
+            x => panic!("Unexpected result: {:?}", x),
+        }
+    }
+
+    #[test]
+    pub fn shuffle_lanes_must_select_from_the_operands() {
+        let mut bytes = vec![0xFD, 0x0D];
+        bytes.extend(0..16);
+        assert!(read(&bytes).is_ok());
+
+        bytes[17] = 32;
+        match read(&bytes) {
+            Err(Error::InvalidLaneIndex { lane: 32, .. }) => {}
+            x => panic!("Unexpected result: {:?}", x),
+        }
+    }
+}
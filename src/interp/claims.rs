@@ -0,0 +1,171 @@
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+
+use crate::{
+    hosting::{Host, MemInst, ModuleAddr},
+    Trap, TrapCause,
+};
+
+/// The memories a thread has claimed while it runs, so threads running concurrently over
+/// clones of a host never use the same non-shared memory at once.
+///
+/// Claims are taken as functions of each module are entered, and all of them are released
+/// when the outermost invocation on the thread returns, pauses or panics.
+pub(crate) struct MemoryClaims(Arc<Mutex<Held>>);
+
+struct Held {
+    mems: Vec<Arc<MemInst>>,
+    /// The number of invocations running on the thread, including those nested in host
+    /// functions.
+    depth: usize,
+}
+
+/// Marks an invocation as running on a thread until it's dropped, see [`MemoryClaims::enter`].
+pub(crate) struct Entered(Arc<Mutex<Held>>);
+
+impl MemoryClaims {
+    pub fn new() -> MemoryClaims {
+        MemoryClaims(Arc::new(Mutex::new(Held {
+            mems: Vec::new(),
+            depth: 0,
+        })))
+    }
+
+    /// Notes that an invocation started running on the thread. Every claim is released once
+    /// the outermost invocation's guard is dropped, even if it's dropped by a panic unwinding.
+    pub fn enter(&self) -> Entered {
+        lock(&self.0).depth += 1;
+        Entered(self.0.clone())
+    }
+
+    /// Claims the memories of `module`, trapping with [`TrapCause::MemoryInUse`] if another
+    /// thread is running code that uses one of them.
+    pub fn claim(&self, host: &Host, module: ModuleAddr) -> Result<(), Trap> {
+        let id = id(&self.0);
+        let mut held = lock(&self.0);
+        for addr in host.module(module).mems() {
+            let mem = host.mem(*addr);
+            if mem.shared() || held.mems.iter().any(|held| Arc::ptr_eq(held, &mem)) {
+                continue;
+            }
+            if !mem.claim(id) {
+                return Err(TrapCause::MemoryInUse.into());
+            }
+            held.mems.push(mem);
+        }
+        Ok(())
+    }
+}
+
+impl Drop for Entered {
+    fn drop(&mut self) {
+        let id = id(&self.0);
+        let mut held = lock(&self.0);
+        held.depth -= 1;
+        if held.depth == 0 {
+            for mem in held.mems.drain(..) {
+                mem.release(id);
+            }
+        }
+    }
+}
+
+/// Identifies the thread to the memories it claims, by the address of its claims.
+fn id(held: &Arc<Mutex<Held>>) -> usize {
+    &**held as *const Mutex<Held> as usize
+}
+
+/// Locks the claims, even if a panic poisoned the lock, as they're consistent between calls.
+fn lock(held: &Mutex<Held>) -> MutexGuard<'_, Held> {
+    held.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::{Arc, Barrier},
+        thread,
+    };
+
+    use crate::{
+        builder::{FuncBuilder, ModuleBuilder},
+        hosting::Host,
+        module::MemoryType,
+        Error, Instruction, TrapCause, ValType, Value,
+    };
+
+    #[test]
+    pub fn threads_run_concurrently_over_host_clones() {
+        let entered = Arc::new(Barrier::new(2));
+        let release = Arc::new(Barrier::new(2));
+        let mut host = Host::new();
+        {
+            let (entered, release) = (entered.clone(), release.clone());
+            host.define("env", "wait", move || {
+                entered.wait();
+                release.wait();
+            })
+            .unwrap();
+        }
+        let module = ModuleBuilder::new()
+            .mem("memory", MemoryType::new(1, None))
+            .func(FuncBuilder::new().import_from("env", "wait"))
+            .func(
+                FuncBuilder::new()
+                    .export_as("wait")
+                    .body(vec![Instruction::Call(0)]),
+            )
+            .func(
+                FuncBuilder::new()
+                    .export_as("get")
+                    .result(ValType::I32)
+                    .body(vec![Instruction::I32Const(Value::I32(7))]),
+            );
+        let addr = host.instantiate("test", module.build()).unwrap();
+        let fork = host.fork_instance(addr).unwrap();
+
+        let mut worker = host.clone();
+        let waiting = thread::spawn(move || worker.invoke(addr, "wait", &[]).unwrap());
+        entered.wait();
+
+        // The instance's memory is in use, but its fork's isn't
+        match host.invoke(addr, "get", &[]) {
+            Err(Error::Trap(trap)) => assert!(matches!(trap.cause(), TrapCause::MemoryInUse)),
+            res => panic!("expected a trap, got {:?}", res),
+        }
+        assert_eq!(vec![Value::I32(7)], host.invoke(fork, "get", &[]).unwrap());
+
+        release.wait();
+        waiting.join().unwrap();
+        assert_eq!(vec![Value::I32(7)], host.invoke(addr, "get", &[]).unwrap());
+    }
+
+    #[test]
+    pub fn claims_are_released_when_a_call_panics() {
+        let mut host = Host::new();
+        host.define("env", "fail", || -> () {
+            panic!("the host function failed")
+        })
+        .unwrap();
+        let module = ModuleBuilder::new()
+            .mem("memory", MemoryType::new(1, None))
+            .func(FuncBuilder::new().import_from("env", "fail"))
+            .func(
+                FuncBuilder::new()
+                    .export_as("fail")
+                    .body(vec![Instruction::Call(0)]),
+            )
+            .func(
+                FuncBuilder::new()
+                    .export_as("get")
+                    .result(ValType::I32)
+                    .body(vec![Instruction::I32Const(Value::I32(7))]),
+            );
+        let addr = host.instantiate("test", module.build()).unwrap();
+
+        let mut worker = host.clone();
+        let failed = thread::spawn(move || worker.invoke(addr, "fail", &[]));
+        assert!(failed.join().is_err());
+
+        assert_eq!(vec![Value::I32(7)], host.invoke(addr, "get", &[]).unwrap());
+    }
+}
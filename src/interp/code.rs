@@ -0,0 +1,369 @@
+use std::{io, sync::OnceLock};
+
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+
+use crate::{module::FuncBody, Error, Instruction, ValType};
+
+/// An instruction lowered for execution, with the targets of structured control flow resolved
+/// ahead of time so they don't need to be found by scanning the code.
+#[derive(Clone, PartialEq, Debug)]
+pub enum Op {
+    /// A `block`, whose matching `end` is at index `end`.
+    Block {
+        arity: usize,
+        end: usize,
+    },
+    /// A `loop`, which branches back to the instruction after it.
+    Loop,
+    /// An `if`, whose `else` (if any) and matching `end` are at indices `els` and `end`.
+    If {
+        arity: usize,
+        els: Option<usize>,
+        end: usize,
+    },
+    /// An `else`, which continues after the end of its `if` when reached.
+    Else,
+    End,
+    /// A `block`, `loop` or `if` that has no matching `end`, which traps when executed.
+    Unterminated,
+    /// Any other instruction, executed as-is.
+    Inst(Instruction),
+}
+
+impl Op {
+    /// Gets the mnemonic of the instruction the op was lowered from.
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Op::Block { .. } => "block",
+            Op::Loop => "loop",
+            Op::If { .. } => "if",
+            Op::Else => "else",
+            Op::End => "end",
+            Op::Unterminated => "unterminated",
+            Op::Inst(ref inst) => inst.name(),
+        }
+    }
+}
+
+/// The body of a function, lowered once when the function is instantiated.
+///
+/// Lowering maps each instruction to exactly one [`Op`], so instruction indices in the original
+/// body and in the lowered code are the same.
+///
+/// A body that was loaded lazily, and hasn't been decoded yet, is lowered when the function is
+/// first called instead. See [`Module::load_lazy`](crate::module::Module::load_lazy).
+#[derive(Clone, Debug)]
+pub struct Code {
+    locals: Vec<ValType>,
+    ops: OnceLock<Vec<Op>>,
+    /// The body to lower when it's first needed, if it wasn't lowered up front.
+    body: Option<FuncBody>,
+}
+
+impl Code {
+    /// Lowers the body of a function, or arranges for it to be lowered when it's first needed
+    /// if its instructions haven't been decoded yet.
+    pub fn new(body: &FuncBody) -> Code {
+        if !body.is_decoded() {
+            return Code {
+                locals: body.locals().to_vec(),
+                ops: OnceLock::new(),
+                body: Some(body.clone()),
+            };
+        }
+        Code {
+            locals: body.locals().to_vec(),
+            ops: OnceLock::from(Code::lower(body.body())),
+            body: None,
+        }
+    }
+
+    /// Lowers a sequence of instructions that has no locals, such as a constant expression.
+    pub fn from_instructions(code: &[Instruction]) -> Code {
+        Code {
+            locals: Vec::new(),
+            ops: OnceLock::from(Code::lower(code)),
+            body: None,
+        }
+    }
+
+    pub fn locals(&self) -> &[ValType] {
+        &self.locals
+    }
+
+    /// Lowers the body if that was put off, which decodes it. Fails if the body is malformed.
+    pub fn prepare(&self) -> Result<&[Op], Error> {
+        if let Some(ops) = self.ops.get() {
+            return Ok(ops);
+        }
+        let body = self.body.as_ref().expect("code is lowered or has a body");
+        let ops = Code::lower(body.try_body()?);
+        Ok(self.ops.get_or_init(|| ops))
+    }
+
+    /// Gets the lowered code. A malformed body that was loaded lazily has none, see
+    /// [`Code::prepare`].
+    pub fn ops(&self) -> &[Op] {
+        self.prepare().unwrap_or_default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.ops().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ops().is_empty()
+    }
+
+    /// Writes the lowered code, with its resolved targets, in a form [`Code::read`] can load
+    /// without lowering it again.
+    pub fn write<W: io::Write>(&self, writer: &mut W) -> io::Result<()> {
+        writer.write_u32::<LittleEndian>(self.locals.len() as u32)?;
+        for local in self.locals.iter() {
+            local.write(writer)?;
+        }
+
+        writer.write_u32::<LittleEndian>(self.len() as u32)?;
+        for op in self.ops().iter() {
+            match *op {
+                Op::Block { arity, end } => {
+                    writer.write_u8(0)?;
+                    writer.write_u32::<LittleEndian>(arity as u32)?;
+                    writer.write_u32::<LittleEndian>(end as u32)?;
+                }
+                Op::Loop => writer.write_u8(1)?,
+                Op::If { arity, els, end } => {
+                    writer.write_u8(2)?;
+                    writer.write_u32::<LittleEndian>(arity as u32)?;
+                    // An 'else' can't be the first instruction, so zero means there is none
+                    writer.write_u32::<LittleEndian>(els.unwrap_or(0) as u32)?;
+                    writer.write_u32::<LittleEndian>(end as u32)?;
+                }
+                Op::Else => writer.write_u8(3)?,
+                Op::End => writer.write_u8(4)?,
+                Op::Unterminated => writer.write_u8(5)?,
+                Op::Inst(ref inst) => {
+                    writer.write_u8(6)?;
+                    inst.write(writer)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Reads code written by [`Code::write`].
+    pub fn read<R: io::Read>(reader: &mut R) -> Result<Code, Error> {
+        let count = reader.read_u32::<LittleEndian>()?;
+        let mut locals = Vec::new();
+        for _ in 0..count {
+            locals.push(ValType::read(reader)?);
+        }
+
+        let count = reader.read_u32::<LittleEndian>()?;
+        let mut ops = Vec::new();
+        for _ in 0..count {
+            let op = match reader.read_u8()? {
+                0 => Op::Block {
+                    arity: read_index(reader)?,
+                    end: read_index(reader)?,
+                },
+                1 => Op::Loop,
+                2 => Op::If {
+                    arity: read_index(reader)?,
+                    els: Some(read_index(reader)?).filter(|els| *els != 0),
+                    end: read_index(reader)?,
+                },
+                3 => Op::Else,
+                4 => Op::End,
+                5 => Op::Unterminated,
+                6 => Op::Inst(Instruction::read(reader)?),
+                tag => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("unknown lowered op: {}", tag),
+                    )
+                    .into())
+                }
+            };
+            ops.push(op);
+        }
+
+        // The targets that were written aren't trusted, so corrupt code can't jump outside
+        // itself or into the middle of another block
+        Code::resolve_targets(&mut ops);
+        Ok(Code {
+            locals,
+            ops: OnceLock::from(ops),
+            body: None,
+        })
+    }
+
+    /// Resolves the targets of the blocks in lowered code again from its structure, the same
+    /// way [`Code::lower`] does. A `block` or `if` without a matching `end` is left
+    /// unterminated.
+    fn resolve_targets(ops: &mut [Op]) {
+        // The indices of the blocks that are still open, innermost last, and the 'else' of each
+        let mut open: Vec<(usize, Option<usize>)> = Vec::new();
+        for pc in 0..ops.len() {
+            match ops[pc] {
+                Op::Block { .. } | Op::Loop | Op::If { .. } | Op::Unterminated => {
+                    open.push((pc, None))
+                }
+                Op::Else => {
+                    if let Some(&mut (_, ref mut els)) = open.last_mut() {
+                        *els = Some(pc);
+                    }
+                }
+                Op::End => {
+                    if let Some((start, else_pc)) = open.pop() {
+                        match ops[start] {
+                            Op::Block { ref mut end, .. } => *end = pc,
+                            Op::If {
+                                ref mut els,
+                                ref mut end,
+                                ..
+                            } => {
+                                *els = else_pc;
+                                *end = pc;
+                            }
+                            _ => {}
+                        }
+                    }
+                }
+                Op::Inst(_) => {}
+            }
+        }
+        for (start, _) in open {
+            if let Op::Block { .. } | Op::If { .. } = ops[start] {
+                ops[start] = Op::Unterminated;
+            }
+        }
+    }
+
+    fn lower(code: &[Instruction]) -> Vec<Op> {
+        let mut ops = Vec::with_capacity(code.len());
+
+        // The indices of the blocks that are still open, innermost last, and the 'else' of each
+        let mut open: Vec<(usize, Option<usize>)> = Vec::new();
+        for (pc, inst) in code.iter().enumerate() {
+            let op = match *inst {
+                Instruction::Block(_) | Instruction::Loop(_) | Instruction::If(_) => {
+                    open.push((pc, None));
+                    // Replaced when the matching 'end' is reached
+                    Op::Unterminated
+                }
+                Instruction::Else => {
+                    if let Some(&mut (_, ref mut els)) = open.last_mut() {
+                        *els = Some(pc);
+                    }
+                    Op::Else
+                }
+                Instruction::End => {
+                    if let Some((start, els)) = open.pop() {
+                        ops[start] = match code[start] {
+                            Instruction::Block(typ) => Op::Block {
+                                arity: arity(typ),
+                                end: pc,
+                            },
+                            Instruction::If(typ) => Op::If {
+                                arity: arity(typ),
+                                els,
+                                end: pc,
+                            },
+                            _ => Op::Loop,
+                        };
+                    }
+                    Op::End
+                }
+                ref inst => Op::Inst(inst.clone()),
+            };
+            ops.push(op);
+        }
+        ops
+    }
+}
+
+// Lowered code is the same whether it was lowered up front or when it was first needed
+impl PartialEq for Code {
+    fn eq(&self, other: &Code) -> bool {
+        self.locals == other.locals && self.ops() == other.ops()
+    }
+}
+
+fn read_index<R: io::Read>(reader: &mut R) -> io::Result<usize> {
+    Ok(reader.read_u32::<LittleEndian>()? as usize)
+}
+
+fn arity(typ: ValType) -> usize {
+    if typ == ValType::Nil {
+        0
+    } else {
+        1
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Code, Op};
+    use crate::{Instruction::*, ValType, Value};
+
+    #[test]
+    pub fn lowering_resolves_block_targets() {
+        let code = Code::from_instructions(&[
+            Block(ValType::I32),
+            I32Const(Value::I32(1)),
+            If(ValType::Nil),
+            Loop(ValType::Nil),
+            Br(0),
+            End,
+            Else,
+            Nop,
+            End,
+            End,
+            If(ValType::Nil),
+        ]);
+
+        assert_eq!(
+            &[
+                Op::Block { arity: 1, end: 9 },
+                Op::Inst(I32Const(Value::I32(1))),
+                Op::If {
+                    arity: 0,
+                    els: Some(6),
+                    end: 8
+                },
+                Op::Loop,
+                Op::Inst(Br(0)),
+                Op::End,
+                Op::Else,
+                Op::Inst(Nop),
+                Op::End,
+                Op::End,
+                Op::Unterminated,
+            ],
+            code.ops()
+        );
+    }
+
+    #[test]
+    pub fn targets_read_back_are_resolved_again() {
+        let code = Code::from_instructions(&[Block(ValType::Nil), If(ValType::Nil), Nop, End, End]);
+        let mut bytes = Vec::new();
+        code.write(&mut bytes).unwrap();
+
+        // Point the block past the end of the code, and the 'if' at an 'else' it doesn't have
+        bytes[13..17].copy_from_slice(&u32::MAX.to_le_bytes());
+        bytes[22..26].copy_from_slice(&2u32.to_le_bytes());
+        bytes[26..30].copy_from_slice(&4u32.to_le_bytes());
+        let read = Code::read(&mut &bytes[..]).unwrap();
+        assert_eq!(code.ops(), read.ops());
+
+        // Blocks whose 'end' is missing can't be entered
+        let code = Code::from_instructions(&[Block(ValType::Nil), Nop, End]);
+        let mut bytes = Vec::new();
+        code.write(&mut bytes).unwrap();
+        bytes[4..8].copy_from_slice(&2u32.to_le_bytes());
+        let read = Code::read(&mut &bytes[..bytes.len() - 1]).unwrap();
+        assert_eq!(&[Op::Unterminated, Op::Inst(Nop)], read.ops());
+    }
+}
@@ -0,0 +1,180 @@
+use std::time::Instant;
+
+use crate::{hosting::FuncAddr, Trap, Value};
+
+/// The state of an invocation after [`Thread::step`](crate::interp::Thread::step) or
+/// [`Thread::resume`](crate::interp::Thread::resume).
+#[derive(Debug, PartialEq)]
+pub enum StepResult {
+    /// The invocation stopped before the instruction at index `offset` in `func`, and can be
+    /// continued.
+    Paused { func: FuncAddr, offset: usize },
+    /// The invocation trapped, and its frames have been discarded.
+    Trapped(Trap),
+    /// The invocation returned these results.
+    Finished(Vec<Value>),
+    /// An external function suspended the invocation with
+    /// [`Thread::suspend`](crate::interp::Thread::suspend). It stopped before the instruction
+    /// after the call, and can be continued once `resume` allows.
+    Suspended {
+        func: FuncAddr,
+        offset: usize,
+        resume: Suspend,
+    },
+}
+
+/// When an invocation that suspended itself wants to be continued.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Suspend {
+    /// As soon as other work has had a turn.
+    Yield,
+    /// Once this instant has passed.
+    Until(Instant),
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use super::{StepResult, Suspend};
+    use crate::{
+        builder::{FuncBuilder, ModuleBuilder},
+        hosting::Host,
+        interp::Thread,
+        runtime::Env,
+        ValType, Value,
+    };
+
+    #[test]
+    pub fn stepping_pauses_between_instructions() {
+        use crate::Instruction::*;
+
+        let module = ModuleBuilder::new()
+            .func(
+                FuncBuilder::new()
+                    .export_as("double")
+                    .param(ValType::I32)
+                    .result(ValType::I32)
+                    .body(vec![LocalGet(0), Call(1)]),
+            )
+            .func(
+                FuncBuilder::new()
+                    .param(ValType::I32)
+                    .result(ValType::I32)
+                    .body(vec![LocalGet(0), LocalGet(0), I32Add]),
+            )
+            .func(
+                FuncBuilder::new()
+                    .export_as("fail")
+                    .body(vec![Nop, Unreachable]),
+            );
+        let mut host = Host::new();
+        let addr = host.instantiate("test", module.build()).unwrap();
+        let export = |host: &Host, name| host.get_module(addr).unwrap().export_func(name).unwrap();
+        let double = export(&host, "double");
+        let fail = export(&host, "fail");
+        let add = host.get_module(addr).unwrap().funcs()[1];
+
+        let mut thread = Thread::new();
+        thread
+            .start(&host, addr, double, &[Value::I32(21)])
+            .unwrap();
+        assert!(thread.is_paused());
+        assert_eq!(
+            StepResult::Paused {
+                func: double,
+                offset: 1
+            },
+            thread.step(&mut host)
+        );
+        // Steps into calls
+        assert_eq!(
+            StepResult::Paused {
+                func: add,
+                offset: 0
+            },
+            thread.step(&mut host)
+        );
+
+        thread.breakpoints_mut().insert((add, 2));
+        assert_eq!(
+            StepResult::Paused {
+                func: add,
+                offset: 2
+            },
+            thread.resume(&mut host)
+        );
+        assert_eq!(Some(Value::I32(21)), thread.stack().current().peek(0));
+        assert_eq!(
+            StepResult::Finished(vec![Value::I32(42)]),
+            thread.resume(&mut host)
+        );
+        assert!(!thread.is_paused());
+        assert_eq!(0, thread.stack().depth());
+
+        // Traps end the invocation and discard its frames
+        thread.start(&host, addr, fail, &[]).unwrap();
+        assert!(matches!(thread.resume(&mut host), StepResult::Trapped(_)));
+        assert!(!thread.is_paused());
+        assert_eq!(0, thread.stack().depth());
+    }
+
+    #[test]
+    pub fn external_functions_can_suspend_an_invocation() {
+        use crate::Instruction::*;
+
+        let module = ModuleBuilder::new()
+            .func(FuncBuilder::new().import_from("env", "sched_yield"))
+            .func(
+                FuncBuilder::new()
+                    .import_from("env", "sleep_ms")
+                    .param(ValType::I32),
+            )
+            .func(
+                FuncBuilder::new()
+                    .export_as("main")
+                    .result(ValType::I32)
+                    .body(vec![
+                        Call(0),
+                        I32Const(Value::I32(1)),
+                        Call(1),
+                        I32Const(Value::I32(7)),
+                    ]),
+            );
+        let mut host = Host::new();
+        host.external(Env::new()).unwrap();
+        let addr = host.instantiate("test", module.build()).unwrap();
+        let main = host.get_module(addr).unwrap().export_func("main").unwrap();
+
+        let mut thread = Thread::new();
+        thread.start(&host, addr, main, &[]).unwrap();
+        assert_eq!(
+            StepResult::Suspended {
+                func: main,
+                offset: 1,
+                resume: Suspend::Yield
+            },
+            thread.resume(&mut host)
+        );
+        let before = Instant::now();
+        match thread.resume(&mut host) {
+            StepResult::Suspended {
+                offset: 3,
+                resume: Suspend::Until(until),
+                ..
+            } => assert!(until > before),
+            res => panic!("unexpected {:?}", res),
+        }
+        assert_eq!(
+            StepResult::Finished(vec![Value::I32(7)]),
+            thread.resume(&mut host)
+        );
+
+        // Without a scheduler, the thread blocks instead
+        assert_eq!(
+            vec![Value::I32(7)],
+            thread.call(&mut host, addr, main, &[]).unwrap()
+        );
+        assert!(before.elapsed() >= Duration::from_millis(1));
+    }
+}
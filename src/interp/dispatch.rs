@@ -0,0 +1,72 @@
+/// How the interpreter finds the code that executes each instruction.
+///
+/// Both strategies are always available, so they can be compared in the same build (see
+/// `benches/dispatch.rs`). Threads use [`Dispatch::default`], which is [`Dispatch::Match`]
+/// unless the crate is built with the `table-dispatch` feature.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Dispatch {
+    /// Match on the instruction, so the compiler decides how to branch.
+    Match,
+    /// Index a table of executors by opcode, which is one indirect call for any instruction.
+    Table,
+}
+
+impl Default for Dispatch {
+    #[cfg(not(feature = "table-dispatch"))]
+    fn default() -> Dispatch {
+        Dispatch::Match
+    }
+
+    #[cfg(feature = "table-dispatch")]
+    fn default() -> Dispatch {
+        Dispatch::Table
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Dispatch;
+    use crate::{
+        builder::{FuncBuilder, ModuleBuilder},
+        hosting::Host,
+        interp::Thread,
+        module::MemoryType,
+        ValType, Value,
+    };
+
+    #[test]
+    pub fn dispatch_strategies_agree() {
+        use crate::Instruction::*;
+
+        // Stores the argument, then loads it back twice and multiplies
+        let module = ModuleBuilder::new()
+            .mem("memory", MemoryType::new(1, None))
+            .func(
+                FuncBuilder::new()
+                    .export_as("main")
+                    .param(ValType::I32)
+                    .result(ValType::I64)
+                    .body(vec![
+                        I32Const(Value::I32(16)),
+                        LocalGet(0),
+                        I32Store(2, 0),
+                        Nop,
+                        I32Const(Value::I32(16)),
+                        I64Load32U(2, 0),
+                        I32Const(Value::I32(16)),
+                        I64Load32U(2, 0),
+                        I64Mul,
+                    ]),
+            );
+        let mut host = Host::new();
+        let addr = host.instantiate("test", module.build()).unwrap();
+        let func = host.get_module(addr).unwrap().export_func("main").unwrap();
+
+        for dispatch in [Dispatch::Match, Dispatch::Table].iter() {
+            let mut thread = Thread::new();
+            thread.set_dispatch(*dispatch);
+            let res = thread.call(&mut host, addr, func, &[Value::I32(12)]);
+            assert_eq!(Ok(vec![Value::I64(144)]), res);
+        }
+    }
+}
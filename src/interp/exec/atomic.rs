@@ -0,0 +1,324 @@
+use std::time::Duration;
+
+use crate::{
+    hosting::{Host, MemInst},
+    interp::{exec::memory, Thread},
+    Instruction, Trap, TrapCause,
+};
+
+pub fn exec(thread: &mut Thread, host: &mut Host, inst: &Instruction) -> Result<(), Trap> {
+    use crate::Instruction::*;
+
+    match *inst {
+        MemoryAtomicNotify(_, offset) => {
+            let count = thread.stack_mut().pop_as::<u32>()?;
+            let mem = memory::current_memory(thread, host)?;
+            let addr = memory::pop_address(thread, &mem)?;
+            let (start, _) = atomic_range(&mem, addr, offset, 4)?;
+            thread.stack_mut().push(mem.notify(start, count));
+            Ok(())
+        }
+        MemoryAtomicWait32(_, offset) => {
+            let timeout = thread.stack_mut().pop_as::<i64>()?;
+            let expected = thread.stack_mut().pop_as::<u32>()? as u64;
+            wait(thread, host, offset, 4, expected, timeout)
+        }
+        MemoryAtomicWait64(_, offset) => {
+            let timeout = thread.stack_mut().pop_as::<i64>()?;
+            let expected = thread.stack_mut().pop_as::<u64>()?;
+            wait(thread, host, offset, 8, expected, timeout)
+        }
+        // Every atomic access is sequentially consistent already, so there's nothing to fence.
+        AtomicFence(_) => Ok(()),
+
+        I32AtomicLoad(_, offset) => load(thread, host, offset, 4, false),
+        I64AtomicLoad(_, offset) => load(thread, host, offset, 8, true),
+        I32AtomicLoad8U(_, offset) => load(thread, host, offset, 1, false),
+        I32AtomicLoad16U(_, offset) => load(thread, host, offset, 2, false),
+        I64AtomicLoad8U(_, offset) => load(thread, host, offset, 1, true),
+        I64AtomicLoad16U(_, offset) => load(thread, host, offset, 2, true),
+        I64AtomicLoad32U(_, offset) => load(thread, host, offset, 4, true),
+
+        I32AtomicStore(_, offset) => store(thread, host, offset, 4, false),
+        I64AtomicStore(_, offset) => store(thread, host, offset, 8, true),
+        I32AtomicStore8(_, offset) => store(thread, host, offset, 1, false),
+        I32AtomicStore16(_, offset) => store(thread, host, offset, 2, false),
+        I64AtomicStore8(_, offset) => store(thread, host, offset, 1, true),
+        I64AtomicStore16(_, offset) => store(thread, host, offset, 2, true),
+        I64AtomicStore32(_, offset) => store(thread, host, offset, 4, true),
+
+        I32AtomicRmwAdd(_, offset) => rmw(thread, host, offset, 4, false, u64::wrapping_add),
+        I64AtomicRmwAdd(_, offset) => rmw(thread, host, offset, 8, true, u64::wrapping_add),
+        I32AtomicRmw8AddU(_, offset) => rmw(thread, host, offset, 1, false, u64::wrapping_add),
+        I32AtomicRmw16AddU(_, offset) => rmw(thread, host, offset, 2, false, u64::wrapping_add),
+        I64AtomicRmw8AddU(_, offset) => rmw(thread, host, offset, 1, true, u64::wrapping_add),
+        I64AtomicRmw16AddU(_, offset) => rmw(thread, host, offset, 2, true, u64::wrapping_add),
+        I64AtomicRmw32AddU(_, offset) => rmw(thread, host, offset, 4, true, u64::wrapping_add),
+
+        I32AtomicRmwSub(_, offset) => rmw(thread, host, offset, 4, false, u64::wrapping_sub),
+        I64AtomicRmwSub(_, offset) => rmw(thread, host, offset, 8, true, u64::wrapping_sub),
+        I32AtomicRmw8SubU(_, offset) => rmw(thread, host, offset, 1, false, u64::wrapping_sub),
+        I32AtomicRmw16SubU(_, offset) => rmw(thread, host, offset, 2, false, u64::wrapping_sub),
+        I64AtomicRmw8SubU(_, offset) => rmw(thread, host, offset, 1, true, u64::wrapping_sub),
+        I64AtomicRmw16SubU(_, offset) => rmw(thread, host, offset, 2, true, u64::wrapping_sub),
+        I64AtomicRmw32SubU(_, offset) => rmw(thread, host, offset, 4, true, u64::wrapping_sub),
+
+        I32AtomicRmwAnd(_, offset) => rmw(thread, host, offset, 4, false, |l, r| l & r),
+        I64AtomicRmwAnd(_, offset) => rmw(thread, host, offset, 8, true, |l, r| l & r),
+        I32AtomicRmw8AndU(_, offset) => rmw(thread, host, offset, 1, false, |l, r| l & r),
+        I32AtomicRmw16AndU(_, offset) => rmw(thread, host, offset, 2, false, |l, r| l & r),
+        I64AtomicRmw8AndU(_, offset) => rmw(thread, host, offset, 1, true, |l, r| l & r),
+        I64AtomicRmw16AndU(_, offset) => rmw(thread, host, offset, 2, true, |l, r| l & r),
+        I64AtomicRmw32AndU(_, offset) => rmw(thread, host, offset, 4, true, |l, r| l & r),
+
+        I32AtomicRmwOr(_, offset) => rmw(thread, host, offset, 4, false, |l, r| l | r),
+        I64AtomicRmwOr(_, offset) => rmw(thread, host, offset, 8, true, |l, r| l | r),
+        I32AtomicRmw8OrU(_, offset) => rmw(thread, host, offset, 1, false, |l, r| l | r),
+        I32AtomicRmw16OrU(_, offset) => rmw(thread, host, offset, 2, false, |l, r| l | r),
+        I64AtomicRmw8OrU(_, offset) => rmw(thread, host, offset, 1, true, |l, r| l | r),
+        I64AtomicRmw16OrU(_, offset) => rmw(thread, host, offset, 2, true, |l, r| l | r),
+        I64AtomicRmw32OrU(_, offset) => rmw(thread, host, offset, 4, true, |l, r| l | r),
+
+        I32AtomicRmwXor(_, offset) => rmw(thread, host, offset, 4, false, |l, r| l ^ r),
+        I64AtomicRmwXor(_, offset) => rmw(thread, host, offset, 8, true, |l, r| l ^ r),
+        I32AtomicRmw8XorU(_, offset) => rmw(thread, host, offset, 1, false, |l, r| l ^ r),
+        I32AtomicRmw16XorU(_, offset) => rmw(thread, host, offset, 2, false, |l, r| l ^ r),
+        I64AtomicRmw8XorU(_, offset) => rmw(thread, host, offset, 1, true, |l, r| l ^ r),
+        I64AtomicRmw16XorU(_, offset) => rmw(thread, host, offset, 2, true, |l, r| l ^ r),
+        I64AtomicRmw32XorU(_, offset) => rmw(thread, host, offset, 4, true, |l, r| l ^ r),
+
+        I32AtomicRmwXchg(_, offset) => rmw(thread, host, offset, 4, false, |_, r| r),
+        I64AtomicRmwXchg(_, offset) => rmw(thread, host, offset, 8, true, |_, r| r),
+        I32AtomicRmw8XchgU(_, offset) => rmw(thread, host, offset, 1, false, |_, r| r),
+        I32AtomicRmw16XchgU(_, offset) => rmw(thread, host, offset, 2, false, |_, r| r),
+        I64AtomicRmw8XchgU(_, offset) => rmw(thread, host, offset, 1, true, |_, r| r),
+        I64AtomicRmw16XchgU(_, offset) => rmw(thread, host, offset, 2, true, |_, r| r),
+        I64AtomicRmw32XchgU(_, offset) => rmw(thread, host, offset, 4, true, |_, r| r),
+
+        I32AtomicRmwCmpxchg(_, offset) => cmpxchg(thread, host, offset, 4, false),
+        I64AtomicRmwCmpxchg(_, offset) => cmpxchg(thread, host, offset, 8, true),
+        I32AtomicRmw8CmpxchgU(_, offset) => cmpxchg(thread, host, offset, 1, false),
+        I32AtomicRmw16CmpxchgU(_, offset) => cmpxchg(thread, host, offset, 2, false),
+        I64AtomicRmw8CmpxchgU(_, offset) => cmpxchg(thread, host, offset, 1, true),
+        I64AtomicRmw16CmpxchgU(_, offset) => cmpxchg(thread, host, offset, 2, true),
+        I64AtomicRmw32CmpxchgU(_, offset) => cmpxchg(thread, host, offset, 4, true),
+
+        ref x => {
+            Err(Trap::new(TrapCause::Unimplemented)
+                .with_message(format!("Not yet implemented: {}", x)))
+        }
+    }
+}
+
+/// Computes the byte range of an atomic access, which must be naturally aligned.
+fn atomic_range(mem: &MemInst, addr: u64, offset: u64, len: usize) -> Result<(usize, usize), Trap> {
+    let (start, end) = memory::effective_range(mem, addr, offset, len)?;
+    if start % len != 0 {
+        Err(TrapCause::UnalignedAtomic.into())
+    } else {
+        Ok((start, end))
+    }
+}
+
+fn read_le(bytes: &[u8]) -> u64 {
+    let mut buf = [0u8; 8];
+    buf[..bytes.len()].copy_from_slice(bytes);
+    u64::from_le_bytes(buf)
+}
+
+fn write_le(bytes: &mut [u8], val: u64) {
+    let len = bytes.len();
+    bytes.copy_from_slice(&val.to_le_bytes()[..len]);
+}
+
+/// Pops an `i32` or `i64` operand, zero-extended to 64 bits.
+fn pop_operand(thread: &mut Thread, is64: bool) -> Result<u64, Trap> {
+    if is64 {
+        Ok(thread.stack_mut().pop_as::<u64>()?)
+    } else {
+        Ok(thread.stack_mut().pop_as::<u32>()? as u64)
+    }
+}
+
+fn push_result(thread: &mut Thread, is64: bool, val: u64) {
+    if is64 {
+        thread.stack_mut().push(val);
+    } else {
+        thread.stack_mut().push(val as u32);
+    }
+}
+
+fn load(thread: &mut Thread, host: &Host, offset: u64, len: usize, is64: bool) -> Result<(), Trap> {
+    let mem = memory::current_memory(thread, host)?;
+    let addr = memory::pop_address(thread, &mem)?;
+    let (start, end) = atomic_range(&mem, addr, offset, len)?;
+    let val = mem.atomically(start, end - start, |data| read_le(data))?;
+    push_result(thread, is64, val);
+    Ok(())
+}
+
+fn store(
+    thread: &mut Thread,
+    host: &Host,
+    offset: u64,
+    len: usize,
+    is64: bool,
+) -> Result<(), Trap> {
+    let val = pop_operand(thread, is64)?;
+    let mem = memory::current_memory(thread, host)?;
+    let addr = memory::pop_address(thread, &mem)?;
+    let (start, end) = atomic_range(&mem, addr, offset, len)?;
+    mem.atomically(start, end - start, |data| write_le(data, val))
+}
+
+/// Atomically replaces the value in memory with `op(old, operand)` and pushes the old value.
+fn rmw<F: Fn(u64, u64) -> u64>(
+    thread: &mut Thread,
+    host: &Host,
+    offset: u64,
+    len: usize,
+    is64: bool,
+    op: F,
+) -> Result<(), Trap> {
+    let operand = pop_operand(thread, is64)?;
+    let mem = memory::current_memory(thread, host)?;
+    let addr = memory::pop_address(thread, &mem)?;
+    let (start, end) = atomic_range(&mem, addr, offset, len)?;
+    let old = mem.atomically(start, end - start, |data| {
+        let old = read_le(data);
+        write_le(data, op(old, operand));
+        old
+    })?;
+    push_result(thread, is64, old);
+    Ok(())
+}
+
+fn cmpxchg(
+    thread: &mut Thread,
+    host: &Host,
+    offset: u64,
+    len: usize,
+    is64: bool,
+) -> Result<(), Trap> {
+    let replacement = pop_operand(thread, is64)?;
+    let expected = pop_operand(thread, is64)?;
+    let mem = memory::current_memory(thread, host)?;
+    let addr = memory::pop_address(thread, &mem)?;
+    let (start, end) = atomic_range(&mem, addr, offset, len)?;
+
+    // The expected value is compared after wrapping it to the access width.
+    let mask = u64::MAX >> (64 - len * 8);
+    let old = mem.atomically(start, end - start, |data| {
+        let old = read_le(data);
+        if old == expected & mask {
+            write_le(data, replacement);
+        }
+        old
+    })?;
+    push_result(thread, is64, old);
+    Ok(())
+}
+
+fn wait(
+    thread: &mut Thread,
+    host: &Host,
+    offset: u64,
+    len: usize,
+    expected: u64,
+    timeout: i64,
+) -> Result<(), Trap> {
+    let mem = memory::current_memory(thread, host)?;
+    let addr = memory::pop_address(thread, &mem)?;
+    if !mem.shared() {
+        return Err(TrapCause::ExpectedSharedMemory.into());
+    }
+    let (start, end) = atomic_range(&mem, addr, offset, len)?;
+
+    // A negative timeout means "wait forever"
+    let timeout = if timeout < 0 {
+        None
+    } else {
+        Some(Duration::from_nanos(timeout as u64))
+    };
+    let res = mem.wait(
+        start,
+        end - start,
+        |data| read_le(data) == expected,
+        timeout,
+    )?;
+    thread.stack_mut().push(res as u32);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        builder::{FuncBuilder, ModuleBuilder},
+        interp::{exec::test_util::run_body, Thread},
+        module::{Expr, Global, GlobalType, MemoryType},
+        Instruction, Trap, ValType, Value,
+    };
+
+    fn run(mem: MemoryType, body: Vec<Instruction>) -> Result<Vec<Value>, Trap> {
+        // The "scratch" global gives tests somewhere to discard intermediate results
+        let scratch = Global::new(
+            GlobalType::new(ValType::I32, true),
+            Expr::new(vec![Instruction::I32Const(Value::I32(0))]),
+        );
+        let module = ModuleBuilder::new()
+            .mem("memory", mem)
+            .global("scratch", scratch);
+        let func = FuncBuilder::new().result(ValType::I32).body(body);
+        run_body(module, func, &mut Thread::new())
+    }
+
+    fn i32(v: u32) -> Instruction {
+        Instruction::I32Const(Value::I32(v))
+    }
+
+    #[test]
+    pub fn rmw_returns_old_value_and_cmpxchg_compares_at_width() {
+        let res = run(
+            MemoryType::new(1, None),
+            vec![
+                i32(8),
+                i32(0x1FF),
+                Instruction::I32AtomicStore(2, 0),
+                // Only the low byte of the expected value participates in the comparison
+                i32(8),
+                i32(0x7FF),
+                i32(0x42),
+                Instruction::I32AtomicRmw8CmpxchgU(0, 0),
+                Instruction::GlobalSet(0),
+                i32(8),
+                i32(1),
+                Instruction::I32AtomicRmwAdd(2, 0),
+            ],
+        );
+        assert_eq!(Ok(vec![Value::I32(0x142)]), res);
+    }
+
+    #[test]
+    pub fn unaligned_atomic_access_traps() {
+        let res = run(
+            MemoryType::new(1, None),
+            vec![i32(2), Instruction::I32AtomicLoad(2, 0)],
+        );
+        assert_eq!("unaligned atomic", res.unwrap_err().cause().message());
+    }
+
+    #[test]
+    pub fn wait_requires_shared_memory_and_times_out() {
+        let wait = vec![
+            i32(0),
+            i32(0),
+            Instruction::I64Const(Value::I64(1000)),
+            Instruction::MemoryAtomicWait32(2, 0),
+        ];
+        let res = run(MemoryType::new(1, None), wait.clone());
+        assert_eq!("expected shared memory", res.unwrap_err().cause().message());
+
+        let res = run(MemoryType::new_shared(1, 1), wait);
+        assert_eq!(Ok(vec![Value::I32(2)]), res);
+    }
+}
@@ -0,0 +1,337 @@
+use crate::{
+    hosting::{FuncAddr, Host},
+    interp::{exec::Flow, Label, Op, Thread},
+    IndexSpace, Instruction, Trap, TrapCause,
+};
+
+/// Executes the structured control instruction at `code[pc]`, using the targets resolved when
+/// the code was lowered.
+pub fn exec_structured(thread: &mut Thread, op: &Op, pc: usize) -> Result<Flow, Trap> {
+    match *op {
+        Op::Block { arity, end } => {
+            let height = thread.stack().current().height();
+            let label = Label::block(arity, height, end + 1);
+            thread.stack_mut().current_mut().push_label(label);
+            Ok(Flow::Next)
+        }
+        Op::Loop => {
+            let height = thread.stack().current().height();
+            let label = Label::looping(height, pc + 1);
+            thread.stack_mut().current_mut().push_label(label);
+            Ok(Flow::Next)
+        }
+        Op::If { arity, els, end } => {
+            let cond = thread.stack_mut().pop_as::<u32>()?;
+            let height = thread.stack().current().height();
+            let label = Label::block(arity, height, end + 1);
+            match (cond, els) {
+                (0, None) => Ok(Flow::Jump(end + 1)),
+                (0, Some(els)) => {
+                    thread.stack_mut().current_mut().push_label(label);
+                    Ok(Flow::Jump(els + 1))
+                }
+                _ => {
+                    thread.stack_mut().current_mut().push_label(label);
+                    Ok(Flow::Next)
+                }
+            }
+        }
+        // Reaching an 'else' means the 'then' arm has completed, so skip to the end of the 'if'
+        Op::Else => match thread.stack_mut().current_mut().pop_label() {
+            Some(label) => Ok(Flow::Jump(label.target())),
+            None => {
+                Err(Trap::new(TrapCause::InvalidCode).with_message("'else' without matching 'if'."))
+            }
+        },
+        Op::End => match thread.stack_mut().current_mut().pop_label() {
+            Some(_) => Ok(Flow::Next),
+            None => {
+                Err(Trap::new(TrapCause::InvalidCode).with_message("'end' without matching block."))
+            }
+        },
+        Op::Unterminated => {
+            Err(Trap::new(TrapCause::InvalidCode).with_message("Block has no matching 'end'."))
+        }
+        Op::Inst(ref x) => Err(Trap::new(TrapCause::InvalidCode)
+            .with_message(format!("Not a structured control instruction: {}", x))),
+    }
+}
+
+/// Executes a control instruction that branches, returns or calls.
+pub fn exec(thread: &mut Thread, host: &mut Host, inst: &Instruction) -> Result<Flow, Trap> {
+    use crate::Instruction::*;
+
+    match *inst {
+        Unreachable => Err(TrapCause::Unreachable.into()),
+        Br(depth) => branch(thread, host, depth),
+        BrIf(depth) => {
+            if thread.stack_mut().pop_as::<u32>()? != 0 {
+                branch(thread, host, depth)
+            } else {
+                Ok(Flow::Next)
+            }
+        }
+        BrTable(ref table) => {
+            let idx = thread.stack_mut().pop_as::<u32>()?;
+            branch(thread, host, table.target(idx))
+        }
+        Return => ret(thread, host),
+        Call(func_idx) => {
+            let module_addr = thread.stack().current().frame().module();
+            match host.resolve_func(module_addr, func_idx as usize) {
+                Some(func) => Ok(Flow::Call(func)),
+                None => Err(TrapCause::UnknownIndex {
+                    space: IndexSpace::Function,
+                    index: func_idx as usize,
+                }
+                .into()),
+            }
+        }
+        CallIndirect(type_idx, table_idx) => {
+            let func = resolve_indirect(thread, host, type_idx, table_idx)?;
+            Ok(Flow::Call(func))
+        }
+        ReturnCall(func_idx) => {
+            let module_addr = thread.stack().current().frame().module();
+            match host.resolve_func(module_addr, func_idx as usize) {
+                Some(func) => Ok(Flow::TailCall(func)),
+                None => Err(TrapCause::UnknownIndex {
+                    space: IndexSpace::Function,
+                    index: func_idx as usize,
+                }
+                .into()),
+            }
+        }
+        ReturnCallIndirect(type_idx, table_idx) => {
+            let func = resolve_indirect(thread, host, type_idx, table_idx)?;
+            Ok(Flow::TailCall(func))
+        }
+        ref x => Err(Trap::new(TrapCause::InvalidCode)
+            .with_message(format!("Not a control instruction: {}", x))),
+    }
+}
+
+/// Pops the element index for a `call_indirect` and resolves the function it refers to,
+/// checking that its signature matches the type with index `type_idx`.
+pub fn resolve_indirect(
+    thread: &mut Thread,
+    host: &Host,
+    type_idx: u32,
+    table_idx: u32,
+) -> Result<FuncAddr, Trap> {
+    let elem_idx = thread.stack_mut().pop_as::<u32>()? as usize;
+    let module_addr = thread.stack().current().frame().module();
+    let table = match host.resolve_table(module_addr, table_idx as usize) {
+        Some(table_addr) => host.table(table_addr),
+        None => {
+            return Err(TrapCause::UnknownIndex {
+                space: IndexSpace::Table,
+                index: table_idx as usize,
+            }
+            .into())
+        }
+    };
+    if elem_idx >= table.len() {
+        return Err(TrapCause::UndefinedElement.into());
+    }
+    let func = match table.get(elem_idx) {
+        Some(func) => func,
+        None => return Err(TrapCause::UninitializedElement.into()),
+    };
+
+    let module = host.module(module_addr);
+    match module.types().get(type_idx as usize).and_then(|t| t.func()) {
+        Some(typ) if typ == host.func(func).typ() => Ok(func),
+        _ => Err(TrapCause::IndirectCallTypeMismatch.into()),
+    }
+}
+
+fn branch(thread: &mut Thread, host: &Host, depth: u32) -> Result<Flow, Trap> {
+    let depth = depth as usize;
+    let context = thread.stack_mut().current_mut();
+
+    // Branching out of the outermost block returns from the function
+    if depth == context.label_count() {
+        return ret(thread, host);
+    }
+
+    let label = match context.label(depth) {
+        Some(label) => label.clone(),
+        None => {
+            return Err(TrapCause::UnknownIndex {
+                space: IndexSpace::Label,
+                index: depth,
+            }
+            .into())
+        }
+    };
+    context.unwind(label.height(), label.arity())?;
+
+    // Loops stay active when they're branched to, blocks are exited
+    if label.is_loop() {
+        context.exit_labels(depth);
+    } else {
+        context.exit_labels(depth + 1);
+    }
+    Ok(Flow::Jump(label.target()))
+}
+
+fn ret(thread: &mut Thread, host: &Host) -> Result<Flow, Trap> {
+    let arity = match thread.stack().current().frame().func() {
+        Some(func) => host.func(func).typ().results().len(),
+        None => 0,
+    };
+    thread.stack_mut().current_mut().unwind(0, arity)?;
+    Ok(Flow::Return)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        builder::{FuncBuilder, ModuleBuilder},
+        hosting::Host,
+        interp::{StackLimits, Thread},
+        module::{ElemItem, Expr, TableType},
+        Trap, TrapCause, ValType, Value,
+    };
+
+    fn call(module: ModuleBuilder, name: &str, args: &[Value]) -> Result<Vec<Value>, Trap> {
+        let mut host = Host::new();
+        let addr = host.instantiate("test", module.build()).unwrap();
+        let func = host.get_module(addr).unwrap().export_func(name).unwrap();
+        Thread::new().call(&mut host, addr, func, args)
+    }
+
+    #[test]
+    pub fn return_call_does_not_grow_the_stack() {
+        use crate::Instruction::*;
+
+        // (func $count (param i32 i64) (result i64)) counts down the first argument,
+        // adding one to the second for each step
+        let module = ModuleBuilder::new().func(
+            FuncBuilder::new()
+                .export_as("count")
+                .param(ValType::I32)
+                .param(ValType::I64)
+                .result(ValType::I64)
+                .body(vec![
+                    LocalGet(0),
+                    I32Eqz,
+                    If(ValType::Nil),
+                    LocalGet(1),
+                    Return,
+                    End,
+                    LocalGet(0),
+                    I32Const(Value::I32(1)),
+                    I32Sub,
+                    LocalGet(1),
+                    I64Const(Value::I64(1)),
+                    I64Add,
+                    ReturnCall(0),
+                ]),
+        );
+        let res = call(module, "count", &[Value::I32(100_000), Value::I64(7)]);
+        assert_eq!(Ok(vec![Value::I64(100_007)]), res);
+    }
+
+    #[test]
+    pub fn recursion_is_limited_by_the_stack() {
+        use crate::Instruction::*;
+
+        // (func $depth (param i32) (result i32)) recurses until its argument is zero
+        let module = ModuleBuilder::new().func(
+            FuncBuilder::new()
+                .export_as("depth")
+                .param(ValType::I32)
+                .result(ValType::I32)
+                .body(vec![
+                    LocalGet(0),
+                    I32Eqz,
+                    If(ValType::Nil),
+                    I32Const(Value::I32(0)),
+                    Return,
+                    End,
+                    LocalGet(0),
+                    I32Const(Value::I32(1)),
+                    I32Sub,
+                    Call(0),
+                    I32Const(Value::I32(1)),
+                    I32Add,
+                ]),
+        );
+        let mut host = Host::new();
+        let addr = host.instantiate("test", module.build()).unwrap();
+        let func = host.get_module(addr).unwrap().export_func("depth").unwrap();
+
+        // Far deeper than the native stack would allow if calls recursed
+        let mut thread = Thread::with_limits(StackLimits {
+            max_frames: 1_000_000,
+            max_slots: 10_000_000,
+        });
+        let res = thread.call(&mut host, addr, func, &[Value::I32(500_000)]);
+        assert_eq!(Ok(vec![Value::I32(500_000)]), res);
+
+        // Both the number of frames and the number of values are limited
+        for limits in [(100, 10_000), (10_000, 100)].iter() {
+            thread.stack_mut().set_limits(StackLimits {
+                max_frames: limits.0,
+                max_slots: limits.1,
+            });
+            let trap = thread
+                .call(&mut host, addr, func, &[Value::I32(500_000)])
+                .unwrap_err();
+            assert!(matches!(trap.cause(), TrapCause::StackExhausted));
+            assert_eq!("call stack exhausted", trap.cause().message());
+        }
+
+        // The thread is left in a usable state after trapping
+        let res = thread.call(&mut host, addr, func, &[Value::I32(50)]);
+        assert_eq!(Ok(vec![Value::I32(50)]), res);
+    }
+
+    fn dispatch_module() -> ModuleBuilder {
+        use crate::Instruction::*;
+
+        let mut module = ModuleBuilder::new()
+            .func(
+                FuncBuilder::new()
+                    .export_as("dispatch")
+                    .param(ValType::I32)
+                    .result(ValType::I32)
+                    .body(vec![
+                        I32Const(Value::I32(21)),
+                        LocalGet(0),
+                        ReturnCallIndirect(0, 0),
+                    ]),
+            )
+            .func(
+                FuncBuilder::new()
+                    .param(ValType::I32)
+                    .result(ValType::I32)
+                    .body(vec![LocalGet(0), LocalGet(0), I32Add]),
+            )
+            .func(
+                FuncBuilder::new()
+                    .result(ValType::I32)
+                    .body(vec![I32Const(Value::I32(0))]),
+            )
+            .table("table", TableType::new(4, None));
+        module.elems.push(ElemItem::new(
+            0,
+            Expr::new(vec![I32Const(Value::I32(0))]),
+            vec![1, 2],
+        ));
+        module
+    }
+
+    #[test]
+    pub fn call_indirect_checks_the_signature() {
+        let dispatch = |idx| call(dispatch_module(), "dispatch", &[Value::I32(idx)]);
+
+        assert_eq!(Ok(vec![Value::I32(42)]), dispatch(0));
+        let message = |idx| dispatch(idx).unwrap_err().cause().message().to_string();
+        assert_eq!("indirect call type mismatch", message(1));
+        assert_eq!("uninitialized element", message(2));
+        assert_eq!("undefined element", message(4));
+    }
+}
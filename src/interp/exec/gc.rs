@@ -0,0 +1,443 @@
+use std::sync::Arc;
+
+use crate::{
+    hosting::{Host, ObjectInst},
+    interp::Thread,
+    module::{ArrayType, FieldType, StorageType, StructType, TypeDef},
+    value::Ref,
+    IndexSpace, Instruction, Trap, TrapCause, Value,
+};
+
+pub fn exec(thread: &mut Thread, host: &mut Host, inst: &Instruction) -> Result<(), Trap> {
+    use crate::Instruction::*;
+
+    match *inst {
+        RefNull(heap) => thread.push(Value::Ref(Ref::Null(heap))),
+        RefIsNull => {
+            let r = thread.stack_mut().pop_as::<Ref>()?;
+            thread.push(matches!(r, Ref::Null(_)).into());
+        }
+        RefEq => {
+            let (left, right) = thread.stack_mut().pop_pair_as::<Ref, Ref>()?;
+            let eq = match (left, right) {
+                (Ref::Null(_), Ref::Null(_)) => true,
+                (Ref::I31(l), Ref::I31(r)) => l == r,
+                (Ref::Object(l, _), Ref::Object(r, _)) => l == r,
+                _ => false,
+            };
+            thread.push(eq.into());
+        }
+
+        StructNew(type_idx) => {
+            let typ = struct_type(thread, host, type_idx)?;
+            let mut fields = Vec::with_capacity(typ.fields().len());
+            for field in typ.fields().iter().rev() {
+                fields.push(field.storage().pack(thread.pop()?));
+            }
+            fields.reverse();
+            alloc(thread, host, type_idx, fields);
+        }
+        StructNewDefault(type_idx) => {
+            let typ = struct_type(thread, host, type_idx)?;
+            let fields = typ
+                .fields()
+                .iter()
+                .map(|f| f.storage().default_value())
+                .collect();
+            alloc(thread, host, type_idx, fields);
+        }
+        StructGet(type_idx, field_idx) => struct_get(thread, host, type_idx, field_idx, false)?,
+        StructGetS(type_idx, field_idx) => struct_get(thread, host, type_idx, field_idx, true)?,
+        StructGetU(type_idx, field_idx) => struct_get(thread, host, type_idx, field_idx, false)?,
+        StructSet(type_idx, field_idx) => {
+            let field = field_type(&struct_type(thread, host, type_idx)?, field_idx)?;
+            let value = thread.pop()?;
+            let object = pop_object(thread, host, TrapCause::NullStructureReference)?;
+            object.set(field_idx as usize, field.storage().pack(value));
+        }
+
+        ArrayNew(type_idx) => {
+            let element = array_type(thread, host, type_idx)?.element();
+            let len = thread.stack_mut().pop_as::<u32>()? as usize;
+            let value = element.storage().pack(thread.pop()?);
+            let mut values = alloc_array(thread, host, len)?;
+            values.resize(len, value);
+            alloc(thread, host, type_idx, values);
+        }
+        ArrayNewDefault(type_idx) => {
+            let element = array_type(thread, host, type_idx)?.element();
+            let len = thread.stack_mut().pop_as::<u32>()? as usize;
+            let mut values = alloc_array(thread, host, len)?;
+            values.resize(len, element.storage().default_value());
+            alloc(thread, host, type_idx, values);
+        }
+        ArrayNewFixed(type_idx, len) => {
+            let element = array_type(thread, host, type_idx)?.element();
+            // The length comes from the code, so don't trust it any further than the stack goes
+            let len = len as usize;
+            if thread.stack().current().height() < len {
+                return Err(TrapCause::StackUnderflow.into());
+            }
+            let mut values = alloc_array(thread, host, len)?;
+            for _ in 0..len {
+                values.push(element.storage().pack(thread.pop()?));
+            }
+            values.reverse();
+            alloc(thread, host, type_idx, values);
+        }
+        ArrayGet(type_idx) => array_get(thread, host, type_idx, false)?,
+        ArrayGetS(type_idx) => array_get(thread, host, type_idx, true)?,
+        ArrayGetU(type_idx) => array_get(thread, host, type_idx, false)?,
+        ArraySet(type_idx) => {
+            let element = array_type(thread, host, type_idx)?.element();
+            let value = thread.pop()?;
+            let idx = thread.stack_mut().pop_as::<u32>()?;
+            let object = pop_object(thread, host, TrapCause::NullArrayReference)?;
+            if !object.set(idx as usize, element.storage().pack(value)) {
+                return Err(TrapCause::OutOfBoundsArrayAccess.into());
+            }
+        }
+        ArrayLen => {
+            let object = pop_object(thread, host, TrapCause::NullArrayReference)?;
+            thread.push(Value::I32(object.len() as u32));
+        }
+
+        RefI31 => {
+            let val = thread.stack_mut().pop_as::<u32>()?;
+            thread.push(Value::Ref(Ref::I31(val & 0x7FFF_FFFF)));
+        }
+        I31GetS | I31GetU => {
+            let val = match thread.stack_mut().pop_as::<Ref>()? {
+                Ref::I31(x) => x,
+                Ref::Null(_) => return Err(TrapCause::NullI31Reference.into()),
+                r => {
+                    return Err(Trap::new(TrapCause::InvalidCode)
+                        .with_message(format!("Expected an i31 reference: {}", r)))
+                }
+            };
+            if *inst == I31GetS {
+                thread.push(Value::I32((((val << 1) as i32) >> 1) as u32));
+            } else {
+                thread.push(Value::I32(val));
+            }
+        }
+
+        ref x => {
+            return Err(Trap::new(TrapCause::InvalidCode)
+                .with_message(format!("Not a GC instruction: {}", x)))
+        }
+    };
+
+    Ok(())
+}
+
+fn type_def(thread: &Thread, host: &Host, type_idx: u32) -> Result<TypeDef, Trap> {
+    let module = host.module(thread.stack().current().frame().module());
+    match module.types().get(type_idx as usize) {
+        Some(typ) => Ok(typ.clone()),
+        None => Err(TrapCause::UnknownIndex {
+            space: IndexSpace::Type,
+            index: type_idx as usize,
+        }
+        .into()),
+    }
+}
+
+fn struct_type(thread: &Thread, host: &Host, type_idx: u32) -> Result<StructType, Trap> {
+    match type_def(thread, host, type_idx)? {
+        TypeDef::Struct(typ) => Ok(typ),
+        _ => Err(Trap::new(TrapCause::InvalidCode)
+            .with_message(format!("Type {} is not a struct type.", type_idx))),
+    }
+}
+
+fn array_type(thread: &Thread, host: &Host, type_idx: u32) -> Result<ArrayType, Trap> {
+    match type_def(thread, host, type_idx)? {
+        TypeDef::Array(typ) => Ok(typ),
+        _ => Err(Trap::new(TrapCause::InvalidCode)
+            .with_message(format!("Type {} is not an array type.", type_idx))),
+    }
+}
+
+fn field_type(typ: &StructType, field_idx: u32) -> Result<FieldType, Trap> {
+    match typ.fields().get(field_idx as usize) {
+        Some(field) => Ok(*field),
+        None => Err(TrapCause::UnknownIndex {
+            space: IndexSpace::Field,
+            index: field_idx as usize,
+        }
+        .into()),
+    }
+}
+
+fn alloc(thread: &mut Thread, host: &mut Host, type_idx: u32, fields: Vec<Value>) {
+    let module = thread.stack().current().frame().module();
+    let addr = host.alloc_object(ObjectInst::new(module, type_idx, fields));
+    thread.push(Value::Ref(Ref::Object(addr, type_idx)));
+}
+
+/// Makes room for the `len` elements of a new array, trapping if the host's resource limiter
+/// refuses or there isn't enough memory for them.
+fn alloc_array(thread: &Thread, host: &Host, len: usize) -> Result<Vec<Value>, Trap> {
+    let module = thread.stack().current().frame().module();
+    let mut values = Vec::new();
+    if !host.allows_array(module, len) || values.try_reserve_exact(len).is_err() {
+        return Err(TrapCause::AllocationFailed.into());
+    }
+    Ok(values)
+}
+
+/// Pops a reference to an object, trapping with `null_trap` if the reference is null.
+fn pop_object(
+    thread: &mut Thread,
+    host: &Host,
+    null_trap: TrapCause,
+) -> Result<Arc<ObjectInst>, Trap> {
+    match thread.stack_mut().pop_as::<Ref>()? {
+        Ref::Object(addr, _) => Ok(host.object(addr)),
+        Ref::Null(_) => Err(null_trap.into()),
+        r => Err(Trap::new(TrapCause::InvalidCode)
+            .with_message(format!("Expected a reference to an object: {}", r))),
+    }
+}
+
+/// Extends a value read from a packed field to an `i32`.
+fn unpack(storage: StorageType, value: Value, signed: bool) -> Value {
+    let bits = match storage {
+        StorageType::I8 => 8,
+        StorageType::I16 => 16,
+        StorageType::Val(_) => return value,
+    };
+    match value {
+        Value::I32(x) if signed => {
+            let shift = 32 - bits;
+            Value::I32((((x << shift) as i32) >> shift) as u32)
+        }
+        x => x,
+    }
+}
+
+fn struct_get(
+    thread: &mut Thread,
+    host: &Host,
+    type_idx: u32,
+    field_idx: u32,
+    signed: bool,
+) -> Result<(), Trap> {
+    let field = field_type(&struct_type(thread, host, type_idx)?, field_idx)?;
+    let object = pop_object(thread, host, TrapCause::NullStructureReference)?;
+    match object.get(field_idx as usize) {
+        Some(value) => {
+            thread.push(unpack(field.storage(), value, signed));
+            Ok(())
+        }
+        None => Err(TrapCause::UnknownIndex {
+            space: IndexSpace::Field,
+            index: field_idx as usize,
+        }
+        .into()),
+    }
+}
+
+fn array_get(thread: &mut Thread, host: &Host, type_idx: u32, signed: bool) -> Result<(), Trap> {
+    let element = array_type(thread, host, type_idx)?.element();
+    let idx = thread.stack_mut().pop_as::<u32>()?;
+    let object = pop_object(thread, host, TrapCause::NullArrayReference)?;
+    match object.get(idx as usize) {
+        Some(value) => {
+            thread.push(unpack(element.storage(), value, signed));
+            Ok(())
+        }
+        None => Err(TrapCause::OutOfBoundsArrayAccess.into()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use crate::{
+        builder::{FuncBuilder, ModuleBuilder},
+        hosting::{Host, Limits},
+        interp::{exec::test_util::run_body, Thread},
+        module::{ArrayType, FieldType, StorageType, StructType, TypeDef},
+        value::{HeapType, RefType},
+        Instruction, Trap, ValType, Value,
+    };
+
+    fn run(types: Vec<TypeDef>, body: Vec<Instruction>) -> Result<Vec<Value>, Trap> {
+        run_with_locals(types, Vec::new(), body)
+    }
+
+    fn run_with_locals(
+        types: Vec<TypeDef>,
+        locals: Vec<ValType>,
+        body: Vec<Instruction>,
+    ) -> Result<Vec<Value>, Trap> {
+        let module = types
+            .into_iter()
+            .fold(ModuleBuilder::new(), ModuleBuilder::type_def);
+        let func = FuncBuilder::new()
+            .result(ValType::I32)
+            .locals(locals)
+            .body(body);
+        run_body(module, func, &mut Thread::new())
+    }
+
+    fn i32(v: u32) -> Instruction {
+        Instruction::I32Const(Value::I32(v))
+    }
+
+    #[test]
+    pub fn rec_groups_are_flattened() {
+        // (rec (type (struct (field (mut i8)))) (type (sub (array i64))))
+        let bytes = [
+            0x4E, 0x02, 0x5F, 0x01, 0x78, 0x01, 0x50, 0x00, 0x5E, 0x7E, 0x00,
+        ];
+        let types = TypeDef::read_group(&mut Cursor::new(&bytes[..])).unwrap();
+        assert_eq!(2, types.len());
+        assert!(
+            types[0]
+                == TypeDef::Struct(StructType::new(vec![FieldType::new(StorageType::I8, true)]))
+        );
+        assert!(
+            types[1]
+                == TypeDef::Array(ArrayType::new(FieldType::new(
+                    StorageType::Val(ValType::I64),
+                    false
+                )))
+        );
+    }
+
+    #[test]
+    pub fn struct_fields_are_packed_and_extended() {
+        use crate::Instruction::*;
+
+        let point = TypeDef::Struct(StructType::new(vec![
+            FieldType::new(StorageType::I8, true),
+            FieldType::new(StorageType::Val(ValType::I32), true),
+        ]));
+        let res = run(
+            vec![point.clone()],
+            vec![
+                i32(0x1FF),
+                i32(7),
+                StructNew(0),
+                StructGetS(0, 0),
+                i32(0x80),
+                i32(0),
+                StructNew(0),
+                StructGetU(0, 0),
+                I32Add,
+            ],
+        );
+        // -1 + 0x80
+        assert_eq!(Ok(vec![Value::I32(0x7F)]), res);
+
+        let res = run_with_locals(
+            vec![point.clone()],
+            vec![ValType::Ref(RefType::new(true, HeapType::Type(0)))],
+            vec![
+                StructNewDefault(0),
+                LocalTee(0),
+                i32(42),
+                StructSet(0, 1),
+                LocalGet(0),
+                StructGet(0, 1),
+            ],
+        );
+        assert_eq!(Ok(vec![Value::I32(42)]), res);
+
+        let res = run(vec![point], vec![RefNull(HeapType::None), StructGet(0, 1)]);
+        assert_eq!(
+            "null structure reference",
+            res.unwrap_err().cause().message()
+        );
+    }
+
+    #[test]
+    pub fn arrays_check_their_bounds() {
+        use crate::Instruction::*;
+
+        let bytes = TypeDef::Array(ArrayType::new(FieldType::new(StorageType::I16, true)));
+        let res = run(
+            vec![bytes.clone()],
+            vec![
+                i32(1),
+                i32(0x1_0002),
+                i32(3),
+                ArrayNewFixed(0, 3),
+                i32(1),
+                ArrayGetU(0),
+            ],
+        );
+        assert_eq!(Ok(vec![Value::I32(2)]), res);
+
+        let res = run(
+            vec![bytes.clone()],
+            vec![i32(5), i32(4), ArrayNew(0), ArrayLen],
+        );
+        assert_eq!(Ok(vec![Value::I32(4)]), res);
+
+        let res = run(
+            vec![bytes],
+            vec![i32(4), ArrayNewDefault(0), i32(4), ArrayGet(0)],
+        );
+        assert_eq!(
+            "out of bounds array access",
+            res.unwrap_err().cause().message()
+        );
+    }
+
+    #[test]
+    pub fn arrays_too_large_to_allocate_trap() {
+        use crate::Instruction::*;
+
+        let values = TypeDef::Array(ArrayType::new(FieldType::new(
+            StorageType::Val(ValType::I64),
+            true,
+        )));
+        let res = run(
+            vec![values.clone()],
+            vec![ArrayNewFixed(0, u32::MAX), ArrayLen],
+        );
+        assert_eq!("stack underflow", res.unwrap_err().cause().message());
+
+        let module = ModuleBuilder::new().type_def(values).func(
+            FuncBuilder::new()
+                .export_as("test")
+                .param(ValType::I32)
+                .result(ValType::I32)
+                .body(vec![LocalGet(0), ArrayNewDefault(0), ArrayLen]),
+        );
+        let mut host = Host::new();
+        host.set_limiter(Limits::new().array_elements(16));
+        let addr = host.instantiate("test", module.build()).unwrap();
+        let func = host.get_module(addr).unwrap().export_func("test").unwrap();
+        let mut thread = Thread::new();
+        let res = thread.call(&mut host, addr, func, &[Value::I32(16)]);
+        assert_eq!(Ok(vec![Value::I32(16)]), res);
+        let res = thread.call(&mut host, addr, func, &[Value::I32(17)]);
+        assert_eq!("allocation failed", res.unwrap_err().cause().message());
+    }
+
+    #[test]
+    pub fn i31_references_round_trip_31_bits() {
+        use crate::Instruction::*;
+
+        let res = run(
+            Vec::new(),
+            vec![
+                i32(0xFFFF_FFFF),
+                RefI31,
+                I31GetU,
+                i32(0x4000_0000),
+                RefI31,
+                I31GetS,
+                I32Add,
+            ],
+        );
+        // 0x7FFF_FFFF + -0x4000_0000
+        assert_eq!(Ok(vec![Value::I32(0x3FFF_FFFF)]), res);
+    }
+}
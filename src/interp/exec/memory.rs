@@ -0,0 +1,352 @@
+use std::sync::Arc;
+
+use crate::{
+    hosting::{Event, Host, MemInst},
+    interp::Thread,
+    Instruction, Trap, TrapCause, Value,
+};
+
+pub fn exec(thread: &mut Thread, host: &mut Host, inst: &Instruction) -> Result<(), Trap> {
+    use crate::Instruction::*;
+
+    match *inst {
+        I32Load(_, offset) => load_int(thread, host, offset, 4, false, false),
+        I64Load(_, offset) => load_int(thread, host, offset, 8, false, true),
+        F32Load(_, offset) => {
+            let mut bytes = [0u8; 4];
+            load(thread, host, offset, &mut bytes)?;
+            thread.push(Value::F32(f32::from_le_bytes(bytes)));
+            Ok(())
+        }
+        F64Load(_, offset) => {
+            let mut bytes = [0u8; 8];
+            load(thread, host, offset, &mut bytes)?;
+            thread.push(Value::F64(f64::from_le_bytes(bytes)));
+            Ok(())
+        }
+        I32Load8S(_, offset) => load_int(thread, host, offset, 1, true, false),
+        I32Load8U(_, offset) => load_int(thread, host, offset, 1, false, false),
+        I32Load16S(_, offset) => load_int(thread, host, offset, 2, true, false),
+        I32Load16U(_, offset) => load_int(thread, host, offset, 2, false, false),
+        I64Load8S(_, offset) => load_int(thread, host, offset, 1, true, true),
+        I64Load8U(_, offset) => load_int(thread, host, offset, 1, false, true),
+        I64Load16S(_, offset) => load_int(thread, host, offset, 2, true, true),
+        I64Load16U(_, offset) => load_int(thread, host, offset, 2, false, true),
+        I64Load32S(_, offset) => load_int(thread, host, offset, 4, true, true),
+        I64Load32U(_, offset) => load_int(thread, host, offset, 4, false, true),
+        I32Store(_, offset) => store_int(thread, host, offset, 4, false),
+        I64Store(_, offset) => store_int(thread, host, offset, 8, true),
+        F32Store(_, offset) => {
+            let val = thread.stack_mut().pop_as::<f32>()?;
+            store(thread, host, offset, &val.to_le_bytes())
+        }
+        F64Store(_, offset) => {
+            let val = thread.stack_mut().pop_as::<f64>()?;
+            store(thread, host, offset, &val.to_le_bytes())
+        }
+        I32Store8(_, offset) => store_int(thread, host, offset, 1, false),
+        I32Store16(_, offset) => store_int(thread, host, offset, 2, false),
+        I64Store8(_, offset) => store_int(thread, host, offset, 1, true),
+        I64Store16(_, offset) => store_int(thread, host, offset, 2, true),
+        I64Store32(_, offset) => store_int(thread, host, offset, 4, true),
+        MemorySize(_) => {
+            let mem = current_memory(thread, host)?;
+            push_pages(thread, &mem, (mem.memory().len() / mem.page_size()) as u64);
+            Ok(())
+        }
+        MemoryGrow(_) => {
+            let mem = current_memory(thread, host)?;
+            let delta = pop_address(thread, &mem)?;
+
+            // Memories grow up to their maximum size, or as far as their storage can, see
+            // MemoryConfig. Failing is always permitted, and is reported as -1.
+            let len = mem.memory().len();
+            let desired = (delta as usize)
+                .checked_mul(mem.page_size())
+                .and_then(|bytes| bytes.checked_add(len));
+            let allowed = desired.is_some_and(|desired| host.allows_memory_growth(&mem, desired));
+            let pages = if allowed { mem.grow(delta) } else { None };
+            push_pages(thread, &mem, pages.unwrap_or(u64::MAX));
+
+            if host.has_event_sink() {
+                let module = thread.stack().current().frame().module();
+                host.report(&Event::MemoryGrow {
+                    mem: host.module(module).mems()[0],
+                    delta,
+                    pages,
+                });
+            }
+            Ok(())
+        }
+        ref x => Err(Trap::new(TrapCause::InvalidCode)
+            .with_message(format!("Not a memory instruction: {}", x))),
+    }
+}
+
+/// Resolves memory 0 of the module that owns the current stack frame.
+pub fn current_memory(thread: &Thread, host: &Host) -> Result<Arc<MemInst>, Trap> {
+    let module = thread.stack().current().frame().module();
+    match host.module(module).mems().first() {
+        Some(mem_addr) => Ok(host.mem(*mem_addr)),
+        None => Err(TrapCause::NoMemory.into()),
+    }
+}
+
+/// Pops an address operand for `mem`, which is an `i64` for 64-bit memories and an `i32` otherwise.
+pub fn pop_address(thread: &mut Thread, mem: &MemInst) -> Result<u64, Trap> {
+    if mem.is_64() {
+        Ok(thread.stack_mut().pop_as::<u64>()?)
+    } else {
+        Ok(thread.stack_mut().pop_as::<u32>()? as u64)
+    }
+}
+
+/// Pushes a count of pages as an `i64` for 64-bit memories and an `i32` otherwise.
+fn push_pages(thread: &mut Thread, mem: &MemInst, pages: u64) {
+    if mem.is_64() {
+        thread.push(Value::I64(pages));
+    } else {
+        thread.push(Value::I32(pages as u32));
+    }
+}
+
+/// Computes the range of bytes covered by an access of `len` bytes at `addr + offset`,
+/// trapping if any part of that range is outside the memory.
+pub fn effective_range(
+    mem: &MemInst,
+    addr: u64,
+    offset: u64,
+    len: usize,
+) -> Result<(usize, usize), Trap> {
+    // With 64-bit memories, the effective address can overflow
+    let end = addr
+        .checked_add(offset)
+        .and_then(|start| start.checked_add(len as u64));
+    match end {
+        Some(end) if end <= mem.memory().len() as u64 => Ok((end as usize - len, end as usize)),
+        _ => {
+            Err(Trap::new(TrapCause::OutOfBoundsMemoryAccess)
+                .with_address(addr.saturating_add(offset)))
+        }
+    }
+}
+
+/// Pops an address and reads `buf.len()` bytes starting at `addr + offset` in the current memory.
+pub fn load(thread: &mut Thread, host: &Host, offset: u64, buf: &mut [u8]) -> Result<(), Trap> {
+    let mem = current_memory(thread, host)?;
+    let addr = pop_address(thread, &mem)?;
+    let (start, _) = effective_range(&mem, addr, offset, buf.len())?;
+
+    // Copied through a raw pointer, as other threads may access a shared memory meanwhile
+    if !mem.memory().read(start, buf) {
+        return Err(Trap::new(TrapCause::OutOfBoundsMemoryAccess).with_address(start as u64));
+    }
+    Ok(())
+}
+
+/// Pops an address and writes `bytes` starting at `addr + offset` in the current memory.
+pub fn store(thread: &mut Thread, host: &Host, offset: u64, bytes: &[u8]) -> Result<(), Trap> {
+    let mem = current_memory(thread, host)?;
+    let addr = pop_address(thread, &mem)?;
+    let (start, _) = effective_range(&mem, addr, offset, bytes.len())?;
+
+    // Copied through a raw pointer, as other threads may access a shared memory meanwhile
+    if !mem.memory().write(start, bytes) {
+        return Err(Trap::new(TrapCause::OutOfBoundsMemoryAccess).with_address(start as u64));
+    }
+    Ok(())
+}
+
+/// Loads a `len`-byte integer and extends it to an `i32` or `i64`.
+fn load_int(
+    thread: &mut Thread,
+    host: &Host,
+    offset: u64,
+    len: usize,
+    signed: bool,
+    is64: bool,
+) -> Result<(), Trap> {
+    let mut bytes = [0u8; 8];
+    load(thread, host, offset, &mut bytes[..len])?;
+    let mut val = u64::from_le_bytes(bytes);
+    if signed {
+        let shift = 64 - len as u32 * 8;
+        val = (((val << shift) as i64) >> shift) as u64;
+    }
+
+    if is64 {
+        thread.push(Value::I64(val));
+    } else {
+        thread.push(Value::I32(val as u32));
+    }
+    Ok(())
+}
+
+/// Stores the low `len` bytes of an `i32` or `i64`.
+fn store_int(
+    thread: &mut Thread,
+    host: &Host,
+    offset: u64,
+    len: usize,
+    is64: bool,
+) -> Result<(), Trap> {
+    let val = if is64 {
+        thread.stack_mut().pop_as::<u64>()?
+    } else {
+        thread.stack_mut().pop_as::<u32>()? as u64
+    };
+    store(thread, host, offset, &val.to_le_bytes()[..len])
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use crate::{
+        builder::{FuncBuilder, ModuleBuilder},
+        interp::{exec::test_util::run_body, Thread},
+        module::MemoryType,
+        Instruction, Trap, ValType, Value, PAGE_SIZE,
+    };
+
+    fn run(mem: MemoryType, body: Vec<Instruction>) -> Result<Vec<Value>, Trap> {
+        let module = ModuleBuilder::new().mem("memory", mem);
+        let func = FuncBuilder::new().result(ValType::I64).body(body);
+        run_body(module, func, &mut Thread::new())
+    }
+
+    fn i64(v: u64) -> Instruction {
+        Instruction::I64Const(Value::I64(v))
+    }
+
+    #[test]
+    pub fn memory64_limits_are_read_as_64_bit_integers() {
+        // Flags 0x05: 64-bit with a maximum. The maximum doesn't fit in 32 bits.
+        let bytes = [0x05, 0x01, 0x80, 0x80, 0x80, 0x80, 0x20];
+        let typ = MemoryType::read(&mut Cursor::new(&bytes[..])).unwrap();
+        assert!(typ.is_64());
+        assert_eq!(1, typ.min());
+        assert_eq!(Some(0x2_0000_0000), typ.max());
+    }
+
+    #[test]
+    pub fn memory64_uses_64_bit_addresses() {
+        let res = run(
+            MemoryType::new_64(1, None),
+            vec![
+                i64(8),
+                i64(0xFFFF_FFFF_FFFF_FFF0),
+                Instruction::I64Store(3, 0),
+                i64(0),
+                Instruction::I64Load32S(2, 12),
+            ],
+        );
+        assert_eq!(Ok(vec![Value::I64(u64::MAX)]), res);
+
+        // An offset that overflows the effective address traps rather than wrapping
+        let res = run(
+            MemoryType::new_64(1, None),
+            vec![i64(u64::MAX), Instruction::I64Load(3, 8)],
+        );
+        assert_eq!(
+            "out of bounds memory access",
+            res.unwrap_err().cause().message()
+        );
+
+        // The trap records the address and the operands left in the frame
+        let trap = run(
+            MemoryType::new_64(1, None),
+            vec![i64(7), i64(0x1_0000), Instruction::I64Load(3, 4)],
+        )
+        .unwrap_err();
+        assert_eq!(Some(0x1_0004), trap.address());
+        let state = trap.frame_state().unwrap();
+        assert_eq!(&[Value::I64(7)], state.operands());
+    }
+
+    #[test]
+    pub fn memory_grow_makes_new_pages_accessible() {
+        use crate::Instruction::*;
+
+        // A memory allocated with the default configuration grows, and the new page is
+        // zeroed and can be written
+        let res = run(
+            MemoryType::new_64(2, None),
+            vec![
+                i64(3 * PAGE_SIZE as u64 - 8),
+                i64(1),
+                MemoryGrow(0),
+                I64Store(3, 0),
+                i64(3 * PAGE_SIZE as u64 - 16),
+                I64Load(3, 0),
+                i64(3 * PAGE_SIZE as u64 - 8),
+                I64Load(3, 0),
+                I64Add,
+            ],
+        );
+        // 0 + 2
+        assert_eq!(Ok(vec![Value::I64(2)]), res);
+
+        // But not beyond the maximum
+        let res = run(
+            MemoryType::new_64(1, Some(2)),
+            vec![i64(2), MemoryGrow(0), i64(1), MemoryGrow(0), I64Add],
+        );
+        // -1 + 1
+        assert_eq!(Ok(vec![Value::I64(0)]), res);
+    }
+
+    #[cfg(feature = "custom-page-sizes")]
+    #[test]
+    pub fn custom_page_sizes_measure_memory_in_bytes() {
+        // Flags 0x0C: 64-bit with a page size of 2^0 bytes
+        let bytes = [0x0C, 0x03, 0x00];
+        let typ = MemoryType::read(&mut Cursor::new(&bytes[..])).unwrap();
+        assert_eq!(1, typ.page_size());
+
+        let res = run(typ.clone(), vec![Instruction::MemorySize(0)]);
+        assert_eq!(Ok(vec![Value::I64(3)]), res);
+
+        // The last byte is in bounds, but one past it isn't
+        let res = run(typ.clone(), vec![i64(2), Instruction::I64Load8U(0, 0)]);
+        assert_eq!(Ok(vec![Value::I64(0)]), res);
+        let res = run(typ, vec![i64(3), Instruction::I64Load8U(0, 0)]);
+        assert_eq!(
+            "out of bounds memory access",
+            res.unwrap_err().cause().message()
+        );
+
+        // Only 1-byte and 64KiB pages are allowed
+        let bytes = [0x08, 0x01, 0x0C];
+        assert!(MemoryType::read(&mut Cursor::new(&bytes[..])).is_err());
+    }
+
+    #[cfg(feature = "custom-page-sizes")]
+    #[test]
+    pub fn custom_page_sizes_grow_by_their_own_pages() {
+        use crate::Instruction::*;
+
+        // 64-bit with a page size of 2^0 bytes and a minimum of 3 pages
+        let bytes = [0x0C, 0x03, 0x00];
+        let typ = MemoryType::read(&mut Cursor::new(&bytes[..])).unwrap();
+
+        // Growing by 5 pages produces the old size, and makes bytes 3 to 7 accessible
+        let res = run(
+            typ,
+            vec![
+                i64(5),
+                MemoryGrow(0),
+                i64(7),
+                i64(0x42),
+                I64Store8(0, 0),
+                i64(7),
+                I64Load8U(0, 0),
+                MemorySize(0),
+                I64Add,
+                I64Add,
+            ],
+        );
+        // 3 + (0x42 + 8)
+        assert_eq!(Ok(vec![Value::I64(0x4D)]), res);
+    }
+}
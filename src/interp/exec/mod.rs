@@ -1,33 +1,254 @@
-use crate::{hosting::Host, interp::Thread, Instruction, Trap};
+use crate::{
+    hosting::{FuncAddr, Host},
+    interp::{Code, Dispatch, Op, Thread},
+    IndexSpace, Instruction, Trap, TrapCause,
+};
 
+mod atomic;
+mod control;
+#[cfg(feature = "gc")]
+mod gc;
+mod memory;
 mod numops;
+#[cfg(feature = "relaxed-simd")]
+mod relaxed_simd;
+mod simd;
+#[cfg(test)]
+mod test_util;
 
-pub fn execute(thread: &mut Thread, host: &mut Host, inst: Instruction) -> Result<(), Trap> {
+/// Describes where execution continues after an instruction.
+pub enum Flow {
+    /// Continue with the next instruction.
+    Next,
+    /// Continue at the instruction with the specified index.
+    Jump(usize),
+    /// Return from the current function.
+    Return,
+    /// Call the specified function, then continue with the next instruction.
+    Call(FuncAddr),
+    /// Replace the current function with a call to the specified function.
+    TailCall(FuncAddr),
+}
+
+type Executor = fn(&mut Thread, &mut Host, &Instruction) -> Result<(), Trap>;
+
+/// Executors for the prefixed opcode namespaces, keyed by prefix byte.
+static NAMESPACES: &[(u8, Executor)] = &[
+    // The GC proposal's struct, array and i31 instructions
+    #[cfg(feature = "gc")]
+    (0xFB, gc::exec as Executor),
+    // SIMD instructions
+    (0xFD, simd::exec as Executor),
+    // The threads proposal's atomic instructions
+    (0xFE, atomic::exec as Executor),
+];
+
+/// Executors for the unprefixed opcodes, indexed by opcode, for [`Dispatch::Table`].
+/// Control instructions are handled by [`step`] before dispatching, so they aren't included.
+static OPCODES: [Executor; 256] = opcode_table();
+
+const fn opcode_table() -> [Executor; 256] {
+    let mut table = [exec_numeric as Executor; 256];
+
+    // nop, drop, select, local.*, global.* and the constants
+    let variable: [usize; 12] = [
+        0x01, 0x1A, 0x1B, 0x20, 0x21, 0x22, 0x23, 0x24, 0x41, 0x42, 0x43, 0x44,
+    ];
+    let mut i = 0;
+    while i < variable.len() {
+        table[variable[i]] = exec_variable as Executor;
+        i += 1;
+    }
+
+    // The loads, stores, memory.size and memory.grow
+    let mut opcode = 0x28;
+    while opcode <= 0x40 {
+        table[opcode] = memory::exec as Executor;
+        opcode += 1;
+    }
+
+    // ref.null, ref.is_null and ref.eq
+    #[cfg(feature = "gc")]
+    {
+        table[0xD0] = gc::exec as Executor;
+        table[0xD1] = gc::exec as Executor;
+        table[0xD3] = gc::exec as Executor;
+    }
+    table
+}
+
+/// Executes the instruction at `code[pc]`.
+pub fn step(thread: &mut Thread, host: &mut Host, code: &Code, pc: usize) -> Result<Flow, Trap> {
+    use crate::Instruction::*;
+
+    match code.ops()[pc] {
+        Op::Inst(ref inst) => match *inst {
+            Unreachable
+            | Br(_)
+            | BrIf(_)
+            | BrTable(_)
+            | Return
+            | Call(_)
+            | CallIndirect(_, _)
+            | ReturnCall(_)
+            | ReturnCallIndirect(_, _) => control::exec(thread, host, inst),
+            _ => {
+                execute(thread, host, inst)?;
+                Ok(Flow::Next)
+            }
+        },
+        ref op => control::exec_structured(thread, op, pc),
+    }
+}
+
+/// Executes a non-control instruction, dispatching it with the thread's [`Dispatch`] strategy.
+pub fn execute(thread: &mut Thread, host: &mut Host, inst: &Instruction) -> Result<(), Trap> {
+    match thread.dispatch() {
+        Dispatch::Match => execute_match(thread, host, inst),
+        Dispatch::Table => execute_table(thread, host, inst),
+    }
+}
+
+/// Finds the executor for an instruction by matching on it.
+fn execute_match(thread: &mut Thread, host: &mut Host, inst: &Instruction) -> Result<(), Trap> {
     use crate::Instruction::*;
 
-    match inst {
+    match *inst {
+        Nop | Drop | Select | I32Const(_) | I64Const(_) | F32Const(_) | F64Const(_)
+        | LocalGet(_) | LocalSet(_) | LocalTee(_) | GlobalGet(_) | GlobalSet(_) => {
+            exec_variable(thread, host, inst)
+        }
+        #[cfg(feature = "gc")]
+        RefNull(_) | RefIsNull | RefEq => gc::exec(thread, host, inst),
+        // Opcodes 0x28 to 0x40 are the loads, stores, memory.size and memory.grow
+        _ if inst.prefix().is_none() && (0x28..=0x40).contains(&inst.opcode()) => {
+            memory::exec(thread, host, inst)
+        }
+        _ => match inst.prefix() {
+            Some(prefix) => execute_prefixed(thread, host, inst, prefix),
+            None => numops::exec(thread, inst),
+        },
+    }
+}
+
+/// Finds the executor for an instruction by indexing a table with its opcode.
+fn execute_table(thread: &mut Thread, host: &mut Host, inst: &Instruction) -> Result<(), Trap> {
+    match inst.prefix() {
+        None => OPCODES[inst.opcode() as usize](thread, host, inst),
+        Some(prefix) => execute_prefixed(thread, host, inst, prefix),
+    }
+}
+
+fn execute_prefixed(
+    thread: &mut Thread,
+    host: &mut Host,
+    inst: &Instruction,
+    prefix: u8,
+) -> Result<(), Trap> {
+    match NAMESPACES.iter().find(|(p, _)| *p == prefix) {
+        Some((_, exec)) => exec(thread, host, inst),
+        None => numops::exec(thread, inst),
+    }
+}
+
+fn exec_numeric(thread: &mut Thread, _host: &mut Host, inst: &Instruction) -> Result<(), Trap> {
+    numops::exec(thread, inst)
+}
+
+/// Executes the instructions that work with the operand stack, locals and globals.
+fn exec_variable(thread: &mut Thread, host: &mut Host, inst: &Instruction) -> Result<(), Trap> {
+    use crate::Instruction::*;
+
+    match *inst {
+        Nop => {}
+        Drop => {
+            thread.pop()?;
+        }
+        Select => {
+            let cond = thread.stack_mut().pop_as::<u32>()?;
+            let val2 = thread.pop()?;
+            let val1 = thread.pop()?;
+            thread.push(if cond != 0 { val1 } else { val2 });
+        }
         I32Const(v) => thread.push(v),
         I64Const(v) => thread.push(v),
         F32Const(v) => thread.push(v),
         F64Const(v) => thread.push(v),
-        Call(func_idx) => {
-            let module_addr = thread.stack().current().frame().module();
-            let func = host.resolve_func(module_addr, func_idx as usize);
-            let values = thread.invoke(host, func)?;
-
-            // Push the result values on to the stack
-            for value in values {
-                thread.push(value)
-            }
-        }
         LocalGet(local_idx) => {
             let val = match thread.stack().current().local(local_idx as usize) {
                 Some(l) => l,
-                None => return Err(format!("No such local: {}", local_idx).into()),
+                None => {
+                    return Err(TrapCause::UnknownIndex {
+                        space: IndexSpace::Local,
+                        index: local_idx as usize,
+                    }
+                    .into())
+                }
             };
             thread.push(val);
         }
-        _ => numops::exec(thread, inst)?,
+        LocalSet(local_idx) => {
+            let val = thread.pop()?;
+            if !thread
+                .stack_mut()
+                .current_mut()
+                .set_local(local_idx as usize, val)
+            {
+                return Err(TrapCause::UnknownIndex {
+                    space: IndexSpace::Local,
+                    index: local_idx as usize,
+                }
+                .into());
+            }
+        }
+        LocalTee(local_idx) => {
+            let val = thread.pop()?;
+            thread.push(val);
+            if !thread
+                .stack_mut()
+                .current_mut()
+                .set_local(local_idx as usize, val)
+            {
+                return Err(TrapCause::UnknownIndex {
+                    space: IndexSpace::Local,
+                    index: local_idx as usize,
+                }
+                .into());
+            }
+        }
+        GlobalGet(global_idx) => {
+            let module_addr = thread.stack().current().frame().module();
+            let global_addr = match host.resolve_global(module_addr, global_idx as usize) {
+                Some(global_addr) => global_addr,
+                None => {
+                    return Err(TrapCause::UnknownIndex {
+                        space: IndexSpace::Global,
+                        index: global_idx as usize,
+                    }
+                    .into())
+                }
+            };
+            thread.push(host.global(global_addr).get());
+        }
+        GlobalSet(global_idx) => {
+            let module_addr = thread.stack().current().frame().module();
+            let global_addr = match host.resolve_global(module_addr, global_idx as usize) {
+                Some(global_addr) => global_addr,
+                None => {
+                    return Err(TrapCause::UnknownIndex {
+                        space: IndexSpace::Global,
+                        index: global_idx as usize,
+                    }
+                    .into())
+                }
+            };
+            let val = thread.pop()?;
+            host.set_global(global_addr, val)?;
+        }
+        ref x => {
+            return Err(Trap::new(TrapCause::InvalidCode)
+                .with_message(format!("Not a variable instruction: {}", x)))
+        }
     };
 
     Ok(())
@@ -1,11 +1,90 @@
 use std::{cmp, ops};
 
-use crate::{interp::Thread, value, FromValue, Instruction, Trap, Value};
+use crate::{interp::Thread, value, FromValue, Instruction, Trap, TrapCause, Value};
 
-pub fn exec(thread: &mut Thread, inst: Instruction) -> Result<(), Trap> {
+pub fn exec(thread: &mut Thread, inst: &Instruction) -> Result<(), Trap> {
+    if !thread.trap_on_nan() {
+        exec_scalar(thread, inst)?;
+        if thread.canonicalize_nans() && is_float_arithmetic(inst) {
+            let value = thread.pop()?;
+            thread.push(canonicalize(value));
+        }
+        return Ok(());
+    }
+
+    // Operands are checked as well as results, since some instructions (like comparisons)
+    // consume a NaN without producing one.
+    for depth in 0..float_operands(inst) {
+        if thread.stack().current().peek(depth).is_some_and(is_nan) {
+            return Err(TrapCause::UnexpectedNaN.into());
+        }
+    }
+    exec_scalar(thread, inst)?;
+    if thread.stack().current().peek(0).is_some_and(is_nan) {
+        return Err(TrapCause::UnexpectedNaN.into());
+    }
+    Ok(())
+}
+
+/// Gets the number of operands of a scalar instruction that may be floating-point values.
+fn float_operands(inst: &Instruction) -> usize {
+    if inst.prefix().is_some() {
+        return 0;
+    }
+    match inst.opcode() {
+        // Comparisons and binary arithmetic
+        0x5B..=0x66 | 0x92..=0x98 | 0xA0..=0xA6 => 2,
+        // Unary arithmetic and conversions
+        0x8B..=0x91 | 0x99..=0x9F | 0xA7..=0xBF => 1,
+        _ => 0,
+    }
+}
+
+/// Checks if a scalar instruction is floating-point arithmetic (or a conversion to a float),
+/// which may produce a NaN with any sign and payload. `abs`, `neg` and `copysign` only change
+/// the sign bit, so their results are already deterministic.
+fn is_float_arithmetic(inst: &Instruction) -> bool {
+    inst.prefix().is_none() && matches!(inst.opcode(), 0x8D..=0x97 | 0x9B..=0xA5 | 0xB2..=0xBB)
+}
+
+/// Replaces a NaN with the canonical NaN, a positive quiet NaN with no payload.
+pub fn canonical_f32(x: f32) -> f32 {
+    if x.is_nan() {
+        f32::from_bits(0x7FC0_0000)
+    } else {
+        x
+    }
+}
+
+/// Replaces a NaN with the canonical NaN, a positive quiet NaN with no payload.
+pub fn canonical_f64(x: f64) -> f64 {
+    if x.is_nan() {
+        f64::from_bits(0x7FF8_0000_0000_0000)
+    } else {
+        x
+    }
+}
+
+fn canonicalize(value: Value) -> Value {
+    match value {
+        Value::F32(x) => Value::F32(canonical_f32(x)),
+        Value::F64(x) => Value::F64(canonical_f64(x)),
+        v => v,
+    }
+}
+
+fn is_nan(value: Value) -> bool {
+    match value {
+        Value::F32(x) => x.is_nan(),
+        Value::F64(x) => x.is_nan(),
+        _ => false,
+    }
+}
+
+fn exec_scalar(thread: &mut Thread, inst: &Instruction) -> Result<(), Trap> {
     use crate::Instruction::*;
 
-    match inst {
+    match *inst {
         I32Eqz => eqz::<u32>(thread),
         I32Eq => eq::<u32>(thread),
         I32Ne => ne::<u32>(thread),
@@ -138,19 +217,11 @@ pub fn exec(thread: &mut Thread, inst: Instruction) -> Result<(), Trap> {
         F32ReinterpretI32 => reinterpret::<f32, u32>(thread),
         F64ReinterpretI64 => reinterpret::<f64, u64>(thread),
 
-        x => return Err(format!("Instruction not implemented: {}", x).into()),
+        ref x => Err(Trap::new(TrapCause::Unimplemented)
+            .with_message(format!("Instruction not implemented: {}", x))),
     }
 }
 
-#[allow(unused_macros)]
-macro_rules! impl_panic {
-    ($name: ident) => {
-        fn $name<T>(_thread: &mut Thread) -> Result<(), Trap> {
-            unimplemented!(concat!("not yet implemented: ", stringify!($name)))
-        }
-    };
-}
-
 macro_rules! maybe_try {
     ($e: expr, true) => {
         $e?
@@ -337,3 +408,75 @@ where
     thread.stack_mut().push(res);
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        builder::{FuncBuilder, ModuleBuilder},
+        interp::{exec::test_util::run_body, Thread},
+        Instruction, Trap, ValType, Value,
+    };
+
+    fn run(strict: bool, body: Vec<Instruction>) -> Result<Vec<Value>, Trap> {
+        run_on(body, |thread| thread.set_trap_on_nan(strict))
+    }
+
+    fn run_on<F: FnOnce(&mut Thread)>(
+        body: Vec<Instruction>,
+        configure: F,
+    ) -> Result<Vec<Value>, Trap> {
+        let mut thread = Thread::new();
+        configure(&mut thread);
+        let func = FuncBuilder::new().result(ValType::I32).body(body);
+        run_body(ModuleBuilder::new(), func, &mut thread)
+    }
+
+    fn f64(v: f64) -> Instruction {
+        Instruction::F64Const(Value::F64(v))
+    }
+
+    #[test]
+    pub fn strict_mode_traps_on_nan_operands_and_results() {
+        use crate::Instruction::*;
+
+        // 0/0 produces a NaN, which the comparison swallows
+        let produce = vec![f64(0.0), f64(0.0), F64Div, f64(1.0), F64Lt];
+        assert_eq!(Ok(vec![Value::I32(0)]), run(false, produce.clone()));
+        assert_eq!(
+            "unexpected NaN",
+            run(true, produce).unwrap_err().cause().message()
+        );
+
+        // A NaN that didn't come from an arithmetic instruction is still rejected when it's used
+        let consume = vec![f64(f64::NAN), f64(1.0), F64Eq];
+        assert_eq!(
+            "unexpected NaN",
+            run(true, consume).unwrap_err().cause().message()
+        );
+
+        // NaN-free arithmetic is unaffected
+        let ok = vec![f64(1.0), f64(2.0), F64Add, f64(3.0), F64Eq];
+        assert_eq!(Ok(vec![Value::I32(1)]), run(true, ok));
+    }
+
+    #[test]
+    pub fn canonicalized_nans_are_positive_and_quiet() {
+        use crate::Instruction::*;
+
+        let canonical = |body| run_on(body, |thread| thread.set_canonicalize_nans(true));
+        let nan = F32Const(Value::F32(f32::from_bits(0xFFC0_0001)));
+
+        // Arithmetic produces the canonical NaN, whatever NaN went in
+        let add = vec![
+            nan.clone(),
+            F32Const(Value::F32(1.0)),
+            F32Add,
+            I32ReinterpretF32,
+        ];
+        assert_eq!(Ok(vec![Value::I32(0x7FC0_0000)]), canonical(add));
+
+        // Only the sign of a negated NaN changes
+        let neg = vec![nan, F32Neg, I32ReinterpretF32];
+        assert_eq!(Ok(vec![Value::I32(0x7FC0_0001)]), canonical(neg));
+    }
+}
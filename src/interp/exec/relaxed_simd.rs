@@ -0,0 +1,169 @@
+//! The relaxed SIMD proposal permits each of its instructions to produce one of a small set of
+//! results, chosen by the implementation. This interpreter always makes the same choice, which
+//! matches the corresponding deterministic SIMD instruction where there is one:
+//!
+//! * `relaxed_swizzle` selects zero for out-of-range indices, like `i8x16.swizzle`.
+//! * `relaxed_trunc` saturates out-of-range values and converts NaN to zero, like `trunc_sat`.
+//! * `relaxed_madd` and `relaxed_nmadd` round the product before adding, rather than fusing.
+//! * `relaxed_laneselect` selects each bit independently, like `v128.bitselect`.
+//! * `relaxed_min` and `relaxed_max` propagate NaN and order zeros, like `min` and `max`.
+//! * `relaxed_q15mulr_s` saturates the overflowing case, like `i16x8.q15mulr_sat_s`.
+//! * `relaxed_dot` treats the second operand as signed, and the 16-bit sums wrap.
+
+use crate::{interp::Thread, value::ops::FloatOps, Instruction, Trap, TrapCause};
+
+use super::simd::{binop, pack, pop_lanes, Lane};
+
+pub fn exec(thread: &mut Thread, inst: &Instruction) -> Result<(), Trap> {
+    use crate::Instruction::*;
+
+    match *inst {
+        I8x16RelaxedSwizzle => {
+            let indices = pop_lanes::<u8, 16>(thread)?;
+            let bytes = pop_lanes::<u8, 16>(thread)?;
+            let mut res = [0u8; 16];
+            for (i, idx) in indices.iter().enumerate() {
+                res[i] = bytes.get(*idx as usize).cloned().unwrap_or(0);
+            }
+            thread.stack_mut().push(pack(res));
+            Ok(())
+        }
+
+        I32x4RelaxedTruncF32x4S => trunc::<f32, i32, 4>(thread, |x| x as i32),
+        I32x4RelaxedTruncF32x4U => trunc::<f32, u32, 4>(thread, |x| x as u32),
+        I32x4RelaxedTruncF64x2SZero => trunc::<f64, i32, 2>(thread, |x| x as i32),
+        I32x4RelaxedTruncF64x2UZero => trunc::<f64, u32, 2>(thread, |x| x as u32),
+
+        F32x4RelaxedMadd => ternop::<f32, 4>(thread, |a, b, c| a * b + c),
+        F32x4RelaxedNmadd => ternop::<f32, 4>(thread, |a, b, c| -(a * b) + c),
+        F64x2RelaxedMadd => ternop::<f64, 2>(thread, |a, b, c| a * b + c),
+        F64x2RelaxedNmadd => ternop::<f64, 2>(thread, |a, b, c| -(a * b) + c),
+
+        I8x16RelaxedLaneselect
+        | I16x8RelaxedLaneselect
+        | I32x4RelaxedLaneselect
+        | I64x2RelaxedLaneselect => ternop::<u128, 1>(thread, |a, b, m| (a & m) | (b & !m)),
+
+        F32x4RelaxedMin => binop::<f32, 4>(thread, FloatOps::min),
+        F32x4RelaxedMax => binop::<f32, 4>(thread, FloatOps::max),
+        F64x2RelaxedMin => binop::<f64, 2>(thread, FloatOps::min),
+        F64x2RelaxedMax => binop::<f64, 2>(thread, FloatOps::max),
+
+        I16x8RelaxedQ15mulrS => binop::<i16, 8>(thread, |l, r| {
+            let product = (l as i32 * r as i32 + 0x4000) >> 15;
+            product.clamp(i16::MIN as i32, i16::MAX as i32) as i16
+        }),
+        I16x8RelaxedDotI8x16I7x16S => {
+            let right = pop_lanes::<i8, 16>(thread)?;
+            let left = pop_lanes::<i8, 16>(thread)?;
+            let mut res = [0i16; 8];
+            for (i, lane) in res.iter_mut().enumerate() {
+                let lo = left[2 * i] as i16 * right[2 * i] as i16;
+                let hi = left[2 * i + 1] as i16 * right[2 * i + 1] as i16;
+                *lane = lo.wrapping_add(hi);
+            }
+            thread.stack_mut().push(pack(res));
+            Ok(())
+        }
+        I32x4RelaxedDotI8x16I7x16AddS => {
+            let acc = pop_lanes::<i32, 4>(thread)?;
+            let right = pop_lanes::<i8, 16>(thread)?;
+            let left = pop_lanes::<i8, 16>(thread)?;
+            let mut res = [0i32; 4];
+            for (i, lane) in res.iter_mut().enumerate() {
+                let sum: i32 = (4 * i..4 * i + 4)
+                    .map(|j| left[j] as i32 * right[j] as i32)
+                    .sum();
+                *lane = sum.wrapping_add(acc[i]);
+            }
+            thread.stack_mut().push(pack(res));
+            Ok(())
+        }
+
+        ref x => Err(Trap::new(TrapCause::InvalidCode)
+            .with_message(format!("Not a relaxed SIMD instruction: {}", x))),
+    }
+}
+
+/// Applies `f` lane-wise to three operands, the last of which is on the top of the stack.
+fn ternop<T: Lane, const N: usize>(
+    thread: &mut Thread,
+    f: impl Fn(T, T, T) -> T,
+) -> Result<(), Trap> {
+    let c = pop_lanes::<T, N>(thread)?;
+    let b = pop_lanes::<T, N>(thread)?;
+    let mut lanes = pop_lanes::<T, N>(thread)?;
+    for (i, lane) in lanes.iter_mut().enumerate() {
+        *lane = f(*lane, b[i], c[i]);
+    }
+    thread.stack_mut().push(pack(lanes));
+    Ok(())
+}
+
+/// Converts `N` float lanes to 32-bit integer lanes, zeroing any remaining lanes.
+///
+/// Rust's float-to-integer casts saturate and map NaN to zero.
+fn trunc<S: Lane, T: Lane, const N: usize>(
+    thread: &mut Thread,
+    f: impl Fn(S) -> T,
+) -> Result<(), Trap> {
+    let source = pop_lanes::<S, N>(thread)?;
+    let mut lanes = [T::default(); 4];
+    for (lane, src) in lanes.iter_mut().zip(source.iter()) {
+        *lane = f(*src);
+    }
+    thread.stack_mut().push(pack(lanes));
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        builder::{FuncBuilder, ModuleBuilder},
+        interp::{exec::test_util::run_body, Thread},
+        Instruction, ValType, Value,
+    };
+
+    fn run(body: Vec<Instruction>) -> Value {
+        let func = FuncBuilder::new().result(ValType::V128).body(body);
+        run_body(ModuleBuilder::new(), func, &mut Thread::new()).unwrap()[0]
+    }
+
+    fn f32x4(lanes: [f32; 4]) -> Instruction {
+        Instruction::V128Const(Value::V128(super::pack(lanes)))
+    }
+
+    #[test]
+    pub fn relaxed_trunc_saturates_and_zeroes_nan() {
+        let res = run(vec![
+            f32x4([1.5, f32::NAN, 1e20, -1e20]),
+            Instruction::I32x4RelaxedTruncF32x4S,
+        ]);
+        let expected = super::pack([1i32, 0, i32::MAX, i32::MIN]);
+        assert_eq!(Value::V128(expected), res);
+    }
+
+    #[test]
+    pub fn relaxed_madd_multiplies_then_adds() {
+        let res = run(vec![
+            f32x4([1.0, 2.0, 3.0, 4.0]),
+            f32x4([2.0, 2.0, 2.0, 2.0]),
+            f32x4([0.5, 0.5, 0.5, 0.5]),
+            Instruction::F32x4RelaxedMadd,
+        ]);
+        let expected = super::pack([2.5f32, 4.5, 6.5, 8.5]);
+        assert_eq!(Value::V128(expected), res);
+    }
+
+    #[test]
+    pub fn relaxed_nmadd_negates_the_product_then_adds() {
+        let res = run(vec![
+            f32x4([1.0, 2.0, 3.0, 4.0]),
+            f32x4([2.0, 2.0, 2.0, 2.0]),
+            f32x4([0.5, 0.5, 0.5, 0.5]),
+            Instruction::F32x4RelaxedNmadd,
+        ]);
+        let expected = super::pack([-1.5f32, -3.5, -5.5, -7.5]);
+        assert_eq!(Value::V128(expected), res);
+    }
+}
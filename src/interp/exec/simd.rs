@@ -0,0 +1,524 @@
+use std::mem;
+
+use crate::{
+    hosting::Host,
+    interp::{
+        exec::{memory, numops},
+        Thread,
+    },
+    value::ops::FloatOps,
+    FromValue, Instruction, Trap, TrapCause, Value,
+};
+
+pub fn exec(thread: &mut Thread, host: &mut Host, inst: &Instruction) -> Result<(), Trap> {
+    use crate::Instruction::*;
+
+    exec_lanes(thread, host, inst)?;
+    if !thread.canonicalize_nans() {
+        return Ok(());
+    }
+    match *inst {
+        F32x4Sqrt | F32x4Add | F32x4Sub | F32x4Mul | F32x4Div | F32x4Min | F32x4Max => {
+            unop::<f32, 4>(thread, numops::canonical_f32)
+        }
+        F64x2Sqrt | F64x2Add | F64x2Sub | F64x2Mul | F64x2Div | F64x2Min | F64x2Max => {
+            unop::<f64, 2>(thread, numops::canonical_f64)
+        }
+        _ => Ok(()),
+    }
+}
+
+fn exec_lanes(thread: &mut Thread, host: &mut Host, inst: &Instruction) -> Result<(), Trap> {
+    use crate::Instruction::*;
+
+    match *inst {
+        V128Load(_, offset) => load::<u8, u8, 16>(thread, host, offset),
+        V128Load8x8S(_, offset) => load::<i8, i16, 8>(thread, host, offset),
+        V128Load8x8U(_, offset) => load::<u8, u16, 8>(thread, host, offset),
+        V128Load16x4S(_, offset) => load::<i16, i32, 4>(thread, host, offset),
+        V128Load16x4U(_, offset) => load::<u16, u32, 4>(thread, host, offset),
+        V128Load32x2S(_, offset) => load::<i32, i64, 2>(thread, host, offset),
+        V128Load32x2U(_, offset) => load::<u32, u64, 2>(thread, host, offset),
+        V128Load8Splat(_, offset) => load_splat::<u8, 16>(thread, host, offset),
+        V128Load16Splat(_, offset) => load_splat::<u16, 8>(thread, host, offset),
+        V128Load32Splat(_, offset) => load_splat::<u32, 4>(thread, host, offset),
+        V128Load64Splat(_, offset) => load_splat::<u64, 2>(thread, host, offset),
+        V128Store(_, offset) => {
+            let val = thread.stack_mut().pop_as::<u128>()?;
+            memory::store(thread, host, offset, &val.to_le_bytes())
+        }
+        V128Const(v) => {
+            thread.push(v);
+            Ok(())
+        }
+
+        I8x16Shuffle(ref lanes) => {
+            let (left, right) = thread.stack_mut().pop_pair_as::<u128, u128>()?;
+            let mut concat = [0u8; 32];
+            concat[..16].copy_from_slice(&left.to_le_bytes());
+            concat[16..].copy_from_slice(&right.to_le_bytes());
+
+            let mut res = [0u8; 16];
+            for (i, lane) in lanes.lanes().iter().enumerate() {
+                // Lanes are checked when the instruction is read
+                res[i] = concat[*lane as usize];
+            }
+            thread.stack_mut().push(u128::from_le_bytes(res));
+            Ok(())
+        }
+        I8x16Swizzle => {
+            let (left, right) = thread.stack_mut().pop_pair_as::<u128, u128>()?;
+            let (bytes, indices) = (left.to_le_bytes(), right.to_le_bytes());
+            let mut res = [0u8; 16];
+            for (i, idx) in indices.iter().enumerate() {
+                // Out-of-range indices select zero
+                res[i] = bytes.get(*idx as usize).cloned().unwrap_or(0);
+            }
+            thread.stack_mut().push(u128::from_le_bytes(res));
+            Ok(())
+        }
+
+        I8x16Splat => splat::<u32, u8, 16>(thread, |x| x as u8),
+        I16x8Splat => splat::<u32, u16, 8>(thread, |x| x as u16),
+        I32x4Splat => splat::<u32, u32, 4>(thread, |x| x),
+        I64x2Splat => splat::<u64, u64, 2>(thread, |x| x),
+        F32x4Splat => splat::<f32, f32, 4>(thread, |x| x),
+        F64x2Splat => splat::<f64, f64, 2>(thread, |x| x),
+
+        I8x16ExtractLaneS(lane) => extract::<i8, 16, _>(thread, lane, |x| x as i32),
+        I8x16ExtractLaneU(lane) => extract::<u8, 16, _>(thread, lane, |x| x as u32),
+        I16x8ExtractLaneS(lane) => extract::<i16, 8, _>(thread, lane, |x| x as i32),
+        I16x8ExtractLaneU(lane) => extract::<u16, 8, _>(thread, lane, |x| x as u32),
+        I32x4ExtractLane(lane) => extract::<u32, 4, _>(thread, lane, |x| x),
+        I64x2ExtractLane(lane) => extract::<u64, 2, _>(thread, lane, |x| x),
+        F32x4ExtractLane(lane) => extract::<f32, 4, _>(thread, lane, |x| x),
+        F64x2ExtractLane(lane) => extract::<f64, 2, _>(thread, lane, |x| x),
+
+        I8x16ReplaceLane(lane) => replace::<u32, u8, 16>(thread, lane, |x| x as u8),
+        I16x8ReplaceLane(lane) => replace::<u32, u16, 8>(thread, lane, |x| x as u16),
+        I32x4ReplaceLane(lane) => replace::<u32, u32, 4>(thread, lane, |x| x),
+        I64x2ReplaceLane(lane) => replace::<u64, u64, 2>(thread, lane, |x| x),
+        F32x4ReplaceLane(lane) => replace::<f32, f32, 4>(thread, lane, |x| x),
+        F64x2ReplaceLane(lane) => replace::<f64, f64, 2>(thread, lane, |x| x),
+
+        I8x16Eq => cmpop::<u8, 16>(thread, |l, r| l == r),
+        I8x16Ne => cmpop::<u8, 16>(thread, |l, r| l != r),
+        I8x16LtS => cmpop::<i8, 16>(thread, |l, r| l < r),
+        I8x16LtU => cmpop::<u8, 16>(thread, |l, r| l < r),
+        I8x16GtS => cmpop::<i8, 16>(thread, |l, r| l > r),
+        I8x16GtU => cmpop::<u8, 16>(thread, |l, r| l > r),
+        I8x16LeS => cmpop::<i8, 16>(thread, |l, r| l <= r),
+        I8x16LeU => cmpop::<u8, 16>(thread, |l, r| l <= r),
+        I8x16GeS => cmpop::<i8, 16>(thread, |l, r| l >= r),
+        I8x16GeU => cmpop::<u8, 16>(thread, |l, r| l >= r),
+
+        I16x8Eq => cmpop::<u16, 8>(thread, |l, r| l == r),
+        I16x8Ne => cmpop::<u16, 8>(thread, |l, r| l != r),
+        I16x8LtS => cmpop::<i16, 8>(thread, |l, r| l < r),
+        I16x8LtU => cmpop::<u16, 8>(thread, |l, r| l < r),
+        I16x8GtS => cmpop::<i16, 8>(thread, |l, r| l > r),
+        I16x8GtU => cmpop::<u16, 8>(thread, |l, r| l > r),
+        I16x8LeS => cmpop::<i16, 8>(thread, |l, r| l <= r),
+        I16x8LeU => cmpop::<u16, 8>(thread, |l, r| l <= r),
+        I16x8GeS => cmpop::<i16, 8>(thread, |l, r| l >= r),
+        I16x8GeU => cmpop::<u16, 8>(thread, |l, r| l >= r),
+
+        I32x4Eq => cmpop::<u32, 4>(thread, |l, r| l == r),
+        I32x4Ne => cmpop::<u32, 4>(thread, |l, r| l != r),
+        I32x4LtS => cmpop::<i32, 4>(thread, |l, r| l < r),
+        I32x4LtU => cmpop::<u32, 4>(thread, |l, r| l < r),
+        I32x4GtS => cmpop::<i32, 4>(thread, |l, r| l > r),
+        I32x4GtU => cmpop::<u32, 4>(thread, |l, r| l > r),
+        I32x4LeS => cmpop::<i32, 4>(thread, |l, r| l <= r),
+        I32x4LeU => cmpop::<u32, 4>(thread, |l, r| l <= r),
+        I32x4GeS => cmpop::<i32, 4>(thread, |l, r| l >= r),
+        I32x4GeU => cmpop::<u32, 4>(thread, |l, r| l >= r),
+
+        I64x2Eq => cmpop::<u64, 2>(thread, |l, r| l == r),
+        I64x2Ne => cmpop::<u64, 2>(thread, |l, r| l != r),
+        I64x2LtS => cmpop::<i64, 2>(thread, |l, r| l < r),
+        I64x2GtS => cmpop::<i64, 2>(thread, |l, r| l > r),
+        I64x2LeS => cmpop::<i64, 2>(thread, |l, r| l <= r),
+        I64x2GeS => cmpop::<i64, 2>(thread, |l, r| l >= r),
+
+        F32x4Eq => cmpop::<f32, 4>(thread, |l, r| l == r),
+        F32x4Ne => cmpop::<f32, 4>(thread, |l, r| l != r),
+        F32x4Lt => cmpop::<f32, 4>(thread, |l, r| l < r),
+        F32x4Gt => cmpop::<f32, 4>(thread, |l, r| l > r),
+        F32x4Le => cmpop::<f32, 4>(thread, |l, r| l <= r),
+        F32x4Ge => cmpop::<f32, 4>(thread, |l, r| l >= r),
+
+        F64x2Eq => cmpop::<f64, 2>(thread, |l, r| l == r),
+        F64x2Ne => cmpop::<f64, 2>(thread, |l, r| l != r),
+        F64x2Lt => cmpop::<f64, 2>(thread, |l, r| l < r),
+        F64x2Gt => cmpop::<f64, 2>(thread, |l, r| l > r),
+        F64x2Le => cmpop::<f64, 2>(thread, |l, r| l <= r),
+        F64x2Ge => cmpop::<f64, 2>(thread, |l, r| l >= r),
+
+        V128Not => unop::<u128, 1>(thread, |x| !x),
+        V128And => binop::<u128, 1>(thread, |l, r| l & r),
+        V128Andnot => binop::<u128, 1>(thread, |l, r| l & !r),
+        V128Or => binop::<u128, 1>(thread, |l, r| l | r),
+        V128Xor => binop::<u128, 1>(thread, |l, r| l ^ r),
+        V128Bitselect => {
+            let mask = thread.stack_mut().pop_as::<u128>()?;
+            let (left, right) = thread.stack_mut().pop_pair_as::<u128, u128>()?;
+            thread.stack_mut().push((left & mask) | (right & !mask));
+            Ok(())
+        }
+        V128AnyTrue => {
+            let val = thread.stack_mut().pop_as::<u128>()?;
+            thread.stack_mut().push(val != 0);
+            Ok(())
+        }
+
+        I8x16Abs => unop::<i8, 16>(thread, |x| x.wrapping_abs()),
+        I8x16Neg => unop::<i8, 16>(thread, |x| x.wrapping_neg()),
+        I8x16Popcnt => unop::<u8, 16>(thread, |x| x.count_ones() as u8),
+        I8x16AllTrue => all_true::<u8, 16>(thread),
+        I8x16Bitmask => bitmask::<i8, 16>(thread, |x| x < 0),
+        I8x16Shl => shiftop::<u8, 16>(thread, |x, s| x.wrapping_shl(s)),
+        I8x16ShrS => shiftop::<i8, 16>(thread, |x, s| x.wrapping_shr(s)),
+        I8x16ShrU => shiftop::<u8, 16>(thread, |x, s| x.wrapping_shr(s)),
+        I8x16Add => binop::<u8, 16>(thread, |l, r| l.wrapping_add(r)),
+        I8x16AddSatS => binop::<i8, 16>(thread, |l, r| l.saturating_add(r)),
+        I8x16AddSatU => binop::<u8, 16>(thread, |l, r| l.saturating_add(r)),
+        I8x16Sub => binop::<u8, 16>(thread, |l, r| l.wrapping_sub(r)),
+        I8x16SubSatS => binop::<i8, 16>(thread, |l, r| l.saturating_sub(r)),
+        I8x16SubSatU => binop::<u8, 16>(thread, |l, r| l.saturating_sub(r)),
+        I8x16MinS => binop::<i8, 16>(thread, |l, r| l.min(r)),
+        I8x16MinU => binop::<u8, 16>(thread, |l, r| l.min(r)),
+        I8x16MaxS => binop::<i8, 16>(thread, |l, r| l.max(r)),
+        I8x16MaxU => binop::<u8, 16>(thread, |l, r| l.max(r)),
+        I8x16AvgrU => binop::<u8, 16>(thread, |l, r| (l as u16 + r as u16).div_ceil(2) as u8),
+
+        I16x8Abs => unop::<i16, 8>(thread, |x| x.wrapping_abs()),
+        I16x8Neg => unop::<i16, 8>(thread, |x| x.wrapping_neg()),
+        I16x8AllTrue => all_true::<u16, 8>(thread),
+        I16x8Bitmask => bitmask::<i16, 8>(thread, |x| x < 0),
+        I16x8Shl => shiftop::<u16, 8>(thread, |x, s| x.wrapping_shl(s)),
+        I16x8ShrS => shiftop::<i16, 8>(thread, |x, s| x.wrapping_shr(s)),
+        I16x8ShrU => shiftop::<u16, 8>(thread, |x, s| x.wrapping_shr(s)),
+        I16x8Add => binop::<u16, 8>(thread, |l, r| l.wrapping_add(r)),
+        I16x8AddSatS => binop::<i16, 8>(thread, |l, r| l.saturating_add(r)),
+        I16x8AddSatU => binop::<u16, 8>(thread, |l, r| l.saturating_add(r)),
+        I16x8Sub => binop::<u16, 8>(thread, |l, r| l.wrapping_sub(r)),
+        I16x8SubSatS => binop::<i16, 8>(thread, |l, r| l.saturating_sub(r)),
+        I16x8SubSatU => binop::<u16, 8>(thread, |l, r| l.saturating_sub(r)),
+        I16x8Mul => binop::<u16, 8>(thread, |l, r| l.wrapping_mul(r)),
+        I16x8MinS => binop::<i16, 8>(thread, |l, r| l.min(r)),
+        I16x8MinU => binop::<u16, 8>(thread, |l, r| l.min(r)),
+        I16x8MaxS => binop::<i16, 8>(thread, |l, r| l.max(r)),
+        I16x8MaxU => binop::<u16, 8>(thread, |l, r| l.max(r)),
+        I16x8AvgrU => binop::<u16, 8>(thread, |l, r| (l as u32 + r as u32).div_ceil(2) as u16),
+
+        I32x4Abs => unop::<i32, 4>(thread, |x| x.wrapping_abs()),
+        I32x4Neg => unop::<i32, 4>(thread, |x| x.wrapping_neg()),
+        I32x4AllTrue => all_true::<u32, 4>(thread),
+        I32x4Bitmask => bitmask::<i32, 4>(thread, |x| x < 0),
+        I32x4Shl => shiftop::<u32, 4>(thread, |x, s| x.wrapping_shl(s)),
+        I32x4ShrS => shiftop::<i32, 4>(thread, |x, s| x.wrapping_shr(s)),
+        I32x4ShrU => shiftop::<u32, 4>(thread, |x, s| x.wrapping_shr(s)),
+        I32x4Add => binop::<u32, 4>(thread, |l, r| l.wrapping_add(r)),
+        I32x4Sub => binop::<u32, 4>(thread, |l, r| l.wrapping_sub(r)),
+        I32x4Mul => binop::<u32, 4>(thread, |l, r| l.wrapping_mul(r)),
+        I32x4MinS => binop::<i32, 4>(thread, |l, r| l.min(r)),
+        I32x4MinU => binop::<u32, 4>(thread, |l, r| l.min(r)),
+        I32x4MaxS => binop::<i32, 4>(thread, |l, r| l.max(r)),
+        I32x4MaxU => binop::<u32, 4>(thread, |l, r| l.max(r)),
+
+        I64x2Abs => unop::<i64, 2>(thread, |x| x.wrapping_abs()),
+        I64x2Neg => unop::<i64, 2>(thread, |x| x.wrapping_neg()),
+        I64x2AllTrue => all_true::<u64, 2>(thread),
+        I64x2Bitmask => bitmask::<i64, 2>(thread, |x| x < 0),
+        I64x2Shl => shiftop::<u64, 2>(thread, |x, s| x.wrapping_shl(s)),
+        I64x2ShrS => shiftop::<i64, 2>(thread, |x, s| x.wrapping_shr(s)),
+        I64x2ShrU => shiftop::<u64, 2>(thread, |x, s| x.wrapping_shr(s)),
+        I64x2Add => binop::<u64, 2>(thread, |l, r| l.wrapping_add(r)),
+        I64x2Sub => binop::<u64, 2>(thread, |l, r| l.wrapping_sub(r)),
+        I64x2Mul => binop::<u64, 2>(thread, |l, r| l.wrapping_mul(r)),
+
+        F32x4Abs => unop::<f32, 4>(thread, FloatOps::abs),
+        F32x4Neg => unop::<f32, 4>(thread, FloatOps::neg),
+        F32x4Sqrt => unop::<f32, 4>(thread, FloatOps::sqrt),
+        F32x4Add => binop::<f32, 4>(thread, |l, r| l + r),
+        F32x4Sub => binop::<f32, 4>(thread, |l, r| l - r),
+        F32x4Mul => binop::<f32, 4>(thread, |l, r| l * r),
+        F32x4Div => binop::<f32, 4>(thread, |l, r| l / r),
+        F32x4Min => binop::<f32, 4>(thread, FloatOps::min),
+        F32x4Max => binop::<f32, 4>(thread, FloatOps::max),
+
+        F64x2Abs => unop::<f64, 2>(thread, FloatOps::abs),
+        F64x2Neg => unop::<f64, 2>(thread, FloatOps::neg),
+        F64x2Sqrt => unop::<f64, 2>(thread, FloatOps::sqrt),
+        F64x2Add => binop::<f64, 2>(thread, |l, r| l + r),
+        F64x2Sub => binop::<f64, 2>(thread, |l, r| l - r),
+        F64x2Mul => binop::<f64, 2>(thread, |l, r| l * r),
+        F64x2Div => binop::<f64, 2>(thread, |l, r| l / r),
+        F64x2Min => binop::<f64, 2>(thread, FloatOps::min),
+        F64x2Max => binop::<f64, 2>(thread, FloatOps::max),
+
+        #[cfg(feature = "relaxed-simd")]
+        ref x if x.opcode() >= 0x100 => super::relaxed_simd::exec(thread, x),
+
+        ref x => Err(Trap::new(TrapCause::Unimplemented)
+            .with_message(format!("Instruction not implemented: {}", x))),
+    }
+}
+
+/// A scalar type that can occupy a lane of a `v128` value.
+pub trait Lane: Copy + Default {
+    const WIDTH: usize;
+
+    fn read(bytes: &[u8]) -> Self;
+    fn write(self, bytes: &mut [u8]);
+}
+
+macro_rules! impl_lane {
+    ($t: ty) => {
+        impl Lane for $t {
+            const WIDTH: usize = mem::size_of::<$t>();
+
+            fn read(bytes: &[u8]) -> $t {
+                let mut buf = [0u8; mem::size_of::<$t>()];
+                buf.copy_from_slice(bytes);
+                <$t>::from_le_bytes(buf)
+            }
+
+            fn write(self, bytes: &mut [u8]) {
+                bytes.copy_from_slice(&self.to_le_bytes())
+            }
+        }
+    };
+}
+
+impl_lane!(i8);
+impl_lane!(u8);
+impl_lane!(i16);
+impl_lane!(u16);
+impl_lane!(i32);
+impl_lane!(u32);
+impl_lane!(i64);
+impl_lane!(u64);
+impl_lane!(f32);
+impl_lane!(f64);
+impl_lane!(u128);
+
+/// Splits the bytes of a vector into `N` lanes of type `T` (lane 0 is the lowest address).
+fn unpack<T: Lane, const N: usize>(bytes: &[u8]) -> [T; N] {
+    let mut lanes = [T::default(); N];
+    for (i, lane) in lanes.iter_mut().enumerate() {
+        *lane = T::read(&bytes[i * T::WIDTH..(i + 1) * T::WIDTH]);
+    }
+    lanes
+}
+
+pub fn pack<T: Lane, const N: usize>(lanes: [T; N]) -> u128 {
+    let mut bytes = [0u8; 16];
+    for (i, lane) in lanes.iter().enumerate() {
+        lane.write(&mut bytes[i * T::WIDTH..(i + 1) * T::WIDTH]);
+    }
+    u128::from_le_bytes(bytes)
+}
+
+pub fn pop_lanes<T: Lane, const N: usize>(thread: &mut Thread) -> Result<[T; N], Trap> {
+    let val = thread.stack_mut().pop_as::<u128>()?;
+    Ok(unpack(&val.to_le_bytes()))
+}
+
+fn check_lane<const N: usize>(lane: u8) -> Result<usize, Trap> {
+    if (lane as usize) < N {
+        Ok(lane as usize)
+    } else {
+        Err(Trap::new(TrapCause::InvalidCode).with_message(format!("Invalid lane index: {}", lane)))
+    }
+}
+
+/// Loads `N` lanes of type `S` from memory, extending each to type `T`.
+fn load<S: Lane, T: Lane + From<S>, const N: usize>(
+    thread: &mut Thread,
+    host: &Host,
+    offset: u64,
+) -> Result<(), Trap> {
+    let mut bytes = [0u8; 16];
+    memory::load(thread, host, offset, &mut bytes[..N * S::WIDTH])?;
+
+    let source: [S; N] = unpack(&bytes);
+    let mut lanes = [T::default(); N];
+    for (lane, src) in lanes.iter_mut().zip(source.iter()) {
+        *lane = T::from(*src);
+    }
+    thread.stack_mut().push(pack(lanes));
+    Ok(())
+}
+
+fn load_splat<T: Lane, const N: usize>(
+    thread: &mut Thread,
+    host: &Host,
+    offset: u64,
+) -> Result<(), Trap> {
+    let mut bytes = [0u8; 8];
+    memory::load(thread, host, offset, &mut bytes[..T::WIDTH])?;
+    let val = T::read(&bytes[..T::WIDTH]);
+    thread.stack_mut().push(pack([val; N]));
+    Ok(())
+}
+
+fn splat<S: FromValue, T: Lane, const N: usize>(
+    thread: &mut Thread,
+    f: impl Fn(S) -> T,
+) -> Result<(), Trap> {
+    let val = thread.stack_mut().pop_as::<S>()?;
+    thread.stack_mut().push(pack([f(val); N]));
+    Ok(())
+}
+
+fn extract<T: Lane, const N: usize, R>(
+    thread: &mut Thread,
+    lane: u8,
+    f: impl Fn(T) -> R,
+) -> Result<(), Trap>
+where
+    Value: From<R>,
+{
+    let lane = check_lane::<N>(lane)?;
+    let lanes = pop_lanes::<T, N>(thread)?;
+    thread.stack_mut().push(f(lanes[lane]));
+    Ok(())
+}
+
+fn replace<S: FromValue, T: Lane, const N: usize>(
+    thread: &mut Thread,
+    lane: u8,
+    f: impl Fn(S) -> T,
+) -> Result<(), Trap> {
+    let lane = check_lane::<N>(lane)?;
+    let val = thread.stack_mut().pop_as::<S>()?;
+    let mut lanes = pop_lanes::<T, N>(thread)?;
+    lanes[lane] = f(val);
+    thread.stack_mut().push(pack(lanes));
+    Ok(())
+}
+
+fn unop<T: Lane, const N: usize>(thread: &mut Thread, f: impl Fn(T) -> T) -> Result<(), Trap> {
+    let mut lanes = pop_lanes::<T, N>(thread)?;
+    for lane in lanes.iter_mut() {
+        *lane = f(*lane);
+    }
+    thread.stack_mut().push(pack(lanes));
+    Ok(())
+}
+
+pub fn binop<T: Lane, const N: usize>(
+    thread: &mut Thread,
+    f: impl Fn(T, T) -> T,
+) -> Result<(), Trap> {
+    let right = pop_lanes::<T, N>(thread)?;
+    let mut lanes = pop_lanes::<T, N>(thread)?;
+    for (lane, r) in lanes.iter_mut().zip(right.iter()) {
+        *lane = f(*lane, *r);
+    }
+    thread.stack_mut().push(pack(lanes));
+    Ok(())
+}
+
+/// Compares each lane, producing a lane of all ones for `true` and all zeros for `false`.
+fn cmpop<T: Lane, const N: usize>(
+    thread: &mut Thread,
+    f: impl Fn(T, T) -> bool,
+) -> Result<(), Trap> {
+    let right = pop_lanes::<T, N>(thread)?;
+    let left = pop_lanes::<T, N>(thread)?;
+    let mut bytes = [0u8; 16];
+    for (i, (l, r)) in left.iter().zip(right.iter()).enumerate() {
+        if f(*l, *r) {
+            for byt in bytes[i * T::WIDTH..(i + 1) * T::WIDTH].iter_mut() {
+                *byt = 0xFF;
+            }
+        }
+    }
+    thread.stack_mut().push(u128::from_le_bytes(bytes));
+    Ok(())
+}
+
+/// Shifts each lane by the popped shift count, modulo the lane width in bits.
+fn shiftop<T: Lane, const N: usize>(
+    thread: &mut Thread,
+    f: impl Fn(T, u32) -> T,
+) -> Result<(), Trap> {
+    let shift = thread.stack_mut().pop_as::<u32>()? % (T::WIDTH as u32 * 8);
+    unop::<T, N>(thread, |x| f(x, shift))
+}
+
+fn all_true<T: Lane + PartialEq, const N: usize>(thread: &mut Thread) -> Result<(), Trap> {
+    let lanes = pop_lanes::<T, N>(thread)?;
+    let res = lanes.iter().all(|l| *l != T::default());
+    thread.stack_mut().push(res);
+    Ok(())
+}
+
+fn bitmask<T: Lane, const N: usize>(
+    thread: &mut Thread,
+    is_negative: impl Fn(T) -> bool,
+) -> Result<(), Trap> {
+    let lanes = pop_lanes::<T, N>(thread)?;
+    let mut res = 0u32;
+    for (i, lane) in lanes.iter().enumerate() {
+        if is_negative(*lane) {
+            res |= 1 << i;
+        }
+    }
+    thread.stack_mut().push(res);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        builder::{FuncBuilder, ModuleBuilder},
+        interp::{exec::test_util::run_body, Thread},
+        Instruction, ValType, Value,
+    };
+
+    fn run(body: Vec<Instruction>, result: ValType) -> Value {
+        let func = FuncBuilder::new().result(result).body(body);
+        run_body(ModuleBuilder::new(), func, &mut Thread::new()).unwrap()[0]
+    }
+
+    #[test]
+    pub fn i32x4_add_adds_lanes() {
+        let lanes = |a: u32, b: u32, c: u32, d: u32| {
+            Value::V128(a as u128 | (b as u128) << 32 | (c as u128) << 64 | (d as u128) << 96)
+        };
+        let res = run(
+            vec![
+                Instruction::V128Const(lanes(1, 2, 3, u32::MAX)),
+                Instruction::V128Const(lanes(10, 20, 30, 2)),
+                Instruction::I32x4Add,
+            ],
+            ValType::V128,
+        );
+        assert_eq!(lanes(11, 22, 33, 1), res);
+    }
+
+    #[test]
+    pub fn i8x16_shuffle_selects_from_both_operands() {
+        let left = u128::from_le_bytes([0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15]);
+        let right = u128::from_le_bytes([
+            16, 17, 18, 19, 20, 21, 22, 23, 24, 25, 26, 27, 28, 29, 30, 31,
+        ]);
+        let mut cursor =
+            ::std::io::Cursor::new([31u8, 0, 30, 1, 29, 2, 28, 3, 27, 4, 26, 5, 25, 6, 24, 7]);
+        let lanes = crate::instruction::ShuffleLanes::read(&mut cursor).unwrap();
+        let res = run(
+            vec![
+                Instruction::V128Const(Value::V128(left)),
+                Instruction::V128Const(Value::V128(right)),
+                Instruction::I8x16Shuffle(lanes),
+                Instruction::I8x16ExtractLaneU(2),
+            ],
+            ValType::I32,
+        );
+        assert_eq!(Value::I32(30), res);
+    }
+}
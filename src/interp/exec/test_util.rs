@@ -0,0 +1,20 @@
+use crate::{
+    builder::{FuncBuilder, ModuleBuilder},
+    hosting::Host,
+    interp::Thread,
+    Trap, Value,
+};
+
+/// Runs `func` on `thread`, after exporting it as "test" from `module` and instantiating that
+/// in a new host. `module` supplies anything else the body needs, such as a memory or types.
+pub fn run_body(
+    module: ModuleBuilder,
+    func: FuncBuilder,
+    thread: &mut Thread,
+) -> Result<Vec<Value>, Trap> {
+    let module = module.func(func.export_as("test")).build();
+    let mut host = Host::new();
+    let addr = host.instantiate("test", module).unwrap();
+    let func = host.get_module(addr).unwrap().export_func("test").unwrap();
+    thread.call(&mut host, addr, func, &[])
+}
@@ -0,0 +1,280 @@
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+use crate::{hosting::MemInst, interp::ObserverHandle};
+
+/// How the pixels of a frame are stored.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum PixelFormat {
+    Gray8,
+    Rgb565,
+    Rgb888,
+    Rgba8888,
+}
+
+impl PixelFormat {
+    pub fn bytes_per_pixel(self) -> usize {
+        match self {
+            PixelFormat::Gray8 => 1,
+            PixelFormat::Rgb565 => 2,
+            PixelFormat::Rgb888 => 3,
+            PixelFormat::Rgba8888 => 4,
+        }
+    }
+}
+
+/// Where a guest keeps a framebuffer in its memory: `height` rows of `width` pixels, starting
+/// at `offset`, with each row starting `stride` bytes after the one before.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct FrameLayout {
+    offset: usize,
+    width: usize,
+    height: usize,
+    stride: usize,
+    format: PixelFormat,
+}
+
+impl FrameLayout {
+    /// Describes a framebuffer whose rows are packed, one straight after the other.
+    pub fn new(offset: usize, width: usize, height: usize, format: PixelFormat) -> FrameLayout {
+        FrameLayout {
+            offset,
+            width,
+            height,
+            stride: width * format.bytes_per_pixel(),
+            format,
+        }
+    }
+
+    /// Sets the number of bytes from the start of one row to the start of the next, for
+    /// framebuffers with padding between rows.
+    pub fn with_stride(mut self, stride: usize) -> FrameLayout {
+        self.stride = stride;
+        self
+    }
+
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn stride(&self) -> usize {
+        self.stride
+    }
+
+    pub fn format(&self) -> PixelFormat {
+        self.format
+    }
+
+    /// Gets the number of bytes in a row, without padding.
+    pub fn row_len(&self) -> usize {
+        self.width * self.format.bytes_per_pixel()
+    }
+
+    /// Gets a boolean indicating if the framebuffer lies within a memory of `len` bytes.
+    pub fn fits(&self, len: usize) -> bool {
+        if self.height == 0 {
+            return self.offset <= len;
+        }
+        self.stride >= self.row_len()
+            && self
+                .stride
+                .checked_mul(self.height - 1)
+                .and_then(|n| n.checked_add(self.row_len()))
+                .and_then(|n| n.checked_add(self.offset))
+                .is_some_and(|end| end <= len)
+    }
+
+    /// Copies the framebuffer out of `mem` once the thread `observer` came from is quiescent,
+    /// see [`ObserverHandle`]. Returns `None` if the framebuffer doesn't fit in `mem`.
+    pub fn capture(&self, observer: &ObserverHandle, mem: &MemInst) -> Option<Frame> {
+        if !self.fits(mem.memory().len()) {
+            return None;
+        }
+        let row_len = self.row_len();
+        let mut pixels = vec![0; row_len * self.height];
+        observer.pause(|| {
+            // Other threads may still write a shared memory, so it isn't borrowed
+            for (row, pixels) in pixels.chunks_mut(row_len.max(1)).enumerate() {
+                let start = self.offset + row * self.stride;
+                mem.memory().read(start, pixels);
+            }
+        });
+        Some(Frame {
+            layout: *self,
+            pixels,
+            sequence: 0,
+            captured: Instant::now(),
+        })
+    }
+}
+
+/// A copy of a framebuffer, with its rows packed one after the other.
+#[derive(Clone, Debug)]
+pub struct Frame {
+    layout: FrameLayout,
+    pixels: Vec<u8>,
+    sequence: u64,
+    captured: Instant,
+}
+
+impl Frame {
+    pub fn layout(&self) -> &FrameLayout {
+        &self.layout
+    }
+
+    pub fn pixels(&self) -> &[u8] {
+        &self.pixels
+    }
+
+    /// Gets the pixels of row `y`.
+    pub fn row(&self, y: usize) -> &[u8] {
+        let row_len = self.layout.row_len();
+        &self.pixels[y * row_len..(y + 1) * row_len]
+    }
+
+    /// Gets the number of frames a [`FrameStream`] captured before this one.
+    pub fn sequence(&self) -> u64 {
+        self.sequence
+    }
+
+    pub fn captured(&self) -> Instant {
+        self.captured
+    }
+}
+
+/// Captures a framebuffer periodically on a background thread, and hands each frame to a
+/// callback, so embedders can show what a guest draws while it runs. The stream stops when
+/// [`FrameStream::stop`] is called or it's dropped.
+pub struct FrameStream {
+    stopped: Arc<AtomicBool>,
+    worker: Option<JoinHandle<()>>,
+}
+
+impl FrameStream {
+    /// Starts capturing the framebuffer `layout` describes from `mem` every `interval`. Each
+    /// capture pauses the thread `observer` came from only while the frame is copied.
+    ///
+    /// Returns `None` if the framebuffer doesn't fit in `mem`.
+    pub fn start<F: FnMut(Frame) + Send + 'static>(
+        observer: ObserverHandle,
+        mem: Arc<MemInst>,
+        layout: FrameLayout,
+        interval: Duration,
+        mut on_frame: F,
+    ) -> Option<FrameStream> {
+        if !layout.fits(mem.memory().len()) {
+            return None;
+        }
+
+        let stopped = Arc::new(AtomicBool::new(false));
+        let worker = {
+            let stopped = stopped.clone();
+            thread::spawn(move || {
+                let mut sequence = 0;
+                let mut next = Instant::now();
+                while !stopped.load(Ordering::Relaxed) {
+                    if let Some(mut frame) = layout.capture(&observer, &mem) {
+                        frame.sequence = sequence;
+                        sequence += 1;
+                        on_frame(frame);
+                    }
+
+                    // Skip frames rather than falling behind
+                    next += interval;
+                    let now = Instant::now();
+                    if next > now {
+                        thread::park_timeout(next - now);
+                    } else {
+                        next = now;
+                    }
+                }
+            })
+        };
+        Some(FrameStream {
+            stopped,
+            worker: Some(worker),
+        })
+    }
+
+    /// Stops capturing, and waits for the callback to return if it's running.
+    pub fn stop(mut self) {
+        self.shutdown();
+    }
+
+    fn shutdown(&mut self) {
+        if let Some(worker) = self.worker.take() {
+            self.stopped.store(true, Ordering::Relaxed);
+            worker.thread().unpark();
+            // A panic in the callback has already been reported
+            let _ = worker.join();
+        }
+    }
+}
+
+impl Drop for FrameStream {
+    fn drop(&mut self) {
+        self.shutdown();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{sync::mpsc, time::Duration};
+
+    use crate::{
+        builder::ModuleBuilder,
+        hosting::Host,
+        interp::{FrameLayout, FrameStream, PixelFormat, Thread},
+        module::MemoryType,
+    };
+
+    #[test]
+    pub fn framebuffers_are_streamed() {
+        let module = ModuleBuilder::new().mem("memory", MemoryType::new(1, None));
+        let mut host = Host::new();
+        let addr = host.instantiate("test", module.build()).unwrap();
+        let mem = host
+            .get_mem(host.get_module(addr).unwrap().export_mem("memory").unwrap())
+            .unwrap();
+        unsafe { mem.memory().data()[16..24].copy_from_slice(&[1, 2, 0, 0, 3, 4, 0, 0]) };
+
+        // Two rows of one RGB565 pixel, padded to 4 bytes
+        let layout = FrameLayout::new(16, 1, 2, PixelFormat::Rgb565).with_stride(4);
+        assert!(!layout.with_stride(1).fits(0x10000));
+        assert!(!FrameLayout::new(0xFFFF, 1, 1, PixelFormat::Rgb565).fits(0x10000));
+
+        let thread = Thread::new();
+        let (sender, receiver) = mpsc::channel();
+        let stream = FrameStream::start(
+            thread.observer_handle(),
+            mem,
+            layout,
+            Duration::from_millis(1),
+            move |frame| {
+                let _ = sender.send(frame);
+            },
+        )
+        .unwrap();
+        let first = receiver.recv_timeout(Duration::from_secs(10)).unwrap();
+        let second = receiver.recv_timeout(Duration::from_secs(10)).unwrap();
+        stream.stop();
+
+        assert_eq!(&[1, 2, 3, 4], first.pixels());
+        assert_eq!(&[3, 4], first.row(1));
+        assert_eq!((0, 1), (first.sequence(), second.sequence()));
+    }
+}
@@ -0,0 +1,246 @@
+use std::{collections::HashMap, fs, path::Path};
+
+use crate::{interp::Op, json::Json, Error, Instruction};
+
+/// The amount of fuel each instruction consumes when a [`Thread`](crate::interp::Thread) is
+/// metering execution.
+///
+/// Instructions are identified by their prefix byte (if any) and opcode, so the immediates of an
+/// instruction don't affect its cost. Any instruction without its own cost uses the default.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FuelCosts {
+    default: u64,
+    costs: HashMap<(Option<u8>, u32), u64>,
+    version: Option<String>,
+}
+
+impl FuelCosts {
+    /// Creates a cost table where every instruction costs `default`.
+    pub fn new(default: u64) -> FuelCosts {
+        FuelCosts {
+            default,
+            costs: HashMap::new(),
+            version: None,
+        }
+    }
+
+    /// Loads a cost table from a JSON file, so embedders can tune and version their costs
+    /// without rebuilding. See [`FuelCosts::from_json`] for the format.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<FuelCosts, Error> {
+        FuelCosts::from_json(&fs::read_to_string(path)?)
+    }
+
+    /// Parses a cost table from JSON like this, where instructions are named by their
+    /// mnemonic in the text format:
+    ///
+    /// ```json
+    /// { "version": "2024-06", "costs": { "unreachable": 0, "nop": 1, "i32.add": 1 } }
+    /// ```
+    ///
+    /// Every instruction must be given a cost, so a schedule can't leave one out by mistake,
+    /// unless a `"default"` cost is given for the rest. The optional `"version"` is kept for
+    /// the embedder to record which schedule metered execution.
+    pub fn from_json(text: &str) -> Result<FuelCosts, Error> {
+        let invalid = |reason: String| Error::InvalidFuelCosts(reason);
+        let members = match Json::parse(text).map_err(invalid)? {
+            Json::Object(members) => members,
+            _ => return Err(invalid("expected an object".into())),
+        };
+
+        let mut version = None;
+        let mut default = None;
+        let mut entries = None;
+        for (name, value) in members {
+            match (name.as_str(), value) {
+                ("version", Json::String(v)) => version = Some(v),
+                ("default", value) => default = Some(cost_of("default", &value)?),
+                ("costs", Json::Object(costs)) => entries = Some(costs),
+                (name, _) => return Err(invalid(format!("unexpected member '{}'", name))),
+            }
+        }
+        let entries = entries.ok_or_else(|| invalid("missing 'costs'".into()))?;
+
+        let mut costs = HashMap::new();
+        for (name, value) in entries {
+            let (prefix, opcode) = match Instruction::OPCODES.iter().find(|op| op.0 == name) {
+                Some(&(_, prefix, opcode)) => (prefix, opcode),
+                None => return Err(invalid(format!("unknown instruction '{}'", name))),
+            };
+            if costs
+                .insert((prefix, opcode), cost_of(&name, &value)?)
+                .is_some()
+            {
+                return Err(invalid(format!("'{}' is given more than one cost", name)));
+            }
+        }
+
+        if default.is_none() {
+            let missing: Vec<_> = Instruction::OPCODES
+                .iter()
+                .filter(|op| !costs.contains_key(&(op.1, op.2)))
+                .map(|op| op.0)
+                .collect();
+            if !missing.is_empty() {
+                return Err(invalid(format!(
+                    "{} instruction(s) have no cost and there's no default, starting with '{}'",
+                    missing.len(),
+                    missing[0]
+                )));
+            }
+        }
+
+        Ok(FuelCosts {
+            default: default.unwrap_or(0),
+            costs,
+            version,
+        })
+    }
+
+    /// Gets the version the cost table was loaded with, if it had one.
+    pub fn version(&self) -> Option<&str> {
+        self.version.as_deref()
+    }
+
+    pub fn default_cost(&self) -> u64 {
+        self.default
+    }
+
+    /// Sets the cost of the instruction with the specified prefix and opcode.
+    pub fn set(&mut self, prefix: Option<u8>, opcode: u32, cost: u64) {
+        self.costs.insert((prefix, opcode), cost);
+    }
+
+    /// Gets the cost of executing `inst`.
+    pub fn cost(&self, inst: &Instruction) -> u64 {
+        self.lookup(inst.prefix(), inst.opcode())
+    }
+
+    /// Gets the cost of executing `op`, which is the cost of the instruction it was lowered from.
+    pub fn op_cost(&self, op: &Op) -> u64 {
+        let opcode = match *op {
+            Op::Block { .. } => 0x02,
+            Op::Loop => 0x03,
+            Op::If { .. } => 0x04,
+            Op::Else => 0x05,
+            Op::End => 0x0B,
+            Op::Unterminated => return self.default,
+            Op::Inst(ref inst) => return self.cost(inst),
+        };
+        self.lookup(None, opcode)
+    }
+
+    fn lookup(&self, prefix: Option<u8>, opcode: u32) -> u64 {
+        // Most tables only override a handful of instructions, if any
+        if self.costs.is_empty() {
+            return self.default;
+        }
+        match self.costs.get(&(prefix, opcode)) {
+            Some(cost) => *cost,
+            None => self.default,
+        }
+    }
+}
+
+/// Reads the cost of `name`, which has to be a whole number of units.
+fn cost_of(name: &str, value: &Json) -> Result<u64, Error> {
+    match *value {
+        Json::Number(n) if n >= 0.0 && n.fract() == 0.0 && n <= (1u64 << 53) as f64 => Ok(n as u64),
+        _ => Err(Error::InvalidFuelCosts(format!(
+            "the cost of '{}' isn't a whole number of units",
+            name
+        ))),
+    }
+}
+
+impl Default for FuelCosts {
+    fn default() -> FuelCosts {
+        FuelCosts::new(1)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::FuelCosts;
+    use crate::{
+        builder::{FuncBuilder, ModuleBuilder},
+        hosting::Host,
+        interp::Thread,
+        Error, Instruction, TrapCause, ValType, Value,
+    };
+
+    #[test]
+    pub fn execution_stops_when_fuel_runs_out() {
+        use crate::Instruction::*;
+
+        // (func $spin) loops forever
+        let module = ModuleBuilder::new().func(FuncBuilder::new().export_as("spin").body(vec![
+            Loop(ValType::Nil),
+            Br(0),
+            End,
+        ]));
+        let mut host = Host::new();
+        let addr = host.instantiate("test", module.build()).unwrap();
+        let func = host.get_module(addr).unwrap().export_func("spin").unwrap();
+
+        let mut thread = Thread::new();
+        thread.set_fuel(Some(1_001));
+        let trap = thread.call(&mut host, addr, func, &[]).unwrap_err();
+        assert!(matches!(trap.cause(), TrapCause::OutOfFuel));
+        assert_eq!("all fuel consumed", trap.cause().message());
+        // One unit for the 'loop', then one for each iteration of the 'br'
+        assert_eq!(Some(0), thread.fuel());
+
+        // Instructions without their own cost use the default
+        let mut costs = FuelCosts::new(0);
+        costs.set(None, 0x0C, 10);
+        thread.set_fuel_costs(costs);
+        thread.set_fuel(Some(105));
+        thread.call(&mut host, addr, func, &[]).unwrap_err();
+        assert_eq!(Some(5), thread.fuel());
+
+        // Without fuel, execution isn't metered
+        let module = ModuleBuilder::new().func(
+            FuncBuilder::new()
+                .export_as("one")
+                .result(ValType::I32)
+                .body(vec![I32Const(Value::I32(1))]),
+        );
+        let addr = host.instantiate("one", module.build()).unwrap();
+        let func = host.get_module(addr).unwrap().export_func("one").unwrap();
+        thread.set_fuel(None);
+        let res = thread.call(&mut host, addr, func, &[]);
+        assert_eq!(Ok(vec![Value::I32(1)]), res);
+        assert_eq!(None, thread.fuel());
+    }
+
+    #[test]
+    pub fn cost_tables_are_loaded_from_json() {
+        let costs = FuelCosts::from_json(
+            r#"{ "version": "v2", "default": 1, "costs": { "i32.add": 3, "memory.grow": 100 } }"#,
+        )
+        .unwrap();
+        assert_eq!(Some("v2"), costs.version());
+        assert_eq!(3, costs.cost(&Instruction::I32Add));
+        assert_eq!(100, costs.cost(&Instruction::MemoryGrow(0)));
+        assert_eq!(1, costs.cost(&Instruction::Nop));
+
+        // Without a default, every instruction needs a cost
+        let all = Instruction::OPCODES
+            .iter()
+            .map(|op| format!("\"{}\": 2", op.0))
+            .collect::<Vec<_>>();
+        let costs = FuelCosts::from_json(&format!("{{ \"costs\": {{ {} }} }}", all.join(", ")));
+        assert_eq!(2, costs.unwrap().cost(&Instruction::Nop));
+        let partial = format!("{{ \"costs\": {{ {} }} }}", all[1..].join(", "));
+        for text in [
+            partial.as_str(),
+            r#"{ "default": 1, "costs": { "i32.frobnicate": 1 } }"#,
+            r#"{ "default": 1, "costs": { "nop": -1 } }"#,
+            r#"{ "default": 1, "costs": { "nop": 1, "nop": 2 } }"#,
+            r#"{ "default": 1 }"#,
+        ] {
+            let res = FuelCosts::from_json(text);
+            assert!(matches!(res, Err(Error::InvalidFuelCosts(_))), "{}", text);
+        }
+    }
+}
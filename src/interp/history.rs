@@ -0,0 +1,234 @@
+use std::{collections::VecDeque, fmt};
+
+use crate::{
+    interp::{Op, StackFrame},
+    Value,
+};
+
+/// How an instruction changed the operand stack of its frame: the values it removed from the
+/// top of the stack, then the values it left there.
+///
+/// The change is worked out by comparing the stack before and after the instruction, so an
+/// instruction that pops a value and pushes it back unchanged (such as `i32.add` with `0`) only
+/// shows the values that differ.
+#[derive(Clone, PartialEq, Debug)]
+pub struct OperandDelta {
+    popped: Vec<Value>,
+    pushed: Vec<Value>,
+}
+
+impl OperandDelta {
+    fn between(before: &[Value], after: &[Value]) -> OperandDelta {
+        let common = before
+            .iter()
+            .zip(after.iter())
+            .take_while(|(b, a)| b == a)
+            .count();
+        OperandDelta {
+            popped: before[common..].to_vec(),
+            pushed: after[common..].to_vec(),
+        }
+    }
+
+    /// Gets the values removed from the stack, from the bottom of the stack to the top.
+    pub fn popped(&self) -> &[Value] {
+        &self.popped
+    }
+
+    /// Gets the values added to the stack, from the bottom of the stack to the top.
+    pub fn pushed(&self) -> &[Value] {
+        &self.pushed
+    }
+}
+
+impl fmt::Display for OperandDelta {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let list = |f: &mut fmt::Formatter, values: &[Value]| -> fmt::Result {
+            write!(f, "[")?;
+            for (i, value) in values.iter().enumerate() {
+                if i > 0 {
+                    write!(f, ", ")?;
+                }
+                write!(f, "{} {}", value.typ(), value)?;
+            }
+            write!(f, "]")
+        };
+        write!(f, "popped ")?;
+        list(f, &self.popped)?;
+        write!(f, " pushed ")?;
+        list(f, &self.pushed)
+    }
+}
+
+/// An instruction recorded in a [`History`].
+#[derive(Clone, PartialEq, Debug)]
+pub struct HistoryEntry {
+    frame: StackFrame,
+    pc: usize,
+    op: &'static str,
+    delta: Option<OperandDelta>,
+}
+
+impl HistoryEntry {
+    pub fn frame(&self) -> &StackFrame {
+        &self.frame
+    }
+
+    /// Gets the index of the instruction in the lowered code of its function.
+    pub fn pc(&self) -> usize {
+        self.pc
+    }
+
+    /// Gets the mnemonic of the instruction, such as `i32.add`.
+    pub fn op(&self) -> &'static str {
+        self.op
+    }
+
+    /// Gets how the instruction changed the operand stack. This is `None` for the most recent
+    /// instruction (which may have trapped), and for instructions that called or returned from
+    /// a function, since their frame's stack isn't seen again straight away.
+    pub fn delta(&self) -> Option<&OperandDelta> {
+        self.delta.as_ref()
+    }
+}
+
+impl fmt::Display for HistoryEntry {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}+{} {}", self.frame, self.pc, self.op)?;
+        if let Some(delta) = &self.delta {
+            write!(f, " {}", delta)?;
+        }
+        Ok(())
+    }
+}
+
+/// The most recent instructions a [`Thread`](crate::interp::Thread) executed, with how each
+/// changed the operand stack, so the lead-up to a trap can be examined after the fact.
+///
+/// See [`Thread::set_history_len`](crate::interp::Thread::set_history_len).
+#[derive(Clone, Debug)]
+pub struct History {
+    capacity: usize,
+    entries: VecDeque<HistoryEntry>,
+    /// The operand stack, and the depth of the execution stack, before the latest instruction.
+    last_stack: Vec<Value>,
+    last_depth: usize,
+}
+
+impl History {
+    pub(crate) fn new(capacity: usize) -> History {
+        History {
+            capacity,
+            entries: VecDeque::with_capacity(capacity),
+            last_stack: Vec::new(),
+            last_depth: 0,
+        }
+    }
+
+    /// Gets the number of instructions the history holds before the oldest is dropped.
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Enumerates the recorded instructions, from the oldest to the most recent.
+    pub fn entries(&self) -> impl Iterator<Item = &HistoryEntry> {
+        self.entries.iter()
+    }
+
+    /// Records that `op` is about to execute in `frame`, at the given depth of the execution
+    /// stack, with `stack` as its frame's operand stack.
+    pub(crate) fn record(
+        &mut self,
+        frame: &StackFrame,
+        depth: usize,
+        pc: usize,
+        op: &Op,
+        stack: &[Value],
+    ) {
+        // The previous instruction's effect is only known if it stayed in the same frame
+        if let Some(prev) = self.entries.back_mut() {
+            if self.last_depth == depth
+                && prev.frame.module() == frame.module()
+                && prev.frame.func() == frame.func()
+            {
+                prev.delta = Some(OperandDelta::between(&self.last_stack, stack));
+            }
+        }
+
+        if self.entries.len() == self.capacity {
+            self.entries.pop_front();
+        }
+        self.entries.push_back(HistoryEntry {
+            frame: frame.clone(),
+            pc,
+            op: op.name(),
+            delta: None,
+        });
+        self.last_stack.clear();
+        self.last_stack.extend_from_slice(stack);
+        self.last_depth = depth;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        builder::{FuncBuilder, ModuleBuilder},
+        hosting::Host,
+        interp::Thread,
+        TrapCause, ValType, Value,
+    };
+
+    #[test]
+    pub fn instructions_leading_up_to_a_trap_are_kept() {
+        use crate::Instruction::*;
+
+        // (func $div (param i32) (result i32)) divides 10 by its argument, after some busywork
+        let module = ModuleBuilder::new().func(
+            FuncBuilder::new()
+                .export_as("div")
+                .param(ValType::I32)
+                .result(ValType::I32)
+                .body(vec![
+                    I32Const(Value::I32(1)),
+                    Drop,
+                    I32Const(Value::I32(10)),
+                    LocalGet(0),
+                    I32DivU,
+                ]),
+        );
+        let mut host = Host::new();
+        let addr = host.instantiate("test", module.build()).unwrap();
+        let div = host.get_module(addr).unwrap().export_func("div").unwrap();
+
+        let mut thread = Thread::new();
+        thread.set_history_len(3);
+        let trap = thread
+            .call(&mut host, addr, div, &[Value::I32(0)])
+            .unwrap_err();
+        assert!(matches!(trap.cause(), TrapCause::IntegerDivideByZero));
+
+        // Only the last three instructions are kept, with the trapping one last
+        let history = thread.history().unwrap();
+        let ops: Vec<_> = history.entries().map(|e| e.op()).collect();
+        assert_eq!(vec!["i32.const", "local.get", "i32.div_u"], ops);
+        let entries: Vec<_> = history.entries().collect();
+        assert_eq!(&[Value::I32(0)], entries[1].delta().unwrap().pushed());
+        assert!(entries[2].delta().is_none());
+        assert_eq!(
+            "test!<function 0>+3 local.get popped [] pushed [i32 0]",
+            entries[1].to_string()
+        );
+
+        thread.set_history_len(0);
+        assert!(thread.history().is_none());
+    }
+}
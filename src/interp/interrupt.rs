@@ -0,0 +1,181 @@
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Condvar, Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+/// Interrupts the WebAssembly code running on a [`Thread`](crate::interp::Thread) from another
+/// OS thread, for example to enforce a timeout.
+///
+/// Handles are cheap to clone and can be sent between threads. Once interrupted, the thread
+/// traps with [`TrapCause::Interrupted`](crate::TrapCause::Interrupted) at its next branch or
+/// call. Straight-line code in between runs to completion, which is bounded by its length.
+///
+/// External functions that block, such as reads or sleeps, can't be stopped that way. They can
+/// get the handle of the thread calling them to check for an interrupt with
+/// [`InterruptHandle::is_interrupted`], or block with [`InterruptHandle::wait`] so they wake up
+/// when interrupted, and then return early with
+/// [`Thread::check_interrupt`](crate::interp::Thread::check_interrupt).
+#[derive(Clone, Debug)]
+pub struct InterruptHandle(Arc<Interrupt>);
+
+#[derive(Debug)]
+struct Interrupt {
+    pending: AtomicBool,
+    /// Wakes external functions waiting for an interrupt.
+    lock: Mutex<()>,
+    wake: Condvar,
+}
+
+impl InterruptHandle {
+    pub(crate) fn new() -> InterruptHandle {
+        InterruptHandle(Arc::new(Interrupt {
+            pending: AtomicBool::new(false),
+            lock: Mutex::new(()),
+            wake: Condvar::new(),
+        }))
+    }
+
+    /// Requests that the thread traps. If nothing is running, the next call on the thread
+    /// traps once it branches or calls.
+    pub fn interrupt(&self) {
+        let _guard = self.0.lock.lock().unwrap();
+        self.0.pending.store(true, Ordering::Relaxed);
+        self.0.wake.notify_all();
+    }
+
+    /// Gets a boolean indicating if an interrupt is pending, without clearing it.
+    pub fn is_interrupted(&self) -> bool {
+        self.0.pending.load(Ordering::Relaxed)
+    }
+
+    /// Blocks until the thread is interrupted or `timeout` elapses, returning `true` if it was
+    /// interrupted. The interrupt stays pending.
+    pub fn wait(&self, timeout: Duration) -> bool {
+        let deadline = Instant::now() + timeout;
+        let mut guard = self.0.lock.lock().unwrap();
+        while !self.is_interrupted() {
+            let now = Instant::now();
+            if now >= deadline {
+                return false;
+            }
+            guard = self.0.wake.wait_timeout(guard, deadline - now).unwrap().0;
+        }
+        true
+    }
+
+    /// Clears a pending interrupt, returning `true` if there was one.
+    pub(crate) fn take(&self) -> bool {
+        // Checked on every branch, so avoid the cost of a write in the common case
+        self.0.pending.load(Ordering::Relaxed) && self.0.pending.swap(false, Ordering::Relaxed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::Arc,
+        thread,
+        time::{Duration, Instant},
+    };
+
+    use crate::{
+        builder::{FuncBuilder, ModuleBuilder},
+        hosting::{Caller, ExternalFunc, ExternalMemory, ExternalModule, Host},
+        interp::Thread,
+        module::FuncType,
+        Trap, TrapCause, ValType, Value,
+    };
+
+    /// An external module with a function that sleeps for a minute, unless interrupted.
+    struct Sleeper(Vec<Arc<ExternalFunc>>);
+
+    impl ExternalModule for Sleeper {
+        fn name(&self) -> &str {
+            "sleeper"
+        }
+
+        fn funcs(&self) -> &[Arc<ExternalFunc>] {
+            &self.0
+        }
+
+        fn mems(&self) -> &[ExternalMemory] {
+            &[]
+        }
+    }
+
+    fn sleep(caller: &mut Caller, _values: &[Value]) -> Result<Vec<Value>, Trap> {
+        caller
+            .thread()
+            .interrupt_handle()
+            .wait(Duration::from_secs(60));
+        caller.thread().check_interrupt()?;
+        Ok(Vec::new())
+    }
+
+    #[test]
+    pub fn interrupts_stop_a_running_thread() {
+        use crate::Instruction::*;
+
+        // (func $spin) loops forever
+        let module = ModuleBuilder::new().func(FuncBuilder::new().export_as("spin").body(vec![
+            Loop(ValType::Nil),
+            Br(0),
+            End,
+        ]));
+        let mut host = Host::new();
+        let addr = host.instantiate("test", module.build()).unwrap();
+        let func = host.get_module(addr).unwrap().export_func("spin").unwrap();
+
+        let mut thread = Thread::new();
+        let handle = thread.interrupt_handle();
+        let interrupter = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(10));
+            handle.interrupt();
+        });
+        let trap = thread.call(&mut host, addr, func, &[]).unwrap_err();
+        interrupter.join().unwrap();
+        assert!(matches!(trap.cause(), TrapCause::Interrupted));
+        assert_eq!("interrupted", trap.cause().message());
+
+        // Interrupts are consumed by the trap they cause
+        thread.interrupt_handle().interrupt();
+        assert!(thread.call(&mut host, addr, func, &[]).is_err());
+        assert!(!thread.interrupt_handle().take());
+    }
+
+    #[test]
+    pub fn external_functions_can_be_interrupted() {
+        let mut host = Host::new();
+        host.external(Sleeper(vec![Arc::new(ExternalFunc::new(
+            "sleep",
+            FuncType::empty(),
+            sleep,
+        ))]))
+        .unwrap();
+        let module = ModuleBuilder::new()
+            .func(FuncBuilder::new().import_from("sleeper", "sleep"))
+            .func(
+                FuncBuilder::new()
+                    .export_as("main")
+                    .body(vec![crate::Instruction::Call(0)]),
+            );
+        let addr = host.instantiate("test", module.build()).unwrap();
+        let func = host.get_module(addr).unwrap().export_func("main").unwrap();
+
+        let mut thread = Thread::new();
+        let handle = thread.interrupt_handle();
+        let interrupter = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(10));
+            handle.interrupt();
+        });
+        let start = Instant::now();
+        let trap = thread.call(&mut host, addr, func, &[]).unwrap_err();
+        interrupter.join().unwrap();
+        assert!(matches!(trap.cause(), TrapCause::Interrupted));
+        assert!(start.elapsed() < Duration::from_secs(30));
+        assert!(!thread.interrupt_handle().is_interrupted());
+    }
+}
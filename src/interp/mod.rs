@@ -1,6 +1,34 @@
+mod claims;
+mod code;
+mod debug;
+mod dispatch;
 mod exec;
+mod frames;
+mod fuel;
+mod history;
+mod interrupt;
+mod observer;
+mod precompiled;
+mod profiler;
+mod saved_thread;
 mod stack;
+mod stats;
 mod thread;
+mod trace;
 
-pub use self::stack::{ExecutionContext, ExecutionStack, StackFrame, StackTrace};
-pub use self::thread::Thread;
+pub use self::code::{Code, Op};
+pub use self::debug::{StepResult, Suspend};
+pub use self::dispatch::Dispatch;
+pub use self::frames::{Frame, FrameLayout, FrameStream, PixelFormat};
+pub use self::fuel::FuelCosts;
+pub use self::history::{History, HistoryEntry, OperandDelta};
+pub use self::interrupt::InterruptHandle;
+pub use self::observer::ObserverHandle;
+pub use self::precompiled::PrecompiledModule;
+pub use self::profiler::{FuncProfile, Profile};
+pub use self::stack::{
+    ExecutionContext, ExecutionStack, Label, StackFrame, StackLimits, StackTrace,
+};
+pub use self::stats::OpcodeStats;
+pub use self::thread::{InvokeAsync, Thread};
+pub use self::trace::{first_divergence, Divergence, TraceSink, TraceWriter};
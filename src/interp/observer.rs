@@ -0,0 +1,200 @@
+use std::{
+    ops::Range,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Condvar, Mutex,
+    },
+};
+
+use crate::hosting::MemInst;
+
+/// Lets other OS threads safely look at the memory a [`Thread`](crate::interp::Thread) is
+/// running against, for example to show a dashboard or capture a guest's framebuffer.
+///
+/// Reading memory while the interpreter writes it would be a data race, so the observer
+/// [pauses](ObserverHandle::pause) the thread at its next quiescent point, a branch or call
+/// between instructions, which is also where interrupts are noticed. The thread carries on as
+/// soon as the observer is done, so observations should be brief, such as copying out the
+/// bytes of interest with [`ObserverHandle::read`]. A thread that isn't running anything is
+/// already quiescent, and is kept from starting until the observer is done.
+///
+/// This only coordinates with the thread the handle came from. If other threads run against
+/// the same memory, such as a shared one, their handles have to be used too.
+#[derive(Clone, Debug)]
+pub struct ObserverHandle(Arc<Observers>);
+
+#[derive(Debug)]
+struct Observers {
+    /// Set while observers are waiting or observing, so the thread only locks when needed.
+    requested: AtomicBool,
+    state: Mutex<State>,
+    changed: Condvar,
+}
+
+#[derive(Debug, Default)]
+struct State {
+    /// The number of invocations running, which is more than one if an external function
+    /// calls back into WebAssembly.
+    running: usize,
+    /// Whether the thread is stopped at a quiescent point.
+    parked: bool,
+    waiting: usize,
+    observing: usize,
+}
+
+/// Marks the thread as running until dropped.
+pub(crate) struct Running(Arc<Observers>);
+
+impl ObserverHandle {
+    pub(crate) fn new() -> ObserverHandle {
+        ObserverHandle(Arc::new(Observers {
+            requested: AtomicBool::new(false),
+            state: Mutex::new(State::default()),
+            changed: Condvar::new(),
+        }))
+    }
+
+    /// Waits for the thread to reach a quiescent point, then runs `f` while it stays there.
+    pub fn pause<T, F: FnOnce() -> T>(&self, f: F) -> T {
+        let observers = &self.0;
+        {
+            let mut state = observers.state.lock().unwrap();
+            state.waiting += 1;
+            observers.requested.store(true, Ordering::Relaxed);
+            while state.running > 0 && !state.parked {
+                state = observers.changed.wait(state).unwrap();
+            }
+            state.waiting -= 1;
+            state.observing += 1;
+        }
+
+        let result = f();
+
+        let mut state = observers.state.lock().unwrap();
+        state.observing -= 1;
+        if state.waiting == 0 && state.observing == 0 {
+            observers.requested.store(false, Ordering::Relaxed);
+        }
+        observers.changed.notify_all();
+        result
+    }
+
+    /// Copies the bytes of `mem` in `range` once the thread is quiescent. Returns `None` if
+    /// `range` is out of bounds.
+    pub fn read(&self, mem: &MemInst, range: Range<usize>) -> Option<Vec<u8>> {
+        if range.start > range.end {
+            return None;
+        }
+        // Other threads may still write a shared memory, so it isn't borrowed
+        let mut bytes = vec![0; range.end - range.start];
+        self.pause(|| mem.memory().read(range.start, &mut bytes))
+            .then_some(bytes)
+    }
+
+    /// Marks the thread as running, so observers wait for a quiescent point.
+    pub(crate) fn enter(&self) -> Running {
+        let mut state = self.0.state.lock().unwrap();
+        while state.running == 0 && (state.waiting > 0 || state.observing > 0) {
+            state = self.0.changed.wait(state).unwrap();
+        }
+        state.running += 1;
+        Running(self.0.clone())
+    }
+
+    /// Stops at a quiescent point for as long as observers want it to.
+    pub(crate) fn quiesce(&self) {
+        // Checked on every branch, so avoid locking in the common case
+        if !self.0.requested.load(Ordering::Relaxed) {
+            return;
+        }
+
+        let mut state = self.0.state.lock().unwrap();
+        state.parked = true;
+        self.0.changed.notify_all();
+        while state.waiting > 0 || state.observing > 0 {
+            state = self.0.changed.wait(state).unwrap();
+        }
+        state.parked = false;
+    }
+}
+
+impl Drop for Running {
+    fn drop(&mut self) {
+        let mut state = self.0.state.lock().unwrap();
+        state.running -= 1;
+        self.0.changed.notify_all();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use crate::{
+        builder::{FuncBuilder, ModuleBuilder},
+        hosting::Host,
+        interp::Thread,
+        module::MemoryType,
+        ValType, Value,
+    };
+
+    #[test]
+    pub fn memory_is_read_while_a_thread_runs() {
+        use crate::Instruction::*;
+
+        // (func $count (param i32)) stores each number from the argument down to 1 at 0
+        let module = ModuleBuilder::new()
+            .mem("memory", MemoryType::new(1, None))
+            .func(
+                FuncBuilder::new()
+                    .export_as("count")
+                    .param(ValType::I32)
+                    .body(vec![
+                        Block(ValType::Nil),
+                        Loop(ValType::Nil),
+                        LocalGet(0),
+                        I32Eqz,
+                        BrIf(1),
+                        I32Const(Value::I32(0)),
+                        LocalGet(0),
+                        I32Store(2, 0),
+                        LocalGet(0),
+                        I32Const(Value::I32(1)),
+                        I32Sub,
+                        LocalSet(0),
+                        Br(0),
+                        End,
+                        End,
+                    ]),
+            );
+        let mut host = Host::new();
+        let addr = host.instantiate("test", module.build()).unwrap();
+        let func = host.get_module(addr).unwrap().export_func("count").unwrap();
+        let mem = host
+            .get_mem(host.get_module(addr).unwrap().export_mem("memory").unwrap())
+            .unwrap();
+
+        let mut thread = Thread::new();
+        let handle = thread.observer_handle();
+        let observer = {
+            let handle = handle.clone();
+            let mem = mem.clone();
+            thread::spawn(move || {
+                // Once the call starts, the counter only ever goes down
+                let mut last = 0;
+                for _ in 0..100 {
+                    let bytes = handle.read(&mem, 0..4).unwrap();
+                    let value = u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+                    assert!(last == 0 || value <= last);
+                    last = value;
+                }
+            })
+        };
+        thread
+            .call(&mut host, addr, func, &[Value::I32(200_000)])
+            .unwrap();
+        observer.join().unwrap();
+        assert_eq!(Some(vec![1, 0, 0, 0]), handle.read(&mem, 0..4));
+        assert_eq!(None, handle.read(&mem, 0..0x10001));
+    }
+}
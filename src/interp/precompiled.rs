@@ -0,0 +1,182 @@
+use std::{
+    io::{self, Cursor, Read},
+    sync::Arc,
+};
+
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+
+use crate::{
+    interp::Code,
+    module::Module,
+    reader::{Reader, SectionId},
+    utils, Error, SectionOffset,
+};
+
+/// Identifies a precompiled module file, as opposed to a WebAssembly binary (`\0asm`).
+const MAGIC: &[u8; 4] = b"\0wrt";
+const VERSION: u32 = 1;
+
+/// The id of the code section in a WebAssembly binary.
+const CODE_SECTION: u8 = 10;
+
+/// A module whose function bodies have already been lowered, which can be saved to a file and
+/// loaded again without decoding and lowering the bodies a second time.
+///
+/// The file holds the module's WebAssembly binary with the code section removed, followed by
+/// the lowered code of each function.
+pub struct PrecompiledModule {
+    wasm: Vec<u8>,
+    module: Module,
+    code: Vec<Arc<Code>>,
+}
+
+impl PrecompiledModule {
+    /// Loads the WebAssembly binary in `wasm`, and lowers its function bodies.
+    pub fn compile(wasm: &[u8]) -> Result<PrecompiledModule, Error> {
+        let module = Module::load(Reader::new(Cursor::new(wasm)))?;
+        let code = module
+            .code()
+            .iter()
+            .map(|body| Arc::new(Code::new(body)))
+            .collect();
+        Ok(PrecompiledModule {
+            wasm: strip_code_section(wasm)?,
+            module,
+            code,
+        })
+    }
+
+    /// Gets a boolean indicating if `bytes` starts like a precompiled module file.
+    pub fn is_precompiled(bytes: &[u8]) -> bool {
+        bytes.starts_with(MAGIC)
+    }
+
+    /// Gets the module. The function bodies of a module that was read from a file aren't
+    /// available, only their lowered code.
+    pub fn module(&self) -> &Module {
+        &self.module
+    }
+
+    pub fn code(&self) -> &[Arc<Code>] {
+        &self.code
+    }
+
+    pub fn into_parts(self) -> (Module, Vec<Arc<Code>>) {
+        (self.module, self.code)
+    }
+
+    pub fn write<W: io::Write>(&self, writer: &mut W) -> io::Result<()> {
+        writer.write_all(MAGIC)?;
+        writer.write_u32::<LittleEndian>(VERSION)?;
+        writer.write_u32::<LittleEndian>(self.wasm.len() as u32)?;
+        writer.write_all(&self.wasm)?;
+        writer.write_u32::<LittleEndian>(self.code.len() as u32)?;
+        for code in self.code.iter() {
+            code.write(writer)?;
+        }
+        Ok(())
+    }
+
+    /// Reads a module written by [`PrecompiledModule::write`].
+    pub fn read<R: io::Read>(reader: &mut R) -> Result<PrecompiledModule, Error> {
+        let mut magic = [0; 4];
+        reader.read_exact(&mut magic)?;
+        if magic != *MAGIC {
+            return Err(Error::InvalidMagic);
+        }
+        let version = reader.read_u32::<LittleEndian>()?;
+        if version != VERSION {
+            return Err(Error::UnsupportedVersion { version });
+        }
+
+        // The length isn't trusted to allocate up front, the file may be truncated or corrupt
+        let len = reader.read_u32::<LittleEndian>()?;
+        let mut wasm = Vec::new();
+        reader.by_ref().take(len as u64).read_to_end(&mut wasm)?;
+        if wasm.len() != len as usize {
+            return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into());
+        }
+        let module = Module::load_without_code(Reader::new(Cursor::new(&wasm)))?;
+
+        let count = reader.read_u32::<LittleEndian>()?;
+        let mut code = Vec::new();
+        for _ in 0..count {
+            code.push(Arc::new(Code::read(reader)?));
+        }
+        if module.funcs().len() != code.len() {
+            return Err(Error::FunctionCodeMismatch {
+                funcs: module.funcs().len(),
+                bodies: code.len(),
+                at: SectionOffset::in_section(SectionId::Code),
+            });
+        }
+        Ok(PrecompiledModule { wasm, module, code })
+    }
+}
+
+/// Copies a WebAssembly binary, leaving out the code section.
+fn strip_code_section(wasm: &[u8]) -> Result<Vec<u8>, Error> {
+    // The header is the magic number and version
+    let mut stripped = wasm[..wasm.len().min(8)].to_vec();
+    let mut cursor = Cursor::new(wasm);
+    cursor.set_position(stripped.len() as u64);
+    while (cursor.position() as usize) < wasm.len() {
+        let start = cursor.position() as usize;
+        let id = cursor.read_u8()?;
+        let size = utils::read_leb128_u32(&mut cursor)? as usize;
+        let end = cursor.position() as usize + size;
+        if end > wasm.len() {
+            return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into());
+        }
+        if id != CODE_SECTION {
+            stripped.extend_from_slice(&wasm[start..end]);
+        }
+        cursor.set_position(end as u64);
+    }
+    Ok(stripped)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::PrecompiledModule;
+    use crate::{hosting::Host, interp::Thread, Value};
+
+    #[test]
+    pub fn precompiled_modules_round_trip() {
+        #[rustfmt::skip]
+        let wasm = [
+            0x00, 0x61, 0x73, 0x6D, 0x01, 0x00, 0x00, 0x00,
+            // Type section: (func (param i32) (result i32))
+            0x01, 0x06, 0x01, 0x60, 0x01, 0x7F, 0x01, 0x7F,
+            // Function section
+            0x03, 0x02, 0x01, 0x00,
+            // Export section: "abs"
+            0x07, 0x07, 0x01, 0x03, 0x61, 0x62, 0x73, 0x00, 0x00,
+            // Code section:
+            //   local.get 0 i32.const 0 i32.lt_s
+            //   if (result i32) i32.const 0 local.get 0 i32.sub else local.get 0 end
+            0x0A, 0x14, 0x01, 0x12, 0x00,
+            0x20, 0x00, 0x41, 0x00, 0x48,
+            0x04, 0x7F, 0x41, 0x00, 0x20, 0x00, 0x6B, 0x05, 0x20, 0x00, 0x0B,
+            0x0B,
+        ];
+        let compiled = PrecompiledModule::compile(&wasm).unwrap();
+        let mut file = Vec::new();
+        compiled.write(&mut file).unwrap();
+        assert!(PrecompiledModule::is_precompiled(&file));
+
+        let loaded = PrecompiledModule::read(&mut Cursor::new(&file)).unwrap();
+        assert!(loaded.module().code().is_empty());
+        assert_eq!(compiled.code(), loaded.code());
+
+        let mut host = Host::new();
+        let addr = host.instantiate_precompiled("test", loaded).unwrap();
+        let func = host.get_module(addr).unwrap().export_func("abs").unwrap();
+        for (arg, expected) in [(-5i32, 5u32), (7, 7)].iter() {
+            let res = Thread::new().call(&mut host, addr, func, &[Value::I32(*arg as u32)]);
+            assert_eq!(Ok(vec![Value::I32(*expected)]), res);
+        }
+    }
+}
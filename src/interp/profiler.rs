@@ -0,0 +1,194 @@
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+
+use crate::hosting::FuncAddr;
+
+/// The calls made to a single function while a [`Thread`](crate::interp::Thread) was profiling.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub struct FuncProfile {
+    calls: u64,
+    inclusive: Duration,
+    exclusive: Duration,
+}
+
+impl FuncProfile {
+    pub fn calls(&self) -> u64 {
+        self.calls
+    }
+
+    /// Gets the time spent in the function, including the functions it called. Time spent in
+    /// recursive calls is only counted once.
+    pub fn inclusive(&self) -> Duration {
+        self.inclusive
+    }
+
+    /// Gets the time spent in the function itself, excluding the functions it called.
+    pub fn exclusive(&self) -> Duration {
+        self.exclusive
+    }
+}
+
+/// Call counts and times for each function a thread called while profiling, including
+/// external functions.
+///
+/// See [`Thread::set_profiling`](crate::interp::Thread::set_profiling).
+#[derive(Clone, PartialEq, Debug, Default)]
+pub struct Profile {
+    funcs: HashMap<FuncAddr, FuncProfile>,
+}
+
+impl Profile {
+    pub fn get(&self, func: FuncAddr) -> Option<&FuncProfile> {
+        self.funcs.get(&func)
+    }
+
+    /// Gets every function that was called, with the most exclusive time first.
+    pub fn funcs(&self) -> Vec<(FuncAddr, &FuncProfile)> {
+        let mut funcs: Vec<_> = self.funcs.iter().map(|(a, p)| (*a, p)).collect();
+        funcs.sort_by(|(a, x), (b, y)| {
+            y.exclusive
+                .cmp(&x.exclusive)
+                .then_with(|| a.val().cmp(&b.val()))
+        });
+        funcs
+    }
+}
+
+/// A function call that hasn't returned yet.
+struct OpenCall {
+    func: FuncAddr,
+    /// The depth of the execution stack while the function runs.
+    depth: usize,
+    start: Instant,
+    /// The time spent in the functions it called so far.
+    children: Duration,
+}
+
+/// Records a [`Profile`] as functions are entered and exited.
+#[derive(Default)]
+pub(crate) struct Profiler {
+    profile: Profile,
+    open: Vec<OpenCall>,
+    /// The number of open calls to each function, so recursive calls aren't counted twice.
+    active: HashMap<FuncAddr, usize>,
+}
+
+impl Profiler {
+    pub fn profile(&self) -> &Profile {
+        &self.profile
+    }
+
+    pub fn into_profile(self) -> Profile {
+        self.profile
+    }
+
+    pub fn enter(&mut self, func: FuncAddr, depth: usize) {
+        *self.active.entry(func).or_insert(0) += 1;
+        self.open.push(OpenCall {
+            func,
+            depth,
+            start: Instant::now(),
+            children: Duration::ZERO,
+        });
+    }
+
+    /// Records the end of the innermost call.
+    pub fn exit(&mut self) {
+        let call = match self.open.pop() {
+            Some(call) => call,
+            None => return,
+        };
+        let elapsed = call.start.elapsed();
+        if let Some(parent) = self.open.last_mut() {
+            parent.children += elapsed;
+        }
+
+        let active = self.active.entry(call.func).or_insert(1);
+        *active -= 1;
+        let outermost = *active == 0;
+
+        let profile = self.profile.funcs.entry(call.func).or_default();
+        profile.calls += 1;
+        profile.exclusive += elapsed.saturating_sub(call.children);
+        if outermost {
+            profile.inclusive += elapsed;
+        }
+    }
+
+    /// Records the end of every call that ran above `depth` in the execution stack, after a
+    /// trap unwinds them.
+    pub fn exit_to(&mut self, depth: usize) {
+        while self.open.last().is_some_and(|c| c.depth > depth) {
+            self.exit();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        builder::{FuncBuilder, ModuleBuilder},
+        hosting::Host,
+        interp::Thread,
+        ValType, Value,
+    };
+
+    #[test]
+    pub fn calls_are_counted_per_function() {
+        use crate::Instruction::*;
+
+        // (func $sum (param $n i32) (result i32)) recursively adds 1 to n through $inc
+        let module = ModuleBuilder::new()
+            .func(
+                FuncBuilder::new()
+                    .export_as("sum")
+                    .param(ValType::I32)
+                    .result(ValType::I32)
+                    .body(vec![
+                        LocalGet(0),
+                        I32Eqz,
+                        If(ValType::I32),
+                        I32Const(Value::I32(0)),
+                        Else,
+                        LocalGet(0),
+                        I32Const(Value::I32(1)),
+                        I32Sub,
+                        Call(0),
+                        Call(1),
+                        End,
+                    ]),
+            )
+            .func(
+                FuncBuilder::new()
+                    .param(ValType::I32)
+                    .result(ValType::I32)
+                    .body(vec![LocalGet(0), I32Const(Value::I32(1)), I32Add]),
+            );
+        let mut host = Host::new();
+        let addr = host.instantiate("test", module.build()).unwrap();
+        let sum = host.get_module(addr).unwrap().export_func("sum").unwrap();
+        let inc = host.get_module(addr).unwrap().funcs()[1];
+
+        let mut thread = Thread::new();
+        thread.set_profiling(true);
+        let res = thread.call(&mut host, addr, sum, &[Value::I32(5)]);
+        assert_eq!(Ok(vec![Value::I32(5)]), res);
+
+        let profile = thread.profile().unwrap();
+        assert_eq!(6, profile.get(sum).unwrap().calls());
+        assert_eq!(5, profile.get(inc).unwrap().calls());
+        assert_eq!(2, profile.funcs().len());
+
+        // Recursion isn't double-counted, so callers include at least as much as their callees
+        let (outer, inner) = (profile.get(sum).unwrap(), profile.get(inc).unwrap());
+        assert!(outer.inclusive() >= outer.exclusive() + inner.inclusive());
+
+        // Profiles accumulate until they are taken
+        thread.call(&mut host, addr, inc, &[Value::I32(1)]).unwrap();
+        let profile = thread.take_profile().unwrap();
+        assert_eq!(6, profile.get(inc).unwrap().calls());
+        assert!(thread.profile().is_none());
+    }
+}
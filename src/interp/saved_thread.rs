@@ -0,0 +1,263 @@
+//! The file format of [`Thread::save_to`] and [`Thread::load_from`].
+//!
+//! A saved thread holds the frames of the invocation paused on it, but refers to functions and
+//! instances by the slots they occupy in the host, so it can only be loaded into a host that
+//! instantiated the same modules in the same order. Values are written as saved globals are,
+//! see [`Host::save`]. All integers are little-endian.
+//!
+//! ```text
+//! magic    "\0wth"
+//! version  u32, currently 1
+//! next     u32, 0 or the slot of the function to enter before resuming plus one
+//! count    u32, the number of frames
+//! frames   one per frame, starting with the one that holds the invocation's arguments:
+//!   module  u32, the slot of the instance
+//!   func    u32, 0 for the first frame or the slot of the function plus one
+//!   pc      u32, the index of the instruction the function resumes at
+//!   locals  u32 count, then the values
+//!   values  u32 count, then the operands, from the bottom of the stack
+//!   labels  u32 count, then for each its arity, height and target as u32s, and a u8 that is
+//!           1 for a loop
+//! ```
+//!
+//! [`Thread::save_to`]: crate::interp::Thread::save_to
+//! [`Thread::load_from`]: crate::interp::Thread::load_from
+//! [`Host::save`]: crate::hosting::Host::save
+
+use std::io::{Read, Write};
+
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+
+use crate::{
+    hosting::{read_value, write_value, FuncAddr, Host, ModuleAddr},
+    interp::{ExecutionContext, Label},
+    Error, Value,
+};
+
+const MAGIC: &[u8; 4] = b"\0wth";
+const VERSION: u32 = 1;
+
+/// A frame of a paused invocation, read from a file but not yet entered.
+pub(crate) struct SavedFrame {
+    pub module: ModuleAddr,
+    pub func: Option<FuncAddr>,
+    pub pc: usize,
+    pub locals: Vec<Value>,
+    pub values: Vec<Value>,
+    pub labels: Vec<Label>,
+}
+
+/// Writes the frames of a paused invocation, each with the instruction its function resumes
+/// at, and the function it enters next.
+pub(crate) fn write<W: Write>(
+    writer: &mut W,
+    next: Option<FuncAddr>,
+    frames: &[(&ExecutionContext, usize)],
+) -> Result<(), Error> {
+    writer.write_all(MAGIC)?;
+    writer.write_u32::<LittleEndian>(VERSION)?;
+    writer.write_u32::<LittleEndian>(slot(next))?;
+    writer.write_u32::<LittleEndian>(frames.len() as u32)?;
+    for &(context, pc) in frames {
+        let frame = context.frame();
+        writer.write_u32::<LittleEndian>(frame.module().val() as u32)?;
+        writer.write_u32::<LittleEndian>(slot(frame.func()))?;
+        writer.write_u32::<LittleEndian>(pc as u32)?;
+        write_values(writer, context.locals())?;
+        write_values(writer, context.values())?;
+        writer.write_u32::<LittleEndian>(context.labels().len() as u32)?;
+        for label in context.labels() {
+            writer.write_u32::<LittleEndian>(label.arity() as u32)?;
+            writer.write_u32::<LittleEndian>(label.height() as u32)?;
+            writer.write_u32::<LittleEndian>(label.target() as u32)?;
+            writer.write_u8(label.is_loop() as u8)?;
+        }
+    }
+    Ok(())
+}
+
+/// Reads the function a paused invocation enters next and its frames, checking that the
+/// instances and functions they refer to are in `host`.
+pub(crate) fn read<R: Read>(
+    host: &Host,
+    reader: &mut R,
+) -> Result<(Option<FuncAddr>, Vec<SavedFrame>), Error> {
+    let mut magic = [0; 4];
+    reader.read_exact(&mut magic)?;
+    if magic != *MAGIC {
+        return Err(Error::InvalidMagic);
+    }
+    let version = reader.read_u32::<LittleEndian>()?;
+    if version != VERSION {
+        return Err(Error::UnsupportedVersion { version });
+    }
+
+    let next = read_func(host, reader)?;
+    let mut frames = Vec::new();
+    for _ in 0..reader.read_u32::<LittleEndian>()? {
+        let module = host
+            .module_at(reader.read_u32::<LittleEndian>()? as usize)
+            .ok_or_else(|| invalid("a frame belongs to an instance the host doesn't have"))?;
+        let func = read_func(host, reader)?;
+        let pc = reader.read_u32::<LittleEndian>()? as usize;
+        let locals = read_values(reader)?;
+        let values = read_values(reader)?;
+        let mut labels = Vec::new();
+        for _ in 0..reader.read_u32::<LittleEndian>()? {
+            let arity = reader.read_u32::<LittleEndian>()? as usize;
+            let height = reader.read_u32::<LittleEndian>()? as usize;
+            let target = reader.read_u32::<LittleEndian>()? as usize;
+            labels.push(match reader.read_u8()? {
+                0 => Label::block(arity, height, target),
+                _ => Label::looping(height, target),
+            });
+        }
+        frames.push(SavedFrame {
+            module,
+            func,
+            pc,
+            locals,
+            values,
+            labels,
+        });
+    }
+    Ok((next, frames))
+}
+
+/// Gets the slot of `func` plus one, or 0 for no function.
+fn slot(func: Option<FuncAddr>) -> u32 {
+    func.map_or(0, |f| f.val() as u32 + 1)
+}
+
+fn read_func<R: Read>(host: &Host, reader: &mut R) -> Result<Option<FuncAddr>, Error> {
+    match reader.read_u32::<LittleEndian>()? {
+        0 => Ok(None),
+        // Functions are saved by slot, which may be in a later generation now
+        slot => host
+            .func_at(slot as usize - 1)
+            .map(Some)
+            .ok_or_else(|| invalid("a frame refers to a function the host doesn't have")),
+    }
+}
+
+fn write_values<W: Write>(writer: &mut W, values: &[Value]) -> Result<(), Error> {
+    writer.write_u32::<LittleEndian>(values.len() as u32)?;
+    for value in values {
+        if !write_value(writer, *value)? {
+            return Err(invalid("only numeric and vector values can be saved"));
+        }
+    }
+    Ok(())
+}
+
+fn read_values<R: Read>(reader: &mut R) -> Result<Vec<Value>, Error> {
+    let mut values = Vec::new();
+    for _ in 0..reader.read_u32::<LittleEndian>()? {
+        values.push(read_value(reader)?.ok_or_else(|| invalid("a value has no type"))?);
+    }
+    Ok(values)
+}
+
+pub(crate) fn invalid(reason: &'static str) -> Error {
+    Error::InvalidState {
+        module: None,
+        reason,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        builder::{FuncBuilder, ModuleBuilder},
+        hosting::{Host, ModuleAddr},
+        interp::{StepResult, Thread},
+        module::MemoryType,
+        Error, ValType, Value,
+    };
+
+    fn world() -> (Host, ModuleAddr) {
+        use crate::Instruction::*;
+
+        // Sums 1 to n, storing the running total at address 0 as it goes
+        let module = ModuleBuilder::new()
+            .mem("memory", MemoryType::new(1, None))
+            .func(
+                FuncBuilder::new()
+                    .export_as("main")
+                    .param(ValType::I32)
+                    .result(ValType::I32)
+                    .body(vec![
+                        LocalGet(0),
+                        Call(1),
+                        I32Const(Value::I32(1000)),
+                        I32Add,
+                    ]),
+            )
+            .func(
+                FuncBuilder::new()
+                    .param(ValType::I32)
+                    .result(ValType::I32)
+                    .locals(vec![ValType::I32])
+                    .body(vec![
+                        Loop(ValType::Nil),
+                        LocalGet(1),
+                        LocalGet(0),
+                        I32Add,
+                        LocalSet(1),
+                        I32Const(Value::I32(0)),
+                        LocalGet(1),
+                        I32Store(2, 0),
+                        LocalGet(0),
+                        I32Const(Value::I32(1)),
+                        I32Sub,
+                        LocalTee(0),
+                        BrIf(0),
+                        End,
+                        LocalGet(1),
+                    ]),
+            );
+        let mut host = Host::new();
+        let addr = host.instantiate("world", module.build()).unwrap();
+        (host, addr)
+    }
+
+    #[test]
+    pub fn paused_invocations_are_resumed_from_a_checkpoint() {
+        let (mut host, addr) = world();
+        let main = host.get_module(addr).unwrap().export_func("main").unwrap();
+        let mut thread = Thread::new();
+        assert!(matches!(
+            thread.save_to(&mut Vec::new()),
+            Err(Error::InvalidState { .. })
+        ));
+
+        thread.start(&host, addr, main, &[Value::I32(10)]).unwrap();
+        for _ in 0..40 {
+            assert!(matches!(thread.step(&mut host), StepResult::Paused { .. }));
+        }
+        let mut state = Vec::new();
+        host.save_to(&mut state).unwrap();
+        let mut stack = Vec::new();
+        thread.save_to(&mut stack).unwrap();
+        let expected = StepResult::Finished(vec![Value::I32(1055)]);
+        assert_eq!(expected, thread.resume(&mut host));
+
+        let (mut restored, addr) = world();
+        restored.load_from(&mut &state[..]).unwrap();
+        let mut thread = Thread::new();
+        thread.load_from(&restored, &mut &stack[..]).unwrap();
+        assert!(thread.is_paused());
+        assert_eq!(expected, thread.resume(&mut restored));
+        let mem = restored
+            .get_mem(
+                restored
+                    .get_module(addr)
+                    .unwrap()
+                    .export_mem("memory")
+                    .unwrap(),
+            )
+            .unwrap();
+        assert_eq!(55, mem.read_u32(0).unwrap());
+        assert_eq!(0, thread.stack().depth());
+    }
+}
@@ -1,4 +1,4 @@
-use std::fmt;
+use std::{fmt, sync::Arc};
 
 use crate::{
     hosting::{FuncAddr, ModuleAddr},
@@ -22,11 +22,24 @@ impl StackTrace {
 pub struct StackFrame {
     module: ModuleAddr,
     func: Option<FuncAddr>,
+    name: Option<Arc<str>>,
+    offset: usize,
 }
 
 impl StackFrame {
     pub fn new(module: ModuleAddr, func: Option<FuncAddr>) -> StackFrame {
-        StackFrame { module, func }
+        StackFrame {
+            module,
+            func,
+            name: None,
+            offset: 0,
+        }
+    }
+
+    /// Sets the name the frame is displayed with, if the function has one.
+    pub fn with_name(mut self, name: Option<Arc<str>>) -> StackFrame {
+        self.name = name;
+        self
     }
 
     pub fn module(&self) -> ModuleAddr {
@@ -36,11 +49,28 @@ impl StackFrame {
     pub fn func(&self) -> Option<FuncAddr> {
         self.func
     }
+
+    /// Gets the name of the function, as `module!function`.
+    pub fn name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
+
+    /// Gets the index, in the lowered code of the function, of the instruction the frame is
+    /// executing. For frames that called another function, this is the call instruction.
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+
+    pub(crate) fn set_offset(&mut self, offset: usize) {
+        self.offset = offset;
+    }
 }
 
 impl fmt::Display for StackFrame {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        if let Some(func) = self.func {
+        if let Some(name) = &self.name {
+            write!(f, "{}", name)
+        } else if let Some(func) = self.func {
             write!(f, "0x{:08X}", func)
         } else {
             write!(f, "<module: 0x{:08X}>", self.module)
@@ -54,15 +84,67 @@ impl fmt::Debug for StackFrame {
     }
 }
 
+/// Represents a structured control instruction (`block`, `loop` or `if`) that can be branched to.
+#[derive(Clone, PartialEq, Debug)]
+pub struct Label {
+    arity: usize,
+    height: usize,
+    target: usize,
+    is_loop: bool,
+}
+
+impl Label {
+    /// Creates a label for a `block` or `if`, where branches continue after the `end` at `target`.
+    pub fn block(arity: usize, height: usize, target: usize) -> Label {
+        Label {
+            arity,
+            height,
+            target,
+            is_loop: false,
+        }
+    }
+
+    /// Creates a label for a `loop`, where branches continue at the start of the body at `target`.
+    pub fn looping(height: usize, target: usize) -> Label {
+        Label {
+            arity: 0,
+            height,
+            target,
+            is_loop: true,
+        }
+    }
+
+    /// Gets the number of values carried by a branch to this label.
+    pub fn arity(&self) -> usize {
+        self.arity
+    }
+
+    /// Gets the height of the operand stack when the label was entered.
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    /// Gets the instruction index at which execution continues after branching to this label.
+    pub fn target(&self) -> usize {
+        self.target
+    }
+
+    pub fn is_loop(&self) -> bool {
+        self.is_loop
+    }
+}
+
 /// Represents the context under which a function executes.
 ///
 /// The execution context contains the following items:
 /// * The operand stack for the invocation.
 /// * The values of the locals currently in scope.
+/// * The labels of the structured control instructions currently being executed.
 /// * A [`StackFrame`] representing the current location in the program.
 pub struct ExecutionContext {
     values: Vec<Value>,
     locals: Vec<Value>,
+    labels: Vec<Label>,
     frame: StackFrame,
 }
 
@@ -73,6 +155,7 @@ impl ExecutionContext {
             values: Vec::new(),
             frame,
             locals,
+            labels: Vec::new(),
         }
     }
 
@@ -81,6 +164,10 @@ impl ExecutionContext {
         &self.frame
     }
 
+    pub(crate) fn frame_mut(&mut self) -> &mut StackFrame {
+        &mut self.frame
+    }
+
     /// Pushes a new value on to the operand stack for this execution context.
     pub fn push(&mut self, value: Value) {
         // Don't push nils, just drop them.
@@ -89,6 +176,11 @@ impl ExecutionContext {
         }
     }
 
+    /// Gets the number of operands and locals in this execution context.
+    fn slots(&self) -> usize {
+        self.values.len() + self.locals.len()
+    }
+
     /// Pops a new value off the operand stack for this execution context.
     pub fn pop(&mut self) -> Option<Value> {
         self.values.pop()
@@ -99,6 +191,76 @@ impl ExecutionContext {
         self.values.is_empty()
     }
 
+    /// Gets the value `depth` entries down from the top of the operand stack.
+    pub fn peek(&self, depth: usize) -> Option<Value> {
+        if depth < self.values.len() {
+            Some(self.values[self.values.len() - 1 - depth])
+        } else {
+            None
+        }
+    }
+
+    /// Gets the operand stack for this execution context, from the bottom to the top.
+    pub fn values(&self) -> &[Value] {
+        &self.values
+    }
+
+    /// Gets the locals of this execution context, starting with the parameters.
+    pub fn locals(&self) -> &[Value] {
+        &self.locals
+    }
+
+    /// Gets the number of values on the operand stack for this execution context.
+    pub fn height(&self) -> usize {
+        self.values.len()
+    }
+
+    /// Discards operands down to `height`, preserving the `arity` values on top of the stack.
+    pub fn unwind(&mut self, height: usize, arity: usize) -> Result<(), TrapCause> {
+        if self.values.len() < height + arity {
+            return Err(TrapCause::StackUnderflow);
+        }
+        let kept = self.values.split_off(self.values.len() - arity);
+        self.values.truncate(height);
+        self.values.extend(kept);
+        Ok(())
+    }
+
+    /// Pushes a new [`Label`] on to the label stack for this execution context.
+    pub fn push_label(&mut self, label: Label) {
+        self.labels.push(label)
+    }
+
+    /// Pops the innermost [`Label`] off the label stack for this execution context.
+    pub fn pop_label(&mut self) -> Option<Label> {
+        self.labels.pop()
+    }
+
+    /// Gets the [`Label`] `depth` levels out from the innermost label.
+    pub fn label(&self, depth: usize) -> Option<&Label> {
+        if depth < self.labels.len() {
+            Some(&self.labels[self.labels.len() - 1 - depth])
+        } else {
+            None
+        }
+    }
+
+    /// Gets the labels currently in scope, from the outermost to the innermost.
+    pub fn labels(&self) -> &[Label] {
+        &self.labels
+    }
+
+    /// Gets the number of labels currently in scope.
+    pub fn label_count(&self) -> usize {
+        self.labels.len()
+    }
+
+    /// Discards the `count` innermost labels.
+    pub fn exit_labels(&mut self, count: usize) {
+        let len = self.labels.len().saturating_sub(count);
+        self.labels.truncate(len);
+    }
+
     /// Gets the value of the local with the specified index.
     pub fn local(&self, idx: usize) -> Option<Value> {
         if idx < self.locals.len() {
@@ -107,13 +269,68 @@ impl ExecutionContext {
             None
         }
     }
+
+    /// Sets the value of the local with the specified index, returning `false` if there is no such local.
+    pub fn set_local(&mut self, idx: usize, value: Value) -> bool {
+        match self.locals.get_mut(idx) {
+            Some(local) => {
+                *local = value;
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+/// Bounds the size of an [`ExecutionStack`], so that runaway recursion traps with
+/// [`TrapCause::StackExhausted`] instead of exhausting the host's memory.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct StackLimits {
+    /// The maximum number of nested execution contexts, one for each active function call.
+    pub max_frames: usize,
+    /// The maximum number of values, counting both operands and locals, across every frame.
+    pub max_slots: usize,
 }
 
-pub struct ExecutionStack(Vec<ExecutionContext>);
+impl Default for StackLimits {
+    fn default() -> StackLimits {
+        StackLimits {
+            max_frames: 10_000,
+            max_slots: 1 << 20,
+        }
+    }
+}
+
+pub struct ExecutionStack {
+    contexts: Vec<ExecutionContext>,
+    limits: StackLimits,
+    /// The number of slots used by every context below the current one. Only the current
+    /// context's values change, so this is updated as contexts are entered and exited.
+    slots_below: usize,
+    /// Contexts that have been exited, kept so entering a frame can reuse their allocations.
+    spare: Vec<ExecutionContext>,
+}
 
 impl ExecutionStack {
     pub fn new() -> ExecutionStack {
-        ExecutionStack(Vec::new())
+        ExecutionStack::with_limits(StackLimits::default())
+    }
+
+    pub fn with_limits(limits: StackLimits) -> ExecutionStack {
+        ExecutionStack {
+            contexts: Vec::new(),
+            limits,
+            slots_below: 0,
+            spare: Vec::new(),
+        }
+    }
+
+    pub fn limits(&self) -> StackLimits {
+        self.limits
+    }
+
+    pub fn set_limits(&mut self, limits: StackLimits) {
+        self.limits = limits;
     }
 
     /// Gets a reference to the active [`ExecutionContext`]
@@ -121,7 +338,7 @@ impl ExecutionStack {
     /// # Panics
     /// Panics if there is no current [`ExecutionContext`] on the stack
     pub fn current(&self) -> &ExecutionContext {
-        self.0.last().unwrap()
+        self.contexts.last().unwrap()
     }
 
     /// Gets a mutable reference to the active [`ExecutionContext`].
@@ -129,13 +346,78 @@ impl ExecutionStack {
     /// # Panics
     /// Panics if there is no current [`ExecutionContext`] on the stack
     pub fn current_mut(&mut self) -> &mut ExecutionContext {
-        self.0.last_mut().unwrap()
+        self.contexts.last_mut().unwrap()
+    }
+
+    /// Gets every [`ExecutionContext`] on the stack, from the outermost to the current one.
+    pub fn frames(&self) -> &[ExecutionContext] {
+        &self.contexts
+    }
+
+    /// Gets the number of [`ExecutionContext`]s on the stack.
+    pub fn depth(&self) -> usize {
+        self.contexts.len()
+    }
+
+    /// Gets the number of operands and locals in every [`ExecutionContext`] on the stack.
+    pub fn slots(&self) -> usize {
+        self.slots_below + self.contexts.last().map_or(0, |c| c.slots())
     }
 
-    /// Pushes a new [`ExecutionContext`] on to the stack
-    pub fn enter(&mut self, module: ModuleAddr, func: Option<FuncAddr>, locals: Vec<Value>) {
-        self.0
-            .push(ExecutionContext::new(StackFrame::new(module, func), locals))
+    /// Checks that the stack is within its limits.
+    pub fn check_limits(&self) -> Result<(), TrapCause> {
+        if self.depth() > self.limits.max_frames || self.slots() > self.limits.max_slots {
+            Err(TrapCause::StackExhausted)
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Pushes a new [`ExecutionContext`] on to the stack, unless doing so would exceed the
+    /// stack's limits.
+    pub fn enter(
+        &mut self,
+        module: ModuleAddr,
+        func: Option<FuncAddr>,
+        locals: Vec<Value>,
+    ) -> Result<(), TrapCause> {
+        self.enter_frame(StackFrame::new(module, func), locals)
+    }
+
+    /// Pushes a new [`ExecutionContext`] for `frame` on to the stack, unless doing so would
+    /// exceed the stack's limits.
+    pub fn enter_frame(&mut self, frame: StackFrame, locals: Vec<Value>) -> Result<(), TrapCause> {
+        if self.depth() >= self.limits.max_frames
+            || self.slots() + locals.len() > self.limits.max_slots
+        {
+            return Err(TrapCause::StackExhausted);
+        }
+
+        self.slots_below = self.slots();
+        let context = match self.spare.pop() {
+            Some(mut context) => {
+                context.frame = frame;
+                context.locals = locals;
+                context
+            }
+            None => ExecutionContext::new(frame, locals),
+        };
+        self.contexts.push(context);
+        Ok(())
+    }
+
+    /// Gets an empty vector to hold the locals of the next frame entered, reusing the
+    /// allocation of a frame that has exited if there is one.
+    pub(crate) fn take_locals(&mut self) -> Vec<Value> {
+        match self.spare.last_mut() {
+            Some(context) => std::mem::take(&mut context.locals),
+            None => Vec::new(),
+        }
+    }
+
+    /// Gets the number of exited [`ExecutionContext`]s whose allocations are kept for reuse.
+    pub fn spare_capacity(&self) -> usize {
+        self.spare.len()
     }
 
     /// Pops the current [`ExecutionContext`] (and all values associated with it) off the stack
@@ -143,17 +425,37 @@ impl ExecutionStack {
     /// # Panics
     /// Panics if there is no current [`ExecutionContext`] on the stack
     pub fn exit(&mut self) {
-        if self.0.len() == 0 {
+        if self.contexts.is_empty() {
             panic!("There is no current frame to exit!");
         } else {
-            self.0.pop();
+            if let Some(mut context) = self.contexts.pop() {
+                context.values.clear();
+                context.locals.clear();
+                context.labels.clear();
+                self.spare.push(context);
+            }
+            if let Some(context) = self.contexts.last() {
+                self.slots_below -= context.slots();
+            }
+        }
+    }
+
+    /// Exits every [`ExecutionContext`] on the stack, keeping their allocations for reuse.
+    pub fn clear(&mut self) {
+        while !self.contexts.is_empty() {
+            self.exit();
         }
     }
 
     /// Creates a [`StackTrace`] representing the current position in the stack.
     pub fn trace(&self) -> StackTrace {
         // Iterate up the stack from bottom to top, cloning the stack frames
-        let frames = self.0.iter().rev().map(|c| c.frame().clone()).collect();
+        let frames = self
+            .contexts
+            .iter()
+            .rev()
+            .map(|c| c.frame().clone())
+            .collect();
         StackTrace(frames)
     }
 
@@ -191,3 +493,165 @@ impl ExecutionStack {
         Ok((left, right))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        builder::{FuncBuilder, ModuleBuilder},
+        hosting::Host,
+        interp::{StepResult, Thread},
+        module::ModuleNames,
+        reader::{NameAssoc, NameSection},
+        TrapCause, ValType, Value,
+    };
+
+    #[test]
+    pub fn every_frame_can_be_inspected() {
+        use crate::Instruction::*;
+
+        let module = ModuleBuilder::new()
+            .func(
+                FuncBuilder::new()
+                    .export_as("outer")
+                    .param(ValType::I32)
+                    .result(ValType::I32)
+                    .body(vec![I32Const(Value::I32(7)), LocalGet(0), Call(1), I32Add]),
+            )
+            .func(
+                FuncBuilder::new()
+                    .param(ValType::I32)
+                    .result(ValType::I32)
+                    .locals(vec![ValType::I64])
+                    .body(vec![LocalGet(0), I32Const(Value::I32(1)), I32Add]),
+            );
+        let mut host = Host::new();
+        let addr = host.instantiate("test", module.build()).unwrap();
+        let outer = host.get_module(addr).unwrap().export_func("outer").unwrap();
+        let inner = host.get_module(addr).unwrap().funcs()[1];
+
+        let mut thread = Thread::new();
+        thread.start(&host, addr, outer, &[Value::I32(4)]).unwrap();
+        thread.breakpoints_mut().insert((inner, 2));
+        assert!(matches!(
+            thread.resume(&mut host),
+            StepResult::Paused { offset: 2, .. }
+        ));
+
+        // The frame holding the arguments, then each function from the outermost
+        let frames = thread.stack().frames();
+        assert_eq!(3, frames.len());
+        assert_eq!(None, frames[0].frame().func());
+        assert_eq!(Some(outer), frames[1].frame().func());
+        assert_eq!(&[Value::I32(4)], frames[1].locals());
+        assert_eq!(&[Value::I32(7)], frames[1].values());
+        assert_eq!(Some(inner), frames[2].frame().func());
+        assert_eq!(&[Value::I32(4), Value::I64(0)], frames[2].locals());
+        assert_eq!(&[Value::I32(4), Value::I32(1)], frames[2].values());
+    }
+
+    #[test]
+    pub fn frames_are_named_by_module_and_function() {
+        use crate::Instruction::*;
+
+        let mut module = ModuleBuilder::new()
+            .func(FuncBuilder::new().export_as("outer").body(vec![Call(1)]))
+            .func(FuncBuilder::new().body(vec![Unreachable]));
+        module.names = Some(ModuleNames::load(NameSection {
+            module_name: None,
+            func_names: vec![NameAssoc::new(1, "fail")],
+            ..NameSection::default()
+        }));
+        let mut host = Host::new();
+        let addr = host.instantiate("test", module.build()).unwrap();
+        let outer = host.get_module(addr).unwrap().export_func("outer").unwrap();
+
+        let trap = Thread::new().invoke(&mut host, outer).unwrap_err();
+        assert!(matches!(trap.cause(), TrapCause::Unreachable));
+        let names: Vec<_> = trap
+            .trace()
+            .unwrap()
+            .frames()
+            .iter()
+            .map(|f| f.name())
+            .collect();
+        assert_eq!(vec![Some("test!fail"), Some("test!<function 0>")], names);
+        assert_eq!(
+            "unreachable\n    at test!fail+0\n    at test!<function 0>+0",
+            trap.to_string()
+        );
+    }
+
+    #[test]
+    pub fn frames_record_the_offset_of_the_current_instruction() {
+        use crate::Instruction::*;
+
+        // The trap is after a branch, and in a function called after another call returned
+        let module = ModuleBuilder::new()
+            .func(
+                FuncBuilder::new()
+                    .export_as("outer")
+                    .body(vec![Call(2), Nop, Call(1)]),
+            )
+            .func(FuncBuilder::new().body(vec![
+                Block(ValType::Nil),
+                I32Const(Value::I32(1)),
+                BrIf(0),
+                Unreachable,
+                End,
+                Unreachable,
+            ]))
+            .func(FuncBuilder::new().body(vec![Nop]));
+        let mut host = Host::new();
+        let addr = host.instantiate("test", module.build()).unwrap();
+        let outer = host.get_module(addr).unwrap().export_func("outer").unwrap();
+
+        let trap = Thread::new().invoke(&mut host, outer).unwrap_err();
+        let offsets: Vec<_> = trap
+            .trace()
+            .unwrap()
+            .frames()
+            .iter()
+            .map(|f| f.offset())
+            .collect();
+        assert_eq!(vec![5, 2], offsets);
+    }
+
+    #[test]
+    pub fn reset_threads_reuse_their_stacks() {
+        use crate::Instruction::*;
+
+        let module = ModuleBuilder::new()
+            .func(
+                FuncBuilder::new()
+                    .export_as("outer")
+                    .param(ValType::I32)
+                    .result(ValType::I32)
+                    .body(vec![LocalGet(0), Call(1)]),
+            )
+            .func(
+                FuncBuilder::new()
+                    .param(ValType::I32)
+                    .result(ValType::I32)
+                    .body(vec![LocalGet(0), I32Const(Value::I32(1)), I32Add]),
+            );
+        let mut host = Host::new();
+        let addr = host.instantiate("test", module.build()).unwrap();
+        let outer = host.get_module(addr).unwrap().export_func("outer").unwrap();
+
+        let mut thread = Thread::new();
+        thread.start(&host, addr, outer, &[Value::I32(1)]).unwrap();
+        thread.step(&mut host);
+        thread.step(&mut host);
+        assert_eq!(3, thread.stack().depth());
+
+        thread.reset();
+        assert!(!thread.is_paused());
+        assert_eq!(0, thread.stack().depth());
+        assert_eq!(3, thread.stack().spare_capacity());
+
+        // Calls take their frames from the spare ones
+        let res = thread.call(&mut host, addr, outer, &[Value::I32(41)]);
+        assert_eq!(vec![Value::I32(42)], res.unwrap());
+        assert_eq!(3, thread.stack().spare_capacity());
+    }
+}
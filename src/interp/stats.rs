@@ -0,0 +1,87 @@
+use std::collections::HashMap;
+
+use crate::interp::Op;
+
+/// The number of times a [`Thread`](crate::interp::Thread) executed each kind of instruction,
+/// counted by mnemonic.
+///
+/// See [`Thread::set_opcode_stats`](crate::interp::Thread::set_opcode_stats).
+#[derive(Clone, PartialEq, Eq, Debug, Default)]
+pub struct OpcodeStats {
+    counts: HashMap<&'static str, u64>,
+    total: u64,
+}
+
+impl OpcodeStats {
+    /// Gets the number of times the instruction with the mnemonic `name` (such as `i32.add`)
+    /// was executed.
+    pub fn count(&self, name: &str) -> u64 {
+        self.counts.get(name).cloned().unwrap_or(0)
+    }
+
+    /// Gets the number of instructions executed.
+    pub fn total(&self) -> u64 {
+        self.total
+    }
+
+    /// Gets the count of each instruction that was executed, most frequent first.
+    pub fn histogram(&self) -> Vec<(&'static str, u64)> {
+        let mut counts: Vec<_> = self.counts.iter().map(|(n, c)| (*n, *c)).collect();
+        counts.sort_by(|(a, x), (b, y)| y.cmp(x).then_with(|| a.cmp(b)));
+        counts
+    }
+
+    pub(crate) fn record(&mut self, op: &Op) {
+        *self.counts.entry(op.name()).or_insert(0) += 1;
+        self.total += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        builder::{FuncBuilder, ModuleBuilder},
+        hosting::Host,
+        interp::Thread,
+        ValType, Value,
+    };
+
+    #[test]
+    pub fn executed_instructions_are_counted() {
+        use crate::Instruction::*;
+
+        // (func $count (param $n i32)) loops until $n reaches zero
+        let module = ModuleBuilder::new().func(
+            FuncBuilder::new()
+                .export_as("count")
+                .param(ValType::I32)
+                .body(vec![
+                    Loop(ValType::Nil),
+                    LocalGet(0),
+                    I32Const(Value::I32(1)),
+                    I32Sub,
+                    LocalTee(0),
+                    BrIf(0),
+                    End,
+                ]),
+        );
+        let mut host = Host::new();
+        let addr = host.instantiate("test", module.build()).unwrap();
+        let count = host.get_module(addr).unwrap().export_func("count").unwrap();
+
+        let mut thread = Thread::new();
+        thread.set_opcode_stats(true);
+        thread
+            .call(&mut host, addr, count, &[Value::I32(3)])
+            .unwrap();
+
+        let stats = thread.take_opcode_stats().unwrap();
+        assert_eq!(1, stats.count("loop"));
+        assert_eq!(3, stats.count("br_if"));
+        assert_eq!(3, stats.count("i32.sub"));
+        assert_eq!(0, stats.count("i32.add"));
+        assert_eq!(17, stats.total());
+        assert_eq!(("br_if", 3), stats.histogram()[0]);
+        assert!(thread.opcode_stats().is_none());
+    }
+}
@@ -1,21 +1,349 @@
+use std::{
+    collections::HashSet,
+    future::Future,
+    io::{Read, Write},
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+    thread,
+    time::{Duration, Instant},
+};
+
 use crate::{
-    hosting::{FuncAddr, FuncImpl, Host, ModuleAddr},
-    interp::{exec, ExecutionStack},
-    module::Expr,
-    Instruction, Trap, TrapCause, ValType, Value,
+    hosting::{
+        Event, ExternVal, ExternalFunc, FuncAddr, FuncImpl, FuncInst, Host, HostFuture, ModuleAddr,
+        Stub,
+    },
+    interp::{
+        claims::MemoryClaims,
+        exec::{self, Flow},
+        profiler::Profiler,
+        saved_thread, Code, Dispatch, ExecutionStack, FuelCosts, History, InterruptHandle,
+        ObserverHandle, OpcodeStats, Profile, StackFrame, StackLimits, StepResult, Suspend,
+        TraceSink,
+    },
+    module::{Expr, FuncType},
+    Error, FrameState, Instruction, Trap, TrapCause, ValType, Value,
 };
 
 pub struct Thread {
     stack: ExecutionStack,
+    trap_on_nan: bool,
+    canonicalize_nans: bool,
+    dispatch: Dispatch,
+    fuel: Option<u64>,
+    fuel_costs: FuelCosts,
+    interrupt: InterruptHandle,
+    observers: ObserverHandle,
+    trace_sink: Option<Box<dyn TraceSink>>,
+    breakpoints: HashSet<(FuncAddr, usize)>,
+    /// The invocation started by [`Thread::start`], if it hasn't completed.
+    paused: Option<Invocation>,
+    /// The suspension requested by the external function that is running, if any.
+    suspend: Option<Suspend>,
+    /// The future of the asynchronous host function [`Thread::invoke_async`] is waiting for.
+    pending: Option<HostFuture>,
+    profiler: Option<Profiler>,
+    opcode_stats: Option<OpcodeStats>,
+    history: Option<History>,
+    claims: MemoryClaims,
+}
+
+/// A local function that is running on a thread, and the instruction it resumes at when the
+/// function it called returns.
+struct Activation {
+    addr: FuncAddr,
+    func: Arc<FuncInst>,
+    pc: usize,
+}
+
+impl Activation {
+    fn code(&self) -> &Code {
+        match self.func.imp() {
+            FuncImpl::Local(code, _) => code,
+            FuncImpl::External(_) => unreachable!("external functions aren't activated"),
+        }
+    }
+}
+
+/// A call that is in progress on a thread.
+struct Invocation {
+    /// The depth of the stack when the call started.
+    base: usize,
+    calls: Vec<Activation>,
+    /// The function to enter before running the innermost activation, if any.
+    next: Option<FuncAddr>,
+}
+
+impl Invocation {
+    fn new(base: usize, func: FuncAddr) -> Invocation {
+        Invocation {
+            base,
+            calls: Vec::new(),
+            next: Some(func),
+        }
+    }
+}
+
+/// When a debugged invocation stops, after it executes at least one instruction.
+#[derive(Clone, Copy, PartialEq)]
+enum Pause {
+    /// Before the next instruction.
+    Step,
+    /// Before an instruction that has a breakpoint.
+    Breakpoint,
+    /// When an asynchronous host function is called, see [`Thread::invoke_async`].
+    Await,
+}
+
+/// The future of a call made by [`Thread::invoke_async`].
+pub struct InvokeAsync<'a> {
+    thread: &'a mut Thread,
+    host: &'a mut Host,
+    invocation: Invocation,
+}
+
+impl<'a> Future for InvokeAsync<'a> {
+    type Output = Result<Vec<Value>, Trap>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        let call = self.get_mut();
+        let thread = &mut *call.thread;
+        loop {
+            if let Some(pending) = &mut thread.pending {
+                let results = match pending.as_mut().poll(cx) {
+                    Poll::Pending => return Poll::Pending,
+                    Poll::Ready(results) => results,
+                };
+                thread.pending = None;
+                match results {
+                    Ok(values) if call.invocation.calls.is_empty() => {
+                        return Poll::Ready(Ok(values))
+                    }
+                    Ok(values) => {
+                        for value in values {
+                            thread.push(value);
+                        }
+                    }
+                    Err(e) => {
+                        let trap = thread.throw(e);
+                        let trap = thread.unwind(call.invocation.base, trap);
+                        report_trap(call.host, &trap);
+                        return Poll::Ready(Err(trap));
+                    }
+                }
+            }
+
+            match thread.drive(call.host, &mut call.invocation, Some(Pause::Await)) {
+                Ok(Some(results)) => return Poll::Ready(Ok(results)),
+                Ok(None) => {}
+                Err(trap) => {
+                    report_trap(call.host, &trap);
+                    return Poll::Ready(Err(trap));
+                }
+            }
+        }
+    }
+}
+
+impl<'a> Drop for InvokeAsync<'a> {
+    fn drop(&mut self) {
+        // A call abandoned while it waits for a host function leaves no frames behind
+        if self.thread.pending.take().is_some() {
+            self.thread.discard_frames(self.invocation.base);
+        }
+    }
 }
 
 impl Thread {
     pub fn new() -> Thread {
+        Thread::with_limits(StackLimits::default())
+    }
+
+    /// Creates a thread whose stack traps with [`TrapCause::StackExhausted`] when it grows
+    /// beyond `limits`.
+    pub fn with_limits(limits: StackLimits) -> Thread {
         Thread {
-            stack: ExecutionStack::new(),
+            stack: ExecutionStack::with_limits(limits),
+            trap_on_nan: false,
+            canonicalize_nans: false,
+            dispatch: Dispatch::default(),
+            fuel: None,
+            fuel_costs: FuelCosts::default(),
+            interrupt: InterruptHandle::new(),
+            observers: ObserverHandle::new(),
+            trace_sink: None,
+            breakpoints: HashSet::new(),
+            paused: None,
+            suspend: None,
+            pending: None,
+            profiler: None,
+            opcode_stats: None,
+            history: None,
+            claims: MemoryClaims::new(),
+        }
+    }
+
+    /// Gets a boolean indicating if scalar floating-point instructions trap when they consume
+    /// or produce a NaN.
+    pub fn trap_on_nan(&self) -> bool {
+        self.trap_on_nan
+    }
+
+    /// Enables or disables strict NaN mode.
+    ///
+    /// In strict mode, any scalar floating-point instruction (including comparisons and
+    /// conversions) that has a NaN operand or result traps with [`TrapCause::UnexpectedNaN`],
+    /// instead of letting the nondeterministic bits of the NaN flow through the program.
+    /// Loads, constants and SIMD instructions are not checked.
+    pub fn set_trap_on_nan(&mut self, enabled: bool) {
+        self.trap_on_nan = enabled;
+    }
+
+    /// Gets a boolean indicating if floating-point arithmetic produces the canonical NaN
+    /// instead of whatever NaN the machine produces.
+    pub fn canonicalize_nans(&self) -> bool {
+        self.canonicalize_nans
+    }
+
+    /// Enables or disables NaN canonicalization.
+    ///
+    /// The sign and payload of a NaN produced by arithmetic differ between machines. When
+    /// canonicalization is enabled, scalar and SIMD floating-point arithmetic replaces any NaN
+    /// it produces with a positive quiet NaN without a payload, so runs are bit-for-bit
+    /// reproducible. NaNs that are loaded, constant or only have their sign changed are left
+    /// alone, since they're already deterministic.
+    pub fn set_canonicalize_nans(&mut self, enabled: bool) {
+        self.canonicalize_nans = enabled;
+    }
+
+    pub fn dispatch(&self) -> Dispatch {
+        self.dispatch
+    }
+
+    /// Chooses how instructions are dispatched, which changes how fast they run but not what
+    /// they do. Mostly useful for comparing the strategies.
+    pub fn set_dispatch(&mut self, dispatch: Dispatch) {
+        self.dispatch = dispatch;
+    }
+
+    /// Gets the fuel remaining, or `None` if execution isn't metered.
+    pub fn fuel(&self) -> Option<u64> {
+        self.fuel
+    }
+
+    /// Sets the fuel available to the thread, or disables metering with `None`.
+    ///
+    /// While metering, each instruction consumes fuel according to the thread's
+    /// [`FuelCosts`] before it executes. An instruction that costs more than the fuel remaining
+    /// traps with [`TrapCause::OutOfFuel`] instead, which lets embedders bound how long an
+    /// untrusted module can run. Fuel is not refilled between calls.
+    pub fn set_fuel(&mut self, fuel: Option<u64>) {
+        self.fuel = fuel;
+    }
+
+    pub fn fuel_costs(&self) -> &FuelCosts {
+        &self.fuel_costs
+    }
+
+    pub fn set_fuel_costs(&mut self, costs: FuelCosts) {
+        self.fuel_costs = costs;
+    }
+
+    /// Gets a handle that other OS threads can use to interrupt the code running on this thread.
+    pub fn interrupt_handle(&self) -> InterruptHandle {
+        self.interrupt.clone()
+    }
+
+    /// Gets a handle that other OS threads can use to read memory safely while code runs on
+    /// this thread.
+    pub fn observer_handle(&self) -> ObserverHandle {
+        self.observers.clone()
+    }
+
+    /// Clears a pending interrupt, and returns an [`Interrupted`](TrapCause::Interrupted) trap
+    /// if there was one. External functions that run for a long time can call this so that
+    /// interrupting the thread stops them too.
+    pub fn check_interrupt(&self) -> Result<(), Trap> {
+        if self.interrupt.take() {
+            Err(TrapCause::Interrupted.into())
+        } else {
+            Ok(())
         }
     }
 
+    /// Installs a [`TraceSink`] that is called before each instruction executes, replacing any
+    /// previous sink.
+    pub fn set_trace_sink<S: TraceSink + 'static>(&mut self, sink: S) {
+        self.trace_sink = Some(Box::new(sink));
+    }
+
+    /// Removes the installed [`TraceSink`], if any.
+    pub fn take_trace_sink(&mut self) -> Option<Box<dyn TraceSink>> {
+        self.trace_sink.take()
+    }
+
+    /// Enables or disables recording a [`Profile`] of the functions this thread calls.
+    /// Disabling discards the profile recorded so far.
+    pub fn set_profiling(&mut self, enabled: bool) {
+        match (enabled, &self.profiler) {
+            (true, None) => self.profiler = Some(Profiler::default()),
+            (false, _) => self.profiler = None,
+            _ => {}
+        }
+    }
+
+    /// Gets the profile recorded so far, if profiling is enabled. Profiles accumulate across
+    /// calls.
+    pub fn profile(&self) -> Option<&Profile> {
+        self.profiler.as_ref().map(|p| p.profile())
+    }
+
+    /// Stops profiling, and returns the profile that was recorded.
+    pub fn take_profile(&mut self) -> Option<Profile> {
+        self.profiler.take().map(|p| p.into_profile())
+    }
+
+    /// Enables or disables counting the instructions this thread executes. Disabling
+    /// discards the counts so far.
+    pub fn set_opcode_stats(&mut self, enabled: bool) {
+        match (enabled, &self.opcode_stats) {
+            (true, None) => self.opcode_stats = Some(OpcodeStats::default()),
+            (false, _) => self.opcode_stats = None,
+            _ => {}
+        }
+    }
+
+    /// Gets the instructions counted so far, if counting is enabled.
+    pub fn opcode_stats(&self) -> Option<&OpcodeStats> {
+        self.opcode_stats.as_ref()
+    }
+
+    /// Stops counting instructions, and returns the counts.
+    pub fn take_opcode_stats(&mut self) -> Option<OpcodeStats> {
+        self.opcode_stats.take()
+    }
+
+    /// Keeps a [`History`] of the last `len` instructions this thread executes, or stops
+    /// keeping one if `len` is zero. Changing the length discards the history so far.
+    pub fn set_history_len(&mut self, len: usize) {
+        match &self.history {
+            _ if len == 0 => self.history = None,
+            Some(history) if history.capacity() == len => {}
+            _ => self.history = Some(History::new(len)),
+        }
+    }
+
+    /// Gets the instructions executed most recently, if a history is being kept.
+    pub fn history(&self) -> Option<&History> {
+        self.history.as_ref()
+    }
+
+    /// Stops keeping a history, and returns it.
+    pub fn take_history(&mut self) -> Option<History> {
+        self.history.take()
+    }
+
     pub fn stack(&self) -> &ExecutionStack {
         &self.stack
     }
@@ -32,7 +360,9 @@ impl Thread {
         host: &mut Host,
     ) -> Result<Value, Trap> {
         // Push a stack frame
-        self.stack.enter(module, None, Vec::new());
+        self.stack
+            .enter(module, None, Vec::new())
+            .map_err(|e| self.throw(e))?;
 
         // Evaluate the expression
         let val = match self.run(host, expr.instructions()) {
@@ -58,18 +388,24 @@ impl Thread {
     /// This method enters a new stack frame, pushes [`values`] to the stack, then invokes
     /// the requested function. Because this enters a new stack frame before evaluating the expressions,
     /// the stack will have **two** new frames by the time the function code actually runs
+    ///
+    /// Traps without running anything if the host doesn't allow [`func`] to be invoked.
     pub fn call(
         &mut self,
         host: &mut Host,
         module: ModuleAddr,
         func: FuncAddr,
-        mut values: Vec<Value>,
+        values: &[Value],
     ) -> Result<Vec<Value>, Trap> {
-        self.stack_mut().enter(module, None, Vec::new());
+        check_invokable(host, func)?;
+
+        self.stack
+            .enter(module, None, Vec::new())
+            .map_err(|e| self.throw(e))?;
 
         // Push the values on to the stack
-        for value in values.drain(..).rev() {
-            self.push(value);
+        for value in values {
+            self.push(*value);
         }
 
         let res = self.invoke(host, func);
@@ -81,93 +417,629 @@ impl Thread {
 
     /// Runs the function specified by [`func`] in the context of this thread.
     pub fn invoke(&mut self, host: &mut Host, func: FuncAddr) -> Result<Vec<Value>, Trap> {
-        // Resolve the function
-        let func_inst = host.get_func(func);
-        match func_inst.imp() {
-            FuncImpl::External(synth_fn) => synth_fn.invoke(host, self).map_err(|e| self.throw(e)),
-            FuncImpl::Local(code, _) => {
-                // Pop parameters
-                let mut locals =
-                    Vec::with_capacity(func_inst.typ().params().len() + code.locals().len());
-                for param in func_inst.typ().params() {
-                    if let Some(val) = self.stack.current_mut().pop() {
-                        if val.typ() != *param {
-                            return Err(self.throw(format!(
-                                "Type mismatch. Expected: {}, Actual: {}",
-                                param,
-                                val.typ()
-                            )));
+        let mut invocation = Invocation::new(self.stack.depth(), func);
+        match self.drive(host, &mut invocation, None) {
+            Ok(Some(results)) => Ok(results),
+            Ok(None) => unreachable!("invocations only pause while debugging"),
+            Err(trap) => {
+                report_trap(host, &trap);
+                Err(trap)
+            }
+        }
+    }
+
+    /// Runs the function specified by [`func`] like [`Thread::invoke`] does, but as a future
+    /// that's pending while an asynchronous host function it calls is, so I/O-bound imports
+    /// don't block an executor's thread. See
+    /// [`Host::define_async`](crate::hosting::Host::define_async).
+    ///
+    /// The thread's memory claims are released while the call is pending. Dropping the future
+    /// before it completes abandons the call.
+    pub fn invoke_async<'a>(&'a mut self, host: &'a mut Host, func: FuncAddr) -> InvokeAsync<'a> {
+        let invocation = Invocation::new(self.stack.depth(), func);
+        InvokeAsync {
+            thread: self,
+            host,
+            invocation,
+        }
+    }
+
+    /// Prepares to call `func` one instruction at a time with [`Thread::step`] and
+    /// [`Thread::resume`], instead of running it to completion like [`Thread::call`] does.
+    ///
+    /// Any invocation that was already paused on this thread is abandoned.
+    pub fn start(
+        &mut self,
+        host: &Host,
+        module: ModuleAddr,
+        func: FuncAddr,
+        values: &[Value],
+    ) -> Result<(), Trap> {
+        self.abandon();
+        check_invokable(host, func)?;
+
+        self.stack
+            .enter(module, None, Vec::new())
+            .map_err(|e| self.throw(e))?;
+        for value in values {
+            self.push(*value);
+        }
+        self.paused = Some(Invocation::new(self.stack.depth(), func));
+        Ok(())
+    }
+
+    /// Executes the next instruction of the paused invocation, stepping into calls.
+    pub fn step(&mut self, host: &mut Host) -> StepResult {
+        self.continue_paused(host, Pause::Step)
+    }
+
+    /// Runs the paused invocation until it reaches a breakpoint or completes. Resuming never
+    /// stops at the instruction the invocation is paused at, even if it has a breakpoint.
+    pub fn resume(&mut self, host: &mut Host) -> StepResult {
+        self.continue_paused(host, Pause::Breakpoint)
+    }
+
+    /// Gets a boolean indicating if an invocation started by [`Thread::start`] is paused.
+    pub fn is_paused(&self) -> bool {
+        self.paused.is_some()
+    }
+
+    /// Saves the invocation paused on this thread to `writer`, so it can be resumed later with
+    /// [`Thread::load_from`], possibly by another process. Its frames refer to functions and
+    /// instances in the host, whose state is saved separately with [`Host::save_to`].
+    ///
+    /// Fails with [`Error::InvalidState`] if no invocation is paused, or if its frames hold
+    /// references, which can't be saved.
+    pub fn save_to<W: Write>(&self, writer: &mut W) -> Result<(), Error> {
+        let invocation = self
+            .paused
+            .as_ref()
+            .ok_or_else(|| saved_thread::invalid("the thread has no paused invocation"))?;
+        // The first frame holds the arguments, and each of the others runs one of the calls
+        let pcs = Some(0)
+            .into_iter()
+            .chain(invocation.calls.iter().map(|a| a.pc));
+        let frames: Vec<_> = self.stack.frames()[invocation.base - 1..]
+            .iter()
+            .zip(pcs)
+            .collect();
+        saved_thread::write(writer, invocation.next, &frames)
+    }
+
+    /// Loads an invocation saved by [`Thread::save_to`] from `reader`, replacing the one paused
+    /// on this thread, if any, so [`Thread::step`] and [`Thread::resume`] continue it. The
+    /// host should be in the state it was in when the invocation was saved, see
+    /// [`Host::load_from`].
+    ///
+    /// The whole invocation is checked against the host before the thread is changed.
+    pub fn load_from<R: Read>(&mut self, host: &Host, reader: &mut R) -> Result<(), Error> {
+        let (next, frames) = saved_thread::read(host, reader)?;
+        let mut calls = Vec::with_capacity(frames.len());
+        for frame in frames.iter().skip(1) {
+            let addr = frame
+                .func
+                .ok_or_else(|| saved_thread::invalid("a frame doesn't run a function"))?;
+            let func = host.func(addr);
+            match func.imp() {
+                FuncImpl::Local(code, _) if frame.pc <= code.len() => {}
+                _ => {
+                    return Err(saved_thread::invalid(
+                        "a frame doesn't fit the function it runs",
+                    ))
+                }
+            }
+            calls.push(Activation {
+                addr,
+                func,
+                pc: frame.pc,
+            });
+        }
+        if frames.is_empty() || (calls.is_empty() && next.is_none()) {
+            return Err(saved_thread::invalid("the invocation has nothing to run"));
+        }
+
+        self.abandon();
+        let base = self.stack.depth() + 1;
+        for frame in frames {
+            let name = frame.func.and_then(|f| host.func(f).name().cloned());
+            let stack_frame = StackFrame::new(frame.module, frame.func).with_name(name);
+            if self.stack.enter_frame(stack_frame, frame.locals).is_err() {
+                self.discard_frames(base - 1);
+                return Err(saved_thread::invalid(
+                    "the invocation doesn't fit in the thread's stack limits",
+                ));
+            }
+            let context = self.stack.current_mut();
+            for value in frame.values {
+                context.push(value);
+            }
+            for label in frame.labels {
+                context.push_label(label);
+            }
+        }
+        self.paused = Some(Invocation { base, calls, next });
+        Ok(())
+    }
+
+    /// Asks for the invocation running on this thread to be suspended when the calling
+    /// external function returns, so cooperative guests can sleep or yield without holding up
+    /// the OS thread.
+    ///
+    /// If the invocation was started by [`Thread::start`], [`Thread::step`] or
+    /// [`Thread::resume`] stop and produce [`StepResult::Suspended`], and the embedder's
+    /// scheduler decides when to resume it. Otherwise there's no way to return to the
+    /// embedder, so the thread blocks until `resume` allows, or it's interrupted.
+    pub fn suspend(&mut self, resume: Suspend) {
+        self.suspend = Some(resume);
+    }
+
+    /// Gets the instructions, by function and instruction index, that [`Thread::resume`]
+    /// stops at.
+    pub fn breakpoints(&self) -> &HashSet<(FuncAddr, usize)> {
+        &self.breakpoints
+    }
+
+    pub fn breakpoints_mut(&mut self) -> &mut HashSet<(FuncAddr, usize)> {
+        &mut self.breakpoints
+    }
+
+    fn continue_paused(&mut self, host: &mut Host, pause: Pause) -> StepResult {
+        let mut invocation = match self.paused.take() {
+            Some(invocation) => invocation,
+            None => return StepResult::Trapped(TrapCause::NotPaused.into()),
+        };
+        let res = self.drive(host, &mut invocation, Some(pause));
+        match res {
+            Ok(None) => {
+                let (func, offset) = match invocation.calls.last() {
+                    Some(activation) => (activation.addr, activation.pc),
+                    None => unreachable!("a paused invocation has an active function"),
+                };
+                self.paused = Some(invocation);
+                match self.suspend.take() {
+                    Some(resume) => StepResult::Suspended {
+                        func,
+                        offset,
+                        resume,
+                    },
+                    None => StepResult::Paused { func, offset },
+                }
+            }
+            Ok(Some(results)) => {
+                self.stack.exit();
+                StepResult::Finished(results)
+            }
+            Err(trap) => {
+                self.stack.exit();
+                report_trap(host, &trap);
+                StepResult::Trapped(trap)
+            }
+        }
+    }
+
+    /// Returns the thread to how it was when it was created, ready for another call: the
+    /// paused invocation and any frames left on the stack are discarded, along with pending
+    /// interrupts and suspensions. Settings such as fuel, limits and sinks are kept.
+    ///
+    /// The stack keeps the memory it allocated, so embedders that call into a module in a hot
+    /// loop can reuse one thread without allocating stacks on each call.
+    pub fn reset(&mut self) {
+        self.abandon();
+        self.stack.clear();
+        if let Some(profiler) = &mut self.profiler {
+            profiler.exit_to(0);
+        }
+        self.suspend = None;
+        self.interrupt.take();
+    }
+
+    /// Discards the frames of the paused invocation, if any.
+    fn abandon(&mut self) {
+        if let Some(invocation) = self.paused.take() {
+            // Including the frame that holds the arguments
+            while self.stack.depth() >= invocation.base {
+                self.stack.exit();
+            }
+            if let Some(profiler) = &mut self.profiler {
+                profiler.exit_to(invocation.base - 1);
+            }
+        }
+    }
+
+    /// Runs an invocation until it completes, or until `pause` says to stop before an
+    /// instruction, which produces `None`.
+    ///
+    /// Calls between WebAssembly functions are tracked on the heap rather than by recursing,
+    /// so a deeply recursive guest traps with [`TrapCause::StackExhausted`] once the thread
+    /// reaches its [`StackLimits`], instead of overflowing the native stack.
+    ///
+    /// The memories of each module whose functions run are claimed for this thread, and
+    /// released when the outermost invocation returns, pauses or unwinds.
+    fn drive(
+        &mut self,
+        host: &mut Host,
+        invocation: &mut Invocation,
+        pause: Option<Pause>,
+    ) -> Result<Option<Vec<Value>>, Trap> {
+        let _entered = self.claims.enter();
+        self.reclaim(host, invocation)?;
+        self.drive_calls(host, invocation, pause)
+    }
+
+    /// Claims the memories of the functions a paused invocation is running again.
+    fn reclaim(&mut self, host: &Host, invocation: &Invocation) -> Result<(), Trap> {
+        for activation in &invocation.calls {
+            if let Err(e) = self.claims.claim(host, activation.func.module()) {
+                let trap = self.throw(e);
+                return Err(self.unwind(invocation.base, trap));
+            }
+        }
+        Ok(())
+    }
+
+    fn drive_calls(
+        &mut self,
+        host: &mut Host,
+        invocation: &mut Invocation,
+        pause: Option<Pause>,
+    ) -> Result<Option<Vec<Value>>, Trap> {
+        // Frames above this depth belong to this invocation, and are discarded if it traps
+        let base = invocation.base;
+        let calls = &mut invocation.calls;
+        let next = &mut invocation.next;
+        // The instruction execution is paused at doesn't pause it again
+        let mut executed = false;
+        let _running = self.observers.enter();
+        loop {
+            if let Some(func) = next.take() {
+                let func_inst = host.func(func);
+                // Functions that don't run on the heap-allocated call stack produce their
+                // results immediately
+                if let Some(profiler) = &mut self.profiler {
+                    profiler.enter(func, self.stack.depth() + 1);
+                }
+                let results = match (host.stub(func), func_inst.imp()) {
+                    (Some(stub), _) => Some(self.invoke_stub(host, func, &func_inst, stub)),
+                    (None, FuncImpl::External(synth_fn))
+                        if pause == Some(Pause::Await) && synth_fn.is_async() =>
+                    {
+                        self.pending = Some(synth_fn.start(host, self));
+                        if let Some(profiler) = &mut self.profiler {
+                            profiler.exit();
+                        }
+                        return Ok(None);
+                    }
+                    (None, FuncImpl::External(synth_fn)) => {
+                        Some(self.invoke_external(host, func, synth_fn))
+                    }
+                    (None, FuncImpl::Local(code, _)) => {
+                        let caller = calls.last().map(|activation| activation.func.module());
+                        if caller != Some(func_inst.module()) {
+                            if let Err(e) = self.claims.claim(host, func_inst.module()) {
+                                let trap = self.throw(e);
+                                return Err(self.unwind(base, trap));
+                            }
+                        }
+                        if let Err(e) = self.enter(func, &func_inst, code) {
+                            return Err(self.unwind(base, e));
+                        }
+                        calls.push(Activation {
+                            addr: func,
+                            func: func_inst.clone(),
+                            pc: 0,
+                        });
+                        None
+                    }
+                };
+                if let (Some(Ok(_)), Some(profiler)) = (&results, &mut self.profiler) {
+                    profiler.exit();
+                }
+                let suspend = self.suspend.take();
+                match results {
+                    Some(Ok(values)) if calls.is_empty() => return Ok(Some(values)),
+                    Some(Ok(values)) => {
+                        for value in values {
+                            self.push(value);
+                        }
+                        match (suspend, pause) {
+                            (Some(resume), Some(Pause::Step))
+                            | (Some(resume), Some(Pause::Breakpoint)) => {
+                                self.suspend = Some(resume);
+                                return Ok(None);
+                            }
+                            // There's no scheduler to hand the suspension to while awaiting
+                            (Some(resume), None) | (Some(resume), Some(Pause::Await)) => {
+                                self.block(resume)
+                            }
+                            (None, _) => {}
                         }
-                        locals.push(val);
-                    } else {
-                        return Err(self.throw("Stack underflow!"));
                     }
+                    Some(Err(e)) => return Err(self.unwind(base, e)),
+                    None => {}
+                }
+            }
+
+            // Run the innermost function until it calls another function or completes
+            let activation = match calls.last_mut() {
+                Some(activation) => activation,
+                None => unreachable!("a function should have been entered"),
+            };
+            let code = activation.code();
+            let mut pc = activation.pc;
+            let flow = loop {
+                if pc >= code.len() {
+                    break Ok(Flow::Return);
+                }
+                if let Some(pause) = pause {
+                    if executed
+                        && (pause == Pause::Step
+                            || (pause == Pause::Breakpoint
+                                && self.breakpoints.contains(&(activation.addr, pc))))
+                    {
+                        activation.pc = pc;
+                        return Ok(None);
+                    }
+                    executed = true;
+                }
+                match self.execute(host, code, pc) {
+                    Ok(Flow::Next) => pc += 1,
+                    Ok(Flow::Jump(target)) => pc = target,
+                    flow => break flow,
                 }
+            };
 
-                // Initialize locals
-                for local in code.locals() {
-                    let v = match local {
-                        ValType::Nil => unreachable!(),
-                        ValType::I32 => Value::I32(0),
-                        ValType::I64 => Value::I64(0),
-                        ValType::F32 => Value::F32(0.0),
-                        ValType::F64 => Value::F64(0.0),
+            match flow {
+                Err(e) => return Err(self.unwind(base, e)),
+                Ok(Flow::Next) | Ok(Flow::Jump(_)) => unreachable!("handled by the loop above"),
+                Ok(Flow::Call(callee)) => {
+                    activation.pc = pc + 1;
+                    *next = Some(callee);
+                }
+                Ok(Flow::TailCall(callee)) => {
+                    // A tail call replaces this frame: move the callee's arguments to the
+                    // caller's operand stack, discard this frame, and invoke the callee in its place.
+                    calls.pop();
+                    if let Err(e) = self.leave_for_tail_call(host, callee) {
+                        return Err(self.unwind(base, e));
+                    }
+                    if let Some(profiler) = &mut self.profiler {
+                        profiler.exit();
+                    }
+                    *next = Some(callee);
+                }
+                Ok(Flow::Return) => {
+                    let func_inst = match calls.pop() {
+                        Some(activation) => activation.func,
+                        None => unreachable!("the returning function should be on the stack"),
                     };
-                    locals.push(v);
-                }
-
-                self.stack
-                    .enter(func_inst.module().clone(), Some(func), locals);
-                if let Err(e) = self.run(host, code.body()) {
-                    self.stack.exit();
-                    return Err(e);
-                }
-
-                // Pop the result
-                // In WASM v1, there is only zero or one result.
-                let mut results = Vec::with_capacity(func_inst.typ().results().len());
-                for result in func_inst.typ().results() {
-                    if let Some(val) = self.stack.current_mut().pop() {
-                        if val.typ() != *result {
-                            return Err(self.throw(format!(
-                                "Type mismatch. Expected: {}, Actual: {}",
-                                result,
-                                val.typ()
-                            )));
-                        }
-                        results.push(val);
-                    } else {
-                        return Err(self.throw("Stack underflow!"));
+                    let results = match self.leave(func_inst.typ()) {
+                        Ok(results) => results,
+                        Err(e) => return Err(self.unwind(base, e)),
+                    };
+                    if let Some(profiler) = &mut self.profiler {
+                        profiler.exit();
+                    }
+                    if calls.is_empty() {
+                        return Ok(Some(results));
+                    }
+                    for value in results {
+                        self.push(value);
                     }
                 }
+            }
+        }
+    }
 
-                // Validate that the stack is empty
-                let result = if !self.stack.current().is_empty() {
-                    Err(self.throw(TrapCause::StackNotEmpty))
-                } else {
-                    Ok(results)
-                };
+    fn invoke_external(
+        &mut self,
+        host: &mut Host,
+        func: FuncAddr,
+        synth_fn: &ExternalFunc,
+    ) -> Result<Vec<Value>, Trap> {
+        if !host.is_tracking_import_latency() && !host.has_event_sink() {
+            return synth_fn.invoke(host, self).map_err(|e| self.throw(e));
+        }
 
-                // Exit the stack frame
-                self.stack.exit();
+        let caller = self.stack.current().frame().module();
+        let start = Instant::now();
+        let res = synth_fn.invoke(host, self);
+        let duration = start.elapsed();
+        host.record_import_latency(caller, func, duration);
+        host.report(&Event::HostCall {
+            caller,
+            func,
+            name: synth_fn.name(),
+            duration,
+            trapped: res.is_err(),
+        });
+        res.map_err(|e| self.throw(e))
+    }
 
-                result
+    /// Waits for a suspension to end, when there's no scheduler to hand it to.
+    fn block(&self, resume: Suspend) {
+        match resume {
+            Suspend::Yield => thread::yield_now(),
+            Suspend::Until(instant) => {
+                let timeout = instant.saturating_duration_since(Instant::now());
+                if timeout > Duration::from_secs(0) {
+                    // An interrupt ends the wait, and stays pending to trap at the next branch
+                    self.interrupt.wait(timeout);
+                }
             }
         }
     }
 
-    pub fn run(&mut self, host: &mut Host, code: &[Instruction]) -> Result<(), Trap> {
-        for inst in code {
-            self.execute(host, inst.clone())?;
+    /// Consumes the arguments of a stubbed function, and produces its results without
+    /// running it.
+    fn invoke_stub(
+        &mut self,
+        host: &Host,
+        func: FuncAddr,
+        func_inst: &FuncInst,
+        stub: Stub,
+    ) -> Result<Vec<Value>, Trap> {
+        if stub == Stub::Trap {
+            let name = host
+                .item_name(ExternVal::Func(func))
+                .unwrap_or_else(|| func.to_string());
+            return Err(self.throw(TrapCause::Stubbed { name }));
+        }
+
+        for _ in func_inst.typ().params() {
+            self.pop()?;
+        }
+        Ok(func_inst
+            .typ()
+            .results()
+            .iter()
+            .map(|r| r.default_value())
+            .collect())
+    }
+
+    /// Pops the parameters of a local function off the caller's operand stack, and enters a
+    /// new frame for it.
+    fn enter(&mut self, func: FuncAddr, func_inst: &FuncInst, code: &Code) -> Result<(), Trap> {
+        // A body that was loaded lazily is decoded and lowered when it's first called
+        if let Err(e) = code.prepare() {
+            let message = format!("Malformed function body: {:?}", e);
+            return Err(self.throw(Trap::new(TrapCause::InvalidCode).with_message(message)));
+        }
+
+        // Pop parameters, the last parameter is on the top of the stack
+        let params = func_inst.typ().params();
+        let mut locals = self.stack.take_locals();
+        locals.reserve(params.len() + code.locals().len());
+        for param in params.iter().rev() {
+            if let Some(val) = self.stack.current_mut().pop() {
+                if !param.accepts(val.typ()) {
+                    return Err(self.throw(TrapCause::TypeMismatch {
+                        expected: *param,
+                        actual: val.typ(),
+                    }));
+                }
+                locals.push(val);
+            } else {
+                return Err(self.throw(TrapCause::StackUnderflow));
+            }
+        }
+        locals.reverse();
+
+        // Initialize locals
+        for local in code.locals() {
+            if *local == ValType::Nil {
+                return Err(self.throw(
+                    Trap::new(TrapCause::InvalidCode)
+                        .with_message("Locals can't have the nil type!"),
+                ));
+            }
+            locals.push(local.default_value());
+        }
+
+        let frame =
+            StackFrame::new(func_inst.module(), Some(func)).with_name(func_inst.name().cloned());
+        self.stack
+            .enter_frame(frame, locals)
+            .map_err(|e| self.throw(e))
+    }
+
+    /// Pops the results of a function that has completed, and exits its frame.
+    fn leave(&mut self, typ: &FuncType) -> Result<Vec<Value>, Trap> {
+        // The last result is on top of the stack, so they're popped in reverse
+        let mut results = Vec::with_capacity(typ.results().len());
+        for result in typ.results().iter().rev() {
+            if let Some(val) = self.stack.current_mut().pop() {
+                if !result.accepts(val.typ()) {
+                    return Err(self.throw(TrapCause::TypeMismatch {
+                        expected: *result,
+                        actual: val.typ(),
+                    }));
+                }
+                results.push(val);
+            } else {
+                return Err(self.throw(TrapCause::StackUnderflow));
+            }
+        }
+
+        // Validate that the stack is empty
+        if !self.stack.current().is_empty() {
+            return Err(self.throw(TrapCause::StackNotEmpty));
+        }
+
+        self.stack.exit();
+        results.reverse();
+        Ok(results)
+    }
+
+    /// Moves the arguments for `callee` from the current frame to its caller's, and exits the
+    /// current frame.
+    fn leave_for_tail_call(&mut self, host: &Host, callee: FuncAddr) -> Result<(), Trap> {
+        let arg_count = host.func(callee).typ().params().len();
+        let mut args = Vec::with_capacity(arg_count);
+        for _ in 0..arg_count {
+            match self.stack.current_mut().pop() {
+                Some(v) => args.push(v),
+                None => return Err(self.throw(TrapCause::StackUnderflow)),
+            }
+        }
+        self.stack.exit();
+        for arg in args.into_iter().rev() {
+            self.push(arg);
         }
         Ok(())
     }
 
+    /// Exits the frames above `depth` after a trap.
+    fn unwind(&mut self, depth: usize, trap: Trap) -> Trap {
+        self.discard_frames(depth);
+        trap
+    }
+
+    /// Exits the frames above `depth`.
+    fn discard_frames(&mut self, depth: usize) {
+        while self.stack.depth() > depth {
+            self.stack.exit();
+        }
+        if let Some(profiler) = &mut self.profiler {
+            profiler.exit_to(depth);
+        }
+    }
+
+    pub fn run(&mut self, host: &mut Host, code: &[Instruction]) -> Result<(), Trap> {
+        match self.run_body(host, &Code::from_instructions(code))? {
+            None => Ok(()),
+            Some(_) => Err(self.throw(
+                Trap::new(TrapCause::InvalidCode)
+                    .with_message("Tail calls are only permitted in a function body."),
+            )),
+        }
+    }
+
+    /// Runs `code` until it completes or returns, producing the function to invoke next if
+    /// the code ends in a tail call.
+    fn run_body(&mut self, host: &mut Host, code: &Code) -> Result<Option<FuncAddr>, Trap> {
+        let mut pc = 0;
+        while pc < code.len() {
+            match self.execute(host, code, pc)? {
+                Flow::Next => pc += 1,
+                Flow::Jump(target) => pc = target,
+                Flow::Call(func) => {
+                    for value in self.invoke(host, func)? {
+                        self.push(value);
+                    }
+                    pc += 1;
+                }
+                Flow::Return => return Ok(None),
+                Flow::TailCall(func) => return Ok(Some(func)),
+            }
+        }
+        Ok(None)
+    }
+
     /// Tries to pop a value off the stack for the current frame, traps if there is no current value.
     pub fn pop(&mut self) -> Result<Value, Trap> {
         match self.stack.current_mut().pop() {
             Some(v) => Ok(v),
-            None => Err(self.throw("Stack underflow!")),
+            None => Err(self.throw(TrapCause::StackUnderflow)),
         }
     }
 
@@ -175,14 +1047,77 @@ impl Thread {
         self.stack.current_mut().push(v)
     }
 
-    fn execute(&mut self, host: &mut Host, inst: Instruction) -> Result<(), Trap> {
-        exec::execute(self, host, inst).map_err(|e| self.throw(e))
+    fn execute(&mut self, host: &mut Host, code: &Code, pc: usize) -> Result<Flow, Trap> {
+        self.stack.current_mut().frame_mut().set_offset(pc);
+        if let Some(sink) = &mut self.trace_sink {
+            let context = self.stack.current();
+            sink.before(context.frame(), pc, &code.ops()[pc], context.values());
+        }
+        if let Some(stats) = &mut self.opcode_stats {
+            stats.record(&code.ops()[pc]);
+        }
+        if let Some(history) = &mut self.history {
+            let context = self.stack.current();
+            history.record(
+                context.frame(),
+                self.stack.depth(),
+                pc,
+                &code.ops()[pc],
+                context.values(),
+            );
+        }
+
+        if let Some(fuel) = self.fuel {
+            let cost = self.fuel_costs.op_cost(&code.ops()[pc]);
+            if cost > fuel {
+                return Err(self.throw(TrapCause::OutOfFuel));
+            }
+            self.fuel = Some(fuel - cost);
+        }
+
+        let flow = exec::step(self, host, code, pc).map_err(|e| self.throw(e))?;
+        self.stack.check_limits().map_err(|e| self.throw(e))?;
+
+        // Every loop branches and all recursion calls, so checking there bounds the time
+        // until an interrupt or observer is noticed
+        match flow {
+            Flow::Jump(_) | Flow::Call(_) | Flow::TailCall(_) if self.interrupt.take() => {
+                Err(self.throw(TrapCause::Interrupted))
+            }
+            Flow::Jump(_) | Flow::Call(_) | Flow::TailCall(_) => {
+                self.observers.quiesce();
+                Ok(flow)
+            }
+            flow => Ok(flow),
+        }
     }
 
     /// Creates a new [`Trap`], capturing the current stack frame.
     fn throw<T: Into<Trap>>(&self, trap: T) -> Trap {
         let mut trap = trap.into();
-        trap.try_set_stack(self.stack.trace());
+        if trap.try_set_stack(self.stack.trace()) && self.stack.depth() > 0 {
+            let context = self.stack.current();
+            trap.try_set_frame_state(FrameState::new(
+                context.values().to_vec(),
+                context.locals().to_vec(),
+            ));
+        }
         trap
     }
 }
+
+/// Traps unless the embedder may call `func`, see [`Host::is_invokable`].
+fn check_invokable(host: &Host, func: FuncAddr) -> Result<(), Trap> {
+    match host.is_invokable(func) {
+        Ok(true) => Ok(()),
+        Ok(false) => Err(TrapCause::NotInvokable.into()),
+        Err(_) => Err(format!("{} refers to a function that has been dropped", func).into()),
+    }
+}
+
+/// Reports that a call trapped to the host's event sink, unless the guest exited.
+fn report_trap(host: &Host, trap: &Trap) {
+    if trap.exit_code().is_none() {
+        host.report(&Event::Trapped { trap });
+    }
+}
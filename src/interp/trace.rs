@@ -0,0 +1,265 @@
+use std::io::{self, BufRead, Write};
+
+use crate::{
+    interp::{Op, StackFrame},
+    Instruction, Value,
+};
+
+/// Receives a callback before each instruction a [`Thread`](crate::interp::Thread) executes,
+/// for building tracers, debuggers and differential tests.
+///
+/// See [`Thread::set_trace_sink`](crate::interp::Thread::set_trace_sink).
+pub trait TraceSink {
+    /// Called before `op`, the instruction at index `pc`, executes in `frame`. `stack` is the
+    /// operand stack of the frame, with the top of the stack last.
+    fn before(&mut self, frame: &StackFrame, pc: usize, op: &Op, stack: &[Value]);
+}
+
+/// A [`TraceSink`] that writes a compact execution trace, one event per line, so that two runs
+/// can be compared with [`first_divergence`].
+///
+/// Only events that decide the path execution takes are written: function entries, the
+/// outcomes of `if`, `br_if` and `br_table`, and `memory.grow` requests. Events identify
+/// functions by address and instructions by index, so the traces of two runs that instantiate
+/// the same modules in the same order can be compared line by line.
+///
+/// Writing stops at the first I/O error. Wrap `W` in a [`BufWriter`](std::io::BufWriter) for
+/// large traces, which is flushed when the thread drops the sink.
+pub struct TraceWriter<W: Write> {
+    out: W,
+    failed: bool,
+}
+
+impl<W: Write> TraceWriter<W> {
+    pub fn new(out: W) -> TraceWriter<W> {
+        TraceWriter { out, failed: false }
+    }
+
+    fn event(&mut self, frame: &StackFrame, pc: usize, op: &Op, stack: &[Value]) -> io::Result<()> {
+        if pc == 0 {
+            writeln!(self.out, "enter {}", frame)?;
+        }
+        let top = match stack.last() {
+            Some(&Value::I32(top)) => top,
+            _ => return Ok(()),
+        };
+        match *op {
+            Op::If { .. } => {
+                let arm = if top != 0 { "then" } else { "else" };
+                writeln!(self.out, "if {}+{} {}", frame, pc, arm)
+            }
+            Op::Inst(Instruction::BrIf(_)) => {
+                let taken = if top != 0 { "taken" } else { "not-taken" };
+                writeln!(self.out, "br_if {}+{} {}", frame, pc, taken)
+            }
+            Op::Inst(Instruction::BrTable(_)) => {
+                writeln!(self.out, "br_table {}+{} {}", frame, pc, top)
+            }
+            Op::Inst(Instruction::MemoryGrow(_)) => {
+                writeln!(self.out, "memory.grow {}+{} {}", frame, pc, top)
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+impl<W: Write> TraceSink for TraceWriter<W> {
+    fn before(&mut self, frame: &StackFrame, pc: usize, op: &Op, stack: &[Value]) {
+        if !self.failed && self.event(frame, pc, op, stack).is_err() {
+            self.failed = true;
+        }
+    }
+}
+
+/// The first line at which two traces differ. A missing line means that trace ended first.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Divergence {
+    /// The line number, starting at 1.
+    pub line: usize,
+    pub left: Option<String>,
+    pub right: Option<String>,
+}
+
+/// Compares two traces written by [`TraceWriter`], returning the first line at which they
+/// differ, or `None` if they are identical.
+pub fn first_divergence<A: BufRead, B: BufRead>(
+    left: A,
+    right: B,
+) -> io::Result<Option<Divergence>> {
+    let mut left = left.lines();
+    let mut right = right.lines();
+    let mut line = 0;
+    loop {
+        line += 1;
+        let (l, r) = match (left.next(), right.next()) {
+            (None, None) => return Ok(None),
+            (l, r) => (l.transpose()?, r.transpose()?),
+        };
+        if l != r {
+            return Ok(Some(Divergence {
+                line,
+                left: l,
+                right: r,
+            }));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        cell::RefCell,
+        io::{self, Cursor, Write},
+        rc::Rc,
+    };
+
+    use super::{first_divergence, Divergence, TraceSink, TraceWriter};
+    use crate::{
+        builder::{FuncBuilder, ModuleBuilder},
+        hosting::{FuncAddr, Host},
+        interp::{Op, StackFrame, Thread},
+        ValType, Value,
+    };
+
+    type Event = (Option<FuncAddr>, usize, Vec<Value>);
+
+    struct Recorder(Rc<RefCell<Vec<Event>>>);
+
+    impl TraceSink for Recorder {
+        fn before(&mut self, frame: &StackFrame, pc: usize, _op: &Op, stack: &[Value]) {
+            self.0.borrow_mut().push((frame.func(), pc, stack.to_vec()));
+        }
+    }
+
+    #[test]
+    pub fn sinks_see_every_instruction() {
+        use crate::Instruction::*;
+
+        let module = ModuleBuilder::new()
+            .func(
+                FuncBuilder::new()
+                    .export_as("double")
+                    .param(ValType::I32)
+                    .result(ValType::I32)
+                    .body(vec![LocalGet(0), Call(1)]),
+            )
+            .func(
+                FuncBuilder::new()
+                    .param(ValType::I32)
+                    .result(ValType::I32)
+                    .body(vec![LocalGet(0), LocalGet(0), I32Add]),
+            );
+        let mut host = Host::new();
+        let addr = host.instantiate("test", module.build()).unwrap();
+        let double = host
+            .get_module(addr)
+            .unwrap()
+            .export_func("double")
+            .unwrap();
+        let add = host.get_module(addr).unwrap().funcs()[1];
+
+        let events = Rc::new(RefCell::new(Vec::new()));
+        let mut thread = Thread::new();
+        thread.set_trace_sink(Recorder(events.clone()));
+        let res = thread.call(&mut host, addr, double, &[Value::I32(21)]);
+        assert_eq!(Ok(vec![Value::I32(42)]), res);
+
+        let i32s = |values: &[u32]| values.iter().map(|v| Value::I32(*v)).collect::<Vec<_>>();
+        assert_eq!(
+            vec![
+                (Some(double), 0, i32s(&[])),
+                (Some(double), 1, i32s(&[21])),
+                (Some(add), 0, i32s(&[])),
+                (Some(add), 1, i32s(&[21])),
+                (Some(add), 2, i32s(&[21, 21])),
+            ],
+            *events.borrow()
+        );
+
+        // Once removed, the sink is no longer called
+        assert!(thread.take_trace_sink().is_some());
+        thread
+            .call(&mut host, addr, double, &[Value::I32(1)])
+            .unwrap();
+        assert_eq!(5, events.borrow().len());
+    }
+
+    #[derive(Clone, Default)]
+    struct SharedBuf(Rc<RefCell<Vec<u8>>>);
+
+    impl Write for SharedBuf {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.borrow_mut().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    pub fn traces_diverge_at_the_first_different_decision() {
+        use crate::Instruction::*;
+
+        // (func $pick (param i32) (result i32)) returns 10 for true and 20 for false
+        let module = ModuleBuilder::new().func(
+            FuncBuilder::new()
+                .export_as("pick")
+                .param(ValType::I32)
+                .result(ValType::I32)
+                .body(vec![
+                    LocalGet(0),
+                    If(ValType::I32),
+                    I32Const(Value::I32(10)),
+                    Else,
+                    I32Const(Value::I32(20)),
+                    End,
+                ]),
+        );
+        let mut host = Host::new();
+        let addr = host.instantiate("test", module.build()).unwrap();
+        let pick = host.get_module(addr).unwrap().export_func("pick").unwrap();
+
+        let mut trace = |arg| {
+            let buf = SharedBuf::default();
+            let mut thread = Thread::new();
+            thread.set_trace_sink(TraceWriter::new(buf.clone()));
+            thread
+                .call(&mut host, addr, pick, &[Value::I32(arg)])
+                .unwrap();
+            let bytes = buf.0.borrow().clone();
+            bytes
+        };
+        let first = trace(1);
+        let second = trace(1);
+        let third = trace(0);
+
+        // Frames are written by name, so traces from different hosts line up
+        let frame = host.get_func(pick).unwrap().name().cloned().unwrap();
+        let enter = format!("enter {}\n", frame);
+        assert_eq!(
+            format!("{}if {}+1 then\n", enter, frame),
+            String::from_utf8(first.clone()).unwrap()
+        );
+        assert_eq!(
+            None,
+            first_divergence(Cursor::new(&first), Cursor::new(&second)).unwrap()
+        );
+        assert_eq!(
+            Some(Divergence {
+                line: 2,
+                left: Some(format!("if {}+1 then", frame)),
+                right: Some(format!("if {}+1 else", frame)),
+            }),
+            first_divergence(Cursor::new(&first), Cursor::new(&third)).unwrap()
+        );
+        assert_eq!(
+            Some(Divergence {
+                line: 2,
+                left: Some(format!("if {}+1 then", frame)),
+                right: None,
+            }),
+            first_divergence(Cursor::new(&first), Cursor::new(&first[..enter.len()])).unwrap()
+        );
+    }
+}
@@ -2,11 +2,17 @@
 // #![deny(warnings)]
 // Quiet down some warnings when running tests.
 #![cfg_attr(test, allow(dead_code))]
+// Types in this crate use `new()` constructors without `Default` implementations.
+#![allow(clippy::new_without_default)]
+// `module::module` holds the `Module` type.
+#![allow(clippy::module_inception)]
 // The isa! macro is very recursive because it's a push-down accumulator.
 #![recursion_limit = "256"]
 
 extern crate byteorder;
 extern crate leb128;
+#[cfg(feature = "wat")]
+extern crate wat;
 
 // This module has to be imported first because macros are processed
 // in a single pass.
@@ -14,8 +20,11 @@ mod macros;
 
 mod error;
 mod instruction;
+mod json;
 mod location;
 mod memory;
+#[cfg(feature = "mmap")]
+mod mmap;
 mod sparse_vec;
 mod trap;
 mod utils;
@@ -27,12 +36,18 @@ pub mod interp;
 pub mod module;
 pub mod reader;
 pub mod runtime;
+pub mod validate;
 
-pub use crate::error::Error;
+pub use crate::error::{Error, SectionOffset};
 pub use crate::instruction::Instruction;
 pub use crate::location::Location;
-pub use crate::memory::Memory;
-pub use crate::trap::{Trap, TrapCause};
+pub use crate::memory::{
+    BufferMemory, FileBackend, HeapBackend, LinearMemory, Memory, MemoryBackend, MemoryConfig,
+    Zeroing,
+};
+#[cfg(feature = "mmap")]
+pub use crate::mmap::MmapBackend;
+pub use crate::trap::{FrameState, IndexSpace, Trap, TrapCause};
 pub use crate::value::{FromValue, ValType, Value};
 
 pub(crate) use crate::sparse_vec::SparseVec;
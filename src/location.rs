@@ -40,13 +40,11 @@ impl Location {
     }
 
     pub fn module_name(&self) -> Option<&str> {
-        // Convert &String to &str with this one weird trick!
-        self.module_name.as_ref().map(|x| &**x)
+        self.module_name.as_deref()
     }
 
     pub fn func_name(&self) -> Option<&str> {
-        // Convert &String to &str with this one weird trick!
-        self.func_name.as_ref().map(|x| &**x)
+        self.func_name.as_deref()
     }
 }
 
@@ -1,39 +1,734 @@
 use std::{
     alloc::{self, Layout},
-    mem, slice,
+    convert::TryFrom,
+    fs::{self, File, OpenOptions},
+    io::{Read, Seek, SeekFrom, Write},
+    mem,
+    path::{Path, PathBuf},
+    ptr::{self, NonNull},
+    slice,
+    sync::{
+        atomic::{AtomicPtr, AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
 };
 
 use crate::error::Error;
 
+/// Storage for the contents of a linear memory, allocated by a [`MemoryBackend`].
+///
+/// # Safety
+/// `ptr` must point to `len` bytes that are valid for reads and writes. `len` may only change
+/// by growing when the storage is committed, and `ptr` may only change then too, if the storage
+/// has to move to grow. Storage allocated at its maximum size must never move. The storage may
+/// be read from other threads, see [`ObserverHandle`](crate::interp::ObserverHandle).
+pub unsafe trait LinearMemory: Send + Sync {
+    fn ptr(&self) -> *mut u8;
+    fn len(&self) -> usize;
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Gets the number of bytes the storage can grow to. By default it can't grow.
+    fn capacity(&self) -> usize {
+        self.len()
+    }
+
+    /// Makes at least the first `len` bytes valid for reads and writes, zeroing any that
+    /// weren't. `len` is never more than the capacity. Produces `false` if the storage can't
+    /// grow after all, such as when the system is out of memory.
+    fn commit(&self, len: usize) -> bool {
+        len <= self.len()
+    }
+
+    /// Gets the number of bytes of existing contents the storage was loaded with, such as from
+    /// a file, which the memory starts out at least as large as. Fresh storage has none.
+    fn loaded_len(&self) -> usize {
+        0
+    }
+
+    /// Writes the first `len` bytes of the storage, the memory's current size, to whatever backs
+    /// it, if anything. Storage that isn't backed by anything more durable than the process
+    /// does nothing.
+    fn persist(&self, len: usize) -> Result<(), Error> {
+        let _ = len;
+        Ok(())
+    }
+
+    /// Creates a copy of the storage that shares its pages until either copy writes to them,
+    /// for [`MemoryBackend::fork`]. Produces `None` if the storage can't share its pages, which
+    /// it can't by default.
+    fn fork(&self) -> Option<Box<dyn LinearMemory>> {
+        None
+    }
+}
+
+// Storage shared with the embedder keeps its promises however many owners it has
+unsafe impl<L: LinearMemory + ?Sized> LinearMemory for Arc<L> {
+    fn ptr(&self) -> *mut u8 {
+        (**self).ptr()
+    }
+
+    fn len(&self) -> usize {
+        (**self).len()
+    }
+
+    fn capacity(&self) -> usize {
+        (**self).capacity()
+    }
+
+    fn commit(&self, len: usize) -> bool {
+        (**self).commit(len)
+    }
+
+    fn loaded_len(&self) -> usize {
+        (**self).loaded_len()
+    }
+
+    fn persist(&self, len: usize) -> Result<(), Error> {
+        (**self).persist(len)
+    }
+
+    fn fork(&self) -> Option<Box<dyn LinearMemory>> {
+        (**self).fork()
+    }
+}
+
+/// Allocates the storage for linear memories, so embedders can choose how memory is provided:
+/// from the heap, from a mapping that reserves room to grow, or from memory shared with
+/// another process.
+///
+/// Memory accesses are always bounds checked by the interpreter, so a backend only needs to
+/// provide the bytes. Storage that can grow reports a larger [`LinearMemory::capacity`] than it
+/// allocates.
+pub trait MemoryBackend: Send + Sync {
+    /// Allocates `min_size` zeroed bytes for a memory that may grow up to `max_size` bytes.
+    fn allocate(
+        &self,
+        min_size: usize,
+        max_size: Option<usize>,
+    ) -> Result<Box<dyn LinearMemory>, Error>;
+
+    /// Allocates a copy of `parent` for a forked instance, see
+    /// [`Host::fork_instance`](crate::hosting::Host::fork_instance).
+    ///
+    /// Backends that can map the same pages copy-on-write should override this, see
+    /// [`LinearMemory::fork`]. By default the contents are copied eagerly into a new allocation.
+    fn fork(
+        &self,
+        parent: &dyn LinearMemory,
+        max_size: Option<usize>,
+    ) -> Result<Box<dyn LinearMemory>, Error> {
+        copy_of(self, parent, max_size)
+    }
+}
+
+/// Allocates storage from `backend` holding a copy of the contents of `parent`.
+pub(crate) fn copy_of<B: MemoryBackend + ?Sized>(
+    backend: &B,
+    parent: &dyn LinearMemory,
+    max_size: Option<usize>,
+) -> Result<Box<dyn LinearMemory>, Error> {
+    let memory = backend.allocate(parent.len(), max_size)?;
+    let len = parent.len().min(memory.len());
+    unsafe { ptr::copy_nonoverlapping(parent.ptr(), memory.ptr(), len) };
+    Ok(memory)
+}
+
+/// The default [`MemoryBackend`], which allocates memories on the heap.
+///
+/// Large allocations are served by fresh zeroed pages from the operating system, which are
+/// only backed by physical memory once touched. This keeps sparse use of a large 64-bit
+/// address space cheap. Memories grow up to their maximum size by reallocating, which may move
+/// their contents.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct HeapBackend;
+
+impl MemoryBackend for HeapBackend {
+    fn allocate(
+        &self,
+        min_size: usize,
+        max_size: Option<usize>,
+    ) -> Result<Box<dyn LinearMemory>, Error> {
+        let memory = HeapMemory {
+            ptr: AtomicPtr::new(NonNull::dangling().as_ptr()),
+            len: AtomicUsize::new(0),
+            max_size,
+            growing: Mutex::new(()),
+        };
+        // Zero-sized allocations aren't permitted by the allocator, so those stay dangling
+        if min_size > 0 && !memory.commit(min_size) {
+            return Err(Error::OutOfMemory { size: min_size });
+        }
+        Ok(Box::new(memory))
+    }
+}
+
+struct HeapMemory {
+    ptr: AtomicPtr<u8>,
+    len: AtomicUsize,
+    max_size: Option<usize>,
+    /// Serializes growth, so the allocation is only replaced once at a time.
+    growing: Mutex<()>,
+}
+
+unsafe impl LinearMemory for HeapMemory {
+    fn ptr(&self) -> *mut u8 {
+        self.ptr.load(Ordering::Acquire)
+    }
+
+    fn len(&self) -> usize {
+        self.len.load(Ordering::Acquire)
+    }
+
+    fn capacity(&self) -> usize {
+        // The allocator can't provide more than isize::MAX bytes
+        self.max_size.unwrap_or(isize::MAX as usize)
+    }
+
+    fn commit(&self, len: usize) -> bool {
+        let _guard = self.growing.lock().unwrap();
+        let old_len = self.len();
+        if len <= old_len {
+            return true;
+        }
+        let layout = match Layout::from_size_align(len, mem::align_of::<u8>()) {
+            Ok(layout) => layout,
+            Err(_) => return false,
+        };
+        let ptr = unsafe {
+            if old_len == 0 {
+                alloc::alloc_zeroed(layout)
+            } else {
+                let old_layout = Layout::from_size_align_unchecked(old_len, layout.align());
+                let ptr = alloc::realloc(self.ptr(), old_layout, len);
+                if !ptr.is_null() {
+                    ptr::write_bytes(ptr.add(old_len), 0, len - old_len);
+                }
+                ptr
+            }
+        };
+        if ptr.is_null() {
+            return false;
+        }
+        self.ptr.store(ptr, Ordering::Release);
+        self.len.store(len, Ordering::Release);
+        true
+    }
+}
+
+impl Drop for HeapMemory {
+    fn drop(&mut self) {
+        let len = self.len();
+        if len > 0 {
+            let layout = Layout::from_size_align(len, mem::align_of::<u8>()).unwrap();
+            unsafe { alloc::dealloc(self.ptr(), layout) }
+        }
+    }
+}
+
+/// Storage for a memory in a buffer the embedder already has, so it can be shared with a guest
+/// without copying. See
+/// [`ExternalMemory::with_storage`](crate::hosting::ExternalMemory::with_storage).
+///
+/// The memory grows in place into the buffer's spare capacity, which is zeroed as it's used.
+pub struct BufferMemory {
+    ptr: *mut u8,
+    len: AtomicUsize,
+    capacity: usize,
+    /// Serializes growth, so bytes are zeroed once.
+    growing: Mutex<()>,
+    on_grow: Option<Box<dyn Fn(usize) -> bool + Send + Sync>>,
+    // Owns the buffer if it was given as a Vec, which is only accessed through the pointer
+    _buf: Option<Vec<u8>>,
+}
+
+// The buffer is only accessed through the pointer, and growth is serialized
+unsafe impl Send for BufferMemory {}
+unsafe impl Sync for BufferMemory {}
+
+impl BufferMemory {
+    /// Uses the contents of `buf` as the memory's, and its spare capacity as room to grow.
+    pub fn new(mut buf: Vec<u8>) -> BufferMemory {
+        BufferMemory {
+            ptr: buf.as_mut_ptr(),
+            len: AtomicUsize::new(buf.len()),
+            capacity: buf.capacity(),
+            growing: Mutex::new(()),
+            on_grow: None,
+            _buf: Some(buf),
+        }
+    }
+
+    /// Uses the `len` bytes at `ptr` as the memory's contents, with room for it to grow to
+    /// `capacity` bytes.
+    ///
+    /// # Safety
+    /// `ptr` must be valid for reads and writes of `len` bytes, and of `capacity` bytes once
+    /// the memory grows, for as long as the storage is alive. While guests run, the buffer
+    /// must only be accessed as a guest's memory may be, see [`Memory::data`].
+    pub unsafe fn from_raw_parts(ptr: *mut u8, len: usize, capacity: usize) -> BufferMemory {
+        BufferMemory {
+            ptr,
+            len: AtomicUsize::new(len),
+            capacity: capacity.max(len),
+            growing: Mutex::new(()),
+            on_grow: None,
+            _buf: None,
+        }
+    }
+
+    /// Calls `f` with the new size of the memory, in bytes, before it grows. The memory only
+    /// grows if `f` produces `true`, so the embedder can refuse, or prepare the buffer.
+    pub fn on_grow<F: Fn(usize) -> bool + Send + Sync + 'static>(mut self, f: F) -> BufferMemory {
+        self.on_grow = Some(Box::new(f));
+        self
+    }
+}
+
+unsafe impl LinearMemory for BufferMemory {
+    fn ptr(&self) -> *mut u8 {
+        self.ptr
+    }
+
+    fn len(&self) -> usize {
+        self.len.load(Ordering::Acquire)
+    }
+
+    fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    fn commit(&self, len: usize) -> bool {
+        let _guard = self.growing.lock().unwrap();
+        let old_len = self.len();
+        if len <= old_len {
+            return true;
+        }
+        if self.on_grow.as_ref().is_some_and(|f| !f(len)) {
+            return false;
+        }
+        unsafe { ptr::write_bytes(self.ptr.add(old_len), 0, len - old_len) };
+        self.len.store(len, Ordering::Release);
+        true
+    }
+}
+
+/// A [`MemoryBackend`] that keeps the contents of each memory in a file, so guest state can
+/// survive across runs.
+///
+/// Memories are given files in `dir` in the order they are allocated: `memory0.bin`,
+/// `memory1.bin` and so on. Instantiating the same modules in the same order therefore finds the
+/// same files again. An existing file is loaded when its memory is allocated, and written back
+/// whenever the memory is persisted with [`MemInst::persist`](crate::hosting::MemInst::persist).
+/// A memory that grew before it was persisted starts out at the size it grew to, so a file
+/// larger than the memory's maximum size is refused with [`Error::InvalidState`]. Files are
+/// never truncated. Note that data segments are still applied over the loaded contents when a
+/// module is instantiated.
+#[derive(Debug)]
+pub struct FileBackend {
+    dir: PathBuf,
+    allocated: AtomicUsize,
+}
+
+impl FileBackend {
+    /// Creates a backend that stores memories in `dir`, creating it if it doesn't exist.
+    pub fn new<P: Into<PathBuf>>(dir: P) -> Result<FileBackend, Error> {
+        let dir = dir.into();
+        fs::create_dir_all(&dir)?;
+        Ok(FileBackend {
+            dir,
+            allocated: AtomicUsize::new(0),
+        })
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+}
+
+impl MemoryBackend for FileBackend {
+    fn allocate(
+        &self,
+        min_size: usize,
+        max_size: Option<usize>,
+    ) -> Result<Box<dyn LinearMemory>, Error> {
+        let index = self.allocated.fetch_add(1, Ordering::SeqCst);
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(self.dir.join(format!("memory{}.bin", index)))?;
+
+        // Load whatever was persisted last time, the rest of the memory starts zeroed
+        let loaded = usize::try_from(file.metadata()?.len())
+            .ok()
+            .filter(|len| max_size.is_none_or(|max_size| *len <= max_size))
+            .ok_or(Error::InvalidState {
+                module: None,
+                reason: "memory file is larger than the memory's maximum size",
+            })?;
+        let heap = HeapBackend.allocate(min_size.max(loaded), max_size)?;
+        file.read_exact(unsafe { slice::from_raw_parts_mut(heap.ptr(), loaded) })?;
+        Ok(Box::new(FileMemory { file, heap, loaded }))
+    }
+}
+
+struct FileMemory {
+    file: File,
+    heap: Box<dyn LinearMemory>,
+    loaded: usize,
+}
+
+unsafe impl LinearMemory for FileMemory {
+    fn ptr(&self) -> *mut u8 {
+        self.heap.ptr()
+    }
+
+    fn len(&self) -> usize {
+        self.heap.len()
+    }
+
+    fn capacity(&self) -> usize {
+        self.heap.capacity()
+    }
+
+    fn commit(&self, len: usize) -> bool {
+        self.heap.commit(len)
+    }
+
+    fn loaded_len(&self) -> usize {
+        self.loaded
+    }
+
+    fn persist(&self, len: usize) -> Result<(), Error> {
+        let contents = unsafe { slice::from_raw_parts(self.ptr(), len.min(self.len())) };
+        let mut file = &self.file;
+        file.seek(SeekFrom::Start(0))?;
+        file.write_all(contents)?;
+        file.sync_data()?;
+        Ok(())
+    }
+}
+
+/// How a host allocates the storage of its linear memories. See
+/// [`Host::set_memory_config`](crate::hosting::Host::set_memory_config).
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct MemoryConfig {
+    /// The number of bytes to allocate for each memory up front. Memories that grow beyond it
+    /// get more storage from their backend, which for the [`HeapBackend`] means reallocating
+    /// and copying their contents. Memories are always given at least their initial size, and
+    /// never more than their maximum size. Shared memories are allocated at their maximum size,
+    /// so they never move.
+    pub reserve: usize,
+    /// The most pages any memory may have, whatever its type allows. Memories that need more
+    /// to begin with can't be allocated, and growing beyond this fails.
+    pub max_pages: Option<usize>,
+    pub zeroing: Zeroing,
+}
+
+/// When the storage reserved for a memory is zeroed.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Zeroing {
+    /// Whenever the backend zeroes it. For the [`HeapBackend`], large reservations are fresh
+    /// pages from the operating system, which are zeroed as they're first touched.
+    #[default]
+    Lazy,
+    /// As soon as the memory is allocated, so the whole reservation is backed by physical
+    /// memory before the guest runs and it never waits for a page to be zeroed.
+    Eager,
+}
+
 /// Represents a growable linear memory, with an optional maximum size
 ///
 /// WebAssembly memory is inherently "unsafe" in Rust terms because the
 /// WebAssembly runtime doesn't expect the same safety guarantees. It's up to the
 /// WebAssembly program to ensure safety
-pub struct Memory(*mut u8, usize, Option<usize>);
+///
+/// The storage of a memory may move when it grows, see [`LinearMemory`], so neither the
+/// pointer to its contents nor a slice from [`Memory::data`] may be kept across a grow.
+pub struct Memory {
+    /// The number of bytes in use, which only grows, up to the size of the storage.
+    len: AtomicUsize,
+    max_size: Option<usize>,
+    storage: Box<dyn LinearMemory>,
+}
 
 impl Memory {
+    /// Allocates a zeroed memory of `min_size` bytes on the heap.
     pub fn new(min_size: usize, max_size: Option<usize>) -> Result<Memory, Error> {
-        let layout = Layout::from_size_align(min_size, mem::align_of::<u8>())?;
-        unsafe {
-            let mem = alloc::alloc_zeroed(layout);
-            Ok(Memory(mem, min_size, max_size))
+        Memory::with_backend(&HeapBackend, min_size, max_size)
+    }
+
+    /// Allocates a zeroed memory of `min_size` bytes from `backend`.
+    pub fn with_backend(
+        backend: &dyn MemoryBackend,
+        min_size: usize,
+        max_size: Option<usize>,
+    ) -> Result<Memory, Error> {
+        Memory::with_config(backend, min_size, max_size, &MemoryConfig::default())
+    }
+
+    /// Allocates a zeroed memory of `min_size` bytes from `backend`, reserving and zeroing
+    /// storage as `config` says. Storage loaded with existing contents makes the memory as
+    /// large as they are. The memory's maximum size is left to the caller, so
+    /// `config.max_pages` isn't applied.
+    pub fn with_config(
+        backend: &dyn MemoryBackend,
+        min_size: usize,
+        max_size: Option<usize>,
+        config: &MemoryConfig,
+    ) -> Result<Memory, Error> {
+        let mut reserve = config.reserve.max(min_size);
+        if let Some(max_size) = max_size {
+            reserve = reserve.min(max_size).max(min_size);
         }
+        let storage = backend.allocate(reserve, max_size)?;
+        // Contents the storage was loaded with mustn't be zeroed again
+        let loaded = storage.loaded_len().min(storage.len());
+        if config.zeroing == Zeroing::Eager {
+            unsafe { ptr::write_bytes(storage.ptr().add(loaded), 0, storage.len() - loaded) };
+        }
+        Ok(Memory {
+            len: AtomicUsize::new(min_size.max(loaded).min(storage.len())),
+            max_size,
+            storage,
+        })
+    }
+
+    /// Creates a memory of `min_size` bytes kept in `storage`, which the caller has already
+    /// allocated. The memory is no larger than the storage to begin with.
+    pub fn with_storage(
+        storage: Box<dyn LinearMemory>,
+        min_size: usize,
+        max_size: Option<usize>,
+    ) -> Memory {
+        Memory {
+            len: AtomicUsize::new(min_size.min(storage.len())),
+            max_size,
+            storage,
+        }
+    }
+
+    /// Allocates a copy of this memory from `backend`. See [`MemoryBackend::fork`].
+    pub fn fork(&self, backend: &dyn MemoryBackend) -> Result<Memory, Error> {
+        let storage = backend.fork(self.storage(), self.max_size)?;
+        Ok(Memory {
+            len: AtomicUsize::new(self.len().min(storage.len())),
+            max_size: self.max_size,
+            storage,
+        })
     }
 
     pub fn ptr(&self) -> *mut u8 {
-        self.0
+        self.storage.ptr()
     }
 
     pub fn len(&self) -> usize {
-        self.1
+        self.len.load(Ordering::Acquire)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Gets the number of bytes the memory's storage can grow to. See
+    /// [`LinearMemory::capacity`].
+    pub fn capacity(&self) -> usize {
+        self.storage.capacity()
+    }
+
+    /// Grows the memory by `additional` bytes, producing its previous size, or `None` if that
+    /// would take it beyond its capacity or maximum size.
+    pub fn grow(&self, additional: usize) -> Option<usize> {
+        let limit = match self.max_size {
+            Some(max_size) => max_size.min(self.capacity()),
+            None => self.capacity(),
+        };
+        let mut len = self.len();
+        loop {
+            let new_len = len.checked_add(additional).filter(|n| *n <= limit)?;
+            // The storage is committed before the new size is published, so accesses within it
+            // never fault. Committing more than another thread publishes is harmless.
+            if !self.storage.commit(new_len) {
+                return None;
+            }
+            match self
+                .len
+                .compare_exchange(len, new_len, Ordering::AcqRel, Ordering::Acquire)
+            {
+                Ok(_) => return Some(len),
+                Err(actual) => len = actual,
+            }
+        }
     }
 
     pub fn max_size(&self) -> Option<usize> {
-        self.2
+        self.max_size
+    }
+
+    /// Gets the storage this memory was allocated from.
+    pub fn storage(&self) -> &dyn LinearMemory {
+        self.storage.as_ref()
+    }
+
+    /// Writes the contents of the memory to its storage. See [`LinearMemory::persist`].
+    pub fn persist(&self) -> Result<(), Error> {
+        self.storage.persist(self.len())
+    }
+
+    /// Copies `buf.len()` bytes starting at `offset` into `buf`, producing `false` if they
+    /// aren't all in the memory.
+    ///
+    /// The bytes are copied through a raw pointer, without borrowing the contents, so this may
+    /// be used while other threads access the memory, as they do shared memories. What the
+    /// copy holds is then unspecified wherever they write, as WebAssembly allows.
+    pub fn read(&self, offset: usize, buf: &mut [u8]) -> bool {
+        match offset.checked_add(buf.len()) {
+            Some(end) if end <= self.len() => {
+                unsafe {
+                    ptr::copy_nonoverlapping(self.ptr().add(offset), buf.as_mut_ptr(), buf.len())
+                };
+                true
+            }
+            _ => false,
+        }
     }
 
+    /// Copies `bytes` into the memory starting at `offset`, producing `false` if they wouldn't
+    /// all be in it. Like [`Memory::read`], this doesn't borrow the contents.
+    pub fn write(&self, offset: usize, bytes: &[u8]) -> bool {
+        match offset.checked_add(bytes.len()) {
+            Some(end) if end <= self.len() => {
+                unsafe {
+                    ptr::copy_nonoverlapping(bytes.as_ptr(), self.ptr().add(offset), bytes.len())
+                };
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Gets the contents of the memory as a mutable slice.
+    ///
+    /// # Safety
+    /// The caller must ensure nothing else accesses the memory contents while the returned
+    /// slice is in use, including other threads running code over a shared memory, and that
+    /// the memory doesn't grow meanwhile. [`Memory::read`] and [`Memory::write`] don't have
+    /// these requirements.
+    #[allow(clippy::mut_from_ref)]
     pub unsafe fn data(&self) -> &mut [u8] {
-        slice::from_raw_parts_mut(self.0, self.1)
+        slice::from_raw_parts_mut(self.ptr(), self.len())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs, process};
+
+    use super::{FileBackend, Memory, MemoryConfig, Zeroing};
+    use crate::{
+        builder::{FuncBuilder, ModuleBuilder},
+        hosting::Host,
+        module::{MemoryType, Module},
+        Error, Instruction, ValType, Value, PAGE_SIZE,
+    };
+
+    fn growable(min: usize) -> Module {
+        ModuleBuilder::new()
+            .mem("memory", MemoryType::new(min, None))
+            .func(
+                FuncBuilder::new()
+                    .export_as("grow")
+                    .param(ValType::I32)
+                    .result(ValType::I32)
+                    .body(vec![Instruction::LocalGet(0), Instruction::MemoryGrow(0)]),
+            )
+            .build()
+    }
+
+    #[test]
+    pub fn file_backed_memories_survive_reallocation() {
+        let dir = env::temp_dir().join(format!("warthog-file-backend-{}", process::id()));
+        let allocate = || Memory::with_backend(&FileBackend::new(&dir).unwrap(), 16, None);
+
+        let mem = allocate().unwrap();
+        unsafe { mem.data()[..4].copy_from_slice(b"wasm") };
+        mem.persist().unwrap();
+        drop(mem);
+
+        let mem = allocate().unwrap();
+        assert_eq!(b"wasm\0\0", unsafe { &mem.data()[..6] });
+        assert_eq!(16, fs::metadata(dir.join("memory0.bin")).unwrap().len());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    pub fn file_backed_memories_reopen_at_the_size_they_grew_to() {
+        let dir = env::temp_dir().join(format!("warthog-file-growth-{}", process::id()));
+        let open = |module: Module| {
+            let mut host = Host::new();
+            host.set_memory_backend(FileBackend::new(&dir).unwrap());
+            let addr = host.instantiate("test", module)?;
+            let mem = host.get_module(addr).unwrap().mems()[0];
+            Ok::<_, Error>((host.get_mem(mem).unwrap(), host, addr))
+        };
+
+        let (mem, mut host, addr) = open(growable(1)).unwrap();
+        let grown = host.invoke(addr, "grow", &[Value::I32(2)]).unwrap();
+        assert_eq!(vec![Value::I32(1)], grown);
+        mem.write(2 * PAGE_SIZE + 4, &42u32.to_le_bytes()).unwrap();
+        mem.persist().unwrap();
+        drop((mem, host));
+
+        let (mem, _, _) = open(growable(1)).unwrap();
+        assert_eq!(3 * PAGE_SIZE, mem.memory().len());
+        assert_eq!(42, mem.read_u32(2 * PAGE_SIZE + 4).unwrap());
+        drop(mem);
+        let file = dir.join("memory0.bin");
+        assert_eq!(3 * PAGE_SIZE as u64, fs::metadata(&file).unwrap().len());
+
+        // A file that has outgrown the memory's maximum, or isn't whole pages, is refused
+        let bounded = ModuleBuilder::new()
+            .mem("memory", MemoryType::new(1, Some(2)))
+            .build();
+        assert!(matches!(open(bounded), Err(Error::InvalidState { .. })));
+        fs::OpenOptions::new()
+            .append(true)
+            .open(&file)
+            .unwrap()
+            .set_len(3 * PAGE_SIZE as u64 + 1)
+            .unwrap();
+        assert!(matches!(open(growable(1)), Err(Error::InvalidState { .. })));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    pub fn memories_grow_within_their_reservation() {
+        let mut host = Host::new();
+        host.set_memory_config(MemoryConfig {
+            reserve: 3 * PAGE_SIZE,
+            max_pages: Some(2),
+            zeroing: Zeroing::Eager,
+        });
+        assert!(matches!(
+            host.instantiate("large", growable(3)),
+            Err(Error::ResourceLimitExceeded { .. })
+        ));
+
+        // The reservation is cut down to the maximum the configuration allows
+        let addr = host.instantiate("small", growable(1)).unwrap();
+        let mem = host
+            .get_mem(host.get_module(addr).unwrap().mems()[0])
+            .unwrap();
+        assert_eq!(2 * PAGE_SIZE, mem.memory().capacity());
+        let mut grow = |pages| host.invoke(addr, "grow", &[Value::I32(pages)]).unwrap();
+        assert_eq!(vec![Value::I32(1)], grow(1));
+        assert_eq!(vec![Value::I32(u32::MAX)], grow(1));
+        assert_eq!(vec![Value::I32(2)], grow(0));
+        assert_eq!(2 * PAGE_SIZE, mem.memory().len());
+        assert_eq!(0, unsafe { mem.memory().data()[2 * PAGE_SIZE - 1] });
     }
 }
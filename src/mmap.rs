@@ -0,0 +1,433 @@
+//! A memory backend that reserves address space for memories to grow into, so they never move.
+
+use crate::{
+    memory::{self, HeapBackend, LinearMemory, MemoryBackend},
+    Error,
+};
+
+/// A [`MemoryBackend`] that reserves the whole range a memory can address with `mmap`, and only
+/// commits the pages a memory uses.
+///
+/// A memory without a maximum size gets 4 GiB of address space, everything a 32-bit index can
+/// reach. Memories therefore grow in place without copying, unlike with the [`HeapBackend`].
+///
+/// The backend is only for growing in place and forking cheaply. It doesn't speed up loads and
+/// stores: eliding bounds checks by reserving guard pages and turning the faults they raise
+/// into traps is out of scope, since the interpreter has no way to recover from a fault. Every
+/// access is bounds checked, whatever the backend, so nothing is reserved beyond the memory
+/// itself.
+///
+/// On Linux, a memory's pages are kept in an anonymous file, so a fork of it can map the same
+/// pages copy-on-write: forking costs next to nothing, and only the pages either copy writes
+/// to are duplicated. See [`Host::fork_instance`](crate::hosting::Host::fork_instance).
+/// Elsewhere forks are copied eagerly.
+///
+/// Reserving address space doesn't use physical memory, but a process may only reserve so
+/// much of it. When a reservation fails, or on platforms without `mmap`, memories are
+/// allocated from the [`HeapBackend`] instead.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct MmapBackend;
+
+/// The address space reserved for memories without a maximum size.
+#[cfg(all(unix, target_pointer_width = "64"))]
+const RESERVATION: usize = 1 << 32;
+
+impl MemoryBackend for MmapBackend {
+    fn allocate(
+        &self,
+        min_size: usize,
+        max_size: Option<usize>,
+    ) -> Result<Box<dyn LinearMemory>, Error> {
+        #[cfg(all(unix, target_pointer_width = "64"))]
+        {
+            let capacity = max_size.unwrap_or(RESERVATION).max(min_size);
+            if let Some(memory) = unix::MmapMemory::reserve(capacity) {
+                if memory.commit(min_size) {
+                    return Ok(Box::new(memory));
+                }
+            }
+        }
+        HeapBackend.allocate(min_size, max_size)
+    }
+
+    fn fork(
+        &self,
+        parent: &dyn LinearMemory,
+        max_size: Option<usize>,
+    ) -> Result<Box<dyn LinearMemory>, Error> {
+        match parent.fork() {
+            Some(memory) => Ok(memory),
+            None => memory::copy_of(self, parent, max_size),
+        }
+    }
+}
+
+#[cfg(all(unix, target_pointer_width = "64"))]
+mod unix {
+    use std::{
+        convert::TryInto,
+        fs::File,
+        io::{Read, Seek, SeekFrom},
+        os::unix::{fs::FileExt, io::AsRawFd},
+        ptr, slice,
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Mutex,
+        },
+    };
+
+    use crate::memory::LinearMemory;
+
+    /// A mapping whose first `committed` bytes are readable and writable, which can grow to
+    /// `capacity` bytes. The rest of the mapping is inaccessible until then.
+    pub struct MmapMemory {
+        ptr: *mut u8,
+        mapped: usize,
+        capacity: usize,
+        committed: AtomicUsize,
+        // Serializes changes to the mapping
+        backing: Mutex<Backing>,
+    }
+
+    /// Where the committed pages of a mapping come from.
+    enum Backing {
+        /// Anonymous pages, which can't be shared with a fork.
+        Anonymous,
+        /// A file the pages are mapped from, shared, so writes go to the file.
+        Shared(File),
+        /// A snapshot of the memory in a file that no longer changes, with its length in
+        /// pages. Those pages are mapped privately, so writes copy them, and the rest of the
+        /// committed pages are anonymous. Forks map the same snapshot.
+        Private(File, usize),
+    }
+
+    // The mapping is only accessed through the pointer, like a Box<[u8]>
+    unsafe impl Send for MmapMemory {}
+    unsafe impl Sync for MmapMemory {}
+
+    impl MmapMemory {
+        /// Reserves `capacity` bytes, none of which are accessible yet.
+        pub fn reserve(capacity: usize) -> Option<MmapMemory> {
+            let mapped = round_to_page(capacity)?;
+            let ptr = unsafe {
+                libc::mmap(
+                    ptr::null_mut(),
+                    mapped,
+                    libc::PROT_NONE,
+                    libc::MAP_PRIVATE | libc::MAP_ANON | libc::MAP_NORESERVE,
+                    -1,
+                    0,
+                )
+            };
+            if ptr == libc::MAP_FAILED {
+                return None;
+            }
+            let backing = match anonymous_file() {
+                Some(file) => Backing::Shared(file),
+                None => Backing::Anonymous,
+            };
+            Some(MmapMemory {
+                ptr: ptr as *mut u8,
+                mapped,
+                capacity,
+                committed: AtomicUsize::new(0),
+                backing: Mutex::new(backing),
+            })
+        }
+
+        /// Maps `len` bytes of `file` over the start of the reservation, privately if
+        /// `private` is set.
+        fn map(&self, file: &File, len: usize, private: bool) -> bool {
+            let sharing = if private {
+                libc::MAP_PRIVATE
+            } else {
+                libc::MAP_SHARED
+            };
+            let ptr = unsafe {
+                libc::mmap(
+                    self.ptr as *mut libc::c_void,
+                    len,
+                    libc::PROT_READ | libc::PROT_WRITE,
+                    sharing | libc::MAP_FIXED,
+                    file.as_raw_fd(),
+                    0,
+                )
+            };
+            ptr != libc::MAP_FAILED
+        }
+
+        /// Makes the anonymous pages of the reservation from `start` to `end` accessible.
+        /// They're zeroed when they're first touched.
+        fn protect(&self, start: usize, end: usize) -> bool {
+            start >= end
+                || unsafe {
+                    libc::mprotect(
+                        self.ptr.add(start) as *mut libc::c_void,
+                        end - start,
+                        libc::PROT_READ | libc::PROT_WRITE,
+                    ) == 0
+                }
+        }
+    }
+
+    unsafe impl LinearMemory for MmapMemory {
+        fn ptr(&self) -> *mut u8 {
+            self.ptr
+        }
+
+        fn len(&self) -> usize {
+            self.committed.load(Ordering::Acquire)
+        }
+
+        fn capacity(&self) -> usize {
+            self.capacity
+        }
+
+        fn commit(&self, len: usize) -> bool {
+            if len <= self.len() {
+                return true;
+            }
+            let pages = match round_to_page(len) {
+                Some(pages) if len <= self.capacity => pages,
+                _ => return false,
+            };
+            let backing = self.backing.lock().unwrap();
+            let mapped = match &*backing {
+                // Growing the file zeroes the new pages
+                Backing::Shared(file) => {
+                    file.set_len(pages as u64).is_ok() && self.map(file, pages, false)
+                }
+                _ => self.protect(round_to_page(self.len()).unwrap(), pages),
+            };
+            if mapped {
+                self.committed.fetch_max(len, Ordering::AcqRel);
+            }
+            mapped
+        }
+
+        fn fork(&self) -> Option<Box<dyn LinearMemory>> {
+            let mut backing = self.backing.lock().unwrap();
+            let len = self.len();
+            let pages = round_to_page(len)?;
+
+            // Both copies map a file that doesn't change any more privately. When the pages
+            // are written to a shared file, the parent stops writing to it, which doesn't lose
+            // any writes other threads make meanwhile as they land in the file or the private
+            // mapping. Otherwise the file the parent maps is reused while the parent hasn't
+            // written to it, and its pages are copied to a new file once it has.
+            let snapshot = match &*backing {
+                Backing::Anonymous => return None,
+                Backing::Shared(file) => {
+                    let file = file.try_clone().ok()?;
+                    if !self.map(&file, pages, true) {
+                        return None;
+                    }
+                    *backing = Backing::Private(file.try_clone().ok()?, pages);
+                    file
+                }
+                Backing::Private(file, snapshot) if *snapshot == pages && !self.written(pages) => {
+                    file.try_clone().ok()?
+                }
+                Backing::Private(..) => {
+                    let file = anonymous_file()?;
+                    let contents = unsafe { slice::from_raw_parts(self.ptr, len) };
+                    file.write_all_at(contents, 0).ok()?;
+                    file.set_len(pages as u64).ok()?;
+                    file
+                }
+            };
+
+            let fork = MmapMemory::reserve(self.capacity)?;
+            if !fork.map(&snapshot, pages, true) {
+                return None;
+            }
+            fork.committed.store(len, Ordering::Release);
+            *fork.backing.lock().unwrap() = Backing::Private(snapshot, pages);
+            Some(Box::new(fork))
+        }
+    }
+
+    impl MmapMemory {
+        /// Gets a boolean indicating if any of the first `pages` bytes of the mapping have been
+        /// written since they were mapped privately, or might have been.
+        fn written(&self, pages: usize) -> bool {
+            match page_flags(self.ptr, pages) {
+                // Pages that have been copied on write aren't file pages any more
+                Some(flags) => flags
+                    .iter()
+                    .any(|f| f & (PAGE_PRESENT | PAGE_SWAPPED) != 0 && f & PAGE_FILE == 0),
+                None => true,
+            }
+        }
+    }
+
+    impl Drop for MmapMemory {
+        fn drop(&mut self) {
+            unsafe { libc::munmap(self.ptr as *mut libc::c_void, self.mapped) };
+        }
+    }
+
+    pub(super) const PAGE_PRESENT: u64 = 1 << 63;
+    pub(super) const PAGE_SWAPPED: u64 = 1 << 62;
+    pub(super) const PAGE_FILE: u64 = 1 << 61;
+
+    /// Reads the flags the kernel reports for each page in the `len` bytes at `ptr`, see
+    /// `/proc/self/pagemap`. Produces `None` where they aren't available.
+    pub(super) fn page_flags(ptr: *const u8, len: usize) -> Option<Vec<u64>> {
+        let page_size = page_size();
+        let mut entries = vec![0u8; len / page_size * 8];
+        let mut pagemap = File::open("/proc/self/pagemap").ok()?;
+        pagemap
+            .seek(SeekFrom::Start((ptr as usize / page_size * 8) as u64))
+            .ok()?;
+        pagemap.read_exact(&mut entries).ok()?;
+        Some(
+            entries
+                .chunks(8)
+                .map(|e| u64::from_le_bytes(e.try_into().unwrap()))
+                .collect(),
+        )
+    }
+
+    /// Creates a file that only lives in memory, to keep the pages of a mapping in.
+    #[cfg(target_os = "linux")]
+    fn anonymous_file() -> Option<File> {
+        use std::os::unix::io::FromRawFd;
+
+        let fd = unsafe {
+            libc::memfd_create(
+                b"warthog-memory\0".as_ptr() as *const libc::c_char,
+                libc::MFD_CLOEXEC,
+            )
+        };
+        if fd < 0 {
+            return None;
+        }
+        Some(unsafe { File::from_raw_fd(fd) })
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    fn anonymous_file() -> Option<File> {
+        None
+    }
+
+    fn page_size() -> usize {
+        unsafe { libc::sysconf(libc::_SC_PAGESIZE) as usize }
+    }
+
+    fn round_to_page(len: usize) -> Option<usize> {
+        let page_size = page_size();
+        Some(len.checked_add(page_size - 1)? / page_size * page_size)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::MmapBackend;
+    use crate::{
+        builder::{FuncBuilder, ModuleBuilder},
+        hosting::Host,
+        module::MemoryType,
+        Error, TrapCause, ValType, Value, PAGE_SIZE,
+    };
+
+    #[test]
+    pub fn mapped_memories_grow_in_place() {
+        use crate::Instruction::*;
+
+        let module = ModuleBuilder::new()
+            .mem("memory", MemoryType::new(1, None))
+            .func(
+                FuncBuilder::new()
+                    .export_as("grow")
+                    .param(ValType::I32)
+                    .result(ValType::I32)
+                    .body(vec![LocalGet(0), MemoryGrow(0)]),
+            )
+            .func(
+                FuncBuilder::new()
+                    .export_as("poke")
+                    .param(ValType::I32)
+                    .result(ValType::I32)
+                    .body(vec![
+                        LocalGet(0),
+                        I32Const(Value::I32(42)),
+                        I32Store8(0, 0),
+                        LocalGet(0),
+                        I32Load8U(0, 0),
+                    ]),
+            );
+        let mut host = Host::new();
+        host.set_memory_backend(MmapBackend);
+        let addr = host.instantiate("test", module.build()).unwrap();
+        let mem = host
+            .get_mem(host.get_module(addr).unwrap().mems()[0])
+            .unwrap();
+        let ptr = mem.memory().ptr();
+
+        let last = Value::I32((16 * PAGE_SIZE - 1) as u32);
+        match host.invoke(addr, "poke", &[last]) {
+            Err(Error::Trap(trap)) => {
+                assert!(matches!(trap.cause(), TrapCause::OutOfBoundsMemoryAccess))
+            }
+            res => panic!("expected a trap, got {:?}", res),
+        }
+        let res = host.invoke(addr, "grow", &[Value::I32(15)]).unwrap();
+        assert_eq!(vec![Value::I32(1)], res);
+        assert_eq!(
+            vec![Value::I32(42)],
+            host.invoke(addr, "poke", &[last]).unwrap()
+        );
+        assert_eq!(ptr, mem.memory().ptr());
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    pub fn forks_share_pages_until_written() {
+        use super::unix::{page_flags, PAGE_FILE, PAGE_PRESENT};
+        use crate::memory::MemoryBackend;
+
+        let len = 4 * PAGE_SIZE;
+        let page = |ptr: *mut u8, i: usize| unsafe { ptr.add(i * PAGE_SIZE) };
+        let shared = |ptr: *mut u8, i: usize| {
+            let flags = page_flags(page(ptr, i), PAGE_SIZE).unwrap()[0];
+            flags & PAGE_PRESENT != 0 && flags & PAGE_FILE != 0
+        };
+
+        let parent = MmapBackend.allocate(len, None).unwrap();
+        for i in 0..4 {
+            unsafe { *page(parent.ptr(), i) = i as u8 + 1 };
+        }
+        let fork = MmapBackend.fork(&*parent, None).unwrap();
+        assert_eq!(len, fork.len());
+
+        // Reading doesn't copy the pages
+        for i in 0..4 {
+            assert_eq!(i as u8 + 1, unsafe { *page(fork.ptr(), i) });
+            assert!(shared(fork.ptr(), i));
+        }
+
+        // Writing copies the page written to, and only that page
+        unsafe { *page(fork.ptr(), 1) = 42 };
+        assert!(!shared(fork.ptr(), 1));
+        assert!(shared(fork.ptr(), 2));
+        assert_eq!(2, unsafe { *page(parent.ptr(), 1) });
+
+        unsafe { *page(parent.ptr(), 2) = 43 };
+        assert!(!shared(parent.ptr(), 2));
+        assert_eq!(3, unsafe { *page(fork.ptr(), 2) });
+
+        // A later fork sees what the parent has written since
+        let later = MmapBackend.fork(&*parent, None).unwrap();
+        assert_eq!(43, unsafe { *page(later.ptr(), 2) });
+        assert_eq!(2, unsafe { *page(later.ptr(), 1) });
+        assert_eq!(4, unsafe { *page(later.ptr(), 3) });
+        assert!(shared(later.ptr(), 3));
+
+        // Forks grow like any other memory
+        assert!(later.commit(2 * len));
+        assert_eq!(0, unsafe { *page(later.ptr(), 4) });
+        unsafe { *page(later.ptr(), 5) = 44 };
+        assert_eq!(3, unsafe { *page(fork.ptr(), 2) });
+    }
+}
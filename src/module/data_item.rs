@@ -12,6 +12,10 @@ pub struct DataItem {
 }
 
 impl DataItem {
+    pub fn new(index: usize, expr: Expr, init: Vec<u8>) -> DataItem {
+        DataItem { index, expr, init }
+    }
+
     pub fn read<R: io::Read>(reader: &mut R) -> Result<DataItem, Error> {
         let index = utils::read_leb128_u32(reader)? as usize;
         let expr = Expr::new(Instruction::read_sequence(reader)?);
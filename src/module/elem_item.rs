@@ -0,0 +1,92 @@
+use std::{fmt, io};
+
+use crate::{module::Expr, utils, Error, Instruction, SectionOffset};
+
+/// An element segment, used to initialize a range of a table with function references.
+#[derive(PartialEq, Clone)]
+pub struct ElemItem {
+    index: usize,
+    expr: Option<Expr>,
+    init: Vec<usize>,
+}
+
+impl ElemItem {
+    /// Creates an active element segment, which initializes table `index` starting at the offset
+    /// produced by `expr` when the module is instantiated.
+    pub fn new(index: usize, expr: Expr, init: Vec<usize>) -> ElemItem {
+        ElemItem {
+            index,
+            expr: Some(expr),
+            init,
+        }
+    }
+
+    pub fn read<R: io::Read>(reader: &mut R) -> Result<ElemItem, Error> {
+        // Only the segment forms that list function indices are supported
+        let flags = utils::read_leb128_u32(reader)?;
+        let (index, expr) = match flags {
+            0x00 => (0, Some(read_offset(reader)?)),
+            0x02 => (
+                utils::read_leb128_u32(reader)? as usize,
+                Some(read_offset(reader)?),
+            ),
+            // Passive and declarative segments aren't used to initialize tables
+            0x01 | 0x03 => (0, None),
+            _ => return Err(unsupported(flags)),
+        };
+
+        // All forms other than 0x00 carry an element kind, which must be 'funcref'
+        if flags != 0x00 && utils::read_leb128_u32(reader)? != 0x00 {
+            return Err(unsupported(flags));
+        }
+
+        let init = utils::read_vec(reader, |r| Ok(utils::read_leb128_u32(r)? as usize))?;
+        Ok(ElemItem { index, expr, init })
+    }
+
+    pub fn index(&self) -> usize {
+        self.index
+    }
+
+    /// Gets the offset expression, if this is an active segment.
+    pub fn expr(&self) -> Option<&Expr> {
+        self.expr.as_ref()
+    }
+
+    pub fn init(&self) -> &[usize] {
+        &self.init
+    }
+}
+
+fn unsupported(flags: u32) -> Error {
+    Error::UnsupportedElemSegment {
+        flags,
+        at: SectionOffset::unknown(),
+    }
+}
+
+fn read_offset<R: io::Read>(reader: &mut R) -> Result<Expr, Error> {
+    Ok(Expr::new(Instruction::read_sequence(reader)?))
+}
+
+impl fmt::Display for ElemItem {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "(elem")?;
+        if self.index > 0 {
+            write!(f, " {}", self.index)?;
+        }
+        if let Some(ref expr) = self.expr {
+            write!(f, " (offset {})", expr)?;
+        }
+        for func in self.init.iter() {
+            write!(f, " {}", func)?;
+        }
+        write!(f, ")")
+    }
+}
+
+impl fmt::Debug for ElemItem {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(self, f)
+    }
+}
@@ -1,26 +1,31 @@
 use std::{fmt, io};
 
-use crate::{
-    module::{MemberDesc, MemoryType},
-    utils, Error,
-};
+use crate::{module::ExportDesc, utils, Error};
 
 #[derive(PartialEq, Clone)]
 pub struct Export {
     name: String,
-    description: MemberDesc,
+    description: ExportDesc,
 }
 
 impl Export {
     pub fn func<S: Into<String>>(name: S, idx: usize) -> Export {
-        Export::new(name, MemberDesc::Function(idx))
+        Export::new(name, ExportDesc::Function(idx))
     }
 
-    pub fn mem<S: Into<String>>(name: S, typ: MemoryType) -> Export {
-        Export::new(name, MemberDesc::Memory(typ))
+    pub fn table<S: Into<String>>(name: S, idx: usize) -> Export {
+        Export::new(name, ExportDesc::Table(idx))
     }
 
-    pub fn new<S: Into<String>>(name: S, description: MemberDesc) -> Export {
+    pub fn mem<S: Into<String>>(name: S, idx: usize) -> Export {
+        Export::new(name, ExportDesc::Memory(idx))
+    }
+
+    pub fn global<S: Into<String>>(name: S, idx: usize) -> Export {
+        Export::new(name, ExportDesc::Global(idx))
+    }
+
+    pub fn new<S: Into<String>>(name: S, description: ExportDesc) -> Export {
         Export {
             name: name.into(),
             description,
@@ -29,7 +34,7 @@ impl Export {
 
     pub fn read<R: io::Read>(reader: &mut R) -> Result<Export, Error> {
         let name = utils::read_name(reader)?;
-        let description = MemberDesc::read(reader)?;
+        let description = ExportDesc::read(reader)?;
         Ok(Export { name, description })
     }
 
@@ -37,7 +42,7 @@ impl Export {
         &self.name
     }
 
-    pub fn description(&self) -> &MemberDesc {
+    pub fn description(&self) -> &ExportDesc {
         &self.description
     }
 }
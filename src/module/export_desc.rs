@@ -0,0 +1,48 @@
+use std::{fmt, io};
+
+use byteorder::ReadBytesExt;
+
+use crate::{utils, Error, SectionOffset};
+
+/// Describes the member referenced by an export, by its index in the relevant index space.
+#[derive(PartialEq, Clone, Copy)]
+pub enum ExportDesc {
+    Function(usize),
+    Table(usize),
+    Memory(usize),
+    Global(usize),
+}
+
+impl ExportDesc {
+    pub fn read<R: io::Read>(reader: &mut R) -> Result<ExportDesc, Error> {
+        let code = reader.read_u8()?;
+        let idx = utils::read_leb128_u32(reader)? as usize;
+        match code {
+            0x00 => Ok(ExportDesc::Function(idx)),
+            0x01 => Ok(ExportDesc::Table(idx)),
+            0x02 => Ok(ExportDesc::Memory(idx)),
+            0x03 => Ok(ExportDesc::Global(idx)),
+            kind => Err(Error::InvalidExternKind {
+                kind,
+                at: SectionOffset::unknown(),
+            }),
+        }
+    }
+}
+
+impl fmt::Display for ExportDesc {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ExportDesc::Function(x) => write!(f, "(func {})", x),
+            ExportDesc::Table(x) => write!(f, "(table {})", x),
+            ExportDesc::Memory(x) => write!(f, "(memory {})", x),
+            ExportDesc::Global(x) => write!(f, "(global {})", x),
+        }
+    }
+}
+
+impl fmt::Debug for ExportDesc {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(self, f)
+    }
+}
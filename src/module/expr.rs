@@ -18,6 +18,10 @@ impl Expr {
         self.0.len()
     }
 
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
     pub fn iter(&self) -> impl Iterator<Item = &Instruction> {
         self.0.iter()
     }
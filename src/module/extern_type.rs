@@ -0,0 +1,225 @@
+use std::fmt;
+
+use crate::module::{FuncType, GlobalType, MemoryType, TableType};
+
+/// The type of an item a module imports or exports, with its index resolved against the
+/// module. See [`Module::import_types`](crate::module::Module::import_types).
+#[derive(PartialEq, Clone)]
+pub enum ExternType {
+    Func(FuncType),
+    Table(TableType),
+    Memory(MemoryType),
+    Global(GlobalType),
+}
+
+impl ExternType {
+    pub fn func(&self) -> Option<&FuncType> {
+        match self {
+            ExternType::Func(typ) => Some(typ),
+            _ => None,
+        }
+    }
+
+    pub fn table(&self) -> Option<&TableType> {
+        match self {
+            ExternType::Table(typ) => Some(typ),
+            _ => None,
+        }
+    }
+
+    pub fn memory(&self) -> Option<&MemoryType> {
+        match self {
+            ExternType::Memory(typ) => Some(typ),
+            _ => None,
+        }
+    }
+
+    pub fn global(&self) -> Option<&GlobalType> {
+        match self {
+            ExternType::Global(typ) => Some(typ),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for ExternType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ExternType::Func(x) if x.params().is_empty() && x.results().is_empty() => {
+                write!(f, "(func)")
+            }
+            ExternType::Func(x) => write!(f, "(func {})", x),
+            ExternType::Table(x) => write!(f, "{}", x),
+            ExternType::Memory(x) => write!(f, "{}", x),
+            ExternType::Global(x) => write!(f, "{}", x),
+        }
+    }
+}
+
+impl fmt::Debug for ExternType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(self, f)
+    }
+}
+
+/// An item a module needs from another to be instantiated, and its type.
+#[derive(PartialEq, Clone)]
+pub struct ImportType {
+    module: String,
+    name: String,
+    typ: ExternType,
+}
+
+impl ImportType {
+    pub fn new<S: Into<String>, T: Into<String>>(
+        module: S,
+        name: T,
+        typ: ExternType,
+    ) -> ImportType {
+        ImportType {
+            module: module.into(),
+            name: name.into(),
+            typ,
+        }
+    }
+
+    pub fn module(&self) -> &str {
+        &self.module
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn typ(&self) -> &ExternType {
+        &self.typ
+    }
+}
+
+impl fmt::Display for ImportType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "(import \"{}\" \"{}\" {})",
+            self.module, self.name, self.typ
+        )
+    }
+}
+
+impl fmt::Debug for ImportType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(self, f)
+    }
+}
+
+/// An item a module offers to others, and its type.
+#[derive(PartialEq, Clone)]
+pub struct ExportType {
+    name: String,
+    typ: ExternType,
+}
+
+impl ExportType {
+    pub fn new<S: Into<String>>(name: S, typ: ExternType) -> ExportType {
+        ExportType {
+            name: name.into(),
+            typ,
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn typ(&self) -> &ExternType {
+        &self.typ
+    }
+}
+
+impl fmt::Display for ExportType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "(export \"{}\" {})", self.name, self.typ)
+    }
+}
+
+impl fmt::Debug for ExportType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(self, f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        builder::{FuncBuilder, ModuleBuilder},
+        module::{
+            Export, ExportDesc, Expr, ExternType, FuncType, Global, GlobalType, Import, MemberDesc,
+            MemoryType, TableType,
+        },
+        Error,
+        Instruction::*,
+        ValType, Value,
+    };
+
+    #[test]
+    pub fn imports_and_exports_are_typed_without_instantiating() {
+        let mut module = ModuleBuilder::new()
+            .func(
+                FuncBuilder::new()
+                    .import_from("env", "log")
+                    .param(ValType::I32),
+            )
+            .func(
+                FuncBuilder::new()
+                    .export_as("main")
+                    .result(ValType::I64)
+                    .body(vec![I64Const(Value::I64(0))]),
+            )
+            .mem("memory", MemoryType::new(1, Some(2)))
+            .global(
+                "counter",
+                Global::new(
+                    GlobalType::new(ValType::I32, true),
+                    Expr::new(vec![I32Const(Value::I32(0))]),
+                ),
+            );
+        let table = MemberDesc::Table(TableType::new(4, None));
+        module.imports.push(Import::new("env", "table", table));
+        let module = module.build();
+
+        let imports = module.import_types().unwrap();
+        assert_eq!(2, imports.len());
+        assert_eq!(("env", "log"), (imports[0].module(), imports[0].name()));
+        assert_eq!(
+            Some(&FuncType::new(vec![ValType::I32], vec![])),
+            imports[0].typ().func()
+        );
+        assert_eq!(
+            &ExternType::Table(TableType::new(4, None)),
+            imports[1].typ()
+        );
+
+        let exports = module.export_types().unwrap();
+        let names: Vec<_> = exports.iter().map(|e| e.name()).collect();
+        assert_eq!(vec!["main", "memory", "counter"], names);
+        assert_eq!(
+            Some(&FuncType::new(vec![], vec![ValType::I64])),
+            exports[0].typ().func()
+        );
+        assert_eq!(
+            Some(&MemoryType::new(1, Some(2))),
+            exports[1].typ().memory()
+        );
+        assert!(exports[2].typ().global().unwrap().mutable());
+
+        // Exports of items the module doesn't have are reported rather than skipped
+        let mut broken = ModuleBuilder::new();
+        broken
+            .exports
+            .push(Export::new("missing", ExportDesc::Global(0)));
+        assert!(matches!(
+            broken.build().export_types(),
+            Err(Error::UnknownGlobalIndex { index: 0, .. })
+        ));
+    }
+}
@@ -1,44 +1,126 @@
-use std::{fmt, io};
+use std::{
+    fmt, io,
+    ops::Range,
+    sync::{Arc, OnceLock},
+};
 
-use crate::{utils, Error, Instruction, ValType};
+use crate::{utils, Error, Instruction, SectionOffset, ValType};
 
-#[derive(PartialEq, Clone)]
+/// The most locals a function body may declare, which is the limit web embeddings use. The binary
+/// format allows almost 2^32, which a few bytes of a malicious module could use to exhaust memory.
+pub const MAX_LOCALS: usize = 50_000;
+
+#[derive(Clone)]
 pub struct FuncBody {
     locals: Vec<ValType>,
-    body: Vec<Instruction>,
+    body: OnceLock<Vec<Instruction>>,
+    /// Where the instructions of a body that was loaded lazily are, in the code section's
+    /// bytes, until they're decoded. See [`Module::load_lazy`](crate::module::Module::load_lazy).
+    encoded: Option<(Arc<[u8]>, Range<usize>)>,
 }
 
 impl FuncBody {
     pub fn new(locals: Vec<ValType>, body: Vec<Instruction>) -> FuncBody {
-        FuncBody { locals, body }
+        FuncBody {
+            locals,
+            body: OnceLock::from(body),
+            encoded: None,
+        }
     }
 
-    pub fn read<R: io::Read>(reader: &mut R) -> Result<FuncBody, Error> {
-        utils::read_leb128_u32(reader)?;
-
-        // Locals is a vec, but each item also indicates repeated locals, so we
-        // don't use read_vec because we want the expanded form
-        let size = utils::read_leb128_u32(reader)?;
-        let mut locals = Vec::new();
-        for _ in 0..size {
-            let count = utils::read_leb128_u32(reader)?;
-            let typ = ValType::read(reader)?;
-            for _ in 0..count {
-                locals.push(typ);
-            }
+    /// Creates a body whose instructions are decoded from `code[range]` when they're first
+    /// needed.
+    pub fn lazy(locals: Vec<ValType>, code: Arc<[u8]>, range: Range<usize>) -> FuncBody {
+        FuncBody {
+            locals,
+            body: OnceLock::new(),
+            encoded: Some((code, range)),
         }
+    }
 
-        let body = Instruction::read_sequence(reader)?;
+    /// Reads a body like [`FuncBody::read`], except for its instructions, which are left in
+    /// `code` to be decoded when they're first needed. Produces the body and the offset in
+    /// `code` after it.
+    pub fn read_lazy(code: &Arc<[u8]>, offset: usize) -> Result<(FuncBody, usize), Error> {
+        let mut reader = io::Cursor::new(code.get(offset..).unwrap_or_default());
+        let size = utils::read_leb128_u32(&mut reader)? as usize;
+        let start = offset + reader.position() as usize;
+        let end = start
+            .checked_add(size)
+            .filter(|end| *end <= code.len())
+            .ok_or_else(|| Error::from(io::Error::from(io::ErrorKind::UnexpectedEof)))?;
 
-        Ok(FuncBody { locals, body })
+        let mut reader = io::Cursor::new(&code[start..end]);
+        let locals = read_locals(&mut reader)?;
+        let body_start = start + reader.position() as usize;
+        Ok((FuncBody::lazy(locals, code.clone(), body_start..end), end))
+    }
+
+    pub fn read<R: io::Read>(reader: &mut R) -> Result<FuncBody, Error> {
+        utils::read_leb128_u32(reader)?;
+        let locals = read_locals(reader)?;
+        let body = Instruction::read_sequence(reader)?;
+        Ok(FuncBody::new(locals, body))
     }
 
     pub fn locals(&self) -> &[ValType] {
         &self.locals
     }
 
+    /// Gets the body's instructions, decoding them if the body was loaded lazily.
+    ///
+    /// # Panics
+    /// Panics if the body was loaded lazily and is malformed. Use [`FuncBody::try_body`] to
+    /// handle that.
     pub fn body(&self) -> &[Instruction] {
-        &self.body
+        self.try_body().expect("malformed function body")
+    }
+
+    /// Gets the body's instructions, decoding them if the body was loaded lazily. Fails if
+    /// they're malformed.
+    pub fn try_body(&self) -> Result<&[Instruction], Error> {
+        if let Some(body) = self.body.get() {
+            return Ok(body);
+        }
+        let (code, range) = self
+            .encoded
+            .as_ref()
+            .expect("bodies are decoded or encoded");
+        let body = Instruction::read_sequence(&mut io::Cursor::new(&code[range.clone()]))?;
+        Ok(self.body.get_or_init(|| body))
+    }
+
+    /// Checks if the body's instructions have been decoded, which they are unless the body was
+    /// loaded lazily and hasn't been needed yet.
+    pub fn is_decoded(&self) -> bool {
+        self.body.get().is_some()
+    }
+}
+
+/// Reads the locals of a function body. Each entry in the vector can declare several locals of
+/// the same type, so they're expanded rather than read with `read_vec`.
+fn read_locals<R: io::Read>(reader: &mut R) -> Result<Vec<ValType>, Error> {
+    let size = utils::read_leb128_u32(reader)?;
+    let mut locals = Vec::new();
+    let mut total = 0u64;
+    for _ in 0..size {
+        let count = utils::read_leb128_u32(reader)?;
+        total += count as u64;
+        if total > MAX_LOCALS as u64 {
+            return Err(Error::TooManyLocals {
+                count: total,
+                at: SectionOffset::unknown(),
+            });
+        }
+        let typ = ValType::read(reader)?;
+        locals.extend(std::iter::repeat_n(typ, count as usize));
+    }
+    Ok(locals)
+}
+
+impl PartialEq for FuncBody {
+    fn eq(&self, other: &FuncBody) -> bool {
+        self.locals == other.locals && self.try_body().ok() == other.try_body().ok()
     }
 }
 
@@ -53,7 +135,11 @@ impl fmt::Display for FuncBody {
             }
             write!(f, "{}", param)?;
         }
-        for inst in self.body.iter() {
+        let body = match self.try_body() {
+            Ok(body) => body,
+            Err(_) => return write!(f, " <malformed>"),
+        };
+        for inst in body.iter() {
             if start {
                 start = false;
             } else {
@@ -70,3 +156,41 @@ impl fmt::Debug for FuncBody {
         fmt::Display::fmt(self, f)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use crate::{hosting::Host, module::Module, reader::Reader, Error, TrapCause, Value};
+
+    /// A module exporting "good", which produces 42, and "bad", whose body has an unknown
+    /// opcode.
+    const WASM: &[u8] = &[
+        0x00, 0x61, 0x73, 0x6d, 0x01, 0x00, 0x00, 0x00, // header
+        0x01, 0x05, 0x01, 0x60, 0x00, 0x01, 0x7f, // type: [] -> [i32]
+        0x03, 0x03, 0x02, 0x00, 0x00, // function: two of type 0
+        0x07, 0x0e, 0x02, 0x04, b'g', b'o', b'o', b'd', 0x00, 0x00, // export: "good"
+        0x03, b'b', b'a', b'd', 0x00, 0x01, // and "bad"
+        0x0a, 0x0a, 0x02, 0x04, 0x00, 0x41, 0x2a, 0x0b, 0x03, 0x00, 0xff, 0x0b, // code
+    ];
+
+    #[test]
+    pub fn lazy_bodies_are_decoded_when_first_called() {
+        assert!(Module::load(Reader::new(Cursor::new(WASM))).is_err());
+
+        let module = Module::load_lazy(Reader::new(Cursor::new(WASM))).unwrap();
+        assert!(module.code().iter().all(|body| !body.is_decoded()));
+        assert!(module.code()[1].try_body().is_err());
+
+        let mut host = Host::new();
+        let addr = host.instantiate("lazy", module).unwrap();
+        assert_eq!(
+            vec![Value::I32(42)],
+            host.invoke(addr, "good", &[]).unwrap()
+        );
+        match host.invoke(addr, "bad", &[]) {
+            Err(Error::Trap(trap)) => assert!(*trap.cause() == TrapCause::InvalidCode),
+            other => panic!("expected a trap, got {:?}", other),
+        }
+    }
+}
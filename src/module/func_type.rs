@@ -2,7 +2,7 @@ use std::{fmt, io};
 
 use byteorder::ReadBytesExt;
 
-use crate::{utils, Error, ValType};
+use crate::{utils, Error, SectionOffset, ValType};
 
 #[derive(Clone, Eq, PartialEq)]
 pub struct FuncType {
@@ -25,14 +25,22 @@ impl FuncType {
     pub fn read<R: io::Read>(reader: &mut R) -> Result<FuncType, Error> {
         let type_code = reader.read_u8()?;
         if type_code != 0x60 {
-            Err(Error::InvalidModule)
+            Err(Error::InvalidTypeForm {
+                form: type_code,
+                at: SectionOffset::unknown(),
+            })
         } else {
-            let params = utils::read_vec(reader, |r| ValType::read(r))?;
-            let results = utils::read_vec(reader, |r| ValType::read(r))?;
-            Ok(FuncType { params, results })
+            FuncType::read_signature(reader)
         }
     }
 
+    /// Reads the parameter and result types that follow the `0x60` type code.
+    pub(crate) fn read_signature<R: io::Read>(reader: &mut R) -> Result<FuncType, Error> {
+        let params = utils::read_vec(reader, |r| ValType::read(r))?;
+        let results = utils::read_vec(reader, |r| ValType::read(r))?;
+        Ok(FuncType { params, results })
+    }
+
     pub fn params(&self) -> &[ValType] {
         &self.params
     }
@@ -45,7 +53,7 @@ impl FuncType {
 impl fmt::Display for FuncType {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         let mut start = true;
-        if self.params().len() > 0 {
+        if !self.params().is_empty() {
             write!(f, "(param")?;
             start = false;
             for param in self.params().iter() {
@@ -53,7 +61,7 @@ impl fmt::Display for FuncType {
             }
             write!(f, ")")?;
         }
-        if self.results.len() > 0 {
+        if !self.results.is_empty() {
             if start {
                 write!(f, "(result")?;
             } else {
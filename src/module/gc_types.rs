@@ -0,0 +1,163 @@
+use std::{
+    fmt,
+    io::{self, Read},
+};
+
+use byteorder::ReadBytesExt;
+
+use crate::{utils, Error, SectionOffset, ValType, Value};
+
+/// The type of a value stored in a struct field or array element.
+#[derive(Copy, Clone, PartialEq, Eq)]
+pub enum StorageType {
+    Val(ValType),
+    I8,
+    I16,
+}
+
+impl StorageType {
+    pub fn read<R: io::Read>(reader: &mut R) -> Result<StorageType, Error> {
+        let code = reader.read_u8()?;
+        match code {
+            0x78 => Ok(StorageType::I8),
+            0x77 => Ok(StorageType::I16),
+            _ => Ok(StorageType::Val(ValType::read(
+                &mut io::Cursor::new([code]).chain(reader),
+            )?)),
+        }
+    }
+
+    /// Gets the type of the operand used to read or write a value of this type.
+    pub fn unpacked(&self) -> ValType {
+        match self {
+            StorageType::Val(typ) => *typ,
+            StorageType::I8 | StorageType::I16 => ValType::I32,
+        }
+    }
+
+    /// Gets the value a field or element of this type holds when it isn't initialized.
+    pub fn default_value(&self) -> Value {
+        self.unpacked().default_value()
+    }
+
+    /// Wraps `value` to fit in a packed field, leaving unpacked values unchanged.
+    pub fn pack(&self, value: Value) -> Value {
+        match (self, value) {
+            (StorageType::I8, Value::I32(x)) => Value::I32(x & 0xFF),
+            (StorageType::I16, Value::I32(x)) => Value::I32(x & 0xFFFF),
+            (_, x) => x,
+        }
+    }
+}
+
+impl fmt::Display for StorageType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            StorageType::Val(typ) => write!(f, "{}", typ),
+            StorageType::I8 => write!(f, "i8"),
+            StorageType::I16 => write!(f, "i16"),
+        }
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Eq)]
+pub struct FieldType {
+    storage: StorageType,
+    mutable: bool,
+}
+
+impl FieldType {
+    pub fn new(storage: StorageType, mutable: bool) -> FieldType {
+        FieldType { storage, mutable }
+    }
+
+    pub fn read<R: io::Read>(reader: &mut R) -> Result<FieldType, Error> {
+        let storage = StorageType::read(reader)?;
+        let mutable = match reader.read_u8()? {
+            0x00 => false,
+            0x01 => true,
+            byte => {
+                return Err(Error::InvalidMutability {
+                    byte,
+                    at: SectionOffset::unknown(),
+                })
+            }
+        };
+        Ok(FieldType { storage, mutable })
+    }
+
+    pub fn storage(&self) -> StorageType {
+        self.storage
+    }
+
+    pub fn mutable(&self) -> bool {
+        self.mutable
+    }
+}
+
+impl fmt::Display for FieldType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.mutable {
+            write!(f, "(mut {})", self.storage)
+        } else {
+            write!(f, "{}", self.storage)
+        }
+    }
+}
+
+#[derive(Clone, PartialEq)]
+pub struct StructType {
+    fields: Vec<FieldType>,
+}
+
+impl StructType {
+    pub fn new(fields: Vec<FieldType>) -> StructType {
+        StructType { fields }
+    }
+
+    pub fn read<R: io::Read>(reader: &mut R) -> Result<StructType, Error> {
+        let fields = utils::read_vec(reader, FieldType::read)?;
+        Ok(StructType { fields })
+    }
+
+    pub fn fields(&self) -> &[FieldType] {
+        &self.fields
+    }
+}
+
+impl fmt::Display for StructType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "(struct")?;
+        for field in self.fields.iter() {
+            write!(f, " (field {})", field)?;
+        }
+        write!(f, ")")
+    }
+}
+
+#[derive(Clone, PartialEq)]
+pub struct ArrayType {
+    element: FieldType,
+}
+
+impl ArrayType {
+    pub fn new(element: FieldType) -> ArrayType {
+        ArrayType { element }
+    }
+
+    pub fn read<R: io::Read>(reader: &mut R) -> Result<ArrayType, Error> {
+        Ok(ArrayType {
+            element: FieldType::read(reader)?,
+        })
+    }
+
+    pub fn element(&self) -> FieldType {
+        self.element
+    }
+}
+
+impl fmt::Display for ArrayType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "(array {})", self.element)
+    }
+}
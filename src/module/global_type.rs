@@ -2,7 +2,7 @@ use std::{fmt, io};
 
 use byteorder::ReadBytesExt;
 
-use crate::{Error, ValType};
+use crate::{Error, SectionOffset, ValType};
 
 #[derive(PartialEq, Clone)]
 pub struct GlobalType {
@@ -11,12 +11,21 @@ pub struct GlobalType {
 }
 
 impl GlobalType {
+    pub fn new(typ: ValType, mutable: bool) -> GlobalType {
+        GlobalType { typ, mutable }
+    }
+
     pub fn read<R: io::Read>(reader: &mut R) -> Result<GlobalType, Error> {
         let typ = ValType::read(reader)?;
         let mutable = match reader.read_u8()? {
             0x00 => false,
             0x01 => true,
-            _ => return Err(Error::InvalidModule),
+            byte => {
+                return Err(Error::InvalidMutability {
+                    byte,
+                    at: SectionOffset::unknown(),
+                })
+            }
         };
         Ok(GlobalType { typ, mutable })
     }
@@ -4,7 +4,7 @@ use byteorder::ReadBytesExt;
 
 use crate::{
     module::{GlobalType, MemoryType, TableType},
-    utils, Error,
+    utils, Error, SectionOffset,
 };
 
 #[derive(PartialEq, Clone)]
@@ -26,7 +26,10 @@ impl MemberDesc {
             0x01 => Ok(MemberDesc::Table(TableType::read(reader)?)),
             0x02 => Ok(MemberDesc::Memory(MemoryType::read(reader)?)),
             0x03 => Ok(MemberDesc::Global(GlobalType::read(reader)?)),
-            _ => Err(Error::InvalidModule),
+            kind => Err(Error::InvalidExternKind {
+                kind,
+                at: SectionOffset::unknown(),
+            }),
         }
     }
 }
@@ -1,21 +1,128 @@
 use std::{fmt, io};
 
-use crate::{utils, Error};
+use byteorder::ReadBytesExt;
+
+use crate::{utils, Error, SectionOffset, PAGE_SIZE};
 
 #[derive(PartialEq, Clone)]
 pub struct MemoryType {
     min: usize,
     max: Option<usize>,
+    shared: bool,
+    memory64: bool,
+    page_size: usize,
 }
 
 impl MemoryType {
     pub fn new(min: usize, max: Option<usize>) -> MemoryType {
-        MemoryType { min, max }
+        MemoryType {
+            min,
+            max,
+            shared: false,
+            memory64: false,
+            page_size: PAGE_SIZE,
+        }
+    }
+
+    /// Creates the type of a memory that is indexed by 64-bit addresses.
+    pub fn new_64(min: usize, max: Option<usize>) -> MemoryType {
+        MemoryType {
+            min,
+            max,
+            shared: false,
+            memory64: true,
+            page_size: PAGE_SIZE,
+        }
+    }
+
+    /// Creates the type of a memory that can be shared between threads.
+    ///
+    /// Shared memories must declare a maximum size.
+    pub fn new_shared(min: usize, max: usize) -> MemoryType {
+        MemoryType {
+            min,
+            max: Some(max),
+            shared: true,
+            memory64: false,
+            page_size: PAGE_SIZE,
+        }
+    }
+
+    /// Returns a copy of this type whose sizes are measured in pages of `page_size` bytes.
+    ///
+    /// The custom-page-sizes proposal only permits pages of 1 byte or the default 64KiB.
+    #[cfg(feature = "custom-page-sizes")]
+    pub fn with_page_size(self, page_size: usize) -> Result<MemoryType, Error> {
+        if page_size != 1 && page_size != PAGE_SIZE {
+            return Err(Error::InvalidPageSize {
+                at: SectionOffset::unknown(),
+            });
+        }
+        Ok(MemoryType { page_size, ..self })
     }
 
     pub fn read<R: io::Read>(reader: &mut R) -> Result<MemoryType, Error> {
-        let (min, max) = utils::read_limits(reader)?;
-        Ok(MemoryType { min, max })
+        // Bit 0 indicates a maximum is present, bit 1 indicates the memory is shared,
+        // bit 2 indicates the memory is indexed by 64-bit addresses and bit 3 indicates a
+        // custom page size follows the limits.
+        #[cfg(feature = "custom-page-sizes")]
+        const KNOWN_FLAGS: u8 = 0x0F;
+        #[cfg(not(feature = "custom-page-sizes"))]
+        const KNOWN_FLAGS: u8 = 0x07;
+
+        let flags = reader.read_u8()?;
+        if flags & !KNOWN_FLAGS != 0 {
+            return Err(Error::InvalidLimits {
+                flags,
+                at: SectionOffset::unknown(),
+            });
+        }
+
+        // The limits of a 64-bit memory are encoded as 64-bit integers
+        let memory64 = flags & 0x04 != 0;
+        let read_limit = |reader: &mut R| -> Result<usize, Error> {
+            if memory64 {
+                utils::read_leb128_u64(reader).map(|x| x as usize)
+            } else {
+                utils::read_leb128_u32(reader).map(|x| x as usize)
+            }
+        };
+        let min = read_limit(reader)?;
+        let max = if flags & 0x01 != 0 {
+            Some(read_limit(reader)?)
+        } else {
+            None
+        };
+        let shared = flags & 0x02 != 0;
+        if shared && max.is_none() {
+            return Err(Error::SharedMemoryWithoutMaximum {
+                at: SectionOffset::unknown(),
+            });
+        }
+
+        let typ = MemoryType {
+            min,
+            max,
+            shared,
+            memory64,
+            page_size: PAGE_SIZE,
+        };
+
+        // The page size is encoded as its base 2 logarithm
+        #[cfg(feature = "custom-page-sizes")]
+        {
+            if flags & 0x08 != 0 {
+                let log2 = utils::read_leb128_u32(reader)?;
+                if log2 >= usize::BITS {
+                    return Err(Error::InvalidPageSize {
+                        at: SectionOffset::unknown(),
+                    });
+                }
+                return typ.with_page_size(1 << log2);
+            }
+        }
+
+        Ok(typ)
     }
 
     pub fn min(&self) -> usize {
@@ -25,14 +132,38 @@ impl MemoryType {
     pub fn max(&self) -> Option<usize> {
         self.max
     }
+
+    pub fn shared(&self) -> bool {
+        self.shared
+    }
+
+    /// Gets the size, in bytes, of the pages `min` and `max` are measured in.
+    pub fn page_size(&self) -> usize {
+        self.page_size
+    }
+
+    /// Gets a boolean indicating if this memory is indexed by 64-bit addresses.
+    pub fn is_64(&self) -> bool {
+        self.memory64
+    }
 }
 
 impl fmt::Display for MemoryType {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "(memory {}", self.min)?;
+        write!(f, "(memory ")?;
+        if self.memory64 {
+            write!(f, "i64 ")?;
+        }
+        write!(f, "{}", self.min)?;
         if let Some(max) = self.max {
             write!(f, " {}", max)?;
         }
+        if self.shared {
+            write!(f, " shared")?;
+        }
+        if self.page_size != PAGE_SIZE {
+            write!(f, " (pagesize {})", self.page_size)?;
+        }
         write!(f, ")")
     }
 }
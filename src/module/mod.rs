@@ -1,8 +1,14 @@
 mod data_item;
+mod elem_item;
 mod export;
+mod export_desc;
 mod expr;
+mod extern_type;
 mod func_body;
 mod func_type;
+#[cfg(feature = "gc")]
+mod gc_types;
+mod global;
 mod global_type;
 mod import;
 mod member_desc;
@@ -10,12 +16,19 @@ mod memory_type;
 mod module;
 mod module_names;
 mod table_type;
+mod type_def;
 
 pub use self::data_item::DataItem;
+pub use self::elem_item::ElemItem;
 pub use self::export::Export;
+pub use self::export_desc::ExportDesc;
 pub use self::expr::Expr;
-pub use self::func_body::FuncBody;
+pub use self::extern_type::{ExportType, ExternType, ImportType};
+pub use self::func_body::{FuncBody, MAX_LOCALS};
 pub use self::func_type::FuncType;
+#[cfg(feature = "gc")]
+pub use self::gc_types::{ArrayType, FieldType, StorageType, StructType};
+pub use self::global::Global;
 pub use self::global_type::GlobalType;
 pub use self::import::Import;
 pub use self::member_desc::MemberDesc;
@@ -23,3 +36,4 @@ pub use self::memory_type::MemoryType;
 pub use self::module::Module;
 pub use self::module_names::ModuleNames;
 pub use self::table_type::TableType;
+pub use self::type_def::TypeDef;
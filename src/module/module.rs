@@ -2,24 +2,33 @@ use std::{fmt, io};
 
 use crate::{
     builder::ModuleBuilder,
-    module::{DataItem, Export, FuncBody, FuncType, Import, ModuleNames},
+    module::{
+        DataItem, ElemItem, Export, ExportDesc, ExportType, ExternType, FuncBody, FuncType, Global,
+        Import, ImportType, MemberDesc, MemoryType, ModuleNames, TableType, TypeDef,
+    },
     reader::{
-        CodeSection, CustomSection, DataSection, ExportSection, FunctionSection, ImportSection,
-        Reader, SectionHeader, SectionId, TypeSection,
+        CodeSection, CustomSection, DataSection, DylinkSection, ElementSection, ExportSection,
+        FunctionSection, GlobalSection, ImportSection, LazyCodeSection, MemorySection,
+        ProducersSection, Reader, SectionHeader, SectionId, TableSection, TypeSection,
     },
-    Error,
+    validate, Error, SectionOffset,
 };
 
 /// Represents the static information associated with a WebAssembly Module
 #[derive(Clone, PartialEq)]
 pub struct Module {
-    types: Vec<FuncType>,
+    types: Vec<TypeDef>,
     imports: Vec<Import>,
     funcs: Vec<usize>,
+    tables: Vec<TableType>,
+    mems: Vec<MemoryType>,
+    globals: Vec<Global>,
     exports: Vec<Export>,
+    elems: Vec<ElemItem>,
     code: Vec<FuncBody>,
     data: Vec<DataItem>,
     names: Option<ModuleNames>,
+    custom_sections: Vec<CustomSection>,
 }
 
 impl Module {
@@ -28,15 +37,41 @@ impl Module {
             types: builder.types,
             imports: builder.imports,
             funcs: builder.funcs,
+            tables: builder.tables,
+            mems: builder.mems,
+            globals: builder.globals,
             exports: builder.exports,
+            elems: builder.elems,
             code: builder.code,
             data: builder.data,
             names: builder.names,
+            custom_sections: builder.custom_sections,
         }
     }
 
     /// Loads a module up from the provided reader, consuming the reader in the process
-    pub fn load<R: io::Read + io::Seek>(mut r: Reader<R>) -> Result<Module, Error> {
+    pub fn load<R: io::Read + io::Seek>(r: Reader<R>) -> Result<Module, Error> {
+        Module::load_with(r, Bodies::Decoded)
+    }
+
+    /// Loads a module like [`Module::load`], but leaves function bodies undecoded until they're
+    /// first needed, which is usually when they're first called. This makes loading large
+    /// modules that only use a few of their functions much faster. Malformed bodies aren't
+    /// found until they're needed, when calling them traps.
+    pub fn load_lazy<R: io::Read + io::Seek>(r: Reader<R>) -> Result<Module, Error> {
+        Module::load_with(r, Bodies::Lazy)
+    }
+
+    /// Loads a module whose code section has been removed, like the binary in a precompiled
+    /// module, whose bodies are kept alongside it instead.
+    pub(crate) fn load_without_code<R: io::Read + io::Seek>(r: Reader<R>) -> Result<Module, Error> {
+        Module::load_with(r, Bodies::Stripped)
+    }
+
+    fn load_with<R: io::Read + io::Seek>(
+        mut r: Reader<R>,
+        bodies: Bodies,
+    ) -> Result<Module, Error> {
         // Read and validate the header
         let header = r.read_module_header()?;
 
@@ -49,28 +84,64 @@ impl Module {
         let mut types = None;
         let mut imports = None;
         let mut funcs = None;
+        let mut tables = None;
+        let mut mems = None;
+        let mut globals = None;
         let mut exports = None;
+        let mut elems = None;
         let mut code = None;
         let mut data = None;
         let mut names = None;
+        let mut custom_sections = Vec::new();
 
-        // Load all the sections
+        // Load all the sections. Only custom sections may appear more than once, and the others
+        // must appear in order of their IDs.
+        let mut seen: Vec<SectionId> = Vec::new();
+        let mut code_offset = None;
         while let Some(header) = r.read_section_header()? {
+            if header.id != SectionId::Custom {
+                if seen.contains(&header.id) {
+                    return Err(Error::DuplicateSection {
+                        at: SectionOffset::unknown(),
+                    }
+                    .in_section(header.id, Some(header.offset)));
+                }
+                if let Some(&last) = seen.last() {
+                    if (last as u8) > (header.id as u8) {
+                        return Err(Error::SectionOutOfOrder {
+                            after: last,
+                            at: SectionOffset::unknown(),
+                        }
+                        .in_section(header.id, Some(header.offset)));
+                    }
+                }
+                seen.push(header.id);
+            }
+            if header.id == SectionId::Code {
+                code_offset = Some(header.offset);
+            }
+
             match header.id {
                 SectionId::Type => types = Some(load_types(&mut r, header)?),
                 SectionId::Import => imports = Some(load_imports(&mut r, header)?),
                 SectionId::Function => funcs = Some(load_functions(&mut r, header)?),
+                SectionId::Table => tables = Some(load_tables(&mut r, header)?),
+                SectionId::Memory => mems = Some(load_mems(&mut r, header)?),
+                SectionId::Global => globals = Some(load_globals(&mut r, header)?),
                 SectionId::Export => exports = Some(load_exports(&mut r, header)?),
+                SectionId::Element => elems = Some(load_elems(&mut r, header)?),
+                SectionId::Code if bodies == Bodies::Lazy => {
+                    let section: LazyCodeSection = r.read_section(header)?;
+                    code = Some(section.code)
+                }
                 SectionId::Code => code = Some(load_code(&mut r, header)?),
                 SectionId::Data => data = Some(load_data(&mut r, header)?),
                 SectionId::Custom => {
                     let section: CustomSection = r.read_section(header)?;
-                    match section.name.as_str() {
-                        "name" => names = Some(ModuleNames::load(section.read_content()?)),
-                        _ => {
-                            // Unknown custom section, just ignore it
-                        }
+                    if section.name == "name" {
+                        names = Some(ModuleNames::load(section.read_content()?));
                     }
+                    custom_sections.push(section);
                 }
                 _ => {
                     // Unknown section
@@ -80,33 +151,167 @@ impl Module {
             }
         }
 
+        // Every function needs a body, and every body a function
+        let func_count = funcs.as_ref().map_or(0, |f: &Vec<usize>| f.len());
+        let body_count = code.as_ref().map_or(0, |c: &Vec<FuncBody>| c.len());
+        if bodies != Bodies::Stripped && func_count != body_count {
+            return Err(Error::FunctionCodeMismatch {
+                funcs: func_count,
+                bodies: body_count,
+                at: SectionOffset::unknown(),
+            }
+            .in_section(SectionId::Code, code_offset));
+        }
+
         Ok(Module {
-            types: types.unwrap_or_else(|| Vec::new()),
-            imports: imports.unwrap_or_else(|| Vec::new()),
-            funcs: funcs.unwrap_or_else(|| Vec::new()),
-            exports: exports.unwrap_or_else(|| Vec::new()),
-            code: code.unwrap_or_else(|| Vec::new()),
-            data: data.unwrap_or_else(|| Vec::new()),
+            types: types.unwrap_or_default(),
+            imports: imports.unwrap_or_default(),
+            funcs: funcs.unwrap_or_default(),
+            tables: tables.unwrap_or_default(),
+            mems: mems.unwrap_or_default(),
+            globals: globals.unwrap_or_default(),
+            exports: exports.unwrap_or_default(),
+            elems: elems.unwrap_or_default(),
+            code: code.unwrap_or_default(),
+            data: data.unwrap_or_default(),
             names,
+            custom_sections,
         })
     }
 
-    pub fn types(&self) -> &Vec<FuncType> {
+    /// Parses a module from the WebAssembly text format. Like [`Module::load`], the module isn't
+    /// validated.
+    #[cfg(feature = "wat")]
+    pub fn from_wat(text: &str) -> Result<Module, Error> {
+        let wasm = wat::parse_str(text).map_err(|e| Error::InvalidText(e.to_string()))?;
+        Module::load(Reader::new(io::Cursor::new(wasm)))
+    }
+
+    /// Checks that the module is valid, including the type of every function body. Modules
+    /// aren't validated by `Host::instantiate`, so call this first to catch errors up front.
+    pub fn validate(&self) -> Result<(), Error> {
+        validate::validate(self)
+    }
+
+    pub fn types(&self) -> &Vec<TypeDef> {
         &self.types
     }
 
+    /// Gets the function type with the specified index in the type section, if that entry is
+    /// a function type.
+    pub fn func_type(&self, idx: usize) -> Option<&FuncType> {
+        self.types.get(idx).and_then(|t| t.func())
+    }
+
     pub fn imports(&self) -> &Vec<Import> {
         &self.imports
     }
 
+    /// Gets each import with the type of the item it needs, so linking requirements can be
+    /// checked without instantiating the module. Fails if an import refers to a type the module
+    /// doesn't have, which validation would also reject.
+    pub fn import_types(&self) -> Result<Vec<ImportType>, Error> {
+        self.imports
+            .iter()
+            .map(|import| {
+                let typ = match import.description() {
+                    MemberDesc::Function(type_id) => {
+                        ExternType::Func(self.resolve_type(*type_id, SectionId::Import)?.clone())
+                    }
+                    MemberDesc::Table(typ) => ExternType::Table(typ.clone()),
+                    MemberDesc::Memory(typ) => ExternType::Memory(typ.clone()),
+                    MemberDesc::Global(typ) => ExternType::Global(typ.clone()),
+                };
+                Ok(ImportType::new(import.module(), import.name(), typ))
+            })
+            .collect()
+    }
+
+    /// Gets each export with the type of the item it offers, resolving its index against the
+    /// imported and defined items. Fails if an export refers to an item the module doesn't have.
+    pub fn export_types(&self) -> Result<Vec<ExportType>, Error> {
+        let mut funcs = Vec::new();
+        let mut tables = Vec::new();
+        let mut mems = Vec::new();
+        let mut globals = Vec::new();
+        for import in &self.imports {
+            match import.description() {
+                MemberDesc::Function(type_id) => funcs.push(*type_id),
+                MemberDesc::Table(typ) => tables.push(typ),
+                MemberDesc::Memory(typ) => mems.push(typ),
+                MemberDesc::Global(typ) => globals.push(typ),
+            }
+        }
+        funcs.extend(self.funcs.iter());
+        tables.extend(self.tables.iter());
+        mems.extend(self.mems.iter());
+        globals.extend(self.globals.iter().map(|g| g.typ()));
+
+        let at = SectionOffset::in_section(SectionId::Export);
+        self.exports
+            .iter()
+            .map(|export| {
+                let typ = match *export.description() {
+                    ExportDesc::Function(index) => {
+                        let type_id = funcs
+                            .get(index)
+                            .ok_or(Error::UnknownFunctionIndex { index, at })?;
+                        ExternType::Func(self.resolve_type(*type_id, SectionId::Export)?.clone())
+                    }
+                    ExportDesc::Table(index) => ExternType::Table(
+                        tables
+                            .get(index)
+                            .map(|t| (*t).clone())
+                            .ok_or(Error::UnknownTableIndex { index, at })?,
+                    ),
+                    ExportDesc::Memory(index) => ExternType::Memory(
+                        mems.get(index)
+                            .map(|m| (*m).clone())
+                            .ok_or(Error::UnknownMemoryIndex { index, at })?,
+                    ),
+                    ExportDesc::Global(index) => ExternType::Global(
+                        globals
+                            .get(index)
+                            .map(|g| (*g).clone())
+                            .ok_or(Error::UnknownGlobalIndex { index, at })?,
+                    ),
+                };
+                Ok(ExportType::new(export.name(), typ))
+            })
+            .collect()
+    }
+
+    fn resolve_type(&self, type_id: usize, section: SectionId) -> Result<&FuncType, Error> {
+        self.func_type(type_id).ok_or(Error::UnknownTypeIndex {
+            index: type_id,
+            at: SectionOffset::in_section(section),
+        })
+    }
+
     pub fn funcs(&self) -> &Vec<usize> {
         &self.funcs
     }
 
+    pub fn tables(&self) -> &Vec<TableType> {
+        &self.tables
+    }
+
+    pub fn mems(&self) -> &Vec<MemoryType> {
+        &self.mems
+    }
+
+    pub fn globals(&self) -> &Vec<Global> {
+        &self.globals
+    }
+
     pub fn exports(&self) -> &Vec<Export> {
         &self.exports
     }
 
+    pub fn elems(&self) -> &Vec<ElemItem> {
+        &self.elems
+    }
+
     pub fn code(&self) -> &Vec<FuncBody> {
         &self.code
     }
@@ -118,12 +323,54 @@ impl Module {
     pub fn names(&self) -> Option<&ModuleNames> {
         self.names.as_ref()
     }
+
+    /// Gets the module's custom sections in the order they appear, including those the module
+    /// understands itself, such as `name`.
+    pub fn custom_sections(&self) -> &Vec<CustomSection> {
+        &self.custom_sections
+    }
+
+    /// Parses the `producers` section, if the module has one. The section is only metadata, so
+    /// it isn't parsed when the module is loaded, and a malformed one is only reported here.
+    pub fn producers(&self) -> Result<Option<ProducersSection>, Error> {
+        self.custom_sections
+            .iter()
+            .find(|section| section.name == "producers")
+            .map(|section| section.read_content())
+            .transpose()
+    }
+
+    /// Parses the `dylink.0` section, if the module has one, which makes it a side module.
+    pub fn dylink(&self) -> Result<Option<DylinkSection>, Error> {
+        self.custom_sections
+            .iter()
+            .find(|section| section.name == "dylink.0")
+            .map(|section| section.read_content())
+            .transpose()
+    }
+
+    /// Gets the content of the first custom section called `name`, if any.
+    pub fn custom_section(&self, name: &str) -> Option<&[u8]> {
+        self.custom_sections
+            .iter()
+            .find(|section| section.name == name)
+            .map(|section| section.content.as_slice())
+    }
+}
+
+/// How `Module::load_with` handles the code section.
+#[derive(Clone, Copy, PartialEq)]
+enum Bodies {
+    Decoded,
+    Lazy,
+    /// The code section has been removed, so there's no body for any function.
+    Stripped,
 }
 
 fn load_types<R: io::Read>(
     r: &mut Reader<R>,
     header: SectionHeader,
-) -> Result<Vec<FuncType>, Error> {
+) -> Result<Vec<TypeDef>, Error> {
     let section: TypeSection = r.read_section(header)?;
     Ok(section.types)
 }
@@ -144,6 +391,30 @@ fn load_functions<R: io::Read>(
     Ok(section.funcs)
 }
 
+fn load_tables<R: io::Read>(
+    r: &mut Reader<R>,
+    header: SectionHeader,
+) -> Result<Vec<TableType>, Error> {
+    let section: TableSection = r.read_section(header)?;
+    Ok(section.tables)
+}
+
+fn load_mems<R: io::Read>(
+    r: &mut Reader<R>,
+    header: SectionHeader,
+) -> Result<Vec<MemoryType>, Error> {
+    let section: MemorySection = r.read_section(header)?;
+    Ok(section.mems)
+}
+
+fn load_globals<R: io::Read>(
+    r: &mut Reader<R>,
+    header: SectionHeader,
+) -> Result<Vec<Global>, Error> {
+    let section: GlobalSection = r.read_section(header)?;
+    Ok(section.globals)
+}
+
 fn load_exports<R: io::Read>(
     r: &mut Reader<R>,
     header: SectionHeader,
@@ -152,6 +423,14 @@ fn load_exports<R: io::Read>(
     Ok(section.exports)
 }
 
+fn load_elems<R: io::Read>(
+    r: &mut Reader<R>,
+    header: SectionHeader,
+) -> Result<Vec<ElemItem>, Error> {
+    let section: ElementSection = r.read_section(header)?;
+    Ok(section.elems)
+}
+
 fn load_code<R: io::Read>(
     r: &mut Reader<R>,
     header: SectionHeader,
@@ -172,14 +451,16 @@ impl fmt::Display for Module {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(f, "(module")?;
         for typ in self.types().iter() {
-            if typ.params().len() > 0 || typ.results().len() > 0 {
-                write!(f, " (type {})", typ)?;
-            } else {
-                write!(f, " (type)")?;
+            match typ.func() {
+                Some(typ) if typ.params().is_empty() && typ.results().is_empty() => {
+                    write!(f, " (type)")?
+                }
+                Some(typ) => write!(f, " (type {})", typ)?,
+                None => write!(f, " (type {})", typ)?,
             }
         }
         for (func_idx, code) in self.funcs().iter().zip(self.code().iter()) {
-            if code.locals().len() > 0 || code.body().len() > 0 {
+            if !code.locals().is_empty() || !code.body().is_empty() {
                 write!(f, " (func (type {}) {})", func_idx, code)?;
             } else {
                 write!(f, " (func (type {}))", func_idx)?;
@@ -188,9 +469,21 @@ impl fmt::Display for Module {
         for import in self.imports().iter() {
             write!(f, " {}", import)?;
         }
+        for table in self.tables().iter() {
+            write!(f, " {}", table)?;
+        }
+        for mem in self.mems().iter() {
+            write!(f, " {}", mem)?;
+        }
+        for global in self.globals().iter() {
+            write!(f, " {}", global)?;
+        }
         for export in self.exports().iter() {
             write!(f, " {}", export)?;
         }
+        for elem in self.elems().iter() {
+            write!(f, " {}", elem)?;
+        }
         for data in self.data().iter() {
             write!(f, " {}", data)?;
         }
@@ -1,9 +1,13 @@
-use crate::{reader::NameSection, SparseVec};
+use crate::{
+    reader::{NameAssoc, NameSection},
+    SparseVec,
+};
 
 #[derive(Clone, PartialEq)]
 pub struct FuncNames {
     func_name: Option<String>,
     locals: SparseVec<String>,
+    labels: SparseVec<String>,
 }
 
 impl FuncNames {
@@ -11,11 +15,12 @@ impl FuncNames {
         FuncNames {
             func_name: None,
             locals: SparseVec::new(),
+            labels: SparseVec::new(),
         }
     }
 
     pub fn func_name(&self) -> Option<&str> {
-        self.func_name.as_ref().map(|x| &**x)
+        self.func_name.as_deref()
     }
 
     pub fn locals(&self) -> &SparseVec<String> {
@@ -25,12 +30,28 @@ impl FuncNames {
     pub fn local_name(&self, local_idx: usize) -> Option<&str> {
         self.locals.get(local_idx).map(|x| &**x)
     }
+
+    /// Gets the names of the function's labels, numbered in the order their blocks, loops and
+    /// ifs appear in its body.
+    pub fn labels(&self) -> &SparseVec<String> {
+        &self.labels
+    }
+
+    pub fn label_name(&self, label_idx: usize) -> Option<&str> {
+        self.labels.get(label_idx).map(|x| &**x)
+    }
 }
 
 #[derive(Clone, PartialEq)]
 pub struct ModuleNames {
     module_name: Option<String>,
     funcs: SparseVec<FuncNames>,
+    types: SparseVec<String>,
+    tables: SparseVec<String>,
+    mems: SparseVec<String>,
+    globals: SparseVec<String>,
+    elems: SparseVec<String>,
+    data: SparseVec<String>,
 }
 
 impl ModuleNames {
@@ -38,6 +59,12 @@ impl ModuleNames {
         ModuleNames {
             module_name: None,
             funcs: SparseVec::new(),
+            types: SparseVec::new(),
+            tables: SparseVec::new(),
+            mems: SparseVec::new(),
+            globals: SparseVec::new(),
+            elems: SparseVec::new(),
+            data: SparseVec::new(),
         }
     }
 
@@ -46,29 +73,75 @@ impl ModuleNames {
 
         // Load function names
         for name in section.func_names {
-            let mut f = funcs.get_or_add(name.index(), |_| FuncNames::new());
+            let f = funcs.get_or_add(name.index(), |_| FuncNames::new());
             f.func_name = Some(name.name().to_owned());
         }
 
-        // Load local names
+        // Load local and label names
         for ind_name in section.local_names {
-            let mut f = funcs.get_or_add(ind_name.index(), |_| FuncNames::new());
+            let f = funcs.get_or_add(ind_name.index(), |_| FuncNames::new());
             for name in ind_name.names() {
                 f.locals.set(name.index(), name.name().to_owned());
             }
         }
+        for ind_name in section.label_names {
+            let f = funcs.get_or_add(ind_name.index(), |_| FuncNames::new());
+            for name in ind_name.names() {
+                f.labels.set(name.index(), name.name().to_owned());
+            }
+        }
 
         ModuleNames {
             module_name: section.module_name,
             funcs,
+            types: name_map(section.type_names),
+            tables: name_map(section.table_names),
+            mems: name_map(section.memory_names),
+            globals: name_map(section.global_names),
+            elems: name_map(section.elem_names),
+            data: name_map(section.data_names),
         }
     }
 
     pub fn module_name(&self) -> Option<&str> {
-        self.module_name.as_ref().map(|x| &**x)
+        self.module_name.as_deref()
     }
 
     pub fn funcs(&self) -> &SparseVec<FuncNames> {
         &self.funcs
     }
+
+    pub fn types(&self) -> &SparseVec<String> {
+        &self.types
+    }
+
+    pub fn tables(&self) -> &SparseVec<String> {
+        &self.tables
+    }
+
+    pub fn mems(&self) -> &SparseVec<String> {
+        &self.mems
+    }
+
+    pub fn globals(&self) -> &SparseVec<String> {
+        &self.globals
+    }
+
+    /// Gets the names of the element segments.
+    pub fn elems(&self) -> &SparseVec<String> {
+        &self.elems
+    }
+
+    /// Gets the names of the data segments.
+    pub fn data(&self) -> &SparseVec<String> {
+        &self.data
+    }
+}
+
+fn name_map(names: Vec<NameAssoc>) -> SparseVec<String> {
+    let mut map = SparseVec::new();
+    for name in names {
+        map.set(name.index(), name.name().to_owned());
+    }
+    map
 }
@@ -2,7 +2,7 @@ use std::{fmt, io};
 
 use byteorder::ReadBytesExt;
 
-use crate::{utils, Error};
+use crate::{utils, Error, SectionOffset};
 
 #[repr(u8)]
 #[derive(Copy, PartialEq, Clone)]
@@ -18,10 +18,21 @@ pub struct TableType {
 }
 
 impl TableType {
+    pub fn new(min: usize, max: Option<usize>) -> TableType {
+        TableType {
+            elem_type: ElemType::AnyFunc,
+            min,
+            max,
+        }
+    }
+
     pub fn read<R: io::Read>(reader: &mut R) -> Result<TableType, Error> {
         let elem_type = reader.read_u8()?;
         if elem_type != 0x70 {
-            Err(Error::InvalidModule)
+            Err(Error::InvalidElemType {
+                byte: elem_type,
+                at: SectionOffset::unknown(),
+            })
         } else {
             let (min, max) = utils::read_limits(reader)?;
             Ok(TableType {
@@ -0,0 +1,90 @@
+use std::{fmt, io};
+
+use byteorder::ReadBytesExt;
+
+use crate::{module::FuncType, Error, SectionOffset};
+#[cfg(feature = "gc")]
+use crate::{
+    module::{ArrayType, StructType},
+    utils,
+};
+
+/// An entry in the type section.
+#[derive(Clone, PartialEq)]
+pub enum TypeDef {
+    Func(FuncType),
+    #[cfg(feature = "gc")]
+    Struct(StructType),
+    #[cfg(feature = "gc")]
+    Array(ArrayType),
+}
+
+impl TypeDef {
+    /// Reads a type section entry. With the GC proposal, an entry may be a recursion group
+    /// defining several types, which are returned in order.
+    pub fn read_group<R: io::Read>(reader: &mut R) -> Result<Vec<TypeDef>, Error> {
+        let code = reader.read_u8()?;
+        match code {
+            #[cfg(feature = "gc")]
+            0x4E => utils::read_vec(reader, |r| {
+                let code = r.read_u8()?;
+                TypeDef::read_sub(code, r)
+            }),
+            _ => Ok(vec![TypeDef::read_sub(code, reader)?]),
+        }
+    }
+
+    fn read_sub<R: io::Read>(code: u8, reader: &mut R) -> Result<TypeDef, Error> {
+        match code {
+            // Declared supertypes and finality are not tracked yet, so they are skipped
+            #[cfg(feature = "gc")]
+            0x50 | 0x4F => {
+                utils::read_vec(reader, utils::read_leb128_u32)?;
+                let code = reader.read_u8()?;
+                TypeDef::read_composite(code, reader)
+            }
+            _ => TypeDef::read_composite(code, reader),
+        }
+    }
+
+    fn read_composite<R: io::Read>(code: u8, reader: &mut R) -> Result<TypeDef, Error> {
+        match code {
+            0x60 => Ok(TypeDef::Func(FuncType::read_signature(reader)?)),
+            #[cfg(feature = "gc")]
+            0x5F => Ok(TypeDef::Struct(StructType::read(reader)?)),
+            #[cfg(feature = "gc")]
+            0x5E => Ok(TypeDef::Array(ArrayType::read(reader)?)),
+            form => Err(Error::InvalidTypeForm {
+                form,
+                at: SectionOffset::unknown(),
+            }),
+        }
+    }
+
+    /// Gets the function type this entry defines, if it defines one.
+    pub fn func(&self) -> Option<&FuncType> {
+        match self {
+            TypeDef::Func(f) => Some(f),
+            #[cfg(feature = "gc")]
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for TypeDef {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TypeDef::Func(typ) => write!(f, "(func {})", typ),
+            #[cfg(feature = "gc")]
+            TypeDef::Struct(typ) => write!(f, "{}", typ),
+            #[cfg(feature = "gc")]
+            TypeDef::Array(typ) => write!(f, "{}", typ),
+        }
+    }
+}
+
+impl fmt::Debug for TypeDef {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(self, f)
+    }
+}
@@ -1,15 +1,53 @@
-use std::io;
+use std::{io, sync::Arc};
 
-use crate::{module::FuncBody, reader::Section, utils, Error};
+use crate::{
+    module::FuncBody,
+    reader::{Section, SectionRead},
+    utils, Error, SectionOffset,
+};
 
 pub struct CodeSection {
     pub code: Vec<FuncBody>,
 }
 
 impl Section for CodeSection {
-    fn read<R: io::Read>(reader: &mut R) -> Result<CodeSection, Error> {
-        let code = utils::read_vec(reader, |r| FuncBody::read(r))?;
+    fn read<R: SectionRead>(reader: &mut R) -> Result<CodeSection, Error> {
+        // Each body has at least its size, a count of locals and an `end`
+        let code = utils::read_section_vec(reader, 3, |r| FuncBody::read(r))?;
 
         Ok(CodeSection { code })
     }
 }
+
+/// The code section, with each body's instructions left undecoded until they're first needed.
+/// The bodies share one copy of the section's bytes. See [`FuncBody::read_lazy`].
+pub struct LazyCodeSection {
+    pub code: Vec<FuncBody>,
+}
+
+impl Section for LazyCodeSection {
+    fn read<R: SectionRead>(reader: &mut R) -> Result<LazyCodeSection, Error> {
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes)?;
+        let bytes: Arc<[u8]> = bytes.into();
+
+        let mut counter = io::Cursor::new(&bytes[..]);
+        let count = utils::read_leb128_u32(&mut counter)?;
+        let mut offset = counter.position() as usize;
+        let remaining = bytes.len() - offset;
+        if count as usize > remaining {
+            return Err(Error::CountTooLarge {
+                count,
+                remaining,
+                at: SectionOffset::unknown(),
+            });
+        }
+        let mut code = Vec::new();
+        for _ in 0..count {
+            let (body, next) = FuncBody::read_lazy(&bytes, offset)?;
+            code.push(body);
+            offset = next;
+        }
+        Ok(LazyCodeSection { code })
+    }
+}
@@ -1,7 +1,13 @@
 use std::io;
 
-use crate::{reader::Section, utils, Error};
+use crate::{
+    reader::{Section, SectionRead},
+    utils, Error,
+};
 
+/// A custom section, named by the toolchain that produced it. Modules keep them, see
+/// [`Module::custom_sections`](crate::module::Module::custom_sections).
+#[derive(Clone, PartialEq)]
 pub struct CustomSection {
     pub name: String,
     pub content: Vec<u8>,
@@ -14,7 +20,7 @@ impl CustomSection {
 }
 
 impl Section for CustomSection {
-    fn read<R: io::Read>(reader: &mut R) -> Result<CustomSection, Error> {
+    fn read<R: SectionRead>(reader: &mut R) -> Result<CustomSection, Error> {
         let name = utils::read_name(reader)?;
 
         // Read the content
@@ -24,3 +30,29 @@ impl Section for CustomSection {
         Ok(CustomSection { name, content })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use crate::{module::Module, reader::Reader};
+
+    #[test]
+    pub fn modules_keep_custom_sections() {
+        let mut wasm = b"\0asm\x01\0\0\0".to_vec();
+        for (name, content) in [("meta", &[1, 2][..]), ("meta", &[3]), ("empty", &[])] {
+            wasm.push(0);
+            wasm.push((1 + name.len() + content.len()) as u8);
+            wasm.push(name.len() as u8);
+            wasm.extend_from_slice(name.as_bytes());
+            wasm.extend_from_slice(content);
+        }
+        let module = Module::load(Reader::new(Cursor::new(wasm))).unwrap();
+
+        let names: Vec<_> = module.custom_sections().iter().map(|s| &*s.name).collect();
+        assert_eq!(vec!["meta", "meta", "empty"], names);
+        assert_eq!(Some(&[1, 2][..]), module.custom_section("meta"));
+        assert_eq!(Some(&[][..]), module.custom_section("empty"));
+        assert_eq!(None, module.custom_section("producers"));
+    }
+}
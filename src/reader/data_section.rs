@@ -1,14 +1,17 @@
-use std::io;
-
-use crate::{module::DataItem, reader::Section, utils, Error};
+use crate::{
+    module::DataItem,
+    reader::{Section, SectionRead},
+    utils, Error,
+};
 
 pub struct DataSection {
     pub data: Vec<DataItem>,
 }
 
 impl Section for DataSection {
-    fn read<R: io::Read>(reader: &mut R) -> Result<DataSection, Error> {
-        let data = utils::read_vec(reader, |r| DataItem::read(r))?;
+    fn read<R: SectionRead>(reader: &mut R) -> Result<DataSection, Error> {
+        // Each segment has at least its flags and a length
+        let data = utils::read_section_vec(reader, 2, |r| DataItem::read(r))?;
 
         Ok(DataSection { data })
     }
@@ -0,0 +1,189 @@
+use std::io::{self, Read};
+
+use byteorder::ReadBytesExt;
+
+use crate::{
+    reader::{Section, SectionRead},
+    utils, Error,
+};
+
+const MEM_INFO: u8 = 1;
+const NEEDED: u8 = 2;
+
+/// The `dylink.0` custom section, which marks a module as a side module to be linked into
+/// another at run time, and says how much of the shared memory and table it needs. See
+/// [`Host::instantiate_side_module`](crate::hosting::Host::instantiate_side_module).
+#[derive(Clone, PartialEq, Debug, Default)]
+pub struct DylinkSection {
+    pub mem_info: DylinkMemInfo,
+    /// The side modules this one needs linked first.
+    pub needed: Vec<String>,
+}
+
+impl Section for DylinkSection {
+    fn read<R: SectionRead>(reader: &mut R) -> Result<DylinkSection, Error> {
+        let mut section = DylinkSection::default();
+        loop {
+            let id = match reader.read_u8() {
+                Ok(id) => id,
+                Err(ref e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(section),
+                Err(e) => return Err(e.into()),
+            };
+            let size = utils::read_leb128_u32(reader)?;
+            let mut content = reader.take(size as u64);
+            match id {
+                MEM_INFO => {
+                    section.mem_info = DylinkMemInfo {
+                        memory_size: utils::read_leb128_u32(&mut content)?,
+                        memory_align: utils::read_leb128_u32(&mut content)?,
+                        table_size: utils::read_leb128_u32(&mut content)?,
+                        table_align: utils::read_leb128_u32(&mut content)?,
+                    }
+                }
+                NEEDED => section.needed = utils::read_vec(&mut content, utils::read_name)?,
+                _ => {}
+            }
+
+            // Skip whatever's left, including subsections that are only hints for tools
+            io::copy(&mut content, &mut io::sink())?;
+        }
+    }
+}
+
+/// How much of the shared memory and table a side module needs for its data and functions.
+/// Alignments are powers of two, given as their logarithm.
+#[derive(Clone, Copy, PartialEq, Debug, Default)]
+pub struct DylinkMemInfo {
+    pub memory_size: u32,
+    pub memory_align: u32,
+    pub table_size: u32,
+    pub table_align: u32,
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        builder::{FuncBuilder, ModuleBuilder},
+        hosting::Host,
+        module::{ElemItem, Expr, GlobalType, Import, MemberDesc, MemoryType, TableType},
+        reader::{CustomSection, DylinkMemInfo},
+        Error,
+        Instruction::*,
+        MemoryConfig, ValType, Value, PAGE_SIZE,
+    };
+
+    /// A side module that places a function in the table, and stores to memory, at the bases
+    /// it's given.
+    fn side() -> ModuleBuilder {
+        let mut side = ModuleBuilder::new();
+        let global = || MemberDesc::Global(GlobalType::new(ValType::I32, false));
+        let imports = [
+            ("memory", MemberDesc::Memory(MemoryType::new(1, None))),
+            (
+                "__indirect_function_table",
+                MemberDesc::Table(TableType::new(0, None)),
+            ),
+            ("__memory_base", global()),
+            ("__table_base", global()),
+        ];
+        for (name, desc) in imports {
+            side.imports.push(Import::new("env", name, desc));
+        }
+        let mut side = side.func(FuncBuilder::new().export_as("store").body(vec![
+            GlobalGet(0),
+            I32Const(Value::I32(7)),
+            I32Store(2, 0),
+        ]));
+        side.elems
+            .push(ElemItem::new(0, Expr::new(vec![GlobalGet(1)]), vec![0]));
+        side
+    }
+
+    /// The side module, marked as one by its `dylink.0` section.
+    fn dylinked_side() -> ModuleBuilder {
+        let mut side = side();
+        side.custom_sections.push(CustomSection {
+            name: "dylink.0".to_owned(),
+            content: vec![1, 4, 8, 3, 1, 0, 2, 1, 0],
+        });
+        side
+    }
+
+    #[test]
+    pub fn side_modules_are_linked_at_their_base_offsets() {
+        let mut host = Host::new();
+        host.set_memory_config(MemoryConfig {
+            reserve: 2 * PAGE_SIZE,
+            ..MemoryConfig::default()
+        });
+        let main = ModuleBuilder::new()
+            .mem("memory", MemoryType::new(1, None))
+            .table("table", TableType::new(2, None));
+        let main = host.instantiate("main", main.build()).unwrap();
+        let memory = host.get_module(main).unwrap().mems()[0];
+        let table = host.get_module(main).unwrap().tables()[0];
+        assert!(matches!(
+            host.instantiate_side_module("side", side().build(), memory, table),
+            Err(Error::NotSideModule { .. })
+        ));
+
+        let side = dylinked_side().build();
+        let dylink = side.dylink().unwrap().unwrap();
+        assert_eq!(
+            DylinkMemInfo {
+                memory_size: 8,
+                memory_align: 3,
+                table_size: 1,
+                table_align: 0,
+            },
+            dylink.mem_info
+        );
+        assert!(dylink.needed.is_empty());
+
+        // The side module's data goes in a new page, and its function after the main module's
+        let side = host
+            .instantiate_side_module("side", side, memory, table)
+            .unwrap();
+        assert_eq!(2 * PAGE_SIZE, host.get_mem(memory).unwrap().memory().len());
+        assert_eq!(3, host.get_table(table).unwrap().len());
+        let store = host.resolve_func(side, 0).unwrap();
+        assert_eq!(Some(store), host.get_table(table).unwrap().get(2));
+        host.invoke(side, "store", &[]).unwrap();
+        assert_eq!(
+            7,
+            host.get_mem(memory).unwrap().read_u32(PAGE_SIZE).unwrap()
+        );
+    }
+
+    #[test]
+    pub fn side_modules_arent_linked_into_stale_items() {
+        let main = || {
+            ModuleBuilder::new()
+                .mem("memory", MemoryType::new(1, None))
+                .table("table", TableType::new(2, None))
+                .build()
+        };
+        let mut host = Host::new();
+        let addr = host.instantiate("dropped", main()).unwrap();
+        let dropped = host.get_module(addr).unwrap();
+        host.drop_instance(addr).unwrap();
+        let live = host.instantiate("main", main()).unwrap();
+        let live = host.get_module(live).unwrap();
+
+        let stale = |res: Result<_, Error>| matches!(res, Err(Error::StaleAddress { .. }));
+        let res = host.instantiate_side_module(
+            "side",
+            dylinked_side().build(),
+            dropped.mems()[0],
+            live.tables()[0],
+        );
+        assert!(stale(res));
+        let res = host.instantiate_side_module(
+            "side",
+            dylinked_side().build(),
+            live.mems()[0],
+            dropped.tables()[0],
+        );
+        assert!(stale(res));
+    }
+}
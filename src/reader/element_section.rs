@@ -0,0 +1,18 @@
+use crate::{
+    module::ElemItem,
+    reader::{Section, SectionRead},
+    utils, Error,
+};
+
+pub struct ElementSection {
+    pub elems: Vec<ElemItem>,
+}
+
+impl Section for ElementSection {
+    fn read<R: SectionRead>(reader: &mut R) -> Result<ElementSection, Error> {
+        // Each segment has at least its flags, an element kind or offset, and a count
+        let elems = utils::read_section_vec(reader, 3, |r| ElemItem::read(r))?;
+
+        Ok(ElementSection { elems })
+    }
+}
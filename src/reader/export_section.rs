@@ -1,14 +1,17 @@
-use std::io;
-
-use crate::{module::Export, reader::Section, utils, Error};
+use crate::{
+    module::Export,
+    reader::{Section, SectionRead},
+    utils, Error,
+};
 
 pub struct ExportSection {
     pub exports: Vec<Export>,
 }
 
 impl Section for ExportSection {
-    fn read<R: io::Read>(reader: &mut R) -> Result<ExportSection, Error> {
-        let exports = utils::read_vec(reader, |r| Export::read(r))?;
+    fn read<R: SectionRead>(reader: &mut R) -> Result<ExportSection, Error> {
+        // Each export has at least a name's length, a kind and an index
+        let exports = utils::read_section_vec(reader, 3, |r| Export::read(r))?;
 
         Ok(ExportSection { exports })
     }
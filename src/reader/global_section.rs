@@ -0,0 +1,18 @@
+use crate::{
+    module::Global,
+    reader::{Section, SectionRead},
+    utils, Error,
+};
+
+pub struct GlobalSection {
+    pub globals: Vec<Global>,
+}
+
+impl Section for GlobalSection {
+    fn read<R: SectionRead>(reader: &mut R) -> Result<GlobalSection, Error> {
+        // Each global has at least a type, a mutability and an `end`
+        let globals = utils::read_section_vec(reader, 3, |r| Global::read(r))?;
+
+        Ok(GlobalSection { globals })
+    }
+}
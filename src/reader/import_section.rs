@@ -1,14 +1,17 @@
-use std::io;
-
-use crate::{module::Import, reader::Section, utils, Error};
+use crate::{
+    module::Import,
+    reader::{Section, SectionRead},
+    utils, Error,
+};
 
 pub struct ImportSection {
     pub imports: Vec<Import>,
 }
 
 impl Section for ImportSection {
-    fn read<R: io::Read>(reader: &mut R) -> Result<ImportSection, Error> {
-        let imports = utils::read_vec(reader, |r| Import::read(r))?;
+    fn read<R: SectionRead>(reader: &mut R) -> Result<ImportSection, Error> {
+        // Each import has at least two names' lengths, a kind and an index or type
+        let imports = utils::read_section_vec(reader, 4, |r| Import::read(r))?;
 
         Ok(ImportSection { imports })
     }
@@ -0,0 +1,18 @@
+use crate::{
+    module::MemoryType,
+    reader::{Section, SectionRead},
+    utils, Error,
+};
+
+pub struct MemorySection {
+    pub mems: Vec<MemoryType>,
+}
+
+impl Section for MemorySection {
+    fn read<R: SectionRead>(reader: &mut R) -> Result<MemorySection, Error> {
+        // Each memory has at least its limits' flags and minimum
+        let mems = utils::read_section_vec(reader, 2, |r| MemoryType::read(r))?;
+
+        Ok(MemorySection { mems })
+    }
+}
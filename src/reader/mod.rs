@@ -1,31 +1,60 @@
 mod code_section;
 mod custom_section;
 mod data_section;
+mod dylink_section;
+mod element_section;
 mod export_section;
 mod function_section;
+mod global_section;
 mod import_section;
+mod memory_section;
 mod name_section;
+mod producers_section;
 mod section_header;
+mod slice_reader;
+mod table_section;
+mod target_features_section;
 mod type_section;
 
-pub use self::code_section::CodeSection;
+pub use self::code_section::{CodeSection, LazyCodeSection};
 pub use self::custom_section::CustomSection;
 pub use self::data_section::DataSection;
+pub use self::dylink_section::{DylinkMemInfo, DylinkSection};
+pub use self::element_section::ElementSection;
 pub use self::export_section::ExportSection;
 pub use self::function_section::FunctionSection;
+pub use self::global_section::GlobalSection;
 pub use self::import_section::ImportSection;
-pub use self::name_section::NameSection;
+pub use self::memory_section::MemorySection;
+pub use self::name_section::{NameAssoc, NameSection};
+pub use self::producers_section::{ProducerField, ProducerValue, ProducersSection};
 pub use self::section_header::{SectionHeader, SectionId};
+pub use self::slice_reader::{CustomSectionRef, DataSegmentRef, SliceReader};
+pub use self::table_section::TableSection;
+pub use self::target_features_section::{TargetFeature, TargetFeaturesSection};
 pub use self::type_section::TypeSection;
 
-use std::io;
+use std::io::{self, Read};
 
 use byteorder::{ByteOrder, LittleEndian, ReadBytesExt};
 
-use crate::{utils, Error};
+use crate::{utils, Error, SectionOffset};
 
 pub trait Section: Sized {
-    fn read<R: io::Read>(reader: &mut R) -> Result<Self, Error>;
+    fn read<R: SectionRead>(reader: &mut R) -> Result<Self, Error>;
+}
+
+/// Reads the contents of a section, knowing how many of them are left, so that vectors that
+/// declare more items than could possibly fit are rejected before any are read.
+pub trait SectionRead: io::Read {
+    /// Gets the number of bytes of the section that haven't been read yet.
+    fn remaining(&self) -> usize;
+}
+
+impl<T: AsRef<[u8]>> SectionRead for io::Cursor<T> {
+    fn remaining(&self) -> usize {
+        (self.get_ref().as_ref().len() as u64).saturating_sub(self.position()) as usize
+    }
 }
 
 pub struct ModuleHeader {
@@ -35,12 +64,18 @@ pub struct ModuleHeader {
 const EXPECTED_MAGIC: u32 = 0x6D736100;
 
 pub struct Reader<R: io::Read> {
-    source: R,
+    /// Counts the bytes of the module that have been read or skipped so far, to locate errors.
+    source: Counted<R>,
 }
 
 impl<R: io::Read> Reader<R> {
     pub fn new(source: R) -> Reader<R> {
-        Reader { source }
+        Reader {
+            source: Counted {
+                inner: source,
+                count: 0,
+            },
+        }
     }
 
     pub fn read_module_header(&mut self) -> Result<ModuleHeader, Error> {
@@ -50,7 +85,7 @@ impl<R: io::Read> Reader<R> {
         let magic_num = LittleEndian::read_u32(&magic);
 
         if magic_num != EXPECTED_MAGIC {
-            return Err(Error::InvalidModule);
+            return Err(Error::InvalidMagic);
         }
 
         let mut version = [0u8; 4];
@@ -62,35 +97,306 @@ impl<R: io::Read> Reader<R> {
     }
 
     pub fn read_section_header(&mut self) -> Result<Option<SectionHeader>, Error> {
+        let start = self.source.count;
         let id = match self.source.read_u8() {
-            Ok(i) => SectionId::from(i),
+            Ok(i) => SectionId::from_u8(i).map_err(|_| Error::UnknownSection {
+                id: i,
+                at: SectionOffset::at(start),
+            })?,
             Err(ref e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
             Err(e) => return Err(e.into()),
         };
 
+        let size = utils::read_leb128_u32(&mut self.source)
+            .map_err(|e| e.in_section(id, Some(self.source.count - 1)))?;
+
         Ok(Some(SectionHeader {
             id,
-            size: utils::read_leb128_u32(&mut self.source)?,
+            size,
+            offset: self.source.count,
         }))
     }
 
+    /// Reads the section, which must use exactly the number of bytes its header says.
+    /// Otherwise, fails with [`Error::SectionSizeMismatch`] rather than misreading what follows.
     pub fn read_section<S: Section>(&mut self, header: SectionHeader) -> Result<S, Error> {
-        read_section_helper(&mut self.source, header)
+        read_exactly(&mut self.source, header)
     }
 }
 
 impl<R: io::Read + io::Seek> Reader<R> {
     pub fn skip(&mut self, amount: usize) -> Result<(), Error> {
-        self.source.seek(io::SeekFrom::Current(amount as i64))?;
+        self.source
+            .inner
+            .seek(io::SeekFrom::Current(amount as i64))?;
+        self.source.count += amount as u64;
         Ok(())
     }
 }
 
-// This helper forces rust to consider the &mut Read we pass in as an implementation of Read
-// itself, so we can call take.
-fn read_section_helper<R: io::Read, S: Section>(
-    reader: R,
-    header: SectionHeader,
-) -> Result<S, Error> {
-    S::read(&mut reader.take(header.size as u64))
+/// Counts the bytes read through it, to know where in the module reading has got to.
+struct Counted<R> {
+    inner: R,
+    count: u64,
+}
+
+impl<R: io::Read> io::Read for Counted<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.count += n as u64;
+        Ok(n)
+    }
+}
+
+/// Reads the section that `header` describes from `source`, failing with
+/// [`Error::SectionSizeMismatch`] if reading it uses fewer bytes than the header says or needs
+/// more. Other errors are located at the byte being decoded when they were found.
+fn read_exactly<R: io::Read, S: Section>(source: R, header: SectionHeader) -> Result<S, Error> {
+    let SectionHeader { id, size, offset } = header;
+    let mut bounded = Bounded {
+        inner: source.take(size as u64),
+        overran: false,
+    };
+    let result = S::read(&mut bounded);
+    let consumed = size as u64 - bounded.inner.limit();
+    let mismatch = |consumed| {
+        Error::SectionSizeMismatch {
+            size,
+            consumed,
+            at: SectionOffset::unknown(),
+        }
+        .in_section(id, Some(offset))
+    };
+    match result {
+        // Running out of section is the problem if reading failed for want of bytes. Sections
+        // read to the end of their contents look past it too, but then fail for other reasons.
+        Err(Error::IoError(_)) if bounded.overran => Err(mismatch(None)),
+        Ok(_) if consumed < size as u64 => Err(mismatch(Some(consumed))),
+        Ok(section) => Ok(section),
+        Err(e) => Err(e.in_section(id, Some(offset + consumed.saturating_sub(1)))),
+    }
+}
+
+/// Limits reading to a section's contents, noting whether anything tried to read past them.
+struct Bounded<R> {
+    inner: io::Take<R>,
+    overran: bool,
+}
+
+impl<R: io::Read> io::Read for Bounded<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.inner.limit() == 0 && !buf.is_empty() {
+            self.overran = true;
+        }
+        self.inner.read(buf)
+    }
+}
+
+impl<R: io::Read> SectionRead for Bounded<R> {
+    fn remaining(&self) -> usize {
+        self.inner.limit() as usize
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use crate::{
+        module::Module,
+        reader::{Reader, SectionId, SliceReader, TypeSection},
+        Error,
+    };
+
+    /// A module whose type section has one `[] -> [i32]` type, but says it's `size` bytes.
+    fn module(size: u8) -> Vec<u8> {
+        let mut wasm = b"\0asm\x01\0\0\0".to_vec();
+        wasm.extend_from_slice(&[1, size, 0x01, 0x60, 0x00, 0x01, 0x7f]);
+        wasm.extend_from_slice(&[3, 1, 0]);
+        wasm
+    }
+
+    #[test]
+    pub fn sections_must_be_the_size_they_say() {
+        let size_mismatch = |wasm: Vec<u8>| match Module::load(Reader::new(Cursor::new(wasm))) {
+            Err(e @ Error::SectionSizeMismatch { .. }) => {
+                assert_eq!(Some(SectionId::Type), e.section_offset().unwrap().section());
+                match e {
+                    Error::SectionSizeMismatch { consumed, .. } => consumed,
+                    _ => unreachable!(),
+                }
+            }
+            other => panic!("expected a size mismatch, got {:?}", other),
+        };
+        assert!(Module::load(Reader::new(Cursor::new(module(5)))).is_ok());
+        assert_eq!(Some(5), size_mismatch(module(6)));
+        assert_eq!(None, size_mismatch(module(4)));
+
+        // Reading in place is just as strict
+        let wasm = module(6);
+        let mut reader = SliceReader::new(&wasm);
+        reader.read_module_header().unwrap();
+        let header = reader.read_section_header().unwrap().unwrap();
+        assert!(matches!(
+            reader.read_section::<TypeSection>(header),
+            Err(Error::SectionSizeMismatch {
+                size: 6,
+                consumed: Some(5),
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    pub fn sections_appear_once_and_in_order() {
+        let load = |sections: &[u8]| {
+            let mut wasm = b"\0asm\x01\0\0\0".to_vec();
+            wasm.extend_from_slice(sections);
+            Module::load(Reader::new(Cursor::new(wasm)))
+        };
+        let types = [1, 4, 1, 0x60, 0, 0];
+        let funcs = [3, 2, 1, 0];
+        let code = [10, 4, 1, 2, 0, 0x0b];
+        let custom = [0, 2, 1, b'x'];
+
+        let module = load(&[&types[..], &custom, &funcs, &custom, &code].concat()).unwrap();
+        assert_eq!(1, module.code().len());
+
+        match load(&[&funcs[..], &types, &code].concat()) {
+            Err(Error::SectionOutOfOrder {
+                after: SectionId::Function,
+                at,
+            }) => assert_eq!(
+                (Some(SectionId::Type), Some(14)),
+                (at.section(), at.offset())
+            ),
+            r => panic!("expected a section out of order, got {:?}", r.map(|_| ())),
+        }
+        assert!(matches!(
+            load(&[&types[..], &funcs, &funcs, &code].concat()),
+            Err(Error::DuplicateSection { .. })
+        ));
+        match load(&[&types[..], &funcs].concat()) {
+            Err(Error::FunctionCodeMismatch {
+                funcs: 1,
+                bodies: 0,
+                ..
+            }) => {}
+            r => panic!("expected a function/code mismatch, got {:?}", r.map(|_| ())),
+        }
+        assert!(matches!(
+            load(&[&types[..], &code].concat()),
+            Err(Error::FunctionCodeMismatch {
+                funcs: 0,
+                bodies: 1,
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    pub fn errors_are_located_at_the_byte_that_failed() {
+        let mut wasm = b"\0asm\x01\0\0\0".to_vec();
+        wasm.extend_from_slice(&[1, 4, 1, 0x60, 0, 0]);
+        wasm.extend_from_slice(&[3, 2, 1, 0]);
+        // One body: no locals, then nop and an opcode that doesn't exist
+        wasm.extend_from_slice(&[10, 6, 1, 4, 0, 0x01, 0xFF, 0x0B]);
+        match Module::load(Reader::new(Cursor::new(&wasm))) {
+            Err(Error::UnknownOpcode {
+                prefix: None,
+                opcode: 0xFF,
+                at,
+            }) => assert_eq!(
+                (Some(SectionId::Code), Some(24)),
+                (at.section(), at.offset())
+            ),
+            r => panic!("expected an unknown opcode, got {:?}", r.map(|_| ())),
+        }
+
+        // Names are read in place by the slice reader
+        let mut wasm = b"\0asm\x01\0\0\0".to_vec();
+        wasm.extend_from_slice(&[0, 4, 3, b'a', 0xC0, b'b']);
+        let mut reader = SliceReader::new(&wasm);
+        reader.read_module_header().unwrap();
+        let header = reader.read_section_header().unwrap().unwrap();
+        match reader.read_custom_section(header) {
+            Err(Error::InvalidUtf8 { at }) => assert_eq!(
+                (Some(SectionId::Custom), Some(12)),
+                (at.section(), at.offset())
+            ),
+            r => panic!("expected invalid UTF-8, got {:?}", r.map(|_| ())),
+        }
+
+        // An over-long section size is located in the section's header
+        let mut wasm = b"\0asm\x01\0\0\0".to_vec();
+        wasm.extend_from_slice(&[1, 0x80, 0x80, 0x80, 0x80, 0x80, 0x00]);
+        match Module::load(Reader::new(Cursor::new(&wasm))) {
+            Err(Error::IntegerTooLong { at }) => assert_eq!(
+                (Some(SectionId::Type), Some(13)),
+                (at.section(), at.offset())
+            ),
+            r => panic!("expected an over-long integer, got {:?}", r.map(|_| ())),
+        }
+    }
+
+    #[test]
+    pub fn declared_counts_cant_exhaust_memory() {
+        let module = |sections: &[&[u8]]| {
+            let mut wasm = b"\0asm\x01\0\0\0".to_vec();
+            wasm.extend(sections.concat());
+            wasm
+        };
+        let types: &[u8] = &[1, 4, 1, 0x60, 0, 0];
+        let funcs: &[u8] = &[3, 2, 1, 0];
+        let u32_max = [0xFF, 0xFF, 0xFF, 0xFF, 0x0F];
+
+        // A type section that claims 2^32 - 1 types is rejected before any are read, at the
+        // count, as each type takes at least two bytes
+        let wasm = module(&[&[1, 6], &u32_max, &[0x60]]);
+        match Module::load(Reader::new(Cursor::new(wasm))) {
+            Err(Error::CountTooLarge {
+                count: u32::MAX,
+                remaining: 1,
+                at,
+            }) => assert_eq!(
+                (Some(SectionId::Type), Some(14)),
+                (at.section(), at.offset())
+            ),
+            r => panic!("expected too large a count, got {:?}", r.map(|_| ())),
+        }
+
+        // As is an export section whose count only fits if exports took one byte each
+        let wasm = module(&[&[7, 3, 2, 1, b'x']]);
+        assert!(matches!(
+            Module::load(Reader::new(Cursor::new(wasm))),
+            Err(Error::CountTooLarge {
+                count: 2,
+                remaining: 2,
+                ..
+            })
+        ));
+
+        // As does a body with 2^32 - 1 locals, which is far more than any function needs
+        let wasm = module(&[types, funcs, &[10, 10, 1, 8, 1], &u32_max, &[0x7F, 0x0B]]);
+        for load in [Module::load, Module::load_lazy].iter() {
+            match load(Reader::new(Cursor::new(&wasm))) {
+                Err(Error::TooManyLocals { count, at }) => {
+                    assert_eq!(u32::MAX as u64, count);
+                    assert_eq!(Some(SectionId::Code), at.section());
+                }
+                r => panic!("expected too many locals, got {:?}", r.map(|_| ())),
+            }
+        }
+
+        // Lazily loaded code knows how many bytes its bodies have to share
+        let wasm = module(&[types, funcs, &[10, 8], &u32_max, &[2, 0, 0x0B]]);
+        assert!(matches!(
+            Module::load_lazy(Reader::new(Cursor::new(wasm))),
+            Err(Error::CountTooLarge {
+                count: u32::MAX,
+                remaining: 3,
+                ..
+            })
+        ));
+    }
 }
@@ -1,39 +1,56 @@
-use std::io;
+use std::io::{self, Read};
 
 use byteorder::ReadBytesExt;
 
-use crate::{reader::Section, utils, Error};
+use crate::{
+    reader::{Section, SectionRead},
+    utils, Error,
+};
 
+/// The `name` custom section, which gives debug names to a module and the items in it. Each
+/// subsection is empty if the module doesn't have it.
+#[derive(Default)]
 pub struct NameSection {
     pub module_name: Option<String>,
     pub func_names: Vec<NameAssoc>,
     pub local_names: Vec<IndirectNameAssoc>,
+    /// The names of the labels of blocks, loops and ifs in each function, numbered in the order
+    /// they appear in the function's body.
+    pub label_names: Vec<IndirectNameAssoc>,
+    pub type_names: Vec<NameAssoc>,
+    pub table_names: Vec<NameAssoc>,
+    pub memory_names: Vec<NameAssoc>,
+    pub global_names: Vec<NameAssoc>,
+    pub elem_names: Vec<NameAssoc>,
+    pub data_names: Vec<NameAssoc>,
 }
 
 impl Section for NameSection {
-    fn read<R: io::Read>(reader: &mut R) -> Result<NameSection, Error> {
-        let mut module_name = None;
-        let mut func_names = None;
-        let mut local_names = None;
-
+    fn read<R: SectionRead>(reader: &mut R) -> Result<NameSection, Error> {
+        let mut section = NameSection::default();
         while let Some(subsection_id) = read_subsection_id(reader)? {
-            let size = utils::read_leb128_u32(reader)? as usize;
+            let size = utils::read_leb128_u32(reader)? as u64;
+            let reader = &mut (&mut *reader).take(size);
             match subsection_id {
-                0x00 => module_name = Some(utils::read_name(reader)?),
-                0x01 => func_names = Some(read_name_map(reader)?),
-                0x02 => local_names = Some(read_ind_name_map(reader)?),
+                0x00 => section.module_name = Some(utils::read_name(reader)?),
+                0x01 => section.func_names = read_name_map(reader)?,
+                0x02 => section.local_names = read_ind_name_map(reader)?,
+                0x03 => section.label_names = read_ind_name_map(reader)?,
+                0x04 => section.type_names = read_name_map(reader)?,
+                0x05 => section.table_names = read_name_map(reader)?,
+                0x06 => section.memory_names = read_name_map(reader)?,
+                0x07 => section.global_names = read_name_map(reader)?,
+                0x08 => section.elem_names = read_name_map(reader)?,
+                0x09 => section.data_names = read_name_map(reader)?,
                 _ => {
-                    // Skip by dumping bytes into a buffer
-                    reader.read_exact(&mut vec![0u8; size])?;
+                    // Unknown subsection, skipped below
                 }
             }
-        }
 
-        Ok(NameSection {
-            module_name,
-            func_names: func_names.unwrap_or_else(|| Vec::new()),
-            local_names: local_names.unwrap_or_else(|| Vec::new()),
-        })
+            // Skip whatever the subsection has that wasn't read
+            io::copy(reader, &mut io::sink())?;
+        }
+        Ok(section)
     }
 }
 
@@ -92,3 +109,42 @@ fn read_ind_name_map<R: io::Read>(reader: &mut R) -> Result<Vec<IndirectNameAsso
         Ok(IndirectNameAssoc::new(idx, names))
     })
 }
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use crate::{
+        module::ModuleNames,
+        reader::{NameSection, Section},
+    };
+
+    /// Encodes a name subsection, whose content must be under 128 bytes.
+    fn subsection(id: u8, content: &[u8]) -> Vec<u8> {
+        let mut bytes = vec![id, content.len() as u8];
+        bytes.extend_from_slice(content);
+        bytes
+    }
+
+    #[test]
+    pub fn every_name_subsection_is_read() {
+        let mut content = Vec::new();
+        // Function 0's local 1 is "x", and its label 0 "loop"
+        content.extend(subsection(0x02, &[1, 0, 1, 1, 1, b'x']));
+        content.extend(subsection(0x03, &[1, 0, 1, 0, 4, b'l', b'o', b'o', b'p']));
+        content.extend(subsection(0x07, &[1, 2, 2, b's', b'p']));
+        // Subsections that aren't understood, or have more than they should, are skipped
+        content.extend(subsection(0x0b, &[1, 2, 3]));
+        content.extend(subsection(0x09, &[1, 0, 3, b'r', b'o', b'm', 0xff]));
+
+        let section = NameSection::read(&mut Cursor::new(content)).unwrap();
+        let names = ModuleNames::load(section);
+        let func = names.funcs().get(0).unwrap();
+        assert_eq!(None, func.func_name());
+        assert_eq!(Some("x"), func.local_name(1));
+        assert_eq!(Some("loop"), func.label_name(0));
+        assert_eq!(Some("sp"), names.globals().get(2).map(|s| &**s));
+        assert_eq!(Some("rom"), names.data().get(0).map(|s| &**s));
+        assert_eq!(0, names.tables().len());
+    }
+}
@@ -0,0 +1,108 @@
+use crate::{
+    reader::{Section, SectionRead},
+    utils, Error,
+};
+
+/// The `producers` custom section, which records the languages, tools and SDKs that produced
+/// a module, and their versions. See [`Module::producers`](crate::module::Module::producers).
+#[derive(Clone, PartialEq, Debug)]
+pub struct ProducersSection {
+    pub fields: Vec<ProducerField>,
+}
+
+impl ProducersSection {
+    /// Gets the values of the field called `name`, which are empty if the section doesn't have it.
+    pub fn field(&self, name: &str) -> &[ProducerValue] {
+        self.fields
+            .iter()
+            .find(|field| field.name == name)
+            .map_or(&[], |field| field.values.as_slice())
+    }
+
+    /// Gets the source languages the module was written in.
+    pub fn language(&self) -> &[ProducerValue] {
+        self.field("language")
+    }
+
+    /// Gets the tools that compiled or transformed the module.
+    pub fn processed_by(&self) -> &[ProducerValue] {
+        self.field("processed-by")
+    }
+
+    /// Gets the SDKs the module was built with.
+    pub fn sdk(&self) -> &[ProducerValue] {
+        self.field("sdk")
+    }
+}
+
+impl Section for ProducersSection {
+    fn read<R: SectionRead>(reader: &mut R) -> Result<ProducersSection, Error> {
+        // Each field has at least a name's length and a count of values
+        let fields = utils::read_section_vec(reader, 2, |r| {
+            let name = utils::read_name(r)?;
+            let values = utils::read_vec(r, |r| {
+                let name = utils::read_name(r)?;
+                let version = utils::read_name(r)?;
+                Ok(ProducerValue { name, version })
+            })?;
+            Ok(ProducerField { name, values })
+        })?;
+        Ok(ProducersSection { fields })
+    }
+}
+
+/// A field of the `producers` section, such as `language` or `processed-by`.
+#[derive(Clone, PartialEq, Debug)]
+pub struct ProducerField {
+    pub name: String,
+    pub values: Vec<ProducerValue>,
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct ProducerValue {
+    pub name: String,
+    pub version: String,
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{builder::ModuleBuilder, reader::CustomSection};
+
+    fn name(s: &str) -> Vec<u8> {
+        let mut bytes = vec![s.len() as u8];
+        bytes.extend_from_slice(s.as_bytes());
+        bytes
+    }
+
+    #[test]
+    pub fn producers_are_parsed_from_modules() {
+        let mut content = vec![2];
+        content.extend(name("language"));
+        content.push(1);
+        content.extend(name("Rust"));
+        content.extend(name("1.80"));
+        content.extend(name("processed-by"));
+        content.push(2);
+        for (tool, version) in [("rustc", "1.80"), ("wasm-opt", "118")] {
+            content.extend(name(tool));
+            content.extend(name(version));
+        }
+
+        assert_eq!(None, ModuleBuilder::new().build().producers().unwrap());
+
+        let mut module = ModuleBuilder::new();
+        module.custom_sections.push(CustomSection {
+            name: "producers".to_owned(),
+            content,
+        });
+        let producers = module.build().producers().unwrap().unwrap();
+        assert_eq!("Rust", producers.language()[0].name);
+        let tools: Vec<_> = producers
+            .processed_by()
+            .iter()
+            .map(|v| (&*v.name, &*v.version))
+            .collect();
+        assert_eq!(vec![("rustc", "1.80"), ("wasm-opt", "118")], tools);
+        assert!(producers.sdk().is_empty());
+    }
+}
@@ -1,7 +1,9 @@
 use std::{fmt, mem};
 
+use crate::{Error, SectionOffset};
+
 #[repr(u8)]
-#[derive(Copy, Clone, Debug)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
 pub enum SectionId {
     Custom = 0,
     Type = 1,
@@ -17,12 +19,15 @@ pub enum SectionId {
     Data = 11,
 }
 
-impl From<u8> for SectionId {
-    fn from(i: u8) -> SectionId {
+impl SectionId {
+    pub fn from_u8(i: u8) -> Result<SectionId, Error> {
         if i > 11 {
-            panic!("Section ID unknown: {}", i);
+            Err(Error::UnknownSection {
+                id: i,
+                at: SectionOffset::unknown(),
+            })
         } else {
-            unsafe { mem::transmute(i) }
+            Ok(unsafe { mem::transmute::<u8, SectionId>(i) })
         }
     }
 }
@@ -36,4 +41,6 @@ impl fmt::Display for SectionId {
 pub struct SectionHeader {
     pub id: SectionId,
     pub size: u32,
+    /// The offset of the section's contents from the start of the module.
+    pub offset: u64,
 }
@@ -0,0 +1,255 @@
+use std::io;
+
+use byteorder::{ByteOrder, LittleEndian};
+
+use crate::{
+    module::{DataItem, Expr},
+    reader::{
+        read_exactly, CustomSection, ModuleHeader, Section, SectionHeader, SectionId,
+        EXPECTED_MAGIC,
+    },
+    utils, Error, Instruction, SectionOffset,
+};
+
+/// Reads a module from bytes that are already in memory. Unlike [`Reader`](super::Reader), it
+/// doesn't go through `io::Read`, so it always knows its offset, and it hands out section
+/// payloads, custom sections and data segments as slices of the module rather than copies.
+pub struct SliceReader<'a> {
+    bytes: &'a [u8],
+    /// The offset of the next byte to read.
+    offset: usize,
+}
+
+impl<'a> SliceReader<'a> {
+    pub fn new(bytes: &'a [u8]) -> SliceReader<'a> {
+        SliceReader { bytes, offset: 0 }
+    }
+
+    /// Gets the number of bytes of the module that have been read or skipped so far.
+    pub fn offset(&self) -> u64 {
+        self.offset as u64
+    }
+
+    pub fn read_module_header(&mut self) -> Result<ModuleHeader, Error> {
+        let header = self.take(8)?;
+        if LittleEndian::read_u32(&header[..4]) != EXPECTED_MAGIC {
+            return Err(Error::InvalidMagic);
+        }
+        Ok(ModuleHeader {
+            version: LittleEndian::read_u32(&header[4..]),
+        })
+    }
+
+    pub fn read_section_header(&mut self) -> Result<Option<SectionHeader>, Error> {
+        let start = self.offset;
+        let id = match self.bytes.get(start) {
+            Some(&i) => SectionId::from_u8(i).map_err(|_| Error::UnknownSection {
+                id: i,
+                at: SectionOffset::at(start as u64),
+            })?,
+            None => return Ok(None),
+        };
+        self.offset += 1;
+        let size = self
+            .parse(utils::read_leb128_u32)
+            .map_err(|e| e.in_section(id, Some(self.offset as u64)))?;
+        Ok(Some(SectionHeader {
+            id,
+            size,
+            offset: self.offset as u64,
+        }))
+    }
+
+    /// Gets the payload of the section, moving past it.
+    pub fn section_bytes(&mut self, header: SectionHeader) -> Result<&'a [u8], Error> {
+        self.offset = header.offset as usize;
+        self.take(header.size as usize)
+            .map_err(|e| e.in_section(header.id, Some(header.offset)))
+    }
+
+    /// Parses the section, reading its payload in place.
+    pub fn read_section<S: Section>(&mut self, header: SectionHeader) -> Result<S, Error> {
+        let (id, size, offset) = (header.id, header.size, header.offset);
+        let bytes = self.section_bytes(header)?;
+        read_exactly(bytes, SectionHeader { id, size, offset })
+    }
+
+    pub fn skip(&mut self, amount: usize) -> Result<(), Error> {
+        self.take(amount).map(|_| ())
+    }
+
+    /// Reads a custom section, borrowing its name and content from the module.
+    pub fn read_custom_section(
+        &mut self,
+        header: SectionHeader,
+    ) -> Result<CustomSectionRef<'a>, Error> {
+        let (id, offset) = (header.id, header.offset);
+        let mut section = SliceReader::new(self.section_bytes(header)?);
+        let name = section
+            .read_name()
+            .map_err(|e| e.in_section(id, Some(offset + section.offset as u64)))?;
+        Ok(CustomSectionRef {
+            name,
+            content: section.rest(),
+        })
+    }
+
+    /// Reads the data section, borrowing each segment's bytes from the module.
+    pub fn read_data_section(
+        &mut self,
+        header: SectionHeader,
+    ) -> Result<Vec<DataSegmentRef<'a>>, Error> {
+        let (id, offset) = (header.id, header.offset);
+        let mut section = SliceReader::new(self.section_bytes(header)?);
+        section
+            .read_data_segments()
+            .map_err(|e| e.in_section(id, Some(offset + section.offset as u64)))
+    }
+
+    fn read_data_segments(&mut self) -> Result<Vec<DataSegmentRef<'a>>, Error> {
+        let count = self.parse(utils::read_leb128_u32)?;
+        let remaining = self.rest().len();
+        if count as usize > remaining {
+            return Err(Error::CountTooLarge {
+                count,
+                remaining,
+                at: SectionOffset::unknown(),
+            });
+        }
+        let mut segments = Vec::new();
+        for _ in 0..count {
+            let index = self.parse(utils::read_leb128_u32)? as usize;
+            let expr = Expr::new(self.parse(Instruction::read_sequence)?);
+            let len = self.parse(utils::read_leb128_u32)? as usize;
+            let init = self.take(len)?;
+            segments.push(DataSegmentRef { index, expr, init });
+        }
+        Ok(segments)
+    }
+
+    fn read_name(&mut self) -> Result<&'a str, Error> {
+        let len = self.parse(utils::read_leb128_u32)? as usize;
+        let bytes = self.take(len)?;
+        std::str::from_utf8(bytes).map_err(|e| {
+            self.offset -= len - e.valid_up_to();
+            Error::InvalidUtf8 {
+                at: SectionOffset::unknown(),
+            }
+        })
+    }
+
+    /// Runs `read` over the unread bytes, moving past what it reads. If it fails, stops at the
+    /// last byte it read so that the error can be located there.
+    fn parse<T, F>(&mut self, read: F) -> Result<T, Error>
+    where
+        F: FnOnce(&mut io::Cursor<&'a [u8]>) -> Result<T, Error>,
+    {
+        let mut cursor = io::Cursor::new(self.rest());
+        let result = read(&mut cursor);
+        let read = cursor.position() as usize;
+        self.offset += if result.is_ok() {
+            read
+        } else {
+            read.saturating_sub(1)
+        };
+        result
+    }
+
+    /// Moves past the next `len` bytes, producing them.
+    fn take(&mut self, len: usize) -> Result<&'a [u8], Error> {
+        let bytes = self
+            .offset
+            .checked_add(len)
+            .and_then(|end| self.bytes.get(self.offset..end))
+            .ok_or_else(|| Error::from(io::Error::from(io::ErrorKind::UnexpectedEof)))?;
+        self.offset += len;
+        Ok(bytes)
+    }
+
+    fn rest(&self) -> &'a [u8] {
+        &self.bytes[self.offset.min(self.bytes.len())..]
+    }
+}
+
+/// A custom section read in place by a [`SliceReader`].
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct CustomSectionRef<'a> {
+    pub name: &'a str,
+    pub content: &'a [u8],
+}
+
+impl<'a> CustomSectionRef<'a> {
+    pub fn read_content<S: Section>(&self) -> Result<S, Error> {
+        S::read(&mut io::Cursor::new(self.content))
+    }
+
+    /// Copies the section, so it can outlive the module's bytes.
+    pub fn to_custom_section(&self) -> CustomSection {
+        CustomSection {
+            name: self.name.to_owned(),
+            content: self.content.to_vec(),
+        }
+    }
+}
+
+/// A data segment read in place by a [`SliceReader`].
+#[derive(Clone, PartialEq)]
+pub struct DataSegmentRef<'a> {
+    pub index: usize,
+    pub expr: Expr,
+    pub init: &'a [u8],
+}
+
+impl<'a> DataSegmentRef<'a> {
+    /// Copies the segment, so it can outlive the module's bytes.
+    pub fn to_data_item(&self) -> DataItem {
+        DataItem::new(self.index, self.expr.clone(), self.init.to_vec())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use crate::{
+        module::DataItem,
+        reader::{DataSection, Reader, SectionId, SliceReader},
+        Instruction, Value,
+    };
+
+    #[test]
+    pub fn payloads_are_borrowed_from_the_module() {
+        let mut wasm = b"\0asm\x01\0\0\0".to_vec();
+        // A custom section called "meta", and a data section with "hi" at offset 8
+        wasm.extend_from_slice(&[0, 7, 4, b'm', b'e', b't', b'a', 1, 2]);
+        wasm.extend_from_slice(&[11, 8, 1, 0, 0x41, 8, 0x0b, 2, b'h', b'i']);
+
+        let mut reader = SliceReader::new(&wasm);
+        assert_eq!(1, reader.read_module_header().unwrap().version);
+        let header = reader.read_section_header().unwrap().unwrap();
+        let custom = reader.read_custom_section(header).unwrap();
+        assert_eq!(("meta", &[1, 2][..]), (custom.name, custom.content));
+        assert!(wasm.as_ptr_range().contains(&custom.content.as_ptr()));
+
+        let header = reader.read_section_header().unwrap().unwrap();
+        assert_eq!((SectionId::Data, 19), (header.id, header.offset));
+        let data = reader.read_data_section(header).unwrap();
+        assert_eq!(b"hi", data[0].init);
+        assert_eq!(
+            Some(&Instruction::I32Const(Value::I32(8))),
+            data[0].expr.iter().next()
+        );
+        assert!(reader.read_section_header().unwrap().is_none());
+        assert_eq!(wasm.len() as u64, reader.offset());
+
+        // It agrees with reading the module through io::Read
+        let mut io_reader = Reader::new(Cursor::new(&wasm));
+        io_reader.read_module_header().unwrap();
+        let header = io_reader.read_section_header().unwrap().unwrap();
+        io_reader.skip(header.size as usize).unwrap();
+        let header = io_reader.read_section_header().unwrap().unwrap();
+        let section: DataSection = io_reader.read_section(header).unwrap();
+        let data: Vec<DataItem> = data.iter().map(|d| d.to_data_item()).collect();
+        assert_eq!(section.data, data);
+    }
+}
@@ -0,0 +1,18 @@
+use crate::{
+    module::TableType,
+    reader::{Section, SectionRead},
+    utils, Error,
+};
+
+pub struct TableSection {
+    pub tables: Vec<TableType>,
+}
+
+impl Section for TableSection {
+    fn read<R: SectionRead>(reader: &mut R) -> Result<TableSection, Error> {
+        // Each table has at least a reference type, and limits' flags and minimum
+        let tables = utils::read_section_vec(reader, 3, |r| TableType::read(r))?;
+
+        Ok(TableSection { tables })
+    }
+}
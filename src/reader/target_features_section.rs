@@ -0,0 +1,32 @@
+use byteorder::ReadBytesExt;
+
+use crate::{
+    reader::{Section, SectionRead},
+    utils, Error,
+};
+
+/// The `target_features` custom section, which records the WebAssembly features a module was
+/// compiled to use.
+pub struct TargetFeaturesSection {
+    pub features: Vec<TargetFeature>,
+}
+
+impl Section for TargetFeaturesSection {
+    fn read<R: SectionRead>(reader: &mut R) -> Result<TargetFeaturesSection, Error> {
+        // Each feature has at least a prefix and a name's length
+        let features = utils::read_section_vec(reader, 2, |r| {
+            let prefix = r.read_u8()?;
+            let name = utils::read_name(r)?;
+            Ok(TargetFeature { prefix, name })
+        })?;
+        Ok(TargetFeaturesSection { features })
+    }
+}
+
+/// A feature a module was compiled with. `prefix` is `b'+'` if the module uses the feature,
+/// `b'-'` if it must not be linked with modules that use it, and `b'='` if every linked
+/// module must use it.
+pub struct TargetFeature {
+    pub prefix: u8,
+    pub name: String,
+}
@@ -1,14 +1,19 @@
-use std::io;
-
-use crate::{module::FuncType, reader::Section, utils, Error};
+use crate::{
+    module::TypeDef,
+    reader::{Section, SectionRead},
+    utils, Error,
+};
 
 pub struct TypeSection {
-    pub types: Vec<FuncType>,
+    pub types: Vec<TypeDef>,
 }
 
 impl Section for TypeSection {
-    fn read<R: io::Read>(reader: &mut R) -> Result<TypeSection, Error> {
-        let types: Vec<FuncType> = utils::read_vec(reader, |r| FuncType::read(r))?;
+    fn read<R: SectionRead>(reader: &mut R) -> Result<TypeSection, Error> {
+        // Each group has at least a form and the count or type that follows it
+        let groups: Vec<Vec<TypeDef>> =
+            utils::read_section_vec(reader, 2, |r| TypeDef::read_group(r))?;
+        let types = groups.into_iter().flatten().collect();
 
         Ok(TypeSection { types })
     }
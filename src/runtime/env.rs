@@ -1,26 +1,77 @@
-use std::sync::Arc;
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
 
 use crate::{
-    hosting::{ExternalFunc, ExternalMemory, ExternalModule, Host},
-    interp::Thread,
+    hosting::{
+        Caller, ExternalFunc, ExternalGlobal, ExternalMemory, ExternalModule, ExternalTable,
+    },
+    interp::Suspend,
     module::FuncType,
-    FromValue, Trap, ValType, Value,
+    FromValue, Trap, TrapCause, ValType, Value,
 };
 
+/// The `env` module, with warthog's own imports and the ones simple emscripten-compiled modules
+/// need to instantiate: a memory, a growable table, `__memory_base` and `__table_base`, and
+/// the functions emscripten calls when it aborts or copies large blocks of memory.
 pub struct Env {
     funcs: Vec<Arc<ExternalFunc>>,
+    tables: Vec<ExternalTable>,
     mems: Vec<ExternalMemory>,
+    globals: Vec<ExternalGlobal>,
 }
 
 impl Env {
     pub fn new() -> Env {
         Env {
-            funcs: vec![Arc::new(ExternalFunc::new(
-                "print",
-                FuncType::new(vec![ValType::I32, ValType::I32], vec![]),
-                print,
-            ))],
+            funcs: vec![
+                Arc::new(ExternalFunc::new(
+                    "print",
+                    FuncType::new(vec![ValType::I32, ValType::I32], vec![]),
+                    print,
+                )),
+                Arc::new(ExternalFunc::new(
+                    "sleep_ms",
+                    FuncType::new(vec![ValType::I32], vec![]),
+                    sleep_ms,
+                )),
+                Arc::new(ExternalFunc::new(
+                    "sched_yield",
+                    FuncType::empty(),
+                    sched_yield,
+                )),
+                Arc::new(ExternalFunc::new(
+                    "exit",
+                    FuncType::new(vec![ValType::I32], vec![]),
+                    exit,
+                )),
+                Arc::new(ExternalFunc::new(
+                    "abort",
+                    FuncType::new(vec![ValType::I32], vec![]),
+                    abort,
+                )),
+                Arc::new(ExternalFunc::new(
+                    "abortOnCannotGrowMemory",
+                    FuncType::new(vec![ValType::I32], vec![ValType::I32]),
+                    abort_on_cannot_grow_memory,
+                )),
+                Arc::new(ExternalFunc::new(
+                    "emscripten_memcpy_big",
+                    FuncType::new(
+                        vec![ValType::I32, ValType::I32, ValType::I32],
+                        vec![ValType::I32],
+                    ),
+                    emscripten_memcpy_big,
+                )),
+            ],
+            tables: vec![ExternalTable::new("table", 1024, None)],
             mems: vec![ExternalMemory::new("memory", 256, Some(256))],
+            globals: vec![
+                // Static data starts after the first kilobyte, as emscripten lays it out
+                ExternalGlobal::new("__memory_base", Value::I32(1024), false),
+                ExternalGlobal::new("__table_base", Value::I32(0), false),
+            ],
         }
     }
 }
@@ -37,30 +88,153 @@ impl ExternalModule for Env {
     fn mems(&self) -> &[ExternalMemory] {
         &self.mems
     }
+
+    fn tables(&self) -> &[ExternalTable] {
+        &self.tables
+    }
+
+    fn globals(&self) -> &[ExternalGlobal] {
+        &self.globals
+    }
 }
 
-fn print(host: &mut Host, thread: &mut Thread, values: &[Value]) -> Result<Vec<Value>, Trap> {
-    let (count, start) = (
+fn print(caller: &mut Caller, values: &[Value]) -> Result<Vec<Value>, Trap> {
+    let (start, count) = (
         u32::from_value(values[0])? as usize,
         u32::from_value(values[1])? as usize,
     );
 
-    let module = thread.stack().current().frame().module();
-    let end = start + count;
-
     // Get memory 0 for the current frame
-    let mem_addr = host.resolve_mem(module, 0);
-    let mem_inst = host.get_mem(mem_addr);
-    let mem = mem_inst.memory();
-
-    // Safe as long as other threads (which don't even exist in WASM yet)
-    // aren't accessing memory. When threading exists, WASM will provide
-    // it's own synchronization primitives.
-    unsafe {
-        // Read the memory sequence in as a UTF-8 string
-        let s = std::str::from_utf8(&mem.data()[start..end]).unwrap();
-        println!("{}", s);
-    }
+    let mem_inst = match caller.memory(0) {
+        Some(mem_inst) => mem_inst,
+        None => return Err(TrapCause::NoMemory.into()),
+    };
+    let line = format!("{}\n", mem_inst.read_utf8(start, count)?);
+    caller
+        .host()
+        .write_stdout(line.as_bytes())
+        .map_err(|e| format!("Failed to print: {}", e))?;
+    Ok(Vec::new())
+}
 
+/// Suspends the calling thread for a number of milliseconds. See [`Thread::suspend`].
+fn sleep_ms(caller: &mut Caller, values: &[Value]) -> Result<Vec<Value>, Trap> {
+    let millis = u32::from_value(values[0])?;
+    let until = Instant::now() + Duration::from_millis(u64::from(millis));
+    caller.thread_mut().suspend(Suspend::Until(until));
     Ok(Vec::new())
 }
+
+/// Suspends the calling thread to let other work run. See [`Thread::suspend`].
+fn sched_yield(caller: &mut Caller, _values: &[Value]) -> Result<Vec<Value>, Trap> {
+    caller.thread_mut().suspend(Suspend::Yield);
+    Ok(Vec::new())
+}
+
+/// Ends the program with a status code. See [`TrapCause::Exit`].
+fn exit(_caller: &mut Caller, values: &[Value]) -> Result<Vec<Value>, Trap> {
+    Err(TrapCause::Exit(u32::from_value(values[0])?).into())
+}
+
+/// Aborts the program, which traps with the code emscripten passes.
+fn abort(_caller: &mut Caller, values: &[Value]) -> Result<Vec<Value>, Trap> {
+    let code = u32::from_value(values[0])?;
+    Err(format!("The program aborted with code {}", code).into())
+}
+
+fn abort_on_cannot_grow_memory(_caller: &mut Caller, values: &[Value]) -> Result<Vec<Value>, Trap> {
+    let size = u32::from_value(values[0])?;
+    Err(format!("Cannot grow memory to {} bytes", size).into())
+}
+
+/// Copies `num` bytes from `src` to `dest` in memory 0, producing `dest`. The ranges may
+/// overlap.
+fn emscripten_memcpy_big(caller: &mut Caller, values: &[Value]) -> Result<Vec<Value>, Trap> {
+    let (dest, src, num) = (
+        u32::from_value(values[0])?,
+        u32::from_value(values[1])? as usize,
+        u32::from_value(values[2])? as usize,
+    );
+    let mem_inst = match caller.memory(0) {
+        Some(mem_inst) => mem_inst,
+        None => return Err(TrapCause::NoMemory.into()),
+    };
+    let mut bytes = vec![0; num];
+    mem_inst.read(src, &mut bytes)?;
+    mem_inst.write(dest as usize, &bytes)?;
+    Ok(vec![Value::I32(dest)])
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        builder::{FuncBuilder, ModuleBuilder},
+        hosting::Host,
+        module::{GlobalType, Import, MemberDesc, MemoryType, TableType},
+        runtime::Env,
+        Error,
+        Instruction::*,
+        ValType, Value,
+    };
+
+    #[test]
+    pub fn emscripten_modules_instantiate() {
+        let mut host = Host::new();
+        host.external(Env::new()).unwrap();
+
+        // Emscripten imports the table and globals, and calls out to copy and abort
+        let mut module =
+            ModuleBuilder::new().import_mem("env", "memory", MemoryType::new(256, Some(256)));
+        let table = MemberDesc::Table(TableType::new(10, None));
+        module.imports.push(Import::new("env", "table", table));
+        let memory_base = MemberDesc::Global(GlobalType::new(ValType::I32, false));
+        module
+            .imports
+            .push(Import::new("env", "__memory_base", memory_base));
+        let module = module
+            .func(
+                FuncBuilder::new()
+                    .import_from("env", "emscripten_memcpy_big")
+                    .param(ValType::I32)
+                    .param(ValType::I32)
+                    .param(ValType::I32)
+                    .result(ValType::I32),
+            )
+            .func(
+                FuncBuilder::new()
+                    .import_from("env", "abort")
+                    .param(ValType::I32),
+            )
+            .func(
+                FuncBuilder::new()
+                    .export_as("copy")
+                    .result(ValType::I32)
+                    .body(vec![
+                        GlobalGet(0),
+                        I32Const(Value::I32(0x0102_0304)),
+                        I32Store(2, 0),
+                        I32Const(Value::I32(0)),
+                        GlobalGet(0),
+                        I32Const(Value::I32(4)),
+                        Call(0),
+                        I32Load(2, 0),
+                    ]),
+            )
+            .func(
+                FuncBuilder::new()
+                    .export_as("fail")
+                    .body(vec![I32Const(Value::I32(7)), Call(1)]),
+            );
+        let addr = host.instantiate("app", module.build()).unwrap();
+        assert_eq!(
+            vec![Value::I32(0x0102_0304)],
+            host.invoke(addr, "copy", &[]).unwrap()
+        );
+        match host.invoke(addr, "fail", &[]) {
+            Err(Error::Trap(trap)) => {
+                assert_eq!("The program aborted with code 7", trap.cause().message())
+            }
+            res => panic!("expected a trap, got {:?}", res),
+        }
+    }
+}
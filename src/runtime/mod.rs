@@ -1,5 +1,9 @@
 mod env;
 mod spectest;
+mod wasi;
 
 pub use self::env::Env;
 pub use self::spectest::SpecTest;
+pub use self::wasi::{
+    FileStat, Filesystem, HostFilesystem, MemoryFilesystem, OpenFlags, Wasi, WasiFile,
+};
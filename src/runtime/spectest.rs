@@ -1,24 +1,51 @@
 use std::sync::Arc;
 
 use crate::{
-    hosting::{ExternalFunc, ExternalMemory, ExternalModule, Host},
-    interp::Thread,
+    hosting::{
+        Caller, ExternalFunc, ExternalGlobal, ExternalMemory, ExternalModule, ExternalTable,
+    },
     module::FuncType,
     Trap, ValType, Value,
 };
 
+/// The `spectest` module the official test suite imports from, with the print functions,
+/// globals, table and memory it expects.
 pub struct SpecTest {
     funcs: Vec<Arc<ExternalFunc>>,
+    tables: Vec<ExternalTable>,
+    mems: Vec<ExternalMemory>,
+    globals: Vec<ExternalGlobal>,
 }
 
 impl SpecTest {
     pub fn new() -> SpecTest {
+        use crate::ValType::{F32, F64, I32, I64};
+
+        let prints: &[(&str, &[ValType])] = &[
+            ("print_i32", &[I32]),
+            ("print", &[]),
+            ("print_i64", &[I64]),
+            ("print_f32", &[F32]),
+            ("print_f64", &[F64]),
+            ("print_i32_f32", &[I32, F32]),
+            ("print_f64_f64", &[F64, F64]),
+        ];
         SpecTest {
-            funcs: vec![Arc::new(ExternalFunc::new(
-                "print_i32",
-                FuncType::new(vec![ValType::I32], vec![]),
-                print_i32,
-            ))],
+            funcs: prints
+                .iter()
+                .map(|&(name, params)| {
+                    let typ = FuncType::new(params.to_vec(), vec![]);
+                    Arc::new(ExternalFunc::new(name, typ, print))
+                })
+                .collect(),
+            tables: vec![ExternalTable::new("table", 10, Some(20))],
+            mems: vec![ExternalMemory::new("memory", 1, Some(2))],
+            globals: vec![
+                ExternalGlobal::new("global_i32", Value::I32(666), false),
+                ExternalGlobal::new("global_i64", Value::I64(666), false),
+                ExternalGlobal::new("global_f32", Value::F32(666.6), false),
+                ExternalGlobal::new("global_f64", Value::F64(666.6), false),
+            ],
         }
     }
 }
@@ -33,19 +60,78 @@ impl ExternalModule for SpecTest {
     }
 
     fn mems(&self) -> &[ExternalMemory] {
-        &[]
+        &self.mems
+    }
+
+    fn tables(&self) -> &[ExternalTable] {
+        &self.tables
+    }
+
+    fn globals(&self) -> &[ExternalGlobal] {
+        &self.globals
     }
 }
 
-fn print_i32(_host: &mut Host, _thread: &mut Thread, values: &[Value]) -> Result<Vec<Value>, Trap> {
-    let value = match values.iter().next() {
-        Some(Value::I32(v)) => v,
-        Some(v) => {
-            return Err(format!("Type mismatch, expected an i32 but found a {}", v.typ()).into())
-        }
-        None => return Err("Stack underflow!".into()),
+/// Prints each argument and its type on a line of its own. Arguments have already been checked
+/// against the function's type.
+fn print(caller: &mut Caller, values: &[Value]) -> Result<Vec<Value>, Trap> {
+    for value in values {
+        let line = format!("{} : {}\n", value, value.typ());
+        caller
+            .host()
+            .write_stdout(line.as_bytes())
+            .map_err(|e| format!("Failed to print: {}", e))?;
+    }
+    Ok(Vec::new())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        builder::{FuncBuilder, ModuleBuilder},
+        hosting::Host,
+        module::{GlobalType, Import, MemberDesc, MemoryType, TableType},
+        runtime::SpecTest,
+        Instruction::*,
+        ValType, Value,
     };
-    println!("{} : {}", value, ValType::I32);
 
-    Ok(Vec::new())
+    #[test]
+    pub fn spec_test_imports_link() {
+        let mut host = Host::new();
+        host.external(SpecTest::new()).unwrap();
+        let mut module =
+            ModuleBuilder::new().import_mem("spectest", "memory", MemoryType::new(1, Some(2)));
+        let table = MemberDesc::Table(TableType::new(10, Some(20)));
+        module.imports.push(Import::new("spectest", "table", table));
+        let global = MemberDesc::Global(GlobalType::new(ValType::I32, false));
+        module
+            .imports
+            .push(Import::new("spectest", "global_i32", global));
+        let module = module
+            .func(
+                FuncBuilder::new()
+                    .import_from("spectest", "print_f64_f64")
+                    .param(ValType::F64)
+                    .param(ValType::F64),
+            )
+            .func(
+                FuncBuilder::new()
+                    .export_as("main")
+                    .result(ValType::I32)
+                    .body(vec![
+                        F64Const(Value::F64(1.5)),
+                        F64Const(Value::F64(2.5)),
+                        Call(0),
+                        GlobalGet(0),
+                        MemorySize(0),
+                        I32Add,
+                    ]),
+            );
+        let addr = host.instantiate("test", module.build()).unwrap();
+        assert_eq!(
+            vec![Value::I32(667)],
+            host.invoke(addr, "main", &[]).unwrap()
+        );
+    }
 }
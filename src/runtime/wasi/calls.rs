@@ -0,0 +1,485 @@
+//! The WASI calls warthog supports. Each takes its parameters as they're passed to the import,
+//! and writes its results through the pointers among them.
+
+use std::{
+    collections::hash_map::RandomState,
+    convert::TryFrom,
+    fs,
+    hash::{BuildHasher, Hasher},
+    io::{self, Read, Seek, SeekFrom, Write},
+    path::{Component, Path, PathBuf},
+    sync::Arc,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use crate::{
+    hosting::{Caller, MemInst, WasmPtr},
+    runtime::wasi::{errno, fd::Descriptor, Failure, Filesystem, OpenFlags, WasiState},
+    FromValue, Trap, TrapCause, Value,
+};
+
+const FILETYPE_CHARACTER_DEVICE: u8 = 2;
+const FILETYPE_DIRECTORY: u8 = 3;
+const FILETYPE_REGULAR_FILE: u8 = 4;
+
+const OFLAGS_CREAT: u32 = 1;
+const OFLAGS_DIRECTORY: u32 = 2;
+const OFLAGS_EXCL: u32 = 4;
+const OFLAGS_TRUNC: u32 = 8;
+const FDFLAGS_APPEND: u32 = 1;
+const RIGHTS_FD_READ: u64 = 1 << 1;
+const RIGHTS_FD_WRITE: u64 = 1 << 6;
+
+/// Gets argument `idx` of a call as an `i32`.
+pub(crate) fn arg(values: &[Value], idx: usize) -> Result<u32, Trap> {
+    Ok(u32::from_value(values[idx])?)
+}
+
+fn arg64(values: &[Value], idx: usize) -> Result<u64, Trap> {
+    Ok(u64::from_value(values[idx])?)
+}
+
+fn memory(caller: &Caller) -> Result<Arc<MemInst>, Failure> {
+    caller.memory(0).ok_or_else(|| TrapCause::NoMemory.into())
+}
+
+/// Writes `strings` as NUL-terminated strings starting at `buf`, and a pointer to each into the
+/// array at `ptrs`.
+fn write_strings(mem: &MemInst, strings: &[String], ptrs: u32, buf: u32) -> Result<(), Failure> {
+    let mut ptr = WasmPtr::<u32>::new(ptrs);
+    let mut offset = WasmPtr::<u8>::new(buf);
+    for s in strings {
+        ptr.write(mem, offset.offset())?;
+        offset.write_array(mem, s.as_bytes())?;
+        let end = offset
+            .offset_by(s.len() as u32)
+            .ok_or(TrapCause::OutOfBoundsMemoryAccess)?;
+        end.write(mem, 0)?;
+        offset = end.offset_by(1).ok_or(TrapCause::OutOfBoundsMemoryAccess)?;
+        ptr = ptr.offset_by(1).ok_or(TrapCause::OutOfBoundsMemoryAccess)?;
+    }
+    Ok(())
+}
+
+/// Writes the number of `strings`, and the size of the buffer they need, to `count` and `size`.
+fn write_sizes(mem: &MemInst, strings: &[String], count: u32, size: u32) -> Result<(), Failure> {
+    let len: usize = strings.iter().map(|s| s.len() + 1).sum();
+    WasmPtr::<u32>::new(count).write(mem, strings.len() as u32)?;
+    WasmPtr::<u32>::new(size).write(mem, len as u32)?;
+    Ok(())
+}
+
+pub(crate) fn args_get(
+    state: &mut WasiState,
+    caller: &mut Caller,
+    values: &[Value],
+) -> Result<(), Failure> {
+    let mem = memory(caller)?;
+    write_strings(&mem, &state.args, arg(values, 0)?, arg(values, 1)?)
+}
+
+pub(crate) fn args_sizes_get(
+    state: &mut WasiState,
+    caller: &mut Caller,
+    values: &[Value],
+) -> Result<(), Failure> {
+    let mem = memory(caller)?;
+    write_sizes(&mem, &state.args, arg(values, 0)?, arg(values, 1)?)
+}
+
+pub(crate) fn environ_get(
+    state: &mut WasiState,
+    caller: &mut Caller,
+    values: &[Value],
+) -> Result<(), Failure> {
+    let mem = memory(caller)?;
+    write_strings(&mem, &state.env, arg(values, 0)?, arg(values, 1)?)
+}
+
+pub(crate) fn environ_sizes_get(
+    state: &mut WasiState,
+    caller: &mut Caller,
+    values: &[Value],
+) -> Result<(), Failure> {
+    let mem = memory(caller)?;
+    write_sizes(&mem, &state.env, arg(values, 0)?, arg(values, 1)?)
+}
+
+pub(crate) fn clock_res_get(
+    _: &mut WasiState,
+    caller: &mut Caller,
+    values: &[Value],
+) -> Result<(), Failure> {
+    let mem = memory(caller)?;
+    match arg(values, 0)? {
+        0..=3 => {
+            WasmPtr::<u64>::new(arg(values, 1)?).write(&mem, 1)?;
+            Ok(())
+        }
+        _ => Err(Failure::Errno(errno::INVAL)),
+    }
+}
+
+/// Reads one of the clocks, in nanoseconds. The realtime clock counts from the Unix epoch, and
+/// the others count from when the [`Wasi`](crate::runtime::Wasi) module was created.
+pub(crate) fn clock_time_get(
+    state: &mut WasiState,
+    caller: &mut Caller,
+    values: &[Value],
+) -> Result<(), Failure> {
+    let mem = memory(caller)?;
+    let time = match arg(values, 0)? {
+        0 => SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_err(|_| Failure::Errno(errno::IO))?,
+        1..=3 => state.start.elapsed(),
+        _ => return Err(Failure::Errno(errno::INVAL)),
+    };
+    let nanos = u64::try_from(time.as_nanos()).unwrap_or(u64::MAX);
+    WasmPtr::<u64>::new(arg(values, 2)?).write(&mem, nanos)?;
+    Ok(())
+}
+
+pub(crate) fn fd_close(
+    state: &mut WasiState,
+    _: &mut Caller,
+    values: &[Value],
+) -> Result<(), Failure> {
+    state.fds.remove(arg(values, 0)?)?;
+    Ok(())
+}
+
+pub(crate) fn fd_fdstat_get(
+    state: &mut WasiState,
+    caller: &mut Caller,
+    values: &[Value],
+) -> Result<(), Failure> {
+    let mem = memory(caller)?;
+    let filetype = match state.fds.get(arg(values, 0)?)? {
+        Descriptor::Stdin | Descriptor::Stdout | Descriptor::Stderr => FILETYPE_CHARACTER_DEVICE,
+        Descriptor::Dir { .. } => FILETYPE_DIRECTORY,
+        Descriptor::File(_) => FILETYPE_REGULAR_FILE,
+    };
+
+    // The filetype, no flags, and every right, both for the descriptor and ones opened from it
+    let stat = WasmPtr::<u8>::new(arg(values, 1)?);
+    stat.write_array(&mem, &[filetype, 0, 0, 0, 0, 0, 0, 0])?;
+    stat.cast::<u64>()
+        .offset_by(1)
+        .ok_or(TrapCause::OutOfBoundsMemoryAccess)?
+        .write_array(&mem, &[u64::MAX, u64::MAX])?;
+    Ok(())
+}
+
+pub(crate) fn fd_filestat_get(
+    state: &mut WasiState,
+    caller: &mut Caller,
+    values: &[Value],
+) -> Result<(), Failure> {
+    let mem = memory(caller)?;
+    let (filetype, size) = match state.fds.get(arg(values, 0)?)? {
+        Descriptor::Stdin | Descriptor::Stdout | Descriptor::Stderr => {
+            (FILETYPE_CHARACTER_DEVICE, 0)
+        }
+        Descriptor::Dir { path, .. } => (FILETYPE_DIRECTORY, state.filesystem.stat(path)?.size),
+        Descriptor::File(file) => (FILETYPE_REGULAR_FILE, file.size()?),
+    };
+
+    // The device, inode, filetype, link count, size and times, with only the filetype and size
+    // filled in
+    let stat = [0, 0, u64::from(filetype), 1, size, 0, 0, 0];
+    WasmPtr::<u64>::new(arg(values, 1)?).write_array(&mem, &stat)?;
+    Ok(())
+}
+
+pub(crate) fn fd_prestat_get(
+    state: &mut WasiState,
+    caller: &mut Caller,
+    values: &[Value],
+) -> Result<(), Failure> {
+    let mem = memory(caller)?;
+    match state.fds.get(arg(values, 0)?)? {
+        Descriptor::Dir {
+            preopen: Some(name),
+            ..
+        } => {
+            // A directory, and the length of its name
+            let prestat = WasmPtr::<u32>::new(arg(values, 1)?);
+            prestat.write_array(&mem, &[0, name.len() as u32])?;
+            Ok(())
+        }
+        _ => Err(Failure::Errno(errno::BADF)),
+    }
+}
+
+pub(crate) fn fd_prestat_dir_name(
+    state: &mut WasiState,
+    caller: &mut Caller,
+    values: &[Value],
+) -> Result<(), Failure> {
+    let mem = memory(caller)?;
+    match state.fds.get(arg(values, 0)?)? {
+        Descriptor::Dir {
+            preopen: Some(name),
+            ..
+        } => {
+            let len = (arg(values, 2)? as usize).min(name.len());
+            WasmPtr::<u8>::new(arg(values, 1)?).write_array(&mem, &name.as_bytes()[..len])?;
+            Ok(())
+        }
+        _ => Err(Failure::Errno(errno::BADF)),
+    }
+}
+
+/// Reads the buffers of the `len` I/O vectors at `iovs`, as offsets and lengths.
+fn iovecs(mem: &MemInst, iovs: u32, len: u32) -> Result<Vec<(u32, u32)>, Failure> {
+    let count = len
+        .checked_mul(2)
+        .ok_or(TrapCause::OutOfBoundsMemoryAccess)?;
+    let fields = WasmPtr::<u32>::new(iovs).read_array(mem, count)?;
+    Ok(fields.chunks(2).map(|iov| (iov[0], iov[1])).collect())
+}
+
+pub(crate) fn fd_read(
+    state: &mut WasiState,
+    caller: &mut Caller,
+    values: &[Value],
+) -> Result<(), Failure> {
+    let mem = memory(caller)?;
+    let desc = state.fds.get(arg(values, 0)?)?;
+    let mut total = 0;
+    for (buf, len) in iovecs(&mem, arg(values, 1)?, arg(values, 2)?)? {
+        let mut bytes = vec![0; len as usize];
+        let read = match desc {
+            Descriptor::Stdin => io::stdin().read(&mut bytes)?,
+            Descriptor::File(file) => file.read(&mut bytes)?,
+            Descriptor::Dir { .. } => return Err(Failure::Errno(errno::ISDIR)),
+            _ => return Err(Failure::Errno(errno::BADF)),
+        };
+        mem.write(buf as usize, &bytes[..read])?;
+        total += read as u32;
+        if read < bytes.len() {
+            break;
+        }
+    }
+    WasmPtr::<u32>::new(arg(values, 3)?).write(&mem, total)?;
+    Ok(())
+}
+
+pub(crate) fn fd_seek(
+    state: &mut WasiState,
+    caller: &mut Caller,
+    values: &[Value],
+) -> Result<(), Failure> {
+    let mem = memory(caller)?;
+    let offset = arg64(values, 1)? as i64;
+    let pos = match arg(values, 2)? {
+        0 => SeekFrom::Start(u64::try_from(offset).map_err(|_| Failure::Errno(errno::INVAL))?),
+        1 => SeekFrom::Current(offset),
+        2 => SeekFrom::End(offset),
+        _ => return Err(Failure::Errno(errno::INVAL)),
+    };
+    let pos = match state.fds.get(arg(values, 0)?)? {
+        Descriptor::File(file) => file.seek(pos)?,
+        Descriptor::Dir { .. } => return Err(Failure::Errno(errno::BADF)),
+        _ => return Err(Failure::Errno(errno::SPIPE)),
+    };
+    WasmPtr::<u64>::new(arg(values, 3)?).write(&mem, pos)?;
+    Ok(())
+}
+
+pub(crate) fn fd_write(
+    state: &mut WasiState,
+    caller: &mut Caller,
+    values: &[Value],
+) -> Result<(), Failure> {
+    let mem = memory(caller)?;
+    let desc = state.fds.get(arg(values, 0)?)?;
+    let mut total = 0;
+    for (buf, len) in iovecs(&mem, arg(values, 1)?, arg(values, 2)?)? {
+        let mut bytes = vec![0; len as usize];
+        mem.read(buf as usize, &mut bytes)?;
+        match desc {
+            Descriptor::Stdout => caller.host().write_stdout(&bytes)?,
+            Descriptor::Stderr => caller.host().write_stderr(&bytes)?,
+            Descriptor::File(file) => file.write_all(&bytes)?,
+            _ => return Err(Failure::Errno(errno::BADF)),
+        }
+        total += len;
+    }
+    WasmPtr::<u32>::new(arg(values, 3)?).write(&mem, total)?;
+    Ok(())
+}
+
+/// Resolves `name`, which the guest gave relative to the directory `dir`, to a path in the
+/// filesystem. It fails with `ENOTCAPABLE` if it's absolute, or leads out of `root`, the
+/// preopened directory `dir` is in.
+fn sandboxed(
+    filesystem: &dyn Filesystem,
+    root: &Path,
+    dir: &Path,
+    name: &str,
+) -> Result<PathBuf, Failure> {
+    let mut path = dir.to_path_buf();
+    let mut depth = dir.strip_prefix(root).map_or(0, |p| p.components().count());
+    for component in Path::new(name).components() {
+        match component {
+            Component::Normal(name) => {
+                path.push(name);
+                depth += 1;
+            }
+            Component::CurDir => {}
+            Component::ParentDir if depth > 0 => {
+                path.pop();
+                depth -= 1;
+            }
+            _ => return Err(Failure::Errno(errno::NOTCAPABLE)),
+        }
+    }
+
+    // Links may lead elsewhere, even when the path itself doesn't
+    if !filesystem
+        .real_path(&path)?
+        .starts_with(filesystem.real_path(root)?)
+    {
+        return Err(Failure::Errno(errno::NOTCAPABLE));
+    }
+    Ok(path)
+}
+
+/// Opens a file or directory relative to a directory the guest has open.
+pub(crate) fn path_open(
+    state: &mut WasiState,
+    caller: &mut Caller,
+    values: &[Value],
+) -> Result<(), Failure> {
+    let mem = memory(caller)?;
+    let (dir, root) = match state.fds.get(arg(values, 0)?)? {
+        Descriptor::Dir { path, root, .. } => (path.clone(), root.clone()),
+        _ => return Err(Failure::Errno(errno::NOTDIR)),
+    };
+    let name = WasmPtr::<u8>::new(arg(values, 2)?).read_utf8(&mem, arg(values, 3)?)?;
+    let path = sandboxed(&*state.filesystem, &root, &dir, &name)?;
+    let oflags = arg(values, 4)?;
+    let rights = arg64(values, 5)?;
+    let fdflags = arg(values, 7)?;
+
+    let stat = state.filesystem.stat(&path);
+    let is_dir = stat.as_ref().is_ok_and(|stat| stat.is_dir);
+    let desc = if oflags & OFLAGS_DIRECTORY != 0 || is_dir {
+        if !is_dir {
+            return Err(Failure::Errno(if stat.is_ok() {
+                errno::NOTDIR
+            } else {
+                errno::NOENT
+            }));
+        }
+        Descriptor::Dir {
+            path,
+            root,
+            preopen: None,
+        }
+    } else {
+        let write = rights & RIGHTS_FD_WRITE != 0;
+        let flags = OpenFlags {
+            read: rights & RIGHTS_FD_READ != 0 || !write,
+            write,
+            append: fdflags & FDFLAGS_APPEND != 0,
+            create: oflags & OFLAGS_CREAT != 0,
+            create_new: oflags & OFLAGS_CREAT != 0 && oflags & OFLAGS_EXCL != 0,
+            truncate: oflags & OFLAGS_TRUNC != 0,
+        };
+        Descriptor::File(state.filesystem.open(&path, flags)?)
+    };
+    let fd = state.fds.insert(desc);
+    WasmPtr::<u32>::new(arg(values, 8)?).write(&mem, fd)?;
+    Ok(())
+}
+
+pub(crate) fn random_get(
+    _: &mut WasiState,
+    caller: &mut Caller,
+    values: &[Value],
+) -> Result<(), Failure> {
+    let mem = memory(caller)?;
+    let mut bytes = vec![0; arg(values, 1)? as usize];
+    fill_random(&mut bytes);
+    mem.write(arg(values, 0)? as usize, &bytes)?;
+    Ok(())
+}
+
+/// Fills `buf` from the system's random number generator, or if it doesn't have one that can
+/// be read as a file, from hashes keyed by the standard library's per-process random seed.
+fn fill_random(buf: &mut [u8]) {
+    let read = fs::File::open("/dev/urandom").and_then(|mut f| f.read_exact(buf));
+    if read.is_ok() {
+        return;
+    }
+    let state = RandomState::new();
+    for (idx, chunk) in buf.chunks_mut(8).enumerate() {
+        let mut hasher = state.build_hasher();
+        hasher.write_usize(idx);
+        let len = chunk.len();
+        chunk.copy_from_slice(&hasher.finish().to_le_bytes()[..len]);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs, process};
+
+    use crate::{
+        hosting::{Host, WasmPtr},
+        runtime::{
+            wasi::tests::{call, guest},
+            Wasi,
+        },
+        ValType::{I32, I64},
+    };
+
+    #[test]
+    pub fn paths_stay_in_preopened_directories() {
+        let dir = env::temp_dir().join(format!("warthog-sandbox-{}", process::id()));
+        let data = dir.join("data");
+        fs::create_dir_all(data.join("sub")).unwrap();
+        fs::write(dir.join("secret.txt"), "secret").unwrap();
+        fs::write(data.join("file.txt"), "file").unwrap();
+        #[cfg(unix)]
+        std::os::unix::fs::symlink(dir.join("secret.txt"), data.join("link.txt")).unwrap();
+
+        let mut host = Host::new();
+        host.external(Wasi::new().preopen_dir(&data, "/data"))
+            .unwrap();
+        let addr = guest(
+            &mut host,
+            &[(
+                "path_open",
+                &[I32, I32, I32, I32, I32, I64, I64, I32, I32],
+                &[I32],
+            )],
+        );
+        let mem = host
+            .get_mem(host.get_module(addr).unwrap().export_mem("memory").unwrap())
+            .unwrap();
+        let mut open = |fd: u64, path: &str| {
+            mem.write(64, path.as_bytes()).unwrap();
+            let args = [fd, 0, 64, path.len() as u64, 0, 1 << 1, 0, 0, 0];
+            match call(&mut host, addr, "path_open", &args) {
+                0 => Ok(WasmPtr::<u32>::new(0).read(&mem).unwrap() as u64),
+                errno => Err(errno),
+            }
+        };
+
+        let sub = open(3, "sub").unwrap();
+        assert_eq!(Ok(5), open(sub, "../file.txt"));
+        assert_eq!(Ok(6), open(3, "./sub/../file.txt"));
+        assert_eq!(Err(76), open(3, "../secret.txt"));
+        assert_eq!(Err(76), open(sub, "../../secret.txt"));
+        assert_eq!(Err(76), open(3, "sub/../../secret.txt"));
+        let absolute = dir.join("secret.txt");
+        assert_eq!(Err(76), open(3, absolute.to_str().unwrap()));
+        #[cfg(unix)]
+        assert_eq!(Err(76), open(3, "link.txt"));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
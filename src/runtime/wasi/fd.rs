@@ -0,0 +1,65 @@
+use std::path::PathBuf;
+
+use crate::runtime::wasi::{errno, Failure, WasiFile};
+
+/// Something a WASI file descriptor refers to.
+pub(crate) enum Descriptor {
+    Stdin,
+    Stdout,
+    Stderr,
+    /// A directory in the filesystem, which paths can be opened relative to, as long as they
+    /// stay within `root`, the preopened directory it was opened from. Preopened directories have
+    /// the name the guest knows them by.
+    Dir {
+        path: PathBuf,
+        root: PathBuf,
+        preopen: Option<String>,
+    },
+    File(Box<dyn WasiFile>),
+}
+
+/// The file descriptors a guest has open, by number.
+pub(crate) struct FdTable {
+    fds: Vec<Option<Descriptor>>,
+}
+
+impl FdTable {
+    /// Creates a table with the standard streams open as 0, 1 and 2.
+    pub fn new() -> FdTable {
+        FdTable {
+            fds: vec![
+                Some(Descriptor::Stdin),
+                Some(Descriptor::Stdout),
+                Some(Descriptor::Stderr),
+            ],
+        }
+    }
+
+    pub fn get(&mut self, fd: u32) -> Result<&mut Descriptor, Failure> {
+        self.fds
+            .get_mut(fd as usize)
+            .and_then(Option::as_mut)
+            .ok_or(Failure::Errno(errno::BADF))
+    }
+
+    /// Opens `desc` as the lowest file descriptor that isn't in use.
+    pub fn insert(&mut self, desc: Descriptor) -> u32 {
+        match self.fds.iter().position(Option::is_none) {
+            Some(fd) => {
+                self.fds[fd] = Some(desc);
+                fd as u32
+            }
+            None => {
+                self.fds.push(Some(desc));
+                self.fds.len() as u32 - 1
+            }
+        }
+    }
+
+    pub fn remove(&mut self, fd: u32) -> Result<Descriptor, Failure> {
+        self.fds
+            .get_mut(fd as usize)
+            .and_then(Option::take)
+            .ok_or(Failure::Errno(errno::BADF))
+    }
+}
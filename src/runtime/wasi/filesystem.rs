@@ -0,0 +1,395 @@
+use std::{
+    collections::HashMap,
+    fs,
+    io::{self, Read, Seek, SeekFrom, Write},
+    path::{Component, Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+
+/// A file a guest has open, from a [`Filesystem`].
+pub trait WasiFile: Read + Write + Seek + Send {
+    /// Gets the size of the file, in bytes.
+    fn size(&self) -> io::Result<u64>;
+}
+
+/// How a file is opened. These correspond to the options of the same names on
+/// [`std::fs::OpenOptions`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct OpenFlags {
+    pub read: bool,
+    pub write: bool,
+    pub append: bool,
+    pub create: bool,
+    pub create_new: bool,
+    pub truncate: bool,
+}
+
+/// What a [`Filesystem`] knows about a file or directory.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct FileStat {
+    pub is_dir: bool,
+    /// The size of a file, in bytes.
+    pub size: u64,
+}
+
+/// The files a [`Wasi`](crate::runtime::Wasi) module gives guests access to. Paths are the
+/// ones of preopened directories, with the paths the guest opens relative to them joined on.
+///
+/// Errors are reported as [`io::Error`]s, and reach the guest as the error numbers for their
+/// kinds.
+pub trait Filesystem: Send + Sync {
+    /// Opens the file at `path`. Directories are never opened this way.
+    fn open(&self, path: &Path, flags: OpenFlags) -> io::Result<Box<dyn WasiFile>>;
+
+    fn stat(&self, path: &Path) -> io::Result<FileStat>;
+
+    /// Gets where `path` really leads once any links in it are followed, so guests can be kept
+    /// within their preopened directories. Some of the path may not exist yet, when a file is
+    /// about to be created. By default paths are taken to have no links.
+    fn real_path(&self, path: &Path) -> io::Result<PathBuf> {
+        Ok(path.to_path_buf())
+    }
+}
+
+impl WasiFile for fs::File {
+    fn size(&self) -> io::Result<u64> {
+        Ok(self.metadata()?.len())
+    }
+}
+
+/// The default [`Filesystem`], which is the real one of the host.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct HostFilesystem;
+
+impl Filesystem for HostFilesystem {
+    fn open(&self, path: &Path, flags: OpenFlags) -> io::Result<Box<dyn WasiFile>> {
+        let file = fs::OpenOptions::new()
+            .read(flags.read)
+            .write(flags.write)
+            .append(flags.append)
+            .create(flags.create)
+            .create_new(flags.create_new)
+            .truncate(flags.truncate)
+            .open(path)?;
+        Ok(Box::new(file))
+    }
+
+    fn stat(&self, path: &Path) -> io::Result<FileStat> {
+        let metadata = fs::metadata(path)?;
+        Ok(FileStat {
+            is_dir: metadata.is_dir(),
+            size: metadata.len(),
+        })
+    }
+
+    fn real_path(&self, path: &Path) -> io::Result<PathBuf> {
+        // Resolve as much of the path as exists, then add the names that don't
+        let mut existing = path;
+        let mut missing = Vec::new();
+        loop {
+            let dir = if existing.as_os_str().is_empty() {
+                Path::new(".")
+            } else {
+                existing
+            };
+            match fs::canonicalize(dir) {
+                Ok(mut real) => {
+                    real.extend(missing.iter().rev());
+                    return Ok(real);
+                }
+                // A link that leads nowhere can't be followed safely
+                Err(e) if fs::symlink_metadata(dir).is_ok() => return Err(e),
+                Err(e) => match (existing.parent(), existing.file_name()) {
+                    (Some(parent), Some(name)) => {
+                        missing.push(name);
+                        existing = parent;
+                    }
+                    _ => return Err(e),
+                },
+            }
+        }
+    }
+}
+
+enum Node {
+    Dir,
+    File(Arc<Mutex<Vec<u8>>>),
+}
+
+/// A [`Filesystem`] that only exists in memory, so guests can use files without touching the
+/// disk.
+///
+/// Paths are all relative to a single root, so `/data` and `data` are the same directory. The
+/// root always exists, and other directories are made with [`create_dir`]. Clones share their
+/// files, so a clone kept by the embedder can set up the files a guest reads and look at the
+/// ones it writes.
+///
+/// [`create_dir`]: MemoryFilesystem::create_dir
+#[derive(Clone, Default)]
+pub struct MemoryFilesystem {
+    nodes: Arc<Mutex<HashMap<PathBuf, Node>>>,
+}
+
+impl MemoryFilesystem {
+    pub fn new() -> MemoryFilesystem {
+        MemoryFilesystem::default()
+    }
+
+    /// Creates the directory at `path`, and any of its parents that don't exist yet.
+    pub fn create_dir<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let mut nodes = self.nodes.lock().unwrap();
+        let mut dir = PathBuf::new();
+        for name in normalize(path.as_ref()).iter() {
+            dir.push(name);
+            match nodes.get(&dir) {
+                Some(Node::Dir) => {}
+                Some(Node::File(_)) => return Err(io::ErrorKind::NotADirectory.into()),
+                None => {
+                    nodes.insert(dir.clone(), Node::Dir);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Creates or replaces the file at `path`, creating its directory if it doesn't exist.
+    pub fn write_file<P: AsRef<Path>, C: Into<Vec<u8>>>(
+        &self,
+        path: P,
+        contents: C,
+    ) -> io::Result<()> {
+        let path = normalize(path.as_ref());
+        let parent = path.parent().ok_or(io::ErrorKind::IsADirectory)?;
+        self.create_dir(parent)?;
+        let mut nodes = self.nodes.lock().unwrap();
+        match nodes.get(&path) {
+            Some(Node::Dir) => Err(io::ErrorKind::IsADirectory.into()),
+            _ => {
+                let contents = Arc::new(Mutex::new(contents.into()));
+                nodes.insert(path, Node::File(contents));
+                Ok(())
+            }
+        }
+    }
+
+    /// Gets the contents of the file at `path`, if there is one.
+    pub fn read_file<P: AsRef<Path>>(&self, path: P) -> Option<Vec<u8>> {
+        match self.nodes.lock().unwrap().get(&normalize(path.as_ref())) {
+            Some(Node::File(contents)) => Some(contents.lock().unwrap().clone()),
+            _ => None,
+        }
+    }
+}
+
+impl Filesystem for MemoryFilesystem {
+    fn open(&self, path: &Path, flags: OpenFlags) -> io::Result<Box<dyn WasiFile>> {
+        let path = normalize(path);
+        let mut nodes = self.nodes.lock().unwrap();
+        let contents = match nodes.get(&path) {
+            Some(Node::Dir) => return Err(io::ErrorKind::IsADirectory.into()),
+            Some(Node::File(_)) if flags.create_new => {
+                return Err(io::ErrorKind::AlreadyExists.into())
+            }
+            Some(Node::File(contents)) => {
+                if flags.truncate && flags.write {
+                    contents.lock().unwrap().clear();
+                }
+                contents.clone()
+            }
+            None if flags.create || flags.create_new => {
+                // Files are only created in directories that exist
+                let parent = path.parent().ok_or(io::ErrorKind::IsADirectory)?;
+                if !parent.as_os_str().is_empty() && !matches!(nodes.get(parent), Some(Node::Dir)) {
+                    return Err(io::ErrorKind::NotFound.into());
+                }
+                let contents = Arc::new(Mutex::new(Vec::new()));
+                nodes.insert(path, Node::File(contents.clone()));
+                contents
+            }
+            None => return Err(io::ErrorKind::NotFound.into()),
+        };
+        Ok(Box::new(MemoryFile {
+            contents,
+            pos: 0,
+            flags,
+        }))
+    }
+
+    fn stat(&self, path: &Path) -> io::Result<FileStat> {
+        let path = normalize(path);
+        match self.nodes.lock().unwrap().get(&path) {
+            Some(Node::File(contents)) => Ok(FileStat {
+                is_dir: false,
+                size: contents.lock().unwrap().len() as u64,
+            }),
+            Some(Node::Dir) => Ok(FileStat {
+                is_dir: true,
+                size: 0,
+            }),
+            None if path.as_os_str().is_empty() => Ok(FileStat {
+                is_dir: true,
+                size: 0,
+            }),
+            None => Err(io::ErrorKind::NotFound.into()),
+        }
+    }
+}
+
+/// Gets the names that lead from the root of a [`MemoryFilesystem`] to `path`.
+fn normalize(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::Normal(name) => normalized.push(name),
+            Component::ParentDir => {
+                normalized.pop();
+            }
+            _ => {}
+        }
+    }
+    normalized
+}
+
+/// A file open in a [`MemoryFilesystem`].
+struct MemoryFile {
+    contents: Arc<Mutex<Vec<u8>>>,
+    pos: u64,
+    flags: OpenFlags,
+}
+
+impl Read for MemoryFile {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if !self.flags.read {
+            return Err(io::ErrorKind::PermissionDenied.into());
+        }
+        let contents = self.contents.lock().unwrap();
+        let start = (self.pos as usize).min(contents.len());
+        let len = buf.len().min(contents.len() - start);
+        buf[..len].copy_from_slice(&contents[start..start + len]);
+        self.pos += len as u64;
+        Ok(len)
+    }
+}
+
+impl Write for MemoryFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if !self.flags.write {
+            return Err(io::ErrorKind::PermissionDenied.into());
+        }
+        let mut contents = self.contents.lock().unwrap();
+        if self.flags.append {
+            self.pos = contents.len() as u64;
+        }
+
+        // Writing past the end fills the gap with zeroes
+        let start = self.pos as usize;
+        let end = start + buf.len();
+        if contents.len() < end {
+            contents.resize(end, 0);
+        }
+        contents[start..end].copy_from_slice(buf);
+        self.pos = end as u64;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Seek for MemoryFile {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let (base, offset) = match pos {
+            SeekFrom::Start(offset) => (0, offset as i64),
+            SeekFrom::Current(offset) => (self.pos, offset),
+            SeekFrom::End(offset) => (self.contents.lock().unwrap().len() as u64, offset),
+        };
+        self.pos = base
+            .checked_add_signed(offset)
+            .ok_or(io::ErrorKind::InvalidInput)?;
+        Ok(self.pos)
+    }
+}
+
+impl WasiFile for MemoryFile {
+    fn size(&self) -> io::Result<u64> {
+        Ok(self.contents.lock().unwrap().len() as u64)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        hosting::{Host, WasmPtr},
+        runtime::{
+            wasi::tests::{call, guest},
+            MemoryFilesystem, Wasi,
+        },
+        ValType::{I32, I64},
+    };
+
+    #[test]
+    pub fn guests_use_files_in_memory() {
+        let files = MemoryFilesystem::new();
+        files.write_file("/data/in.txt", "hello").unwrap();
+        let wasi = Wasi::new()
+            .filesystem(files.clone())
+            .preopen_dir("/data", "/data");
+        let mut host = Host::new();
+        host.external(wasi).unwrap();
+        let addr = guest(
+            &mut host,
+            &[
+                (
+                    "path_open",
+                    &[I32, I32, I32, I32, I32, I64, I64, I32, I32],
+                    &[I32],
+                ),
+                ("fd_read", &[I32, I32, I32, I32], &[I32]),
+                ("fd_write", &[I32, I32, I32, I32], &[I32]),
+                ("fd_filestat_get", &[I32, I32], &[I32]),
+            ],
+        );
+        let mem = host
+            .get_mem(host.get_module(addr).unwrap().export_mem("memory").unwrap())
+            .unwrap();
+
+        // Read the file that's there
+        let (creat, read, write) = (1, 1 << 1, 1 << 6);
+        mem.write(64, b"in.txt").unwrap();
+        assert_eq!(
+            0,
+            call(
+                &mut host,
+                addr,
+                "path_open",
+                &[3, 0, 64, 6, 0, read, 0, 0, 0]
+            )
+        );
+        WasmPtr::<u32>::new(96)
+            .write_array(&mem, &[128, 16])
+            .unwrap();
+        assert_eq!(0, call(&mut host, addr, "fd_read", &[4, 96, 1, 0]));
+        assert_eq!(5, WasmPtr::<u32>::new(0).read(&mem).unwrap());
+        assert_eq!("hello", WasmPtr::<u8>::new(128).read_utf8(&mem, 5).unwrap());
+        // Writing needs the right to
+        assert_eq!(2, call(&mut host, addr, "fd_write", &[4, 96, 1, 0]));
+
+        // Create another, which the embedder sees
+        mem.write(64, b"out.txt").unwrap();
+        let args = [3, 0, 64, 7, creat, write, 0, 0, 0];
+        assert_eq!(0, call(&mut host, addr, "path_open", &args));
+        WasmPtr::<u32>::new(96)
+            .write_array(&mem, &[128, 5])
+            .unwrap();
+        assert_eq!(0, call(&mut host, addr, "fd_write", &[5, 96, 1, 0]));
+        assert_eq!(0, call(&mut host, addr, "fd_filestat_get", &[5, 0]));
+        assert_eq!(5, WasmPtr::<u64>::new(32).read(&mem).unwrap());
+        assert_eq!(Some(b"hello".to_vec()), files.read_file("data/out.txt"));
+
+        // Nothing is created in directories that don't exist
+        mem.write(64, b"no/out.txt").unwrap();
+        let args = [3, 0, 64, 10, creat, write, 0, 0, 0];
+        assert_eq!(44, call(&mut host, addr, "path_open", &args));
+    }
+}
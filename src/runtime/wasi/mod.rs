@@ -0,0 +1,416 @@
+//! An implementation of `wasi_snapshot_preview1`, the system interface programs compiled for
+//! the `wasm32-wasi` targets import, so they can run under warthog.
+//!
+//! Guests get their arguments and environment variables, the standard streams, clocks,
+//! random numbers and files in the directories the embedder preopens, from the real filesystem
+//! or one the embedder provides. The rest of the interface is present, so any WASI program
+//! links, but fails with `ENOSYS`.
+
+mod calls;
+mod fd;
+mod filesystem;
+
+use std::{
+    io,
+    path::PathBuf,
+    sync::{Arc, Mutex},
+    time::Instant,
+};
+
+use crate::{
+    hosting::{Caller, ExternalFunc, ExternalMemory, ExternalModule},
+    interp::Suspend,
+    module::FuncType,
+    runtime::wasi::fd::{Descriptor, FdTable},
+    Trap, TrapCause, ValType, Value,
+};
+
+pub use self::filesystem::{
+    FileStat, Filesystem, HostFilesystem, MemoryFilesystem, OpenFlags, WasiFile,
+};
+
+/// The error numbers WASI calls produce.
+pub(crate) mod errno {
+    pub const SUCCESS: u16 = 0;
+    pub const ACCES: u16 = 2;
+    pub const BADF: u16 = 8;
+    pub const EXIST: u16 = 20;
+    pub const INVAL: u16 = 28;
+    pub const IO: u16 = 29;
+    pub const ISDIR: u16 = 31;
+    pub const NOENT: u16 = 44;
+    pub const NOSYS: u16 = 52;
+    pub const NOTDIR: u16 = 54;
+    pub const SPIPE: u16 = 70;
+    pub const NOTCAPABLE: u16 = 76;
+}
+
+/// Why a WASI call failed: with an error number, which the guest handles, or with a trap.
+pub(crate) enum Failure {
+    Errno(u16),
+    Trap(Trap),
+}
+
+impl From<Trap> for Failure {
+    fn from(trap: Trap) -> Failure {
+        Failure::Trap(trap)
+    }
+}
+
+impl From<TrapCause> for Failure {
+    fn from(cause: TrapCause) -> Failure {
+        Failure::Trap(cause.into())
+    }
+}
+
+impl From<io::Error> for Failure {
+    fn from(e: io::Error) -> Failure {
+        Failure::Errno(match e.kind() {
+            io::ErrorKind::NotFound => errno::NOENT,
+            io::ErrorKind::PermissionDenied => errno::ACCES,
+            io::ErrorKind::AlreadyExists => errno::EXIST,
+            io::ErrorKind::InvalidInput => errno::INVAL,
+            io::ErrorKind::NotADirectory => errno::NOTDIR,
+            io::ErrorKind::IsADirectory => errno::ISDIR,
+            _ => errno::IO,
+        })
+    }
+}
+
+/// What a guest can see of the system through WASI.
+pub(crate) struct WasiState {
+    args: Vec<String>,
+    /// Environment variables, as `NAME=value`.
+    env: Vec<String>,
+    fds: FdTable,
+    filesystem: Arc<dyn Filesystem>,
+    /// When the monotonic clock started.
+    start: Instant,
+    exit_code: Option<u32>,
+}
+
+/// A WASI call that produces an error number.
+type Call = fn(&mut WasiState, &mut Caller, &[Value]) -> Result<(), Failure>;
+
+/// The `wasi_snapshot_preview1` module. Configure it, then add it to a host with
+/// [`Host::external`](crate::hosting::Host::external):
+///
+/// ```no_run
+/// # use warthog::{hosting::Host, runtime::Wasi};
+/// let wasi = Wasi::new().arg("app").env("HOME", "/").preopen_dir("data", "/data");
+/// let mut host = Host::new();
+/// host.external(wasi.clone()).unwrap();
+/// ```
+///
+/// Clones share their state, so a clone kept by the embedder reports the guest's
+/// [exit code](Wasi::exit_code).
+#[derive(Clone)]
+pub struct Wasi {
+    funcs: Vec<Arc<ExternalFunc>>,
+    state: Arc<Mutex<WasiState>>,
+}
+
+impl Wasi {
+    pub fn new() -> Wasi {
+        use crate::ValType::{I32, I64};
+
+        let state = Arc::new(Mutex::new(WasiState {
+            args: Vec::new(),
+            env: Vec::new(),
+            fds: FdTable::new(),
+            filesystem: Arc::new(HostFilesystem),
+            start: Instant::now(),
+            exit_code: None,
+        }));
+
+        let calls: &[(&str, &[ValType], Call)] = &[
+            ("args_get", &[I32, I32], calls::args_get),
+            ("args_sizes_get", &[I32, I32], calls::args_sizes_get),
+            ("environ_get", &[I32, I32], calls::environ_get),
+            ("environ_sizes_get", &[I32, I32], calls::environ_sizes_get),
+            ("clock_res_get", &[I32, I32], calls::clock_res_get),
+            ("clock_time_get", &[I32, I64, I32], calls::clock_time_get),
+            ("fd_close", &[I32], calls::fd_close),
+            ("fd_fdstat_get", &[I32, I32], calls::fd_fdstat_get),
+            ("fd_filestat_get", &[I32, I32], calls::fd_filestat_get),
+            ("fd_prestat_get", &[I32, I32], calls::fd_prestat_get),
+            (
+                "fd_prestat_dir_name",
+                &[I32, I32, I32],
+                calls::fd_prestat_dir_name,
+            ),
+            ("fd_read", &[I32, I32, I32, I32], calls::fd_read),
+            ("fd_seek", &[I32, I64, I32, I32], calls::fd_seek),
+            ("fd_write", &[I32, I32, I32, I32], calls::fd_write),
+            (
+                "path_open",
+                &[I32, I32, I32, I32, I32, I64, I64, I32, I32],
+                calls::path_open,
+            ),
+            ("random_get", &[I32, I32], calls::random_get),
+            ("sched_yield", &[], sched_yield),
+        ];
+        let mut funcs: Vec<_> = calls
+            .iter()
+            .map(|&(name, params, call)| {
+                let state = state.clone();
+                let typ = FuncType::new(params.to_vec(), vec![I32]);
+                Arc::new(ExternalFunc::new(name, typ, move |caller, values| {
+                    let mut state = state.lock().unwrap();
+                    let errno = match call(&mut state, caller, values) {
+                        Ok(()) => errno::SUCCESS,
+                        Err(Failure::Errno(errno)) => errno,
+                        Err(Failure::Trap(trap)) => return Err(trap),
+                    };
+                    Ok(vec![Value::I32(u32::from(errno))])
+                }))
+            })
+            .collect();
+        funcs.extend(UNSUPPORTED.iter().map(|&(name, params)| {
+            let typ = FuncType::new(params.to_vec(), vec![I32]);
+            Arc::new(ExternalFunc::new(name, typ, |_, _| {
+                Ok(vec![Value::I32(u32::from(errno::NOSYS))])
+            }))
+        }));
+
+        let exit_state = state.clone();
+        funcs.push(Arc::new(ExternalFunc::new(
+            "proc_exit",
+            FuncType::new(vec![I32], vec![]),
+            move |_, values| {
+                let code = calls::arg(values, 0)?;
+                exit_state.lock().unwrap().exit_code = Some(code);
+                Err(TrapCause::Exit(code).into())
+            },
+        )));
+
+        Wasi { funcs, state }
+    }
+
+    /// Adds an argument, starting with the program's name.
+    pub fn arg<S: Into<String>>(self, arg: S) -> Wasi {
+        self.state.lock().unwrap().args.push(arg.into());
+        self
+    }
+
+    pub fn args<I: IntoIterator<Item = S>, S: Into<String>>(self, args: I) -> Wasi {
+        self.state
+            .lock()
+            .unwrap()
+            .args
+            .extend(args.into_iter().map(Into::into));
+        self
+    }
+
+    /// Sets the environment variable `name` to `value`. The guest sees no variables other than
+    /// the ones set here.
+    pub fn env<S: AsRef<str>, T: AsRef<str>>(self, name: S, value: T) -> Wasi {
+        let var = format!("{}={}", name.as_ref(), value.as_ref());
+        self.state.lock().unwrap().env.push(var);
+        self
+    }
+
+    /// Sets the filesystem the guest's files are in. By default it's the host's own.
+    pub fn filesystem<F: Filesystem + 'static>(self, filesystem: F) -> Wasi {
+        self.state.lock().unwrap().filesystem = Arc::new(filesystem);
+        self
+    }
+
+    /// Lets the guest open files in the directory `path` of the filesystem, which it knows as
+    /// `name`. The guest can't open anything outside of it, whether by absolute paths, `..` or
+    /// links that lead elsewhere.
+    /// Directories are preopened as file descriptors 3 and up, in the order they're added.
+    pub fn preopen_dir<P: Into<PathBuf>, S: Into<String>>(self, path: P, name: S) -> Wasi {
+        let path = path.into();
+        self.state.lock().unwrap().fds.insert(Descriptor::Dir {
+            root: path.clone(),
+            path,
+            preopen: Some(name.into()),
+        });
+        self
+    }
+
+    /// Gets the code the guest passed to `proc_exit`, once it has exited.
+    pub fn exit_code(&self) -> Option<u32> {
+        self.state.lock().unwrap().exit_code
+    }
+}
+
+impl ExternalModule for Wasi {
+    fn name(&self) -> &str {
+        "wasi_snapshot_preview1"
+    }
+
+    fn funcs(&self) -> &[Arc<ExternalFunc>] {
+        &self.funcs
+    }
+
+    fn mems(&self) -> &[ExternalMemory] {
+        &[]
+    }
+}
+
+/// Suspends the calling thread to let other work run. See [`Thread::suspend`].
+///
+/// [`Thread::suspend`]: crate::interp::Thread::suspend
+fn sched_yield(_: &mut WasiState, caller: &mut Caller, _: &[Value]) -> Result<(), Failure> {
+    caller.thread_mut().suspend(Suspend::Yield);
+    Ok(())
+}
+
+/// The calls that fail with `ENOSYS`, with their parameters.
+const UNSUPPORTED: &[(&str, &[ValType])] = {
+    use crate::ValType::{I32, I64};
+    &[
+        ("fd_advise", &[I32, I64, I64, I32]),
+        ("fd_allocate", &[I32, I64, I64]),
+        ("fd_datasync", &[I32]),
+        ("fd_fdstat_set_flags", &[I32, I32]),
+        ("fd_fdstat_set_rights", &[I32, I64, I64]),
+        ("fd_filestat_set_size", &[I32, I64]),
+        ("fd_filestat_set_times", &[I32, I64, I64, I32]),
+        ("fd_pread", &[I32, I32, I32, I64, I32]),
+        ("fd_pwrite", &[I32, I32, I32, I64, I32]),
+        ("fd_readdir", &[I32, I32, I32, I64, I32]),
+        ("fd_renumber", &[I32, I32]),
+        ("fd_sync", &[I32]),
+        ("fd_tell", &[I32, I32]),
+        ("path_create_directory", &[I32, I32, I32]),
+        ("path_filestat_get", &[I32, I32, I32, I32, I32]),
+        (
+            "path_filestat_set_times",
+            &[I32, I32, I32, I32, I64, I64, I32],
+        ),
+        ("path_link", &[I32, I32, I32, I32, I32, I32, I32]),
+        ("path_readlink", &[I32, I32, I32, I32, I32, I32]),
+        ("path_remove_directory", &[I32, I32, I32]),
+        ("path_rename", &[I32, I32, I32, I32, I32, I32]),
+        ("path_symlink", &[I32, I32, I32, I32, I32]),
+        ("path_unlink_file", &[I32, I32, I32]),
+        ("poll_oneoff", &[I32, I32, I32, I32]),
+        ("proc_raise", &[I32]),
+        ("sock_accept", &[I32, I32, I32]),
+        ("sock_recv", &[I32, I32, I32, I32, I32, I32]),
+        ("sock_send", &[I32, I32, I32, I32, I32]),
+        ("sock_shutdown", &[I32, I32]),
+    ]
+};
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use std::{env, fs, process};
+
+    use crate::{
+        builder::{FuncBuilder, ModuleBuilder},
+        hosting::{Host, ModuleAddr, WasmPtr},
+        module::{Export, MemoryType},
+        runtime::Wasi,
+        Error,
+        ValType::{self, I32, I64},
+        Value,
+    };
+
+    /// Instantiates a guest that exports its memory and re-exports the WASI calls it imports, so
+    /// they can be called with pointers into its memory.
+    pub fn guest(host: &mut Host, calls: &[(&str, &[ValType], &[ValType])]) -> ModuleAddr {
+        let mut module = ModuleBuilder::new().mem("memory", MemoryType::new(1, None));
+        for &(name, params, results) in calls {
+            let mut func = FuncBuilder::new().import_from("wasi_snapshot_preview1", name);
+            for param in params {
+                func = func.param(*param);
+            }
+            for result in results {
+                func = func.result(*result);
+            }
+            let idx = module.add_func(func);
+            module.exports.push(Export::func(name, idx));
+        }
+        host.instantiate("guest", module.build()).unwrap()
+    }
+
+    /// Calls one of the WASI calls a [`guest`] re-exports, producing the error number.
+    pub fn call(host: &mut Host, addr: ModuleAddr, name: &str, args: &[u64]) -> u32 {
+        let params = host.func(host.module(addr).export_func(name).unwrap());
+        let args: Vec<_> = args
+            .iter()
+            .zip(params.typ().params())
+            .map(|(arg, typ)| match typ {
+                I64 => Value::I64(*arg),
+                _ => Value::I32(*arg as u32),
+            })
+            .collect();
+        match host.invoke(addr, name, &args).unwrap()[..] {
+            [Value::I32(errno)] => errno,
+            ref results => panic!("expected an error number, got {:?}", results),
+        }
+    }
+
+    #[test]
+    pub fn wasi_calls_reach_the_system() {
+        let dir = env::temp_dir().join(format!("warthog-wasi-{}", process::id()));
+        fs::create_dir_all(&dir).unwrap();
+
+        let wasi = Wasi::new()
+            .args(vec!["app", "-v"])
+            .preopen_dir(&dir, "/data");
+        let mut host = Host::new();
+        host.external(wasi.clone()).unwrap();
+        let addr = guest(
+            &mut host,
+            &[
+                ("args_sizes_get", &[I32, I32], &[I32]),
+                ("args_get", &[I32, I32], &[I32]),
+                ("fd_prestat_get", &[I32, I32], &[I32]),
+                (
+                    "path_open",
+                    &[I32, I32, I32, I32, I32, I64, I64, I32, I32],
+                    &[I32],
+                ),
+                ("fd_write", &[I32, I32, I32, I32], &[I32]),
+                ("fd_close", &[I32], &[I32]),
+                ("proc_exit", &[I32], &[]),
+            ],
+        );
+        let mem = host.mem(host.module(addr).export_mem("memory").unwrap());
+        let mut call = |name: &str, args: &[u64]| call(&mut host, addr, name, args);
+
+        assert_eq!(0, call("args_sizes_get", &[0, 4]));
+        assert_eq!(
+            vec![2, 7],
+            WasmPtr::<u32>::new(0).read_array(&mem, 2).unwrap()
+        );
+        assert_eq!(0, call("args_get", &[0, 16]));
+        let argv = WasmPtr::<u8>::new(WasmPtr::<u32>::new(4).read(&mem).unwrap());
+        assert_eq!("-v", argv.read_cstr(&mem).unwrap().to_str().unwrap());
+        assert_eq!(0, call("fd_prestat_get", &[3, 0]));
+        assert_eq!(
+            vec![0, 5],
+            WasmPtr::<u32>::new(0).read_array(&mem, 2).unwrap()
+        );
+        // Only preopened directories have a prestat
+        assert_eq!(8, call("fd_prestat_get", &[4, 0]));
+
+        // Create a file in the preopened directory, and write to it
+        mem.write(64, b"out.txt").unwrap();
+        let (creat, write) = (1, 1 << 6);
+        assert_eq!(0, call("path_open", &[3, 0, 64, 7, creat, write, 0, 0, 0]));
+        let fd = WasmPtr::<u32>::new(0).read(&mem).unwrap();
+        assert_eq!(4, fd);
+        mem.write(128, b"hello").unwrap();
+        WasmPtr::<u32>::new(96)
+            .write_array(&mem, &[128, 5])
+            .unwrap();
+        assert_eq!(0, call("fd_write", &[4, 96, 1, 0]));
+        assert_eq!(5, WasmPtr::<u32>::new(0).read(&mem).unwrap());
+        assert_eq!(0, call("fd_close", &[4]));
+        assert_eq!(8, call("fd_close", &[4]));
+        assert_eq!("hello", fs::read_to_string(dir.join("out.txt")).unwrap());
+
+        match host.invoke(addr, "proc_exit", &[Value::I32(3)]) {
+            Err(Error::Trap(trap)) => assert_eq!(Some(3), trap.exit_code()),
+            res => panic!("expected the guest to exit, got {:?}", res),
+        }
+        assert_eq!(Some(3), wasi.exit_code());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -41,7 +41,7 @@ impl<T> SparseVec<T> {
         match self.0.binary_search_by(|x| x.0.cmp(&index)) {
             Ok(idx) => &mut self.0[idx].1,
             Err(idx) => {
-                let mut item = factory(index);
+                let item = factory(index);
                 self.0.insert(idx, (index, item));
                 &mut self.0[idx].1
             }
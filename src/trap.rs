@@ -1,18 +1,102 @@
 use std::{borrow::Cow, cmp, fmt};
 
-use crate::{interp::StackTrace, ValType};
+use crate::{interp::StackTrace, ValType, Value};
 
 #[derive(Clone, PartialEq)]
 pub enum TrapCause {
+    Unreachable,
     IntegerOverflow,
     IntegerDivideByZero,
     InvalidConversionToInteger,
+    OutOfBoundsMemoryAccess,
+    UnalignedAtomic,
+    ExpectedSharedMemory,
+    UndefinedElement,
+    UninitializedElement,
+    IndirectCallTypeMismatch,
+    NullStructureReference,
+    NullArrayReference,
+    NullI31Reference,
+    OutOfBoundsArrayAccess,
     StackUnderflow,
     StackNotEmpty,
-    TypeMismatch { expected: ValType, actual: ValType },
+    StackExhausted,
+    UnexpectedNaN,
+    /// A thread that is metering execution ran out of fuel.
+    /// See [`Thread::set_fuel`](crate::interp::Thread::set_fuel).
+    OutOfFuel,
+    /// The thread was interrupted by its [`InterruptHandle`](crate::interp::InterruptHandle).
+    Interrupted,
+    /// A function was called from outside WebAssembly that isn't on its module's allow-list.
+    /// See [`Host::set_invokable`](crate::hosting::Host::set_invokable).
+    NotInvokable,
+    /// An array couldn't be allocated, because the host's
+    /// [`ResourceLimiter`](crate::hosting::ResourceLimiter) refused it or there isn't enough
+    /// memory for it.
+    AllocationFailed,
+    TypeMismatch {
+        expected: ValType,
+        actual: ValType,
+    },
+    /// An instruction refers to an item that doesn't exist. Validated code never does this.
+    UnknownIndex {
+        space: IndexSpace,
+        index: usize,
+    },
+    /// An instruction needs the memory of a module that doesn't have one.
+    NoMemory,
+    /// A function was called whose module's memory is in use by another thread, which is
+    /// running over a clone of the same host. Shared memories are never in use this way.
+    MemoryInUse,
+    ImmutableGlobal,
+    /// A function that has been stubbed to trap was called.
+    /// See [`Host::stub_func`](crate::hosting::Host::stub_func).
+    Stubbed {
+        name: String,
+    },
+    /// [`Thread::resume`](crate::interp::Thread::resume) or
+    /// [`Thread::step`](crate::interp::Thread::step) was called without a paused invocation.
+    NotPaused,
+    /// The interpreter doesn't implement the instruction.
+    Unimplemented,
+    /// The code being executed is malformed, such as a block without an `end`. Validated code
+    /// never does this.
+    InvalidCode,
+    /// The guest asked to exit with a status code, through a host function such as WASI's
+    /// `proc_exit`. It unwinds the guest like any other trap, but is how the program ends
+    /// rather than an error, so it isn't reported as a trap to the host's event sink.
+    Exit(u32),
+    /// A trap raised by the embedder, such as from an external function.
     Other(Cow<'static, str>),
 }
 
+/// The kinds of item an instruction can refer to by index.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum IndexSpace {
+    Local,
+    Global,
+    Function,
+    Table,
+    Label,
+    Type,
+    Field,
+}
+
+impl fmt::Display for IndexSpace {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let space = match self {
+            IndexSpace::Local => "local",
+            IndexSpace::Global => "global",
+            IndexSpace::Function => "function",
+            IndexSpace::Table => "table",
+            IndexSpace::Label => "label",
+            IndexSpace::Type => "type",
+            IndexSpace::Field => "field",
+        };
+        write!(f, "{}", space)
+    }
+}
+
 impl TrapCause {
     pub fn message<'a>(&'a self) -> Cow<'a, str> {
         use self::TrapCause::*;
@@ -20,16 +104,42 @@ impl TrapCause {
         match self {
             // Well-known traps return static strings
             // These strings are described by the spec tests. Do not modify them.
+            Unreachable => "unreachable".into(),
             IntegerOverflow => "integer overflow".into(),
             IntegerDivideByZero => "integer divide by zero".into(),
             InvalidConversionToInteger => "invalid conversion to integer".into(),
             StackNotEmpty => "stack not empty".into(),
+            OutOfBoundsMemoryAccess => "out of bounds memory access".into(),
+            UnalignedAtomic => "unaligned atomic".into(),
+            ExpectedSharedMemory => "expected shared memory".into(),
+            UndefinedElement => "undefined element".into(),
+            UninitializedElement => "uninitialized element".into(),
+            IndirectCallTypeMismatch => "indirect call type mismatch".into(),
+            NullStructureReference => "null structure reference".into(),
+            NullArrayReference => "null array reference".into(),
+            NullI31Reference => "null i31 reference".into(),
+            OutOfBoundsArrayAccess => "out of bounds array access".into(),
+            StackExhausted => "call stack exhausted".into(),
 
             // These are other well-known traps that we define
             StackUnderflow => "stack underflow".into(),
+            UnexpectedNaN => "unexpected NaN".into(),
+            OutOfFuel => "all fuel consumed".into(),
+            Interrupted => "interrupted".into(),
+            NotInvokable => "function is not invokable".into(),
+            AllocationFailed => "allocation failed".into(),
             TypeMismatch { expected, actual } => {
                 format!("type mismatch (expected: {}, actual {})", expected, actual).into()
             }
+            UnknownIndex { space, index } => format!("No such {}: {}", space, index).into(),
+            NoMemory => "The current module has no memory.".into(),
+            MemoryInUse => "The module's memory is in use by another thread.".into(),
+            ImmutableGlobal => "Cannot set an immutable global.".into(),
+            Stubbed { name } => format!("Function '{}' is stubbed.", name).into(),
+            NotPaused => "No invocation is paused.".into(),
+            Unimplemented => "instruction not implemented".into(),
+            InvalidCode => "invalid code".into(),
+            Exit(code) => format!("exited with code {}", code).into(),
 
             // If the content is static, we can just return the reference,
             // because 'static will always outlive any 'a
@@ -63,17 +173,36 @@ impl cmp::PartialEq<str> for TrapCause {
 #[derive(Clone, PartialEq)]
 pub struct Trap {
     cause: TrapCause,
+    message: Option<Cow<'static, str>>,
     stack_trace: Option<StackTrace>,
+    // Boxed, since most traps are never examined this closely
+    frame_state: Option<Box<FrameState>>,
+    address: Option<u64>,
 }
 
 impl Trap {
     pub fn new<C: Into<TrapCause>>(cause: C) -> Trap {
         Trap {
             cause: cause.into(),
+            message: None,
             stack_trace: None,
+            frame_state: None,
+            address: None,
         }
     }
 
+    /// Describes the trap in more detail than its cause does.
+    pub fn with_message<S: Into<Cow<'static, str>>>(mut self, message: S) -> Trap {
+        self.message = Some(message.into());
+        self
+    }
+
+    /// Records the memory address that the trapping instruction tried to access.
+    pub fn with_address(mut self, address: u64) -> Trap {
+        self.address = Some(address);
+        self
+    }
+
     /// Sets the stack trace associated with this trap, but **only** if it hasn't already been set.
     ///
     /// ## Returns
@@ -91,9 +220,73 @@ impl Trap {
         &self.cause
     }
 
+    /// Gets the status code the guest exited with, if it exited rather than trapped. See
+    /// [`TrapCause::Exit`].
+    pub fn exit_code(&self) -> Option<u32> {
+        match self.cause {
+            TrapCause::Exit(code) => Some(code),
+            _ => None,
+        }
+    }
+
+    /// Gets the detailed description of the trap, or the message of its cause if it has none.
+    pub fn message(&self) -> Cow<'_, str> {
+        match &self.message {
+            Some(message) => Cow::Borrowed(message),
+            None => self.cause.message(),
+        }
+    }
+
     pub fn trace(&self) -> Option<&StackTrace> {
         self.stack_trace.as_ref()
     }
+
+    /// Sets the state of the frame that trapped, but **only** if it hasn't already been set.
+    ///
+    /// ## Returns
+    /// A boolean indicating if the frame state was set.
+    pub fn try_set_frame_state(&mut self, state: FrameState) -> bool {
+        if self.frame_state.is_some() {
+            false
+        } else {
+            self.frame_state = Some(Box::new(state));
+            true
+        }
+    }
+
+    /// Gets the operands and locals of the innermost frame at the time of the trap.
+    pub fn frame_state(&self) -> Option<&FrameState> {
+        self.frame_state.as_deref()
+    }
+
+    /// Gets the memory address the trapping instruction tried to access, if it was a memory
+    /// access.
+    pub fn address(&self) -> Option<u64> {
+        self.address
+    }
+}
+
+/// The operands and locals of the frame a trap occurred in, kept so they can be examined after
+/// the stack has unwound. Operands consumed by the trapping instruction are not included.
+#[derive(Clone, PartialEq)]
+pub struct FrameState {
+    operands: Vec<Value>,
+    locals: Vec<Value>,
+}
+
+impl FrameState {
+    pub fn new(operands: Vec<Value>, locals: Vec<Value>) -> FrameState {
+        FrameState { operands, locals }
+    }
+
+    /// Gets the operand stack, from the bottom of the stack to the top.
+    pub fn operands(&self) -> &[Value] {
+        &self.operands
+    }
+
+    pub fn locals(&self) -> &[Value] {
+        &self.locals
+    }
 }
 
 impl<C: Into<TrapCause>> From<C> for Trap {
@@ -102,9 +295,30 @@ impl<C: Into<TrapCause>> From<C> for Trap {
     }
 }
 
+/// The number of frames at each end of a stack trace that a trap displays. The frames between
+/// them are elided, so a trap that exhausted the stack doesn't print thousands of them.
+const DISPLAYED_FRAMES: usize = 10;
+
 impl fmt::Display for Trap {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "{}", self.cause)
+        write!(f, "{}", self.message())?;
+        if let Some(trace) = &self.stack_trace {
+            let frames: Vec<_> = trace
+                .frames()
+                .iter()
+                .filter(|frame| frame.func().is_some())
+                .collect();
+            let elided = frames.len().saturating_sub(2 * DISPLAYED_FRAMES);
+            for (i, frame) in frames.iter().enumerate() {
+                if elided > 0 && i == DISPLAYED_FRAMES {
+                    write!(f, "\n    ... {} frames elided", elided)?;
+                }
+                if elided == 0 || i < DISPLAYED_FRAMES || i >= DISPLAYED_FRAMES + elided {
+                    write!(f, "\n    at {}+{}", frame, frame.offset())?;
+                }
+            }
+        }
+        Ok(())
     }
 }
 
@@ -113,3 +327,114 @@ impl fmt::Debug for Trap {
         fmt::Display::fmt(self, f)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    };
+
+    use crate::{
+        builder::{FuncBuilder, ModuleBuilder},
+        hosting::{Event, EventSink, Host},
+        interp::Thread,
+        runtime::Env,
+        IndexSpace, Instruction, Trap, TrapCause, ValType, Value,
+    };
+
+    #[test]
+    pub fn traps_can_be_matched_by_cause() {
+        let mut host = Host::new();
+        let module = ModuleBuilder::new().func(
+            FuncBuilder::new()
+                .export_as("test")
+                .result(ValType::I32)
+                .body(vec![Instruction::GlobalGet(3)]),
+        );
+        let addr = host.instantiate("test", module.build()).unwrap();
+        let func = host.get_module(addr).unwrap().export_func("test").unwrap();
+
+        let trap = Thread::new().invoke(&mut host, func).unwrap_err();
+        assert!(matches!(
+            trap.cause(),
+            TrapCause::UnknownIndex {
+                space: IndexSpace::Global,
+                index: 3
+            }
+        ));
+        assert_eq!("No such global: 3", trap.message());
+
+        // A message describes the trap in more detail than its cause
+        let trap = Trap::new(TrapCause::InvalidCode).with_message("Block has no matching 'end'.");
+        assert_eq!("invalid code", trap.cause().message());
+        assert_eq!("Block has no matching 'end'.", trap.to_string());
+    }
+
+    #[test]
+    pub fn exits_are_not_reported_as_traps() {
+        struct Traps(AtomicUsize);
+
+        impl EventSink for Arc<Traps> {
+            fn event(&self, event: &Event) {
+                if let Event::Trapped { .. } = event {
+                    self.0.fetch_add(1, Ordering::SeqCst);
+                }
+            }
+        }
+
+        let traps = Arc::new(Traps(AtomicUsize::new(0)));
+        let mut host = Host::new();
+        host.set_event_sink(traps.clone());
+        host.external(Env::new()).unwrap();
+        let module = ModuleBuilder::new()
+            .func(
+                FuncBuilder::new()
+                    .import_from("env", "exit")
+                    .param(ValType::I32),
+            )
+            .func(FuncBuilder::new().export_as("main").body(vec![
+                Instruction::I32Const(Value::I32(3)),
+                Instruction::Call(0),
+                Instruction::Unreachable,
+            ]));
+        let addr = host.instantiate("test", module.build()).unwrap();
+        let func = host.get_module(addr).unwrap().export_func("main").unwrap();
+
+        let trap = Thread::new().invoke(&mut host, func).unwrap_err();
+        assert_eq!(Some(3), trap.exit_code());
+        assert_eq!("exited with code 3", trap.message());
+        assert_eq!(0, traps.0.load(Ordering::SeqCst));
+    }
+
+    #[test]
+    pub fn deep_stacks_are_displayed_by_their_ends() {
+        let mut host = Host::new();
+        let module = ModuleBuilder::new().func(
+            FuncBuilder::new()
+                .export_as("recurse")
+                .body(vec![Instruction::Call(0)]),
+        );
+        let addr = host.instantiate("test", module.build()).unwrap();
+        let func = host
+            .get_module(addr)
+            .unwrap()
+            .export_func("recurse")
+            .unwrap();
+
+        let trap = Thread::new().invoke(&mut host, func).unwrap_err();
+        assert!(matches!(trap.cause(), TrapCause::StackExhausted));
+        let trace = trap.trace().unwrap();
+        let frames = trace.frames().iter().filter(|f| f.func().is_some()).count();
+        assert!(frames > 20);
+
+        let display = trap.to_string();
+        let lines: Vec<_> = display.lines().collect();
+        assert_eq!(22, lines.len());
+        assert_eq!(format!("    ... {} frames elided", frames - 20), lines[11]);
+        assert!(lines[1..11]
+            .iter()
+            .chain(&lines[12..])
+            .all(|l| l.starts_with("    at ")));
+    }
+}
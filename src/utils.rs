@@ -1,51 +1,146 @@
-use std::io;
+use std::{io, mem};
 
 use byteorder::ReadBytesExt;
 
-use crate::Error;
+use crate::{reader::SectionRead, Error, SectionOffset};
 
-pub trait FromLeb128 {
-    fn from_leb128_u(leb: u64) -> Self;
-    fn from_leb128_s(leb: i64) -> Self;
+/// Reads an unsigned LEB128 integer that fits in 32 bits.
+pub fn read_leb128_u32<R: io::Read>(r: &mut R) -> Result<u32, Error> {
+    Ok(read_unsigned(r, 32)? as u32)
 }
 
-macro_rules! impl_from_leb {
-    ($target: ty) => {
-        impl FromLeb128 for $target {
-            fn from_leb128_u(leb: u64) -> $target {
-                leb as $target
-            }
+/// Reads an unsigned LEB128 integer that fits in 64 bits.
+pub fn read_leb128_u64<R: io::Read>(r: &mut R) -> Result<u64, Error> {
+    read_unsigned(r, 64)
+}
+
+/// Reads a signed LEB128 integer that fits in 32 bits.
+pub fn read_leb128_s32<R: io::Read>(r: &mut R) -> Result<i32, Error> {
+    Ok(read_signed(r, 32)? as i32)
+}
+
+/// Reads a signed LEB128 integer that fits in 64 bits.
+pub fn read_leb128_s64<R: io::Read>(r: &mut R) -> Result<i64, Error> {
+    read_signed(r, 64)
+}
 
-            fn from_leb128_s(leb: i64) -> $target {
-                leb as $target
+/// Reads a signed LEB128 integer that fits in 33 bits, which is how heap types encode a type
+/// index alongside the negative single-byte abstract types.
+#[cfg(feature = "gc")]
+pub fn read_leb128_s33<R: io::Read>(r: &mut R) -> Result<i64, Error> {
+    read_signed(r, 33)
+}
+
+/// Reads an unsigned LEB128 integer of at most `bits` bits, which takes at most `ceil(bits / 7)`
+/// bytes. The unused bits of the last byte must be zero.
+fn read_unsigned<R: io::Read>(r: &mut R, bits: u32) -> Result<u64, Error> {
+    let mut result = 0;
+    let mut shift = 0;
+    loop {
+        let byte = r.read_u8()?;
+        let value = (byte & 0x7F) as u64;
+        if shift + 7 >= bits {
+            if byte & 0x80 != 0 {
+                return Err(Error::IntegerTooLong {
+                    at: SectionOffset::unknown(),
+                });
+            }
+            if value >> (bits - shift) != 0 {
+                return Err(Error::MalformedInteger {
+                    at: SectionOffset::unknown(),
+                });
             }
+            return Ok(result | value << shift);
         }
-    };
+
+        result |= value << shift;
+        if byte & 0x80 == 0 {
+            return Ok(result);
+        }
+        shift += 7;
+    }
 }
 
-impl_from_leb!(usize);
-impl_from_leb!(isize);
-impl_from_leb!(u32);
-impl_from_leb!(u64);
-impl_from_leb!(i32);
-impl_from_leb!(i64);
+/// Reads a signed LEB128 integer of at most `bits` bits, which takes at most `ceil(bits / 7)`
+/// bytes. The unused bits of the last byte must all match the sign bit.
+fn read_signed<R: io::Read>(r: &mut R, bits: u32) -> Result<i64, Error> {
+    let mut result = 0;
+    let mut shift = 0;
+    loop {
+        let byte = r.read_u8()?;
+        let value = (byte & 0x7F) as i64;
+        let last = shift + 7 >= bits;
+        if last {
+            if byte & 0x80 != 0 {
+                return Err(Error::IntegerTooLong {
+                    at: SectionOffset::unknown(),
+                });
+            }
 
-pub fn read_leb128_s<R: io::Read, T: FromLeb128>(r: &mut R) -> Result<T, Error> {
-    Ok(T::from_leb128_s(leb128::read::signed(r)?))
-}
+            // The sign bit and the unused bits above it
+            let used = bits - shift;
+            let high = (0x7F >> (used - 1)) << (used - 1);
+            if value & high != 0 && value & high != high {
+                return Err(Error::MalformedInteger {
+                    at: SectionOffset::unknown(),
+                });
+            }
+        }
 
-pub fn read_leb128_u32<R: io::Read>(r: &mut R) -> Result<u32, Error> {
-    Ok(leb128::read::unsigned(r)? as u32)
+        result |= value << shift;
+        shift += 7;
+        if last || byte & 0x80 == 0 {
+            // Sign-extend from the last bit read
+            return Ok(if shift < 64 {
+                result << (64 - shift) >> (64 - shift)
+            } else {
+                result
+            });
+        }
+    }
 }
 
-pub fn read_vec<R, F, I>(r: &mut R, mut body: F) -> Result<Vec<I>, Error>
+/// The most memory, in bytes, that [`read_vec`] reserves up front for the items a vector declares.
+/// Larger vectors grow as their items are read, so declaring a huge count can't make the reader
+/// allocate much more than the module's own size before it runs out of bytes.
+const MAX_PREALLOCATION: usize = 64 * 1024;
+
+pub fn read_vec<R, F, I>(r: &mut R, body: F) -> Result<Vec<I>, Error>
 where
     R: io::Read,
     F: FnMut(&mut R) -> Result<I, Error>,
 {
-    // Read the size and pre-allocate the Vec
     let size = read_leb128_u32(r)?;
-    let mut vec = Vec::with_capacity(size as usize);
+    read_items(r, size, body)
+}
+
+/// Reads a vector like [`read_vec`] from the contents of a section, whose items each take at
+/// least `min_size` bytes. A count the rest of the section couldn't hold fails with
+/// [`Error::CountTooLarge`] before any items are read.
+pub fn read_section_vec<R, F, I>(r: &mut R, min_size: usize, body: F) -> Result<Vec<I>, Error>
+where
+    R: SectionRead,
+    F: FnMut(&mut R) -> Result<I, Error>,
+{
+    let count = read_leb128_u32(r)?;
+    let remaining = r.remaining();
+    if count as u64 * min_size as u64 > remaining as u64 {
+        return Err(Error::CountTooLarge {
+            count,
+            remaining,
+            at: SectionOffset::unknown(),
+        });
+    }
+    read_items(r, count, body)
+}
+
+fn read_items<R, F, I>(r: &mut R, size: u32, mut body: F) -> Result<Vec<I>, Error>
+where
+    F: FnMut(&mut R) -> Result<I, Error>,
+{
+    // Pre-allocate the Vec, up to a limit
+    let limit = MAX_PREALLOCATION / mem::size_of::<I>().max(1);
+    let mut vec = Vec::with_capacity((size as usize).min(limit));
 
     // Read the items
     for _ in 0..size {
@@ -56,7 +151,9 @@ where
 
 pub fn read_name<R: io::Read>(r: &mut R) -> Result<String, Error> {
     let byts: Vec<u8> = read_vec(r, |x| Ok(x.read_u8()?))?;
-    Ok(String::from_utf8(byts)?)
+    String::from_utf8(byts).map_err(|_| Error::InvalidUtf8 {
+        at: SectionOffset::unknown(),
+    })
 }
 
 pub fn read_limits<R: io::Read>(r: &mut R) -> Result<(usize, Option<usize>), Error> {
@@ -69,6 +166,94 @@ pub fn read_limits<R: io::Read>(r: &mut R) -> Result<(usize, Option<usize>), Err
             let max = read_leb128_u32(r)? as usize;
             Ok((min, Some(max)))
         }
-        _ => Err(Error::InvalidModule),
+        flags => Err(Error::InvalidLimits {
+            flags,
+            at: SectionOffset::unknown(),
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+
+    fn read<T, F>(read: F, bytes: &[u8]) -> Result<T, Error>
+    where
+        F: Fn(&mut Cursor<Vec<u8>>) -> Result<T, Error>,
+    {
+        let mut cursor = Cursor::new(bytes.to_vec());
+        let value = read(&mut cursor)?;
+        assert_eq!(bytes.len() as u64, cursor.position(), "trailing bytes");
+        Ok(value)
+    }
+
+    fn too_long<T>(r: Result<T, Error>) -> bool {
+        matches!(r, Err(Error::IntegerTooLong { .. }))
+    }
+
+    fn too_large<T>(r: Result<T, Error>) -> bool {
+        matches!(r, Err(Error::MalformedInteger { .. }))
+    }
+
+    /// `count` bytes of `fill`, followed by `last`.
+    fn encoded(fill: u8, count: usize, last: u8) -> Vec<u8> {
+        let mut bytes = vec![fill; count];
+        bytes.push(last);
+        bytes
+    }
+
+    #[test]
+    pub fn leb128_is_read_strictly() {
+        // Padding is allowed up to the longest encoding of the type
+        assert_eq!(
+            3,
+            read(read_leb128_u32, &[0x83, 0x80, 0x80, 0x80, 0x00]).unwrap()
+        );
+        assert_eq!(
+            u32::MAX,
+            read(read_leb128_u32, &encoded(0xFF, 4, 0x0F)).unwrap()
+        );
+        assert!(too_long(read(read_leb128_u32, &encoded(0x80, 5, 0x00))));
+        assert!(too_large(read(read_leb128_u32, &encoded(0xFF, 4, 0x1F))));
+        assert_eq!(
+            u64::MAX,
+            read(read_leb128_u64, &encoded(0xFF, 9, 0x01)).unwrap()
+        );
+        assert!(too_large(read(read_leb128_u64, &encoded(0xFF, 9, 0x03))));
+
+        // The unused bits of a signed integer's last byte must match its sign
+        assert_eq!(-1, read(read_leb128_s32, &[0x7F]).unwrap());
+        assert_eq!(-1, read(read_leb128_s32, &encoded(0xFF, 4, 0x7F)).unwrap());
+        assert_eq!(
+            i32::MIN,
+            read(read_leb128_s32, &encoded(0x80, 4, 0x78)).unwrap()
+        );
+        assert_eq!(
+            i32::MAX,
+            read(read_leb128_s32, &encoded(0xFF, 4, 0x07)).unwrap()
+        );
+        assert!(too_large(read(read_leb128_s32, &encoded(0xFF, 4, 0x4F))));
+        assert!(too_large(read(read_leb128_s32, &encoded(0x80, 4, 0x70))));
+        assert!(too_long(read(read_leb128_s32, &encoded(0xFF, 5, 0x7F))));
+        assert_eq!(
+            i64::MIN,
+            read(read_leb128_s64, &encoded(0x80, 9, 0x7F)).unwrap()
+        );
+        assert!(too_large(read(read_leb128_s64, &encoded(0x80, 9, 0x7E))));
+
+        #[cfg(feature = "gc")]
+        {
+            let max = encoded(0xFF, 4, 0x0F);
+            assert_eq!(u32::MAX as i64, read(read_leb128_s33, &max).unwrap());
+            assert!(too_large(read(read_leb128_s33, &encoded(0xFF, 4, 0x1F))));
+        }
+
+        // Running out of bytes is an I/O error
+        assert!(matches!(
+            read(read_leb128_u32, &[0x80]),
+            Err(Error::IoError(_))
+        ));
     }
 }
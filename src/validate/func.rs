@@ -0,0 +1,775 @@
+use crate::{
+    module::{FuncBody, FuncType, GlobalType, MemoryType},
+    reader::SectionId,
+    validate::{address_type, Context},
+    Error, Instruction,
+    ValType::{self, F32, F64, I32, I64, V128},
+};
+
+/// Checks the body of the function with index `func`, following the validation algorithm in
+/// the appendix of the WebAssembly specification.
+pub fn validate(ctx: &Context, func: usize, body: &FuncBody) -> Result<(), Error> {
+    let typ = ctx.funcs[func];
+    let mut validator = FuncValidator {
+        ctx,
+        func,
+        position: None,
+        locals: typ.params().iter().chain(body.locals()).cloned().collect(),
+        operands: Vec::new(),
+        frames: vec![Frame {
+            kind: FrameKind::Func,
+            results: typ.results().to_vec(),
+            height: 0,
+            unreachable: false,
+        }],
+    };
+    if validator.locals.contains(&ValType::Nil) {
+        return Err(validator.error("invalid local type"));
+    }
+
+    let insts = body
+        .try_body()
+        .map_err(|e| e.in_section(SectionId::Code, None))?;
+    for (i, inst) in insts.iter().enumerate() {
+        validator.position = Some(i);
+        if validator.frames.is_empty() {
+            return Err(validator.error("operators remaining after end of function"));
+        }
+        validator.check(inst)?;
+    }
+
+    // The final `end` isn't part of the body, so close the function's frame here, unless the
+    // body closed it itself.
+    validator.position = Some(insts.len());
+    match validator.frames.len() {
+        0 => Ok(()),
+        1 => validator.pop_frame().map(|_| ()),
+        _ => Err(validator.error("unexpected end of function")),
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum FrameKind {
+    Func,
+    Block,
+    Loop,
+    If,
+    Else,
+}
+
+/// A block whose label can be branched to.
+struct Frame {
+    kind: FrameKind,
+    results: Vec<ValType>,
+    /// The height of the operand stack when the block was entered.
+    height: usize,
+    /// Set once the rest of the block can't be reached, after which the operand stack below
+    /// `height` behaves as if it held values of any type.
+    unreachable: bool,
+}
+
+impl Frame {
+    /// Gets the types of the values carried by a branch to this frame's label.
+    fn label_types(&self) -> &[ValType] {
+        if self.kind == FrameKind::Loop {
+            &[]
+        } else {
+            &self.results
+        }
+    }
+}
+
+struct FuncValidator<'a> {
+    ctx: &'a Context<'a>,
+    func: usize,
+    position: Option<usize>,
+    locals: Vec<ValType>,
+    /// The types on the operand stack, where `None` is a value of unknown type produced in
+    /// unreachable code.
+    operands: Vec<Option<ValType>>,
+    frames: Vec<Frame>,
+}
+
+impl<'a> FuncValidator<'a> {
+    fn check(&mut self, inst: &Instruction) -> Result<(), Error> {
+        use crate::Instruction::*;
+
+        match inst {
+            Unreachable => self.set_unreachable(),
+            Nop => {}
+            Block(typ) => self.push_frame(FrameKind::Block, *typ),
+            Loop(typ) => self.push_frame(FrameKind::Loop, *typ),
+            If(typ) => {
+                self.pop_expect(I32)?;
+                self.push_frame(FrameKind::If, *typ);
+            }
+            Else => {
+                let frame = self.pop_frame()?;
+                if frame.kind != FrameKind::If {
+                    return Err(self.error("else without matching if"));
+                }
+                self.frames.push(Frame {
+                    kind: FrameKind::Else,
+                    results: frame.results,
+                    height: self.operands.len(),
+                    unreachable: false,
+                });
+            }
+            End => {
+                let frame = self.pop_frame()?;
+                // Without an else, the results would have to come from nowhere
+                if frame.kind == FrameKind::If && !frame.results.is_empty() {
+                    return Err(self.error("type mismatch"));
+                }
+                self.push_all(&frame.results);
+            }
+            Br(depth) => {
+                let types = self.label_types(*depth)?;
+                self.pop_all(&types)?;
+                self.set_unreachable();
+            }
+            BrIf(depth) => {
+                self.pop_expect(I32)?;
+                let types = self.label_types(*depth)?;
+                self.pop_all(&types)?;
+                self.push_all(&types);
+            }
+            BrTable(table) => {
+                self.pop_expect(I32)?;
+                let default = self.label_types(table.default_target())?;
+                for target in table.targets() {
+                    let types = self.label_types(*target)?;
+                    if types.len() != default.len() {
+                        return Err(self.error("type mismatch"));
+                    }
+                    let values = self.pop_all(&types)?;
+                    self.operands.extend(values);
+                }
+                self.pop_all(&default)?;
+                self.set_unreachable();
+            }
+            Return => {
+                let results = self.frames[0].results.clone();
+                self.pop_all(&results)?;
+                self.set_unreachable();
+            }
+            Call(func) => {
+                let typ = self.func_type(*func)?;
+                self.pop_all(typ.params())?;
+                self.push_all(typ.results());
+            }
+            CallIndirect(type_id, table) => {
+                let typ = self.indirect_type(*type_id, *table)?;
+                self.pop_expect(I32)?;
+                self.pop_all(typ.params())?;
+                self.push_all(typ.results());
+            }
+            ReturnCall(func) => {
+                let typ = self.func_type(*func)?;
+                self.check_tail_call(typ)?;
+            }
+            ReturnCallIndirect(type_id, table) => {
+                let typ = self.indirect_type(*type_id, *table)?;
+                self.pop_expect(I32)?;
+                self.check_tail_call(typ)?;
+            }
+            Drop => {
+                self.pop()?;
+            }
+            Select => {
+                self.pop_expect(I32)?;
+                let first = self.pop()?;
+                let second = self.pop()?;
+                if let (Some(first), Some(second)) = (first, second) {
+                    if first != second {
+                        return Err(self.error("type mismatch"));
+                    }
+                }
+                // References need the typed form of select, which isn't supported
+                #[cfg(feature = "gc")]
+                {
+                    if let Some(ValType::Ref(_)) = first.or(second) {
+                        return Err(self.error("type mismatch"));
+                    }
+                }
+                self.operands.push(first.or(second));
+            }
+            LocalGet(idx) => {
+                let typ = self.local(*idx)?;
+                self.push(typ);
+            }
+            LocalSet(idx) => {
+                let typ = self.local(*idx)?;
+                self.pop_expect(typ)?;
+            }
+            LocalTee(idx) => {
+                let typ = self.local(*idx)?;
+                self.pop_expect(typ)?;
+                self.push(typ);
+            }
+            GlobalGet(idx) => {
+                let typ = self.global(*idx)?.typ();
+                self.push(typ);
+            }
+            GlobalSet(idx) => {
+                let global = self.global(*idx)?;
+                if !global.mutable() {
+                    return Err(self.error("global is immutable"));
+                }
+                self.pop_expect(global.typ())?;
+            }
+            MemorySize(idx) => {
+                let addr = address_type(self.memory(*idx)?);
+                self.push(addr);
+            }
+            MemoryGrow(idx) => {
+                let addr = address_type(self.memory(*idx)?);
+                self.pop_expect(addr)?;
+                self.push(addr);
+            }
+            I32Const(v) | I64Const(v) | F32Const(v) | F64Const(v) | V128Const(v) => {
+                self.push(v.typ())
+            }
+            I8x16ExtractLaneS(lane) | I8x16ExtractLaneU(lane) | I8x16ReplaceLane(lane) => {
+                self.check_lane(inst, *lane, 16)?
+            }
+            I16x8ExtractLaneS(lane) | I16x8ExtractLaneU(lane) | I16x8ReplaceLane(lane) => {
+                self.check_lane(inst, *lane, 8)?
+            }
+            I32x4ExtractLane(lane) | I32x4ReplaceLane(lane) => self.check_lane(inst, *lane, 4)?,
+            I64x2ExtractLane(lane) | I64x2ReplaceLane(lane) => self.check_lane(inst, *lane, 2)?,
+            F32x4ExtractLane(lane) | F32x4ReplaceLane(lane) => self.check_lane(inst, *lane, 4)?,
+            F64x2ExtractLane(lane) | F64x2ReplaceLane(lane) => self.check_lane(inst, *lane, 2)?,
+            AtomicFence(_) => {}
+            #[cfg(feature = "gc")]
+            RefNull(_) | RefIsNull | RefEq => self.check_gc(inst)?,
+            #[cfg(feature = "gc")]
+            x if x.prefix() == Some(0xFB) => self.check_gc(x)?,
+            x => match x.memarg() {
+                Some((align, _)) => self.check_memory_access(x, align)?,
+                None => self.check_operator(x)?,
+            },
+        }
+
+        Ok(())
+    }
+
+    /// Checks an instruction with a fixed signature, from the tables in `signature`.
+    fn check_operator(&mut self, inst: &Instruction) -> Result<(), Error> {
+        match signature(inst.prefix(), inst.opcode()) {
+            Some((params, result)) => {
+                self.pop_all(params)?;
+                self.push(result);
+                Ok(())
+            }
+            None => Err(self.error(format!("unsupported instruction {}", inst))),
+        }
+    }
+
+    fn check_lane(&mut self, inst: &Instruction, lane: u8, lanes: u8) -> Result<(), Error> {
+        if lane >= lanes {
+            return Err(self.error("invalid lane index"));
+        }
+        self.check_operator(inst)
+    }
+
+    fn check_memory_access(&mut self, inst: &Instruction, align: u32) -> Result<(), Error> {
+        let (size, operands, result) = match memory_signature(inst.prefix(), inst.opcode()) {
+            Some(signature) => signature,
+            None => return Err(self.error(format!("unsupported instruction {}", inst))),
+        };
+        let addr = address_type(self.memory(0)?);
+
+        // Alignment is encoded as a power of two
+        let natural = size.trailing_zeros();
+        if inst.prefix() == Some(0xFE) {
+            if align != natural {
+                return Err(self.error("alignment must be equal to natural"));
+            }
+        } else if align > natural {
+            return Err(self.error("alignment must not be larger than natural"));
+        }
+
+        self.pop_all(operands)?;
+        self.pop_expect(addr)?;
+        if let Some(result) = result {
+            self.push(result);
+        }
+        Ok(())
+    }
+
+    /// Checks a return call, whose callee must produce the results of the current function.
+    fn check_tail_call(&mut self, typ: &FuncType) -> Result<(), Error> {
+        let results = &self.frames[0].results;
+        let matches = results.len() == typ.results().len()
+            && results
+                .iter()
+                .zip(typ.results())
+                .all(|(expected, actual)| expected.accepts(*actual));
+        if !matches {
+            return Err(self.error("type mismatch"));
+        }
+
+        self.pop_all(typ.params())?;
+        self.set_unreachable();
+        Ok(())
+    }
+
+    #[cfg(feature = "gc")]
+    fn check_gc(&mut self, inst: &Instruction) -> Result<(), Error> {
+        use crate::{
+            module::{StorageType, TypeDef},
+            value::{HeapType, RefType},
+            Instruction::*,
+        };
+
+        let reference = |heap| ValType::Ref(RefType::new(true, heap));
+        let object = |type_idx| ValType::Ref(RefType::new(false, HeapType::Type(type_idx)));
+        let defaultable = |storage: StorageType| match storage.unpacked() {
+            ValType::Ref(r) => r.nullable(),
+            _ => true,
+        };
+        let packed = |storage: StorageType| storage != StorageType::Val(storage.unpacked());
+
+        let types = self.ctx.module.types();
+        let struct_type = |idx: u32| match types.get(idx as usize) {
+            Some(TypeDef::Struct(typ)) => Some(typ),
+            _ => None,
+        };
+        let array_type = |idx: u32| match types.get(idx as usize) {
+            Some(TypeDef::Array(typ)) => Some(typ),
+            _ => None,
+        };
+
+        match inst {
+            RefNull(heap) => {
+                if let HeapType::Type(idx) = heap {
+                    if *idx as usize >= types.len() {
+                        return Err(self.error(format!("unknown type {}", idx)));
+                    }
+                }
+                self.push(reference(*heap));
+            }
+            RefIsNull => {
+                match self.pop()? {
+                    Some(ValType::Ref(_)) | None => {}
+                    Some(_) => return Err(self.error("type mismatch")),
+                }
+                self.push(I32);
+            }
+            RefEq => {
+                self.pop_all(&[reference(HeapType::Eq), reference(HeapType::Eq)])?;
+                self.push(I32);
+            }
+
+            StructNew(idx) | StructNewDefault(idx) => {
+                let typ = match struct_type(*idx) {
+                    Some(typ) => typ,
+                    None => return Err(self.error(format!("unknown struct type {}", idx))),
+                };
+                if let StructNew(_) = inst {
+                    let fields: Vec<_> = typ
+                        .fields()
+                        .iter()
+                        .map(|f| f.storage().unpacked())
+                        .collect();
+                    self.pop_all(&fields)?;
+                } else if !typ.fields().iter().all(|f| defaultable(f.storage())) {
+                    return Err(self.error("type is not defaultable"));
+                }
+                self.push(object(*idx));
+            }
+            StructGet(idx, field)
+            | StructGetS(idx, field)
+            | StructGetU(idx, field)
+            | StructSet(idx, field) => {
+                let field = match struct_type(*idx).and_then(|t| t.fields().get(*field as usize)) {
+                    Some(field) => *field,
+                    None => return Err(self.error(format!("unknown field {}", field))),
+                };
+                let storage = field.storage();
+                match inst {
+                    StructSet(..) => {
+                        if !field.mutable() {
+                            return Err(self.error("field is immutable"));
+                        }
+                        self.pop_expect(storage.unpacked())?;
+                        self.pop_expect(reference(HeapType::Type(*idx)))?;
+                    }
+                    _ => {
+                        if packed(storage) == matches!(inst, StructGet(..)) {
+                            return Err(self.error("type mismatch"));
+                        }
+                        self.pop_expect(reference(HeapType::Type(*idx)))?;
+                        self.push(storage.unpacked());
+                    }
+                }
+            }
+
+            ArrayNew(idx)
+            | ArrayNewDefault(idx)
+            | ArrayNewFixed(idx, _)
+            | ArrayGet(idx)
+            | ArrayGetS(idx)
+            | ArrayGetU(idx)
+            | ArraySet(idx) => {
+                let element = match array_type(*idx) {
+                    Some(typ) => typ.element(),
+                    None => return Err(self.error(format!("unknown array type {}", idx))),
+                };
+                let storage = element.storage();
+                match inst {
+                    ArrayNew(_) => {
+                        self.pop_all(&[storage.unpacked(), I32])?;
+                        self.push(object(*idx));
+                    }
+                    ArrayNewDefault(_) => {
+                        if !defaultable(storage) {
+                            return Err(self.error("type is not defaultable"));
+                        }
+                        self.pop_expect(I32)?;
+                        self.push(object(*idx));
+                    }
+                    ArrayNewFixed(_, len) => {
+                        for _ in 0..*len {
+                            self.pop_expect(storage.unpacked())?;
+                        }
+                        self.push(object(*idx));
+                    }
+                    ArraySet(_) => {
+                        if !element.mutable() {
+                            return Err(self.error("array is immutable"));
+                        }
+                        let array = reference(HeapType::Type(*idx));
+                        self.pop_all(&[array, I32, storage.unpacked()])?;
+                    }
+                    _ => {
+                        if packed(storage) == matches!(inst, ArrayGet(_)) {
+                            return Err(self.error("type mismatch"));
+                        }
+                        self.pop_all(&[reference(HeapType::Type(*idx)), I32])?;
+                        self.push(storage.unpacked());
+                    }
+                }
+            }
+            ArrayLen => {
+                self.pop_expect(reference(HeapType::Array))?;
+                self.push(I32);
+            }
+
+            RefI31 => {
+                self.pop_expect(I32)?;
+                self.push(ValType::Ref(RefType::new(false, HeapType::I31)));
+            }
+            I31GetS | I31GetU => {
+                self.pop_expect(reference(HeapType::I31))?;
+                self.push(I32);
+            }
+
+            x => return Err(self.error(format!("unsupported instruction {}", x))),
+        }
+        Ok(())
+    }
+
+    fn push(&mut self, typ: ValType) {
+        self.operands.push(Some(typ));
+    }
+
+    fn push_all(&mut self, types: &[ValType]) {
+        self.operands.extend(types.iter().map(|t| Some(*t)));
+    }
+
+    /// Pops an operand, which is of unknown type if it's conjured up by unreachable code.
+    fn pop(&mut self) -> Result<Option<ValType>, Error> {
+        let frame = self.frames.last().expect("There should be an open frame!");
+        if self.operands.len() == frame.height {
+            if frame.unreachable {
+                return Ok(None);
+            }
+            return Err(self.error("type mismatch"));
+        }
+        Ok(self.operands.pop().unwrap())
+    }
+
+    fn pop_expect(&mut self, expected: ValType) -> Result<Option<ValType>, Error> {
+        match self.pop()? {
+            Some(actual) if !expected.accepts(actual) => Err(self.error("type mismatch")),
+            actual => Ok(actual),
+        }
+    }
+
+    /// Pops operands matching `types`, returning them in stack order.
+    fn pop_all(&mut self, types: &[ValType]) -> Result<Vec<Option<ValType>>, Error> {
+        let mut values = Vec::with_capacity(types.len());
+        for typ in types.iter().rev() {
+            values.push(self.pop_expect(*typ)?);
+        }
+        values.reverse();
+        Ok(values)
+    }
+
+    fn push_frame(&mut self, kind: FrameKind, typ: ValType) {
+        let results = match typ {
+            ValType::Nil => Vec::new(),
+            typ => vec![typ],
+        };
+        self.frames.push(Frame {
+            kind,
+            results,
+            height: self.operands.len(),
+            unreachable: false,
+        });
+    }
+
+    /// Closes the innermost frame, whose results must be all that's left of its operands.
+    fn pop_frame(&mut self) -> Result<Frame, Error> {
+        let results = match self.frames.last() {
+            Some(frame) => frame.results.clone(),
+            None => return Err(self.error("unexpected end")),
+        };
+        self.pop_all(&results)?;
+        let frame = self.frames.pop().unwrap();
+        if self.operands.len() != frame.height {
+            return Err(self.error("type mismatch"));
+        }
+        Ok(frame)
+    }
+
+    fn set_unreachable(&mut self) {
+        let frame = self
+            .frames
+            .last_mut()
+            .expect("There should be an open frame!");
+        self.operands.truncate(frame.height);
+        frame.unreachable = true;
+    }
+
+    fn label_types(&self, depth: u32) -> Result<Vec<ValType>, Error> {
+        match self.frames.len().checked_sub(depth as usize + 1) {
+            Some(idx) => Ok(self.frames[idx].label_types().to_vec()),
+            None => Err(self.error(format!("unknown label {}", depth))),
+        }
+    }
+
+    fn local(&self, idx: u32) -> Result<ValType, Error> {
+        match self.locals.get(idx as usize) {
+            Some(typ) => Ok(*typ),
+            None => Err(self.error(format!("unknown local {}", idx))),
+        }
+    }
+
+    fn global(&self, idx: u32) -> Result<&'a GlobalType, Error> {
+        match self.ctx.globals.get(idx as usize) {
+            Some(global) => Ok(*global),
+            None => Err(self.error(format!("unknown global {}", idx))),
+        }
+    }
+
+    fn memory(&self, idx: u32) -> Result<&'a MemoryType, Error> {
+        match self.ctx.mems.get(idx as usize) {
+            Some(mem) => Ok(*mem),
+            None => Err(self.error(format!("unknown memory {}", idx))),
+        }
+    }
+
+    fn func_type(&self, idx: u32) -> Result<&'a FuncType, Error> {
+        match self.ctx.funcs.get(idx as usize) {
+            Some(typ) => Ok(*typ),
+            None => Err(self.error(format!("unknown function {}", idx))),
+        }
+    }
+
+    /// Looks up the signature of a `call_indirect` through `table`.
+    fn indirect_type(&self, type_id: u32, table: u32) -> Result<&'a FuncType, Error> {
+        if table as usize >= self.ctx.tables.len() {
+            return Err(self.error(format!("unknown table {}", table)));
+        }
+        match self.ctx.module.func_type(type_id as usize) {
+            Some(typ) => Ok(typ),
+            None => Err(self.error(format!("unknown type {}", type_id))),
+        }
+    }
+
+    fn error<S: Into<String>>(&self, message: S) -> Error {
+        Error::ValidationFailed {
+            func: self.func,
+            instruction: self.position,
+            message: message.into(),
+        }
+    }
+}
+
+/// The operands and result of an instruction.
+type Signature = (&'static [ValType], ValType);
+
+/// Gets the signature of a numeric or vector instruction that doesn't access memory.
+fn signature(prefix: Option<u8>, opcode: u32) -> Option<Signature> {
+    let signature: Signature = match (prefix, opcode) {
+        (None, 0x45) | (None, 0x67..=0x69) => (&[I32], I32),
+        (None, 0x46..=0x4F) | (None, 0x6A..=0x78) => (&[I32, I32], I32),
+        (None, 0x50) | (None, 0xA7) => (&[I64], I32),
+        (None, 0x51..=0x5A) => (&[I64, I64], I32),
+        (None, 0x5B..=0x60) => (&[F32, F32], I32),
+        (None, 0x61..=0x66) => (&[F64, F64], I32),
+        (None, 0x79..=0x7B) => (&[I64], I64),
+        (None, 0x7C..=0x8A) => (&[I64, I64], I64),
+        (None, 0x8B..=0x91) => (&[F32], F32),
+        (None, 0x92..=0x98) => (&[F32, F32], F32),
+        (None, 0x99..=0x9F) => (&[F64], F64),
+        (None, 0xA0..=0xA6) => (&[F64, F64], F64),
+
+        // Conversions
+        (None, 0xA8) | (None, 0xA9) | (None, 0xBC) => (&[F32], I32),
+        (None, 0xAA) | (None, 0xAB) => (&[F64], I32),
+        (None, 0xAC) | (None, 0xAD) => (&[I32], I64),
+        (None, 0xAE) | (None, 0xAF) => (&[F32], I64),
+        (None, 0xB0) | (None, 0xB1) | (None, 0xBD) => (&[F64], I64),
+        (None, 0xB2) | (None, 0xB3) | (None, 0xBE) => (&[I32], F32),
+        (None, 0xB4) | (None, 0xB5) => (&[I64], F32),
+        (None, 0xB6) => (&[F64], F32),
+        (None, 0xB7) | (None, 0xB8) => (&[I32], F64),
+        (None, 0xB9) | (None, 0xBA) | (None, 0xBF) => (&[I64], F64),
+        (None, 0xBB) => (&[F32], F64),
+
+        // Splats and lanes
+        (Some(0xFD), 0x0F..=0x11) => (&[I32], V128),
+        (Some(0xFD), 0x12) => (&[I64], V128),
+        (Some(0xFD), 0x13) => (&[F32], V128),
+        (Some(0xFD), 0x14) => (&[F64], V128),
+        (Some(0xFD), 0x15)
+        | (Some(0xFD), 0x16)
+        | (Some(0xFD), 0x18)
+        | (Some(0xFD), 0x19)
+        | (Some(0xFD), 0x1B) => (&[V128], I32),
+        (Some(0xFD), 0x1D) => (&[V128], I64),
+        (Some(0xFD), 0x1F) => (&[V128], F32),
+        (Some(0xFD), 0x21) => (&[V128], F64),
+        (Some(0xFD), 0x17) | (Some(0xFD), 0x1A) | (Some(0xFD), 0x1C) => (&[V128, I32], V128),
+        (Some(0xFD), 0x1E) => (&[V128, I64], V128),
+        (Some(0xFD), 0x20) => (&[V128, F32], V128),
+        (Some(0xFD), 0x22) => (&[V128, F64], V128),
+
+        // Tests, bitmasks and shifts
+        (Some(0xFD), 0x53)
+        | (Some(0xFD), 0x63)
+        | (Some(0xFD), 0x64)
+        | (Some(0xFD), 0x83)
+        | (Some(0xFD), 0x84)
+        | (Some(0xFD), 0xA3)
+        | (Some(0xFD), 0xA4)
+        | (Some(0xFD), 0xC3)
+        | (Some(0xFD), 0xC4) => (&[V128], I32),
+        (Some(0xFD), 0x6B..=0x6D)
+        | (Some(0xFD), 0x8B..=0x8D)
+        | (Some(0xFD), 0xAB..=0xAD)
+        | (Some(0xFD), 0xCB..=0xCD) => (&[V128, I32], V128),
+
+        // Vector arithmetic
+        (Some(0xFD), 0x4D)
+        | (Some(0xFD), 0x60..=0x62)
+        | (Some(0xFD), 0x80)
+        | (Some(0xFD), 0x81)
+        | (Some(0xFD), 0xA0)
+        | (Some(0xFD), 0xA1)
+        | (Some(0xFD), 0xC0)
+        | (Some(0xFD), 0xC1)
+        | (Some(0xFD), 0xE0)
+        | (Some(0xFD), 0xE1)
+        | (Some(0xFD), 0xE3)
+        | (Some(0xFD), 0xEC)
+        | (Some(0xFD), 0xED)
+        | (Some(0xFD), 0xEF)
+        | (Some(0xFD), 0x101..=0x104) => (&[V128], V128),
+        (Some(0xFD), 0x52) | (Some(0xFD), 0x105..=0x10C) | (Some(0xFD), 0x113) => {
+            (&[V128, V128, V128], V128)
+        }
+        (Some(0xFD), 0x0D) | (Some(0xFD), 0x0E) | (Some(0xFD), 0x23..=0xFF) => {
+            (&[V128, V128], V128)
+        }
+        (Some(0xFD), 0x100) | (Some(0xFD), 0x10D..=0x112) => (&[V128, V128], V128),
+        _ => return None,
+    };
+    Some(signature)
+}
+
+/// The natural size in bytes, the operands following the address and the result of an
+/// instruction that accesses memory.
+type MemorySignature = (u32, &'static [ValType], Option<ValType>);
+
+/// Gets the signature of an instruction that accesses memory.
+fn memory_signature(prefix: Option<u8>, opcode: u32) -> Option<MemorySignature> {
+    // Indexed by opcode, relative to the first instruction of each kind
+    const LOADS: [(u32, ValType); 14] = [
+        (4, I32),
+        (8, I64),
+        (4, F32),
+        (8, F64),
+        (1, I32),
+        (1, I32),
+        (2, I32),
+        (2, I32),
+        (1, I64),
+        (1, I64),
+        (2, I64),
+        (2, I64),
+        (4, I64),
+        (4, I64),
+    ];
+    const STORES: [(u32, &[ValType]); 9] = [
+        (4, &[I32]),
+        (8, &[I64]),
+        (4, &[F32]),
+        (8, &[F64]),
+        (1, &[I32]),
+        (2, &[I32]),
+        (1, &[I64]),
+        (2, &[I64]),
+        (4, &[I64]),
+    ];
+    const VECTOR_LOADS: [u32; 11] = [16, 8, 8, 8, 8, 8, 8, 1, 2, 4, 8];
+    const ATOMICS: [(u32, &[ValType]); 7] = [
+        (4, &[I32]),
+        (8, &[I64]),
+        (1, &[I32]),
+        (2, &[I32]),
+        (1, &[I64]),
+        (2, &[I64]),
+        (4, &[I64]),
+    ];
+    const COMPARE_EXCHANGES: [&[ValType]; 2] = [&[I32, I32], &[I64, I64]];
+
+    let signature: MemorySignature = match (prefix, opcode) {
+        (None, 0x28..=0x35) => {
+            let (size, typ) = LOADS[opcode as usize - 0x28];
+            (size, &[], Some(typ))
+        }
+        (None, 0x36..=0x3E) => {
+            let (size, operands) = STORES[opcode as usize - 0x36];
+            (size, operands, None)
+        }
+        (Some(0xFD), 0x00..=0x0A) => (VECTOR_LOADS[opcode as usize], &[], Some(V128)),
+        (Some(0xFD), 0x0B) => (16, &[V128], None),
+        (Some(0xFE), 0x00) => (4, &[I32], Some(I32)),
+        (Some(0xFE), 0x01) => (4, &[I32, I64], Some(I32)),
+        (Some(0xFE), 0x02) => (8, &[I64, I64], Some(I32)),
+        (Some(0xFE), 0x10..=0x16) => {
+            let (size, operands) = ATOMICS[opcode as usize - 0x10];
+            (size, &[], Some(operands[0]))
+        }
+        (Some(0xFE), 0x17..=0x1D) => {
+            let (size, operands) = ATOMICS[opcode as usize - 0x17];
+            (size, operands, None)
+        }
+        (Some(0xFE), 0x1E..=0x47) => {
+            let (size, operands) = ATOMICS[(opcode as usize - 0x1E) % 7];
+            (size, operands, Some(operands[0]))
+        }
+        (Some(0xFE), 0x48..=0x4E) => {
+            let (size, operands) = ATOMICS[opcode as usize - 0x48];
+            let operands = COMPARE_EXCHANGES[(operands[0] == I64) as usize];
+            (size, operands, Some(operands[0]))
+        }
+        _ => return None,
+    };
+    Some(signature)
+}
@@ -0,0 +1,396 @@
+//! Checks that a module is well-formed before it is instantiated.
+//!
+//! The interpreter checks operand types as it goes, so a module that skips validation still
+//! can't corrupt the host, but it may only fail part way through execution. Validating up front
+//! reports those problems before any code runs.
+
+use std::collections::HashSet;
+
+use crate::{
+    module::{ExportDesc, Expr, FuncType, GlobalType, MemberDesc, MemoryType, Module, TableType},
+    reader::SectionId,
+    Error, SectionOffset, ValType,
+};
+
+mod func;
+
+/// Validates every section of `module`, including the type of every function body.
+pub fn validate(module: &Module) -> Result<(), Error> {
+    let ctx = Context::new(module)?;
+
+    if module.funcs().len() != module.code().len() {
+        return Err(Error::FunctionCodeMismatch {
+            funcs: module.funcs().len(),
+            bodies: module.code().len(),
+            at: SectionOffset::in_section(SectionId::Code),
+        });
+    }
+
+    // Imported tables and memories are declared in the import section
+    let imported = ctx.tables.len() - module.tables().len();
+    for (i, table) in ctx.tables.iter().enumerate() {
+        let section = if i < imported {
+            SectionId::Import
+        } else {
+            SectionId::Table
+        };
+        check_limits(
+            table.min() as u64,
+            table.max().map(|m| m as u64),
+            u32::MAX as u128,
+            section,
+        )?;
+    }
+
+    // The interpreter only ever accesses memory 0
+    if ctx.mems.len() > 1 {
+        return Err(Error::MultipleMemories {
+            at: SectionOffset::in_section(SectionId::Memory),
+        });
+    }
+    let imported = ctx.mems.len() - module.mems().len();
+    for (i, mem) in ctx.mems.iter().enumerate() {
+        let section = if i < imported {
+            SectionId::Import
+        } else {
+            SectionId::Memory
+        };
+        let bits = if mem.is_64() { 64 } else { 32 };
+        let limit = (1u128 << bits) / mem.page_size() as u128;
+        check_limits(
+            mem.min() as u64,
+            mem.max().map(|m| m as u64),
+            limit,
+            section,
+        )?;
+    }
+
+    for global in module.globals() {
+        ctx.check_const(global.init(), global.typ().typ(), SectionId::Global)?;
+    }
+
+    let at = SectionOffset::in_section(SectionId::Export);
+    let mut names = HashSet::new();
+    for export in module.exports() {
+        let unknown = match *export.description() {
+            ExportDesc::Function(index) if index >= ctx.funcs.len() => {
+                Some(Error::UnknownFunctionIndex { index, at })
+            }
+            ExportDesc::Table(index) if index >= ctx.tables.len() => {
+                Some(Error::UnknownTableIndex { index, at })
+            }
+            ExportDesc::Memory(index) if index >= ctx.mems.len() => {
+                Some(Error::UnknownMemoryIndex { index, at })
+            }
+            ExportDesc::Global(index) if index >= ctx.globals.len() => {
+                Some(Error::UnknownGlobalIndex { index, at })
+            }
+            _ => None,
+        };
+        if let Some(err) = unknown {
+            return Err(err);
+        }
+        if !names.insert(export.name()) {
+            return Err(Error::DuplicateExportName {
+                name: export.name().to_string(),
+                at,
+            });
+        }
+    }
+
+    let at = SectionOffset::in_section(SectionId::Element);
+    for elem in module.elems() {
+        if let Some(expr) = elem.expr() {
+            if elem.index() >= ctx.tables.len() {
+                return Err(Error::UnknownTableIndex {
+                    index: elem.index(),
+                    at,
+                });
+            }
+            ctx.check_const(expr, ValType::I32, SectionId::Element)?;
+        }
+        if let Some(func) = elem.init().iter().find(|f| **f >= ctx.funcs.len()) {
+            return Err(Error::UnknownFunctionIndex { index: *func, at });
+        }
+    }
+
+    for data in module.data() {
+        let mem = match ctx.mems.get(data.index()) {
+            Some(mem) => mem,
+            None => {
+                return Err(Error::UnknownMemoryIndex {
+                    index: data.index(),
+                    at: SectionOffset::in_section(SectionId::Data),
+                })
+            }
+        };
+        ctx.check_const(data.expr(), address_type(mem), SectionId::Data)?;
+    }
+
+    let imported = ctx.funcs.len() - module.funcs().len();
+    for (i, body) in module.code().iter().enumerate() {
+        func::validate(&ctx, imported + i, body)?;
+    }
+
+    Ok(())
+}
+
+/// The index spaces of a module, with imports ahead of the module's own definitions.
+struct Context<'a> {
+    module: &'a Module,
+    funcs: Vec<&'a FuncType>,
+    tables: Vec<&'a TableType>,
+    mems: Vec<&'a MemoryType>,
+    globals: Vec<&'a GlobalType>,
+    imported_globals: usize,
+}
+
+impl<'a> Context<'a> {
+    fn new(module: &'a Module) -> Result<Context<'a>, Error> {
+        let mut funcs = Vec::new();
+        let mut tables = Vec::new();
+        let mut mems = Vec::new();
+        let mut globals = Vec::new();
+
+        for import in module.imports() {
+            match import.description() {
+                MemberDesc::Function(type_id) => {
+                    funcs.push(func_type(module, *type_id, SectionId::Import)?)
+                }
+                MemberDesc::Table(typ) => tables.push(typ),
+                MemberDesc::Memory(typ) => mems.push(typ),
+                MemberDesc::Global(typ) => globals.push(typ),
+            }
+        }
+        let imported_globals = globals.len();
+
+        for type_id in module.funcs() {
+            funcs.push(func_type(module, *type_id, SectionId::Function)?);
+        }
+        tables.extend(module.tables().iter());
+        mems.extend(module.mems().iter());
+        globals.extend(module.globals().iter().map(|g| g.typ()));
+
+        Ok(Context {
+            module,
+            funcs,
+            tables,
+            mems,
+            globals,
+            imported_globals,
+        })
+    }
+
+    /// Checks that `expr` is a constant expression producing a value of type `expected`, using
+    /// only the instructions `ConstExpr` can evaluate.
+    fn check_const(&self, expr: &Expr, expected: ValType, section: SectionId) -> Result<(), Error> {
+        use crate::Instruction::*;
+
+        let at = SectionOffset::in_section(section);
+        let mut stack = Vec::new();
+        for inst in expr.iter() {
+            let typ = match inst {
+                I32Const(v) | I64Const(v) | F32Const(v) | F64Const(v) | V128Const(v) => v.typ(),
+                GlobalGet(idx) => {
+                    // Only imported globals have been initialized when constants are evaluated
+                    let idx = *idx as usize;
+                    if idx >= self.imported_globals {
+                        return Err(Error::UnknownGlobalIndex { index: idx, at });
+                    }
+                    if self.globals[idx].mutable() {
+                        return Err(Error::InvalidConstExpr { at });
+                    }
+                    self.globals[idx].typ()
+                }
+                I32Add | I32Sub | I32Mul | I64Add | I64Sub | I64Mul => {
+                    let typ = if matches!(inst, I32Add | I32Sub | I32Mul) {
+                        ValType::I32
+                    } else {
+                        ValType::I64
+                    };
+                    for _ in 0..2 {
+                        if stack.pop() != Some(typ) {
+                            return Err(Error::InvalidConstExpr { at });
+                        }
+                    }
+                    typ
+                }
+                _ => return Err(Error::InvalidConstExpr { at }),
+            };
+            stack.push(typ);
+        }
+
+        match stack.as_slice() {
+            [typ] if expected.accepts(*typ) => Ok(()),
+            _ => Err(Error::InvalidConstExpr { at }),
+        }
+    }
+}
+
+fn func_type(module: &Module, type_id: usize, section: SectionId) -> Result<&FuncType, Error> {
+    module.func_type(type_id).ok_or(Error::UnknownTypeIndex {
+        index: type_id,
+        at: SectionOffset::in_section(section),
+    })
+}
+
+/// Checks that limits are no larger than `limit` and that the minimum is no larger than the
+/// maximum.
+fn check_limits(min: u64, max: Option<u64>, limit: u128, section: SectionId) -> Result<(), Error> {
+    let too_large = |n: u64| n as u128 > limit;
+    if too_large(min) || max.is_some_and(too_large) || max.is_some_and(|max| min > max) {
+        return Err(Error::LimitsOutOfRange {
+            min,
+            max,
+            at: SectionOffset::in_section(section),
+        });
+    }
+    Ok(())
+}
+
+/// Gets the type of addresses into `mem`.
+fn address_type(mem: &MemoryType) -> ValType {
+    if mem.is_64() {
+        ValType::I64
+    } else {
+        ValType::I32
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        builder::{FuncBuilder, ModuleBuilder},
+        module::{Export, MemoryType, Module},
+        reader::SectionId,
+        Error,
+        Instruction::{self, *},
+        ValType, Value,
+    };
+
+    fn module(result: ValType, body: Vec<Instruction>) -> Module {
+        ModuleBuilder::new()
+            .mem("memory", MemoryType::new(1, None))
+            .func(
+                FuncBuilder::new()
+                    .param(ValType::I32)
+                    .result(result)
+                    .locals(vec![ValType::I64])
+                    .body(body),
+            )
+            .build()
+    }
+
+    fn message(module: &Module) -> String {
+        match module.validate() {
+            Err(Error::ValidationFailed { message, .. }) => message,
+            x => panic!("Expected a validation failure: {:?}", x),
+        }
+    }
+
+    fn i32(v: u32) -> Instruction {
+        I32Const(Value::I32(v))
+    }
+
+    #[test]
+    pub fn well_typed_bodies_are_valid() {
+        let valid = [
+            // Blocks, branches and memory accesses
+            vec![
+                Block(ValType::I32),
+                LocalGet(0),
+                LocalGet(0),
+                BrIf(0),
+                I32Load(2, 0),
+                End,
+            ],
+            // A loop's label carries no values
+            vec![Loop(ValType::I32), LocalGet(0), BrIf(0), i32(1), End],
+            vec![LocalGet(0), If(ValType::I32), i32(1), Else, i32(2), End],
+            // Unreachable code may pop values of any type
+            vec![Unreachable, I32Add],
+            vec![Block(ValType::Nil), Br(0), F64Add, Drop, End, i32(0)],
+            vec![i32(0), Return, I64Const(Value::I64(0)), Drop],
+        ];
+        for body in valid.iter() {
+            let module = module(ValType::I32, body.clone());
+            assert!(module.validate().is_ok(), "{:?}", body);
+        }
+    }
+
+    #[test]
+    pub fn ill_typed_bodies_are_rejected() {
+        let invalid = [
+            (vec![I64Const(Value::I64(0))], "type mismatch"),
+            (vec![LocalGet(1)], "type mismatch"),
+            (vec![i32(0), i32(0)], "type mismatch"),
+            (
+                vec![LocalGet(0), If(ValType::I32), i32(1), End],
+                "type mismatch",
+            ),
+            (
+                vec![Block(ValType::Nil), i32(0), End, i32(0)],
+                "type mismatch",
+            ),
+            (vec![LocalGet(2)], "unknown local 2"),
+            (
+                vec![Block(ValType::I32), i32(0), Br(2), End],
+                "unknown label 2",
+            ),
+            (vec![Call(1)], "unknown function 1"),
+            (vec![GlobalGet(0)], "unknown global 0"),
+            (
+                vec![i32(0), I32Load(3, 0)],
+                "alignment must not be larger than natural",
+            ),
+            (
+                vec![Block(ValType::I32), i32(0)],
+                "unexpected end of function",
+            ),
+        ];
+        for (body, expected) in invalid.iter() {
+            let module = module(ValType::I32, body.clone());
+            assert_eq!(*expected, message(&module), "{:?}", body);
+        }
+
+        // Failures are located by function and instruction
+        match module(ValType::I32, vec![i32(0), i32(0), F32Add]).validate() {
+            Err(Error::ValidationFailed {
+                func, instruction, ..
+            }) => assert_eq!((0, Some(2)), (func, instruction)),
+            x => panic!("Expected a validation failure: {:?}", x),
+        }
+    }
+
+    #[test]
+    pub fn index_spaces_are_checked_across_sections() {
+        let mut builder = ModuleBuilder::new();
+        builder.add_func(FuncBuilder::new().export_as("f"));
+        builder.exports.push(Export::func("f", 0));
+        match builder.build().validate() {
+            Err(Error::DuplicateExportName { name, at }) => {
+                assert_eq!("f", name);
+                assert_eq!(Some(SectionId::Export), at.section());
+            }
+            x => panic!("Expected a duplicate export: {:?}", x),
+        }
+
+        let mut builder = ModuleBuilder::new();
+        builder.exports.push(Export::mem("memory", 0));
+        assert!(matches!(
+            builder.build().validate(),
+            Err(Error::UnknownMemoryIndex { index: 0, .. })
+        ));
+
+        let module = ModuleBuilder::new()
+            .mem("memory", MemoryType::new(2, Some(1)))
+            .build();
+        match module.validate() {
+            Err(Error::LimitsOutOfRange { min, max, at }) => {
+                assert_eq!((2, Some(1)), (min, max));
+                assert_eq!(Some(SectionId::Memory), at.section());
+            }
+            x => panic!("Expected invalid limits: {:?}", x),
+        }
+    }
+}
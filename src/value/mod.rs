@@ -1,10 +1,15 @@
 use std::{fmt, io};
 
-use byteorder::ReadBytesExt;
+use byteorder::{ReadBytesExt, WriteBytesExt};
 
-use crate::{Error, TrapCause};
+use crate::{Error, SectionOffset, TrapCause};
 
 pub mod ops;
+#[cfg(feature = "gc")]
+mod ref_type;
+
+#[cfg(feature = "gc")]
+pub use self::ref_type::{HeapType, Ref, RefType};
 
 #[repr(u8)]
 #[derive(Copy, Clone, PartialEq, Eq)]
@@ -14,6 +19,9 @@ pub enum ValType {
     I64 = 0x7E,
     F32 = 0x7D,
     F64 = 0x7C,
+    V128 = 0x7B,
+    #[cfg(feature = "gc")]
+    Ref(RefType) = 0x64,
 }
 
 impl ValType {
@@ -24,13 +32,76 @@ impl ValType {
             0x7E => Ok(ValType::I64),
             0x7D => Ok(ValType::F32),
             0x7C => Ok(ValType::F64),
-            _ => Err(Error::InvalidModule),
+            0x7B => Ok(ValType::V128),
+            // The abbreviated forms of nullable references
+            #[cfg(feature = "gc")]
+            x => match HeapType::from_u8(x) {
+                Some(heap) => Ok(ValType::Ref(RefType::new(true, heap))),
+                None => Err(Error::InvalidValType {
+                    byte: x,
+                    at: SectionOffset::unknown(),
+                }),
+            },
+            #[cfg(not(feature = "gc"))]
+            _ => Err(Error::InvalidValType {
+                byte: v,
+                at: SectionOffset::unknown(),
+            }),
+        }
+    }
+
+    /// Gets the zero value of this type, which locals and uninitialized fields hold. References
+    /// are null.
+    pub fn default_value(&self) -> Value {
+        match *self {
+            ValType::Nil => Value::Nil,
+            ValType::I32 => Value::I32(0),
+            ValType::I64 => Value::I64(0),
+            ValType::F32 => Value::F32(0.0),
+            ValType::F64 => Value::F64(0.0),
+            ValType::V128 => Value::V128(0),
+            #[cfg(feature = "gc")]
+            ValType::Ref(r) => Value::Ref(Ref::Null(r.heap())),
         }
     }
 
     pub fn read<R: io::Read>(reader: &mut R) -> Result<ValType, Error> {
         let v = reader.read_u8()?;
-        ValType::from_u8(v)
+        match v {
+            #[cfg(feature = "gc")]
+            0x63 => Ok(ValType::Ref(RefType::new(true, HeapType::read(reader)?))),
+            #[cfg(feature = "gc")]
+            0x64 => Ok(ValType::Ref(RefType::new(false, HeapType::read(reader)?))),
+            _ => ValType::from_u8(v),
+        }
+    }
+
+    /// Writes the type in the binary format that [`ValType::read`] reads.
+    pub fn write<W: io::Write>(&self, writer: &mut W) -> io::Result<()> {
+        let byte = match self {
+            ValType::Nil => 0x40,
+            ValType::I32 => 0x7F,
+            ValType::I64 => 0x7E,
+            ValType::F32 => 0x7D,
+            ValType::F64 => 0x7C,
+            ValType::V128 => 0x7B,
+            #[cfg(feature = "gc")]
+            ValType::Ref(r) => {
+                writer.write_u8(if r.nullable() { 0x63 } else { 0x64 })?;
+                return r.heap().write(writer);
+            }
+        };
+        writer.write_u8(byte)
+    }
+
+    /// Gets a boolean indicating if a value of type `actual` may be used where this type is
+    /// expected.
+    pub fn accepts(&self, actual: ValType) -> bool {
+        match (self, actual) {
+            #[cfg(feature = "gc")]
+            (ValType::Ref(expected), ValType::Ref(actual)) => actual.is_subtype_of(expected),
+            _ => *self == actual,
+        }
     }
 }
 
@@ -42,6 +113,9 @@ impl fmt::Display for ValType {
             ValType::I64 => "i64",
             ValType::F32 => "f32",
             ValType::F64 => "f64",
+            ValType::V128 => "v128",
+            #[cfg(feature = "gc")]
+            ValType::Ref(r) => return write!(f, "{}", r),
         };
         write!(f, "{}", v)
     }
@@ -60,6 +134,9 @@ pub enum Value {
     I64(u64),
     F32(f32),
     F64(f64),
+    V128(u128),
+    #[cfg(feature = "gc")]
+    Ref(Ref),
 }
 
 impl Value {
@@ -70,6 +147,9 @@ impl Value {
             Value::I64(_) => ValType::I64,
             Value::F32(_) => ValType::F32,
             Value::F64(_) => ValType::F64,
+            Value::V128(_) => ValType::V128,
+            #[cfg(feature = "gc")]
+            Value::Ref(r) => ValType::Ref(r.typ()),
         }
     }
 }
@@ -82,6 +162,9 @@ impl fmt::Display for Value {
             Value::I64(x) => write!(f, "{}", x),
             Value::F32(x) => write!(f, "{}", x),
             Value::F64(x) => write!(f, "{}", x),
+            Value::V128(x) => write!(f, "0x{:032X}", x),
+            #[cfg(feature = "gc")]
+            Value::Ref(x) => write!(f, "{}", x),
         }
     }
 }
@@ -94,6 +177,9 @@ impl fmt::UpperHex for Value {
             Value::I64(x) => write!(f, "{:X}", x),
             Value::F32(x) => write!(f, "{:X}", x.to_bits()),
             Value::F64(x) => write!(f, "{:X}", x.to_bits()),
+            Value::V128(x) => write!(f, "{:X}", x),
+            #[cfg(feature = "gc")]
+            Value::Ref(x) => write!(f, "{}", x),
         }
     }
 }
@@ -114,6 +200,7 @@ impl_from!(i32, u32, I32);
 impl_from!(i64, u64, I64);
 impl_from!(f32, f32, F32);
 impl_from!(f64, f64, F64);
+impl_from!(u128, u128, V128);
 
 impl From<bool> for Value {
     fn from(b: bool) -> Value {
@@ -148,3 +235,25 @@ impl_from_value!(i32, I32);
 impl_from_value!(i64, I64);
 impl_from_value!(f32, F32);
 impl_from_value!(f64, F64);
+impl_from_value!(u128, V128);
+
+#[cfg(feature = "gc")]
+impl From<Ref> for Value {
+    fn from(r: Ref) -> Value {
+        Value::Ref(r)
+    }
+}
+
+#[cfg(feature = "gc")]
+impl FromValue for Ref {
+    fn from_value(v: Value) -> Result<Self, TrapCause> {
+        match v {
+            Value::Ref(r) => Ok(r),
+            Value::Nil => Err(TrapCause::StackUnderflow),
+            x => Err(TrapCause::TypeMismatch {
+                expected: ValType::Ref(RefType::new(true, HeapType::Any)),
+                actual: x.typ(),
+            }),
+        }
+    }
+}
@@ -0,0 +1,190 @@
+use std::{
+    fmt,
+    io::{self, Read},
+};
+
+use byteorder::{ReadBytesExt, WriteBytesExt};
+
+use crate::{hosting::ObjectAddr, utils, Error, SectionOffset};
+
+/// The heap type that a reference points into.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum HeapType {
+    Func,
+    Extern,
+    Any,
+    Eq,
+    I31,
+    Struct,
+    Array,
+    None,
+    NoFunc,
+    NoExtern,
+    /// A struct or array type defined in the module's type section.
+    Type(u32),
+}
+
+impl HeapType {
+    /// Gets the abstract heap type with the specified encoding, if any.
+    pub fn from_u8(v: u8) -> Option<HeapType> {
+        match v {
+            0x70 => Some(HeapType::Func),
+            0x6F => Some(HeapType::Extern),
+            0x6E => Some(HeapType::Any),
+            0x6D => Some(HeapType::Eq),
+            0x6C => Some(HeapType::I31),
+            0x6B => Some(HeapType::Struct),
+            0x6A => Some(HeapType::Array),
+            0x71 => Some(HeapType::None),
+            0x73 => Some(HeapType::NoFunc),
+            0x72 => Some(HeapType::NoExtern),
+            _ => None,
+        }
+    }
+
+    pub fn read<R: io::Read>(reader: &mut R) -> Result<HeapType, Error> {
+        // Heap types are encoded as an s33, where the abstract types are single-byte negative
+        // values and defined types are non-negative type indices.
+        let first = reader.read_u8()?;
+        if let Some(heap) = HeapType::from_u8(first) {
+            return Ok(heap);
+        }
+        let idx = utils::read_leb128_s33(&mut io::Cursor::new([first]).chain(reader))?;
+        if idx < 0 || idx > u32::MAX as i64 {
+            Err(Error::InvalidHeapType {
+                index: idx,
+                at: SectionOffset::unknown(),
+            })
+        } else {
+            Ok(HeapType::Type(idx as u32))
+        }
+    }
+
+    /// Writes the heap type in the binary format that [`HeapType::read`] reads.
+    pub fn write<W: io::Write>(&self, writer: &mut W) -> io::Result<()> {
+        let byte = match self {
+            HeapType::Func => 0x70,
+            HeapType::Extern => 0x6F,
+            HeapType::Any => 0x6E,
+            HeapType::Eq => 0x6D,
+            HeapType::I31 => 0x6C,
+            HeapType::Struct => 0x6B,
+            HeapType::Array => 0x6A,
+            HeapType::None => 0x71,
+            HeapType::NoFunc => 0x73,
+            HeapType::NoExtern => 0x72,
+            HeapType::Type(idx) => return leb128::write::signed(writer, *idx as i64).map(|_| ()),
+        };
+        writer.write_u8(byte)
+    }
+
+    /// Gets the top type of the hierarchy this heap type belongs to.
+    fn top(self) -> HeapType {
+        match self {
+            HeapType::Func | HeapType::NoFunc => HeapType::Func,
+            HeapType::Extern | HeapType::NoExtern => HeapType::Extern,
+            _ => HeapType::Any,
+        }
+    }
+
+    /// Gets a boolean indicating if a reference to `self` may be used as a reference to `other`.
+    ///
+    /// Defined types are only related to themselves, as declared supertypes are not tracked yet.
+    pub fn is_subtype_of(self, other: HeapType) -> bool {
+        use self::HeapType::*;
+
+        if self == other {
+            return true;
+        }
+        match (self, other) {
+            (None, _) | (NoFunc, _) | (NoExtern, _) => self.top() == other.top(),
+            (_, Any) => self.top() == Any,
+            (I31, Eq) | (Struct, Eq) | (Array, Eq) | (Type(_), Eq) => true,
+            (Type(_), Struct) | (Type(_), Array) => true,
+            _ => false,
+        }
+    }
+}
+
+impl fmt::Display for HeapType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            HeapType::Func => write!(f, "func"),
+            HeapType::Extern => write!(f, "extern"),
+            HeapType::Any => write!(f, "any"),
+            HeapType::Eq => write!(f, "eq"),
+            HeapType::I31 => write!(f, "i31"),
+            HeapType::Struct => write!(f, "struct"),
+            HeapType::Array => write!(f, "array"),
+            HeapType::None => write!(f, "none"),
+            HeapType::NoFunc => write!(f, "nofunc"),
+            HeapType::NoExtern => write!(f, "noextern"),
+            HeapType::Type(idx) => write!(f, "{}", idx),
+        }
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct RefType {
+    nullable: bool,
+    heap: HeapType,
+}
+
+impl RefType {
+    pub fn new(nullable: bool, heap: HeapType) -> RefType {
+        RefType { nullable, heap }
+    }
+
+    pub fn nullable(&self) -> bool {
+        self.nullable
+    }
+
+    pub fn heap(&self) -> HeapType {
+        self.heap
+    }
+
+    /// Gets a boolean indicating if a value of type `self` may be used where `other` is expected.
+    pub fn is_subtype_of(&self, other: &RefType) -> bool {
+        (!self.nullable || other.nullable) && self.heap.is_subtype_of(other.heap)
+    }
+}
+
+impl fmt::Display for RefType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.nullable {
+            write!(f, "(ref null {})", self.heap)
+        } else {
+            write!(f, "(ref {})", self.heap)
+        }
+    }
+}
+
+/// A reference value.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum Ref {
+    Null(HeapType),
+    /// An unboxed 31-bit integer.
+    I31(u32),
+    /// An object on the host's heap, with the index of its type in the allocating module.
+    Object(ObjectAddr, u32),
+}
+
+impl Ref {
+    pub fn typ(&self) -> RefType {
+        match self {
+            Ref::Null(heap) => RefType::new(true, *heap),
+            Ref::I31(_) => RefType::new(false, HeapType::I31),
+            Ref::Object(_, idx) => RefType::new(false, HeapType::Type(*idx)),
+        }
+    }
+}
+
+impl fmt::Display for Ref {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Ref::Null(heap) => write!(f, "(ref.null {})", heap),
+            Ref::I31(x) => write!(f, "(ref.i31 {})", x),
+            Ref::Object(addr, idx) => write!(f, "(ref {} {})", idx, addr),
+        }
+    }
+}
@@ -1,15 +1,20 @@
+// Test helpers use `new()` constructors without `Default` implementations.
+#![allow(clippy::new_without_default)]
+
 extern crate warthog;
 
 use std::io::Cursor;
 
 use warthog::{
-    hosting::{ExternVal, Host, ModuleAddr},
+    hosting::{ExternVal, Host, Linker, ModuleAddr},
     interp::Thread,
     module::Module,
     reader::Reader,
     runtime, Trap, Value,
 };
 
+// Used by the generated spec tests, which may not be present.
+#[allow(unused_macros)]
 macro_rules! vals {
     ($($v: expr),*) => {
         vec![$(
@@ -24,6 +29,7 @@ pub struct TestContext {
     current_line: usize,
     active_module: Option<ModuleAddr>,
     host: Host,
+    linker: Linker,
 }
 
 impl TestContext {
@@ -31,13 +37,15 @@ impl TestContext {
         let mut host = Host::new();
 
         // Install the Env and SpecTest modules
-        host.external(runtime::Env::new()).unwrap();
-        host.external(runtime::SpecTest::new()).unwrap();
+        let mut linker = Linker::new();
+        linker.register("env", host.external(runtime::Env::new()).unwrap());
+        linker.register("spectest", host.external(runtime::SpecTest::new()).unwrap());
 
         TestContext {
             current_line: 0,
             active_module: None,
             host,
+            linker,
         }
     }
 
@@ -47,8 +55,8 @@ impl TestContext {
 
     pub fn load_module(&mut self, module_name: &str, module_data: &[u8]) {
         let module = {
-            let mut cur = Cursor::new(module_data);
-            let mut reader = Reader::new(cur);
+            let cur = Cursor::new(module_data);
+            let reader = Reader::new(cur);
             match Module::load(reader) {
                 Ok(m) => m,
                 Err(e) => self.panic(format!(
@@ -57,7 +65,7 @@ impl TestContext {
                 )),
             }
         };
-        let addr = match self.host.instantiate(module_name, module) {
+        let addr = match self.linker.instantiate(&mut self.host, module_name, module) {
             Ok(a) => a,
             Err(e) => self.panic(format!(
                 "Failed to instantiate module: {}. Error: {:?}",
@@ -67,27 +75,26 @@ impl TestContext {
         self.active_module = Some(addr);
     }
 
+    /// Makes a module available to later imports from `as_name`, for the `register` command.
+    pub fn register(&mut self, as_name: &str, module: Option<&str>) {
+        let addr = self.find_module(module);
+        self.linker.register(as_name, addr);
+    }
+
     pub fn invoke(
         &mut self,
         module: Option<&str>,
         field: &str,
         params: Vec<Value>,
     ) -> Result<Vec<Value>, Trap> {
-        let module = match (module, self.active_module) {
-            (None, None) => self.panic("No active module!"),
-            (Some(_name), _) => {
-                self.panic("Not yet implemented: Invoke with module.");
-            }
-            (_, Some(addr)) => addr,
-        };
-
+        let module = self.find_module(module);
         self.invoke_core(module, field, params)
     }
 
     pub fn assert_return(&self, expected: Vec<Value>, actual: Result<Vec<Value>, Trap>) {
         // Extract the actual value
         let actual = match actual {
-            Ok(mut v) => self.unwrap_val(v),
+            Ok(v) => self.unwrap_val(v),
             Err(t) => self.panic(format!("Trapped: {}", t)),
         };
 
@@ -145,6 +152,18 @@ impl TestContext {
         }
     }
 
+    /// Finds the module named `name`, or the active module if there's no name.
+    fn find_module(&self, name: Option<&str>) -> ModuleAddr {
+        match (name, self.active_module) {
+            (Some(name), _) => match self.linker.get(name) {
+                Some(addr) => addr,
+                None => self.panic(format!("No module named {}!", name)),
+            },
+            (None, Some(addr)) => addr,
+            (None, None) => self.panic("No active module!"),
+        }
+    }
+
     fn invoke_core(
         &mut self,
         module: ModuleAddr,
@@ -176,7 +195,7 @@ impl TestContext {
             }
         };
 
-        thread.call(&mut self.host, module, func_addr, params)
+        thread.call(&mut self.host, module, func_addr, &params)
     }
 
     #[inline]